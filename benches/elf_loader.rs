@@ -10,7 +10,7 @@ extern crate solana_sbpf;
 extern crate test;
 extern crate test_utils;
 
-use solana_sbpf::{elf::Executable, program::BuiltinProgram, vm::Config};
+use solana_sbpf::{elf::Executable, elf_parser::Elf64, program::BuiltinProgram, vm::Config};
 use std::{fs::File, io::Read, sync::Arc};
 use test::Bencher;
 use test_utils::{syscalls, TestContextObject};
@@ -31,3 +31,47 @@ fn bench_load_sbpfv0(bencher: &mut Bencher) {
     let loader = loader();
     bencher.iter(|| Executable::<TestContextObject>::from_elf(&elf, loader.clone()).unwrap());
 }
+
+#[bench]
+fn bench_load_call_relocations(bencher: &mut Bencher) {
+    let elf =
+        test_utils::elf_fixtures::create_elf_with_call_relocations(50_000, 64, &["log"]).elf_bytes;
+    let loader = loader();
+    bencher.iter(|| Executable::<TestContextObject>::from_elf(&elf, loader.clone()).unwrap());
+}
+
+#[bench]
+fn bench_parse(bencher: &mut Bencher) {
+    let elf = test_utils::elf_fixtures::create_elf_with_gnu_hash(1, 1000, &["log"]).elf_bytes;
+    bencher.iter(|| Elf64::parse(&elf).unwrap());
+}
+
+#[bench]
+fn bench_parse_headers_only(bencher: &mut Bencher) {
+    let elf = test_utils::elf_fixtures::create_elf_with_gnu_hash(1, 1000, &["log"]).elf_bytes;
+    bencher.iter(|| Elf64::parse_headers_only(&elf).unwrap());
+}
+
+fn bench_lookup_dynamic_symbols(bencher: &mut Bencher, elf: &[u8]) {
+    let elf = Elf64::parse(elf).unwrap();
+    let names = (0..1000)
+        .map(|i| format!("function_{i}").into_bytes())
+        .collect::<Vec<_>>();
+    bencher.iter(|| {
+        for name in &names {
+            assert!(elf.lookup_dynamic_symbol(name).unwrap().is_some());
+        }
+    });
+}
+
+#[bench]
+fn bench_lookup_dynamic_symbols_linear(bencher: &mut Bencher) {
+    let elf = test_utils::elf_fixtures::create_elf_with_call_relocations(1, 1000, &["log"]);
+    bench_lookup_dynamic_symbols(bencher, &elf.elf_bytes);
+}
+
+#[bench]
+fn bench_lookup_dynamic_symbols_gnu_hash(bencher: &mut Bencher) {
+    let elf = test_utils::elf_fixtures::create_elf_with_gnu_hash(1, 1000, &["log"]);
+    bench_lookup_dynamic_symbols(bencher, &elf.elf_bytes);
+}
@@ -2,17 +2,39 @@ use clap::{crate_version, App, Arg};
 use solana_sbpf::{
     aligned_memory::AlignedMemory,
     assembler::assemble,
+    disassembler::DisassemblyStyle,
     ebpf,
     elf::Executable,
-    memory_region::{MemoryMapping, MemoryRegion},
+    memory_region::{MemoryMapping, MemoryRegion, RegionSizes},
     program::BuiltinProgram,
-    static_analysis::Analysis,
+    static_analysis::{Analysis, Profiler},
     verifier::RequisiteVerifier,
     vm::{Config, DynamicAnalysis, EbpfVm},
 };
 use std::{fs::File, io::Read, path::Path, sync::Arc};
 use test_utils::TestContextObject;
 
+fn print_relocation_patches(executable: &Executable<TestContextObject>, elf: &[u8]) {
+    for patch in executable.get_relocation_patches().unwrap() {
+        println!(
+            "{:#010x}: {:02x?} -> {:02x?} {} {}",
+            patch.file_offset,
+            patch.old_bytes,
+            patch.new_bytes,
+            patch
+                .relocation_type
+                .map(|relocation_type| format!("type={relocation_type}"))
+                .unwrap_or_else(|| "pc-relative call".to_string()),
+            patch
+                .symbol_name
+                .as_deref()
+                .map(String::from_utf8_lossy)
+                .unwrap_or_default(),
+        );
+    }
+    executable.verify_relocation_patches(elf).unwrap();
+}
+
 fn main() {
     let matches = App::new("Solana BPF CLI")
         .version(crate_version!())
@@ -60,8 +82,8 @@ fn main() {
                 .short('u')
                 .long("use")
                 .takes_value(true)
-                .possible_values(&["cfg", "debugger", "disassembler", "interpreter", "jit"])
-                .required(true),
+                .possible_values(&["cfg", "debugger", "disassembler", "interpreter", "jit", "report"])
+                .required_unless_present("show patches"),
         )
         .arg(
             Arg::new("instruction limit")
@@ -92,11 +114,29 @@ fn main() {
                 .short('p')
                 .long("prof"),
         )
+        .arg(
+            Arg::new("encoding")
+                .about("Prefix each disassembled instruction with its pc and encoding")
+                .long("encoding"),
+        )
+        .arg(
+            Arg::new("reassemblable")
+                .about("Disassemble into assembler code which reassembles to the same bytes")
+                .long("reassemblable")
+                .conflicts_with("encoding"),
+        )
+        .arg(
+            Arg::new("show patches")
+                .about("Display the modifications made by the relocation pass and exit")
+                .long("show-patches")
+                .requires("elf"),
+        )
         .get_matches();
 
     let loader = Arc::new(BuiltinProgram::new_loader(Config {
         enable_instruction_tracing: matches.is_present("trace") || matches.is_present("profile"),
         enable_symbol_and_section_labels: true,
+        record_relocation_patches: matches.is_present("show patches"),
         ..Config::default()
     }));
     #[allow(unused_mut)]
@@ -111,14 +151,46 @@ fn main() {
             let mut file = File::open(Path::new(matches.value_of("elf").unwrap())).unwrap();
             let mut elf = Vec::new();
             file.read_to_end(&mut elf).unwrap();
-            Executable::<TestContextObject>::from_elf(&elf, loader)
-                .map_err(|err| format!("Executable constructor failed: {err:?}"))
+            let executable = Executable::<TestContextObject>::from_elf(&elf, loader)
+                .map_err(|err| format!("Executable constructor failed: {err:?}"));
+            if matches.is_present("show patches") {
+                print_relocation_patches(&executable.unwrap(), &elf);
+                return;
+            }
+            executable
         }
     }
     .unwrap();
 
+    // The report includes the verification result, so it is emitted before verifying
+    if matches.value_of("use") == Some("report") {
+        println!("{}", executable.report().unwrap().to_json());
+        return;
+    }
+
     executable.verify::<RequisiteVerifier>().unwrap();
 
+    // Reject regions exceeding the configured limits before allocating them
+    let region_sizes = RegionSizes {
+        stack: executable.get_config().stack_size() as u64,
+        heap: matches.value_of("memory").unwrap().parse::<u64>().unwrap(),
+        input: match matches.value_of("input").unwrap().parse::<u64>() {
+            Ok(allocate) => allocate,
+            Err(_) => std::fs::metadata(matches.value_of("input").unwrap())
+                .unwrap()
+                .len(),
+        },
+        ..RegionSizes::default()
+    };
+    RegionSizes {
+        total: region_sizes
+            .writable()
+            .saturating_add(executable.get_ro_region().len),
+        ..region_sizes
+    }
+    .check(executable.get_config())
+    .unwrap();
+
     let mut mem = match matches.value_of("input").unwrap().parse::<usize>() {
         Ok(allocate) => vec![0u8; allocate],
         Err(_) => {
@@ -154,26 +226,27 @@ fn main() {
         executable.get_ro_region(),
         MemoryRegion::new_writable_gapped(
             stack.as_slice_mut(),
-            ebpf::MM_STACK_START,
+            config.memory_layout.stack_start(),
             if !sbpf_version.dynamic_stack_frames() && config.enable_stack_frame_gaps {
                 config.stack_frame_size as u64
             } else {
                 0
             },
         ),
-        MemoryRegion::new_writable(heap.as_slice_mut(), ebpf::MM_HEAP_START),
-        MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START),
+        MemoryRegion::new_writable(heap.as_slice_mut(), config.memory_layout.heap_start()),
+        MemoryRegion::new_writable(&mut mem, config.memory_layout.input_start()),
     ];
 
     let memory_mapping = MemoryMapping::new(regions, config, sbpf_version).unwrap();
 
-    let mut vm = EbpfVm::new(
+    let mut vm = EbpfVm::try_new(
         executable.get_loader().clone(),
         executable.get_sbpf_version(),
         &mut context_object,
         memory_mapping,
         stack_len,
-    );
+    )
+    .unwrap();
 
     let analysis = if matches.value_of("use") == Some("cfg")
         || matches.value_of("use") == Some("disassembler")
@@ -196,11 +269,16 @@ fn main() {
         }
         Some("disassembler") => {
             let stdout = std::io::stdout();
-            analysis
-                .as_ref()
-                .unwrap()
-                .disassemble(&mut stdout.lock())
-                .unwrap();
+            let analysis = analysis.as_ref().unwrap();
+            if matches.is_present("encoding") {
+                analysis.disassemble_with_encoding(&mut stdout.lock()).unwrap();
+            } else if matches.is_present("reassemblable") {
+                analysis
+                    .disassemble_with_style(&mut stdout.lock(), DisassemblyStyle::Reassemblable)
+                    .unwrap();
+            } else {
+                analysis.disassemble(&mut stdout.lock()).unwrap();
+            }
             return;
         }
         _ => {}
@@ -233,5 +311,13 @@ fn main() {
             .unwrap()
             .visualize_graphically(&mut file, Some(&dynamic_analysis))
             .unwrap();
+        let profiler = Profiler::new(
+            &vm.context_object_pointer.trace_log,
+            analysis.as_ref().unwrap(),
+        );
+        let mut file = File::create("profile.folded").unwrap();
+        profiler
+            .write_collapsed_by_instruction_counts(&mut file)
+            .unwrap();
     }
 }
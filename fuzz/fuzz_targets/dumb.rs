@@ -9,7 +9,7 @@ use solana_sbpf::{
     elf::Executable,
     memory_region::MemoryRegion,
     program::{BuiltinFunction, BuiltinProgram, FunctionRegistry, SBPFVersion},
-    verifier::{RequisiteVerifier, Verifier},
+    verifier::{RequisiteVerifier, Verifier, VerifierConfig},
 };
 use test_utils::{create_vm, TestContextObject};
 
@@ -30,7 +30,7 @@ fuzz_target!(|data: DumbFuzzData| {
     let function_registry = FunctionRegistry::default();
     let syscall_registry = FunctionRegistry::<BuiltinFunction<TestContextObject>>::default();
 
-    if RequisiteVerifier::verify(&prog, &config, SBPFVersion::V3, &function_registry, &syscall_registry).is_err() {
+    if RequisiteVerifier::verify(&prog, &config, &VerifierConfig::default(), SBPFVersion::V3, &function_registry, &syscall_registry).is_err() {
         // verify please
         return;
     }
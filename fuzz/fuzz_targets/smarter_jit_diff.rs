@@ -9,7 +9,7 @@ use solana_sbpf::{
     insn_builder::IntoBytes,
     memory_region::MemoryRegion,
     program::{BuiltinFunction, BuiltinProgram, FunctionRegistry, SBPFVersion},
-    verifier::{RequisiteVerifier, Verifier},
+    verifier::{RequisiteVerifier, Verifier, VerifierConfig},
 };
 use test_utils::{create_vm, TestContextObject};
 
@@ -34,6 +34,7 @@ fuzz_target!(|data: FuzzData| {
     if RequisiteVerifier::verify(
         prog.into_bytes(),
         &config,
+        &VerifierConfig::default(),
         SBPFVersion::V3,
         &function_registry,
         &syscall_registry,
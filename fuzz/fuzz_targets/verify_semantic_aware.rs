@@ -6,7 +6,7 @@ use semantic_aware::*;
 use solana_sbpf::{
     insn_builder::IntoBytes,
     program::{BuiltinFunction, FunctionRegistry, SBPFVersion},
-    verifier::{RequisiteVerifier, Verifier},
+    verifier::{RequisiteVerifier, Verifier, VerifierConfig},
 };
 use test_utils::TestContextObject;
 
@@ -30,6 +30,7 @@ fuzz_target!(|data: FuzzData| {
     RequisiteVerifier::verify(
         prog.into_bytes(),
         &config,
+        &VerifierConfig::default(),
         SBPFVersion::V3,
         &function_registry,
         &syscall_registry,
@@ -17,6 +17,28 @@ impl Pod for i16 {}
 impl Pod for i32 {}
 impl Pod for i64 {}
 
+/// Types for which every bit pattern is a valid value
+///
+/// Guest memory is untrusted, so [crate::memory_region::MemoryMapping::translate_type] only
+/// copies such types out of it. Unlike [Pod] this excludes `bool`.
+///
+/// # Safety
+///
+/// Implementors must not have padding, niches or invalid bit patterns.
+pub unsafe trait AnyBitPattern: Copy + 'static {}
+
+unsafe impl AnyBitPattern for u8 {}
+unsafe impl AnyBitPattern for u16 {}
+unsafe impl AnyBitPattern for u32 {}
+unsafe impl AnyBitPattern for u64 {}
+unsafe impl AnyBitPattern for u128 {}
+unsafe impl AnyBitPattern for i8 {}
+unsafe impl AnyBitPattern for i16 {}
+unsafe impl AnyBitPattern for i32 {}
+unsafe impl AnyBitPattern for i64 {}
+unsafe impl AnyBitPattern for i128 {}
+unsafe impl<T: AnyBitPattern, const N: usize> AnyBitPattern for [T; N] {}
+
 /// Provides u8 slices at a specified alignment
 #[derive(Debug, PartialEq, Eq)]
 pub struct AlignedMemory<const ALIGN: usize> {
@@ -0,0 +1,237 @@
+//! Bump allocator for the heap region
+
+use {
+    crate::{
+        aligned_memory::AlignedMemory,
+        ebpf,
+        error::{EbpfError, InternalError},
+        memory_region::{MemoryRegion, MemoryRegionKind},
+    },
+    core::alloc::Layout,
+};
+
+/// Allocates guest memory from the heap region by bumping a pointer
+///
+/// Owns the host memory of the heap region, which can grow up to a maximum length
+/// without moving, and hands out guest addresses relative to the start of the region.
+/// Nothing is freed until [BumpAllocator::reset].
+#[derive(Debug)]
+pub struct BumpAllocator {
+    heap: AlignedMemory<{ ebpf::HOST_ALIGN }>,
+    vm_addr: u64,
+    pos: u64,
+}
+
+impl BumpAllocator {
+    /// Creates a zero filled heap of `len` bytes, which can grow up to `max_len`
+    ///
+    /// `vm_addr` is the guest address of the heap region, usually
+    /// [crate::vm::MemoryLayoutProfile::heap_start].
+    pub fn new(len: usize, max_len: usize, vm_addr: u64) -> Self {
+        let mut heap = AlignedMemory::with_capacity_zeroed(max_len.max(len));
+        heap.fill_write(len, 0)
+            .expect("the capacity covers the initial length");
+        Self {
+            heap,
+            vm_addr,
+            pos: 0,
+        }
+    }
+
+    /// Guest address of the heap region
+    pub fn vm_addr(&self) -> u64 {
+        self.vm_addr
+    }
+
+    /// Current length of the heap region
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    /// Is the heap region empty
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    /// Number of bytes allocated so far, including alignment padding
+    pub fn used(&self) -> u64 {
+        self.pos
+    }
+
+    /// Creates the writable heap region
+    ///
+    /// The region stays valid while the allocator lives, even if it grows.
+    pub fn region(&mut self) -> MemoryRegion {
+        MemoryRegion::new_writable(self.heap.as_slice_mut(), self.vm_addr)
+            .with_kind(MemoryRegionKind::Heap)
+    }
+
+    /// Allocates `layout.size()` bytes and returns their guest address
+    ///
+    /// The guest address, not the host address, is aligned to `layout.align()`.
+    pub fn alloc(&mut self, layout: Layout) -> Result<u64, EbpfError> {
+        let heap_end = self.vm_addr.saturating_add(self.heap.len() as u64);
+        let allocation = self
+            .vm_addr
+            .checked_add(self.pos)
+            .and_then(|addr| addr.checked_next_multiple_of(layout.align() as u64))
+            .and_then(|start| Some((start, start.checked_add(layout.size() as u64)?)));
+        match allocation {
+            Some((start, end)) if end <= heap_end => {
+                self.pos = end.saturating_sub(self.vm_addr);
+                Ok(start)
+            }
+            _ => Err(EbpfError::HeapExhausted {
+                size: layout.size(),
+                align: layout.align(),
+            }),
+        }
+    }
+
+    /// Grows the heap region to `new_len` bytes
+    ///
+    /// The host memory does not move, so a [crate::memory_region::MemoryMapping]
+    /// containing the [BumpAllocator::region] can follow with
+    /// [crate::memory_region::MemoryMapping::resize_region].
+    pub fn grow(&mut self, new_len: usize) -> Result<(), InternalError> {
+        self.heap
+            .fill_write(new_len.saturating_sub(self.heap.len()), 0)
+    }
+
+    /// Frees all allocations and zeroes the memory they used
+    pub fn reset(&mut self) {
+        if let Some(used) = self.heap.as_slice_mut().get_mut(..self.pos as usize) {
+            used.fill(0);
+        }
+        self.pos = 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_alloc_alignment() {
+        let mut allocator = BumpAllocator::new(256, 256, ebpf::MM_HEAP_START);
+        assert_eq!(
+            allocator
+                .alloc(Layout::from_size_align(1, 1).unwrap())
+                .unwrap(),
+            ebpf::MM_HEAP_START
+        );
+        assert_eq!(
+            allocator
+                .alloc(Layout::from_size_align(8, 8).unwrap())
+                .unwrap(),
+            ebpf::MM_HEAP_START + 8
+        );
+        assert_eq!(
+            allocator
+                .alloc(Layout::from_size_align(4, 64).unwrap())
+                .unwrap(),
+            ebpf::MM_HEAP_START + 64
+        );
+        assert_eq!(
+            allocator
+                .alloc(Layout::from_size_align(0, 128).unwrap())
+                .unwrap(),
+            ebpf::MM_HEAP_START + 128
+        );
+        assert_eq!(allocator.used(), 128);
+
+        // Alignment is relative to the guest address, not the start of the heap
+        let mut allocator = BumpAllocator::new(256, 256, ebpf::MM_HEAP_START + 8);
+        assert_eq!(
+            allocator
+                .alloc(Layout::from_size_align(1, 32).unwrap())
+                .unwrap(),
+            ebpf::MM_HEAP_START + 32
+        );
+    }
+
+    #[test]
+    fn test_alloc_exhaustion() {
+        let mut allocator = BumpAllocator::new(64, 64, ebpf::MM_HEAP_START);
+        allocator
+            .alloc(Layout::from_size_align(1, 1).unwrap())
+            .unwrap();
+        assert_eq!(
+            allocator
+                .alloc(Layout::from_size_align(48, 16).unwrap())
+                .unwrap(),
+            ebpf::MM_HEAP_START + 16
+        );
+        assert!(matches!(
+            allocator.alloc(Layout::from_size_align(1, 1).unwrap()),
+            Err(EbpfError::HeapExhausted { size: 1, align: 1 })
+        ));
+        // Zero sized allocations still fit
+        assert_eq!(
+            allocator
+                .alloc(Layout::from_size_align(0, 1).unwrap())
+                .unwrap(),
+            ebpf::MM_HEAP_START + 64
+        );
+
+        allocator.reset();
+        assert_eq!(
+            allocator
+                .alloc(Layout::from_size_align(64, 8).unwrap())
+                .unwrap(),
+            ebpf::MM_HEAP_START
+        );
+    }
+
+    #[test]
+    fn test_alloc_vm_addr_overflow() {
+        let mut allocator = BumpAllocator::new(8, 8, u64::MAX - 8);
+        assert!(matches!(
+            allocator.alloc(Layout::from_size_align(1, 16).unwrap()),
+            Err(EbpfError::HeapExhausted { size: 1, align: 16 })
+        ));
+        assert!(matches!(
+            allocator.alloc(Layout::from_size_align(isize::MAX as usize, 1).unwrap()),
+            Err(EbpfError::HeapExhausted { align: 1, .. })
+        ));
+        assert_eq!(
+            allocator
+                .alloc(Layout::from_size_align(8, 1).unwrap())
+                .unwrap(),
+            u64::MAX - 8
+        );
+        assert!(allocator
+            .alloc(Layout::from_size_align(1, 1).unwrap())
+            .is_err());
+        assert_eq!(allocator.used(), 8);
+    }
+
+    #[test]
+    fn test_grow_and_reset() {
+        let mut allocator = BumpAllocator::new(16, 32, ebpf::MM_HEAP_START);
+        let host_addr = allocator.region().host_addr.get();
+        let vm_addr = allocator
+            .alloc(Layout::from_size_align(16, 1).unwrap())
+            .unwrap();
+        allocator.heap.as_slice_mut().fill(0xff);
+        assert!(allocator
+            .alloc(Layout::from_size_align(16, 1).unwrap())
+            .is_err());
+
+        allocator.grow(32).unwrap();
+        assert!(allocator.grow(33).is_err());
+        let region = allocator.region();
+        assert_eq!(region.host_addr.get(), host_addr);
+        assert_eq!(region.len, 32);
+        assert_eq!(
+            allocator
+                .alloc(Layout::from_size_align(16, 1).unwrap())
+                .unwrap(),
+            vm_addr + 16
+        );
+
+        allocator.reset();
+        assert_eq!(allocator.used(), 0);
+        assert_eq!(&allocator.heap.as_slice()[..16], &[0; 16]);
+    }
+}
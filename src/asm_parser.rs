@@ -10,17 +10,74 @@
 
 //! This module parses eBPF assembly language source code.
 
-use alloc::{format, string::{String}, vec::Vec};
+use alloc::{boxed::Box, format, string::{String}, vec::Vec};
+use core::ops::Range;
 
 use combine::{
-    attempt, between,
+    attempt, between, chainl1,
     parser::char::{alpha_num, char, digit, hex_digit, spaces, string},
-    eof, many, many1, one_of, optional, sep_by, skip_many,
+    many, many1, none_of, one_of, optional, position, sep_by, skip_many,
     stream::position::{self, SourcePosition},
     Parser, Stream,
 };
 use combine::parser;
 
+/// Binary operator of an expression.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryOperator {
+    /// `+`
+    Add,
+    /// `-`
+    Sub,
+    /// `*`
+    Mul,
+    /// `<<`
+    Shl,
+    /// `>>`, arithmetic
+    Shr,
+}
+
+/// Compile-time integer expression.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expression {
+    /// Integer literal.
+    Integer(i64),
+    /// Name of a constant.
+    Symbol(String),
+    /// Negated expression.
+    Neg(Box<Expression>),
+    /// Binary operation (operator, left hand side, right hand side).
+    Binary(BinaryOperator, Box<Expression>, Box<Expression>),
+}
+
+impl Expression {
+    /// Evaluate the expression, `lookup` provides the values of the symbols.
+    ///
+    /// Arithmetic wraps around, shifts by more than 63 bits are an error.
+    pub fn evaluate(&self, lookup: &dyn Fn(&str) -> Option<i64>) -> Result<i64, String> {
+        match self {
+            Expression::Integer(value) => Ok(*value),
+            Expression::Symbol(name) => {
+                lookup(name).ok_or_else(|| format!("Undefined constant {name}"))
+            }
+            Expression::Neg(operand) => Ok(operand.evaluate(lookup)?.wrapping_neg()),
+            Expression::Binary(operator, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.evaluate(lookup)?, rhs.evaluate(lookup)?);
+                match operator {
+                    BinaryOperator::Add => Ok(lhs.wrapping_add(rhs)),
+                    BinaryOperator::Sub => Ok(lhs.wrapping_sub(rhs)),
+                    BinaryOperator::Mul => Ok(lhs.wrapping_mul(rhs)),
+                    BinaryOperator::Shl | BinaryOperator::Shr if !(0..64).contains(&rhs) => {
+                        Err(format!("Invalid shift amount {rhs}"))
+                    }
+                    BinaryOperator::Shl => Ok(lhs << rhs),
+                    BinaryOperator::Shr => Ok(lhs >> rhs),
+                }
+            }
+        }
+    }
+}
+
 /// Operand of an instruction.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Operand {
@@ -30,12 +87,34 @@ pub enum Operand {
     Integer(i64),
     /// Register number and offset.
     Memory(i64, i64),
+    /// Expression which refers to constants.
+    Expression(Expression),
+    /// Register number and offset expression which refers to constants.
+    MemoryExpression(i64, Expression),
     /// Jump target label.
     Label(String),
+    /// Quoted string with its escape sequences resolved.
+    String(Vec<u8>),
 }
 
+/// Position of a statement in the source.
+///
+/// Columns start at 1 and the ranges exclude their end.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StatementSpans {
+    /// Line of the statement, starting at 1.
+    pub line: usize,
+    /// Columns of the label, directive or mnemonic.
+    pub name: Range<usize>,
+    /// Columns of each operand.
+    pub operands: Vec<Range<usize>>,
+}
+
+/// Position of a line which could not be parsed and the reason.
+pub type ParseError = (StatementSpans, String);
+
 /// Parsed statement.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Statement {
     /// Parsed label (name).
     Label { name: String },
@@ -51,6 +130,18 @@ pub enum Statement {
     },
 }
 
+impl Statement {
+    /// Operands of a directive or instruction, empty for a label.
+    pub fn operands(&self) -> &[Operand] {
+        match self {
+            Statement::Label { .. } => &[],
+            Statement::Directive { operands, .. } | Statement::Instruction { operands, .. } => {
+                operands
+            }
+        }
+    }
+}
+
 parser! {
     fn ident[I]()(I) -> String where [I: Stream<Token=char>] {
         many1(alpha_num().or(char('_')))
@@ -59,7 +150,17 @@ parser! {
 
 parser! {
     fn mnemonic[I]()(I) -> String where [I: Stream<Token=char>] {
-        many1(alpha_num())
+        many1(alpha_num().or(char('_')))
+    }
+}
+
+parser! {
+    fn unsigned_integer[I]()(I) -> i64 where [I: Stream<Token=char>] {
+        let hex = string("0x")
+            .with(many1(hex_digit()))
+            .map(|x: String| u64::from_str_radix(&x, 16).unwrap_or(0) as i64);
+        let dec = many1(digit()).map(|x: String| x.parse::<i64>().unwrap_or(0));
+        attempt(hex).or(dec)
     }
 }
 
@@ -69,15 +170,57 @@ parser! {
             Some('-') => -1,
             _ => 1,
         });
-        let hex = string("0x")
-            .with(many1(hex_digit()))
-            .map(|x: String| u64::from_str_radix(&x, 16).unwrap_or(0) as i64);
-        let dec = many1(digit()).map(|x: String| x.parse::<i64>().unwrap_or(0));
-        (sign, attempt(hex).or(dec))
+        (sign, unsigned_integer())
             .map(|(s, x)| s * x)
     }
 }
 
+parser! {
+    fn unary_expression[I]()(I) -> Expression where [I: Stream<Token=char>] {
+        let parenthesized = between(
+            char('(').skip(skip_many(char(' '))),
+            char(')'),
+            expression().skip(skip_many(char(' '))),
+        );
+        let atom = unsigned_integer()
+            .map(Expression::Integer)
+            .or(ident().map(Expression::Symbol))
+            .or(parenthesized);
+        let neg = char('-')
+            .skip(skip_many(char(' ')))
+            .with(unary_expression())
+            .map(|x| Expression::Neg(Box::new(x)));
+        let plus = char('+').skip(skip_many(char(' '))).with(unary_expression());
+        neg.or(plus).or(atom)
+    }
+}
+
+/// Operators consume the blanks around them, so that an expression ends with its last token.
+fn operator<I: Stream<Token = char>, P: Parser<I>>(operator: P) -> impl Parser<I, Output = P::Output> {
+    attempt(skip_many(char(' ')).with(operator)).skip(skip_many(char(' ')))
+}
+
+parser! {
+    fn expression[I]()(I) -> Expression where [I: Stream<Token=char>] {
+        let binary = |operator| {
+            move |lhs, rhs| Expression::Binary(operator, Box::new(lhs), Box::new(rhs))
+        };
+        let product = chainl1(
+            unary_expression(),
+            operator(string("*")).map(move |_| binary(BinaryOperator::Mul)),
+        );
+        let sum = chainl1(
+            product,
+            operator(string("+").or(string("-"))).map(move |x| {
+                binary(if x == "+" { BinaryOperator::Add } else { BinaryOperator::Sub })
+            }),
+        );
+        let shift_operator = operator(attempt(string("<<")).or(string(">>")))
+            .map(move |x| binary(if x == "<<" { BinaryOperator::Shl } else { BinaryOperator::Shr }));
+        chainl1(sum, shift_operator)
+    }
+}
+
 parser! {
     fn register[I]()(I) -> i64 where [I: Stream<Token=char>] {
         char('r')
@@ -86,74 +229,200 @@ parser! {
     }
 }
 
+parser! {
+    fn string_literal[I]()(I) -> Vec<u8> where [I: Stream<Token=char>] {
+        let escaped = char('\\')
+            .with(one_of("nt0\\\"".chars()))
+            .map(|c| match c {
+                'n' => '\n',
+                't' => '\t',
+                '0' => '\0',
+                c => c,
+            });
+        between(char('"'), char('"'), many(escaped.or(none_of("\\\"".chars()))))
+            .map(|x: String| x.into_bytes())
+    }
+}
+
 parser! {
     fn operand[I]()(I) -> Operand where [I: Stream<Token=char>] {
         let register_operand = register().map(Operand::Register);
-        let immediate = integer().map(Operand::Integer);
         let memory = between(
             char('['),
             char(']'),
-            (register().skip(skip_many(char(' '))), optional(integer())),
+            (register().skip(skip_many(char(' '))), optional(expression())),
         )
-        .map(|t| Operand::Memory(t.0, t.1.unwrap_or(0)));
-        let label = ident().map(Operand::Label);
+        .map(|t| match t.1.map(|offset| (offset.evaluate(&|_| None), offset)) {
+            None => Operand::Memory(t.0, 0),
+            Some((Ok(offset), _)) => Operand::Memory(t.0, offset),
+            Some((Err(_), offset)) => Operand::MemoryExpression(t.0, offset),
+        });
+        // Expressions without symbols are folded, a lone symbol is a label
+        let immediate = expression().map(|x| match (x.evaluate(&|_| None), x) {
+            (Ok(value), _) => Operand::Integer(value),
+            (Err(_), Expression::Symbol(name)) => Operand::Label(name),
+            (Err(_), x) => Operand::Expression(x),
+        });
         register_operand
-            .or(immediate)
             .or(memory)
-            .or(label)
+            .or(string_literal().map(Operand::String))
+            .or(immediate)
+    }
+}
+
+/// Statement with the positions of its name and of its operands.
+type Located<P> = (Statement, Range<P>, Vec<Range<P>>);
+
+parser! {
+    fn located_operands[I]()(I) -> (Vec<Operand>, Vec<Range<I::Position>>)
+    where [I: Stream<Token=char>] {
+        let operand = (position(), operand(), position())
+            .map(|(start, operand, end)| (operand, start..end));
+        sep_by(operand, char(',').skip(skip_many(char(' '))))
+            .map(|operands: Vec<_>| operands.into_iter().unzip())
+    }
+}
+
+parser! {
+    fn located_label[I]()(I) -> Located<I::Position> where [I: Stream<Token=char>] {
+        (position(), ident(), position(), char(':'))
+            .map(|t| (Statement::Label { name: t.1 }, t.0..t.2, Vec::new()))
+    }
+}
+
+parser! {
+    fn located_directive[I]()(I) -> Located<I::Position> where [I: Stream<Token=char>] {
+        (
+            position(),
+            char('.').with(many1(alpha_num())),
+            position(),
+            skip_many(char(' ')).with(located_operands()),
+        )
+            .map(|t| {
+                let (operands, operand_spans) = t.3;
+                (Statement::Directive { name: t.1, operands }, t.0..t.2, operand_spans)
+            })
+    }
+}
+
+parser! {
+    fn located_instruction[I]()(I) -> Located<I::Position> where [I: Stream<Token=char>] {
+        (
+            position(),
+            mnemonic(),
+            position(),
+            skip_many(char(' ')).with(located_operands()),
+        )
+            .map(|t| {
+                let (operands, operand_spans) = t.3;
+                (Statement::Instruction { name: t.1, operands }, t.0..t.2, operand_spans)
+            })
     }
 }
 
 parser! {
     fn label[I]()(I) -> Statement where [I: Stream<Token=char>] {
-        (ident(), char(':'))
-            .map(|t| Statement::Label { name: t.0 })
+        located_label().map(|t| t.0)
     }
 }
 
 parser! {
     fn directive[I]()(I) -> Statement where [I: Stream<Token=char>] {
-        let operands = sep_by(operand(), char(',').skip(skip_many(char(' '))));
-        (char('.').with(many1(alpha_num())).skip(skip_many(char(' '))), operands)
-            .map(|t| Statement::Directive { name: t.0, operands: t.1 })
+        located_directive().map(|t| t.0)
     }
 }
 
 parser! {
     fn instruction[I]()(I) -> Statement where [I: Stream<Token=char>] {
-        let operands = sep_by(operand(), char(',').skip(skip_many(char(' '))));
-        (mnemonic().skip(skip_many(char(' '))), operands)
-            .map(|t| Statement::Instruction { name: t.0, operands: t.1 })
+        located_instruction().map(|t| t.0)
     }
 }
 
-/// Parse a string into a list of instructions.
+/// Parse a string into a list of instructions and their positions.
 ///
-/// The instructions are not validated and may have invalid names and operand types.
-pub fn parse(input: &str) -> Result<Vec<Statement>, String> {
-    match spaces()
-        .with(many(
-            attempt(label())
-                .or(directive())
-                .or(instruction())
-                .skip(spaces()),
-        ))
-        .skip(eof())
-        .parse(position::Stream::with_positioner(input, SourcePosition::default()))
-    {
-        Ok((insts, _)) => Ok(insts),
-        Err(err) => Err(format!("{}", err)),
+/// The instructions are not validated and may have invalid names and operand types. Every line
+/// which can not be parsed results in an error pointing at the first statement which failed.
+pub fn parse(input: &str) -> Result<Vec<(StatementSpans, Statement)>, Vec<ParseError>> {
+    let columns =
+        |range: Range<SourcePosition>| range.start.column as usize..range.end.column as usize;
+    let mut statements = Vec::new();
+    let mut errors = Vec::new();
+    for (index, text) in input.lines().enumerate() {
+        let line = index + 1;
+        let statement = attempt(located_label())
+            .or(located_directive())
+            .or(located_instruction());
+        let stream = position::Stream::with_positioner(
+            text,
+            SourcePosition {
+                line: line as i32,
+                column: 1,
+            },
+        );
+        let (located, rest) = match spaces()
+            .with(many(attempt(statement).skip(spaces())))
+            .parse(stream)
+        {
+            Ok((located, rest)) => (located, rest.input),
+            Err(_) => (Vec::new(), text),
+        };
+        statements.extend(located.into_iter().map(
+            |(statement, name, operands): Located<SourcePosition>| {
+                (
+                    StatementSpans {
+                        line,
+                        name: columns(name),
+                        operands: operands.into_iter().map(columns).collect(),
+                    },
+                    statement,
+                )
+            },
+        ));
+        let start = text[..text.len() - rest.len()].chars().count() + 1;
+        let rest = rest.trim_end();
+        if !rest.is_empty() {
+            errors.push((
+                StatementSpans {
+                    line,
+                    name: start..start + rest.chars().count(),
+                    operands: Vec::new(),
+                },
+                format!("Invalid statement {rest:?}"),
+            ));
+        }
+    }
+    if errors.is_empty() {
+        Ok(statements)
+    } else {
+        Err(errors)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use alloc::{string::{ToString}, vec};
+    use alloc::{
+        boxed::Box,
+        string::{String, ToString},
+        vec,
+        vec::Vec,
+    };
     use super::{
-        ident, instruction, integer, mnemonic, operand, parse, register, Operand, Statement,
+        directive, expression, ident, instruction, integer, mnemonic, operand, register,
+        string_literal, BinaryOperator, Expression, Operand, Statement, StatementSpans,
     };
     use combine::Parser;
 
+    fn parse(input: &str) -> Result<Vec<Statement>, String> {
+        super::parse(input)
+            .map(|statements| {
+                statements
+                    .into_iter()
+                    .map(|(_spans, statement)| statement)
+                    .collect()
+            })
+            .map_err(|errors| errors[0].1.clone())
+    }
+
     // Unit tests for the different kinds of parsers.
 
     #[test]
@@ -305,6 +574,135 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_expression() {
+        let symbol = |name: &str| Box::new(Expression::Symbol(name.to_string()));
+        assert_eq!(
+            expression().parse("A - -2 * (B + 1) << 3, r1"),
+            Ok((
+                Expression::Binary(
+                    BinaryOperator::Shl,
+                    Box::new(Expression::Binary(
+                        BinaryOperator::Sub,
+                        symbol("A"),
+                        Box::new(Expression::Binary(
+                            BinaryOperator::Mul,
+                            Box::new(Expression::Neg(Box::new(Expression::Integer(2)))),
+                            Box::new(Expression::Binary(
+                                BinaryOperator::Add,
+                                symbol("B"),
+                                Box::new(Expression::Integer(1))
+                            )),
+                        )),
+                    )),
+                    Box::new(Expression::Integer(3)),
+                ),
+                ", r1"
+            ))
+        );
+        let (x, _) = expression().parse("(A >> 1) - 1").unwrap();
+        let lookup = |name: &str| (name == "A").then_some(-8);
+        assert_eq!(x.evaluate(&lookup), Ok(-5));
+        assert_eq!(
+            expression().parse("B").unwrap().0.evaluate(&lookup),
+            Err("Undefined constant B".to_string())
+        );
+        assert_eq!(
+            expression()
+                .parse("1 << (0 - 1)")
+                .unwrap()
+                .0
+                .evaluate(&lookup),
+            Err("Invalid shift amount -1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_expression_operand() {
+        assert_eq!(operand().parse("-(2 * 3)"), Ok((Operand::Integer(-6), "")));
+        assert_eq!(
+            operand().parse("label"),
+            Ok((Operand::Label("label".to_string()), ""))
+        );
+        assert_eq!(
+            operand().parse("A+1"),
+            Ok((
+                Operand::Expression(Expression::Binary(
+                    BinaryOperator::Add,
+                    Box::new(Expression::Symbol("A".to_string())),
+                    Box::new(Expression::Integer(1))
+                )),
+                ""
+            ))
+        );
+        assert_eq!(
+            operand().parse("[r10 - 4 * 2]"),
+            Ok((Operand::Memory(10, -8), ""))
+        );
+        assert_eq!(
+            operand().parse("[r10-A]"),
+            Ok((
+                Operand::MemoryExpression(
+                    10,
+                    Expression::Neg(Box::new(Expression::Symbol("A".to_string())))
+                ),
+                ""
+            ))
+        );
+    }
+
+    #[test]
+    fn test_string_literal() {
+        assert_eq!(string_literal().parse("\"\""), Ok((vec![], "")));
+        assert_eq!(
+            string_literal().parse("\"hello, world\" r1"),
+            Ok((b"hello, world".to_vec(), " r1"))
+        );
+        assert_eq!(
+            string_literal().parse(r#""a\n\t\0\\\"b""#),
+            Ok((b"a\n\t\0\\\"b".to_vec(), ""))
+        );
+        assert!(string_literal().parse("\"open").is_err());
+    }
+
+    #[test]
+    fn test_directive() {
+        assert_eq!(
+            directive().parse(".rodata"),
+            Ok((
+                Statement::Directive {
+                    name: "rodata".to_string(),
+                    operands: vec![],
+                },
+                ""
+            ))
+        );
+        assert_eq!(
+            directive().parse(".ascii \"a, b\""),
+            Ok((
+                Statement::Directive {
+                    name: "ascii".to_string(),
+                    operands: vec![Operand::String(b"a, b".to_vec())],
+                },
+                ""
+            ))
+        );
+        assert_eq!(
+            directive().parse(".short 1, -2,0x3"),
+            Ok((
+                Statement::Directive {
+                    name: "short".to_string(),
+                    operands: vec![
+                        Operand::Integer(1),
+                        Operand::Integer(-2),
+                        Operand::Integer(3)
+                    ],
+                },
+                ""
+            ))
+        );
+    }
+
     // Other unit tests: try to parse various set of instructions.
 
     #[test]
@@ -628,7 +1026,7 @@ exit
         // Unexpected end of input in a register name.
         assert_eq!(
             parse("lsh r"),
-            Err("unexpected parse".to_string())
+            Err("Invalid statement \"lsh r\"".to_string())
         );
     }
 
@@ -637,7 +1035,36 @@ exit
         // Unexpected character at end of input.
         assert_eq!(
             parse("exit\n^"),
-            Err("unexpected parse".to_string())
+            Err("Invalid statement \"^\"".to_string())
+        );
+    }
+
+    #[test]
+    fn test_statement_spans() {
+        let spans = |line, name, operands| StatementSpans {
+            line,
+            name,
+            operands,
+        };
+        assert_eq!(
+            super::parse("entry: .equ A, 1 << 2\n  ldxw r1, [r2 + A]  exit")
+                .unwrap()
+                .into_iter()
+                .map(|(spans, _statement)| spans)
+                .collect::<Vec<_>>(),
+            vec![
+                spans(1, 1..6, vec![]),
+                spans(1, 8..12, vec![13..14, 16..22]),
+                spans(2, 3..7, vec![8..10, 12..20]),
+                spans(2, 22..26, vec![]),
+            ]
+        );
+        assert_eq!(
+            super::parse("exit\n  lsh r  \nexit\n\t$"),
+            Err(vec![
+                (spans(2, 3..8, vec![]), "Invalid statement \"lsh r\"".to_string()),
+                (spans(4, 2..3, vec![]), "Invalid statement \"$\"".to_string()),
+            ])
         );
     }
 
@@ -7,7 +7,8 @@
 
 //! This module translates eBPF assembly language to binary.
 
-use alloc::{format, string::{ToString, String}, vec::Vec};
+use alloc::{format, string::{ToString, String}, vec, vec::Vec};
+use core::{fmt, ops::Range};
 
 use self::InstructionType::{
     AluBinary, AluUnary, CallImm, CallReg, Endian, JumpConditional, JumpUnconditional, LoadDwImm,
@@ -15,16 +16,16 @@ use self::InstructionType::{
 };
 use crate::{
     asm_parser::{
-        parse,
-        Operand::{Integer, Label, Memory, Register},
-        Statement,
+        parse, Expression,
+        Operand::{self, Integer, Label, Memory, Register},
+        Statement, StatementSpans,
     },
     ebpf::{self, Insn},
     elf::Executable,
-    program::{BuiltinProgram, FunctionRegistry, SBPFVersion},
+    program::{BuiltinProgram, FunctionRegistry, SBPFFeaturePredicate, SBPFVersion},
     vm::ContextObject,
 };
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
 
 #[cfg(not(feature = "shuttle-test"))]
 use alloc::sync::Arc;
@@ -49,6 +50,364 @@ enum InstructionType {
     NoOperand,
 }
 
+/// Problem found in the assembly source, see [assemble_with_diagnostics]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AssemblerDiagnostic {
+    /// Line of the offending statement, starting at 1, or 0 if the problem is not tied to a
+    /// statement
+    pub line: usize,
+    /// Columns of the offending label, directive, mnemonic or operand, starting at 1 and
+    /// excluding the end
+    pub columns: Range<usize>,
+    /// Description of the problem
+    pub message: String,
+    /// Closest valid mnemonic, if the mnemonic is unknown
+    pub suggestion: Option<String>,
+    /// Names of the macros the statement was expanded from and the lines they were invoked on,
+    /// innermost first
+    pub expansions: Vec<(String, usize)>,
+}
+
+impl fmt::Display for AssemblerDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if self.line != 0 {
+            write!(f, " at line {}", self.line)?;
+        }
+        for (name, line) in self.expansions.iter() {
+            write!(f, ", in macro {name:?} invoked at line {line}")?;
+        }
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, ", did you mean {suggestion:?}?")?;
+        }
+        Ok(())
+    }
+}
+
+/// Part of a statement an error points at
+enum ErrorTarget {
+    Name,
+    Operands,
+    /// The operand which is or contains the given one
+    Operand(Operand),
+}
+
+/// Error in a single statement
+struct StatementError {
+    target: ErrorTarget,
+    message: String,
+    suggestion: Option<String>,
+}
+
+impl StatementError {
+    fn at(operand: Operand, message: String) -> Self {
+        Self {
+            target: ErrorTarget::Operand(operand),
+            message,
+            suggestion: None,
+        }
+    }
+
+    fn unexpected_operands(operands: &[Operand]) -> Self {
+        Self {
+            target: ErrorTarget::Operands,
+            message: format!("Unexpected operands: {operands:?}"),
+            suggestion: None,
+        }
+    }
+}
+
+impl From<String> for StatementError {
+    fn from(message: String) -> Self {
+        Self {
+            target: ErrorTarget::Name,
+            message,
+            suggestion: None,
+        }
+    }
+}
+
+/// Source location of a statement
+#[derive(Clone, Debug)]
+struct Location {
+    /// Position of the statement, inside the macro body if it was expanded from a macro
+    spans: StatementSpans,
+    /// Names of the macros the statement was expanded from and the lines they were
+    /// invoked on, innermost first
+    expansions: Vec<(String, usize)>,
+}
+
+impl Location {
+    fn diagnostic(&self, operands: &[Operand], error: StatementError) -> AssemblerDiagnostic {
+        let operand_spans = &self.spans.operands;
+        let columns = match &error.target {
+            ErrorTarget::Name => None,
+            ErrorTarget::Operands => operand_spans
+                .first()
+                .zip(operand_spans.last())
+                .map(|(first, last)| first.start..last.end),
+            ErrorTarget::Operand(target) => operands
+                .iter()
+                .position(|operand| match (target, operand) {
+                    (Register(reg), Memory(base, _) | Operand::MemoryExpression(base, _)) => {
+                        reg == base
+                    }
+                    (Integer(value), Memory(_, offset)) => value == offset,
+                    _ => target == operand,
+                })
+                .and_then(|index| operand_spans.get(index).cloned()),
+        };
+        AssemblerDiagnostic {
+            line: self.spans.line,
+            columns: columns.unwrap_or_else(|| self.spans.name.clone()),
+            message: error.message,
+            suggestion: error.suggestion,
+            expansions: self.expansions.clone(),
+        }
+    }
+}
+
+/// Finds the mnemonic with the smallest edit distance, if it is close enough
+fn suggest_mnemonic(
+    name: &str,
+    instruction_map: &HashMap<String, (InstructionType, u8)>,
+) -> Option<String> {
+    let edit_distance = |a: &str, b: &str| {
+        let b = b.chars().collect::<Vec<_>>();
+        let mut row = (0..=b.len()).collect::<Vec<_>>();
+        for (i, a) in a.chars().enumerate() {
+            let mut diagonal = row[0];
+            row[0] = i + 1;
+            for (j, b) in b.iter().enumerate() {
+                let substitution = diagonal + usize::from(a != *b);
+                diagonal = row[j + 1];
+                row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+            }
+        }
+        row[b.len()]
+    };
+    instruction_map
+        .keys()
+        .map(|mnemonic| (edit_distance(name, mnemonic), mnemonic))
+        .filter(|(distance, _mnemonic)| *distance <= (name.len() / 3).max(1))
+        .min()
+        .map(|(_distance, mnemonic)| mnemonic.clone())
+}
+
+type Macros = HashMap<String, Vec<(StatementSpans, Statement)>>;
+
+/// Records `.macro` definitions and replaces invocations by the bodies of the macros
+///
+/// Macros have no parameters and must be defined before they are invoked.
+fn expand_macros(
+    statements: &[(StatementSpans, Statement)],
+    expansions: &[(String, usize)],
+    macro_names: &HashSet<&str>,
+    macros: &mut Macros,
+    instruction_map: &HashMap<String, (InstructionType, u8)>,
+    output: &mut Vec<(Location, Statement)>,
+    diagnostics: &mut Vec<AssemblerDiagnostic>,
+) {
+    let mut statements = statements.iter();
+    while let Some((spans, statement)) = statements.next() {
+        let location = Location {
+            spans: spans.clone(),
+            expansions: expansions.to_vec(),
+        };
+        let mut report = |location: &Location, operands: &[Operand], error: StatementError| {
+            diagnostics.push(location.diagnostic(operands, error))
+        };
+        match statement {
+            Statement::Directive { name, operands } if name == "macro" => {
+                let mut body = Vec::new();
+                loop {
+                    match statements.next() {
+                        Some((_spans, Statement::Directive { name, .. })) if name == "endm" => {
+                            break
+                        }
+                        Some((spans, Statement::Directive { name, .. })) if name == "macro" => {
+                            let nested = Location {
+                                spans: spans.clone(),
+                                ..location.clone()
+                            };
+                            report(&nested, &[], "Nested macro definition".to_string().into());
+                        }
+                        Some(statement) => body.push(statement.clone()),
+                        None => {
+                            report(
+                                &location,
+                                operands,
+                                ".macro without .endm".to_string().into(),
+                            );
+                            break;
+                        }
+                    }
+                }
+                let macro_name = match operands.as_slice() {
+                    [Label(macro_name)] => macro_name,
+                    _ => {
+                        report(
+                            &location,
+                            operands,
+                            StatementError::unexpected_operands(operands),
+                        );
+                        continue;
+                    }
+                };
+                if macros.contains_key(macro_name) {
+                    report(
+                        &location,
+                        operands,
+                        StatementError::at(
+                            operands[0].clone(),
+                            format!("Macro {macro_name:?} redefined"),
+                        ),
+                    );
+                } else if instruction_map.contains_key(macro_name) {
+                    report(
+                        &location,
+                        operands,
+                        StatementError::at(
+                            operands[0].clone(),
+                            format!("Macro {macro_name:?} has the name of an instruction"),
+                        ),
+                    );
+                } else {
+                    macros.insert(macro_name.clone(), body);
+                }
+            }
+            Statement::Directive { name, operands } if name == "endm" => {
+                report(
+                    &location,
+                    operands,
+                    ".endm without .macro".to_string().into(),
+                );
+            }
+            Statement::Instruction { name, operands } if macro_names.contains(name.as_str()) => {
+                let body = match macros.get(name) {
+                    _ if !operands.is_empty() => {
+                        report(
+                            &location,
+                            operands,
+                            StatementError::unexpected_operands(operands),
+                        );
+                        continue;
+                    }
+                    _ if expansions.iter().any(|(expanded, _line)| expanded == name) => {
+                        report(
+                            &location,
+                            operands,
+                            format!("Macro {name:?} invokes itself").into(),
+                        );
+                        continue;
+                    }
+                    Some(body) => body.clone(),
+                    None => {
+                        report(
+                            &location,
+                            operands,
+                            format!("Macro {name:?} used before its definition").into(),
+                        );
+                        continue;
+                    }
+                };
+                let mut inner_expansions = vec![(name.clone(), spans.line)];
+                inner_expansions.extend_from_slice(expansions);
+                expand_macros(
+                    &body,
+                    &inner_expansions,
+                    macro_names,
+                    macros,
+                    instruction_map,
+                    output,
+                    diagnostics,
+                );
+            }
+            _ => output.push((location, statement.clone())),
+        }
+    }
+}
+
+fn resolve_operand(
+    operand: &Operand,
+    constants: &HashMap<String, i64>,
+) -> Result<Operand, StatementError> {
+    let lookup = |name: &str| constants.get(name).copied();
+    let evaluate = |expression: &Expression| {
+        expression
+            .evaluate(&lookup)
+            .map_err(|err| StatementError::at(operand.clone(), err))
+    };
+    Ok(match operand {
+        Label(name) => match lookup(name) {
+            Some(value) => Integer(value),
+            None => Label(name.clone()),
+        },
+        Operand::Expression(expression) => Integer(evaluate(expression)?),
+        Operand::MemoryExpression(reg, expression) => Memory(*reg, evaluate(expression)?),
+        operand => operand.clone(),
+    })
+}
+
+/// Records `.equ` constants and substitutes them in the operands of the following statements
+fn resolve_constants(
+    statements: Vec<(Location, Statement)>,
+    diagnostics: &mut Vec<AssemblerDiagnostic>,
+) -> Vec<(Location, Statement)> {
+    let mut constants = HashMap::new();
+    let mut output = Vec::with_capacity(statements.len());
+    for (location, mut statement) in statements {
+        let result = match &mut statement {
+            Statement::Directive { name, operands } if name == "equ" => {
+                let result = match operands.as_slice() {
+                    [Label(constant), value] => match resolve_operand(value, &constants) {
+                        Ok(Integer(_)) if constants.contains_key(constant) => {
+                            Err(StatementError::at(
+                                operands[0].clone(),
+                                format!("Constant {constant:?} redefined"),
+                            ))
+                        }
+                        Ok(Integer(value)) => {
+                            constants.insert(constant.clone(), value);
+                            Ok(())
+                        }
+                        Ok(Label(name)) => Err(StatementError::at(
+                            Label(name.clone()),
+                            format!("Undefined constant {name}"),
+                        )),
+                        Ok(_) => Err(StatementError::unexpected_operands(operands)),
+                        Err(err) => Err(err),
+                    },
+                    _ => Err(StatementError::unexpected_operands(operands)),
+                };
+                if let Err(err) = result {
+                    diagnostics.push(location.diagnostic(operands, err));
+                }
+                continue;
+            }
+            Statement::Directive { operands, .. } | Statement::Instruction { operands, .. } => {
+                match operands
+                    .iter()
+                    .map(|operand| resolve_operand(operand, &constants))
+                    .collect::<Result<Vec<_>, _>>()
+                {
+                    Ok(resolved) => {
+                        *operands = resolved;
+                        Ok(())
+                    }
+                    Err(err) => Err(location.diagnostic(operands, err)),
+                }
+            }
+            Statement::Label { .. } => Ok(()),
+        };
+        match result {
+            Ok(()) => output.push((location, statement)),
+            Err(diagnostic) => diagnostics.push(diagnostic),
+        }
+    }
+    output
+}
+
 fn make_instruction_map(sbpf_version: SBPFVersion) -> HashMap<String, (InstructionType, u8)> {
     let mut result = HashMap::new();
 
@@ -114,7 +473,7 @@ fn make_instruction_map(sbpf_version: SBPFVersion) -> HashMap<String, (Instructi
             result.insert(name.to_string(), (inst_type, opc))
         };
 
-        if sbpf_version == SBPFVersion::V0 {
+        if !sbpf_version.static_syscalls() {
             entry("exit", NoOperand, ebpf::EXIT);
             entry("return", NoOperand, ebpf::EXIT);
         } else {
@@ -127,7 +486,7 @@ fn make_instruction_map(sbpf_version: SBPFVersion) -> HashMap<String, (Instructi
         entry(
             "syscall",
             Syscall,
-            if sbpf_version == SBPFVersion::V0 {
+            if !sbpf_version.static_syscalls() {
                 ebpf::CALL_IMM
             } else {
                 ebpf::SYSCALL
@@ -256,18 +615,63 @@ fn make_instruction_map(sbpf_version: SBPFVersion) -> HashMap<String, (Instructi
     result
 }
 
-fn insn(opc: u8, dst: i64, src: i64, off: i64, imm: i64) -> Result<Insn, String> {
+/// The [SBPFVersion::FEATURES] entry a mnemonic depends on and whether the mnemonic is
+/// available when the feature is enabled or when it is disabled
+fn feature_gate(name: &str) -> Option<(&'static str, SBPFFeaturePredicate, bool)> {
+    Some(match name {
+        "neg" | "neg32" | "neg64" => ("disable_neg", SBPFVersion::disable_neg, false),
+        "lddw" => ("disable_lddw", SBPFVersion::disable_lddw, false),
+        "hor" | "hor64" => ("disable_lddw", SBPFVersion::disable_lddw, true),
+        "le16" | "le32" | "le64" => ("disable_le", SBPFVersion::disable_le, false),
+        "mul" | "mul32" | "mul64" | "div" | "div32" | "div64" | "mod" | "mod32" | "mod64" => {
+            ("enable_pqr", SBPFVersion::enable_pqr, false)
+        }
+        "lmul" | "lmul32" | "lmul64" | "uhmul" | "uhmul64" | "shmul" | "shmul64" | "udiv"
+        | "udiv32" | "udiv64" | "urem" | "urem32" | "urem64" | "sdiv" | "sdiv32" | "sdiv64"
+        | "srem" | "srem32" | "srem64" => ("enable_pqr", SBPFVersion::enable_pqr, true),
+        _ => return None,
+    })
+}
+
+/// Rejects mnemonics which the verifier would reject for the given version
+fn check_feature_gate(name: &str, sbpf_version: SBPFVersion) -> Result<(), StatementError> {
+    match feature_gate(name) {
+        Some((feature, predicate, available_if)) if predicate(sbpf_version) != available_if => {
+            Err(format!(
+                "Instruction {name:?} is not available in SBPF {sbpf_version} because {feature} \
+                 is {}",
+                if available_if { "disabled" } else { "enabled" }
+            )
+            .into())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn insn(opc: u8, dst: i64, src: i64, off: i64, imm: i64) -> Result<Insn, StatementError> {
     if !(0..16).contains(&dst) {
-        return Err(format!("Invalid destination register {dst}"));
+        return Err(StatementError::at(
+            Register(dst),
+            format!("Invalid destination register {dst}"),
+        ));
     }
     if !(0..16).contains(&src) {
-        return Err(format!("Invalid source register {src}"));
+        return Err(StatementError::at(
+            Register(src),
+            format!("Invalid source register {src}"),
+        ));
     }
     if off < i16::MIN as i64 || off > i16::MAX as i64 {
-        return Err(format!("Invalid offset {off}"));
+        return Err(StatementError::at(
+            Integer(off),
+            format!("Invalid offset {off}"),
+        ));
     }
     if imm < i32::MIN as i64 || imm > i32::MAX as i64 {
-        return Err(format!("Invalid immediate {imm}"));
+        return Err(StatementError::at(
+            Integer(imm),
+            format!("Invalid immediate {imm}"),
+        ));
     }
     Ok(Insn {
         ptr: 0,
@@ -279,24 +683,84 @@ fn insn(opc: u8, dst: i64, src: i64, off: i64, imm: i64) -> Result<Insn, String>
     })
 }
 
+fn resolve_data_label(
+    rodata_vaddr: u64,
+    data_labels: &HashMap<&str, usize>,
+    label: &str,
+) -> Result<i64, StatementError> {
+    data_labels
+        .get(label)
+        .map(|offset| (rodata_vaddr + *offset as u64) as i64)
+        .ok_or_else(|| {
+            StatementError::at(Label(label.to_string()), format!("Label not found {label}"))
+        })
+}
+
+fn emit_data(rodata: &mut Vec<u8>, name: &str, operands: &[Operand]) -> Result<(), StatementError> {
+    let size = match name {
+        "byte" => 1,
+        "short" => 2,
+        "word" => 4,
+        "quad" => 8,
+        _ => {
+            for operand in operands {
+                match operand {
+                    Operand::String(bytes) => rodata.extend_from_slice(bytes),
+                    _ => return Err(StatementError::unexpected_operands(operands)),
+                }
+                if name == "asciz" {
+                    rodata.push(0);
+                }
+            }
+            return Ok(());
+        }
+    };
+    for operand in operands {
+        match operand {
+            Integer(value)
+                if size == 8 || (-(1i64 << (size * 8 - 1))..1i64 << (size * 8)).contains(value) =>
+            {
+                rodata.extend_from_slice(&value.to_le_bytes()[..size]);
+            }
+            Integer(value) => {
+                return Err(StatementError::at(
+                    Integer(*value),
+                    format!("Invalid .{name} value {value}"),
+                ))
+            }
+            _ => return Err(StatementError::unexpected_operands(operands)),
+        }
+    }
+    Ok(())
+}
+
 fn resolve_label(
     insn_ptr: usize,
     labels: &HashMap<&str, usize>,
     label: &str,
-) -> Result<i64, String> {
+) -> Result<i64, StatementError> {
     labels
         .get(label)
         .map(|target_pc| *target_pc as i64 - insn_ptr as i64 - 1)
-        .ok_or_else(|| format!("Label not found {label}"))
+        .ok_or_else(|| {
+            StatementError::at(Label(label.to_string()), format!("Label not found {label}"))
+        })
 }
 
 /// Parse assembly source and translate to binary.
 ///
+/// The encoding follows the highest SBPF version enabled in the config of the loader, and
+/// mnemonics which are not available in that version are rejected.
+///
 /// # Examples
 ///
 /// ```
-/// use solana_sbpf::{assembler::assemble, program::BuiltinProgram, vm::Config};
+/// use solana_sbpf::{assembler::assemble, program::{BuiltinProgram, SBPFVersion}, vm::Config};
 /// use test_utils::TestContextObject;
+/// let config = Config {
+///     enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+///     ..Config::default()
+/// };
 /// let executable = assemble::<TestContextObject>(
 ///    "add64 r1, 0x605
 ///     mov64 r2, 0x32
@@ -304,7 +768,7 @@ fn resolve_label(
 ///     be16 r0
 ///     neg64 r2
 ///     exit",
-///     std::sync::Arc::new(BuiltinProgram::new_mock()),
+///     std::sync::Arc::new(BuiltinProgram::new_loader(config)),
 /// ).unwrap();
 /// let program = executable.get_text_bytes().1;
 /// println!("{:?}", program);
@@ -314,7 +778,7 @@ fn resolve_label(
 /// #              0xbf, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
 /// #              0xdc, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00,
 /// #              0x87, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-/// #              0x9d, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+/// #              0x95, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
 /// ```
 ///
 /// This will produce the following output:
@@ -331,182 +795,335 @@ pub fn assemble<C: ContextObject>(
     src: &str,
     loader: Arc<BuiltinProgram<C>>,
 ) -> Result<Executable<C>, String> {
+    assemble_with_diagnostics(src, loader).map_err(|diagnostics| {
+        diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    })
+}
+
+/// Like [assemble], but reports every problem found in the source
+///
+/// The diagnostics are ordered by their position in the source.
+pub fn assemble_with_diagnostics<C: ContextObject>(
+    src: &str,
+    loader: Arc<BuiltinProgram<C>>,
+) -> Result<Executable<C>, Vec<AssemblerDiagnostic>> {
     let sbpf_version = *loader.get_config().enabled_sbpf_versions.end();
 
-    let statements = parse(src)?;
     let instruction_map = make_instruction_map(sbpf_version);
+    let parsed = parse(src).map_err(|errors| {
+        errors
+            .into_iter()
+            .map(|(spans, message)| AssemblerDiagnostic {
+                line: spans.line,
+                columns: spans.name,
+                message,
+                suggestion: None,
+                expansions: Vec::new(),
+            })
+            .collect::<Vec<_>>()
+    })?;
+    let macro_names = parsed
+        .iter()
+        .filter_map(|(_spans, statement)| match statement {
+            Statement::Directive { name, operands } if name == "macro" => match operands.first() {
+                Some(Label(macro_name)) => Some(macro_name.as_str()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+    let mut diagnostics = Vec::new();
+    let mut expanded = Vec::new();
+    expand_macros(
+        &parsed,
+        &[],
+        &macro_names,
+        &mut Macros::new(),
+        &instruction_map,
+        &mut expanded,
+        &mut diagnostics,
+    );
+    let statements = resolve_constants(expanded, &mut diagnostics);
     let mut insn_ptr = 0;
     let mut function_registry = FunctionRegistry::default();
     let mut labels = HashMap::new();
     labels.insert("entrypoint", 0);
-    for statement in statements.iter() {
-        match statement {
-            Statement::Label { name } => {
-                if name.starts_with("function_") || name == "entrypoint" {
-                    function_registry
-                        .register_function(insn_ptr as u32, name.as_bytes(), insn_ptr)
-                        .map_err(|_| format!("Label hash collision {name}"))?;
+    let mut in_rodata = false;
+    let mut rodata = Vec::new();
+    let mut data_labels = HashMap::new();
+    for (location, statement) in statements.iter() {
+        (|| {
+            match statement {
+                Statement::Label { name } if in_rodata => {
+                    data_labels.insert(name.as_str(), rodata.len());
                 }
-                labels.insert(name.as_str(), insn_ptr);
-            }
-            Statement::Directive { name, operands } =>
-            {
-                #[allow(clippy::single_match)]
-                match (name.as_str(), operands.as_slice()) {
-                    ("fill", [Integer(repeat), Integer(_value)]) => {
-                        insn_ptr += *repeat as usize;
+                Statement::Label { name } => {
+                    if name.starts_with("function_") || name == "entrypoint" {
+                        function_registry
+                            .register_function_keep_name(insn_ptr as u32, name.as_bytes(), insn_ptr)
+                            .map_err(|_| format!("Label hash collision {name}"))?;
                     }
-                    _ => {}
+                    labels.insert(name.as_str(), insn_ptr);
+                }
+                Statement::Directive { name, operands } => {
+                    match (name.as_str(), operands.as_slice()) {
+                        ("text", []) => in_rodata = false,
+                        ("rodata", []) => in_rodata = true,
+                        ("fill", [Integer(repeat), Integer(_value)]) if !in_rodata => {
+                            insn_ptr += *repeat as usize;
+                        }
+                        ("byte" | "short" | "word" | "quad" | "ascii" | "asciz", _)
+                            if in_rodata =>
+                        {
+                            emit_data(&mut rodata, name, operands)?;
+                        }
+                        ("align", [Integer(align)]) if in_rodata => {
+                            if *align <= 0 || (*align as u64).count_ones() != 1 {
+                                return Err(StatementError::at(
+                                    Integer(*align),
+                                    format!("Invalid alignment {align}"),
+                                ));
+                            }
+                            rodata.resize(rodata.len().next_multiple_of(*align as usize), 0);
+                        }
+                        (
+                            "fill" | "byte" | "short" | "word" | "quad" | "ascii" | "asciz"
+                            | "align",
+                            _,
+                        ) if in_rodata == (name == "fill") => {
+                            return Err(format!(
+                                "Directive {name:?} not allowed in the .{} section",
+                                if in_rodata { "rodata" } else { "text" }
+                            )
+                            .into());
+                        }
+                        _ => return Err(format!("Invalid directive {name:?}").into()),
+                    }
+                }
+                Statement::Instruction { name, .. } => {
+                    if in_rodata {
+                        return Err(format!(
+                            "Instruction {name:?} not allowed in the .rodata section"
+                        )
+                        .into());
+                    }
+                    insn_ptr += if name == "lddw" { 2 } else { 1 };
                 }
             }
-            Statement::Instruction { name, .. } => {
-                insn_ptr += if name == "lddw" { 2 } else { 1 };
-            }
-        }
+            Ok(())
+        })()
+        .unwrap_or_else(|err: StatementError| {
+            diagnostics.push(location.diagnostic(statement.operands(), err))
+        });
     }
+    let rodata_vaddr = if sbpf_version.enable_lower_bytecode_vaddr() {
+        ebpf::MM_RODATA_START
+    } else {
+        ebpf::MM_RODATA_START + insn_ptr as u64 * ebpf::INSN_SIZE as u64
+    };
     insn_ptr = 0;
     let mut instructions: Vec<Insn> = Vec::new();
-    for statement in statements.iter() {
-        match statement {
-            Statement::Label { .. } => {}
-            Statement::Directive { name, operands } =>
-            {
-                #[allow(clippy::single_match)]
-                match (name.as_str(), operands.as_slice()) {
-                    ("fill", [Integer(repeat), Integer(value)]) => {
-                        for _ in 0..*repeat {
-                            instructions.push(Insn {
-                                ptr: insn_ptr,
-                                opc: *value as u8,
-                                dst: (*value >> 8) as u8 & 0xF,
-                                src: (*value >> 12) as u8 & 0xF,
-                                off: (*value >> 16) as u16 as i16,
-                                imm: (*value >> 32) as u32 as i64,
-                            });
-                            insn_ptr += 1;
+    for (location, statement) in statements.iter() {
+        (|| {
+            match statement {
+                Statement::Label { .. } => {}
+                Statement::Directive { name, operands } => {
+                    #[allow(clippy::single_match)]
+                    match (name.as_str(), operands.as_slice()) {
+                        ("fill", [Integer(repeat), Integer(value)]) => {
+                            for _ in 0..*repeat {
+                                instructions.push(Insn {
+                                    ptr: insn_ptr,
+                                    opc: *value as u8,
+                                    dst: (*value >> 8) as u8 & 0xF,
+                                    src: (*value >> 12) as u8 & 0xF,
+                                    off: (*value >> 16) as u16 as i16,
+                                    imm: (*value >> 32) as u32 as i64,
+                                });
+                                insn_ptr += 1;
+                            }
                         }
+                        _ => {}
                     }
-                    _ => return Err(format!("Invalid directive {name:?}")),
                 }
-            }
-            Statement::Instruction { name, operands } => {
-                let name = name.as_str();
-                match instruction_map.get(name) {
-                    Some(&(inst_type, opc)) => {
-                        let mut insn = match (inst_type, operands.as_slice()) {
-                            (AluBinary, [Register(dst), Register(src)]) => {
-                                insn(opc | ebpf::BPF_X, *dst, *src, 0, 0)
-                            }
-                            (AluBinary, [Register(dst), Integer(imm)]) => {
-                                insn(opc | ebpf::BPF_K, *dst, 0, 0, *imm)
-                            }
-                            (AluUnary, [Register(dst)]) => insn(opc, *dst, 0, 0, 0),
-                            (LoadReg, [Register(dst), Memory(src, off)])
-                            | (StoreReg, [Memory(dst, off), Register(src)]) => {
-                                insn(opc, *dst, *src, *off, 0)
-                            }
-                            (StoreImm, [Memory(dst, off), Integer(imm)]) => {
-                                insn(opc, *dst, 0, *off, *imm)
-                            }
-                            (NoOperand, []) => insn(opc, 0, 0, 0, 0),
-                            (JumpUnconditional, [Integer(off)]) => insn(opc, 0, 0, *off, 0),
-                            (JumpConditional, [Register(dst), Register(src), Integer(off)]) => {
-                                insn(opc | ebpf::BPF_X, *dst, *src, *off, 0)
-                            }
-                            (JumpConditional, [Register(dst), Integer(imm), Integer(off)]) => {
-                                insn(opc | ebpf::BPF_K, *dst, 0, *off, *imm)
-                            }
-                            (JumpUnconditional, [Label(label)]) => {
-                                insn(opc, 0, 0, resolve_label(insn_ptr, &labels, label)?, 0)
-                            }
-                            (CallImm, [Integer(imm)]) => {
-                                let instr_imm = if sbpf_version.static_syscalls() {
-                                    *imm
-                                } else {
-                                    *imm + insn_ptr as i64 + 1
-                                };
-                                let target_pc = *imm + insn_ptr as i64 + 1;
-                                let label = format!("function_{}", target_pc as usize);
-                                function_registry
-                                    .register_function(
-                                        target_pc as u32,
-                                        label.as_bytes(),
-                                        target_pc as usize,
+                Statement::Instruction { name, operands } => {
+                    let name = name.as_str();
+                    match instruction_map.get(name) {
+                        Some(&(inst_type, opc)) => {
+                            check_feature_gate(name, sbpf_version)?;
+                            let data_address;
+                            let operands = match (inst_type, operands.as_slice()) {
+                                (LoadDwImm, [dst, Label(label)]) => {
+                                    let address =
+                                        resolve_data_label(rodata_vaddr, &data_labels, label)?;
+                                    data_address = [dst.clone(), Integer(address)];
+                                    &data_address[..]
+                                }
+                                _ => operands.as_slice(),
+                            };
+                            let mut insn = match (inst_type, operands) {
+                                (AluBinary, [Register(dst), Register(src)]) => {
+                                    insn(opc | ebpf::BPF_X, *dst, *src, 0, 0)
+                                }
+                                (AluBinary, [Register(dst), Integer(imm)]) => {
+                                    insn(opc | ebpf::BPF_K, *dst, 0, 0, *imm)
+                                }
+                                (AluUnary, [Register(dst)]) => insn(opc, *dst, 0, 0, 0),
+                                (LoadReg, [Register(dst), Memory(src, off)])
+                                | (StoreReg, [Memory(dst, off), Register(src)]) => {
+                                    insn(opc, *dst, *src, *off, 0)
+                                }
+                                (StoreImm, [Memory(dst, off), Integer(imm)]) => {
+                                    insn(opc, *dst, 0, *off, *imm)
+                                }
+                                (NoOperand, []) => insn(opc, 0, 0, 0, 0),
+                                (JumpUnconditional, [Integer(off)]) => insn(opc, 0, 0, *off, 0),
+                                (JumpConditional, [Register(dst), Register(src), Integer(off)]) => {
+                                    insn(opc | ebpf::BPF_X, *dst, *src, *off, 0)
+                                }
+                                (JumpConditional, [Register(dst), Integer(imm), Integer(off)]) => {
+                                    insn(opc | ebpf::BPF_K, *dst, 0, *off, *imm)
+                                }
+                                (JumpUnconditional, [Label(label)]) => {
+                                    insn(opc, 0, 0, resolve_label(insn_ptr, &labels, label)?, 0)
+                                }
+                                (CallImm, [Integer(imm)]) => {
+                                    let instr_imm = if sbpf_version.static_syscalls() {
+                                        *imm
+                                    } else {
+                                        *imm + insn_ptr as i64 + 1
+                                    };
+                                    let target_pc = *imm + insn_ptr as i64 + 1;
+                                    let label = format!("function_{}", target_pc as usize);
+                                    function_registry
+                                        .register_function_keep_name(
+                                            target_pc as u32,
+                                            label.as_bytes(),
+                                            target_pc as usize,
+                                        )
+                                        .map_err(|_| format!("Label hash collision {name}"))?;
+                                    insn(opc, 0, 0, 0, instr_imm)
+                                }
+                                (CallReg, [Register(dst)]) => {
+                                    if sbpf_version.callx_uses_src_reg() {
+                                        insn(opc, 0, *dst, 0, 0)
+                                    } else {
+                                        insn(opc, 0, 0, 0, *dst)
+                                    }
+                                }
+                                (JumpConditional, [Register(dst), Register(src), Label(label)]) => {
+                                    insn(
+                                        opc | ebpf::BPF_X,
+                                        *dst,
+                                        *src,
+                                        resolve_label(insn_ptr, &labels, label)?,
+                                        0,
                                     )
-                                    .map_err(|_| format!("Label hash collision {name}"))?;
-                                insn(opc, 0, 0, 0, instr_imm)
-                            }
-                            (CallReg, [Register(dst)]) => {
-                                if sbpf_version.callx_uses_src_reg() {
-                                    insn(opc, 0, *dst, 0, 0)
-                                } else {
-                                    insn(opc, 0, 0, 0, *dst)
                                 }
-                            }
-                            (JumpConditional, [Register(dst), Register(src), Label(label)]) => {
-                                insn(
-                                    opc | ebpf::BPF_X,
-                                    *dst,
-                                    *src,
-                                    resolve_label(insn_ptr, &labels, label)?,
+                                (JumpConditional, [Register(dst), Integer(imm), Label(label)]) => {
+                                    insn(
+                                        opc | ebpf::BPF_K,
+                                        *dst,
+                                        0,
+                                        resolve_label(insn_ptr, &labels, label)?,
+                                        *imm,
+                                    )
+                                }
+                                (Syscall, [Label(label)]) => insn(
+                                    opc,
                                     0,
-                                )
-                            }
-                            (JumpConditional, [Register(dst), Integer(imm), Label(label)]) => insn(
-                                opc | ebpf::BPF_K,
-                                *dst,
-                                0,
-                                resolve_label(insn_ptr, &labels, label)?,
-                                *imm,
-                            ),
-                            (Syscall, [Label(label)]) => insn(
-                                opc,
-                                0,
-                                0,
-                                0,
-                                ebpf::hash_symbol_name(label.as_bytes()) as i32 as i64,
-                            ),
-                            (Syscall, [Integer(imm)]) => insn(opc, 0, 0, 0, *imm),
-                            (CallImm, [Label(label)]) => {
-                                let label: &str = label;
-                                let mut target_pc = *labels
-                                    .get(label)
-                                    .ok_or_else(|| format!("Label not found {label}"))?
-                                    as i64;
-                                if sbpf_version.static_syscalls() {
-                                    target_pc = target_pc - insn_ptr as i64 - 1;
+                                    0,
+                                    0,
+                                    ebpf::hash_symbol_name(label.as_bytes()) as i32 as i64,
+                                ),
+                                (Syscall, [Integer(imm)]) => insn(opc, 0, 0, 0, *imm),
+                                (CallImm, [Label(label)]) => {
+                                    let label: &str = label;
+                                    let mut target_pc = *labels.get(label).ok_or_else(|| {
+                                        StatementError::at(
+                                            Label(label.to_string()),
+                                            format!("Label not found {label}"),
+                                        )
+                                    })?
+                                        as i64;
+                                    if sbpf_version.static_syscalls() {
+                                        target_pc = target_pc - insn_ptr as i64 - 1;
+                                    }
+                                    insn(opc, 0, 1, 0, target_pc)
+                                }
+                                (Endian(size), [Register(dst)]) => insn(opc, *dst, 0, 0, size),
+                                (LoadDwImm, [Register(dst), Integer(imm)]) => {
+                                    insn(opc, *dst, 0, 0, (*imm << 32) >> 32)
+                                }
+                                _ => Err(StatementError::unexpected_operands(operands)),
+                            }?;
+                            insn.ptr = insn_ptr;
+                            instructions.push(insn);
+                            insn_ptr += 1;
+                            if let LoadDwImm = inst_type {
+                                if let Integer(imm) = operands[1] {
+                                    instructions.push(Insn {
+                                        ptr: insn_ptr,
+                                        imm: imm >> 32,
+                                        ..Insn::default()
+                                    });
+                                    insn_ptr += 1;
                                 }
-                                insn(opc, 0, 1, 0, target_pc)
-                            }
-                            (Endian(size), [Register(dst)]) => insn(opc, *dst, 0, 0, size),
-                            (LoadDwImm, [Register(dst), Integer(imm)]) => {
-                                insn(opc, *dst, 0, 0, (*imm << 32) >> 32)
-                            }
-                            _ => Err(format!("Unexpected operands: {operands:?}")),
-                        }?;
-                        insn.ptr = insn_ptr;
-                        instructions.push(insn);
-                        insn_ptr += 1;
-                        if let LoadDwImm = inst_type {
-                            if let Integer(imm) = operands[1] {
-                                instructions.push(Insn {
-                                    ptr: insn_ptr,
-                                    imm: imm >> 32,
-                                    ..Insn::default()
-                                });
-                                insn_ptr += 1;
                             }
                         }
+                        None => {
+                            return Err(StatementError {
+                                target: ErrorTarget::Name,
+                                message: format!("Invalid instruction {name:?}"),
+                                suggestion: suggest_mnemonic(name, &instruction_map),
+                            })
+                        }
                     }
-                    None => return Err(format!("Invalid instruction {name:?}")),
                 }
             }
-        }
+            Ok(())
+        })()
+        .unwrap_or_else(|err: StatementError| {
+            diagnostics.push(location.diagnostic(statement.operands(), err))
+        });
+    }
+    if !diagnostics.is_empty() {
+        diagnostics.sort_by_key(|diagnostic| {
+            let invocation_line = diagnostic
+                .expansions
+                .last()
+                .map_or(diagnostic.line, |(_name, line)| *line);
+            (invocation_line, diagnostic.line, diagnostic.columns.start)
+        });
+        return Err(diagnostics);
     }
     let program = instructions
         .iter()
         .flat_map(|insn| insn.to_vec())
         .collect::<Vec<_>>();
-    Executable::<C>::from_text_bytes(&program, loader, sbpf_version, function_registry)
-        .map_err(|err| format!("Executable constructor {err:?}"))
+    let executable = if rodata.is_empty() {
+        Executable::<C>::from_text_bytes(&program, loader, sbpf_version, function_registry)
+    } else {
+        Executable::<C>::from_text_and_rodata(
+            &program,
+            rodata,
+            rodata_vaddr,
+            loader,
+            sbpf_version,
+            function_registry,
+        )
+    };
+    executable.map_err(|err| {
+        vec![AssemblerDiagnostic {
+            line: 0,
+            columns: 0..0,
+            message: format!("Executable constructor {err:?}"),
+            suggestion: None,
+            expansions: Vec::new(),
+        }]
+    })
 }
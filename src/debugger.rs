@@ -72,7 +72,7 @@ pub fn execute<C: ContextObject>(interpreter: &mut Interpreter<C>, port: u16) {
                 let conn = dbg_inner.borrow_conn();
                 match interpreter.debug_state {
                     DebugState::Step => {
-                        let mut stop_reason = if interpreter.step() {
+                        let mut stop_reason = if interpreter.advance() {
                             SingleThreadStopReason::DoneStep
                         } else if let ProgramResult::Ok(result) = &interpreter.vm.program_result {
                             SingleThreadStopReason::Exited(*result as u8)
@@ -89,7 +89,7 @@ pub fn execute<C: ContextObject>(interpreter: &mut Interpreter<C>, port: u16) {
                             let byte = dbg_inner.borrow_conn().read().unwrap();
                             break dbg_inner.incoming_data(interpreter, byte).unwrap();
                         }
-                        if interpreter.step() {
+                        if interpreter.advance() {
                             if interpreter.breakpoints.contains(&interpreter.get_dbg_pc()) {
                                 break dbg_inner
                                     .report_stop(interpreter, SingleThreadStopReason::SwBreak(()))
@@ -8,281 +8,1071 @@
 //! Functions in this module are used to handle eBPF programs with a higher level representation,
 //! for example to disassemble the code into a human-readable format.
 
-use alloc::{format, string::{ToString, String}};
+use alloc::{format, string::{ToString, String}, vec::Vec};
+use core::fmt::{self, Write};
 
 use crate::{
     ebpf,
+    elf::Executable,
+    error::EbpfError,
     program::{BuiltinProgram, FunctionRegistry, SBPFVersion},
-    static_analysis::CfgNode,
+    report::write_json_string,
+    static_analysis::{Analysis, CfgNode},
     vm::ContextObject,
 };
 use alloc::collections::BTreeMap;
 
-fn resolve_label(cfg_nodes: &BTreeMap<usize, CfgNode>, pc: usize) -> &str {
+/// Returns the label of the basic block at `pc`, or `pc` itself if there is none
+fn resolve_label(cfg_nodes: &BTreeMap<usize, CfgNode>, pc: isize) -> String {
     cfg_nodes
-        .get(&pc)
-        .map(|cfg_node| cfg_node.label.as_str())
-        .unwrap_or("[invalid]")
+        .get(&(pc as usize))
+        .filter(|_| pc >= 0)
+        .map(|cfg_node| cfg_node.label.clone())
+        .unwrap_or_else(|| pc.to_string())
 }
 
 #[inline]
-fn alu_imm_str(name: &str, insn: &ebpf::Insn) -> String {
-    format!("{} r{}, {}", name, insn.dst, insn.imm)
+fn imm_str(imm: i64, options: DisassemblyOptions) -> String {
+    match options.imm_radix {
+        Radix::Decimal => imm.to_string(),
+        // The assembler only accepts immediates in the range of i32
+        Radix::Hexadecimal if options.style == DisassemblyStyle::Reassemblable && imm < 0 => {
+            format!("-{:#x}", -imm)
+        }
+        Radix::Hexadecimal => format!("{:#x}", imm as u32),
+    }
 }
 
-#[inline]
-fn alu_reg_str(name: &str, insn: &ebpf::Insn) -> String {
-    format!("{} r{}, r{}", name, insn.dst, insn.src)
-}
+/// Formats an offset with its sign, like `+0x10` or `-0x8`
+struct SignedOffset(i16);
 
-#[inline]
-fn byteswap_str(name: &str, insn: &ebpf::Insn) -> String {
-    match insn.imm {
-        16 | 32 | 64 => {}
-        _ => jam_pvm_common::info!("[Disassembler] Warning: Invalid offset value for {name} insn"),
+impl fmt::Display for SignedOffset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0 < 0 {
+            write!(f, "-{:#x}", -(self.0 as i32))
+        } else {
+            write!(f, "+{:#x}", self.0)
+        }
     }
-    format!("{}{} r{}", name, insn.imm, insn.dst)
+}
+
+/// Returns the statically known target of `callx`, see [CfgNode::indirect_call_target]
+fn indirect_call_target(insn: &ebpf::Insn, cfg_nodes: &BTreeMap<usize, CfgNode>) -> Option<usize> {
+    cfg_nodes
+        .range(..=insn.ptr)
+        .next_back()
+        .and_then(|(_, cfg_node)| cfg_node.indirect_call_target)
 }
 
 #[inline]
-fn signed_off_str(value: i16) -> String {
-    if value < 0 {
-        format!("-{:#x}", -(value as isize))
-    } else {
-        format!("+{value:#x}")
+fn callx_str(
+    name: &str,
+    reg: u8,
+    insn: &ebpf::Insn,
+    cfg_nodes: &BTreeMap<usize, CfgNode>,
+) -> String {
+    match indirect_call_target(insn, cfg_nodes) {
+        Some(target_pc) => format!(
+            "{name} r{reg} ; -> {}",
+            resolve_label(cfg_nodes, target_pc as isize)
+        ),
+        None => format!("{name} r{reg}"),
     }
 }
 
-#[inline]
-fn ld_st_imm_str(name: &str, insn: &ebpf::Insn) -> String {
-    format!(
-        "{} [r{}{}], {}",
-        name,
-        insn.dst,
-        signed_off_str(insn.off),
-        insn.imm
-    )
+/// Returns the number of bytes a load or store accesses
+fn memory_width(mnemonic: &str) -> Option<usize> {
+    match mnemonic {
+        "ldxb" | "stb" | "stxb" => Some(1),
+        "ldxh" | "sth" | "stxh" => Some(2),
+        "ldxw" | "stw" | "stxw" => Some(4),
+        "ldxdw" | "stdw" | "stxdw" => Some(8),
+        _ => None,
+    }
 }
 
 #[inline]
-fn ld_reg_str(name: &str, insn: &ebpf::Insn) -> String {
-    format!(
-        "{} r{}, [r{}{}]",
-        name,
-        insn.dst,
-        insn.src,
-        signed_off_str(insn.off)
-    )
+fn byteswap_name(name: &'static str, insn: &ebpf::Insn) -> &'static str {
+    match (name, insn.imm) {
+        ("le", 16) => "le16",
+        ("le", 32) => "le32",
+        ("le", 64) => "le64",
+        ("be", 16) => "be16",
+        ("be", 32) => "be32",
+        ("be", 64) => "be64",
+        ("le", _) => "le??",
+        _ => "be??",
+    }
 }
 
-#[inline]
-fn st_reg_str(name: &str, insn: &ebpf::Insn) -> String {
-    format!(
-        "{} [r{}{}], r{}",
-        name,
-        insn.dst,
-        signed_off_str(insn.off),
-        insn.src
-    )
+/// Operands of a [DecodedInsn], in the order the assembler code lists them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operands {
+    /// `exit` and `return`
+    None,
+    /// `neg64 r1` and valid byte swaps like `le16 r1`
+    Register {
+        /// Destination register
+        dst: u8,
+    },
+    /// `add64 r1, 2`
+    RegisterImmediate {
+        /// Destination register
+        dst: u8,
+        /// Immediate
+        imm: i64,
+    },
+    /// `add64 r1, r2`
+    RegisterRegister {
+        /// Destination register
+        dst: u8,
+        /// Source register
+        src: u8,
+    },
+    /// `lddw r1, 0x100000000`
+    Immediate64 {
+        /// Destination register
+        dst: u8,
+        /// Immediate spanning both slots
+        imm: u64,
+    },
+    /// `ldxw r1, [r2+0x10]`
+    Load {
+        /// Destination register
+        dst: u8,
+        /// Source register
+        src: u8,
+        /// Offset of the memory address
+        off: i16,
+    },
+    /// `stw [r1+0x8], 2`
+    StoreImmediate {
+        /// Destination register
+        dst: u8,
+        /// Offset of the memory address
+        off: i16,
+        /// Immediate
+        imm: i64,
+    },
+    /// `stxw [r1+0x8], r2`
+    StoreRegister {
+        /// Destination register
+        dst: u8,
+        /// Offset of the memory address
+        off: i16,
+        /// Source register
+        src: u8,
+    },
+    /// `ja 5`, the target being an absolute pc
+    Jump {
+        /// Pc of the jump target
+        target_pc: isize,
+    },
+    /// `jeq r1, 2, 5`
+    JumpImmediate {
+        /// Destination register
+        dst: u8,
+        /// Immediate
+        imm: i64,
+        /// Pc of the jump target
+        target_pc: isize,
+    },
+    /// `jeq r1, r2, 5`
+    JumpRegister {
+        /// Destination register
+        dst: u8,
+        /// Source register
+        src: u8,
+        /// Pc of the jump target
+        target_pc: isize,
+    },
+    /// `call 0x2a`, calling a function of the executable
+    ///
+    /// Without [SBPFVersion::static_syscalls] the key might belong to the function registry of
+    /// the loader instead, then the instruction is a syscall.
+    Call {
+        /// Key in the function registry
+        key: u32,
+    },
+    /// `callx r1`
+    CallRegister {
+        /// Register holding the target address
+        reg: u8,
+    },
+    /// `syscall 0x2a`, calling a builtin of the loader
+    Syscall {
+        /// Key in the function registry of the loader
+        key: u32,
+    },
+    /// A byte swap with an invalid width, like `le?? r1, imm=7`
+    InvalidByteSwap {
+        /// Destination register
+        dst: u8,
+        /// Immediate, which should be the width in bits
+        imm: i64,
+    },
+    /// An opcode which is not valid in the SBPF version
+    Unknown {
+        /// Opcode
+        opc: u8,
+    },
 }
 
-#[inline]
-fn jmp_imm_str(name: &str, insn: &ebpf::Insn, cfg_nodes: &BTreeMap<usize, CfgNode>) -> String {
-    let target_pc = (insn.ptr as isize + insn.off as isize + 1) as usize;
-    format!(
-        "{} r{}, {}, {}",
-        name,
-        insn.dst,
-        insn.imm,
-        resolve_label(cfg_nodes, target_pc)
-    )
+/// An eBPF instruction decoded without resolving any names, see [decode]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodedInsn {
+    /// Name of the instruction, see [DisassembledInstruction::mnemonic]
+    pub mnemonic: &'static str,
+    /// Operands of the instruction
+    pub operands: Operands,
 }
 
-#[inline]
-fn jmp_reg_str(name: &str, insn: &ebpf::Insn, cfg_nodes: &BTreeMap<usize, CfgNode>) -> String {
-    let target_pc = (insn.ptr as isize + insn.off as isize + 1) as usize;
-    format!(
-        "{} r{}, r{}, {}",
-        name,
-        insn.dst,
-        insn.src,
-        resolve_label(cfg_nodes, target_pc)
-    )
+impl fmt::Display for DecodedInsn {
+    /// Formats the assembler code with numeric targets and keys, without allocating
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = self.mnemonic;
+        match self.operands {
+            Operands::None => f.write_str(name),
+            Operands::Register { dst } => write!(f, "{name} r{dst}"),
+            Operands::RegisterImmediate { dst, imm } => write!(f, "{name} r{dst}, {imm}"),
+            Operands::RegisterRegister { dst, src } => write!(f, "{name} r{dst}, r{src}"),
+            Operands::Immediate64 { dst, imm } => write!(f, "{name} r{dst}, {imm:#x}"),
+            Operands::Load { dst, src, off } => {
+                write!(f, "{name} r{dst}, [r{src}{}]", SignedOffset(off))
+            }
+            Operands::StoreImmediate { dst, off, imm } => {
+                write!(f, "{name} [r{dst}{}], {imm}", SignedOffset(off))
+            }
+            Operands::StoreRegister { dst, off, src } => {
+                write!(f, "{name} [r{dst}{}], r{src}", SignedOffset(off))
+            }
+            Operands::Jump { target_pc } => write!(f, "{name} {target_pc}"),
+            Operands::JumpImmediate {
+                dst,
+                imm,
+                target_pc,
+            } => write!(f, "{name} r{dst}, {imm}, {target_pc}"),
+            Operands::JumpRegister {
+                dst,
+                src,
+                target_pc,
+            } => write!(f, "{name} r{dst}, r{src}, {target_pc}"),
+            Operands::Call { key } | Operands::Syscall { key } => write!(f, "{name} {key:#x}"),
+            Operands::CallRegister { reg } => write!(f, "{name} r{reg}"),
+            Operands::InvalidByteSwap { dst, imm } => write!(f, "{name} r{dst}, imm={imm}"),
+            Operands::Unknown { opc } => write!(f, "{name} opcode={opc:#x}"),
+        }
+    }
 }
 
-/// Disassemble an eBPF instruction
+/// Decodes the mnemonic and the operands of an eBPF instruction
+///
+/// Unlike [disassemble_instruction] this needs neither a loader nor any registries and does not
+/// allocate, so it can be used anywhere an instruction is at hand. Jump targets are absolute
+/// pcs and call targets are raw keys, resolving them to names is left to the caller. The
+/// immediate of an `lddw` is expected to span both slots already, see
+/// [ebpf::augment_lddw_unchecked].
 #[rustfmt::skip]
-pub fn disassemble_instruction<C: ContextObject>(
-    insn: &ebpf::Insn,
-    pc: usize,
-    cfg_nodes: &BTreeMap<usize, CfgNode>,
-    function_registry: &FunctionRegistry<usize>,
-    loader: &BuiltinProgram<C>,
-    sbpf_version: SBPFVersion,
-) -> String {
-    let name;
-    let desc;
-    match insn.opc {
+pub fn decode(insn: &ebpf::Insn, sbpf_version: SBPFVersion) -> DecodedInsn {
+    let (dst, src, off, imm) = (insn.dst, insn.src, insn.off, insn.imm);
+    let target_pc = insn.ptr as isize + off as isize + 1;
+    let reg_imm = Operands::RegisterImmediate { dst, imm };
+    let reg_reg = Operands::RegisterRegister { dst, src };
+    let load = Operands::Load { dst, src, off };
+    let store_imm = Operands::StoreImmediate { dst, off, imm };
+    let store_reg = Operands::StoreRegister { dst, off, src };
+    let jump_imm = Operands::JumpImmediate { dst, imm, target_pc };
+    let jump_reg = Operands::JumpRegister { dst, src, target_pc };
+    let byteswap = |name| {
+        let operands = if ebpf::ImmediateRange::ByteSwapWidth.contains(imm) {
+            Operands::Register { dst }
+        } else {
+            Operands::InvalidByteSwap { dst, imm }
+        };
+        (byteswap_name(name, insn), operands)
+    };
+    let (mnemonic, operands) = match insn.opc {
         // BPF_LD class
-        ebpf::LD_DW_IMM  => { name = "lddw"; desc = format!("{} r{:}, {:#x}", name, insn.dst, insn.imm); },
+        ebpf::LD_DW_IMM if !sbpf_version.disable_lddw() => ("lddw",    Operands::Immediate64 { dst, imm: imm as u64 }),
 
         // BPF_LDX class
-        ebpf::LD_B_REG  if !sbpf_version.move_memory_instruction_classes() => { name = "ldxb";  desc = ld_reg_str(name, insn); },
-        ebpf::LD_H_REG  if !sbpf_version.move_memory_instruction_classes() => { name = "ldxh";  desc = ld_reg_str(name, insn); },
-        ebpf::LD_W_REG  if !sbpf_version.move_memory_instruction_classes() => { name = "ldxw";  desc = ld_reg_str(name, insn); },
-        ebpf::LD_DW_REG if !sbpf_version.move_memory_instruction_classes() => { name = "ldxdw"; desc = ld_reg_str(name, insn); },
+        ebpf::LD_B_REG  if !sbpf_version.move_memory_instruction_classes() => ("ldxb",    load),
+        ebpf::LD_H_REG  if !sbpf_version.move_memory_instruction_classes() => ("ldxh",    load),
+        ebpf::LD_W_REG  if !sbpf_version.move_memory_instruction_classes() => ("ldxw",    load),
+        ebpf::LD_DW_REG if !sbpf_version.move_memory_instruction_classes() => ("ldxdw",   load),
 
         // BPF_ST class
-        ebpf::ST_B_IMM  if !sbpf_version.move_memory_instruction_classes() => { name = "stb";   desc = ld_st_imm_str(name, insn); },
-        ebpf::ST_H_IMM  if !sbpf_version.move_memory_instruction_classes() => { name = "sth";   desc = ld_st_imm_str(name, insn); },
-        ebpf::ST_W_IMM  if !sbpf_version.move_memory_instruction_classes() => { name = "stw";   desc = ld_st_imm_str(name, insn); },
-        ebpf::ST_DW_IMM if !sbpf_version.move_memory_instruction_classes() => { name = "stdw";  desc = ld_st_imm_str(name, insn); },
+        ebpf::ST_B_IMM  if !sbpf_version.move_memory_instruction_classes() => ("stb",     store_imm),
+        ebpf::ST_H_IMM  if !sbpf_version.move_memory_instruction_classes() => ("sth",     store_imm),
+        ebpf::ST_W_IMM  if !sbpf_version.move_memory_instruction_classes() => ("stw",     store_imm),
+        ebpf::ST_DW_IMM if !sbpf_version.move_memory_instruction_classes() => ("stdw",    store_imm),
 
         // BPF_STX class
-        ebpf::ST_B_REG  if !sbpf_version.move_memory_instruction_classes() => { name = "stxb";  desc = st_reg_str(name, insn); },
-        ebpf::ST_H_REG  if !sbpf_version.move_memory_instruction_classes() => { name = "stxh";  desc = st_reg_str(name, insn); },
-        ebpf::ST_W_REG  if !sbpf_version.move_memory_instruction_classes() => { name = "stxw";  desc = st_reg_str(name, insn); },
-        ebpf::ST_DW_REG if !sbpf_version.move_memory_instruction_classes() => { name = "stxdw"; desc = st_reg_str(name, insn); },
+        ebpf::ST_B_REG  if !sbpf_version.move_memory_instruction_classes() => ("stxb",    store_reg),
+        ebpf::ST_H_REG  if !sbpf_version.move_memory_instruction_classes() => ("stxh",    store_reg),
+        ebpf::ST_W_REG  if !sbpf_version.move_memory_instruction_classes() => ("stxw",    store_reg),
+        ebpf::ST_DW_REG if !sbpf_version.move_memory_instruction_classes() => ("stxdw",   store_reg),
 
         // BPF_ALU32_LOAD class
-        ebpf::ADD32_IMM  => { name = "add32";  desc = alu_imm_str(name, insn);  },
-        ebpf::ADD32_REG  => { name = "add32";  desc = alu_reg_str(name, insn);  },
-        ebpf::SUB32_IMM  => { name = "sub32";  desc = alu_imm_str(name, insn);  },
-        ebpf::SUB32_REG  => { name = "sub32";  desc = alu_reg_str(name, insn);  },
-        ebpf::MUL32_IMM  if !sbpf_version.enable_pqr() => { name = "mul32";  desc = alu_imm_str(name, insn);  },
-        ebpf::MUL32_REG  if !sbpf_version.enable_pqr() => { name = "mul32";  desc = alu_reg_str(name, insn);  },
-        ebpf::LD_1B_REG  if sbpf_version.move_memory_instruction_classes() => { name = "ldxb";  desc = ld_reg_str(name, insn); },
-        ebpf::DIV32_IMM  if !sbpf_version.enable_pqr() => { name = "div32";  desc = alu_imm_str(name, insn);  },
-        ebpf::DIV32_REG  if !sbpf_version.enable_pqr() => { name = "div32";  desc = alu_reg_str(name, insn);  },
-        ebpf::LD_2B_REG  if sbpf_version.move_memory_instruction_classes() => { name = "ldxh";  desc = ld_reg_str(name, insn); },
-        ebpf::OR32_IMM   => { name = "or32";   desc = alu_imm_str(name, insn);  },
-        ebpf::OR32_REG   => { name = "or32";   desc = alu_reg_str(name, insn);  },
-        ebpf::AND32_IMM  => { name = "and32";  desc = alu_imm_str(name, insn);  },
-        ebpf::AND32_REG  => { name = "and32";  desc = alu_reg_str(name, insn);  },
-        ebpf::LSH32_IMM  => { name = "lsh32";  desc = alu_imm_str(name, insn);  },
-        ebpf::LSH32_REG  => { name = "lsh32";  desc = alu_reg_str(name, insn);  },
-        ebpf::RSH32_IMM  => { name = "rsh32";  desc = alu_imm_str(name, insn);  },
-        ebpf::RSH32_REG  => { name = "rsh32";  desc = alu_reg_str(name, insn);  },
-        ebpf::NEG32      if !sbpf_version.disable_neg() => { name = "neg32";  desc = format!("{} r{}", name, insn.dst); },
-        ebpf::LD_4B_REG  if sbpf_version.move_memory_instruction_classes() => { name = "ldxw";  desc = ld_reg_str(name, insn); },
-        ebpf::MOD32_IMM  if !sbpf_version.enable_pqr() => { name = "mod32";  desc = alu_imm_str(name, insn);  },
-        ebpf::MOD32_REG  if !sbpf_version.enable_pqr() => { name = "mod32";  desc = alu_reg_str(name, insn);  },
-        ebpf::LD_8B_REG  if sbpf_version.move_memory_instruction_classes() => { name = "ldxdw"; desc = ld_reg_str(name, insn); },
-        ebpf::XOR32_IMM  => { name = "xor32";  desc = alu_imm_str(name, insn);  },
-        ebpf::XOR32_REG  => { name = "xor32";  desc = alu_reg_str(name, insn);  },
-        ebpf::MOV32_IMM  => { name = "mov32";  desc = alu_imm_str(name, insn);  },
-        ebpf::MOV32_REG  => { name = "mov32";  desc = alu_reg_str(name, insn);  },
-        ebpf::ARSH32_IMM => { name = "arsh32"; desc = alu_imm_str(name, insn);  },
-        ebpf::ARSH32_REG => { name = "arsh32"; desc = alu_reg_str(name, insn);  },
-        ebpf::LE         => { name = "le";     desc = byteswap_str(name, insn); },
-        ebpf::BE         => { name = "be";     desc = byteswap_str(name, insn); },
+        ebpf::ADD32_IMM  => ("add32",   reg_imm),
+        ebpf::ADD32_REG  => ("add32",   reg_reg),
+        ebpf::SUB32_IMM  => ("sub32",   reg_imm),
+        ebpf::SUB32_REG  => ("sub32",   reg_reg),
+        ebpf::MUL32_IMM  if !sbpf_version.enable_pqr() => ("mul32",   reg_imm),
+        ebpf::MUL32_REG  if !sbpf_version.enable_pqr() => ("mul32",   reg_reg),
+        ebpf::LD_1B_REG  if sbpf_version.move_memory_instruction_classes() => ("ldxb",    load),
+        ebpf::DIV32_IMM  if !sbpf_version.enable_pqr() => ("div32",   reg_imm),
+        ebpf::DIV32_REG  if !sbpf_version.enable_pqr() => ("div32",   reg_reg),
+        ebpf::LD_2B_REG  if sbpf_version.move_memory_instruction_classes() => ("ldxh",    load),
+        ebpf::OR32_IMM   => ("or32",    reg_imm),
+        ebpf::OR32_REG   => ("or32",    reg_reg),
+        ebpf::AND32_IMM  => ("and32",   reg_imm),
+        ebpf::AND32_REG  => ("and32",   reg_reg),
+        ebpf::LSH32_IMM  => ("lsh32",   reg_imm),
+        ebpf::LSH32_REG  => ("lsh32",   reg_reg),
+        ebpf::RSH32_IMM  => ("rsh32",   reg_imm),
+        ebpf::RSH32_REG  => ("rsh32",   reg_reg),
+        ebpf::NEG32      if !sbpf_version.disable_neg() => ("neg32",   Operands::Register { dst }),
+        ebpf::LD_4B_REG  if sbpf_version.move_memory_instruction_classes() => ("ldxw",    load),
+        ebpf::MOD32_IMM  if !sbpf_version.enable_pqr() => ("mod32",   reg_imm),
+        ebpf::MOD32_REG  if !sbpf_version.enable_pqr() => ("mod32",   reg_reg),
+        ebpf::LD_8B_REG  if sbpf_version.move_memory_instruction_classes() => ("ldxdw",   load),
+        ebpf::XOR32_IMM  => ("xor32",   reg_imm),
+        ebpf::XOR32_REG  => ("xor32",   reg_reg),
+        ebpf::MOV32_IMM  => ("mov32",   reg_imm),
+        ebpf::MOV32_REG  => ("mov32",   reg_reg),
+        ebpf::ARSH32_IMM => ("arsh32",  reg_imm),
+        ebpf::ARSH32_REG => ("arsh32",  reg_reg),
+        ebpf::LE         if !sbpf_version.disable_le() => byteswap("le"),
+        ebpf::BE         => byteswap("be"),
 
         // BPF_ALU64_STORE class
-        ebpf::ADD64_IMM  => { name = "add64";  desc = alu_imm_str(name, insn); },
-        ebpf::ADD64_REG  => { name = "add64";  desc = alu_reg_str(name, insn); },
-        ebpf::SUB64_IMM  => { name = "sub64";  desc = alu_imm_str(name, insn); },
-        ebpf::SUB64_REG  => { name = "sub64";  desc = alu_reg_str(name, insn); },
-        ebpf::MUL64_IMM  if !sbpf_version.enable_pqr() => { name = "mul64";  desc = alu_imm_str(name, insn); },
-        ebpf::ST_1B_IMM  if sbpf_version.move_memory_instruction_classes() => { name = "stb";   desc = ld_st_imm_str(name, insn); },
-        ebpf::MUL64_REG  if !sbpf_version.enable_pqr() => { name = "mul64";  desc = alu_reg_str(name, insn); },
-        ebpf::ST_1B_REG  if sbpf_version.move_memory_instruction_classes() => { name = "stxb";  desc = st_reg_str(name, insn); },
-        ebpf::DIV64_IMM  if !sbpf_version.enable_pqr() => { name = "div64";  desc = alu_imm_str(name, insn); },
-        ebpf::ST_2B_IMM  if sbpf_version.move_memory_instruction_classes() => { name = "sth";   desc = ld_st_imm_str(name, insn); },
-        ebpf::DIV64_REG  if !sbpf_version.enable_pqr() => { name = "div64";  desc = alu_reg_str(name, insn); },
-        ebpf::ST_2B_REG  if sbpf_version.move_memory_instruction_classes() => { name = "stxh";  desc = st_reg_str(name, insn); },
-        ebpf::OR64_IMM   => { name = "or64";   desc = alu_imm_str(name, insn); },
-        ebpf::OR64_REG   => { name = "or64";   desc = alu_reg_str(name, insn); },
-        ebpf::AND64_IMM  => { name = "and64";  desc = alu_imm_str(name, insn); },
-        ebpf::AND64_REG  => { name = "and64";  desc = alu_reg_str(name, insn); },
-        ebpf::LSH64_IMM  => { name = "lsh64";  desc = alu_imm_str(name, insn); },
-        ebpf::LSH64_REG  => { name = "lsh64";  desc = alu_reg_str(name, insn); },
-        ebpf::RSH64_IMM  => { name = "rsh64";  desc = alu_imm_str(name, insn); },
-        ebpf::RSH64_REG  => { name = "rsh64";  desc = alu_reg_str(name, insn); },
-        ebpf::ST_4B_IMM  if sbpf_version.move_memory_instruction_classes() => { name = "stw";   desc = ld_st_imm_str(name, insn); },
-        ebpf::NEG64      if !sbpf_version.disable_neg() => { name = "neg64";  desc = format!("{} r{}", name, insn.dst); },
-        ebpf::ST_4B_REG  if sbpf_version.move_memory_instruction_classes() => { name = "stxw";  desc = st_reg_str(name, insn); },
-        ebpf::MOD64_IMM  if !sbpf_version.enable_pqr() => { name = "mod64";  desc = alu_imm_str(name, insn); },
-        ebpf::ST_8B_IMM  if sbpf_version.move_memory_instruction_classes() => { name = "stdw";  desc = ld_st_imm_str(name, insn); },
-        ebpf::MOD64_REG  if !sbpf_version.enable_pqr() => { name = "mod64";  desc = alu_reg_str(name, insn); },
-        ebpf::ST_8B_REG  if sbpf_version.move_memory_instruction_classes() => { name = "stxdw"; desc = st_reg_str(name, insn); },
-        ebpf::XOR64_IMM  => { name = "xor64";  desc = alu_imm_str(name, insn); },
-        ebpf::XOR64_REG  => { name = "xor64";  desc = alu_reg_str(name, insn); },
-        ebpf::MOV64_IMM  => { name = "mov64";  desc = alu_imm_str(name, insn); },
-        ebpf::MOV64_REG  => { name = "mov64";  desc = alu_reg_str(name, insn); },
-        ebpf::ARSH64_IMM => { name = "arsh64"; desc = alu_imm_str(name, insn); },
-        ebpf::ARSH64_REG => { name = "arsh64"; desc = alu_reg_str(name, insn); },
-        ebpf::HOR64_IMM  => { name = "hor64"; desc = alu_imm_str(name, insn); },
+        ebpf::ADD64_IMM  => ("add64",   reg_imm),
+        ebpf::ADD64_REG  => ("add64",   reg_reg),
+        ebpf::SUB64_IMM  => ("sub64",   reg_imm),
+        ebpf::SUB64_REG  => ("sub64",   reg_reg),
+        ebpf::MUL64_IMM  if !sbpf_version.enable_pqr() => ("mul64",   reg_imm),
+        ebpf::ST_1B_IMM  if sbpf_version.move_memory_instruction_classes() => ("stb",     store_imm),
+        ebpf::MUL64_REG  if !sbpf_version.enable_pqr() => ("mul64",   reg_reg),
+        ebpf::ST_1B_REG  if sbpf_version.move_memory_instruction_classes() => ("stxb",    store_reg),
+        ebpf::DIV64_IMM  if !sbpf_version.enable_pqr() => ("div64",   reg_imm),
+        ebpf::ST_2B_IMM  if sbpf_version.move_memory_instruction_classes() => ("sth",     store_imm),
+        ebpf::DIV64_REG  if !sbpf_version.enable_pqr() => ("div64",   reg_reg),
+        ebpf::ST_2B_REG  if sbpf_version.move_memory_instruction_classes() => ("stxh",    store_reg),
+        ebpf::OR64_IMM   => ("or64",    reg_imm),
+        ebpf::OR64_REG   => ("or64",    reg_reg),
+        ebpf::AND64_IMM  => ("and64",   reg_imm),
+        ebpf::AND64_REG  => ("and64",   reg_reg),
+        ebpf::LSH64_IMM  => ("lsh64",   reg_imm),
+        ebpf::LSH64_REG  => ("lsh64",   reg_reg),
+        ebpf::RSH64_IMM  => ("rsh64",   reg_imm),
+        ebpf::RSH64_REG  => ("rsh64",   reg_reg),
+        ebpf::ST_4B_IMM  if sbpf_version.move_memory_instruction_classes() => ("stw",     store_imm),
+        ebpf::NEG64      if !sbpf_version.disable_neg() => ("neg64",   Operands::Register { dst }),
+        ebpf::ST_4B_REG  if sbpf_version.move_memory_instruction_classes() => ("stxw",    store_reg),
+        ebpf::MOD64_IMM  if !sbpf_version.enable_pqr() => ("mod64",   reg_imm),
+        ebpf::ST_8B_IMM  if sbpf_version.move_memory_instruction_classes() => ("stdw",    store_imm),
+        ebpf::MOD64_REG  if !sbpf_version.enable_pqr() => ("mod64",   reg_reg),
+        ebpf::ST_8B_REG  if sbpf_version.move_memory_instruction_classes() => ("stxdw",   store_reg),
+        ebpf::XOR64_IMM  => ("xor64",   reg_imm),
+        ebpf::XOR64_REG  => ("xor64",   reg_reg),
+        ebpf::MOV64_IMM  => ("mov64",   reg_imm),
+        ebpf::MOV64_REG  => ("mov64",   reg_reg),
+        ebpf::ARSH64_IMM => ("arsh64",  reg_imm),
+        ebpf::ARSH64_REG => ("arsh64",  reg_reg),
+        ebpf::HOR64_IMM  if sbpf_version.disable_lddw() => ("hor64",   reg_imm),
 
         // BPF_PQR class
-        ebpf::LMUL32_IMM  if sbpf_version.enable_pqr() => { name = "lmul32"; desc = alu_imm_str(name, insn); },
-        ebpf::LMUL32_REG  if sbpf_version.enable_pqr() => { name = "lmul32"; desc = alu_reg_str(name, insn); },
-        ebpf::LMUL64_IMM  if sbpf_version.enable_pqr() => { name = "lmul64"; desc = alu_imm_str(name, insn); },
-        ebpf::LMUL64_REG  if sbpf_version.enable_pqr() => { name = "lmul64"; desc = alu_reg_str(name, insn); },
-        ebpf::UHMUL64_IMM if sbpf_version.enable_pqr() => { name = "uhmul64"; desc = alu_imm_str(name, insn); },
-        ebpf::UHMUL64_REG if sbpf_version.enable_pqr() => { name = "uhmul64"; desc = alu_reg_str(name, insn); },
-        ebpf::SHMUL64_IMM if sbpf_version.enable_pqr() => { name = "shmul64"; desc = alu_imm_str(name, insn); },
-        ebpf::SHMUL64_REG if sbpf_version.enable_pqr() => { name = "shmul64"; desc = alu_reg_str(name, insn); },
-        ebpf::UDIV32_IMM  if sbpf_version.enable_pqr() => { name = "udiv32"; desc = alu_imm_str(name, insn); },
-        ebpf::UDIV32_REG  if sbpf_version.enable_pqr() => { name = "udiv32"; desc = alu_reg_str(name, insn); },
-        ebpf::UDIV64_IMM  if sbpf_version.enable_pqr() => { name = "udiv64"; desc = alu_imm_str(name, insn); },
-        ebpf::UDIV64_REG  if sbpf_version.enable_pqr() => { name = "udiv64"; desc = alu_reg_str(name, insn); },
-        ebpf::UREM32_IMM  if sbpf_version.enable_pqr() => { name = "urem32"; desc = alu_imm_str(name, insn); },
-        ebpf::UREM32_REG  if sbpf_version.enable_pqr() => { name = "urem32"; desc = alu_reg_str(name, insn); },
-        ebpf::UREM64_IMM  if sbpf_version.enable_pqr() => { name = "urem64"; desc = alu_imm_str(name, insn); },
-        ebpf::UREM64_REG  if sbpf_version.enable_pqr() => { name = "urem64"; desc = alu_reg_str(name, insn); },
-        ebpf::SDIV32_IMM  if sbpf_version.enable_pqr() => { name = "sdiv32"; desc = alu_imm_str(name, insn); },
-        ebpf::SDIV32_REG  if sbpf_version.enable_pqr() => { name = "sdiv32"; desc = alu_reg_str(name, insn); },
-        ebpf::SDIV64_IMM  if sbpf_version.enable_pqr() => { name = "sdiv64"; desc = alu_imm_str(name, insn); },
-        ebpf::SDIV64_REG  if sbpf_version.enable_pqr() => { name = "sdiv64"; desc = alu_reg_str(name, insn); },
-        ebpf::SREM32_IMM  if sbpf_version.enable_pqr() => { name = "srem32"; desc = alu_imm_str(name, insn); },
-        ebpf::SREM32_REG  if sbpf_version.enable_pqr() => { name = "srem32"; desc = alu_reg_str(name, insn); },
-        ebpf::SREM64_IMM  if sbpf_version.enable_pqr() => { name = "srem64"; desc = alu_imm_str(name, insn); },
-        ebpf::SREM64_REG  if sbpf_version.enable_pqr() => { name = "srem64"; desc = alu_reg_str(name, insn); },
+        ebpf::LMUL32_IMM  if sbpf_version.enable_pqr() => ("lmul32",  reg_imm),
+        ebpf::LMUL32_REG  if sbpf_version.enable_pqr() => ("lmul32",  reg_reg),
+        ebpf::LMUL64_IMM  if sbpf_version.enable_pqr() => ("lmul64",  reg_imm),
+        ebpf::LMUL64_REG  if sbpf_version.enable_pqr() => ("lmul64",  reg_reg),
+        ebpf::UHMUL64_IMM if sbpf_version.enable_pqr() => ("uhmul64", reg_imm),
+        ebpf::UHMUL64_REG if sbpf_version.enable_pqr() => ("uhmul64", reg_reg),
+        ebpf::SHMUL64_IMM if sbpf_version.enable_pqr() => ("shmul64", reg_imm),
+        ebpf::SHMUL64_REG if sbpf_version.enable_pqr() => ("shmul64", reg_reg),
+        ebpf::UDIV32_IMM  if sbpf_version.enable_pqr() => ("udiv32",  reg_imm),
+        ebpf::UDIV32_REG  if sbpf_version.enable_pqr() => ("udiv32",  reg_reg),
+        ebpf::UDIV64_IMM  if sbpf_version.enable_pqr() => ("udiv64",  reg_imm),
+        ebpf::UDIV64_REG  if sbpf_version.enable_pqr() => ("udiv64",  reg_reg),
+        ebpf::UREM32_IMM  if sbpf_version.enable_pqr() => ("urem32",  reg_imm),
+        ebpf::UREM32_REG  if sbpf_version.enable_pqr() => ("urem32",  reg_reg),
+        ebpf::UREM64_IMM  if sbpf_version.enable_pqr() => ("urem64",  reg_imm),
+        ebpf::UREM64_REG  if sbpf_version.enable_pqr() => ("urem64",  reg_reg),
+        ebpf::SDIV32_IMM  if sbpf_version.enable_pqr() => ("sdiv32",  reg_imm),
+        ebpf::SDIV32_REG  if sbpf_version.enable_pqr() => ("sdiv32",  reg_reg),
+        ebpf::SDIV64_IMM  if sbpf_version.enable_pqr() => ("sdiv64",  reg_imm),
+        ebpf::SDIV64_REG  if sbpf_version.enable_pqr() => ("sdiv64",  reg_reg),
+        ebpf::SREM32_IMM  if sbpf_version.enable_pqr() => ("srem32",  reg_imm),
+        ebpf::SREM32_REG  if sbpf_version.enable_pqr() => ("srem32",  reg_reg),
+        ebpf::SREM64_IMM  if sbpf_version.enable_pqr() => ("srem64",  reg_imm),
+        ebpf::SREM64_REG  if sbpf_version.enable_pqr() => ("srem64",  reg_reg),
 
         // BPF_JMP class
-        ebpf::JA         => {
-            name = "ja";
-            let target_pc = (insn.ptr as isize + insn.off as isize + 1) as usize;
-            desc = format!("{} {}", name, resolve_label(cfg_nodes, target_pc));
+        ebpf::JA         => ("ja",      Operands::Jump { target_pc }),
+        ebpf::JEQ_IMM    => ("jeq",     jump_imm),
+        ebpf::JEQ_REG    => ("jeq",     jump_reg),
+        ebpf::JGT_IMM    => ("jgt",     jump_imm),
+        ebpf::JGT_REG    => ("jgt",     jump_reg),
+        ebpf::JGE_IMM    => ("jge",     jump_imm),
+        ebpf::JGE_REG    => ("jge",     jump_reg),
+        ebpf::JLT_IMM    => ("jlt",     jump_imm),
+        ebpf::JLT_REG    => ("jlt",     jump_reg),
+        ebpf::JLE_IMM    => ("jle",     jump_imm),
+        ebpf::JLE_REG    => ("jle",     jump_reg),
+        ebpf::JSET_IMM   => ("jset",    jump_imm),
+        ebpf::JSET_REG   => ("jset",    jump_reg),
+        ebpf::JNE_IMM    => ("jne",     jump_imm),
+        ebpf::JNE_REG    => ("jne",     jump_reg),
+        ebpf::JSGT_IMM   => ("jsgt",    jump_imm),
+        ebpf::JSGT_REG   => ("jsgt",    jump_reg),
+        ebpf::JSGE_IMM   => ("jsge",    jump_imm),
+        ebpf::JSGE_REG   => ("jsge",    jump_reg),
+        ebpf::JSLT_IMM   => ("jslt",    jump_imm),
+        ebpf::JSLT_REG   => ("jslt",    jump_reg),
+        ebpf::JSLE_IMM   => ("jsle",    jump_imm),
+        ebpf::JSLE_REG   => ("jsle",    jump_reg),
+        ebpf::CALL_IMM   => ("call",    Operands::Call { key: sbpf_version.calculate_call_imm_target_pc(insn.ptr, imm) }),
+        ebpf::CALL_REG   => ("callx",   Operands::CallRegister { reg: if sbpf_version.callx_uses_src_reg() { src } else { imm as u8 } }),
+        ebpf::EXIT     if !sbpf_version.static_syscalls() => ("exit",    Operands::None),
+        ebpf::RETURN   if sbpf_version.static_syscalls() =>  ("return",  Operands::None),
+        ebpf::SYSCALL  if sbpf_version.static_syscalls() =>  ("syscall", Operands::Syscall { key: imm as u32 }),
+
+
+        _                => ("unknown", Operands::Unknown { opc: insn.opc }),
+    };
+    DecodedInsn { mnemonic, operands }
+}
+
+/// How [disassemble_instruction_with_options] formats instructions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisassemblyStyle {
+    /// Human readable, with the names of the basic blocks and functions as targets
+    Readable,
+    /// Assembler code which [assemble](crate::assembler::assemble) turns back into the same
+    /// encoding, given the same loader and SBPF version
+    ///
+    /// Jump and call targets which have a node in `cfg_nodes` are referred to as `lbb_N`, these
+    /// labels have to be declared by the surrounding listing. Other jumps use numeric offsets and
+    /// syscalls use the name they are registered under in the loader. Encodings the assembler
+    /// can not produce otherwise, like unknown opcodes or unused fields which are not zero, are
+    /// written as `.fill 1, value`.
+    Reassemblable,
+}
+
+/// Radix of the immediates in [DisassemblyOptions]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Radix {
+    /// Signed decimal, like `-16`
+    Decimal,
+    /// Hexadecimal of the 32 bits, like `0xfffffff0`
+    ///
+    /// In [DisassemblyStyle::Reassemblable] negative immediates are signed instead, like
+    /// `-0x10`, as the assembler does not accept values beyond the range of i32.
+    Hexadecimal,
+}
+
+/// Options of [disassemble_instruction_with_options]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisassemblyOptions {
+    /// Radix of the 32 bit immediates of ALU, store and conditional jump instructions
+    ///
+    /// The immediate of `lddw` is always hexadecimal.
+    pub imm_radix: Radix,
+    /// How targets, syscalls and invalid encodings are formatted
+    pub style: DisassemblyStyle,
+    /// Suffixes loads and stores with their width and opcode, like
+    /// `ldxw r1, [r2+0x10] ; 4 bytes, opcode 0x8c`
+    ///
+    /// The mnemonics are the same in all SBPF versions, but the encodings move to other
+    /// classes with [SBPFVersion::move_memory_instruction_classes], the opcode tells them
+    /// apart. Ignored by [DisassemblyStyle::Reassemblable], the assembler does not accept
+    /// comments.
+    pub memory_widths: bool,
+    /// Starts every basic block of a listing with `; block cost: N`, the number of
+    /// instructions the meter charges for executing it once
+    ///
+    /// Only used by the listings of [Analysis] in
+    /// [DisassemblyStyle::Readable], single instructions have no basic block.
+    pub block_costs: bool,
+}
+
+impl Default for DisassemblyOptions {
+    fn default() -> Self {
+        Self {
+            imm_radix: Radix::Decimal,
+            style: DisassemblyStyle::Readable,
+            memory_widths: false,
+            block_costs: false,
+        }
+    }
+}
+
+/// Returns the pc a jump or an internal call is targeting, if `insn` is one
+fn target_pc(insn: &ebpf::Insn, sbpf_version: SBPFVersion) -> Option<isize> {
+    match insn.opc {
+        ebpf::CALL_IMM if insn.src == 1 => Some(if sbpf_version.static_syscalls() {
+            insn.ptr as isize + insn.imm as isize + 1
+        } else {
+            insn.imm as isize
+        }),
+        ebpf::CALL_IMM | ebpf::CALL_REG | ebpf::EXIT | ebpf::RETURN => None,
+        _ if insn.opc & ebpf::BPF_CLS_MASK == ebpf::BPF_JMP => {
+            Some(insn.ptr as isize + insn.off as isize + 1)
+        }
+        _ => None,
+    }
+}
+
+/// Returns the pcs which a listing of `instructions` in [DisassemblyStyle::Reassemblable]
+/// needs to declare labels for
+///
+/// These are the targets of jumps and internal calls which are the start of an instruction or
+/// the end of the program, `end_pc` being the number of slots.
+pub fn reassemblable_labels(
+    instructions: &[ebpf::Insn],
+    end_pc: usize,
+    sbpf_version: SBPFVersion,
+) -> BTreeMap<usize, CfgNode> {
+    let mut labels = BTreeMap::new();
+    for insn in instructions {
+        if let Some(target_pc) = target_pc(insn, sbpf_version) {
+            let is_declarable = target_pc as usize == end_pc
+                || instructions
+                    .binary_search_by_key(&target_pc, |insn| insn.ptr as isize)
+                    .is_ok();
+            if target_pc >= 0 && is_declarable {
+                labels.entry(target_pc as usize).or_insert_with(|| CfgNode {
+                    label: format!("lbb_{target_pc}"),
+                    ..CfgNode::default()
+                });
+            }
+        }
+    }
+    labels
+}
+
+/// Returns the `.fill` directive reproducing the encoding of a single slot
+pub(crate) fn fill_str(slot: &[u8]) -> String {
+    let mut value = [0u8; ebpf::INSN_SIZE];
+    value.copy_from_slice(slot);
+    format!(".fill 1, {:#x}", u64::from_le_bytes(value))
+}
+
+/// Returns true if the assembler parses `name` as a label
+///
+/// Operands starting with `r` are parsed as registers and numbers as integers.
+fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+    let first = chars.next().unwrap_or(' ');
+    (first == '_' || (first.is_ascii_alphabetic() && first != 'r'))
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[inline]
+fn reassemblable_target_str(
+    cfg_nodes: &BTreeMap<usize, CfgNode>,
+    insn: &ebpf::Insn,
+    target_pc: isize,
+) -> String {
+    match cfg_nodes.get(&(target_pc as usize)) {
+        Some(_) if target_pc >= 0 => format!("lbb_{target_pc}"),
+        _ => format!("{:+}", insn.off),
+    }
+}
+
+#[inline]
+fn reassemblable_syscall_str<C: ContextObject>(
+    insn: &ebpf::Insn,
+    loader: &BuiltinProgram<C>,
+) -> String {
+    match loader.get_function_registry().lookup_by_key(insn.imm as u32) {
+        Some((function_name, _))
+            if ebpf::hash_symbol_name(function_name) == insn.imm as u32
+                && core::str::from_utf8(function_name).is_ok_and(is_label) =>
+        {
+            format!("syscall {}", String::from_utf8_lossy(function_name))
+        }
+        _ => format!("syscall {}", insn.imm),
+    }
+}
+
+/// Returns the mnemonic and the assembler code of [DisassemblyStyle::Reassemblable], or `None`
+/// if the encoding can only be reproduced by `.fill`
+fn reassemblable_parts<C: ContextObject>(
+    insn: &ebpf::Insn,
+    mnemonic: &'static str,
+    desc: String,
+    cfg_nodes: &BTreeMap<usize, CfgNode>,
+    loader: &BuiltinProgram<C>,
+    sbpf_version: SBPFVersion,
+    options: DisassemblyOptions,
+) -> Option<(&'static str, String)> {
+    let is_reg = insn.opc & ebpf::BPF_X != 0;
+    let is_canonical = match mnemonic {
+        "unknown" | "le??" | "be??" => false,
+        "lddw" => insn.src == 0 && insn.off == 0,
+        "ldxb" | "ldxh" | "ldxw" | "ldxdw" | "stxb" | "stxh" | "stxw" | "stxdw" => insn.imm == 0,
+        "stb" | "sth" | "stw" | "stdw" => insn.src == 0,
+        "neg32" | "neg64" => insn.src == 0 && insn.off == 0 && insn.imm == 0,
+        "le16" | "le32" | "le64" | "be16" | "be32" | "be64" => insn.src == 0 && insn.off == 0,
+        "exit" | "return" => insn.dst == 0 && insn.src == 0 && insn.off == 0 && insn.imm == 0,
+        "ja" => insn.dst == 0 && insn.src == 0 && insn.imm == 0,
+        "call" | "syscall" if insn.opc == ebpf::CALL_IMM => {
+            insn.dst == 0 && insn.off == 0 && insn.src <= 1
+        }
+        "syscall" => insn.dst == 0 && insn.src == 0 && insn.off == 0,
+        "callx" if sbpf_version.callx_uses_src_reg() => {
+            insn.dst == 0 && insn.off == 0 && insn.imm == 0
+        }
+        "callx" => insn.dst == 0 && insn.src == 0 && insn.off == 0 && (0..16).contains(&insn.imm),
+        _ if insn.opc & ebpf::BPF_CLS_MASK == ebpf::BPF_JMP => {
+            if is_reg {
+                insn.imm == 0
+            } else {
+                insn.src == 0
+            }
+        }
+        _ if is_reg => insn.off == 0 && insn.imm == 0,
+        _ => insn.src == 0 && insn.off == 0,
+    };
+    if !is_canonical {
+        return None;
+    }
+    Some(match mnemonic {
+        "ja" => (
+            mnemonic,
+            format!(
+                "ja {}",
+                reassemblable_target_str(cfg_nodes, insn, target_pc(insn, sbpf_version)?)
+            ),
+        ),
+        "call" | "syscall" if insn.opc == ebpf::CALL_IMM => match insn.src {
+            1 => {
+                let target_pc = target_pc(insn, sbpf_version)?;
+                cfg_nodes.get(&(target_pc as usize)).filter(|_| target_pc >= 0)?;
+                ("call", format!("call lbb_{target_pc}"))
+            }
+            _ if sbpf_version.static_syscalls() => ("call", format!("call {}", insn.imm)),
+            _ => ("syscall", reassemblable_syscall_str(insn, loader)),
         },
-        ebpf::JEQ_IMM    => { name = "jeq";  desc = jmp_imm_str(name, insn, cfg_nodes); },
-        ebpf::JEQ_REG    => { name = "jeq";  desc = jmp_reg_str(name, insn, cfg_nodes); },
-        ebpf::JGT_IMM    => { name = "jgt";  desc = jmp_imm_str(name, insn, cfg_nodes); },
-        ebpf::JGT_REG    => { name = "jgt";  desc = jmp_reg_str(name, insn, cfg_nodes); },
-        ebpf::JGE_IMM    => { name = "jge";  desc = jmp_imm_str(name, insn, cfg_nodes); },
-        ebpf::JGE_REG    => { name = "jge";  desc = jmp_reg_str(name, insn, cfg_nodes); },
-        ebpf::JLT_IMM    => { name = "jlt";  desc = jmp_imm_str(name, insn, cfg_nodes); },
-        ebpf::JLT_REG    => { name = "jlt";  desc = jmp_reg_str(name, insn, cfg_nodes); },
-        ebpf::JLE_IMM    => { name = "jle";  desc = jmp_imm_str(name, insn, cfg_nodes); },
-        ebpf::JLE_REG    => { name = "jle";  desc = jmp_reg_str(name, insn, cfg_nodes); },
-        ebpf::JSET_IMM   => { name = "jset"; desc = jmp_imm_str(name, insn, cfg_nodes); },
-        ebpf::JSET_REG   => { name = "jset"; desc = jmp_reg_str(name, insn, cfg_nodes); },
-        ebpf::JNE_IMM    => { name = "jne";  desc = jmp_imm_str(name, insn, cfg_nodes); },
-        ebpf::JNE_REG    => { name = "jne";  desc = jmp_reg_str(name, insn, cfg_nodes); },
-        ebpf::JSGT_IMM   => { name = "jsgt"; desc = jmp_imm_str(name, insn, cfg_nodes); },
-        ebpf::JSGT_REG   => { name = "jsgt"; desc = jmp_reg_str(name, insn, cfg_nodes); },
-        ebpf::JSGE_IMM   => { name = "jsge"; desc = jmp_imm_str(name, insn, cfg_nodes); },
-        ebpf::JSGE_REG   => { name = "jsge"; desc = jmp_reg_str(name, insn, cfg_nodes); },
-        ebpf::JSLT_IMM   => { name = "jslt"; desc = jmp_imm_str(name, insn, cfg_nodes); },
-        ebpf::JSLT_REG   => { name = "jslt"; desc = jmp_reg_str(name, insn, cfg_nodes); },
-        ebpf::JSLE_IMM   => { name = "jsle"; desc = jmp_imm_str(name, insn, cfg_nodes); },
-        ebpf::JSLE_REG   => { name = "jsle"; desc = jmp_reg_str(name, insn, cfg_nodes); },
-        ebpf::CALL_IMM   => {
-            let key = sbpf_version.calculate_call_imm_target_pc(pc, insn.imm);
-            let mut name = "call";
-            let mut function_name = function_registry.lookup_by_key(key).map(|(function_name, _)| String::from_utf8_lossy(function_name).to_string());
-            if !sbpf_version.static_syscalls() && function_name.is_none() {
-                name = "syscall";
-                function_name = loader.get_function_registry().lookup_by_key(insn.imm as u32).map(|(function_name, _)| String::from_utf8_lossy(function_name).to_string());
+        "syscall" => (mnemonic, reassemblable_syscall_str(insn, loader)),
+        "callx" => match decode(insn, sbpf_version).operands {
+            Operands::CallRegister { reg } => (mnemonic, format!("callx r{reg}")),
+            _ => return None,
+        },
+        _ if insn.opc & ebpf::BPF_CLS_MASK == ebpf::BPF_JMP && mnemonic.starts_with('j') => {
+            let target = reassemblable_target_str(cfg_nodes, insn, target_pc(insn, sbpf_version)?);
+            if is_reg {
+                (mnemonic, format!("{} r{}, r{}, {}", mnemonic, insn.dst, insn.src, target))
+            } else {
+                let imm = imm_str(insn.imm, options);
+                (mnemonic, format!("{} r{}, {}, {}", mnemonic, insn.dst, imm, target))
             }
-            desc = format!("{} {}", name, function_name.unwrap_or_else(|| "[invalid]".to_string()));
+        }
+        _ => (mnemonic, desc),
+    })
+}
+
+/// A disassembled instruction along with its encoding, see [disassemble_instruction_ex]
+///
+/// Formats as the assembler code of [disassemble_instruction].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisassembledInstruction {
+    /// Pc of the instruction, of its first slot for `lddw`
+    pub pc: usize,
+    /// Encoding of the instruction, 16 bytes spanning both slots for `lddw`
+    pub bytes: Vec<u8>,
+    /// Name of the instruction, including the width of byte swaps, `le??` or `be??` if invalid
+    pub mnemonic: &'static str,
+    /// Operands, empty if there are none
+    pub operands: String,
+}
+
+impl fmt::Display for DisassembledInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.operands.is_empty() {
+            f.write_str(self.mnemonic)
+        } else {
+            write!(f, "{} {}", self.mnemonic, self.operands)
+        }
+    }
+}
+
+/// Structured form of a disassembled instruction, see [disassembly_record]
+///
+/// Serializable with the `serde` feature, or as JSON with [disassembly_records_to_json].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisassemblyRecord {
+    /// Pc of the instruction, of its first slot for `lddw`
+    pub pc: usize,
+    /// Opcode byte
+    pub opcode: u8,
+    /// Destination register
+    pub dst: u8,
+    /// Source register
+    pub src: u8,
+    /// Offset
+    pub off: i16,
+    /// Immediate, spanning both slots for `lddw`
+    pub imm: i64,
+    /// Name of the instruction, see [DisassembledInstruction::mnemonic]
+    pub mnemonic: &'static str,
+    /// Label of the jump target, or name of the called function or syscall
+    ///
+    /// For `callx` the label of the target, if [CfgNode::indirect_call_target] knows it.
+    ///
+    /// `None` for other instructions and targets which do not resolve.
+    pub target: Option<String>,
+}
+
+#[cfg(feature = "serde")]
+impl serde_core::Serialize for DisassemblyRecord {
+    fn serialize<S: serde_core::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde_core::ser::SerializeStruct;
+        let mut record = serializer.serialize_struct("DisassemblyRecord", 8)?;
+        record.serialize_field("pc", &self.pc)?;
+        record.serialize_field("opcode", &self.opcode)?;
+        record.serialize_field("dst", &self.dst)?;
+        record.serialize_field("src", &self.src)?;
+        record.serialize_field("off", &self.off)?;
+        record.serialize_field("imm", &self.imm)?;
+        record.serialize_field("mnemonic", self.mnemonic)?;
+        record.serialize_field("target", &self.target)?;
+        record.end()
+    }
+}
+
+/// Serializes records as a JSON array of objects, with the field names of [DisassemblyRecord]
+pub fn disassembly_records_to_json(records: &[DisassemblyRecord]) -> String {
+    let mut json = String::from("[");
+    for (index, record) in records.iter().enumerate() {
+        if index > 0 {
+            json.push(',');
+        }
+        let _ = write!(
+            json,
+            "{{\"pc\":{},\"opcode\":{},\"dst\":{},\"src\":{},\"off\":{},\"imm\":{},\"mnemonic\":",
+            record.pc, record.opcode, record.dst, record.src, record.off, record.imm
+        );
+        write_json_string(&mut json, record.mnemonic);
+        json.push_str(",\"target\":");
+        match &record.target {
+            Some(target) => write_json_string(&mut json, target),
+            None => json.push_str("null"),
+        }
+        json.push('}');
+    }
+    json.push(']');
+    json
+}
+
+/// Returns the label, function or syscall name `insn` refers to
+fn resolve_target<C: ContextObject>(
+    insn: &ebpf::Insn,
+    mnemonic: &str,
+    cfg_nodes: &BTreeMap<usize, CfgNode>,
+    function_registry: &FunctionRegistry<usize>,
+    loader: &BuiltinProgram<C>,
+    sbpf_version: SBPFVersion,
+) -> Option<String> {
+    let function_name = match insn.opc {
+        ebpf::CALL_IMM => {
+            let key = sbpf_version.calculate_call_imm_target_pc(insn.ptr, insn.imm);
+            function_registry
+                .lookup_by_key(key)
+                .map(|(function_name, _)| function_name)
+                .or_else(|| {
+                    if sbpf_version.static_syscalls() {
+                        None
+                    } else {
+                        loader
+                            .get_function_registry()
+                            .lookup_by_key(insn.imm as u32)
+                            .map(|(function_name, _)| function_name)
+                    }
+                })
+        }
+        ebpf::SYSCALL if sbpf_version.static_syscalls() => loader
+            .get_function_registry()
+            .lookup_by_key(insn.imm as u32)
+            .map(|(function_name, _)| function_name),
+        ebpf::CALL_REG => {
+            let target_pc = indirect_call_target(insn, cfg_nodes)?;
+            return cfg_nodes
+                .get(&target_pc)
+                .map(|cfg_node| cfg_node.label.clone());
+        }
+        _ if mnemonic.starts_with('j') => {
+            let target_pc = target_pc(insn, sbpf_version)?;
+            return cfg_nodes
+                .get(&(target_pc as usize))
+                .filter(|_| target_pc >= 0)
+                .map(|cfg_node| cfg_node.label.clone());
+        }
+        _ => None,
+    };
+    function_name.map(|function_name| String::from_utf8_lossy(function_name).to_string())
+}
+
+/// Returns the structured form of an eBPF instruction
+///
+/// The immediate of an `lddw` is expected to span both slots already, see
+/// [ebpf::augment_lddw_unchecked].
+pub fn disassembly_record<C: ContextObject>(
+    insn: &ebpf::Insn,
+    cfg_nodes: &BTreeMap<usize, CfgNode>,
+    function_registry: &FunctionRegistry<usize>,
+    loader: &BuiltinProgram<C>,
+    sbpf_version: SBPFVersion,
+) -> DisassemblyRecord {
+    let instruction = disassemble_instruction_ex(
+        insn,
+        insn.ptr,
+        cfg_nodes,
+        function_registry,
+        loader,
+        sbpf_version,
+    );
+    DisassemblyRecord {
+        pc: insn.ptr,
+        opcode: insn.opc,
+        dst: insn.dst,
+        src: insn.src,
+        off: insn.off,
+        imm: insn.imm,
+        mnemonic: instruction.mnemonic,
+        target: resolve_target(
+            insn,
+            instruction.mnemonic,
+            cfg_nodes,
+            function_registry,
+            loader,
+            sbpf_version,
+        ),
+    }
+}
+
+/// Disassembles the text section of an executable into [DisassemblyRecord]s
+pub fn disassemble_program_records<C: ContextObject>(
+    executable: &Executable<C>,
+) -> Result<Vec<DisassemblyRecord>, EbpfError> {
+    Ok(Analysis::from_executable(executable)?.disassembly_records())
+}
+
+/// Disassembles the text section of an executable, one line per label or instruction
+///
+/// The lines are the ones of [Analysis::disassemble]: labels of functions and basic blocks
+/// precede their first instruction, functions are separated by empty lines and an `lddw` takes
+/// a single line.
+pub fn disassemble_program<C: ContextObject>(
+    executable: &Executable<C>,
+) -> Result<Vec<String>, EbpfError> {
+    let analysis = Analysis::from_executable(executable)?;
+    let mut output = Vec::new();
+    // Writing to a Vec<u8> does not fail
+    let _ = analysis.disassemble(&mut output);
+    Ok(String::from_utf8_lossy(&output)
+        .lines()
+        .map(str::to_string)
+        .collect())
+}
+
+/// Disassemble an eBPF instruction
+pub fn disassemble_instruction<C: ContextObject>(
+    insn: &ebpf::Insn,
+    pc: usize,
+    cfg_nodes: &BTreeMap<usize, CfgNode>,
+    function_registry: &FunctionRegistry<usize>,
+    loader: &BuiltinProgram<C>,
+    sbpf_version: SBPFVersion,
+) -> String {
+    disassemble_instruction_ex(insn, pc, cfg_nodes, function_registry, loader, sbpf_version)
+        .to_string()
+}
+
+/// Disassemble an eBPF instruction into its pc, encoding, mnemonic and operands
+///
+/// The immediate of an `lddw` is expected to span both slots already, see
+/// [ebpf::augment_lddw_unchecked].
+pub fn disassemble_instruction_ex<C: ContextObject>(
+    insn: &ebpf::Insn,
+    pc: usize,
+    cfg_nodes: &BTreeMap<usize, CfgNode>,
+    function_registry: &FunctionRegistry<usize>,
+    loader: &BuiltinProgram<C>,
+    sbpf_version: SBPFVersion,
+) -> DisassembledInstruction {
+    disassemble_instruction_with_options(
+        insn,
+        pc,
+        cfg_nodes,
+        function_registry,
+        loader,
+        sbpf_version,
+        DisassemblyOptions::default(),
+    )
+}
+
+/// Disassemble an eBPF instruction in the given [DisassemblyStyle]
+///
+/// In [DisassemblyStyle::Reassemblable] the second slot of an `lddw` is assumed to be the one
+/// the assembler generates, only containing the upper half of the immediate. An instruction
+/// falling back to `.fill` has the mnemonic `.fill`, for `lddw` it spans both slots.
+pub fn disassemble_instruction_with_style<C: ContextObject>(
+    insn: &ebpf::Insn,
+    pc: usize,
+    cfg_nodes: &BTreeMap<usize, CfgNode>,
+    function_registry: &FunctionRegistry<usize>,
+    loader: &BuiltinProgram<C>,
+    sbpf_version: SBPFVersion,
+    style: DisassemblyStyle,
+) -> DisassembledInstruction {
+    disassemble_instruction_with_options(
+        insn,
+        pc,
+        cfg_nodes,
+        function_registry,
+        loader,
+        sbpf_version,
+        DisassemblyOptions {
+            style,
+            ..DisassemblyOptions::default()
         },
-        ebpf::CALL_REG   => { name = "callx"; desc = format!("{} r{}", name, if sbpf_version.callx_uses_src_reg() { insn.src } else { insn.imm as u8 }); },
-        ebpf::EXIT     if !sbpf_version.static_syscalls() => { name = "exit"; desc = name.to_string(); },
-        ebpf::RETURN   if sbpf_version.static_syscalls() =>  { name = "return"; desc = name.to_string(); },
-        ebpf::SYSCALL  if sbpf_version.static_syscalls() =>  { desc = format!("syscall {}", insn.imm); },
+    )
+}
 
-        _                => { name = "unknown"; desc = format!("{} opcode={:#x}", name, insn.opc); },
+/// Disassemble an eBPF instruction with the given [DisassemblyOptions]
+///
+/// See [disassemble_instruction_with_style] for the details of the style.
+pub fn disassemble_instruction_with_options<C: ContextObject>(
+    insn: &ebpf::Insn,
+    pc: usize,
+    cfg_nodes: &BTreeMap<usize, CfgNode>,
+    function_registry: &FunctionRegistry<usize>,
+    loader: &BuiltinProgram<C>,
+    sbpf_version: SBPFVersion,
+    options: DisassemblyOptions,
+) -> DisassembledInstruction {
+    let (mut mnemonic, mut desc) = disassemble_parts(
+        insn,
+        pc,
+        cfg_nodes,
+        function_registry,
+        loader,
+        sbpf_version,
+        options,
+    );
+    let mut bytes = insn.to_vec();
+    if mnemonic == "lddw" {
+        let mut second_slot = [0u8; ebpf::INSN_SIZE];
+        second_slot[4..].copy_from_slice(&((insn.imm as u64 >> 32) as u32).to_le_bytes());
+        bytes.extend_from_slice(&second_slot);
+    }
+    if options.style == DisassemblyStyle::Reassemblable {
+        match reassemblable_parts(insn, mnemonic, desc, cfg_nodes, loader, sbpf_version, options) {
+            Some(reassemblable) => (mnemonic, desc) = reassemblable,
+            None => {
+                mnemonic = ".fill";
+                desc = bytes
+                    .chunks_exact(ebpf::INSN_SIZE)
+                    .map(fill_str)
+                    .collect::<Vec<_>>()
+                    .join("\n");
+            }
+        }
+    } else if options.memory_widths {
+        if let Some(width) = memory_width(mnemonic) {
+            let unit = if width == 1 { "byte" } else { "bytes" };
+            desc = format!("{desc} ; {width} {unit}, opcode {:#04x}", insn.opc);
+        }
+    }
+    let operands = desc
+        .strip_prefix(mnemonic)
+        .map(|operands| operands.trim_start().to_string())
+        .unwrap_or(desc);
+    DisassembledInstruction {
+        pc: insn.ptr,
+        bytes,
+        mnemonic,
+        operands,
+    }
+}
+
+/// Returns the mnemonic and the assembler code of an eBPF instruction
+fn disassemble_parts<C: ContextObject>(
+    insn: &ebpf::Insn,
+    pc: usize,
+    cfg_nodes: &BTreeMap<usize, CfgNode>,
+    function_registry: &FunctionRegistry<usize>,
+    loader: &BuiltinProgram<C>,
+    sbpf_version: SBPFVersion,
+    options: DisassemblyOptions,
+) -> (&'static str, String) {
+    let decoded = decode(insn, sbpf_version);
+    let mut name = decoded.mnemonic;
+    let desc = match decoded.operands {
+        Operands::RegisterImmediate { dst, imm } => {
+            format!("{name} r{dst}, {}", imm_str(imm, options))
+        }
+        Operands::StoreImmediate { dst, off, imm } => format!(
+            "{name} [r{dst}{}], {}",
+            SignedOffset(off),
+            imm_str(imm, options)
+        ),
+        Operands::Jump { target_pc } => format!("{name} {}", resolve_label(cfg_nodes, target_pc)),
+        Operands::JumpImmediate {
+            dst,
+            imm,
+            target_pc,
+        } => format!(
+            "{name} r{dst}, {}, {}",
+            imm_str(imm, options),
+            resolve_label(cfg_nodes, target_pc)
+        ),
+        Operands::JumpRegister {
+            dst,
+            src,
+            target_pc,
+        } => format!(
+            "{name} r{dst}, r{src}, {}",
+            resolve_label(cfg_nodes, target_pc)
+        ),
+        Operands::Call { .. } => {
+            // The key depends on the pc the caller disassembles the instruction at
+            let key = sbpf_version.calculate_call_imm_target_pc(pc, insn.imm);
+            let mut function_name = function_registry
+                .lookup_by_key(key)
+                .map(|(function_name, _)| String::from_utf8_lossy(function_name).to_string());
+            if sbpf_version.static_syscalls() && function_name.is_none() {
+                // The function might have been recovered by the analysis, as the target is a pc
+                function_name = cfg_nodes
+                    .get(&(key as usize))
+                    .map(|cfg_node| cfg_node.label.clone());
+            } else if function_name.is_none() {
+                name = "syscall";
+                function_name = loader
+                    .get_function_registry()
+                    .lookup_by_key(insn.imm as u32)
+                    .map(|(function_name, _)| String::from_utf8_lossy(function_name).to_string());
+            }
+            format!(
+                "{name} {}",
+                function_name.unwrap_or_else(|| format!("[invalid] imm={:#x}", insn.imm as u32))
+            )
+        }
+        Operands::CallRegister { reg } => callx_str(name, reg, insn, cfg_nodes),
+        Operands::Syscall { key } => {
+            let function_name = loader
+                .get_function_registry()
+                .lookup_by_key(key)
+                .map(|(function_name, _)| String::from_utf8_lossy(function_name).to_string());
+            format!(
+                "{name} {}",
+                function_name.unwrap_or_else(|| insn.imm.to_string())
+            )
+        }
+        _ => decoded.to_string(),
     };
-    desc
+    (name, desc)
 }
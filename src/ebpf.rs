@@ -18,6 +18,7 @@
 //! <https://www.kernel.org/doc/Documentation/networking/filter.txt>, or for a shorter version of
 //! the list of the operation codes: <https://github.com/iovisor/bpf-docs/blob/master/eBPF.md>
 
+use crate::{error::EbpfError, program::SBPFVersion};
 use core::hash::Hash;
 use alloc::vec::Vec;
 use byteorder::{ByteOrder, LittleEndian};
@@ -491,6 +492,54 @@ pub const RETURN: u8 = BPF_JMP | BPF_X | BPF_EXIT;
 /// BPF opcode: `syscall` /// `syscall imm`. /// Valid only since SBPFv3
 pub const SYSCALL: u8 = BPF_JMP | BPF_SYSCALL;
 
+/// Values the immediate operand of an instruction is allowed to take
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImmediateRange {
+    /// Every value is accepted
+    Any,
+    /// Divisor of a division or remainder, must not be zero
+    NonZero,
+    /// Shift amount, must be less than the given number of bits
+    ShiftAmount(u64),
+    /// Width of a byte swap, must be 16, 32 or 64
+    ByteSwapWidth,
+}
+
+impl ImmediateRange {
+    /// Returns the range of the immediate operand of the opcode `opc` in `sbpf_version`
+    ///
+    /// Opcodes which do not exist in `sbpf_version` accept any immediate. Note that some opcodes
+    /// change their meaning between versions, e.g. [DIV64_IMM] is [ST_2B_IMM] since SBPFv2.
+    pub fn of_opcode(opc: u8, sbpf_version: SBPFVersion) -> Self {
+        match opc {
+            DIV32_IMM | MOD32_IMM | DIV64_IMM | MOD64_IMM if !sbpf_version.enable_pqr() => {
+                Self::NonZero
+            }
+            UDIV32_IMM | UDIV64_IMM | UREM32_IMM | UREM64_IMM | SDIV32_IMM | SDIV64_IMM
+            | SREM32_IMM | SREM64_IMM
+                if sbpf_version.enable_pqr() =>
+            {
+                Self::NonZero
+            }
+            LSH32_IMM | RSH32_IMM | ARSH32_IMM => Self::ShiftAmount(32),
+            LSH64_IMM | RSH64_IMM | ARSH64_IMM => Self::ShiftAmount(64),
+            LE if !sbpf_version.disable_le() => Self::ByteSwapWidth,
+            BE => Self::ByteSwapWidth,
+            _ => Self::Any,
+        }
+    }
+
+    /// Returns true if `imm` is in the range
+    pub fn contains(self, imm: i64) -> bool {
+        match self {
+            Self::Any => true,
+            Self::NonZero => imm != 0,
+            Self::ShiftAmount(bits) => imm >= 0 && (imm as u64) < bits,
+            Self::ByteSwapWidth => matches!(imm, 16 | 32 | 64),
+        }
+    }
+}
+
 // Used in JIT
 /// Mask to extract the operation class from an operation code.
 pub const BPF_CLS_MASK: u8 = 0x07;
@@ -529,6 +578,55 @@ impl fmt::Debug for Insn {
 }
 
 impl Insn {
+    /// Create an instruction, rejecting registers other than r0 to r10
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use solana_sbpf::ebpf;
+    ///
+    /// let insn = ebpf::Insn::new(ebpf::MOV64_IMM, 2, 0, 0, -1).unwrap();
+    /// assert_eq!(insn.to_array(), [0xb7, 0x02, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff]);
+    /// assert!(ebpf::Insn::new(ebpf::MOV64_IMM, 11, 0, 0, -1).is_err());
+    /// ```
+    pub fn new(opc: u8, dst: u8, src: u8, off: i16, imm: i32) -> Result<Self, EbpfError> {
+        for register in [dst, src] {
+            if register as usize > FRAME_PTR_REG {
+                return Err(EbpfError::InvalidRegister(register));
+            }
+        }
+        Ok(Self {
+            ptr: 0,
+            opc,
+            dst,
+            src,
+            off,
+            imm: imm as i64,
+        })
+    }
+
+    /// Create the two instruction slots of an `LD_DW_IMM` loading `value` into `dst`
+    ///
+    /// The second slot holds the upper half of `value`, [augment_lddw_unchecked] merges them
+    /// again.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use solana_sbpf::ebpf;
+    ///
+    /// let prog = ebpf::encode_program(&ebpf::Insn::new_lddw(1, 0x1122334455667788).unwrap());
+    /// let mut insn = ebpf::get_insn(&prog, 0);
+    /// ebpf::augment_lddw_unchecked(&prog, &mut insn);
+    /// assert_eq!(insn.imm, 0x1122334455667788);
+    /// ```
+    pub fn new_lddw(dst: u8, value: u64) -> Result<[Self; 2], EbpfError> {
+        Ok([
+            Self::new(LD_DW_IMM, dst, 0, 0, value as u32 as i32)?,
+            Self::new(0, 0, 0, 0, (value >> 32) as u32 as i32)?,
+        ])
+    }
+
     /// Turn an `Insn` back into an array of bytes.
     ///
     /// # Examples
@@ -587,6 +685,14 @@ impl Insn {
     }
 }
 
+/// Encode instructions to bytes, the inverse of [get_insn] for each slot
+///
+/// Only the lower 32 bits of `imm` are encoded, an `LD_DW_IMM` needs its second slot (see
+/// [Insn::new_lddw]).
+pub fn encode_program(insns: &[Insn]) -> Vec<u8> {
+    insns.iter().flat_map(Insn::to_array).collect()
+}
+
 /// Get the instruction at `idx` of an eBPF program. `idx` is the index (number) of the
 /// instruction (not a byte offset). The first instruction has index 0.
 ///
@@ -6,31 +6,45 @@
 // where the section headers may be removed from the ELF.  If that happens then
 // this loader will need to be re-written to use the program headers instead.
 
-use alloc::{format, string::{ToString, String}, vec, vec::Vec};
+use alloc::{boxed::Box, format, string::{ToString, String}, vec, vec::Vec};
 
 use crate::{
     aligned_memory::{is_memory_aligned, AlignedMemory},
+    disassembler::{decode, Operands},
     ebpf::{self, EF_SBPF_V2, HOST_ALIGN, INSN_SIZE},
     elf_parser::{
         consts::{
-            ELFCLASS64, ELFDATA2LSB, ELFOSABI_NONE, EM_BPF, EM_SBPF, ET_DYN, R_X86_64_32,
-            R_X86_64_64, R_X86_64_NONE, R_X86_64_RELATIVE,
+            ELFCLASS64, ELFDATA2LSB, ELFOSABI_NONE, EM_BPF, EM_SBPF, ET_DYN, PF_X, R_X86_64_32,
+            R_X86_64_64, R_X86_64_NONE, R_X86_64_RELATIVE, SHF_ALLOC,
         },
         types::{Elf64Phdr, Elf64Shdr, Elf64Word},
-        Elf64, ElfParserError,
+        saturating_usize, sbpf_version_from_e_flags, AlignedElfBytes, Elf64, ElfParserError,
+        ElfParserOptions,
     },
     error::EbpfError,
-    memory_region::MemoryRegion,
+    memory_region::{MemoryRegion, MemoryRegionKind},
     program::{BuiltinProgram, FunctionRegistry, SBPFVersion},
-    verifier::Verifier,
+    report::ProgramReport,
+    static_analysis::{Analysis, Bound},
+    verifier::{lddw_second_slots, Verifier, VerifierConfig, VerifierError},
     vm::{Config, ContextObject},
 };
 
+#[cfg(target_endian = "big")]
+use crate::elf_parser::swap_byte_order;
 #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
-use crate::jit::{JitCompiler, JitProgram};
+use crate::jit::{JitCompiler, JitProgram, JitSymbol};
+#[cfg(all(target_pointer_width = "64", target_endian = "little"))]
+use crate::{interpreter::predecode_instructions, verifier::RequisiteVerifier};
 use byteorder::{ByteOrder, LittleEndian};
-use alloc::{collections::BTreeMap, fmt::Debug, str};
-use core::{mem, ops::Range};
+use alloc::{fmt::Debug, str};
+use hashbrown::HashMap;
+use core::{
+    convert::TryFrom,
+    hash::{Hash, Hasher},
+    mem,
+    ops::Range,
+};
 
 #[cfg(not(feature = "shuttle-test"))]
 use alloc::sync::Arc;
@@ -65,6 +79,20 @@ pub enum ElfError {
     /// Symbol hash collision
     #[error("Symbol hash collision {0:#x}")]
     SymbolHashCollision(u32),
+    /// Two different names map to the same key of a function registry
+    #[error(
+        "Key collision {key:#x} between {} and {}",
+        String::from_utf8_lossy(.existing),
+        String::from_utf8_lossy(.new)
+    )]
+    SyscallKeyCollision {
+        /// Name registered first
+        existing: Box<[u8]>,
+        /// Name which was rejected
+        new: Box<[u8]>,
+        /// The shared key
+        key: u32,
+    },
     /// Incompatible ELF: wrong endianess
     #[error("Incompatible ELF: wrong endianess")]
     WrongEndianess,
@@ -90,8 +118,20 @@ pub enum ElfError {
     #[error("Relocation failed, invalid referenced virtual address {0:#x}")]
     InvalidVirtualAddress(u64),
     /// Relocation failed, unknown type
-    #[error("Relocation failed, unknown type {0:?}")]
-    UnknownRelocation(u32),
+    #[error(
+        "Relocation failed, unknown type {r_type} of relocation #{index} at {r_offset:#x} \
+         (symbol {symbol_name:?})"
+    )]
+    UnknownRelocation {
+        /// Index of the relocation in the dynamic relocation tables
+        index: usize,
+        /// Virtual address the relocation applies to
+        r_offset: u64,
+        /// Type of the relocation
+        r_type: Elf64Word,
+        /// Name of the dynamic symbol the relocation refers to
+        symbol_name: Option<String>,
+    },
     /// Failed to read relocation info
     #[error("Failed to read relocation info")]
     FailedToReadRelocationInfo,
@@ -110,16 +150,35 @@ pub enum ElfError {
     /// Invalid program header
     #[error("Invalid ELF program header")]
     InvalidProgramHeader,
+    /// Relocation patches were not recorded while loading
+    #[error("Relocation patches were not recorded, see Config::record_relocation_patches")]
+    RelocationPatchesNotRecorded,
+    /// Applying the recorded relocation patches did not reproduce the loaded executable
+    #[error("Relocation patches diverge from the loaded executable: {0}")]
+    RelocationPatchMismatch(String),
+    /// The PT_GNU_STACK segment is executable
+    #[error("Incompatible ELF: executable stack, link with -z noexecstack")]
+    ExecutableStack,
+    /// Read-only data overlaps the text section or does not fit into the read-only region
+    #[error(
+        "Read-only data at virtual address {0:#x} overlaps the text section or leaves the \
+         read-only region"
+    )]
+    InvalidRodataAddress(u64),
+    /// A serialized executable is corrupted or does not belong to the ELF, the config or the
+    /// version of this crate, see [Executable::from_serialized]
+    #[error("Serialized executable rejected: {0}")]
+    InvalidSerializedExecutable(String),
 }
 
 impl From<ElfParserError> for ElfError {
     fn from(err: ElfParserError) -> Self {
         match err {
-            ElfParserError::InvalidSectionHeader
+            ElfParserError::InvalidSectionHeader { .. }
             | ElfParserError::InvalidString
             | ElfParserError::InvalidSize
             | ElfParserError::Overlap
-            | ElfParserError::SectionNotInOrder
+            | ElfParserError::SectionNotInOrder { .. }
             | ElfParserError::NoSectionNameStringTable
             | ElfParserError::InvalidDynamicSectionTable
             | ElfParserError::InvalidRelocationTable
@@ -127,16 +186,24 @@ impl From<ElfParserError> for ElfError {
             | ElfParserError::NoStringTable
             | ElfParserError::NoDynamicStringTable
             | ElfParserError::InvalidFileHeader
+            | ElfParserError::UnsupportedSegmentType(_)
+            | ElfParserError::UnsupportedCallFrameInstruction(_)
+            | ElfParserError::InvalidCallFrameInformation
+            | ElfParserError::InvalidLineNumberProgram
+            | ElfParserError::SegmentVmOverlap { .. }
+            | ElfParserError::InvalidDynamicSymbol { .. }
             | ElfParserError::StringTooLong(_, _) => ElfError::FailedToParse(err.to_string()),
-            ElfParserError::InvalidProgramHeader => ElfError::InvalidProgramHeader,
-            ElfParserError::OutOfBounds => ElfError::ValueOutOfBounds,
+            ElfParserError::InvalidProgramHeader { .. } => ElfError::InvalidProgramHeader,
+            ElfParserError::OutOfBounds { .. } => ElfError::ValueOutOfBounds,
+            ElfParserError::InvalidSymbolIndex(index) => ElfError::UnknownSymbol(index),
         }
     }
 }
 
 fn get_section(elf: &Elf64, name: &[u8]) -> Result<Elf64Shdr, ElfError> {
-    for section_header in elf.section_header_table() {
-        if elf.section_name(section_header.sh_name)? == name {
+    // Unlike Elf64::find_section(), fails on the first name which does not resolve
+    for (section_name, section_header) in elf.section_headers() {
+        if section_name? == name {
             return Ok(section_header.clone());
         }
     }
@@ -148,6 +215,59 @@ fn get_section(elf: &Elf64, name: &[u8]) -> Result<Elf64Shdr, ElfError> {
     ))
 }
 
+/// Resolves an explicit relocation addend relative to `base`, which must
+/// land inside (or right at the end of) one of the allocated sections.
+fn resolve_addend(elf: &Elf64, base: u64, addend: i64) -> Result<u64, ElfError> {
+    let addr = (base as i64)
+        .checked_add(addend)
+        .filter(|addr| *addr >= 0)
+        .ok_or(ElfError::ValueOutOfBounds)? as u64;
+    elf.section_header_table()
+        .iter()
+        .filter(|section_header| section_header.sh_flags & SHF_ALLOC != 0)
+        .any(|section_header| {
+            section_header.sh_addr <= addr
+                && addr <= section_header.sh_addr.saturating_add(section_header.sh_size)
+        })
+        .then_some(addr)
+        .ok_or(ElfError::ValueOutOfBounds)
+}
+
+/// Checks that `len` bytes starting at `vaddr` lie within `region`
+fn is_within(vaddr: u64, len: usize, region: Range<u64>) -> bool {
+    vaddr >= region.start
+        && matches!(vaddr.checked_add(len as u64), Some(end_vaddr) if end_vaddr <= region.end)
+}
+
+/// Overwrites `bytes[offset..]` with `value` and records the modification if `patches` is enabled.
+///
+/// `bytes` starts at `file_offset` in the ELF file. `source` names the relocation type and symbol
+/// of the patch and is only evaluated when recording.
+fn write_patch(
+    patches: &mut Option<Vec<RelocationPatch>>,
+    bytes: &mut [u8],
+    file_offset: usize,
+    offset: usize,
+    value: &[u8],
+    source: impl FnOnce() -> (Option<Elf64Word>, Option<Vec<u8>>),
+) -> Result<(), ElfError> {
+    let slice = bytes
+        .get_mut(offset..offset.saturating_add(value.len()))
+        .ok_or(ElfError::ValueOutOfBounds)?;
+    if let Some(patches) = patches {
+        let (relocation_type, symbol_name) = source();
+        patches.push(RelocationPatch {
+            file_offset: file_offset.saturating_add(offset),
+            old_bytes: slice.to_vec(),
+            new_bytes: value.to_vec(),
+            relocation_type,
+            symbol_name,
+        });
+    }
+    slice.copy_from_slice(value);
+    Ok(())
+}
+
 // For more information on the BPF instruction set:
 // https://github.com/iovisor/bpf-docs/blob/master/eBPF.md
 
@@ -223,13 +343,47 @@ pub enum Section {
     ///
     /// The first field is virtual address of the section.
     /// The second field is the actual section data.
-    Owned(usize, Vec<u8>),
+    Owned(u64, Vec<u8>),
     /// Borrowed section data.
     ///
     /// The first field is virtual address of the section.
     /// The second field can be used to index the input ELF buffer to
     /// retrieve the section data.
-    Borrowed(usize, Range<usize>),
+    Borrowed(u64, Range<usize>),
+}
+
+/// A modification of the ELF bytes made by the relocation pass
+///
+/// Only recorded if [Config::record_relocation_patches] is enabled, see
+/// [Executable::get_relocation_patches].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelocationPatch {
+    /// Offset of the modified bytes in the ELF file
+    pub file_offset: usize,
+    /// Bytes before the modification
+    pub old_bytes: Vec<u8>,
+    /// Bytes after the modification
+    pub new_bytes: Vec<u8>,
+    /// Type of the dynamic relocation, `None` for program counter relative calls
+    pub relocation_type: Option<Elf64Word>,
+    /// Name of the dynamic symbol the relocation refers to
+    pub symbol_name: Option<Vec<u8>>,
+}
+
+/// A dynamic relocation of an unknown type which was left unapplied while loading
+///
+/// Only skipped if [Config::skip_unknown_relocations] is enabled, see
+/// [Executable::get_skipped_relocations].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedRelocation {
+    /// Index of the relocation in the dynamic relocation tables
+    pub index: usize,
+    /// Virtual address the relocation applies to
+    pub r_offset: u64,
+    /// Type of the relocation
+    pub r_type: Elf64Word,
+    /// Name of the dynamic symbol the relocation refers to
+    pub symbol_name: Option<Vec<u8>>,
 }
 
 /// Elf loader/relocator
@@ -251,8 +405,16 @@ pub struct Executable<C: ContextObject> {
     entry_pc: usize,
     /// Call resolution map (hash, pc, name)
     function_registry: FunctionRegistry<usize>,
+    /// Key and pc range of each function, sorted by pc
+    function_ranges: Vec<(u32, Range<usize>)>,
+    /// Modifications made by the relocation pass, if recorded
+    relocation_patches: Option<Vec<RelocationPatch>>,
+    /// Relocations of unknown types the relocation pass left unapplied
+    skipped_relocations: Vec<SkippedRelocation>,
     /// Loader built-in program
     loader: Arc<BuiltinProgram<C>>,
+    /// Decoded instructions for the interpreter, see [Executable::predecode]
+    predecoded_instructions: Option<Vec<ebpf::Insn>>,
     /// Compiled program and argument
     #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
     compiled_program: Option<JitProgram>,
@@ -279,10 +441,7 @@ impl<C: ContextObject> Executable<C> {
 
     /// Get the elf bytes
     pub fn get_elf_bytes(&self) -> &[u8] {
-        self.original_elf_bytes
-            .as_ref()
-            .map(|v| v.as_slice())
-            .unwrap()
+        self.original_elf_bytes.as_deref().unwrap()
     }
 
     /// Get the concatenated read-only sections (including the text section)
@@ -295,6 +454,7 @@ impl<C: ContextObject> Executable<C> {
         }
     }
 
+
     /// Get a memory region that can be used to access the merged readonly section
     pub fn get_ro_region(&self) -> MemoryRegion {
         get_ro_region(&self.ro_section, self.elf_bytes.as_slice())
@@ -323,17 +483,99 @@ impl<C: ContextObject> Executable<C> {
     }
 
     /// Verify the executable
+    ///
+    /// Also checks the static bound of executed instructions if [Config::max_execution_bound]
+    /// is set and rejects leaks of the frame pointer if [Config::reject_stack_pointer_leaks] is
+    /// set.
     pub fn verify<V: Verifier>(&self) -> Result<(), EbpfError> {
+        self.verify_with_config::<V>(&VerifierConfig::default())
+    }
+
+    /// Verify the executable with adjusted rules, see [Executable::verify]
+    pub fn verify_with_config<V: Verifier>(
+        &self,
+        verifier_config: &VerifierConfig,
+    ) -> Result<(), EbpfError> {
         <V as Verifier>::verify(
             self.get_text_bytes().1,
             self.get_config(),
+            verifier_config,
             self.get_sbpf_version(),
             self.get_function_registry(),
             self.loader.get_function_registry(),
         )?;
+        let config = self.get_config();
+        if config.max_execution_bound.is_none() && !config.reject_stack_pointer_leaks {
+            return Ok(());
+        }
+        let analysis = Analysis::from_executable(self)?;
+        if let Some(budget) = config.max_execution_bound {
+            match analysis.max_execution_bound() {
+                Bound::Exact(bound) if bound <= budget => {}
+                Bound::Exact(bound) => {
+                    return Err(VerifierError::ExecutionBoundExceeded(bound, budget).into())
+                }
+                Bound::Unbounded {
+                    loops,
+                    recursion,
+                    indirect_calls,
+                } => {
+                    let pc = loops
+                        .first()
+                        .or_else(|| recursion.first().and_then(|cycle| cycle.first()))
+                        .or_else(|| indirect_calls.first())
+                        .copied()
+                        .unwrap_or_default();
+                    return Err(VerifierError::UnboundedExecution(pc).into());
+                }
+            }
+        }
+        if config.reject_stack_pointer_leaks {
+            if let Some((source, sink)) = analysis.stack_pointer_leaks().first() {
+                return Err(VerifierError::StackPointerLeak(*source, *sink).into());
+            }
+        }
         Ok(())
     }
 
+    /// Get the pc and opcode of every instruction which is invalid in the SBPF version
+    ///
+    /// Unlike the verifier, which stops at the first [VerifierError::UnknownOpCode], this scans
+    /// the whole text section. The opcodes are the ones the disassembler decodes as `unknown`,
+    /// see [decode]. The second slot of an `lddw` is not an instruction of its own.
+    pub fn unsupported_opcodes(&self) -> Vec<(usize, u8)> {
+        let (_program_vm_addr, program) = self.get_text_bytes();
+        let sbpf_version = self.get_sbpf_version();
+        lddw_second_slots(program, sbpf_version)
+            .into_iter()
+            .enumerate()
+            .filter(|(_pc, is_second_slot)| !is_second_slot)
+            .map(|(pc, _is_second_slot)| ebpf::get_insn(program, pc))
+            .filter(|insn| matches!(decode(insn, sbpf_version).operands, Operands::Unknown { .. }))
+            .map(|insn| (insn.ptr, insn.opc))
+            .collect()
+    }
+
+    /// Decode all instructions once, so that the interpreter does not have to on every step
+    ///
+    /// Verifies the executable with the [RequisiteVerifier] first, as the interpreter relies on
+    /// it having checked all jump targets. Trades memory for speed: Each 8 byte instruction slot
+    /// takes a [ebpf::Insn] of 24 bytes. The interpreter still reports the original pc values.
+    #[cfg(all(target_pointer_width = "64", target_endian = "little"))]
+    pub fn predecode(&mut self) -> Result<(), EbpfError> {
+        self.verify::<RequisiteVerifier>()?;
+        let (_program_vm_addr, program) = self.get_text_bytes();
+        self.predecoded_instructions = Some(predecode_instructions(program, self.sbpf_version));
+        Ok(())
+    }
+
+    /// Get the decoded instructions, see [Executable::predecode]
+    ///
+    /// There is one for each instruction slot of the text section.
+    pub fn get_predecoded_instructions(&self) -> Option<&[ebpf::Insn]> {
+        self.predecoded_instructions.as_deref()
+    }
+
     /// JIT compile the executable
     #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
     pub fn jit_compile(&mut self) -> Result<(), crate::error::EbpfError> {
@@ -342,11 +584,116 @@ impl<C: ContextObject> Executable<C> {
         Ok(())
     }
 
+    /// The host machine code of each function, if JIT compiled
+    ///
+    /// For symbolizing profiles, see [JitProgram::write_perf_map] and
+    /// [JitProgram::write_jitdump].
+    #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
+    pub fn jit_symbols(&self) -> Option<Vec<JitSymbol>> {
+        self.compiled_program
+            .as_ref()
+            .map(|program| program.symbols(&self.function_registry))
+    }
+
+    /// Summarize the executable
+    ///
+    /// Runs the static analysis; use [ProgramReport::new] to reuse an existing [Analysis].
+    pub fn report(&self) -> Result<ProgramReport, EbpfError> {
+        Ok(ProgramReport::new(self, &Analysis::from_executable(self)?))
+    }
+
     /// Get the function registry
     pub fn get_function_registry(&self) -> &FunctionRegistry<usize> {
         &self.function_registry
     }
 
+    /// Get the key and pc range of the function which contains `pc`
+    ///
+    /// Each function of the registry extends up to the start of the next one, or to the end of
+    /// the text section. The pcs in front of the first function belong to none. When the
+    /// symbols were stripped the entrypoint is the only function left, covering everything
+    /// from the entrypoint on. Functions sharing a pc are reported by their smallest key.
+    pub fn function_containing_pc(&self, pc: usize) -> Option<(u32, Range<usize>)> {
+        let index = self
+            .function_ranges
+            .partition_point(|(_key, range)| range.start <= pc);
+        let (key, range) = self.function_ranges.get(index.checked_sub(1)?)?;
+        range.contains(&pc).then(|| (*key, range.clone()))
+    }
+
+    /// Get the modifications made by the relocation pass, in the order they were applied
+    ///
+    /// Returns `None` unless [Config::record_relocation_patches] was enabled while loading.
+    pub fn get_relocation_patches(&self) -> Option<&[RelocationPatch]> {
+        self.relocation_patches.as_deref()
+    }
+
+    /// Get the relocations of unknown types which were left unapplied, in the order of the
+    /// dynamic relocation tables
+    ///
+    /// Always empty unless [Config::skip_unknown_relocations] was enabled while loading an ELF.
+    pub fn get_skipped_relocations(&self) -> &[SkippedRelocation] {
+        &self.skipped_relocations
+    }
+
+    /// Check that the recorded relocation patches reproduce the loaded executable
+    ///
+    /// Applies the patches to a copy of the original `elf_bytes` and compares the resulting
+    /// text and read-only sections to the ones of this executable.
+    pub fn verify_relocation_patches(&self, elf_bytes: &[u8]) -> Result<(), ElfError> {
+        let patches = self
+            .relocation_patches
+            .as_ref()
+            .ok_or(ElfError::RelocationPatchesNotRecorded)?;
+        let mut patched = AlignedMemory::<{ HOST_ALIGN }>::from_slice(elf_bytes);
+        for patch in patches {
+            let target = patched
+                .as_slice_mut()
+                .get_mut(patch.file_offset..patch.file_offset.saturating_add(patch.old_bytes.len()))
+                .filter(|target| *target == patch.old_bytes.as_slice())
+                .ok_or_else(|| {
+                    ElfError::RelocationPatchMismatch(format!(
+                        "patch at file offset {:#x} does not apply",
+                        patch.file_offset
+                    ))
+                })?;
+            target.copy_from_slice(&patch.new_bytes);
+        }
+
+        if patched.as_slice().get(self.text_section_range.clone()) != Some(self.get_text_bytes().1)
+        {
+            return Err(ElfError::RelocationPatchMismatch(
+                ".text section".to_string(),
+            ));
+        }
+        let ro_section = match &self.ro_section {
+            Section::Owned(_offset, _data) => {
+                // Copied from the relocated bytes, so copy it again from the patched ones
+                let native_elf_bytes = AlignedElfBytes::new(patched.as_slice());
+                let elf = native_elf_bytes.parse()?;
+                Self::parse_ro_sections(
+                    self.get_config(),
+                    &self.sbpf_version,
+                    elf.section_header_table()
+                        .iter()
+                        .map(|s| (elf.section_name(s.sh_name).ok(), s)),
+                    patched.as_slice(),
+                )?
+            }
+            Section::Borrowed(offset, byte_range) => Section::Borrowed(*offset, byte_range.clone()),
+        };
+        let ro_bytes = match &ro_section {
+            Section::Owned(_offset, data) => Some(data.as_slice()),
+            Section::Borrowed(_offset, byte_range) => patched.as_slice().get(byte_range.clone()),
+        };
+        if ro_bytes != Some(self.get_ro_section()) {
+            return Err(ElfError::RelocationPatchMismatch(
+                "read-only section".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
     /// Create from raw text section bytes (list of instructions)
     pub fn new_from_text_bytes(
         text_bytes: &[u8],
@@ -366,11 +713,12 @@ impl<C: ContextObject> Executable<C> {
             )?;
             0
         };
+        let function_ranges = function_ranges(&function_registry, text_bytes.len());
         Ok(Self {
             original_elf_bytes: None,
             elf_bytes,
             sbpf_version,
-            ro_section: Section::Borrowed(ebpf::MM_RODATA_START as usize, 0..text_bytes.len()),
+            ro_section: Section::Borrowed(ebpf::MM_RODATA_START, 0..text_bytes.len()),
             text_section_vaddr: if sbpf_version.enable_lower_bytecode_vaddr() {
                 ebpf::MM_BYTECODE_START
             } else {
@@ -378,8 +726,191 @@ impl<C: ContextObject> Executable<C> {
             },
             text_section_range: 0..text_bytes.len(),
             entry_pc,
+            function_ranges,
             function_registry,
+            relocation_patches: None,
+            skipped_relocations: Vec::new(),
             loader,
+            predecoded_instructions: None,
+            #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
+            compiled_program: None,
+        })
+    }
+
+    /// Create from raw text section bytes and read-only data at `rodata_vaddr`
+    ///
+    /// The read-only data must lie between [ebpf::MM_RODATA_START] and [ebpf::MM_STACK_START].
+    /// If the text section is mapped into the read-only region too, because the version does
+    /// not [SBPFVersion::enable_lower_bytecode_vaddr], the read-only data must follow it and the
+    /// gap in between is filled with zeros, like the ELF loader does.
+    pub fn new_from_text_and_rodata(
+        text_bytes: &[u8],
+        rodata: Vec<u8>,
+        rodata_vaddr: u64,
+        loader: Arc<BuiltinProgram<C>>,
+        sbpf_version: SBPFVersion,
+        function_registry: FunctionRegistry<usize>,
+    ) -> Result<Self, ElfError> {
+        let mut executable =
+            Self::new_from_text_bytes(text_bytes, loader, sbpf_version, function_registry)?;
+        let lowest_vaddr = if sbpf_version.enable_lower_bytecode_vaddr() {
+            ebpf::MM_RODATA_START
+        } else {
+            ebpf::MM_RODATA_START.saturating_add(text_bytes.len() as u64)
+        };
+        if !is_within(
+            rodata_vaddr,
+            rodata.len(),
+            lowest_vaddr..ebpf::MM_STACK_START,
+        ) {
+            return Err(ElfError::InvalidRodataAddress(rodata_vaddr));
+        }
+        executable.ro_section = if sbpf_version.enable_lower_bytecode_vaddr() {
+            Section::Owned(rodata_vaddr, rodata)
+        } else {
+            let mut ro_data = text_bytes.to_vec();
+            ro_data.resize(
+                rodata_vaddr.saturating_sub(ebpf::MM_RODATA_START) as usize,
+                0,
+            );
+            ro_data.extend_from_slice(&rodata);
+            Section::Owned(ebpf::MM_RODATA_START, ro_data)
+        };
+        Ok(executable)
+    }
+
+    /// Serializes the loaded and relocated program, see [Executable::from_serialized]
+    ///
+    /// Besides the text and read-only sections, the output contains the entry point, the
+    /// function registry, the SBPF version, a hash of the [Config] and a hash of the ELF the
+    /// executable was loaded from, or of the text section if there is no backup of the ELF.
+    /// The format is tied to the version of this crate and ends with a checksum.
+    pub fn serialize_rodata(&self) -> Vec<u8> {
+        let text_bytes = self.get_text_bytes().1;
+        let ro_vaddr = match &self.ro_section {
+            Section::Owned(offset, _data) => *offset,
+            Section::Borrowed(offset, _byte_range) => *offset,
+        };
+        let elf_bytes = self.original_elf_bytes.as_deref().unwrap_or(text_bytes);
+        let mut output = SERIALIZED_MAGIC.to_vec();
+        output.extend_from_slice(&SERIALIZED_FORMAT_VERSION.to_le_bytes());
+        put_prefixed(&mut output, env!("CARGO_PKG_VERSION").as_bytes());
+        put_prefixed(&mut output, self.sbpf_version.to_string().as_bytes());
+        output.extend_from_slice(&config_hash(self.get_config()).to_le_bytes());
+        output.extend_from_slice(&fnv1a(elf_bytes).to_le_bytes());
+        output.extend_from_slice(&(self.entry_pc as u64).to_le_bytes());
+        output.extend_from_slice(&self.text_section_vaddr.to_le_bytes());
+        put_prefixed(&mut output, text_bytes);
+        output.extend_from_slice(&ro_vaddr.to_le_bytes());
+        put_prefixed(&mut output, self.get_ro_section());
+        output.extend_from_slice(&(self.function_registry.iter().count() as u64).to_le_bytes());
+        for (key, (name, pc)) in self.function_registry.iter() {
+            output.extend_from_slice(&key.to_le_bytes());
+            output.extend_from_slice(&(pc as u64).to_le_bytes());
+            put_prefixed(&mut output, name);
+        }
+        let checksum = fnv1a(&output);
+        output.extend_from_slice(&checksum.to_le_bytes());
+        output
+    }
+
+    /// Reconstructs an executable from the output of [Executable::serialize_rodata]
+    ///
+    /// Neither parses nor relocates the ELF, but checks that `elf_bytes` are the ones the
+    /// executable was serialized from, that the config of the `loader` is valid and has the
+    /// same [config_hash] and that the serialized bytes are intact. The checksum only guards
+    /// against accidental corruption, so the sections and functions are bounds checked like
+    /// [Executable::new_from_text_and_rodata] does. The executable still needs to be verified
+    /// before it is executed.
+    pub fn from_serialized(
+        serialized: &[u8],
+        elf_bytes: &[u8],
+        loader: Arc<BuiltinProgram<C>>,
+    ) -> Result<Self, EbpfError> {
+        loader.get_config().validate()?;
+        let rejected = |reason: &str| ElfError::InvalidSerializedExecutable(reason.to_string());
+        let (payload, checksum) = serialized
+            .len()
+            .checked_sub(mem::size_of::<u64>())
+            .map(|len| serialized.split_at(len))
+            .ok_or_else(|| rejected("truncated"))?;
+        if fnv1a(payload).to_le_bytes() != checksum {
+            return Err(rejected("checksum mismatch").into());
+        }
+        let mut reader = SerializedReader(payload);
+        if reader.bytes(SERIALIZED_MAGIC.len())? != SERIALIZED_MAGIC {
+            return Err(rejected("not a serialized executable").into());
+        }
+        if reader.u32()? != SERIALIZED_FORMAT_VERSION
+            || reader.prefixed()? != env!("CARGO_PKG_VERSION").as_bytes()
+        {
+            return Err(rejected("incompatible format version").into());
+        }
+        let sbpf_version = str::from_utf8(reader.prefixed()?)
+            .ok()
+            .and_then(|version| version.parse::<SBPFVersion>().ok())
+            .ok_or_else(|| rejected("unknown SBPF version"))?;
+        if !loader
+            .get_config()
+            .enabled_sbpf_versions
+            .contains(&sbpf_version)
+        {
+            return Err(ElfError::UnsupportedSBPFVersion.into());
+        }
+        if reader.u64()? != config_hash(loader.get_config()) {
+            return Err(rejected("config mismatch").into());
+        }
+        if reader.u64()? != fnv1a(elf_bytes) {
+            return Err(rejected("ELF mismatch").into());
+        }
+        // Out of range values saturate, so that the bounds checks below reject them
+        let entry_pc = usize::try_from(reader.u64()?).unwrap_or(usize::MAX);
+        let text_section_vaddr = reader.u64()?;
+        let text_bytes = reader.prefixed()?;
+        let text_region = if sbpf_version.enable_lower_bytecode_vaddr() {
+            ebpf::MM_BYTECODE_START..ebpf::MM_RODATA_START
+        } else {
+            ebpf::MM_RODATA_START..ebpf::MM_STACK_START
+        };
+        if !is_within(text_section_vaddr, text_bytes.len(), text_region) {
+            return Err(rejected("text section out of bounds").into());
+        }
+        let instruction_count = text_bytes.len() / ebpf::INSN_SIZE;
+        let ro_vaddr = reader.u64()?;
+        let ro_data = reader.prefixed()?.to_vec();
+        if !is_within(
+            ro_vaddr,
+            ro_data.len(),
+            ebpf::MM_RODATA_START..ebpf::MM_STACK_START,
+        ) {
+            return Err(ElfError::InvalidRodataAddress(ro_vaddr).into());
+        }
+        let mut function_registry = FunctionRegistry::default();
+        for _ in 0..reader.u64()? {
+            let key = reader.u32()?;
+            let pc = usize::try_from(reader.u64()?).unwrap_or(usize::MAX);
+            if pc >= instruction_count {
+                return Err(rejected("function out of bounds").into());
+            }
+            function_registry.register_function(key, reader.prefixed()?, pc)?;
+        }
+        if entry_pc >= instruction_count {
+            return Err(ElfError::EntrypointOutOfBounds.into());
+        }
+        Ok(Self {
+            original_elf_bytes: Some(elf_bytes.to_vec()),
+            elf_bytes: AlignedMemory::from_slice(text_bytes),
+            sbpf_version,
+            ro_section: Section::Owned(ro_vaddr, ro_data),
+            text_section_vaddr,
+            text_section_range: 0..text_bytes.len(),
+            entry_pc,
+            function_ranges: function_ranges(&function_registry, text_bytes.len()),
+            function_registry,
+            relocation_patches: None,
+            skipped_relocations: Vec::new(),
+            loader,
+            predecoded_instructions: None,
             #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
             compiled_program: None,
         })
@@ -393,10 +924,14 @@ impl<C: ContextObject> Executable<C> {
     /// Fully loads an ELF with backup config
     pub fn load_with_backup(bytes: &[u8], loader: Arc<BuiltinProgram<C>>, backup: bool) -> Result<Self, ElfError> {
         const E_FLAGS_OFFSET: usize = 48;
+        let e_flags_range = E_FLAGS_OFFSET..E_FLAGS_OFFSET.saturating_add(core::mem::size_of::<u32>());
         let e_flags = LittleEndian::read_u32(
             bytes
-                .get(E_FLAGS_OFFSET..E_FLAGS_OFFSET.saturating_add(core::mem::size_of::<u32>()))
-                .ok_or(ElfParserError::OutOfBounds)?,
+                .get(e_flags_range.clone())
+                .ok_or(ElfParserError::OutOfBounds {
+                    offset: e_flags_range.start,
+                    len: e_flags_range.len(),
+                })?,
         );
         let config = loader.get_config();
         let sbpf_version = if config.enabled_sbpf_versions.end() == &SBPFVersion::V0 {
@@ -406,13 +941,7 @@ impl<C: ContextObject> Executable<C> {
                 SBPFVersion::V0
             }
         } else {
-            match e_flags {
-                0 => SBPFVersion::V0,
-                1 => SBPFVersion::V1,
-                2 => SBPFVersion::V2,
-                3 => SBPFVersion::V3,
-                _ => SBPFVersion::Reserved,
-            }
+            sbpf_version_from_e_flags(e_flags).unwrap_or(SBPFVersion::Reserved)
         };
         if !config.enabled_sbpf_versions.contains(&sbpf_version) {
             return Err(ElfError::UnsupportedSBPFVersion);
@@ -421,7 +950,12 @@ impl<C: ContextObject> Executable<C> {
         let mut executable = if sbpf_version.enable_stricter_elf_headers() {
             Self::load_with_strict_parser(bytes, loader)?
         } else {
-            Self::load_with_lenient_parser(bytes, loader)?
+            let options = ElfParserOptions {
+                reject_unknown_e_flags: config.reject_unknown_e_flags,
+                reject_overlapping_segments: sbpf_version > SBPFVersion::V0,
+                validate_dynamic_symbols: sbpf_version > SBPFVersion::V0,
+            };
+            Self::load_with_lenient_parser(bytes, loader, options)?
         };
         executable.sbpf_version = sbpf_version;
         executable.original_elf_bytes = if backup {
@@ -446,7 +980,10 @@ impl<C: ContextObject> Executable<C> {
         };
 
         let aligned_memory = AlignedMemory::<{ HOST_ALIGN }>::from_slice(bytes);
-        let elf_bytes = aligned_memory.as_slice();
+        // Big endian hosts parse the headers and tables from a converted copy, the text and
+        // rodata are taken from the file bytes
+        let native_elf_bytes = AlignedElfBytes::new(aligned_memory.as_slice());
+        let elf_bytes = native_elf_bytes.as_slice();
 
         let (file_header_range, file_header) = Elf64::parse_file_header(elf_bytes)?;
         let program_header_table_range = mem::size_of::<Elf64Ehdr>()
@@ -466,7 +1003,8 @@ impl<C: ContextObject> Executable<C> {
             // file_header.e_entry
             || file_header.e_phoff != mem::size_of::<Elf64Ehdr>() as u64
             // file_header.e_shoff
-            // file_header.e_flags
+            || (loader.get_config().reject_unknown_e_flags
+                && sbpf_version_from_e_flags(file_header.e_flags).is_err())
             || file_header.e_ehsize != mem::size_of::<Elf64Ehdr>() as u16
             || file_header.e_phentsize != mem::size_of::<Elf64Phdr>() as u16
             || file_header.e_phnum < EXPECTED_PROGRAM_HEADERS.len() as u16
@@ -487,9 +1025,10 @@ impl<C: ContextObject> Executable<C> {
         ];
         let program_header_table =
             Elf64::slice_from_bytes::<Elf64Phdr>(elf_bytes, program_header_table_range.clone())?;
-        for (program_header, (p_type, p_flags, p_vaddr)) in program_header_table
+        for (index, (program_header, (p_type, p_flags, p_vaddr))) in program_header_table
             .iter()
             .zip(EXPECTED_PROGRAM_HEADERS.iter())
+            .enumerate()
         {
             let p_filesz = if (*p_flags & PF_W) != 0 {
                 0
@@ -508,7 +1047,7 @@ impl<C: ContextObject> Executable<C> {
                     > (elf_bytes.len() as u64).saturating_sub(program_header.p_offset)
                 || program_header.p_memsz >= ebpf::MM_REGION_SIZE
             {
-                return Err(ElfParserError::InvalidProgramHeader);
+                return Err(ElfParserError::InvalidProgramHeader { index });
             }
         }
 
@@ -525,7 +1064,10 @@ impl<C: ContextObject> Executable<C> {
                 .then(|| {
                     section_header_table
                         .get(file_header.e_shstrndx as usize)
-                        .ok_or(ElfParserError::OutOfBounds)
+                        .ok_or(ElfParserError::OutOfBounds {
+                            offset: file_header.e_shstrndx as usize,
+                            len: 1,
+                        })
                 })
                 .transpose()?
                 .ok_or(ElfParserError::NoSectionNameStringTable)?;
@@ -556,7 +1098,10 @@ impl<C: ContextObject> Executable<C> {
                 continue;
             }
             if symbol.st_value != expected_symbol_address {
-                return Err(ElfParserError::OutOfBounds);
+                return Err(ElfParserError::OutOfBounds {
+                    offset: symbol.st_value as usize,
+                    len: symbol.st_size as usize,
+                });
             }
             if symbol.st_size == 0 || symbol.st_size.checked_rem(ebpf::INSN_SIZE as u64) != Some(0)
             {
@@ -568,13 +1113,17 @@ impl<C: ContextObject> Executable<C> {
                     .end
                     .saturating_sub(symbol.st_value)
             {
-                return Err(ElfParserError::OutOfBounds);
+                return Err(ElfParserError::OutOfBounds {
+                    offset: symbol.st_value as usize,
+                    len: symbol.st_size as usize,
+                });
             }
             let target_pc = symbol
                 .st_value
                 .saturating_sub(bytecode_header.p_vaddr)
                 .checked_div(ebpf::INSN_SIZE as u64)
-                .unwrap_or_default() as usize;
+                .map(saturating_usize)
+                .unwrap_or_default();
             let name = if config.enable_symbol_and_section_labels {
                 Elf64::get_string_in_section(
                     elf_bytes,
@@ -593,7 +1142,13 @@ impl<C: ContextObject> Executable<C> {
             expected_symbol_address = symbol.st_value.saturating_add(symbol.st_size);
         }
         if expected_symbol_address != bytecode_header.vm_range().end {
-            return Err(ElfParserError::OutOfBounds);
+            return Err(ElfParserError::OutOfBounds {
+                offset: expected_symbol_address as usize,
+                len: bytecode_header
+                    .vm_range()
+                    .end
+                    .saturating_sub(expected_symbol_address) as usize,
+            });
         }
         if !bytecode_header.vm_range().contains(&file_header.e_entry)
             || file_header.e_entry.checked_rem(ebpf::INSN_SIZE as u64) != Some(0)
@@ -604,7 +1159,8 @@ impl<C: ContextObject> Executable<C> {
             .e_entry
             .saturating_sub(bytecode_header.p_vaddr)
             .checked_div(ebpf::INSN_SIZE as u64)
-            .unwrap_or_default() as usize;
+            .map(saturating_usize)
+            .unwrap_or_default();
         if function_registry.lookup_by_key(entry_pc as u32).is_none() {
             return Err(ElfParserError::InvalidFileHeader);
         }
@@ -612,9 +1168,10 @@ impl<C: ContextObject> Executable<C> {
         let text_section_vaddr = bytecode_header.p_vaddr;
         let text_section_range = bytecode_header.file_range().unwrap_or_default();
         let ro_section = Section::Borrowed(
-            rodata_header.p_vaddr as usize,
+            rodata_header.p_vaddr,
             rodata_header.file_range().unwrap_or_default(),
         );
+        let function_ranges = function_ranges(&function_registry, text_section_range.len());
         Ok(Self {
             original_elf_bytes: None,
             elf_bytes: aligned_memory,
@@ -623,8 +1180,13 @@ impl<C: ContextObject> Executable<C> {
             text_section_vaddr,
             text_section_range,
             entry_pc,
+            function_ranges,
             function_registry,
+            // Nothing to record, the strict parser does not relocate
+            relocation_patches: loader.get_config().record_relocation_patches.then(Vec::new),
+            skipped_relocations: Vec::new(),
             loader,
+            predecoded_instructions: None,
             #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
             compiled_program: None,
         })
@@ -634,19 +1196,26 @@ impl<C: ContextObject> Executable<C> {
     fn load_with_lenient_parser(
         bytes: &[u8],
         loader: Arc<BuiltinProgram<C>>,
+        options: ElfParserOptions,
     ) -> Result<Self, ElfError> {
         // We always need one memory copy to take ownership and for relocations
-        let aligned_memory = AlignedMemory::<{ HOST_ALIGN }>::from_slice(bytes);
-        let (mut elf_bytes, unrelocated_elf_bytes) =
-            if is_memory_aligned(bytes.as_ptr() as usize, HOST_ALIGN) {
-                (aligned_memory, bytes)
-            } else {
-                // We might need another memory copy to ensure alignment
-                (aligned_memory.clone(), aligned_memory.as_slice())
-            };
-        let elf = Elf64::parse(unrelocated_elf_bytes)?;
-
+        #[allow(unused_mut)]
+        let mut aligned_memory = AlignedMemory::<{ HOST_ALIGN }>::from_slice(bytes);
+        let (mut elf_bytes, unrelocated_elf_bytes) = if cfg!(target_endian = "little")
+            && is_memory_aligned(bytes.as_ptr() as usize, HOST_ALIGN)
+        {
+            (aligned_memory, bytes)
+        } else {
+            // We might need another memory copy to ensure alignment, or to parse in the native
+            // byte order. Relocations are applied to the little endian copy.
+            let elf_bytes = aligned_memory.clone();
+            #[cfg(target_endian = "big")]
+            swap_byte_order(aligned_memory.as_slice_mut());
+            (elf_bytes, aligned_memory.as_slice())
+        };
         let config = loader.get_config();
+        let elf = Elf64::parse_with_options(unrelocated_elf_bytes, options)?;
+
         let header = elf.file_header();
         let sbpf_version = if header.e_flags == EF_SBPF_V2 {
             SBPFVersion::Reserved
@@ -679,7 +1248,7 @@ impl<C: ContextObject> Executable<C> {
 
         // relocate symbols
         let mut function_registry = FunctionRegistry::default();
-        Self::relocate(
+        let (relocation_patches, skipped_relocations) = Self::relocate(
             &mut function_registry,
             &loader,
             &elf,
@@ -691,7 +1260,8 @@ impl<C: ContextObject> Executable<C> {
         if offset.checked_rem(ebpf::INSN_SIZE as u64) != Some(0) {
             return Err(ElfError::InvalidEntrypoint);
         }
-        let entry_pc = if let Some(entry_pc) = (offset as usize).checked_div(ebpf::INSN_SIZE) {
+        let entry_pc = if let Some(entry_pc) = saturating_usize(offset).checked_div(ebpf::INSN_SIZE)
+        {
             if !sbpf_version.static_syscalls() {
                 function_registry.unregister_function(ebpf::hash_symbol_name(b"entrypoint"));
             }
@@ -714,6 +1284,8 @@ impl<C: ContextObject> Executable<C> {
                 .map(|s| (elf.section_name(s.sh_name).ok(), s)),
             elf_bytes.as_slice(),
         )?;
+        let text_section_range = text_section.file_range().unwrap_or_default();
+        let function_ranges = function_ranges(&function_registry, text_section_range.len());
 
         Ok(Self {
             original_elf_bytes: None,
@@ -721,10 +1293,14 @@ impl<C: ContextObject> Executable<C> {
             sbpf_version,
             ro_section,
             text_section_vaddr,
-            text_section_range: text_section.file_range().unwrap_or_default(),
+            text_section_range,
             entry_pc,
+            function_ranges,
             function_registry,
+            relocation_patches,
+            skipped_relocations,
             loader,
+            predecoded_instructions: None,
             #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
             compiled_program: None,
         })
@@ -744,7 +1320,10 @@ impl<C: ContextObject> Executable<C> {
                 Section::Borrowed(_, _) => 0,
             })
             // bpf functions
-            .saturating_add(self.function_registry.mem_size());
+            .saturating_add(self.function_registry.mem_size())
+            .saturating_add(self.function_ranges.capacity().saturating_mul(mem::size_of::<(u32, Range<usize>)>()))
+            // predecoded instructions
+            .saturating_add(self.predecoded_instructions.as_ref().map_or(0, |insns| insns.capacity().saturating_mul(mem::size_of::<ebpf::Insn>())));
 
         #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
         {
@@ -775,6 +1354,9 @@ impl<C: ContextObject> Executable<C> {
         if header.e_type != ET_DYN {
             return Err(ElfError::WrongType);
         }
+        if matches!(elf.gnu_stack_flags(), Some(p_flags) if p_flags & PF_X != 0) {
+            return Err(ElfError::ExecutableStack);
+        }
 
         let sbpf_version = if header.e_flags == EF_SBPF_V2 {
             SBPFVersion::Reserved
@@ -832,11 +1414,12 @@ impl<C: ContextObject> Executable<C> {
         }
 
         for section_header in elf.section_header_table().iter() {
-            let start = section_header.sh_offset as usize;
+            let start = saturating_usize(section_header.sh_offset);
             let end = section_header
                 .sh_offset
                 .checked_add(section_header.sh_size)
-                .ok_or(ElfError::ValueOutOfBounds)? as usize;
+                .map(saturating_usize)
+                .ok_or(ElfError::ValueOutOfBounds)?;
             let _ = elf_bytes
                 .get(start..end)
                 .ok_or(ElfError::ValueOutOfBounds)?;
@@ -857,11 +1440,11 @@ impl<C: ContextObject> Executable<C> {
         elf_bytes: &[u8],
     ) -> Result<Section, ElfError> {
         // the lowest section address
-        let mut lowest_addr = usize::MAX;
+        let mut lowest_addr = u64::MAX;
         // the highest section address
         let mut highest_addr = 0;
         // the aggregated section length, not including gaps between sections
-        let mut ro_fill_length = 0usize;
+        let mut ro_fill_length = 0u64;
         let mut invalid_offsets = false;
         // when sbpf_version.enable_elf_vaddr()=true, we allow section_addr != sh_offset
         // if section_addr - sh_offset is constant across all sections. That is,
@@ -941,10 +1524,9 @@ impl<C: ContextObject> Executable<C> {
                 .get(section_header.file_range().unwrap_or_default())
                 .ok_or(ElfError::ValueOutOfBounds)?;
 
-            let section_addr = section_addr as usize;
             lowest_addr = lowest_addr.min(section_addr);
-            highest_addr = highest_addr.max(section_addr.saturating_add(section_data.len()));
-            ro_fill_length = ro_fill_length.saturating_add(section_data.len());
+            highest_addr = highest_addr.max(section_addr.saturating_add(section_data.len() as u64));
+            ro_fill_length = ro_fill_length.saturating_add(section_data.len() as u64);
 
             ro_slices.push((section_addr, section_data));
         }
@@ -969,11 +1551,13 @@ impl<C: ContextObject> Executable<C> {
             // corresponding buffer offsets can be translated by a constant
             // amount. Subtract the constant to get buffer positions.
             let buf_offset_start =
-                lowest_addr.saturating_sub(addr_file_offset.unwrap_or(0) as usize);
+                usize::try_from(lowest_addr.saturating_sub(addr_file_offset.unwrap_or(0)))
+                    .map_err(|_| ElfError::ValueOutOfBounds)?;
             let buf_offset_end =
-                highest_addr.saturating_sub(addr_file_offset.unwrap_or(0) as usize);
+                usize::try_from(highest_addr.saturating_sub(addr_file_offset.unwrap_or(0)))
+                    .map_err(|_| ElfError::ValueOutOfBounds)?;
 
-            let addr_offset = if lowest_addr >= ebpf::MM_RODATA_START as usize {
+            let addr_offset = if lowest_addr >= ebpf::MM_RODATA_START {
                 // The first field of Section::Borrowed is an offset from
                 // ebpf::MM_RODATA_START so if the linker has already put the
                 // sections within ebpf::MM_RODATA_START, we need to subtract
@@ -983,7 +1567,7 @@ impl<C: ContextObject> Executable<C> {
                 if sbpf_version.enable_elf_vaddr() {
                     return Err(ElfError::ValueOutOfBounds);
                 }
-                lowest_addr.saturating_add(ebpf::MM_RODATA_START as usize)
+                lowest_addr.saturating_add(ebpf::MM_RODATA_START)
             };
 
             Section::Borrowed(addr_offset, buf_offset_start..buf_offset_end)
@@ -1004,22 +1588,23 @@ impl<C: ContextObject> Executable<C> {
                 lowest_addr = 0;
             };
 
-            let buf_len = highest_addr;
-            if buf_len > elf_bytes.len() {
-                return Err(ElfError::ValueOutOfBounds);
-            }
+            let buf_len = usize::try_from(highest_addr)
+                .ok()
+                .filter(|buf_len| *buf_len <= elf_bytes.len())
+                .ok_or(ElfError::ValueOutOfBounds)?;
 
             let mut ro_section = vec![0; buf_len];
             for (section_addr, slice) in ro_slices.iter() {
-                let buf_offset_start = section_addr.saturating_sub(lowest_addr);
+                // Less than buf_len, which fits the address space
+                let buf_offset_start = section_addr.saturating_sub(lowest_addr) as usize;
                 ro_section[buf_offset_start..buf_offset_start.saturating_add(slice.len())]
                     .copy_from_slice(slice);
             }
 
-            let addr_offset = if lowest_addr >= ebpf::MM_RODATA_START as usize {
+            let addr_offset = if lowest_addr >= ebpf::MM_RODATA_START {
                 lowest_addr
             } else {
-                lowest_addr.saturating_add(ebpf::MM_RODATA_START as usize)
+                lowest_addr.saturating_add(ebpf::MM_RODATA_START)
             };
             Section::Owned(addr_offset, ro_section)
         };
@@ -1028,13 +1613,21 @@ impl<C: ContextObject> Executable<C> {
     }
 
     /// Relocates the ELF in-place
+    ///
+    /// Returns the recorded patches and the relocations of unknown types which were skipped.
     fn relocate(
         function_registry: &mut FunctionRegistry<usize>,
         loader: &BuiltinProgram<C>,
         elf: &Elf64,
         elf_bytes: &mut [u8],
-    ) -> Result<(), ElfError> {
-        let mut syscall_cache = BTreeMap::new();
+    ) -> Result<(Option<Vec<RelocationPatch>>, Vec<SkippedRelocation>), ElfError> {
+        // Call relocations against the same symbol resolve to the same key, so remember the key
+        // per symbol index instead of re-reading, re-hashing and re-registering the target.
+        let mut call_key_cache: HashMap<Elf64Word, u32> = HashMap::with_capacity(
+            elf.dynamic_symbol_table()
+                .map(|table| table.len())
+                .unwrap_or_default(),
+        );
         let text_section = get_section(elf, b".text")?;
         let sbpf_version = if elf.file_header().e_flags == EF_SBPF_V2 {
             SBPFVersion::Reserved
@@ -1044,8 +1637,10 @@ impl<C: ContextObject> Executable<C> {
 
         // Fixup all program counter relative call instructions
         let config = loader.get_config();
+        let mut patches = config.record_relocation_patches.then(Vec::new);
+        let text_range = text_section.file_range().unwrap_or_default();
         let text_bytes = elf_bytes
-            .get_mut(text_section.file_range().unwrap_or_default())
+            .get_mut(text_range.clone())
             .ok_or(ElfError::ValueOutOfBounds)?;
         let instruction_count = text_bytes
             .len()
@@ -1073,38 +1668,44 @@ impl<C: ContextObject> Executable<C> {
                 )?;
                 if !sbpf_version.static_syscalls() {
                     let offset = i.saturating_mul(ebpf::INSN_SIZE).saturating_add(4);
-                    let checked_slice = text_bytes
-                        .get_mut(offset..offset.saturating_add(4))
-                        .ok_or(ElfError::ValueOutOfBounds)?;
-                    LittleEndian::write_u32(checked_slice, key);
+                    write_patch(
+                        &mut patches,
+                        text_bytes,
+                        text_range.start,
+                        offset,
+                        &key.to_le_bytes(),
+                        || (None, None),
+                    )?;
                 }
             }
         }
 
         let mut program_header: Option<&Elf64Phdr> = None;
+        let mut skipped_relocations = Vec::new();
 
         // Fixup all the relocations in the relocation section if exists
-        for relocation in elf.dynamic_relocations_table().unwrap_or_default().iter() {
-            let mut r_offset = relocation.r_offset as usize;
+        for (index, relocation) in elf.dynamic_relocations().enumerate() {
+            let mut r_offset = relocation.r_offset();
 
             // When sbpf_version.enable_elf_vaddr()=true, we allow section.sh_addr !=
             // section.sh_offset so we need to bring r_offset to the correct
             // byte offset.
             if sbpf_version.enable_elf_vaddr() {
                 match program_header {
-                    Some(header) if header.vm_range().contains(&(r_offset as u64)) => {}
+                    Some(header) if header.vm_range().contains(&r_offset) => {}
                     _ => {
                         program_header = elf
                             .program_header_table()
                             .iter()
-                            .find(|header| header.vm_range().contains(&(r_offset as u64)))
+                            .find(|header| header.vm_range().contains(&r_offset))
                     }
                 }
                 let header = program_header.as_ref().ok_or(ElfError::ValueOutOfBounds)?;
                 r_offset = r_offset
-                    .saturating_sub(header.p_vaddr as usize)
-                    .saturating_add(header.p_offset as usize);
+                    .saturating_sub(header.p_vaddr)
+                    .saturating_add(header.p_offset);
             }
+            let r_offset = saturating_usize(r_offset);
 
             match BpfRelocationType::from_x86_relocation_type(relocation.r_type()) {
                 Some(BpfRelocationType::R_Bpf_64_64) => {
@@ -1120,21 +1721,30 @@ impl<C: ContextObject> Executable<C> {
                         r_offset
                     };
 
-                    // Read the instruction's immediate field which contains virtual
-                    // address to convert to physical
-                    let checked_slice = elf_bytes
-                        .get(imm_offset..imm_offset.saturating_add(BYTE_LENGTH_IMMEDIATE))
-                        .ok_or(ElfError::ValueOutOfBounds)?;
-                    let refd_addr = LittleEndian::read_u32(checked_slice) as u64;
-
-                    let symbol = elf
-                        .dynamic_symbol_table()
-                        .and_then(|table| table.get(relocation.r_sym() as usize).cloned())
-                        .ok_or_else(|| ElfError::UnknownSymbol(relocation.r_sym() as usize))?;
+                    let resolved = elf.resolve_relocation(relocation)?;
+                    let symbol = resolved
+                        .symbol
+                        .ok_or(ElfError::UnknownSymbol(resolved.r_sym as usize))?;
+                    let source = || {
+                        (
+                            Some(resolved.r_type),
+                            resolved.symbol_name.map(<[u8]>::to_vec),
+                        )
+                    };
 
                     // The relocated address is relative to the address of the
                     // symbol at index `r_sym`
-                    let mut addr = symbol.st_value.saturating_add(refd_addr);
+                    let mut addr = if let Some(addend) = relocation.r_addend() {
+                        resolve_addend(elf, symbol.st_value, addend)?
+                    } else {
+                        // Read the instruction's immediate field which contains virtual
+                        // address to convert to physical
+                        let checked_slice = elf_bytes
+                            .get(imm_offset..imm_offset.saturating_add(BYTE_LENGTH_IMMEDIATE))
+                            .ok_or(ElfError::ValueOutOfBounds)?;
+                        let refd_addr = LittleEndian::read_u32(checked_slice) as u64;
+                        symbol.st_value.saturating_add(refd_addr)
+                    };
 
                     // The "physical address" from the VM's perspective is rooted
                     // at `MM_RODATA_START`. If the linker hasn't already put
@@ -1154,30 +1764,33 @@ impl<C: ContextObject> Executable<C> {
                         let imm_high_offset = imm_low_offset.saturating_add(INSN_SIZE);
 
                         // Write the low side of the relocate address
-                        let imm_slice = elf_bytes
-                            .get_mut(
-                                imm_low_offset
-                                    ..imm_low_offset.saturating_add(BYTE_LENGTH_IMMEDIATE),
-                            )
-                            .ok_or(ElfError::ValueOutOfBounds)?;
-                        LittleEndian::write_u32(imm_slice, (addr & 0xFFFFFFFF) as u32);
+                        write_patch(
+                            &mut patches,
+                            elf_bytes,
+                            0,
+                            imm_low_offset,
+                            &((addr & 0xFFFFFFFF) as u32).to_le_bytes(),
+                            source,
+                        )?;
 
                         // Write the high side of the relocate address
-                        let imm_slice = elf_bytes
-                            .get_mut(
-                                imm_high_offset
-                                    ..imm_high_offset.saturating_add(BYTE_LENGTH_IMMEDIATE),
-                            )
-                            .ok_or(ElfError::ValueOutOfBounds)?;
-                        LittleEndian::write_u32(
-                            imm_slice,
-                            addr.checked_shr(32).unwrap_or_default() as u32,
-                        );
+                        write_patch(
+                            &mut patches,
+                            elf_bytes,
+                            0,
+                            imm_high_offset,
+                            &(addr.checked_shr(32).unwrap_or_default() as u32).to_le_bytes(),
+                            source,
+                        )?;
                     } else {
-                        let imm_slice = elf_bytes
-                            .get_mut(imm_offset..imm_offset.saturating_add(8))
-                            .ok_or(ElfError::ValueOutOfBounds)?;
-                        LittleEndian::write_u64(imm_slice, addr);
+                        write_patch(
+                            &mut patches,
+                            elf_bytes,
+                            0,
+                            imm_offset,
+                            &addr.to_le_bytes(),
+                            source,
+                        )?;
                     }
                 }
                 Some(BpfRelocationType::R_Bpf_64_Relative) => {
@@ -1187,6 +1800,7 @@ impl<C: ContextObject> Executable<C> {
 
                     // Offset of the immediate field
                     let imm_offset = r_offset.saturating_add(BYTE_OFFSET_IMMEDIATE);
+                    let source = || (Some(relocation.r_type()), None);
 
                     if text_section
                         .file_range()
@@ -1202,26 +1816,30 @@ impl<C: ContextObject> Executable<C> {
                             .saturating_add(INSN_SIZE)
                             .saturating_add(BYTE_OFFSET_IMMEDIATE);
 
-                        // Read the low side of the address
-                        let imm_slice = elf_bytes
-                            .get(
-                                imm_low_offset
-                                    ..imm_low_offset.saturating_add(BYTE_LENGTH_IMMEDIATE),
-                            )
-                            .ok_or(ElfError::ValueOutOfBounds)?;
-                        let va_low = LittleEndian::read_u32(imm_slice) as u64;
-
-                        // Read the high side of the address
-                        let imm_slice = elf_bytes
-                            .get(
-                                imm_high_offset
-                                    ..imm_high_offset.saturating_add(BYTE_LENGTH_IMMEDIATE),
-                            )
-                            .ok_or(ElfError::ValueOutOfBounds)?;
-                        let va_high = LittleEndian::read_u32(imm_slice) as u64;
+                        let mut refd_addr = if let Some(addend) = relocation.r_addend() {
+                            resolve_addend(elf, 0, addend)?
+                        } else {
+                            // Read the low side of the address
+                            let imm_slice = elf_bytes
+                                .get(
+                                    imm_low_offset
+                                        ..imm_low_offset.saturating_add(BYTE_LENGTH_IMMEDIATE),
+                                )
+                                .ok_or(ElfError::ValueOutOfBounds)?;
+                            let va_low = LittleEndian::read_u32(imm_slice) as u64;
+
+                            // Read the high side of the address
+                            let imm_slice = elf_bytes
+                                .get(
+                                    imm_high_offset
+                                        ..imm_high_offset.saturating_add(BYTE_LENGTH_IMMEDIATE),
+                                )
+                                .ok_or(ElfError::ValueOutOfBounds)?;
+                            let va_high = LittleEndian::read_u32(imm_slice) as u64;
 
-                        // Put the address back together
-                        let mut refd_addr = va_high.checked_shl(32).unwrap_or_default() | va_low;
+                            // Put the address back together
+                            va_high.checked_shl(32).unwrap_or_default() | va_low
+                        };
 
                         if refd_addr == 0 {
                             return Err(ElfError::InvalidVirtualAddress(refd_addr));
@@ -1234,27 +1852,36 @@ impl<C: ContextObject> Executable<C> {
                         }
 
                         // Write back the low half
-                        let imm_slice = elf_bytes
-                            .get_mut(
-                                imm_low_offset
-                                    ..imm_low_offset.saturating_add(BYTE_LENGTH_IMMEDIATE),
-                            )
-                            .ok_or(ElfError::ValueOutOfBounds)?;
-                        LittleEndian::write_u32(imm_slice, (refd_addr & 0xFFFFFFFF) as u32);
+                        write_patch(
+                            &mut patches,
+                            elf_bytes,
+                            0,
+                            imm_low_offset,
+                            &((refd_addr & 0xFFFFFFFF) as u32).to_le_bytes(),
+                            source,
+                        )?;
 
                         // Write back the high half
-                        let imm_slice = elf_bytes
-                            .get_mut(
-                                imm_high_offset
-                                    ..imm_high_offset.saturating_add(BYTE_LENGTH_IMMEDIATE),
-                            )
-                            .ok_or(ElfError::ValueOutOfBounds)?;
-                        LittleEndian::write_u32(
-                            imm_slice,
-                            refd_addr.checked_shr(32).unwrap_or_default() as u32,
-                        );
+                        write_patch(
+                            &mut patches,
+                            elf_bytes,
+                            0,
+                            imm_high_offset,
+                            &(refd_addr.checked_shr(32).unwrap_or_default() as u32).to_le_bytes(),
+                            source,
+                        )?;
                     } else {
-                        let refd_addr = if sbpf_version != SBPFVersion::V0 {
+                        let refd_addr = if let Some(addend) = relocation.r_addend() {
+                            // The address is given explicitly, so neither the
+                            // encoding at the relocation site nor the legacy
+                            // toolchain bug below matter.
+                            let refd_addr = resolve_addend(elf, 0, addend)?;
+                            if refd_addr < ebpf::MM_RODATA_START {
+                                ebpf::MM_RODATA_START.saturating_add(refd_addr)
+                            } else {
+                                refd_addr
+                            }
+                        } else if sbpf_version != SBPFVersion::V0 {
                             // We're relocating an address inside a data section (eg .rodata). The
                             // address is encoded as a simple u64.
 
@@ -1280,10 +1907,14 @@ impl<C: ContextObject> Executable<C> {
                             ebpf::MM_RODATA_START.saturating_add(refd_addr)
                         };
 
-                        let addr_slice = elf_bytes
-                            .get_mut(r_offset..r_offset.saturating_add(mem::size_of::<u64>()))
-                            .ok_or(ElfError::ValueOutOfBounds)?;
-                        LittleEndian::write_u64(addr_slice, refd_addr);
+                        write_patch(
+                            &mut patches,
+                            elf_bytes,
+                            0,
+                            r_offset,
+                            &refd_addr.to_le_bytes(),
+                            source,
+                        )?;
                     }
                 }
                 Some(BpfRelocationType::R_Bpf_64_32) => {
@@ -1293,25 +1924,45 @@ impl<C: ContextObject> Executable<C> {
 
                     // Offset of the immediate field
                     let imm_offset = r_offset.saturating_add(BYTE_OFFSET_IMMEDIATE);
+                    let source = || {
+                        (
+                            Some(relocation.r_type()),
+                            elf.resolve_relocation(relocation)
+                                .ok()
+                                .and_then(|resolved| resolved.symbol_name)
+                                .map(<[u8]>::to_vec),
+                        )
+                    };
 
-                    let symbol = elf
-                        .dynamic_symbol_table()
-                        .and_then(|table| table.get(relocation.r_sym() as usize).cloned())
-                        .ok_or_else(|| ElfError::UnknownSymbol(relocation.r_sym() as usize))?;
+                    if let Some(key) = call_key_cache.get(&relocation.r_sym()) {
+                        write_patch(
+                            &mut patches,
+                            elf_bytes,
+                            0,
+                            imm_offset,
+                            &key.to_le_bytes(),
+                            source,
+                        )?;
+                        continue;
+                    }
 
-                    let name = elf
-                        .dynamic_symbol_name(symbol.st_name as Elf64Word)
-                        .map_err(|_| ElfError::UnknownSymbol(symbol.st_name as usize))?;
+                    let resolved = elf.resolve_relocation(relocation)?;
+                    let symbol = resolved
+                        .symbol
+                        .ok_or(ElfError::UnknownSymbol(resolved.r_sym as usize))?;
+                    let name = resolved
+                        .symbol_name
+                        .ok_or(ElfError::UnknownSymbol(symbol.st_name as usize))?;
 
                     // If the symbol is defined, this is a bpf-to-bpf call
                     let key = if symbol.is_function() && symbol.st_value != 0 {
                         if !text_section.vm_range().contains(&symbol.st_value) {
                             return Err(ElfError::ValueOutOfBounds);
                         }
-                        let target_pc = (symbol.st_value.saturating_sub(text_section.sh_addr)
-                            as usize)
-                            .checked_div(ebpf::INSN_SIZE)
-                            .unwrap_or_default();
+                        let target_pc =
+                            saturating_usize(symbol.st_value.saturating_sub(text_section.sh_addr))
+                                .checked_div(ebpf::INSN_SIZE)
+                                .unwrap_or_default();
                         function_registry.register_function_hashed_legacy(
                             loader,
                             !sbpf_version.static_syscalls(),
@@ -1320,9 +1971,7 @@ impl<C: ContextObject> Executable<C> {
                         )?
                     } else {
                         // Else it's a syscall
-                        let hash = *syscall_cache
-                            .entry(symbol.st_name)
-                            .or_insert_with(|| ebpf::hash_symbol_name(name));
+                        let hash = ebpf::hash_symbol_name(name);
                         if config.reject_broken_elfs
                             && loader.get_function_registry().lookup_by_key(hash).is_none()
                         {
@@ -1334,13 +1983,46 @@ impl<C: ContextObject> Executable<C> {
                         }
                         hash
                     };
-
-                    let checked_slice = elf_bytes
-                        .get_mut(imm_offset..imm_offset.saturating_add(BYTE_LENGTH_IMMEDIATE))
-                        .ok_or(ElfError::ValueOutOfBounds)?;
-                    LittleEndian::write_u32(checked_slice, key);
+                    call_key_cache.insert(relocation.r_sym(), key);
+
+                    write_patch(
+                        &mut patches,
+                        elf_bytes,
+                        0,
+                        imm_offset,
+                        &key.to_le_bytes(),
+                        source,
+                    )?;
+                }
+                _ => {
+                    let symbol_name = elf
+                        .resolve_relocation(relocation)
+                        .ok()
+                        .and_then(|resolved| resolved.symbol_name)
+                        .filter(|name| !name.is_empty());
+                    // Unknown relocations can not be applied partially, so only those which
+                    // leave the instructions untouched can be skipped
+                    if !config.skip_unknown_relocations
+                        || text_section
+                            .file_range()
+                            .unwrap_or_default()
+                            .contains(&r_offset)
+                    {
+                        return Err(ElfError::UnknownRelocation {
+                            index,
+                            r_offset: relocation.r_offset(),
+                            r_type: relocation.r_type(),
+                            symbol_name: symbol_name
+                                .map(|name| String::from_utf8_lossy(name).to_string()),
+                        });
+                    }
+                    skipped_relocations.push(SkippedRelocation {
+                        index,
+                        r_offset: relocation.r_offset(),
+                        r_type: relocation.r_type(),
+                        symbol_name: symbol_name.map(<[u8]>::to_vec),
+                    });
                 }
-                _ => return Err(ElfError::UnknownRelocation(relocation.r_type())),
             }
         }
 
@@ -1353,9 +2035,10 @@ impl<C: ContextObject> Executable<C> {
                 if !text_section.vm_range().contains(&symbol.st_value) {
                     return Err(ElfError::ValueOutOfBounds);
                 }
-                let target_pc = (symbol.st_value.saturating_sub(text_section.sh_addr) as usize)
-                    .checked_div(ebpf::INSN_SIZE)
-                    .unwrap_or_default();
+                let target_pc =
+                    saturating_usize(symbol.st_value.saturating_sub(text_section.sh_addr))
+                        .checked_div(ebpf::INSN_SIZE)
+                        .unwrap_or_default();
                 let name = elf
                     .symbol_name(symbol.st_name as Elf64Word)
                     .map_err(|_| ElfError::UnknownSymbol(symbol.st_name as usize))?;
@@ -1368,7 +2051,7 @@ impl<C: ContextObject> Executable<C> {
             }
         }
 
-        Ok(())
+        Ok((patches, skipped_relocations))
     }
 
     #[allow(dead_code)]
@@ -1386,6 +2069,112 @@ impl<C: ContextObject> Executable<C> {
     }
 }
 
+/// Identifies the output of [Executable::serialize_rodata]
+const SERIALIZED_MAGIC: &[u8; 8] = b"SBPFEXEC";
+
+/// Changes whenever the layout of the output of [Executable::serialize_rodata] changes
+const SERIALIZED_FORMAT_VERSION: u32 = 1;
+
+/// 64 bit FNV-1a, guards serialized executables against accidental corruption and mix-ups
+///
+/// It is not collision resistant, so it does not protect against tampering.
+struct Fnv1aHasher(u64);
+
+impl Default for Fnv1aHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv1aHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hasher = Fnv1aHasher::default();
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+/// Hash of all settings of a [Config], stored by [Executable::serialize_rodata]
+pub fn config_hash(config: &Config) -> u64 {
+    let mut hasher = Fnv1aHasher::default();
+    config.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Appends the length of `bytes` and then `bytes`
+fn put_prefixed(output: &mut Vec<u8>, bytes: &[u8]) {
+    output.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+    output.extend_from_slice(bytes);
+}
+
+/// Reads the fields written by [Executable::serialize_rodata]
+struct SerializedReader<'a>(&'a [u8]);
+
+impl<'a> SerializedReader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], ElfError> {
+        if len > self.0.len() {
+            return Err(ElfError::InvalidSerializedExecutable(
+                "truncated".to_string(),
+            ));
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, ElfError> {
+        self.bytes(mem::size_of::<u32>())
+            .map(LittleEndian::read_u32)
+    }
+
+    fn u64(&mut self) -> Result<u64, ElfError> {
+        self.bytes(mem::size_of::<u64>())
+            .map(LittleEndian::read_u64)
+    }
+
+    /// Reads bytes written by [put_prefixed]
+    fn prefixed(&mut self) -> Result<&'a [u8], ElfError> {
+        let len = self.u64()?;
+        self.bytes(saturating_usize(len))
+    }
+}
+
+/// Bounds each function of the registry by the start of the next one, see
+/// [Executable::function_containing_pc]
+fn function_ranges(
+    function_registry: &FunctionRegistry<usize>,
+    text_len: usize,
+) -> Vec<(u32, Range<usize>)> {
+    let insn_count = text_len / ebpf::INSN_SIZE;
+    let mut starts = function_registry
+        .iter()
+        .map(|(key, (_name, pc))| (pc, key))
+        .filter(|(pc, _key)| *pc < insn_count)
+        .collect::<Vec<_>>();
+    starts.sort_unstable();
+    starts.dedup_by_key(|(pc, _key)| *pc);
+    let ends = starts
+        .iter()
+        .skip(1)
+        .map(|(pc, _key)| *pc)
+        .chain(core::iter::once(insn_count));
+    starts
+        .iter()
+        .zip(ends)
+        .map(|((start, key), end)| (*key, *start..end))
+        .collect()
+}
+
 /// Creates a [MemoryRegion] for the given [Section]
 pub fn get_ro_region(ro_section: &Section, elf: &[u8]) -> MemoryRegion {
     let (offset, ro_data) = match ro_section {
@@ -1396,5 +2185,5 @@ pub fn get_ro_region(ro_section: &Section, elf: &[u8]) -> MemoryRegion {
     // If offset > 0, the region will start at MM_RODATA_START + the offset of
     // the first read only byte. [MM_RODATA_START, MM_RODATA_START + offset)
     // will be unmappable, see MemoryRegion::vm_to_host.
-    MemoryRegion::new_readonly(ro_data, offset as u64)
+    MemoryRegion::new_readonly(ro_data, offset).with_kind(MemoryRegionKind::Rodata)
 }
@@ -59,6 +59,7 @@ pub const SHT_FINI_ARRAY: Elf64Word = 15;
 pub const SHT_PREINIT_ARRAY: Elf64Word = 16;
 pub const SHT_GROUP: Elf64Word = 17;
 pub const SHT_SYMTAB_SHNDX: Elf64Word = 18;
+pub const SHT_GNU_HASH: Elf64Word = 0x6FFFFFF6;
 
 pub const SHF_WRITE: Elf64Xword = 0x1;
 pub const SHF_ALLOC: Elf64Xword = 0x2;
@@ -72,6 +73,12 @@ pub const SHF_GROUP: Elf64Xword = 0x200;
 pub const SHF_TLS: Elf64Xword = 0x400;
 
 pub const SHN_UNDEF: Elf64Half = 0;
+pub const SHN_LORESERVE: Elf64Half = 0xff00;
+pub const SHN_ABS: Elf64Half = 0xfff1;
+pub const SHN_COMMON: Elf64Half = 0xfff2;
+pub const SHN_XINDEX: Elf64Half = 0xffff;
+
+pub const NT_GNU_BUILD_ID: Elf64Word = 3;
 
 pub const DT_NULL: Elf64Xword = 0;
 pub const DT_NEEDED: Elf64Xword = 1;
@@ -109,6 +116,7 @@ pub const DT_PREINIT_ARRAY: Elf64Xword = 32;
 pub const DT_PREINIT_ARRAYSZ: Elf64Xword = 33;
 pub const DT_SYMTAB_SHNDX: Elf64Xword = 34;
 pub const DT_NUM: usize = 35;
+pub const DT_GNU_HASH: Elf64Xword = 0x6FFFFEF5;
 
 pub const STT_NOTYPE: u8 = 0;
 pub const STT_OBJECT: u8 = 1;
@@ -124,6 +132,15 @@ pub const STT_HIOS: u8 = 12;
 pub const STT_LOPROC: u8 = 13;
 pub const STT_HIPROC: u8 = 15;
 
+pub const STB_LOCAL: u8 = 0;
+pub const STB_GLOBAL: u8 = 1;
+pub const STB_WEAK: u8 = 2;
+
+pub const STV_DEFAULT: u8 = 0;
+pub const STV_INTERNAL: u8 = 1;
+pub const STV_HIDDEN: u8 = 2;
+pub const STV_PROTECTED: u8 = 3;
+
 pub const R_X86_64_NONE: u32 = 0;
 pub const R_X86_64_64: u32 = 1;
 pub const R_X86_64_PC32: u32 = 2;
@@ -0,0 +1,414 @@
+//! Line number information of the `.debug_line` section
+//!
+//! Interprets the line number programs of DWARF versions 2 to 5 as emitted by LLVM, mapping
+//! instruction addresses to the source lines they were compiled from.
+
+use alloc::vec::Vec;
+use core::{convert::TryFrom, str};
+
+use super::{reader::Reader, saturating_usize, ElfParserError};
+
+const DW_LNS_COPY: u8 = 0x01;
+const DW_LNS_ADVANCE_PC: u8 = 0x02;
+const DW_LNS_ADVANCE_LINE: u8 = 0x03;
+const DW_LNS_SET_FILE: u8 = 0x04;
+const DW_LNS_SET_COLUMN: u8 = 0x05;
+const DW_LNS_CONST_ADD_PC: u8 = 0x08;
+const DW_LNS_FIXED_ADVANCE_PC: u8 = 0x09;
+
+const DW_LNE_END_SEQUENCE: u8 = 0x01;
+const DW_LNE_SET_ADDRESS: u8 = 0x02;
+
+const DW_LNCT_PATH: u64 = 0x01;
+
+const DW_FORM_BLOCK2: u64 = 0x03;
+const DW_FORM_BLOCK4: u64 = 0x04;
+const DW_FORM_DATA2: u64 = 0x05;
+const DW_FORM_DATA4: u64 = 0x06;
+const DW_FORM_DATA8: u64 = 0x07;
+const DW_FORM_STRING: u64 = 0x08;
+const DW_FORM_BLOCK: u64 = 0x09;
+const DW_FORM_BLOCK1: u64 = 0x0a;
+const DW_FORM_DATA1: u64 = 0x0b;
+const DW_FORM_SDATA: u64 = 0x0d;
+const DW_FORM_STRP: u64 = 0x0e;
+const DW_FORM_UDATA: u64 = 0x0f;
+const DW_FORM_DATA16: u64 = 0x1e;
+const DW_FORM_LINE_STRP: u64 = 0x1f;
+
+/// A row of the line number table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineRow<'a> {
+    /// Address of the instruction
+    pub address: u64,
+    /// Name of the source file, without its directory
+    pub file: &'a str,
+    /// Source line, starting at 1, or 0 if the instruction has no source line
+    pub line: u32,
+    /// Source column, starting at 1, or 0 for the whole line
+    pub column: u32,
+    /// The row only marks the address after the end of a sequence of instructions
+    pub end_sequence: bool,
+}
+
+/// The contents of the `.debug_line` section
+#[derive(Debug, Clone, Copy)]
+pub struct DebugLine<'a> {
+    debug_line: &'a [u8],
+    debug_line_str: &'a [u8],
+}
+
+impl<'a> DebugLine<'a> {
+    /// Wraps the `.debug_line` bytes, along with the `.debug_line_str` bytes DWARF 5 file names
+    /// refer to
+    pub fn new(debug_line: &'a [u8], debug_line_str: &'a [u8]) -> Self {
+        Self {
+            debug_line,
+            debug_line_str,
+        }
+    }
+
+    /// Returns the rows of all line number programs
+    pub fn rows(&self) -> DebugLineRows<'a> {
+        DebugLineRows {
+            debug_line: *self,
+            unit_offset: Some(0),
+            program: None,
+        }
+    }
+
+    /// Returns the file name and line of the instruction at `vaddr`
+    ///
+    /// Returns `None` if no sequence covers `vaddr` or the line number programs are invalid.
+    pub fn line_for_address(&self, vaddr: u64) -> Option<(&'a str, u32)> {
+        let mut previous_row: Option<LineRow> = None;
+        for row in self.rows() {
+            let row = row.ok()?;
+            if let Some(previous_row) = previous_row {
+                if (previous_row.address..row.address).contains(&vaddr) {
+                    return Some((previous_row.file, previous_row.line));
+                }
+            }
+            previous_row = if row.end_sequence { None } else { Some(row) };
+        }
+        None
+    }
+}
+
+/// Iterator over the rows of the line number programs, see [DebugLine::rows]
+///
+/// Stops after the first error.
+#[derive(Debug)]
+pub struct DebugLineRows<'a> {
+    debug_line: DebugLine<'a>,
+    unit_offset: Option<usize>,
+    program: Option<LineProgram<'a>>,
+}
+
+impl<'a> Iterator for DebugLineRows<'a> {
+    type Item = Result<LineRow<'a>, ElfParserError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(program) = self.program.as_mut() {
+                match program.next_row() {
+                    Ok(Some(row)) => return Some(Ok(row)),
+                    Ok(None) => self.program = None,
+                    Err(err) => {
+                        self.program = None;
+                        self.unit_offset = None;
+                        return Some(Err(err));
+                    }
+                }
+            }
+            let unit_offset = self.unit_offset?;
+            if unit_offset >= self.debug_line.debug_line.len() {
+                self.unit_offset = None;
+                return None;
+            }
+            match LineProgram::parse(&self.debug_line, unit_offset) {
+                Ok((program, next_unit_offset)) => {
+                    self.program = Some(program);
+                    self.unit_offset = Some(next_unit_offset);
+                }
+                Err(err) => {
+                    self.unit_offset = None;
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
+/// The state machine registers, see DWARF 5 section 6.2.2
+#[derive(Debug, Clone, Copy)]
+struct Registers {
+    address: u64,
+    file: u64,
+    line: u64,
+    column: u64,
+}
+
+/// The header and the remaining opcodes of a line number program
+#[derive(Debug)]
+struct LineProgram<'a> {
+    opcodes: Reader<'a>,
+    minimum_instruction_length: u8,
+    line_base: i8,
+    line_range: u8,
+    opcode_base: u8,
+    standard_opcode_lengths: &'a [u8],
+    /// File names by index, DWARF 5 counts from 0 and earlier versions from 1
+    files: Vec<&'a str>,
+    first_file_index: u64,
+    registers: Registers,
+}
+
+impl<'a> LineProgram<'a> {
+    /// Parses the header of the unit at `offset`, returns the program and the next unit offset
+    fn parse(debug_line: &DebugLine<'a>, offset: usize) -> Result<(Self, usize), ElfParserError> {
+        let mut reader = Reader {
+            bytes: debug_line.debug_line,
+            position: offset,
+        };
+        let (unit_length, offset_size) = match reader.uint(4)? {
+            0xffff_ffff => (saturating_usize(reader.uint(8)?), 8),
+            unit_length => (unit_length as usize, 4),
+        };
+        let mut unit = Reader::new(reader.bytes(unit_length)?);
+        let next_unit_offset = reader.position;
+
+        let version = unit.uint(2)?;
+        if !(2..=5).contains(&version) {
+            return Err(ElfParserError::InvalidLineNumberProgram);
+        }
+        if version >= 5 {
+            unit.u8()?; // address_size
+            unit.u8()?; // segment_selector_size
+        }
+        let header_length = saturating_usize(unit.uint(offset_size)?);
+        let mut header = Reader::new(unit.bytes(header_length)?);
+        let opcodes = Reader::new(unit.rest()?);
+
+        let minimum_instruction_length = header.u8()?;
+        if version >= 4 && header.u8()? != 1 {
+            // VLIW operation indices are not supported
+            return Err(ElfParserError::InvalidLineNumberProgram);
+        }
+        header.u8()?; // default_is_stmt
+        let line_base = header.u8()? as i8;
+        let line_range = header.u8()?;
+        let opcode_base = header.u8()?;
+        if line_range == 0 || opcode_base == 0 {
+            return Err(ElfParserError::InvalidLineNumberProgram);
+        }
+        let standard_opcode_lengths = header.bytes(opcode_base.saturating_sub(1) as usize)?;
+
+        let mut files = Vec::new();
+        let first_file_index = if version >= 5 {
+            let mut read_entries = |mut paths: Option<&mut Vec<&'a str>>| {
+                let format_count = header.u8()?;
+                let mut formats = Vec::with_capacity(format_count as usize);
+                for _ in 0..format_count {
+                    formats.push((header.uleb128()?, header.uleb128()?));
+                }
+                let count = header.uleb128()?;
+                for _ in 0..count {
+                    for (content_type, form) in formats.iter().copied() {
+                        let value =
+                            read_form(&mut header, form, offset_size, debug_line.debug_line_str)?;
+                        if let (Some(paths), DW_LNCT_PATH) = (paths.as_mut(), content_type) {
+                            paths.push(str_from_utf8(
+                                value.ok_or(ElfParserError::InvalidLineNumberProgram)?,
+                            )?);
+                        }
+                    }
+                }
+                Ok::<(), ElfParserError>(())
+            };
+            read_entries(None)?;
+            read_entries(Some(&mut files))?;
+            0
+        } else {
+            // The include directories are not needed for the file names
+            while !header.cstr()?.is_empty() {}
+            loop {
+                let name = header.cstr()?;
+                if name.is_empty() {
+                    break;
+                }
+                header.uleb128()?; // directory index
+                header.uleb128()?; // modification time
+                header.uleb128()?; // length
+                files.push(str_from_utf8(name)?);
+            }
+            1
+        };
+
+        Ok((
+            Self {
+                opcodes,
+                minimum_instruction_length,
+                line_base,
+                line_range,
+                opcode_base,
+                standard_opcode_lengths,
+                files,
+                first_file_index,
+                registers: Self::initial_registers(),
+            },
+            next_unit_offset,
+        ))
+    }
+
+    fn initial_registers() -> Registers {
+        Registers {
+            address: 0,
+            file: 1,
+            line: 1,
+            column: 0,
+        }
+    }
+
+    fn advance_address(&mut self, operation_advance: u64) {
+        self.registers.address = self
+            .registers
+            .address
+            .wrapping_add(operation_advance.wrapping_mul(self.minimum_instruction_length as u64));
+    }
+
+    fn row(&self, end_sequence: bool) -> Result<LineRow<'a>, ElfParserError> {
+        let file = self
+            .registers
+            .file
+            .checked_sub(self.first_file_index)
+            .and_then(|index| self.files.get(index as usize))
+            .ok_or(ElfParserError::InvalidLineNumberProgram)?;
+        Ok(LineRow {
+            address: self.registers.address,
+            file,
+            line: u32::try_from(self.registers.line)
+                .map_err(|_| ElfParserError::InvalidLineNumberProgram)?,
+            column: u32::try_from(self.registers.column)
+                .map_err(|_| ElfParserError::InvalidLineNumberProgram)?,
+            end_sequence,
+        })
+    }
+
+    /// Runs the opcodes up to the next row, returns `None` at the end of the program
+    fn next_row(&mut self) -> Result<Option<LineRow<'a>>, ElfParserError> {
+        while !self.opcodes.is_empty() {
+            let opcode = self.opcodes.u8()?;
+            if opcode >= self.opcode_base {
+                let adjusted_opcode = opcode.saturating_sub(self.opcode_base);
+                self.advance_address(
+                    adjusted_opcode.checked_div(self.line_range).unwrap_or(0) as u64
+                );
+                let line_advance = (self.line_base as i64).saturating_add(
+                    adjusted_opcode.checked_rem(self.line_range).unwrap_or(0) as i64,
+                );
+                self.registers.line = self.registers.line.wrapping_add(line_advance as u64);
+                return self.row(false).map(Some);
+            }
+            match opcode {
+                0 => {
+                    let length = saturating_usize(self.opcodes.uleb128()?);
+                    let mut arguments = Reader::new(self.opcodes.bytes(length)?);
+                    match arguments.u8()? {
+                        DW_LNE_END_SEQUENCE => {
+                            let row = self.row(true)?;
+                            self.registers = Self::initial_registers();
+                            return Ok(Some(row));
+                        }
+                        DW_LNE_SET_ADDRESS => {
+                            self.registers.address = arguments.uint(length.saturating_sub(1))?;
+                        }
+                        // DW_LNE_define_file, DW_LNE_set_discriminator and vendor extensions
+                        _ => {}
+                    }
+                }
+                DW_LNS_COPY => return self.row(false).map(Some),
+                DW_LNS_ADVANCE_PC => {
+                    let operation_advance = self.opcodes.uleb128()?;
+                    self.advance_address(operation_advance);
+                }
+                DW_LNS_ADVANCE_LINE => {
+                    let line_advance = self.opcodes.sleb128()?;
+                    self.registers.line = self.registers.line.wrapping_add(line_advance as u64);
+                }
+                DW_LNS_SET_FILE => self.registers.file = self.opcodes.uleb128()?,
+                DW_LNS_SET_COLUMN => self.registers.column = self.opcodes.uleb128()?,
+                DW_LNS_CONST_ADD_PC => {
+                    let adjusted_opcode = 255u8.saturating_sub(self.opcode_base);
+                    self.advance_address(
+                        adjusted_opcode.checked_div(self.line_range).unwrap_or(0) as u64
+                    );
+                }
+                DW_LNS_FIXED_ADVANCE_PC => {
+                    self.registers.address =
+                        self.registers.address.wrapping_add(self.opcodes.uint(2)?);
+                }
+                // DW_LNS_negate_stmt, DW_LNS_set_basic_block, DW_LNS_set_prologue_end,
+                // DW_LNS_set_epilogue_begin, DW_LNS_set_isa and unknown standard opcodes only
+                // have ULEB128 arguments which do not affect the rows
+                _ => {
+                    let argument_count = self
+                        .standard_opcode_lengths
+                        .get(opcode.saturating_sub(1) as usize)
+                        .copied()
+                        .unwrap_or(0);
+                    for _ in 0..argument_count {
+                        self.opcodes.uleb128()?;
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn str_from_utf8(bytes: &[u8]) -> Result<&str, ElfParserError> {
+    str::from_utf8(bytes).map_err(|_| ElfParserError::InvalidLineNumberProgram)
+}
+
+/// Reads an attribute of a DWARF 5 entry, returns the bytes of the string forms
+fn read_form<'a>(
+    reader: &mut Reader<'a>,
+    form: u64,
+    offset_size: usize,
+    debug_line_str: &'a [u8],
+) -> Result<Option<&'a [u8]>, ElfParserError> {
+    let len = match form {
+        DW_FORM_STRING => return reader.cstr().map(Some),
+        DW_FORM_LINE_STRP => {
+            let offset = saturating_usize(reader.uint(offset_size)?);
+            let mut strings = Reader {
+                bytes: debug_line_str,
+                position: offset,
+            };
+            return strings.cstr().map(Some);
+        }
+        // Offset into `.debug_str`, which file names do not use
+        DW_FORM_STRP => offset_size,
+        DW_FORM_DATA1 => 1,
+        DW_FORM_DATA2 => 2,
+        DW_FORM_DATA4 => 4,
+        DW_FORM_DATA8 => 8,
+        DW_FORM_DATA16 => 16,
+        DW_FORM_UDATA => {
+            reader.uleb128()?;
+            0
+        }
+        DW_FORM_SDATA => {
+            reader.sleb128()?;
+            0
+        }
+        DW_FORM_BLOCK1 => reader.uint(1)? as usize,
+        DW_FORM_BLOCK2 => reader.uint(2)? as usize,
+        DW_FORM_BLOCK4 => reader.uint(4)? as usize,
+        DW_FORM_BLOCK => saturating_usize(reader.uleb128()?),
+        _ => return Err(ElfParserError::InvalidLineNumberProgram),
+    };
+    reader.bytes(len)?;
+    Ok(None)
+}
@@ -0,0 +1,329 @@
+//! Call frame information of the `.eh_frame` section
+//!
+//! Only the subset of the DWARF call frame instructions needed to find the CFA rule at the
+//! entry of each function is supported. The `.eh_frame_hdr` section is a search table over
+//! the same records, so it is not needed to enumerate them.
+
+use core::ops::Range;
+
+use super::{checked_range, reader::Reader, saturating_usize, types::Elf64Addr, ElfParserError};
+
+const DW_EH_PE_ABSPTR: u8 = 0x00;
+const DW_EH_PE_ULEB128: u8 = 0x01;
+const DW_EH_PE_UDATA2: u8 = 0x02;
+const DW_EH_PE_UDATA4: u8 = 0x03;
+const DW_EH_PE_UDATA8: u8 = 0x04;
+const DW_EH_PE_SLEB128: u8 = 0x09;
+const DW_EH_PE_SDATA2: u8 = 0x0a;
+const DW_EH_PE_SDATA4: u8 = 0x0b;
+const DW_EH_PE_SDATA8: u8 = 0x0c;
+const DW_EH_PE_PCREL: u8 = 0x10;
+
+const DW_CFA_NOP: u8 = 0x00;
+const DW_CFA_ADVANCE_LOC1: u8 = 0x02;
+const DW_CFA_ADVANCE_LOC2: u8 = 0x03;
+const DW_CFA_ADVANCE_LOC4: u8 = 0x04;
+const DW_CFA_OFFSET_EXTENDED: u8 = 0x05;
+const DW_CFA_DEF_CFA: u8 = 0x0c;
+const DW_CFA_DEF_CFA_REGISTER: u8 = 0x0d;
+const DW_CFA_DEF_CFA_OFFSET: u8 = 0x0e;
+const DW_CFA_ADVANCE_LOC: u8 = 0x40;
+const DW_CFA_OFFSET: u8 = 0x80;
+
+/// How to compute the canonical frame address (CFA)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CfaRule {
+    /// DWARF number of the register the CFA is relative to
+    pub register: u64,
+    /// Offset added to the register
+    pub offset: u64,
+}
+
+impl Reader<'_> {
+    /// Reads a pointer in a DW_EH_PE_* `encoding`, `vaddr` being the address of the bytes
+    fn pointer(&mut self, encoding: u8, vaddr: Elf64Addr) -> Result<u64, ElfParserError> {
+        let field_vaddr = vaddr.wrapping_add(self.position as u64);
+        let value = match encoding & 0x0f {
+            DW_EH_PE_ABSPTR | DW_EH_PE_UDATA8 => self.uint(8)?,
+            DW_EH_PE_ULEB128 => self.uleb128()?,
+            DW_EH_PE_UDATA2 => self.uint(2)?,
+            DW_EH_PE_UDATA4 => self.uint(4)?,
+            DW_EH_PE_SLEB128 => self.sleb128()? as u64,
+            DW_EH_PE_SDATA2 => self.sint(2)? as u64,
+            DW_EH_PE_SDATA4 => self.sint(4)? as u64,
+            DW_EH_PE_SDATA8 => self.sint(8)? as u64,
+            _ => return Err(ElfParserError::InvalidCallFrameInformation),
+        };
+        match encoding & 0xf0 {
+            0 => Ok(value),
+            DW_EH_PE_PCREL => Ok(field_vaddr.wrapping_add(value)),
+            _ => Err(ElfParserError::InvalidCallFrameInformation),
+        }
+    }
+}
+
+/// A CIE or FDE record
+struct Record<'a> {
+    /// Offset of the CIE id or CIE pointer field in the section
+    id_offset: usize,
+    /// CIE id or CIE pointer
+    id: u32,
+    /// Contents following the id field
+    contents: Reader<'a>,
+}
+
+/// Common information entry, shared by the FDEs referring to it
+struct Cie<'a> {
+    code_alignment_factor: u64,
+    fde_pointer_encoding: u8,
+    has_augmentation_data: bool,
+    initial_instructions: &'a [u8],
+}
+
+/// The `.eh_frame` section
+#[derive(Debug, Clone, Copy)]
+pub struct EhFrame<'a> {
+    bytes: &'a [u8],
+    vaddr: Elf64Addr,
+}
+
+impl<'a> EhFrame<'a> {
+    /// Wraps the contents of a `.eh_frame` section loaded at `vaddr`
+    ///
+    /// The address is needed to resolve pc-relative pointers.
+    pub fn new(bytes: &'a [u8], vaddr: Elf64Addr) -> Self {
+        Self { bytes, vaddr }
+    }
+
+    /// Returns the pc range of each FDE along with the CFA rule at its start
+    pub fn entries(&self) -> EhFrameEntries<'a> {
+        EhFrameEntries {
+            eh_frame: *self,
+            offset: Some(0),
+        }
+    }
+
+    /// Returns the record starting at `offset`, or `None` for the zero terminator
+    fn record(&self, offset: usize) -> Result<Option<(Record<'a>, usize)>, ElfParserError> {
+        let mut reader = Reader {
+            bytes: self.bytes,
+            position: offset,
+        };
+        let length = match reader.uint(4)? {
+            0 => return Ok(None),
+            0xffff_ffff => saturating_usize(reader.uint(8)?),
+            length => length as usize,
+        };
+        let id_offset = reader.position;
+        let record_range = checked_range(id_offset, length)?;
+        let record_bytes =
+            self.bytes
+                .get(..record_range.end)
+                .ok_or(ElfParserError::OutOfBounds {
+                    offset: id_offset,
+                    len: length,
+                })?;
+        let mut contents = Reader {
+            bytes: record_bytes,
+            position: id_offset,
+        };
+        let id = contents.uint(4)? as u32;
+        Ok(Some((
+            Record {
+                id_offset,
+                id,
+                contents,
+            },
+            record_range.end,
+        )))
+    }
+
+    fn parse_cie(&self, offset: usize) -> Result<Cie<'a>, ElfParserError> {
+        let mut record = match self.record(offset)? {
+            Some((record, _end)) if record.id == 0 => record,
+            _ => return Err(ElfParserError::InvalidCallFrameInformation),
+        };
+        let reader = &mut record.contents;
+        let version = reader.u8()?;
+        if version != 1 && version != 3 {
+            return Err(ElfParserError::InvalidCallFrameInformation);
+        }
+        let augmentation = reader
+            .cstr()
+            .map_err(|_| ElfParserError::InvalidCallFrameInformation)?;
+        let code_alignment_factor = reader.uleb128()?;
+        let _data_alignment_factor = reader.sleb128()?;
+        let _return_address_register = if version == 1 {
+            reader.u8()? as u64
+        } else {
+            reader.uleb128()?
+        };
+
+        let mut cie = Cie {
+            code_alignment_factor,
+            fde_pointer_encoding: DW_EH_PE_ABSPTR,
+            has_augmentation_data: augmentation.first() == Some(&b'z'),
+            initial_instructions: &[],
+        };
+        if cie.has_augmentation_data {
+            let augmentation_data_length = saturating_usize(reader.uleb128()?);
+            let augmentation_data_end =
+                checked_range(reader.position, augmentation_data_length)?.end;
+            for augmentation in augmentation.iter().skip(1) {
+                match augmentation {
+                    b'R' => cie.fde_pointer_encoding = reader.u8()?,
+                    b'P' => {
+                        let personality_encoding = reader.u8()?;
+                        reader.pointer(personality_encoding, self.vaddr)?;
+                    }
+                    b'L' => {
+                        reader.u8()?;
+                    }
+                    b'S' => {}
+                    _ => return Err(ElfParserError::InvalidCallFrameInformation),
+                }
+            }
+            if reader.position > augmentation_data_end {
+                return Err(ElfParserError::InvalidCallFrameInformation);
+            }
+            reader.position = augmentation_data_end;
+        } else if !augmentation.is_empty() {
+            return Err(ElfParserError::InvalidCallFrameInformation);
+        }
+        cie.initial_instructions = reader.rest()?;
+        Ok(cie)
+    }
+
+    fn parse_fde(&self, mut record: Record<'a>) -> Result<(Range<u64>, CfaRule), ElfParserError> {
+        let cie_offset = record
+            .id_offset
+            .checked_sub(record.id as usize)
+            .ok_or(ElfParserError::InvalidCallFrameInformation)?;
+        let cie = self.parse_cie(cie_offset)?;
+        let reader = &mut record.contents;
+        let pc_begin = reader.pointer(cie.fde_pointer_encoding, self.vaddr)?;
+        // The range is a length, not an address
+        let pc_range = reader.pointer(cie.fde_pointer_encoding & 0x0f, self.vaddr)?;
+        if cie.has_augmentation_data {
+            let augmentation_data_length = saturating_usize(reader.uleb128()?);
+            reader.bytes(augmentation_data_length)?;
+        }
+        let instructions = reader.rest()?;
+
+        let mut entry_rule = EntryRule::default();
+        entry_rule.execute(cie.initial_instructions, &cie)?;
+        entry_rule.execute(instructions, &cie)?;
+        let cfa = entry_rule
+            .cfa
+            .ok_or(ElfParserError::InvalidCallFrameInformation)?;
+        Ok((pc_begin..pc_begin.saturating_add(pc_range), cfa))
+    }
+}
+
+/// CFA rule at the entry of a function, while executing its instructions
+#[derive(Default)]
+struct EntryRule {
+    cfa: Option<CfaRule>,
+    /// Offset from the entry the instructions advanced to
+    location: u64,
+}
+
+impl EntryRule {
+    /// Applies `instructions`
+    ///
+    /// The instructions after the first advance only apply to later locations, they are decoded
+    /// to check that they are supported but do not change the rule.
+    fn execute(&mut self, instructions: &[u8], cie: &Cie) -> Result<(), ElfParserError> {
+        let mut reader = Reader::new(instructions);
+        while !reader.is_empty() {
+            let opcode = reader.u8()?;
+            let at_entry = self.location == 0;
+            match (opcode & 0xc0, opcode) {
+                (DW_CFA_ADVANCE_LOC, _) => self.advance((opcode & 0x3f) as u64, cie),
+                (DW_CFA_OFFSET, _) => {
+                    reader.uleb128()?;
+                }
+                (0, DW_CFA_NOP) => {}
+                (0, DW_CFA_ADVANCE_LOC1) => self.advance(reader.uint(1)?, cie),
+                (0, DW_CFA_ADVANCE_LOC2) => self.advance(reader.uint(2)?, cie),
+                (0, DW_CFA_ADVANCE_LOC4) => self.advance(reader.uint(4)?, cie),
+                (0, DW_CFA_OFFSET_EXTENDED) => {
+                    reader.uleb128()?;
+                    reader.uleb128()?;
+                }
+                (0, DW_CFA_DEF_CFA) => {
+                    let rule = CfaRule {
+                        register: reader.uleb128()?,
+                        offset: reader.uleb128()?,
+                    };
+                    if at_entry {
+                        self.cfa = Some(rule);
+                    }
+                }
+                (0, DW_CFA_DEF_CFA_REGISTER) => {
+                    let register = reader.uleb128()?;
+                    if at_entry {
+                        self.defined_cfa()?.register = register;
+                    }
+                }
+                (0, DW_CFA_DEF_CFA_OFFSET) => {
+                    let offset = reader.uleb128()?;
+                    if at_entry {
+                        self.defined_cfa()?.offset = offset;
+                    }
+                }
+                _ => return Err(ElfParserError::UnsupportedCallFrameInstruction(opcode)),
+            }
+        }
+        Ok(())
+    }
+
+    fn advance(&mut self, delta: u64, cie: &Cie) {
+        self.location = self
+            .location
+            .saturating_add(delta.saturating_mul(cie.code_alignment_factor));
+    }
+
+    fn defined_cfa(&mut self) -> Result<&mut CfaRule, ElfParserError> {
+        self.cfa
+            .as_mut()
+            .ok_or(ElfParserError::InvalidCallFrameInformation)
+    }
+}
+
+/// Iterator over the FDEs of an [EhFrame], see [EhFrame::entries]
+///
+/// CIE records are skipped. Iteration ends at the zero terminator, at the end of the section,
+/// or after the first error.
+#[derive(Debug, Clone)]
+pub struct EhFrameEntries<'a> {
+    eh_frame: EhFrame<'a>,
+    offset: Option<usize>,
+}
+
+impl Iterator for EhFrameEntries<'_> {
+    type Item = Result<(Range<u64>, CfaRule), ElfParserError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let offset = self.offset.take()?;
+            if offset >= self.eh_frame.bytes.len() {
+                return None;
+            }
+            let (record, end) = match self.eh_frame.record(offset) {
+                Ok(Some(record)) => record,
+                Ok(None) => return None,
+                Err(err) => return Some(Err(err)),
+            };
+            if record.id == 0 {
+                self.offset = Some(end);
+                continue;
+            }
+            let entry = self.eh_frame.parse_fde(record);
+            if entry.is_ok() {
+                self.offset = Some(end);
+            }
+            return Some(entry);
+        }
+    }
+}
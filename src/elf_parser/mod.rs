@@ -1,12 +1,27 @@
 //! Dependency-less 64 bit ELF parser
 
 pub mod consts;
+pub mod debug_line;
+pub mod eh_frame;
+pub mod note;
+pub mod owned;
+mod reader;
 pub mod types;
-
-use alloc::string::{String, ToString};
-use core::{fmt, mem, ops::Range, slice};
-
-use crate::{ArithmeticOverflow, ErrCheckedArithmetic};
+pub mod writer;
+
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{convert::TryFrom, fmt, mem, ops::Range, slice};
+
+use crate::{
+    aligned_memory::{is_memory_aligned, AlignedMemory},
+    ebpf::{EF_SBPF_V2, HOST_ALIGN},
+    program::SBPFVersion,
+};
 use {consts::*, types::*};
 
 /// Maximum length of section name allowed.
@@ -20,11 +35,17 @@ pub enum ElfParserError {
     #[error("invalid file header")]
     InvalidFileHeader,
     /// Program header is inconsistent or unsupported
-    #[error("invalid program header")]
-    InvalidProgramHeader,
+    #[error("invalid program header #{index}")]
+    InvalidProgramHeader {
+        /// Index of the program header in the program header table
+        index: usize,
+    },
     /// Section header is inconsistent or unsupported
-    #[error("invalid section header")]
-    InvalidSectionHeader,
+    #[error("invalid section header #{index}")]
+    InvalidSectionHeader {
+        /// Index of the section header in the section header table
+        index: usize,
+    },
     /// Section or symbol name is not UTF8 or too long
     #[error("invalid string")]
     InvalidString,
@@ -32,17 +53,29 @@ pub enum ElfParserError {
     #[error("Section or symbol name `{0}` is longer than `{1}` bytes")]
     StringTooLong(String, usize),
     /// An index or memory range does exceed its boundaries
-    #[error("value out of bounds")]
-    OutOfBounds,
+    ///
+    /// The offset is a file offset, or a virtual address or table index for lookups by those.
+    #[error("value out of bounds: {len} bytes at {offset:#x}")]
+    OutOfBounds {
+        /// Start of the offending range
+        offset: usize,
+        /// Length of the offending range
+        len: usize,
+    },
     /// The size isn't valid
     #[error("invalid size")]
     InvalidSize,
     /// Headers, tables or sections do overlap in the file
     #[error("values overlap")]
     Overlap,
-    /// Sections are not sorted in ascending order
-    #[error("sections not in ascending order")]
-    SectionNotInOrder,
+    /// Sections are not sorted in ascending order, or overlap
+    #[error("section {section} overlaps or precedes section {previous_section}")]
+    SectionNotInOrder {
+        /// Index and, if it resolves, name of the offending section, e.g. `2 (.dynamic)`
+        section: String,
+        /// Index and, if it resolves, name of the section before it
+        previous_section: String,
+    },
     /// No section name string table present in the file
     #[error("no section name string table found")]
     NoSectionNameStringTable,
@@ -61,14 +94,43 @@ pub enum ElfParserError {
     /// No dynamic string table
     #[error("no dynamic string table")]
     NoDynamicStringTable,
+    /// A relocation refers to a symbol which is not in the dynamic symbol table
+    #[error("invalid symbol index {0}")]
+    InvalidSymbolIndex(usize),
+    /// A program header has a type the loader cannot support, e.g. PT_TLS
+    #[error("unsupported segment type {0:#x}")]
+    UnsupportedSegmentType(Elf64Word),
+    /// The call frame information uses an instruction the parser does not support
+    #[error("unsupported call frame instruction {0:#04x}")]
+    UnsupportedCallFrameInstruction(u8),
+    /// A CIE or FDE record of the call frame information is malformed or unsupported
+    #[error("invalid call frame information")]
+    InvalidCallFrameInformation,
+    /// A line number program of the `.debug_line` section is malformed or unsupported
+    #[error("invalid line number program")]
+    InvalidLineNumberProgram,
+    /// Two PT_LOAD program headers map overlapping virtual address ranges
+    #[error("program header {index} overlaps the vm range of program header {other_index}")]
+    SegmentVmOverlap {
+        /// Index of the later program header
+        index: usize,
+        /// Index of the earlier program header it overlaps
+        other_index: usize,
+    },
+    /// A dynamic symbol names no string of `.dynstr` or refers to no section
+    #[error("invalid dynamic symbol {index}")]
+    InvalidDynamicSymbol {
+        /// Index of the symbol in the dynamic symbol table
+        index: usize,
+    },
 }
 
 impl Elf64Phdr {
     /// Returns the byte range the section spans in the file.
     pub fn file_range(&self) -> Option<Range<usize>> {
         (self.p_type == PT_LOAD).then(|| {
-            let offset = self.p_offset as usize;
-            offset..offset.saturating_add(self.p_filesz as usize)
+            let offset = saturating_usize(self.p_offset);
+            offset..offset.saturating_add(saturating_usize(self.p_filesz))
         })
     }
 
@@ -77,6 +139,21 @@ impl Elf64Phdr {
         let addr = self.p_vaddr;
         addr..addr.saturating_add(self.p_memsz)
     }
+
+    /// Returns whether the segment is executable.
+    pub fn is_executable(&self) -> bool {
+        self.p_flags & PF_X != 0
+    }
+
+    /// Returns whether the segment is writable.
+    pub fn is_writable(&self) -> bool {
+        self.p_flags & PF_W != 0
+    }
+
+    /// Returns whether the segment is readable.
+    pub fn is_readable(&self) -> bool {
+        self.p_flags & PF_R != 0
+    }
 }
 
 impl Elf64Shdr {
@@ -85,11 +162,16 @@ impl Elf64Shdr {
         self.sh_flags & (SHF_ALLOC | SHF_WRITE) == SHF_ALLOC | SHF_WRITE
     }
 
+    /// Returns whether the section is executable.
+    pub fn is_executable(&self) -> bool {
+        self.sh_flags & (SHF_ALLOC | SHF_EXECINSTR) == SHF_ALLOC | SHF_EXECINSTR
+    }
+
     /// Returns the byte range the section spans in the file.
     pub fn file_range(&self) -> Option<Range<usize>> {
         (self.sh_type != SHT_NOBITS).then(|| {
-            let offset = self.sh_offset as usize;
-            offset..offset.saturating_add(self.sh_size as usize)
+            let offset = saturating_usize(self.sh_offset);
+            offset..offset.saturating_add(saturating_usize(self.sh_size))
         })
     }
 
@@ -104,6 +186,56 @@ impl Elf64Sym {
     pub fn is_function(&self) -> bool {
         (self.st_info & 0xF) == STT_FUNC
     }
+
+    /// Returns the symbol type.
+    pub fn symbol_type(&self) -> SymbolType {
+        match self.st_info & 0xF {
+            STT_NOTYPE => SymbolType::NoType,
+            STT_OBJECT => SymbolType::Object,
+            STT_FUNC => SymbolType::Function,
+            STT_SECTION => SymbolType::Section,
+            STT_FILE => SymbolType::File,
+            symbol_type => SymbolType::Other(symbol_type),
+        }
+    }
+
+    /// Returns the symbol binding.
+    pub fn binding(&self) -> SymbolBinding {
+        match self.st_info >> 4 {
+            STB_LOCAL => SymbolBinding::Local,
+            STB_GLOBAL => SymbolBinding::Global,
+            STB_WEAK => SymbolBinding::Weak,
+            binding => SymbolBinding::Other(binding),
+        }
+    }
+
+    /// Returns the symbol visibility.
+    pub fn visibility(&self) -> SymbolVisibility {
+        match self.st_other & 0x3 {
+            STV_DEFAULT => SymbolVisibility::Default,
+            STV_INTERNAL => SymbolVisibility::Internal,
+            STV_HIDDEN => SymbolVisibility::Hidden,
+            _ => SymbolVisibility::Protected,
+        }
+    }
+
+    /// Returns whether the symbol has global binding.
+    pub fn is_global(&self) -> bool {
+        self.binding() == SymbolBinding::Global
+    }
+
+    /// Returns whether the symbol has weak binding.
+    pub fn is_weak(&self) -> bool {
+        self.binding() == SymbolBinding::Weak
+    }
+
+    /// Returns whether the symbol is undefined, like the imported ones.
+    ///
+    /// Together with [Elf64Sym::is_weak] this tells undefined weak symbols, which may stay
+    /// unresolved, from undefined global ones.
+    pub fn is_undefined(&self) -> bool {
+        self.st_shndx == SHN_UNDEF
+    }
 }
 
 impl Elf64Rel {
@@ -118,9 +250,300 @@ impl Elf64Rel {
     }
 }
 
-fn check_that_there_is_no_overlap(
-    range_a: &Range<usize>,
-    range_b: &Range<usize>,
+impl Elf64Rela {
+    /// Returns the relocation type.
+    pub fn r_type(&self) -> Elf64Word {
+        (self.r_info & 0xFFFFFFFF) as Elf64Word
+    }
+
+    /// Returns the symbol index.
+    pub fn r_sym(&self) -> Elf64Word {
+        self.r_info.checked_shr(32).unwrap_or(0) as Elf64Word
+    }
+}
+
+/// Entry types of the dynamic relocation tables, which are cast from the file bytes
+///
+/// Private so that it stays limited to the plain old data types [Elf64Rel] and [Elf64Rela].
+trait RelocationEntry: Sized + 'static {
+    /// Dynamic section tag of the table address
+    const TABLE_TAG: Elf64Xword;
+    /// Dynamic section tag of the table size in bytes
+    const SIZE_TAG: Elf64Xword;
+    /// Dynamic section tag of the entry size in bytes
+    const ENTRY_SIZE_TAG: Elf64Xword;
+}
+
+impl RelocationEntry for Elf64Rel {
+    const TABLE_TAG: Elf64Xword = DT_REL;
+    const SIZE_TAG: Elf64Xword = DT_RELSZ;
+    const ENTRY_SIZE_TAG: Elf64Xword = DT_RELENT;
+}
+
+impl RelocationEntry for Elf64Rela {
+    const TABLE_TAG: Elf64Xword = DT_RELA;
+    const SIZE_TAG: Elf64Xword = DT_RELASZ;
+    const ENTRY_SIZE_TAG: Elf64Xword = DT_RELAENT;
+}
+
+/// An entry of either the DT_REL or the DT_RELA table
+#[derive(Debug, Clone, Copy)]
+pub enum DynamicRelocation<'a> {
+    /// Relocation with an implicit addend stored at the relocation site
+    Rel(&'a Elf64Rel),
+    /// Relocation with an explicit addend
+    Rela(&'a Elf64Rela),
+}
+
+impl DynamicRelocation<'_> {
+    /// Returns the location to apply the relocation to.
+    pub fn r_offset(&self) -> Elf64Addr {
+        match self {
+            Self::Rel(relocation) => relocation.r_offset,
+            Self::Rela(relocation) => relocation.r_offset,
+        }
+    }
+
+    /// Returns the relocation type.
+    pub fn r_type(&self) -> Elf64Word {
+        match self {
+            Self::Rel(relocation) => relocation.r_type(),
+            Self::Rela(relocation) => relocation.r_type(),
+        }
+    }
+
+    /// Returns the symbol index.
+    pub fn r_sym(&self) -> Elf64Word {
+        match self {
+            Self::Rel(relocation) => relocation.r_sym(),
+            Self::Rela(relocation) => relocation.r_sym(),
+        }
+    }
+
+    /// Returns the explicit addend, if any.
+    pub fn r_addend(&self) -> Option<Elf64Sxword> {
+        match self {
+            Self::Rel(_) => None,
+            Self::Rela(relocation) => Some(relocation.r_addend),
+        }
+    }
+}
+
+/// Maps the e_flags of the file header to the SBPF version they encode
+///
+/// The legacy [EF_SBPF_V2] flag maps to [SBPFVersion::Reserved].
+pub fn sbpf_version_from_e_flags(e_flags: Elf64Word) -> Result<SBPFVersion, ElfParserError> {
+    match e_flags {
+        0 => Ok(SBPFVersion::V0),
+        1 => Ok(SBPFVersion::V1),
+        2 => Ok(SBPFVersion::V2),
+        3 => Ok(SBPFVersion::V3),
+        EF_SBPF_V2 => Ok(SBPFVersion::Reserved),
+        _ => Err(ElfParserError::InvalidFileHeader),
+    }
+}
+
+/// Optional checks of [Elf64::parse_with_options]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ElfParserOptions {
+    /// Reject files whose e_flags encode no known SBPF version, see [Elf64::sbpf_version]
+    pub reject_unknown_e_flags: bool,
+    /// Reject PT_LOAD program headers whose vm ranges overlap, touching ranges are accepted
+    ///
+    /// Without this check the segment mapped last wins. The loader enables it for all SBPF
+    /// versions after V0.
+    pub reject_overlapping_segments: bool,
+    /// Reject dynamic symbols whose st_name is beyond `.dynstr` or whose st_shndx is neither a
+    /// section nor a reserved index, see [ElfParserError::InvalidDynamicSymbol]
+    ///
+    /// Without this check such symbols only fail once their name or section is looked up. The
+    /// loader enables it for all SBPF versions after V0.
+    pub validate_dynamic_symbols: bool,
+}
+
+/// Hash function of the DT_GNU_HASH table
+pub fn gnu_hash(name: &[u8]) -> u32 {
+    name.iter().fold(5381u32, |hash, byte| {
+        hash.wrapping_mul(33).wrapping_add(*byte as u32)
+    })
+}
+
+/// The DT_GNU_HASH table, which maps names to dynamic symbols
+///
+/// Only the dynamic symbols from [GnuHashTable::symbol_offset] on are hashed, the ones before
+/// (usually undefined symbols) are not.
+#[derive(Debug, Clone, Copy)]
+pub struct GnuHashTable<'a> {
+    symbol_offset: usize,
+    bloom_shift: u32,
+    bloom: &'a [u64],
+    buckets: &'a [u32],
+    chains: &'a [u32],
+}
+
+impl<'a> GnuHashTable<'a> {
+    /// Parses the table at the start of `bytes`
+    ///
+    /// The chain array has one entry per hashed symbol, so its length is derived from the
+    /// `symbol_count` of the dynamic symbol table.
+    pub fn parse(bytes: &'a [u8], symbol_count: usize) -> Result<Self, ElfParserError> {
+        const HEADER_SIZE: usize = mem::size_of::<[u32; 4]>();
+        let header = Elf64::slice_from_bytes::<u32>(bytes, 0..HEADER_SIZE)?;
+        let (bucket_count, symbol_offset, bloom_size, bloom_shift) = (
+            header[0] as usize,
+            header[1] as usize,
+            header[2] as usize,
+            header[3],
+        );
+        if bucket_count == 0 || bloom_size == 0 {
+            return Err(ElfParserError::InvalidSize);
+        }
+        let chain_count =
+            symbol_count
+                .checked_sub(symbol_offset)
+                .ok_or(ElfParserError::OutOfBounds {
+                    offset: symbol_offset,
+                    len: 0,
+                })?;
+
+        // ELFCLASS64 uses 64 bit bloom filter words
+        let bloom_range = checked_range(
+            HEADER_SIZE,
+            bloom_size.saturating_mul(mem::size_of::<u64>()),
+        )?;
+        let buckets_range = checked_range(
+            bloom_range.end,
+            bucket_count.saturating_mul(mem::size_of::<u32>()),
+        )?;
+        let chains_range = checked_range(
+            buckets_range.end,
+            chain_count.saturating_mul(mem::size_of::<u32>()),
+        )?;
+        Ok(Self {
+            symbol_offset,
+            bloom_shift,
+            bloom: Elf64::slice_from_bytes(bytes, bloom_range)?,
+            buckets: Elf64::slice_from_bytes(bytes, buckets_range)?,
+            chains: Elf64::slice_from_bytes(bytes, chains_range)?,
+        })
+    }
+
+    /// Returns the index of the first hashed dynamic symbol
+    pub fn symbol_offset(&self) -> usize {
+        self.symbol_offset
+    }
+
+    /// Returns the hashed symbol called `name`
+    ///
+    /// `dynsym` and `dynstr` are the contents of the dynamic symbol and string tables.
+    pub fn lookup(
+        &self,
+        name: &[u8],
+        dynsym: &'a [Elf64Sym],
+        dynstr: &'a [u8],
+    ) -> Option<&'a Elf64Sym> {
+        let hash = gnu_hash(name);
+        let bits = u64::BITS;
+        let bloom_word = self
+            .bloom
+            .get((hash.checked_div(bits).unwrap_or(0) as usize).checked_rem(self.bloom.len())?)?;
+        let bloom_mask = 1u64.wrapping_shl(hash.checked_rem(bits).unwrap_or(0))
+            | 1u64.wrapping_shl(
+                hash.checked_shr(self.bloom_shift)
+                    .unwrap_or(0)
+                    .checked_rem(bits)
+                    .unwrap_or(0),
+            );
+        if bloom_word & bloom_mask != bloom_mask {
+            return None;
+        }
+
+        let mut index = *self
+            .buckets
+            .get((hash as usize).checked_rem(self.buckets.len())?)?
+            as usize;
+        if index == 0 {
+            return None;
+        }
+        loop {
+            let chain_hash = *self.chains.get(index.checked_sub(self.symbol_offset)?)?;
+            if chain_hash | 1 == hash | 1 {
+                let symbol = dynsym.get(index)?;
+                if Elf64::symbol_name_in(dynstr, symbol) == Some(name) {
+                    return Some(symbol);
+                }
+            }
+            // The lowest bit marks the end of the chain
+            if chain_hash & 1 != 0 {
+                return None;
+            }
+            index = index.checked_add(1)?;
+        }
+    }
+}
+
+/// A dynamic relocation together with the dynamic symbol it refers to
+#[derive(Debug, Clone, Copy)]
+pub struct ResolvedRelocation<'a> {
+    /// Location to apply the relocation to
+    pub r_offset: Elf64Addr,
+    /// Relocation type
+    pub r_type: Elf64Word,
+    /// Index of the symbol in the dynamic symbol table
+    pub r_sym: Elf64Word,
+    /// Explicit addend of DT_RELA entries
+    pub r_addend: Option<Elf64Sxword>,
+    /// The symbol at index `r_sym`
+    ///
+    /// `None` if `r_sym` is zero and there is no dynamic symbol table.
+    pub symbol: Option<&'a Elf64Sym>,
+    /// Name of the symbol, `None` if it can not be read from the dynamic string table
+    pub symbol_name: Option<&'a [u8]>,
+}
+
+impl<'a> ResolvedRelocation<'a> {
+    fn new(
+        relocation: DynamicRelocation<'a>,
+        dynamic_symbol_table: Option<&'a [Elf64Sym]>,
+        dynamic_symbol_name: impl Fn(Elf64Word) -> Result<&'a [u8], ElfParserError>,
+    ) -> Result<Self, ElfParserError> {
+        let r_sym = relocation.r_sym();
+        let symbol = match dynamic_symbol_table {
+            None if r_sym == 0 => None,
+            symbols => Some(
+                symbols
+                    .and_then(|symbols| symbols.get(r_sym as usize))
+                    .ok_or(ElfParserError::InvalidSymbolIndex(r_sym as usize))?,
+            ),
+        };
+        Ok(Self {
+            r_offset: relocation.r_offset(),
+            r_type: relocation.r_type(),
+            r_sym,
+            r_addend: relocation.r_addend(),
+            symbol,
+            symbol_name: symbol
+                .and_then(|symbol| dynamic_symbol_name(symbol.st_name as Elf64Word).ok()),
+        })
+    }
+}
+
+/// Returns `offset..offset + len`, failing if the end overflows
+/// Converts a file offset or size, so that values beyond the address space fail range checks
+pub(crate) fn saturating_usize(value: u64) -> usize {
+    usize::try_from(value).unwrap_or(usize::MAX)
+}
+
+fn checked_range(offset: usize, len: usize) -> Result<Range<usize>, ElfParserError> {
+    offset
+        .checked_add(len)
+        .map(|end| offset..end)
+        .ok_or(ElfParserError::OutOfBounds { offset, len })
+}
+
+fn check_that_there_is_no_overlap<T: PartialOrd>(
+    range_a: &Range<T>,
+    range_b: &Range<T>,
 ) -> Result<(), ElfParserError> {
     if range_a.end <= range_b.start || range_b.end <= range_a.start {
         Ok(())
@@ -129,6 +552,137 @@ fn check_that_there_is_no_overlap(
     }
 }
 
+/// The headers of an ELF file, see [Elf64::parse_headers_only]
+#[derive(Debug)]
+pub struct Elf64Headers<'a> {
+    elf_bytes: &'a [u8],
+    file_header: &'a Elf64Ehdr,
+    file_header_range: Range<usize>,
+    program_header_table: &'a [Elf64Phdr],
+    program_header_table_range: Range<usize>,
+    section_header_table: &'a [Elf64Shdr],
+    section_header_table_range: Range<usize>,
+    section_names_index: usize,
+    options: ElfParserOptions,
+}
+
+impl<'a> Elf64Headers<'a> {
+    /// Returns the file header.
+    pub fn file_header(&self) -> &'a Elf64Ehdr {
+        self.file_header
+    }
+
+    /// Returns the SBPF version encoded in the e_flags of the file header.
+    pub fn sbpf_version(&self) -> Result<SBPFVersion, ElfParserError> {
+        sbpf_version_from_e_flags(self.file_header.e_flags)
+    }
+
+    /// Returns the program header table.
+    pub fn program_header_table(&self) -> &'a [Elf64Phdr] {
+        self.program_header_table
+    }
+
+    /// Returns the section header table.
+    pub fn section_header_table(&self) -> &'a [Elf64Shdr] {
+        self.section_header_table
+    }
+
+    /// Completes the parse the headers were checked for, without checking them again
+    pub fn finish_parse(self) -> Result<Elf64<'a>, ElfParserError> {
+        let Self {
+            elf_bytes,
+            file_header,
+            file_header_range,
+            program_header_table,
+            program_header_table_range,
+            section_header_table,
+            section_header_table_range,
+            section_names_index,
+            options,
+        } = self;
+        let section_names_section_header = (section_names_index != SHN_UNDEF as usize)
+            .then(|| {
+                section_header_table
+                    .get(section_names_index)
+                    .ok_or(ElfParserError::OutOfBounds {
+                        offset: section_names_index,
+                        len: 1,
+                    })
+            })
+            .transpose()?;
+        // Names are only looked up if the string table is not one of the offending sections
+        let describe_section = |index: usize, with_name: bool| {
+            section_names_section_header
+                .filter(|_| with_name)
+                .zip(section_header_table.get(index))
+                .and_then(|(section_names_section_header, section_header)| {
+                    Elf64::get_string_in_section(
+                        elf_bytes,
+                        section_names_section_header,
+                        section_header.sh_name,
+                        SECTION_NAME_LENGTH_MAXIMUM,
+                    )
+                    .ok()
+                })
+                .map(|name| format!("{index} ({})", String::from_utf8_lossy(name)))
+                .unwrap_or_else(|| index.to_string())
+        };
+
+        let mut offset = 0usize;
+        let mut previous_index = 0;
+        // The null section header has no contents, its sh_size may hold the section count
+        for (index, section_header) in section_header_table.iter().enumerate().skip(1) {
+            if section_header.sh_type == SHT_NOBITS {
+                continue;
+            }
+            let section_range = checked_range(
+                saturating_usize(section_header.sh_offset),
+                saturating_usize(section_header.sh_size),
+            )?;
+            check_that_there_is_no_overlap(&section_range, &file_header_range)?;
+            check_that_there_is_no_overlap(&section_range, &program_header_table_range)?;
+            check_that_there_is_no_overlap(&section_range, &section_header_table_range)?;
+            if section_range.start < offset {
+                let with_names =
+                    index != section_names_index && previous_index != section_names_index;
+                return Err(ElfParserError::SectionNotInOrder {
+                    section: describe_section(index, with_names),
+                    previous_section: describe_section(previous_index, with_names),
+                });
+            }
+            offset = section_range.end;
+            previous_index = index;
+            if offset > elf_bytes.len() {
+                return Err(ElfParserError::OutOfBounds {
+                    offset: section_range.start,
+                    len: section_range.len(),
+                });
+            }
+        }
+
+        let mut parser = Elf64 {
+            elf_bytes,
+            file_header,
+            program_header_table,
+            section_header_table,
+            section_names_section_header,
+            symbol_section_header: None,
+            symbol_names_section_header: None,
+            dynamic_table: [0; DT_NUM],
+            dynamic_relocations_table: None,
+            dynamic_relocations_rela_table: None,
+            dynamic_symbol_table: None,
+            dynamic_symbol_names_section_header: None,
+            gnu_hash_table_vaddr: None,
+        };
+
+        parser.parse_sections()?;
+        parser.parse_dynamic(options)?;
+
+        Ok(parser)
+    }
+}
+
 /// The parsed structure of an ELF file
 pub struct Elf64<'a> {
     elf_bytes: &'a [u8],
@@ -140,13 +694,39 @@ pub struct Elf64<'a> {
     symbol_names_section_header: Option<&'a Elf64Shdr>,
     dynamic_table: [Elf64Xword; DT_NUM],
     dynamic_relocations_table: Option<&'a [Elf64Rel]>,
+    dynamic_relocations_rela_table: Option<&'a [Elf64Rela]>,
     dynamic_symbol_table: Option<&'a [Elf64Sym]>,
     dynamic_symbol_names_section_header: Option<&'a Elf64Shdr>,
+    gnu_hash_table_vaddr: Option<Elf64Addr>,
 }
 
 impl<'a> Elf64<'a> {
     /// Parse from the given byte slice
     pub fn parse(elf_bytes: &'a [u8]) -> Result<Self, ElfParserError> {
+        Self::parse_with_options(elf_bytes, ElfParserOptions::default())
+    }
+
+    /// Parse from the given byte slice, applying the optional checks enabled in `options`
+    pub fn parse_with_options(
+        elf_bytes: &'a [u8],
+        options: ElfParserOptions,
+    ) -> Result<Self, ElfParserError> {
+        Self::parse_headers_only_with_options(elf_bytes, options)?.finish_parse()
+    }
+
+    /// Parses and checks the file header and the program and section header tables only
+    ///
+    /// The section ranges, the section contents and the dynamic tables are checked by
+    /// [Elf64Headers::finish_parse].
+    pub fn parse_headers_only(elf_bytes: &'a [u8]) -> Result<Elf64Headers<'a>, ElfParserError> {
+        Self::parse_headers_only_with_options(elf_bytes, ElfParserOptions::default())
+    }
+
+    /// Same as [Elf64::parse_headers_only], applying the optional checks enabled in `options`
+    pub fn parse_headers_only_with_options(
+        elf_bytes: &'a [u8],
+        options: ElfParserOptions,
+    ) -> Result<Elf64Headers<'a>, ElfParserError> {
         let (file_header_range, file_header) = Self::parse_file_header(elf_bytes)?;
 
         if file_header.e_ident.ei_mag != ELFMAG
@@ -157,10 +737,12 @@ impl<'a> Elf64<'a> {
             || file_header.e_ehsize != mem::size_of::<Elf64Ehdr>() as u16
             || file_header.e_phentsize != mem::size_of::<Elf64Phdr>() as u16
             || file_header.e_shentsize != mem::size_of::<Elf64Shdr>() as u16
-            || file_header.e_shstrndx >= file_header.e_shnum
         {
             return Err(ElfParserError::InvalidFileHeader);
         }
+        if options.reject_unknown_e_flags {
+            sbpf_version_from_e_flags(file_header.e_flags)?;
+        }
 
         let (program_header_table_range, program_header_table) =
             Self::parse_program_header_table(elf_bytes, file_header_range.clone(), file_header)?;
@@ -171,77 +753,77 @@ impl<'a> Elf64<'a> {
             file_header,
             program_header_table_range.clone(),
         )?;
+        let section_names_index = match section_header_table.first() {
+            Some(section_header) if file_header.e_shstrndx == SHN_XINDEX => {
+                section_header.sh_link as usize
+            }
+            _ => file_header.e_shstrndx as usize,
+        };
+        if section_names_index >= section_header_table.len() {
+            return Err(ElfParserError::InvalidFileHeader);
+        }
 
         section_header_table
             .first()
             .filter(|section_header| section_header.sh_type == SHT_NULL)
-            .ok_or(ElfParserError::InvalidSectionHeader)?;
+            .ok_or(ElfParserError::InvalidSectionHeader { index: 0 })?;
 
         let mut vaddr = 0;
-        for program_header in program_header_table {
+        // The earlier PT_LOAD segment reaching the highest address
+        let mut highest_segment: Option<(usize, Range<Elf64Addr>)> = None;
+        for (index, program_header) in program_header_table.iter().enumerate() {
+            // There are no thread locals, so reject instead of loading without them
+            if program_header.p_type == PT_TLS {
+                return Err(ElfParserError::UnsupportedSegmentType(
+                    program_header.p_type,
+                ));
+            }
             if program_header.p_type != PT_LOAD {
                 continue;
             }
             // program headers must be ascending
             if program_header.p_vaddr < vaddr {
-                return Err(ElfParserError::InvalidProgramHeader);
+                return Err(ElfParserError::InvalidProgramHeader { index });
             }
-            if program_header
-                .p_offset
-                .err_checked_add(program_header.p_filesz)? as usize
-                > elf_bytes.len()
-            {
-                return Err(ElfParserError::OutOfBounds);
+            let segment_range = checked_range(
+                saturating_usize(program_header.p_offset),
+                saturating_usize(program_header.p_filesz),
+            )?;
+            if segment_range.end > elf_bytes.len() {
+                return Err(ElfParserError::OutOfBounds {
+                    offset: segment_range.start,
+                    len: segment_range.len(),
+                });
             }
             vaddr = program_header.p_vaddr;
-        }
-
-        let mut offset = 0usize;
-        for section_header in section_header_table.iter() {
-            if section_header.sh_type == SHT_NOBITS {
-                continue;
-            }
-            let section_range = section_header.sh_offset as usize
-                ..(section_header.sh_offset as usize)
-                    .err_checked_add(section_header.sh_size as usize)?;
-            check_that_there_is_no_overlap(&section_range, &file_header_range)?;
-            check_that_there_is_no_overlap(&section_range, &program_header_table_range)?;
-            check_that_there_is_no_overlap(&section_range, &section_header_table_range)?;
-            if section_range.start < offset {
-                return Err(ElfParserError::SectionNotInOrder);
-            }
-            offset = section_range.end;
-            if offset > elf_bytes.len() {
-                return Err(ElfParserError::OutOfBounds);
+            let vm_range = program_header.vm_range();
+            if options.reject_overlapping_segments && !vm_range.is_empty() {
+                if let Some((other_index, other_vm_range)) = &highest_segment {
+                    check_that_there_is_no_overlap(other_vm_range, &vm_range).map_err(|_| {
+                        ElfParserError::SegmentVmOverlap {
+                            index,
+                            other_index: *other_index,
+                        }
+                    })?;
+                }
+                if !matches!(&highest_segment, Some((_, other_vm_range)) if other_vm_range.end >= vm_range.end)
+                {
+                    highest_segment = Some((index, vm_range));
+                }
             }
         }
 
-        let section_names_section_header = (file_header.e_shstrndx != SHN_UNDEF)
-            .then(|| {
-                section_header_table
-                    .get(file_header.e_shstrndx as usize)
-                    .ok_or(ElfParserError::OutOfBounds)
-            })
-            .transpose()?;
-
-        let mut parser = Self {
+        Ok(Elf64Headers {
             elf_bytes,
             file_header,
+            file_header_range,
             program_header_table,
+            program_header_table_range,
             section_header_table,
-            section_names_section_header,
-            symbol_section_header: None,
-            symbol_names_section_header: None,
-            dynamic_table: [0; DT_NUM],
-            dynamic_relocations_table: None,
-            dynamic_symbol_table: None,
-            dynamic_symbol_names_section_header: None,
-        };
-
-        parser.parse_sections()?;
-        parser.parse_dynamic()?;
-
-        Ok(parser)
+            section_header_table_range,
+            section_names_index,
+            options,
+        })
     }
 
     /// Returns the file header.
@@ -249,34 +831,269 @@ impl<'a> Elf64<'a> {
         self.file_header
     }
 
+    /// Returns the SBPF version encoded in the e_flags of the file header.
+    pub fn sbpf_version(&self) -> Result<SBPFVersion, ElfParserError> {
+        sbpf_version_from_e_flags(self.file_header.e_flags)
+    }
+
     /// Returns the program header table.
     pub fn program_header_table(&self) -> &[Elf64Phdr] {
         self.program_header_table
     }
 
+    /// Returns the PT_LOAD program headers along with their file bytes.
+    ///
+    /// The bytes only cover p_filesz, the rest of p_memsz is zero filled when loading.
+    pub fn load_segments(
+        &self,
+    ) -> impl Iterator<Item = Result<(&'a Elf64Phdr, &'a [u8]), ElfParserError>> + '_ {
+        self.program_header_table
+            .iter()
+            .filter(|program_header| program_header.p_type == PT_LOAD)
+            .map(move |program_header| {
+                Self::slice_from_program_header(self.elf_bytes, program_header)
+                    .map(|bytes| (program_header, bytes))
+            })
+    }
+
+    /// Returns the p_flags of the PT_GNU_STACK program header, if there is one.
+    pub fn gnu_stack_flags(&self) -> Option<Elf64Word> {
+        self.program_header_table
+            .iter()
+            .find(|program_header| program_header.p_type == PT_GNU_STACK)
+            .map(|program_header| program_header.p_flags)
+    }
+
     /// Returns the section header table.
     pub fn section_header_table(&self) -> &[Elf64Shdr] {
         self.section_header_table
     }
 
+    /// Returns the section headers along with their names.
+    ///
+    /// A section whose name can not be resolved yields the error in place of its name, the
+    /// sections after it are still yielded.
+    pub fn section_headers(
+        &self,
+    ) -> impl Iterator<Item = (Result<&'a [u8], ElfParserError>, &'a Elf64Shdr)> + '_ {
+        self.section_header_table
+            .iter()
+            .map(move |section_header| (self.section_name(section_header.sh_name), section_header))
+    }
+
+    /// Returns the first section header named `name`.
+    pub fn find_section(&self, name: &[u8]) -> Option<&'a Elf64Shdr> {
+        self.section_headers()
+            .find(|(section_name, _)| matches!(section_name, Ok(section_name) if *section_name == name))
+            .map(|(_, section_header)| section_header)
+    }
+
     /// Returns the dynamic symbol table.
     pub fn dynamic_symbol_table(&self) -> Option<&[Elf64Sym]> {
         self.dynamic_symbol_table
     }
 
+    /// Looks up the symbol a dynamic relocation refers to
+    pub fn resolve_relocation(
+        &self,
+        relocation: DynamicRelocation<'a>,
+    ) -> Result<ResolvedRelocation<'a>, ElfParserError> {
+        ResolvedRelocation::new(relocation, self.dynamic_symbol_table, |st_name| {
+            self.dynamic_symbol_name(st_name)
+        })
+    }
+
+    /// Returns all dynamic relocations with the symbols they refer to
+    ///
+    /// A relocation with an out of range symbol index yields an error, the following ones are
+    /// still resolved.
+    pub fn relocations(
+        &self,
+    ) -> impl Iterator<Item = Result<ResolvedRelocation<'a>, ElfParserError>> + '_ {
+        self.dynamic_relocations()
+            .map(move |relocation| self.resolve_relocation(relocation))
+    }
+
+    /// Returns the DT_GNU_HASH table, if there is one
+    ///
+    /// The table is only parsed when requested, so a broken table does not fail [Elf64::parse].
+    pub fn gnu_hash_table(&self) -> Result<Option<GnuHashTable<'a>>, ElfParserError> {
+        let vaddr = match self.gnu_hash_table_vaddr {
+            Some(vaddr) => vaddr,
+            None => return Ok(None),
+        };
+        let symbol_count = self
+            .dynamic_symbol_table
+            .ok_or(ElfParserError::InvalidDynamicSectionTable)?
+            .len();
+        let (offset, available) =
+            self.file_location_of_vaddr(vaddr)?
+                .ok_or(ElfParserError::OutOfBounds {
+                    offset: saturating_usize(vaddr),
+                    len: 0,
+                })?;
+        let bytes = self
+            .elf_bytes
+            .get(offset..offset.saturating_add(available))
+            .ok_or(ElfParserError::OutOfBounds {
+                offset,
+                len: available,
+            })?;
+        GnuHashTable::parse(bytes, symbol_count).map(Some)
+    }
+
+    /// Returns the pc range and entry CFA rule of each FDE in the `.eh_frame` section
+    ///
+    /// Files without a `.eh_frame` section have no entries. The records are only parsed when
+    /// iterating, see [eh_frame::EhFrameEntries].
+    pub fn eh_frame_entries(&self) -> Result<eh_frame::EhFrameEntries<'a>, ElfParserError> {
+        let section_header = match self.find_section(b".eh_frame") {
+            Some(section_header) => section_header,
+            None => return Ok(eh_frame::EhFrame::new(&[], 0).entries()),
+        };
+        let bytes = self.section_bytes(section_header)?;
+        Ok(eh_frame::EhFrame::new(bytes, section_header.sh_addr).entries())
+    }
+
+    /// Returns the line number information of the `.debug_line` section, if there is one
+    ///
+    /// DWARF 5 file names are looked up in the `.debug_line_str` section. The line number
+    /// programs are only interpreted when iterating, see [debug_line::DebugLine].
+    pub fn debug_line(&self) -> Result<Option<debug_line::DebugLine<'a>>, ElfParserError> {
+        let section_header = match self.find_section(b".debug_line") {
+            Some(section_header) => section_header,
+            None => return Ok(None),
+        };
+        let debug_line_str = match self.find_section(b".debug_line_str") {
+            Some(section_header) => self.section_bytes(section_header)?,
+            None => &[],
+        };
+        Ok(Some(debug_line::DebugLine::new(
+            self.section_bytes(section_header)?,
+            debug_line_str,
+        )))
+    }
+
+    /// Returns the notes of all SHT_NOTE sections, followed by the ones of the PT_NOTE segments
+    ///
+    /// Segments covered by a SHT_NOTE section are skipped, so that files keeping their section
+    /// headers do not report the same notes twice. Each section or segment stops at its first
+    /// error, see [note::Notes].
+    pub fn notes(&self) -> impl Iterator<Item = Result<note::Note<'a>, ElfParserError>> + '_ {
+        let section_ranges = self
+            .section_header_table
+            .iter()
+            .filter(|section_header| section_header.sh_type == SHT_NOTE)
+            .map(|section_header| {
+                checked_range(
+                    saturating_usize(section_header.sh_offset),
+                    saturating_usize(section_header.sh_size),
+                )
+            });
+        let segment_ranges = self
+            .program_header_table
+            .iter()
+            .filter(|program_header| program_header.p_type == PT_NOTE)
+            .map(|program_header| {
+                checked_range(
+                    saturating_usize(program_header.p_offset),
+                    saturating_usize(program_header.p_filesz),
+                )
+            })
+            .filter(move |segment_range| match segment_range {
+                Ok(segment_range) => !self.is_in_note_section(segment_range),
+                Err(_) => true,
+            });
+        section_ranges.chain(segment_ranges).flat_map(move |range| {
+            let bytes = range.and_then(|range| {
+                self.elf_bytes
+                    .get(range.clone())
+                    .ok_or(ElfParserError::OutOfBounds {
+                        offset: range.start,
+                        len: range.len(),
+                    })
+            });
+            let (notes, err) = match bytes {
+                Ok(bytes) => (Some(note::Notes::new(bytes)), None),
+                Err(err) => (None, Some(Err(err))),
+            };
+            notes.into_iter().flatten().chain(err)
+        })
+    }
+
+    /// Returns whether a SHT_NOTE section covers the file `range`
+    fn is_in_note_section(&self, range: &Range<usize>) -> bool {
+        self.section_header_table.iter().any(|section_header| {
+            section_header.sh_type == SHT_NOTE
+                && checked_range(
+                    saturating_usize(section_header.sh_offset),
+                    saturating_usize(section_header.sh_size),
+                )
+                .map(|section_range| {
+                    section_range.start <= range.start && range.end <= section_range.end
+                })
+                .unwrap_or(false)
+        })
+    }
+
+    /// Returns the descriptor of the first NT_GNU_BUILD_ID note, if there is one
+    pub fn build_id(&self) -> Result<Option<&'a [u8]>, ElfParserError> {
+        for note in self.notes() {
+            let note = note?;
+            if note.name == b"GNU" && note.n_type == NT_GNU_BUILD_ID {
+                return Ok(Some(note.desc));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the file bytes of a section
+    fn section_bytes(&self, section_header: &Elf64Shdr) -> Result<&'a [u8], ElfParserError> {
+        section_header
+            .file_range()
+            .and_then(|range| self.elf_bytes.get(range))
+            .ok_or(ElfParserError::OutOfBounds {
+                offset: saturating_usize(section_header.sh_offset),
+                len: saturating_usize(section_header.sh_size),
+            })
+    }
+
     /// Returns the dynamic relocations table.
     pub fn dynamic_relocations_table(&self) -> Option<&[Elf64Rel]> {
         self.dynamic_relocations_table
     }
 
+    /// Returns the dynamic relocations table with explicit addends.
+    pub fn dynamic_relocations_rela_table(&self) -> Option<&[Elf64Rela]> {
+        self.dynamic_relocations_rela_table
+    }
+
+    /// Returns all dynamic relocations, the DT_REL entries followed by the DT_RELA entries.
+    pub fn dynamic_relocations(&self) -> impl Iterator<Item = DynamicRelocation<'a>> + 'a {
+        self.dynamic_relocations_table
+            .unwrap_or_default()
+            .iter()
+            .map(DynamicRelocation::Rel)
+            .chain(
+                self.dynamic_relocations_rela_table
+                    .unwrap_or_default()
+                    .iter()
+                    .map(DynamicRelocation::Rela),
+            )
+    }
+
     /// Parses the file header.
     pub fn parse_file_header(
         elf_bytes: &'a [u8],
     ) -> Result<(core::ops::Range<usize>, &'a Elf64Ehdr), ElfParserError> {
         let file_header_range = 0..mem::size_of::<Elf64Ehdr>();
-        let file_header_bytes = elf_bytes
-            .get(file_header_range.clone())
-            .ok_or(ElfParserError::OutOfBounds)?;
+        let file_header_bytes =
+            elf_bytes
+                .get(file_header_range.clone())
+                .ok_or(ElfParserError::OutOfBounds {
+                    offset: file_header_range.start,
+                    len: file_header_range.len(),
+                })?;
         let ptr = file_header_bytes.as_ptr();
         if (ptr as usize)
             .checked_rem(mem::align_of::<Elf64Ehdr>())
@@ -295,10 +1112,10 @@ impl<'a> Elf64<'a> {
         file_header_range: core::ops::Range<usize>,
         file_header: &Elf64Ehdr,
     ) -> Result<(core::ops::Range<usize>, &'a [Elf64Phdr]), ElfParserError> {
-        let program_header_table_range = file_header.e_phoff as usize
-            ..mem::size_of::<Elf64Phdr>()
-                .err_checked_mul(file_header.e_phnum as usize)?
-                .err_checked_add(file_header.e_phoff as usize)?;
+        let program_header_table_range = checked_range(
+            saturating_usize(file_header.e_phoff),
+            mem::size_of::<Elf64Phdr>().saturating_mul(file_header.e_phnum as usize),
+        )?;
         check_that_there_is_no_overlap(&file_header_range, &program_header_table_range)?;
         let program_header_table =
             Self::slice_from_bytes::<Elf64Phdr>(elf_bytes, program_header_table_range.clone())?;
@@ -312,10 +1129,11 @@ impl<'a> Elf64<'a> {
         file_header: &Elf64Ehdr,
         program_header_table_range: core::ops::Range<usize>,
     ) -> Result<(core::ops::Range<usize>, &'a [Elf64Shdr]), ElfParserError> {
-        let section_header_table_range = file_header.e_shoff as usize
-            ..mem::size_of::<Elf64Shdr>()
-                .err_checked_mul(file_header.e_shnum as usize)?
-                .err_checked_add(file_header.e_shoff as usize)?;
+        let section_count = Self::section_count(elf_bytes, file_header)?;
+        let section_header_table_range = checked_range(
+            saturating_usize(file_header.e_shoff),
+            mem::size_of::<Elf64Shdr>().saturating_mul(section_count),
+        )?;
         check_that_there_is_no_overlap(&file_header_range, &section_header_table_range)?;
         check_that_there_is_no_overlap(&program_header_table_range, &section_header_table_range)?;
         let section_header_table =
@@ -323,14 +1141,39 @@ impl<'a> Elf64<'a> {
         Ok((section_header_table_range, section_header_table))
     }
 
+    /// Returns the number of section headers
+    ///
+    /// Files with SHN_LORESERVE or more sections have an e_shnum of 0 and store the count in
+    /// the sh_size of the first section header, the same way an e_shstrndx of SHN_XINDEX is
+    /// replaced by its sh_link.
+    fn section_count(
+        elf_bytes: &'a [u8],
+        file_header: &Elf64Ehdr,
+    ) -> Result<usize, ElfParserError> {
+        if file_header.e_shnum != 0 || file_header.e_shoff == 0 {
+            return Ok(file_header.e_shnum as usize);
+        }
+        let first_section_header = Self::slice_from_bytes::<Elf64Shdr>(
+            elf_bytes,
+            checked_range(
+                saturating_usize(file_header.e_shoff),
+                mem::size_of::<Elf64Shdr>(),
+            )?,
+        )?;
+        Ok(first_section_header
+            .first()
+            .map(|section_header| saturating_usize(section_header.sh_size))
+            .unwrap_or(0))
+    }
+
     fn parse_sections(&mut self) -> Result<(), ElfParserError> {
         macro_rules! section_header_by_name {
-            ($self:expr, $section_header:expr, $section_name:expr,
+            ($self:expr, $index:expr, $section_header:expr, $section_name:expr,
              $($name:literal => $field:ident,)*) => {
                 match $section_name {
                     $($name => {
                         if $self.$field.is_some() {
-                            return Err(ElfParserError::InvalidSectionHeader);
+                            return Err(ElfParserError::InvalidSectionHeader { index: $index });
                         }
                         $self.$field = Some($section_header);
                     })*
@@ -341,7 +1184,7 @@ impl<'a> Elf64<'a> {
         let section_names_section_header = self
             .section_names_section_header
             .ok_or(ElfParserError::NoSectionNameStringTable)?;
-        for section_header in self.section_header_table.iter() {
+        for (index, section_header) in self.section_header_table.iter().enumerate() {
             let section_name = Self::get_string_in_section(
                 self.elf_bytes,
                 section_names_section_header,
@@ -349,7 +1192,7 @@ impl<'a> Elf64<'a> {
                 SECTION_NAME_LENGTH_MAXIMUM,
             )?;
             section_header_by_name!(
-                self, section_header, section_name,
+                self, index, section_header, section_name,
                 b".symtab" => symbol_section_header,
                 b".strtab" => symbol_names_section_header,
                 b".dynstr" => dynamic_symbol_names_section_header,
@@ -359,7 +1202,7 @@ impl<'a> Elf64<'a> {
         Ok(())
     }
 
-    fn parse_dynamic(&mut self) -> Result<(), ElfParserError> {
+    fn parse_dynamic(&mut self, options: ElfParserOptions) -> Result<(), ElfParserError> {
         let mut dynamic_table: Option<&[Elf64Dyn]> = None;
 
         // try to parse PT_DYNAMIC
@@ -400,7 +1243,12 @@ impl<'a> Elf64<'a> {
                 break;
             }
 
-            if dyn_info.d_tag as usize >= DT_NUM {
+            if dyn_info.d_tag == DT_GNU_HASH {
+                self.gnu_hash_table_vaddr = Some(dyn_info.d_val);
+                continue;
+            }
+
+            if dyn_info.d_tag >= DT_NUM as Elf64Xword {
                 // we don't parse any reserved tags
                 continue;
             }
@@ -408,43 +1256,37 @@ impl<'a> Elf64<'a> {
         }
 
         self.dynamic_relocations_table = self.parse_dynamic_relocations()?;
+        self.dynamic_relocations_rela_table = self.parse_dynamic_relocations()?;
         self.dynamic_symbol_table = self.parse_dynamic_symbol_table()?;
+        if options.validate_dynamic_symbols {
+            self.validate_dynamic_symbols()?;
+        }
 
         Ok(())
     }
 
-    fn parse_dynamic_relocations(&mut self) -> Result<Option<&'a [Elf64Rel]>, ElfParserError> {
-        let vaddr = self.dynamic_table[DT_REL as usize];
+    fn parse_dynamic_relocations<T: RelocationEntry>(
+        &mut self,
+    ) -> Result<Option<&'a [T]>, ElfParserError> {
+        let vaddr = self.dynamic_table[T::TABLE_TAG as usize];
         if vaddr == 0 {
             return Ok(None);
         }
 
-        if self.dynamic_table[DT_RELENT as usize] as usize != mem::size_of::<Elf64Rel>() {
+        if self.dynamic_table[T::ENTRY_SIZE_TAG as usize] != mem::size_of::<T>() as u64 {
             return Err(ElfParserError::InvalidDynamicSectionTable);
         }
 
-        let size = self.dynamic_table[DT_RELSZ as usize] as usize;
+        let size = saturating_usize(self.dynamic_table[T::SIZE_TAG as usize]);
         if size == 0 {
             return Err(ElfParserError::InvalidDynamicSectionTable);
         }
 
-        let offset = if let Some(program_header) = self.program_header_for_vaddr(vaddr)? {
-            vaddr
-                .err_checked_sub(program_header.p_vaddr)?
-                .err_checked_add(program_header.p_offset)?
-        } else {
-            // At least until rust-bpf-sysroot v0.13, we used to generate
-            // invalid dynamic sections where the address of DT_REL was not
-            // contained in any program segment. When loading one of those
-            // files, fallback to relying on section headers.
-            self.section_header_table
-                .iter()
-                .find(|section_header| section_header.sh_addr == vaddr)
-                .ok_or(ElfParserError::InvalidDynamicSectionTable)?
-                .sh_offset
-        } as usize;
+        let (offset, _) = self
+            .file_location_of_vaddr(vaddr)?
+            .ok_or(ElfParserError::InvalidDynamicSectionTable)?;
 
-        Self::slice_from_bytes(self.elf_bytes, offset..offset.err_checked_add(size)?)
+        Self::slice_from_bytes(self.elf_bytes, checked_range(offset, size)?)
             .map(Some)
             .map_err(|_| ElfParserError::InvalidDynamicSectionTable)
     }
@@ -465,6 +1307,64 @@ impl<'a> Elf64<'a> {
             .map(Some)
     }
 
+    /// Checks that each dynamic symbol names a string of `.dynstr` and refers to a section
+    ///
+    /// Section indices from SHN_LORESERVE on, like SHN_ABS, are reserved and accepted.
+    fn validate_dynamic_symbols(&self) -> Result<(), ElfParserError> {
+        let string_table_size = self
+            .dynamic_symbol_names_section_header
+            .map(|section_header| section_header.sh_size)
+            .unwrap_or(0);
+        for (index, symbol) in self
+            .dynamic_symbol_table
+            .unwrap_or_default()
+            .iter()
+            .enumerate()
+        {
+            let valid_name = symbol.st_name == 0 || (symbol.st_name as u64) < string_table_size;
+            let valid_section = (symbol.st_shndx as usize) < self.section_header_table.len()
+                || symbol.st_shndx >= SHN_LORESERVE;
+            if !valid_name || !valid_section {
+                return Err(ElfParserError::InvalidDynamicSymbol { index });
+            }
+        }
+        Ok(())
+    }
+
+    /// Translates the `len` bytes at the virtual address `vaddr` to a range in the file
+    ///
+    /// If no program header contains `vaddr`, a section starting at `vaddr` is used instead, the
+    /// same way the dynamic relocation tables are located. Bytes which are not backed by the file,
+    /// such as the part of a segment beyond `p_filesz`, are [ElfParserError::OutOfBounds].
+    pub fn vaddr_to_file_offset(
+        &self,
+        vaddr: Elf64Addr,
+        len: usize,
+    ) -> Result<Range<usize>, ElfParserError> {
+        let (offset, available) =
+            self.file_location_of_vaddr(vaddr)?
+                .ok_or(ElfParserError::OutOfBounds {
+                    offset: saturating_usize(vaddr),
+                    len,
+                })?;
+        let range = checked_range(offset, len)?;
+        if len > available || range.end > self.elf_bytes.len() {
+            return Err(ElfParserError::OutOfBounds { offset, len });
+        }
+        Ok(range)
+    }
+
+    /// Returns the `len` bytes at the virtual address `vaddr`, see [Elf64::vaddr_to_file_offset]
+    pub fn bytes_at_vaddr(&self, vaddr: Elf64Addr, len: usize) -> Result<&'a [u8], ElfParserError> {
+        let range = self.vaddr_to_file_offset(vaddr, len)?;
+        self.elf_bytes
+            .get(range.clone())
+            .ok_or(ElfParserError::OutOfBounds {
+                offset: range.start,
+                len: range.len(),
+            })
+    }
+
     /// Query a single string from a section which is marked as SHT_STRTAB
     pub fn get_string_in_section(
         elf_bytes: &'a [u8],
@@ -473,17 +1373,26 @@ impl<'a> Elf64<'a> {
         maximum_length: usize,
     ) -> Result<&'a [u8], ElfParserError> {
         if section_header.sh_type != SHT_STRTAB {
-            return Err(ElfParserError::InvalidSectionHeader);
+            return Err(ElfParserError::InvalidSectionHeader {
+                index: Self::section_header_index(elf_bytes, section_header),
+            });
         }
-        let offset_in_file =
-            (section_header.sh_offset as usize).err_checked_add(offset_in_section as usize)?;
+        let section_range = checked_range(
+            saturating_usize(section_header.sh_offset),
+            saturating_usize(section_header.sh_size),
+        )?;
+        let offset_in_file = checked_range(section_range.start, offset_in_section as usize)?.end;
         let string_range = offset_in_file
-            ..(section_header.sh_offset as usize)
-                .err_checked_add(section_header.sh_size as usize)?
-                .min(offset_in_file.err_checked_add(maximum_length)?);
-        let unterminated_string_bytes = elf_bytes
-            .get(string_range)
-            .ok_or(ElfParserError::OutOfBounds)?;
+            ..section_range
+                .end
+                .min(offset_in_file.saturating_add(maximum_length));
+        let unterminated_string_bytes =
+            elf_bytes
+                .get(string_range.clone())
+                .ok_or(ElfParserError::OutOfBounds {
+                    offset: offset_in_file,
+                    len: string_range.len(),
+                })?;
         unterminated_string_bytes
             .iter()
             .position(|byte| *byte == 0x00)
@@ -536,13 +1445,103 @@ impl<'a> Elf64<'a> {
         )
     }
 
+    /// Returns the symbol called `name` in the symbol table
+    ///
+    /// Symbols with names longer than the maximum symbol name length never match.
+    pub fn lookup_symbol(&self, name: &[u8]) -> Result<Option<&'a Elf64Sym>, ElfParserError> {
+        let symbols = match self.symbol_table()? {
+            Some(symbols) => symbols,
+            None => return Ok(None),
+        };
+        let names = self.string_table_bytes(
+            self.symbol_names_section_header
+                .ok_or(ElfParserError::NoStringTable)?,
+        )?;
+        Ok(Self::find_symbol(symbols, names, name))
+    }
+
+    /// Returns the symbol called `name` in the dynamic symbol table
+    ///
+    /// Uses the DT_GNU_HASH table if there is one. Symbols with names longer than the maximum
+    /// symbol name length never match.
+    pub fn lookup_dynamic_symbol(
+        &self,
+        name: &[u8],
+    ) -> Result<Option<&'a Elf64Sym>, ElfParserError> {
+        let symbols = match self.dynamic_symbol_table {
+            Some(symbols) => symbols,
+            None => return Ok(None),
+        };
+        let names = self.string_table_bytes(
+            self.dynamic_symbol_names_section_header
+                .ok_or(ElfParserError::NoDynamicStringTable)?,
+        )?;
+        match self.gnu_hash_table()? {
+            Some(gnu_hash_table) => {
+                // The symbols before the hashed ones have to be searched linearly
+                let unhashed_symbols = symbols.get(..gnu_hash_table.symbol_offset()).ok_or(
+                    ElfParserError::OutOfBounds {
+                        offset: 0,
+                        len: gnu_hash_table.symbol_offset(),
+                    },
+                )?;
+                Ok(Self::find_symbol(unhashed_symbols, names, name)
+                    .or_else(|| gnu_hash_table.lookup(name, symbols, names)))
+            }
+            None => Ok(Self::find_symbol(symbols, names, name)),
+        }
+    }
+
+    /// Returns the symbols of the symbol table by name, for repeated lookups
+    ///
+    /// If several symbols share a name, the first one is kept, like in [Elf64::lookup_symbol].
+    /// Symbols with names which are too long or not terminated are left out.
+    pub fn symbol_map(&self) -> Result<BTreeMap<&'a [u8], &'a Elf64Sym>, ElfParserError> {
+        let symbols = match self.symbol_table()? {
+            Some(symbols) => symbols,
+            None => return Ok(BTreeMap::new()),
+        };
+        let names = self.string_table_bytes(
+            self.symbol_names_section_header
+                .ok_or(ElfParserError::NoStringTable)?,
+        )?;
+        let mut symbol_map = BTreeMap::new();
+        for symbol in symbols {
+            if let Some(name) = Self::symbol_name_in(names, symbol) {
+                symbol_map.entry(name).or_insert(symbol);
+            }
+        }
+        Ok(symbol_map)
+    }
+
+    /// Returns the functions the dynamic symbol table exports along with their names
+    ///
+    /// These are the global function symbols which are defined. Symbols with names which are
+    /// too long or not terminated are left out.
+    pub fn exported_functions(
+        &self,
+    ) -> Result<impl Iterator<Item = (&'a [u8], &'a Elf64Sym)> + 'a, ElfParserError> {
+        let symbols = self.dynamic_symbol_table.unwrap_or_default();
+        let names = match self.dynamic_symbol_names_section_header {
+            Some(section_header) => self.string_table_bytes(section_header)?,
+            None if symbols.is_empty() => &[],
+            None => return Err(ElfParserError::NoDynamicStringTable),
+        };
+        Ok(symbols
+            .iter()
+            .filter(|symbol| symbol.is_function() && symbol.is_global() && !symbol.is_undefined())
+            .filter_map(move |symbol| Some((Self::symbol_name_in(names, symbol)?, symbol))))
+    }
+
     /// Returns the symbol table of a section which is marked as SHT_SYMTAB
     pub fn get_symbol_table_of_section(
         &self,
         section_header: &Elf64Shdr,
     ) -> Result<&'a [Elf64Sym], ElfParserError> {
         if section_header.sh_type != SHT_SYMTAB && section_header.sh_type != SHT_DYNSYM {
-            return Err(ElfParserError::InvalidSectionHeader);
+            return Err(ElfParserError::InvalidSectionHeader {
+                index: Self::section_header_index(self.elf_bytes, section_header),
+            });
         }
 
         Self::slice_from_section_header(self.elf_bytes, section_header)
@@ -558,7 +1557,7 @@ impl<'a> Elf64<'a> {
     ) -> Result<&'a [T], ElfParserError> {
         Self::slice_from_bytes(
             bytes,
-            (p_offset as usize)..(p_offset as usize).err_checked_add(p_filesz as usize)?,
+            checked_range(saturating_usize(p_offset), saturating_usize(p_filesz))?,
         )
     }
 
@@ -572,7 +1571,7 @@ impl<'a> Elf64<'a> {
     ) -> Result<&'a [T], ElfParserError> {
         Self::slice_from_bytes(
             bytes,
-            (sh_offset as usize)..(sh_offset as usize).err_checked_add(sh_size as usize)?,
+            checked_range(saturating_usize(sh_offset), saturating_usize(sh_size))?,
         )
     }
 
@@ -592,7 +1591,10 @@ impl<'a> Elf64<'a> {
 
         let bytes = bytes
             .get(range.clone())
-            .ok_or(ElfParserError::OutOfBounds)?;
+            .ok_or(ElfParserError::OutOfBounds {
+                offset: range.start,
+                len: range.len(),
+            })?;
 
         let ptr = bytes.as_ptr();
         if (ptr as usize)
@@ -611,6 +1613,103 @@ impl<'a> Elf64<'a> {
         })
     }
 
+    /// Returns the file offset of `vaddr` and the number of bytes from there on which are
+    /// backed by the file.
+    fn file_location_of_vaddr(
+        &self,
+        vaddr: Elf64Addr,
+    ) -> Result<Option<(usize, usize)>, ElfParserError> {
+        if let Some(program_header) = self.program_header_for_vaddr(vaddr)? {
+            // The program header contains vaddr, so this does not underflow
+            let offset_in_segment = vaddr.saturating_sub(program_header.p_vaddr);
+            return Ok(Some((
+                checked_range(
+                    saturating_usize(program_header.p_offset),
+                    saturating_usize(offset_in_segment),
+                )?
+                .end,
+                saturating_usize(program_header.p_filesz.saturating_sub(offset_in_segment)),
+            )));
+        }
+        // At least until rust-bpf-sysroot v0.13, we used to generate
+        // invalid dynamic sections where the address of DT_REL was not
+        // contained in any program segment. When loading one of those
+        // files, fallback to relying on section headers.
+        Ok(self
+            .section_header_table
+            .iter()
+            .find(|section_header| section_header.sh_addr == vaddr)
+            .map(|section_header| {
+                (
+                    saturating_usize(section_header.sh_offset),
+                    if section_header.sh_type == SHT_NOBITS {
+                        0
+                    } else {
+                        saturating_usize(section_header.sh_size)
+                    },
+                )
+            }))
+    }
+
+    /// Returns the contents of a section which is marked as SHT_STRTAB
+    fn string_table_bytes(&self, section_header: &Elf64Shdr) -> Result<&'a [u8], ElfParserError> {
+        if section_header.sh_type != SHT_STRTAB {
+            return Err(ElfParserError::InvalidSectionHeader {
+                index: Self::section_header_index(self.elf_bytes, section_header),
+            });
+        }
+        section_header
+            .file_range()
+            .and_then(|range| self.elf_bytes.get(range))
+            .ok_or(ElfParserError::OutOfBounds {
+                offset: saturating_usize(section_header.sh_offset),
+                len: saturating_usize(section_header.sh_size),
+            })
+    }
+
+    /// Returns the index of `section_header` in the section header table of `elf_bytes`
+    ///
+    /// Only used for error reporting, the section headers of an [Elf64] point into its bytes.
+    fn section_header_index(elf_bytes: &[u8], section_header: &Elf64Shdr) -> usize {
+        let e_shoff = Elf64::parse_file_header(elf_bytes)
+            .map(|(_range, file_header)| saturating_usize(file_header.e_shoff))
+            .unwrap_or(0);
+        (section_header as *const Elf64Shdr as usize)
+            .saturating_sub(elf_bytes.as_ptr() as usize)
+            .saturating_sub(e_shoff)
+            .checked_div(mem::size_of::<Elf64Shdr>())
+            .unwrap_or(0)
+    }
+
+    /// Returns the name of `symbol` in the string table `names`, the same way
+    /// [Elf64::get_string_in_section] would, without failing
+    fn symbol_name_in(names: &'a [u8], symbol: &Elf64Sym) -> Option<&'a [u8]> {
+        let offset = symbol.st_name as usize;
+        let unterminated_string_bytes = names.get(
+            offset
+                ..names
+                    .len()
+                    .min(offset.saturating_add(SYMBOL_NAME_LENGTH_MAXIMUM)),
+        )?;
+        unterminated_string_bytes
+            .iter()
+            .position(|byte| *byte == 0x00)
+            .and_then(|string_length| unterminated_string_bytes.get(0..string_length))
+    }
+
+    fn find_symbol(symbols: &'a [Elf64Sym], names: &'a [u8], name: &[u8]) -> Option<&'a Elf64Sym> {
+        if name.len() >= SYMBOL_NAME_LENGTH_MAXIMUM {
+            return None;
+        }
+        symbols.iter().find(|symbol| {
+            names
+                .get(symbol.st_name as usize..)
+                .and_then(|string_bytes| string_bytes.strip_prefix(name))
+                .and_then(|rest| rest.first())
+                == Some(&0x00)
+        })
+    }
+
     fn program_header_for_vaddr(
         &self,
         vaddr: Elf64Addr,
@@ -620,7 +1719,13 @@ impl<'a> Elf64<'a> {
                 p_vaddr, p_memsz, ..
             } = program_header;
 
-            if (*p_vaddr..p_vaddr.err_checked_add(*p_memsz)?).contains(&vaddr) {
+            let end = p_vaddr
+                .checked_add(*p_memsz)
+                .ok_or(ElfParserError::OutOfBounds {
+                    offset: saturating_usize(*p_vaddr),
+                    len: saturating_usize(*p_memsz),
+                })?;
+            if (*p_vaddr..end).contains(&vaddr) {
                 return Ok(Some(program_header));
             }
         }
@@ -628,6 +1733,319 @@ impl<'a> Elf64<'a> {
     }
 }
 
+/// Converts the headers and tables of the little endian `elf_bytes` to the native byte order
+///
+/// [Elf64::parse] casts them in place, so big endian hosts need to convert a copy first, see
+/// [AlignedElfBytes]. The tables are located the way the parser finds them: through the file
+/// header, the section and program headers and the entries of the dynamic section. All other
+/// bytes, like the text and the string tables, are kept. Tables extending beyond `elf_bytes`
+/// or overlapping a table converted before are skipped, the former are left for
+/// [Elf64::parse] to reject.
+#[cfg_attr(target_endian = "little", allow(dead_code))]
+pub(crate) fn swap_byte_order(elf_bytes: &mut [u8]) {
+    let mut converter = ByteOrderConverter {
+        elf_bytes,
+        converted: Vec::new(),
+    };
+
+    let file_header_range = table_range::<Elf64Ehdr>(0, 1);
+    let file_header = match converter
+        .read::<Elf64Ehdr>(file_header_range.clone())
+        .and_then(|mut file_header| file_header.pop())
+    {
+        Some(file_header) => file_header,
+        None => return,
+    };
+    converter.convert::<Elf64Ehdr>(file_header_range);
+
+    let program_header_table_range =
+        table_range::<Elf64Phdr>(file_header.e_phoff, file_header.e_phnum as usize);
+    let program_header_table = converter
+        .read::<Elf64Phdr>(program_header_table_range.clone())
+        .unwrap_or_default();
+    converter.convert::<Elf64Phdr>(program_header_table_range);
+
+    // Files with SHN_LORESERVE or more sections store the count in the first section header
+    let section_count = if file_header.e_shnum == 0 && file_header.e_shoff != 0 {
+        converter
+            .read::<Elf64Shdr>(table_range::<Elf64Shdr>(file_header.e_shoff, 1))
+            .and_then(|section_headers| section_headers.first().cloned())
+            .map(|section_header| saturating_usize(section_header.sh_size))
+            .unwrap_or(0)
+    } else {
+        file_header.e_shnum as usize
+    };
+    let section_header_table_range = table_range::<Elf64Shdr>(file_header.e_shoff, section_count);
+    let section_header_table = converter
+        .read::<Elf64Shdr>(section_header_table_range.clone())
+        .unwrap_or_default();
+    converter.convert::<Elf64Shdr>(section_header_table_range);
+
+    // Same as Elf64::parse_dynamic, which falls back to SHT_DYNAMIC
+    let dynamic_table = program_header_table
+        .iter()
+        .find(|program_header| program_header.p_type == PT_DYNAMIC)
+        .and_then(|program_header| {
+            converter.read::<Elf64Dyn>(table_range::<u8>(
+                program_header.p_offset,
+                saturating_usize(program_header.p_filesz),
+            ))
+        })
+        .or_else(|| {
+            section_header_table
+                .iter()
+                .find(|section_header| section_header.sh_type == SHT_DYNAMIC)
+                .and_then(|section_header| {
+                    converter.read::<Elf64Dyn>(table_range::<u8>(
+                        section_header.sh_offset,
+                        saturating_usize(section_header.sh_size),
+                    ))
+                })
+        })
+        .unwrap_or_default();
+
+    for section_header in section_header_table.iter() {
+        let range = match section_header.file_range() {
+            Some(range) => range,
+            None => continue,
+        };
+        match section_header.sh_type {
+            SHT_SYMTAB | SHT_DYNSYM => converter.convert::<Elf64Sym>(range),
+            SHT_DYNAMIC => converter.convert::<Elf64Dyn>(range),
+            _ => continue,
+        }
+    }
+    let strict_layout = matches!(
+        sbpf_version_from_e_flags(file_header.e_flags),
+        Ok(sbpf_version) if sbpf_version.enable_stricter_elf_headers()
+    );
+    for (index, program_header) in program_header_table.iter().enumerate() {
+        let range = table_range::<u8>(
+            program_header.p_offset,
+            saturating_usize(program_header.p_filesz),
+        );
+        match program_header.p_type {
+            PT_DYNAMIC => converter.convert::<Elf64Dyn>(range),
+            // The stricter layout locates the dynamic symbol table through the fifth one
+            PT_NULL if index == 4 && strict_layout => converter.convert::<Elf64Sym>(range),
+            _ => continue,
+        }
+    }
+
+    let dynamic_value = |tag: Elf64Xword| {
+        dynamic_table
+            .iter()
+            .take_while(|dyn_info| dyn_info.d_tag != DT_NULL)
+            .filter(|dyn_info| dyn_info.d_tag == tag)
+            .last()
+            .map(|dyn_info| dyn_info.d_val)
+    };
+    // Same as Elf64::file_location_of_vaddr
+    let file_location_of_vaddr = |vaddr: Elf64Addr| {
+        if let Some(program_header) = program_header_table
+            .iter()
+            .find(|program_header| program_header.vm_range().contains(&vaddr))
+        {
+            let offset_in_segment = vaddr.saturating_sub(program_header.p_vaddr);
+            return Some((
+                saturating_usize(program_header.p_offset.saturating_add(offset_in_segment)),
+                saturating_usize(program_header.p_filesz.saturating_sub(offset_in_segment)),
+            ));
+        }
+        section_header_table
+            .iter()
+            .find(|section_header| section_header.sh_addr == vaddr)
+            .map(|section_header| {
+                (
+                    saturating_usize(section_header.sh_offset),
+                    saturating_usize(section_header.sh_size),
+                )
+            })
+    };
+    for (table_tag, size_tag, is_rela) in [(DT_REL, DT_RELSZ, false), (DT_RELA, DT_RELASZ, true)] {
+        let (offset, _) = match dynamic_value(table_tag)
+            .filter(|vaddr| *vaddr != 0)
+            .and_then(file_location_of_vaddr)
+        {
+            Some(location) => location,
+            None => continue,
+        };
+        let size = saturating_usize(dynamic_value(size_tag).unwrap_or(0));
+        let range = offset..offset.saturating_add(size);
+        if is_rela {
+            converter.convert::<Elf64Rela>(range);
+        } else {
+            converter.convert::<Elf64Rel>(range);
+        }
+    }
+    // The chain array of the DT_GNU_HASH table has one entry per hashed dynamic symbol
+    let symbol_count = dynamic_value(DT_SYMTAB)
+        .filter(|vaddr| *vaddr != 0)
+        .and_then(|vaddr| {
+            section_header_table
+                .iter()
+                .find(|section_header| section_header.sh_addr == vaddr)
+        })
+        .map(|section_header| {
+            saturating_usize(section_header.sh_size)
+                .checked_div(mem::size_of::<Elf64Sym>())
+                .unwrap_or(0)
+        })
+        .unwrap_or(0);
+    if let Some((offset, available)) = dynamic_value(DT_GNU_HASH).and_then(file_location_of_vaddr) {
+        converter.convert_gnu_hash_table(offset, available, symbol_count);
+    }
+}
+
+/// Returns the file range of `count` entries of type `T` at `offset`
+fn table_range<T>(offset: u64, count: usize) -> Range<usize> {
+    let offset = saturating_usize(offset);
+    offset..offset.saturating_add(count.saturating_mul(mem::size_of::<T>()))
+}
+
+/// Converts tables to the native byte order, see [swap_byte_order]
+struct ByteOrderConverter<'a> {
+    elf_bytes: &'a mut [u8],
+    /// The file ranges of the tables converted so far
+    converted: Vec<Range<usize>>,
+}
+
+impl ByteOrderConverter<'_> {
+    /// Records `range` as converted, unless it is out of bounds or was (partly) converted before
+    fn claim(&mut self, range: Range<usize>) -> bool {
+        if range.start > range.end
+            || range.end > self.elf_bytes.len()
+            || self
+                .converted
+                .iter()
+                .any(|converted| converted.start < range.end && range.start < converted.end)
+        {
+            return false;
+        }
+        self.converted.push(range);
+        true
+    }
+
+    /// Reverses the byte order of the entries in `range`
+    fn swap<T: SwapBytes>(&mut self, range: Range<usize>) {
+        let table = match self.elf_bytes.get_mut(range) {
+            Some(table) => table,
+            None => return,
+        };
+        for entry in table.chunks_exact_mut(mem::size_of::<T>()) {
+            let entry = entry.as_mut_ptr().cast::<T>();
+            // SAFETY: `entry` points to `size_of::<T>()` bytes and `T` is plain old data
+            unsafe {
+                let mut value = entry.read_unaligned();
+                value.swap_bytes();
+                entry.write_unaligned(value);
+            }
+        }
+    }
+
+    /// Returns the little endian entries in `range`, unless [Elf64::slice_from_bytes] fails
+    ///
+    /// They must be read before `range` is converted.
+    fn read<T: SwapBytes + Clone + 'static>(&self, range: Range<usize>) -> Option<Vec<T>> {
+        let entries = Elf64::slice_from_bytes::<T>(self.elf_bytes, range).ok()?;
+        Some(
+            entries
+                .iter()
+                .map(|entry| {
+                    let mut entry = entry.clone();
+                    if cfg!(target_endian = "big") {
+                        entry.swap_bytes();
+                    }
+                    entry
+                })
+                .collect(),
+        )
+    }
+
+    /// Converts the table of entries of type `T` in `range`
+    fn convert<T: SwapBytes>(&mut self, range: Range<usize>) {
+        if self.claim(range.clone()) {
+            self.swap::<T>(range);
+        }
+    }
+
+    /// Converts the DT_GNU_HASH table at `offset`, see [GnuHashTable::parse] for its layout
+    fn convert_gnu_hash_table(&mut self, offset: usize, available: usize, symbol_count: usize) {
+        const HEADER_SIZE: usize = mem::size_of::<[u32; 4]>();
+        let header_range = offset..offset.saturating_add(HEADER_SIZE);
+        let mut header = match self.elf_bytes.get(header_range.clone()) {
+            Some(bytes) => reader::Reader::new(bytes),
+            None => return,
+        };
+        let mut header_word = || saturating_usize(header.uint(4).unwrap_or(0));
+        let (bucket_count, symbol_offset, bloom_size) =
+            (header_word(), header_word(), header_word());
+        let chain_count = symbol_count.saturating_sub(symbol_offset);
+        let bloom_range = header_range.end
+            ..header_range
+                .end
+                .saturating_add(bloom_size.saturating_mul(mem::size_of::<u64>()));
+        let words_range = bloom_range.end
+            ..bloom_range.end.saturating_add(
+                bucket_count
+                    .saturating_add(chain_count)
+                    .saturating_mul(mem::size_of::<u32>()),
+            );
+        let range = offset..words_range.end.min(offset.saturating_add(available));
+        if self.claim(range.clone()) {
+            self.swap::<u32>(header_range);
+            self.swap::<u64>(bloom_range.start..bloom_range.end.min(range.end));
+            self.swap::<u32>(words_range.start..words_range.end.min(range.end));
+        }
+    }
+}
+
+/// ELF file bytes which can be parsed regardless of the alignment of the input
+///
+/// [Elf64::parse] casts the headers and tables in place and fails with
+/// [ElfParserError::InvalidAlignment] if the input is not suitably aligned. This copies such
+/// input into aligned storage first and borrows aligned input as is.
+///
+/// On big endian hosts the input is always copied and its headers and tables are converted to
+/// the native byte order. Parsing the input directly fails there with
+/// [ElfParserError::InvalidFileHeader], as the header sizes do not match.
+pub enum AlignedElfBytes<'a> {
+    /// The input was aligned
+    Borrowed(&'a [u8]),
+    /// Aligned copy of the input
+    Copied(AlignedMemory<HOST_ALIGN>),
+}
+
+impl<'a> AlignedElfBytes<'a> {
+    /// Borrows or copies `elf_bytes`, depending on their alignment
+    pub fn new(elf_bytes: &'a [u8]) -> Self {
+        if cfg!(target_endian = "little")
+            && is_memory_aligned(elf_bytes.as_ptr() as usize, mem::align_of::<Elf64Ehdr>())
+        {
+            Self::Borrowed(elf_bytes)
+        } else {
+            #[allow(unused_mut)]
+            let mut aligned_memory = AlignedMemory::from_slice(elf_bytes);
+            #[cfg(target_endian = "big")]
+            swap_byte_order(aligned_memory.as_slice_mut());
+            Self::Copied(aligned_memory)
+        }
+    }
+
+    /// Returns the aligned bytes
+    pub fn as_slice(&self) -> &[u8] {
+        match self {
+            Self::Borrowed(elf_bytes) => elf_bytes,
+            Self::Copied(aligned_memory) => aligned_memory.as_slice(),
+        }
+    }
+
+    /// Parses the aligned bytes, see [Elf64::parse]
+    pub fn parse(&self) -> Result<Elf64<'_>, ElfParserError> {
+        Elf64::parse(self.as_slice())
+    }
+}
+
 impl fmt::Debug for Elf64<'_> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         writeln!(f, "{:#X?}", self.file_header)?;
@@ -669,8 +2087,324 @@ impl fmt::Debug for Elf64<'_> {
     }
 }
 
-impl From<ArithmeticOverflow> for ElfParserError {
-    fn from(_: ArithmeticOverflow) -> ElfParserError {
-        ElfParserError::OutOfBounds
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::vec::Vec;
+    use test_utils::elf_fixtures;
+
+    #[test]
+    fn test_section_headers_corrupt_sh_name() {
+        let elf_bytes = elf_fixtures::create_elf_with_call_relocations(1, 1, &[]).elf_bytes;
+        let elf = Elf64::parse(&elf_bytes).unwrap();
+        let names = elf
+            .section_headers()
+            .map(|(name, _)| name.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                &b""[..],
+                b".text",
+                b".dynamic",
+                b".dynsym",
+                b".dynstr",
+                b".rel.dyn",
+                b".shstrtab"
+            ]
+        );
+        assert_eq!(
+            elf.find_section(b".dynsym").map(|header| header.sh_type),
+            Some(SHT_DYNSYM)
+        );
+        assert!(elf.find_section(b".data").is_none());
+
+        // Elf64::parse() rejects unresolvable section names, so swap the corrupt section
+        // header table into an already parsed file.
+        let mut corrupt_bytes = elf_bytes.clone();
+        let dynamic_sh_name_offset =
+            elf.file_header().e_shoff as usize + 2 * mem::size_of::<Elf64Shdr>();
+        corrupt_bytes[dynamic_sh_name_offset..dynamic_sh_name_offset + 4]
+            .copy_from_slice(&u32::MAX.to_le_bytes());
+        let section_header_table = Elf64::slice_from_bytes::<Elf64Shdr>(
+            &corrupt_bytes,
+            elf.file_header().e_shoff as usize
+                ..elf.file_header().e_shoff as usize + mem::size_of_val(elf.section_header_table()),
+        )
+        .unwrap();
+        let corrupt_elf = Elf64 {
+            elf_bytes: &corrupt_bytes,
+            section_header_table,
+            section_names_section_header: section_header_table
+                .get(elf.file_header().e_shstrndx as usize),
+            ..elf
+        };
+
+        let sections = corrupt_elf.section_headers().collect::<Vec<_>>();
+        assert_eq!(sections.len(), 7);
+        assert_eq!(sections[1].0, Ok(&b".text"[..]));
+        assert!(matches!(
+            sections[2].0,
+            Err(ElfParserError::OutOfBounds { offset, len })
+                if offset.saturating_add(len) > corrupt_bytes.len()
+        ));
+        assert_eq!(sections[2].1.sh_type, SHT_DYNAMIC);
+        assert_eq!(sections[3].0, Ok(&b".dynsym"[..]));
+        assert_eq!(sections[6].0, Ok(&b".shstrtab"[..]));
+        assert!(corrupt_elf.find_section(b".dynamic").is_none());
+        assert_eq!(
+            corrupt_elf
+                .find_section(b".rel.dyn")
+                .map(|header| header.sh_type),
+            Some(SHT_REL)
+        );
+    }
+
+    #[test]
+    fn test_load_segments() {
+        let mut elf_bytes = elf_fixtures::create_elf_with_call_relocations(1, 1, &[]).elf_bytes;
+        let elf = Elf64::parse(&elf_bytes).unwrap();
+        let program_headers = elf.program_header_table();
+        let (text, data) = (&program_headers[0], &program_headers[1]);
+        assert!(text.is_readable() && text.is_executable() && !text.is_writable());
+        assert!(data.is_readable() && !data.is_executable() && data.is_writable());
+        let text_section = elf.find_section(b".text").unwrap();
+        assert!(text_section.is_executable() && !text_section.is_writable());
+        let dynamic_section = elf.find_section(b".dynamic").unwrap();
+        assert!(!dynamic_section.is_executable() && dynamic_section.is_writable());
+        let segments = elf.load_segments().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(
+            segments[0].1,
+            &elf_bytes[text.p_offset as usize..(text.p_offset + text.p_filesz) as usize]
+        );
+        let data_memsz = data.p_memsz;
+
+        // No file bytes for the text, fewer file bytes than memory for the data
+        let p_filesz_offset = mem::size_of::<Elf64Ehdr>() + 32;
+        elf_bytes[p_filesz_offset..p_filesz_offset + 8].copy_from_slice(&0u64.to_le_bytes());
+        let p_filesz_offset = p_filesz_offset + mem::size_of::<Elf64Phdr>();
+        elf_bytes[p_filesz_offset..p_filesz_offset + 8]
+            .copy_from_slice(&(data_memsz - 8).to_le_bytes());
+        let elf = Elf64::parse(&elf_bytes).unwrap();
+        let segments = elf.load_segments().collect::<Result<Vec<_>, _>>().unwrap();
+        assert!(segments[0].1.is_empty());
+        assert_eq!(segments[1].0.p_memsz, data_memsz);
+        assert_eq!(segments[1].1.len() as u64, data_memsz - 8);
+    }
+
+    #[test]
+    fn test_symbol_accessors() {
+        let symbol = |st_info: u8, st_other: u8, st_shndx: Elf64Section| Elf64Sym {
+            st_name: 0,
+            st_info,
+            st_other,
+            st_shndx,
+            st_value: 0,
+            st_size: 0,
+        };
+        for (st_info, symbol_type, binding) in [
+            (0x00, SymbolType::NoType, SymbolBinding::Local),
+            (0x01, SymbolType::Object, SymbolBinding::Local),
+            (0x12, SymbolType::Function, SymbolBinding::Global),
+            (0x03, SymbolType::Section, SymbolBinding::Local),
+            (0x04, SymbolType::File, SymbolBinding::Local),
+            (0x22, SymbolType::Function, SymbolBinding::Weak),
+            (0x26, SymbolType::Other(STT_TLS), SymbolBinding::Weak),
+            (0xa2, SymbolType::Function, SymbolBinding::Other(10)),
+        ] {
+            let symbol = symbol(st_info, 0, 1);
+            assert_eq!(symbol.symbol_type(), symbol_type);
+            assert_eq!(symbol.binding(), binding);
+            assert_eq!(symbol.is_global(), binding == SymbolBinding::Global);
+            assert_eq!(symbol.is_weak(), binding == SymbolBinding::Weak);
+            assert_eq!(symbol.is_function(), symbol_type == SymbolType::Function);
+        }
+        for (st_other, visibility) in [
+            (STV_DEFAULT, SymbolVisibility::Default),
+            (STV_INTERNAL, SymbolVisibility::Internal),
+            (STV_HIDDEN, SymbolVisibility::Hidden),
+            (STV_PROTECTED, SymbolVisibility::Protected),
+            // The upper bits are not part of the visibility
+            (0xf8 | STV_HIDDEN, SymbolVisibility::Hidden),
+        ] {
+            assert_eq!(symbol(0x12, st_other, 1).visibility(), visibility);
+        }
+
+        let undefined_global = symbol(0x12, 0, SHN_UNDEF);
+        let undefined_weak = symbol(0x22, 0, SHN_UNDEF);
+        assert!(undefined_global.is_undefined() && undefined_global.is_global());
+        assert!(undefined_weak.is_undefined() && undefined_weak.is_weak());
+        assert!(!undefined_weak.is_global());
+        assert!(!symbol(0x12, 0, 1).is_undefined());
+        assert!(!symbol(0x12, 0, SHN_ABS).is_undefined());
+    }
+
+    #[test]
+    fn test_swap_byte_order() {
+        let elf_bytes = elf_fixtures::create_elf_with_call_relocations(1, 1, &[]).elf_bytes;
+        let elf = Elf64::parse(&elf_bytes).unwrap();
+        let mut swapped = AlignedMemory::<{ HOST_ALIGN }>::from_slice(&elf_bytes);
+        swap_byte_order(swapped.as_slice_mut());
+        let swapped = swapped.as_slice();
+
+        // The string tables and the text keep their bytes
+        let text = elf.find_section(b".text").unwrap();
+        assert_eq!(
+            elf.section_bytes(text).unwrap(),
+            &swapped[text.file_range().unwrap()]
+        );
+        let dynstr = elf.find_section(b".dynstr").unwrap();
+        assert_eq!(
+            elf.section_bytes(dynstr).unwrap(),
+            &swapped[dynstr.file_range().unwrap()]
+        );
+
+        let file_header =
+            &Elf64::slice_from_bytes::<Elf64Ehdr>(swapped, 0..mem::size_of::<Elf64Ehdr>()).unwrap()
+                [0];
+        assert_eq!(file_header.e_ident.ei_mag, ELFMAG);
+        assert_eq!(
+            file_header.e_machine,
+            elf.file_header().e_machine.swap_bytes()
+        );
+        assert_eq!(file_header.e_shoff, elf.file_header().e_shoff.swap_bytes());
+        assert_eq!(file_header.e_shnum, elf.file_header().e_shnum.swap_bytes());
+
+        let table_range = |offset: u64, len: usize| offset as usize..offset as usize + len;
+        let program_headers = Elf64::slice_from_bytes::<Elf64Phdr>(
+            swapped,
+            table_range(
+                elf.file_header().e_phoff,
+                mem::size_of_val(elf.program_header_table()),
+            ),
+        )
+        .unwrap();
+        for (swapped, original) in program_headers.iter().zip(elf.program_header_table()) {
+            assert_eq!(swapped.p_type, original.p_type.swap_bytes());
+            assert_eq!(swapped.p_offset, original.p_offset.swap_bytes());
+            assert_eq!(swapped.p_memsz, original.p_memsz.swap_bytes());
+        }
+        let section_headers = Elf64::slice_from_bytes::<Elf64Shdr>(
+            swapped,
+            table_range(
+                elf.file_header().e_shoff,
+                mem::size_of_val(elf.section_header_table()),
+            ),
+        )
+        .unwrap();
+        for (swapped, original) in section_headers.iter().zip(elf.section_header_table()) {
+            assert_eq!(swapped.sh_name, original.sh_name.swap_bytes());
+            assert_eq!(swapped.sh_type, original.sh_type.swap_bytes());
+            assert_eq!(swapped.sh_size, original.sh_size.swap_bytes());
+        }
+        let dynsym = elf.find_section(b".dynsym").unwrap();
+        let symbols =
+            Elf64::slice_from_bytes::<Elf64Sym>(swapped, dynsym.file_range().unwrap()).unwrap();
+        let original_symbols = elf.dynamic_symbol_table().unwrap();
+        assert!(!original_symbols.is_empty());
+        for (swapped, original) in symbols.iter().zip(original_symbols) {
+            assert_eq!(swapped.st_name, original.st_name.swap_bytes());
+            assert_eq!(swapped.st_info, original.st_info);
+            assert_eq!(swapped.st_value, original.st_value.swap_bytes());
+        }
+        let rel_dyn = elf.find_section(b".rel.dyn").unwrap();
+        let relocations =
+            Elf64::slice_from_bytes::<Elf64Rel>(swapped, rel_dyn.file_range().unwrap()).unwrap();
+        for (swapped, original) in relocations
+            .iter()
+            .zip(elf.dynamic_relocations_table().unwrap())
+        {
+            assert_eq!(swapped.r_info, original.r_info.swap_bytes());
+        }
+    }
+
+    #[test]
+    fn test_swap_byte_order_dynamic_tables() {
+        let elf_bytes = elf_fixtures::create_elf_with_gnu_hash(1, 100, &["log"]).elf_bytes;
+        let mut swapped = AlignedMemory::<{ HOST_ALIGN }>::from_slice(&elf_bytes);
+        swap_byte_order(swapped.as_slice_mut());
+        let swapped = swapped.as_slice();
+        let elf = Elf64::parse(&elf_bytes).unwrap();
+
+        // The DT_GNU_HASH table has 32 bit words, except for the 64 bit bloom filter
+        let gnu_hash = elf
+            .find_section(b".gnu.hash")
+            .unwrap()
+            .file_range()
+            .unwrap();
+        let bloom_start = gnu_hash.start + 16;
+        let bloom_size = u32::from_le_bytes(
+            <[u8; 4]>::try_from(&elf_bytes[gnu_hash.start + 8..gnu_hash.start + 12]).unwrap(),
+        ) as usize;
+        let bloom_end = bloom_start + bloom_size * 8;
+        let mut expected = Vec::new();
+        for (range, word_size) in [
+            (gnu_hash.start..bloom_start, 4),
+            (bloom_start..bloom_end, 8),
+            (bloom_end..gnu_hash.end, 4),
+        ] {
+            for word in elf_bytes[range].chunks(word_size) {
+                expected.extend(word.iter().rev());
+            }
+        }
+        assert_eq!(&swapped[gnu_hash], &expected[..]);
+
+        // DT_REL is found even if its section is not marked as SHT_REL
+        let rel_dyn = elf.find_section(b".rel.dyn").unwrap();
+        let rel_dyn_range = rel_dyn.file_range().unwrap();
+        let relocations = elf.dynamic_relocations_table().unwrap().to_vec();
+        let sh_type_offset = rel_dyn as *const Elf64Shdr as usize - elf_bytes.as_ptr() as usize + 4;
+        let mut elf_bytes = elf_bytes;
+        elf_bytes[sh_type_offset..sh_type_offset + 4].copy_from_slice(&SHT_PROGBITS.to_le_bytes());
+        let mut swapped = AlignedMemory::<{ HOST_ALIGN }>::from_slice(&elf_bytes);
+        swap_byte_order(swapped.as_slice_mut());
+        let swapped_relocations =
+            Elf64::slice_from_bytes::<Elf64Rel>(swapped.as_slice(), rel_dyn_range).unwrap();
+        assert!(!relocations.is_empty());
+        for (swapped, original) in swapped_relocations.iter().zip(relocations.iter()) {
+            assert_eq!(swapped.r_offset, original.r_offset.swap_bytes());
+            assert_eq!(swapped.r_info, original.r_info.swap_bytes());
+        }
+    }
+
+    #[test]
+    fn test_swap_byte_order_strict_layout() {
+        let elf_bytes = AlignedMemory::<{ HOST_ALIGN }>::from_slice(include_bytes!(
+            "../../tests/elfs/strict_header.so"
+        ));
+        let mut swapped = elf_bytes.clone();
+        swap_byte_order(swapped.as_slice_mut());
+        let program_header_table_range = table_range::<Elf64Phdr>(64, 5);
+        let program_headers = Elf64::slice_from_bytes::<Elf64Phdr>(
+            elf_bytes.as_slice(),
+            program_header_table_range.clone(),
+        )
+        .unwrap();
+        let swapped_program_headers =
+            Elf64::slice_from_bytes::<Elf64Phdr>(swapped.as_slice(), program_header_table_range)
+                .unwrap();
+        assert_eq!(swapped_program_headers[4].p_type, PT_NULL);
+
+        // The dynamic symbol table is located through the fifth program header
+        let symbols =
+            Elf64::slice_from_program_header::<Elf64Sym>(elf_bytes.as_slice(), &program_headers[4])
+                .unwrap();
+        let swapped_symbols =
+            Elf64::slice_from_program_header::<Elf64Sym>(swapped.as_slice(), &program_headers[4])
+                .unwrap();
+        assert!(!symbols.is_empty());
+        for (swapped, original) in swapped_symbols.iter().zip(symbols) {
+            assert_eq!(swapped.st_name, original.st_name.swap_bytes());
+            assert_eq!(swapped.st_value, original.st_value.swap_bytes());
+            assert_eq!(swapped.st_size, original.st_size.swap_bytes());
+        }
+
+        // The text keeps its bytes
+        let text = program_headers[0].file_range().unwrap();
+        assert_eq!(
+            &swapped.as_slice()[text.clone()],
+            &elf_bytes.as_slice()[text]
+        );
     }
 }
@@ -0,0 +1,73 @@
+//! Notes of the SHT_NOTE sections and PT_NOTE segments
+
+use super::{reader::Reader, types::Elf64Word, ElfParserError};
+
+/// A note, like the NT_GNU_BUILD_ID one stamped by the linker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Note<'a> {
+    /// Owner of the note, without the NUL terminator
+    pub name: &'a [u8],
+    /// Type of the note, its meaning depends on the name
+    pub n_type: Elf64Word,
+    /// Descriptor of the note
+    pub desc: &'a [u8],
+}
+
+/// Iterator over the notes of a note section or segment
+///
+/// Stops after the first error.
+#[derive(Debug)]
+pub struct Notes<'a> {
+    reader: Reader<'a>,
+    failed: bool,
+}
+
+impl<'a> Notes<'a> {
+    /// Iterates the notes in `bytes`
+    ///
+    /// Names and descriptors are padded to 4 bytes, as the linkers do for ELF64 files too.
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            reader: Reader::new(bytes),
+            failed: false,
+        }
+    }
+
+    /// Skips the padding after a name or descriptor, the last one may be unpadded
+    fn skip_padding(&mut self) {
+        let padding = self.reader.position.wrapping_neg() & 3;
+        self.reader.position = self
+            .reader
+            .position
+            .saturating_add(padding)
+            .min(self.reader.bytes.len());
+    }
+
+    fn note(&mut self) -> Result<Note<'a>, ElfParserError> {
+        let namesz = self.reader.uint(4)? as usize;
+        let descsz = self.reader.uint(4)? as usize;
+        let n_type = self.reader.uint(4)? as Elf64Word;
+        let name = self.reader.bytes(namesz)?;
+        self.skip_padding();
+        let desc = self.reader.bytes(descsz)?;
+        self.skip_padding();
+        Ok(Note {
+            name: name.strip_suffix(&[0]).unwrap_or(name),
+            n_type,
+            desc,
+        })
+    }
+}
+
+impl<'a> Iterator for Notes<'a> {
+    type Item = Result<Note<'a>, ElfParserError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.reader.is_empty() {
+            return None;
+        }
+        let note = self.note();
+        self.failed = note.is_err();
+        Some(note)
+    }
+}
@@ -0,0 +1,304 @@
+//! Parsed ELF file which does not borrow the file bytes
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::mem;
+
+use super::{
+    consts::*, sbpf_version_from_e_flags, types::*, AlignedElfBytes, DynamicRelocation, Elf64,
+    ElfParserError, ResolvedRelocation, SECTION_NAME_LENGTH_MAXIMUM, SYMBOL_NAME_LENGTH_MAXIMUM,
+};
+use crate::program::SBPFVersion;
+
+/// Owned copy of the headers, tables and string tables of an [Elf64]
+///
+/// Keeps answering the same queries as [Elf64] after the file bytes are dropped. The section
+/// and segment contents are not copied, so the size depends on the size of the headers and
+/// tables only, see [Elf64Owned::mem_size].
+#[derive(Debug, Clone)]
+pub struct Elf64Owned {
+    file_header: Elf64Ehdr,
+    program_header_table: Vec<Elf64Phdr>,
+    section_header_table: Vec<Elf64Shdr>,
+    section_names: Option<Vec<u8>>,
+    symbol_table: Option<Vec<Elf64Sym>>,
+    symbol_names: Option<Vec<u8>>,
+    dynamic_relocations_table: Option<Vec<Elf64Rel>>,
+    dynamic_relocations_rela_table: Option<Vec<Elf64Rela>>,
+    dynamic_symbol_table: Option<Vec<Elf64Sym>>,
+    dynamic_symbol_names: Option<Vec<u8>>,
+}
+
+/// Same as [Elf64::get_string_in_section], for the bytes of the string table
+fn string_in_table(
+    table: &[u8],
+    offset: Elf64Word,
+    maximum_length: usize,
+) -> Result<&[u8], ElfParserError> {
+    let offset = offset as usize;
+    let string_range = offset..table.len().min(offset.saturating_add(maximum_length));
+    let unterminated_string_bytes =
+        table
+            .get(string_range.clone())
+            .ok_or(ElfParserError::OutOfBounds {
+                offset,
+                len: string_range.len(),
+            })?;
+    unterminated_string_bytes
+        .iter()
+        .position(|byte| *byte == 0x00)
+        .and_then(|string_length| unterminated_string_bytes.get(0..string_length))
+        .ok_or_else(|| {
+            ElfParserError::StringTooLong(
+                String::from_utf8_lossy(unterminated_string_bytes).to_string(),
+                maximum_length,
+            )
+        })
+}
+
+fn table_size<T>(table: &Option<Vec<T>>) -> usize {
+    table
+        .as_ref()
+        .map(|table| table.len().saturating_mul(mem::size_of::<T>()))
+        .unwrap_or(0)
+}
+
+impl Elf64Owned {
+    /// Parses `elf_bytes` and copies what the queries need, see [Elf64Owned::from_elf]
+    ///
+    /// Unaligned input is copied into aligned memory for parsing, see [AlignedElfBytes].
+    pub fn parse(elf_bytes: &[u8]) -> Result<Self, ElfParserError> {
+        Self::from_elf(&AlignedElfBytes::new(elf_bytes).parse()?)
+    }
+
+    /// Copies the headers, tables and string tables of `elf`
+    ///
+    /// Fails if a symbol or string table is invalid, which [Elf64] only reports when it is
+    /// accessed.
+    pub fn from_elf(elf: &Elf64) -> Result<Self, ElfParserError> {
+        let string_table = |section_header: Option<&Elf64Shdr>| {
+            section_header
+                .map(|section_header| {
+                    elf.string_table_bytes(section_header)
+                        .map(|bytes| bytes.to_vec())
+                })
+                .transpose()
+        };
+        Ok(Self {
+            file_header: elf.file_header.clone(),
+            program_header_table: elf.program_header_table.to_vec(),
+            section_header_table: elf.section_header_table.to_vec(),
+            section_names: string_table(elf.section_names_section_header)?,
+            symbol_table: elf.symbol_table()?.map(|symbols| symbols.to_vec()),
+            symbol_names: string_table(elf.symbol_names_section_header)?,
+            dynamic_relocations_table: elf.dynamic_relocations_table.map(|table| table.to_vec()),
+            dynamic_relocations_rela_table: elf
+                .dynamic_relocations_rela_table
+                .map(|table| table.to_vec()),
+            dynamic_symbol_table: elf.dynamic_symbol_table.map(|symbols| symbols.to_vec()),
+            dynamic_symbol_names: string_table(elf.dynamic_symbol_names_section_header)?,
+        })
+    }
+
+    /// Returns the number of bytes allocated for the copies
+    pub fn mem_size(&self) -> usize {
+        mem::size_of::<Self>()
+            .saturating_add(
+                self.program_header_table
+                    .len()
+                    .saturating_mul(mem::size_of::<Elf64Phdr>()),
+            )
+            .saturating_add(
+                self.section_header_table
+                    .len()
+                    .saturating_mul(mem::size_of::<Elf64Shdr>()),
+            )
+            .saturating_add(table_size(&self.section_names))
+            .saturating_add(table_size(&self.symbol_table))
+            .saturating_add(table_size(&self.symbol_names))
+            .saturating_add(table_size(&self.dynamic_relocations_table))
+            .saturating_add(table_size(&self.dynamic_relocations_rela_table))
+            .saturating_add(table_size(&self.dynamic_symbol_table))
+            .saturating_add(table_size(&self.dynamic_symbol_names))
+    }
+
+    /// Returns the file header.
+    pub fn file_header(&self) -> &Elf64Ehdr {
+        &self.file_header
+    }
+
+    /// Returns the SBPF version encoded in the e_flags of the file header.
+    pub fn sbpf_version(&self) -> Result<SBPFVersion, ElfParserError> {
+        sbpf_version_from_e_flags(self.file_header.e_flags)
+    }
+
+    /// Returns the program header table.
+    pub fn program_header_table(&self) -> &[Elf64Phdr] {
+        &self.program_header_table
+    }
+
+    /// Returns the p_flags of the PT_GNU_STACK program header, if there is one.
+    pub fn gnu_stack_flags(&self) -> Option<Elf64Word> {
+        self.program_header_table
+            .iter()
+            .find(|program_header| program_header.p_type == PT_GNU_STACK)
+            .map(|program_header| program_header.p_flags)
+    }
+
+    /// Returns the section header table.
+    pub fn section_header_table(&self) -> &[Elf64Shdr] {
+        &self.section_header_table
+    }
+
+    /// Returns the section headers along with their names, see [Elf64::section_headers].
+    pub fn section_headers(
+        &self,
+    ) -> impl Iterator<Item = (Result<&[u8], ElfParserError>, &Elf64Shdr)> + '_ {
+        self.section_header_table
+            .iter()
+            .map(move |section_header| (self.section_name(section_header.sh_name), section_header))
+    }
+
+    /// Returns the first section header named `name`.
+    pub fn find_section(&self, name: &[u8]) -> Option<&Elf64Shdr> {
+        self.section_headers()
+            .find(|(section_name, _)| matches!(section_name, Ok(section_name) if *section_name == name))
+            .map(|(_, section_header)| section_header)
+    }
+
+    /// Returns the string corresponding to the given `sh_name`
+    pub fn section_name(&self, sh_name: Elf64Word) -> Result<&[u8], ElfParserError> {
+        string_in_table(
+            self.section_names
+                .as_ref()
+                .ok_or(ElfParserError::NoSectionNameStringTable)?,
+            sh_name,
+            SECTION_NAME_LENGTH_MAXIMUM,
+        )
+    }
+
+    /// Returns the name of the `st_name` symbol
+    pub fn symbol_name(&self, st_name: Elf64Word) -> Result<&[u8], ElfParserError> {
+        string_in_table(
+            self.symbol_names
+                .as_ref()
+                .ok_or(ElfParserError::NoStringTable)?,
+            st_name,
+            SYMBOL_NAME_LENGTH_MAXIMUM,
+        )
+    }
+
+    /// Returns the symbol table
+    pub fn symbol_table(&self) -> Result<Option<&[Elf64Sym]>, ElfParserError> {
+        Ok(self.symbol_table.as_deref())
+    }
+
+    /// Returns the dynamic symbol table.
+    pub fn dynamic_symbol_table(&self) -> Option<&[Elf64Sym]> {
+        self.dynamic_symbol_table.as_deref()
+    }
+
+    /// Returns the name of the `st_name` dynamic symbol
+    pub fn dynamic_symbol_name(&self, st_name: Elf64Word) -> Result<&[u8], ElfParserError> {
+        string_in_table(
+            self.dynamic_symbol_names
+                .as_ref()
+                .ok_or(ElfParserError::NoDynamicStringTable)?,
+            st_name,
+            SYMBOL_NAME_LENGTH_MAXIMUM,
+        )
+    }
+
+    /// Returns the symbol called `name` in the symbol table, see [Elf64::lookup_symbol]
+    pub fn lookup_symbol(&self, name: &[u8]) -> Result<Option<&Elf64Sym>, ElfParserError> {
+        let symbols = match &self.symbol_table {
+            Some(symbols) => symbols,
+            None => return Ok(None),
+        };
+        let names = self
+            .symbol_names
+            .as_ref()
+            .ok_or(ElfParserError::NoStringTable)?;
+        Ok(Elf64::find_symbol(symbols, names, name))
+    }
+
+    /// Returns the symbol called `name` in the dynamic symbol table
+    ///
+    /// Unlike [Elf64::lookup_dynamic_symbol] this always searches linearly, as the DT_GNU_HASH
+    /// table is not copied.
+    pub fn lookup_dynamic_symbol(&self, name: &[u8]) -> Result<Option<&Elf64Sym>, ElfParserError> {
+        let symbols = match &self.dynamic_symbol_table {
+            Some(symbols) => symbols,
+            None => return Ok(None),
+        };
+        let names = self
+            .dynamic_symbol_names
+            .as_ref()
+            .ok_or(ElfParserError::NoDynamicStringTable)?;
+        Ok(Elf64::find_symbol(symbols, names, name))
+    }
+
+    /// Returns the symbols of the symbol table by name, see [Elf64::symbol_map]
+    pub fn symbol_map(&self) -> Result<BTreeMap<&[u8], &Elf64Sym>, ElfParserError> {
+        let symbols = match &self.symbol_table {
+            Some(symbols) => symbols,
+            None => return Ok(BTreeMap::new()),
+        };
+        let names = self
+            .symbol_names
+            .as_ref()
+            .ok_or(ElfParserError::NoStringTable)?;
+        let mut symbol_map = BTreeMap::new();
+        for symbol in symbols {
+            if let Some(name) = Elf64::symbol_name_in(names, symbol) {
+                symbol_map.entry(name).or_insert(symbol);
+            }
+        }
+        Ok(symbol_map)
+    }
+
+    /// Returns the dynamic relocations table.
+    pub fn dynamic_relocations_table(&self) -> Option<&[Elf64Rel]> {
+        self.dynamic_relocations_table.as_deref()
+    }
+
+    /// Returns the dynamic relocations table with explicit addends.
+    pub fn dynamic_relocations_rela_table(&self) -> Option<&[Elf64Rela]> {
+        self.dynamic_relocations_rela_table.as_deref()
+    }
+
+    /// Returns all dynamic relocations, the DT_REL entries followed by the DT_RELA entries.
+    pub fn dynamic_relocations(&self) -> impl Iterator<Item = DynamicRelocation<'_>> + '_ {
+        self.dynamic_relocations_table()
+            .unwrap_or_default()
+            .iter()
+            .map(DynamicRelocation::Rel)
+            .chain(
+                self.dynamic_relocations_rela_table()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(DynamicRelocation::Rela),
+            )
+    }
+
+    /// Looks up the symbol a dynamic relocation refers to
+    pub fn resolve_relocation<'a>(
+        &'a self,
+        relocation: DynamicRelocation<'a>,
+    ) -> Result<ResolvedRelocation<'a>, ElfParserError> {
+        ResolvedRelocation::new(relocation, self.dynamic_symbol_table(), |st_name| {
+            self.dynamic_symbol_name(st_name)
+        })
+    }
+
+    /// Returns all dynamic relocations with the symbols they refer to, see [Elf64::relocations]
+    pub fn relocations(
+        &self,
+    ) -> impl Iterator<Item = Result<ResolvedRelocation<'_>, ElfParserError>> + '_ {
+        self.dynamic_relocations()
+            .map(move |relocation| self.resolve_relocation(relocation))
+    }
+}
@@ -0,0 +1,109 @@
+//! Reader for the DWARF encoded sections
+
+use super::{checked_range, ElfParserError};
+
+/// Reads the little endian and LEB128 encoded fields of a section
+#[derive(Debug)]
+pub(super) struct Reader<'a> {
+    pub(super) bytes: &'a [u8],
+    pub(super) position: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(super) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    /// Returns whether all bytes were read
+    pub(super) fn is_empty(&self) -> bool {
+        self.position >= self.bytes.len()
+    }
+
+    pub(super) fn bytes(&mut self, len: usize) -> Result<&'a [u8], ElfParserError> {
+        let range = checked_range(self.position, len)?;
+        let bytes = self
+            .bytes
+            .get(range.clone())
+            .ok_or(ElfParserError::OutOfBounds {
+                offset: self.position,
+                len,
+            })?;
+        self.position = range.end;
+        Ok(bytes)
+    }
+
+    /// Returns the remaining bytes
+    pub(super) fn rest(&mut self) -> Result<&'a [u8], ElfParserError> {
+        self.bytes(self.bytes.len().saturating_sub(self.position))
+    }
+
+    /// Reads a NUL terminated string, without the NUL
+    pub(super) fn cstr(&mut self) -> Result<&'a [u8], ElfParserError> {
+        let len = self
+            .bytes
+            .get(self.position..)
+            .unwrap_or_default()
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or(ElfParserError::OutOfBounds {
+                offset: self.position,
+                len: self.bytes.len().saturating_sub(self.position),
+            })?;
+        let string = self.bytes(len)?;
+        self.u8()?;
+        Ok(string)
+    }
+
+    pub(super) fn u8(&mut self) -> Result<u8, ElfParserError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(super) fn uint(&mut self, len: usize) -> Result<u64, ElfParserError> {
+        let mut value = [0u8; 8];
+        value
+            .get_mut(..len)
+            .ok_or(ElfParserError::InvalidSize)?
+            .copy_from_slice(self.bytes(len)?);
+        Ok(u64::from_le_bytes(value))
+    }
+
+    pub(super) fn sint(&mut self, len: usize) -> Result<i64, ElfParserError> {
+        let unused_bits = 64u32.saturating_sub((len as u32).saturating_mul(8));
+        Ok((self.uint(len)? as i64)
+            .wrapping_shl(unused_bits)
+            .wrapping_shr(unused_bits))
+    }
+
+    /// Returns the value and the number of bits it was encoded in
+    fn leb128(&mut self) -> Result<(u64, u32), ElfParserError> {
+        let start = self.position;
+        let mut value = 0u64;
+        let mut shift = 0u32;
+        loop {
+            let byte = self.u8()?;
+            if shift >= u64::BITS {
+                return Err(ElfParserError::OutOfBounds {
+                    offset: start,
+                    len: self.position.saturating_sub(start),
+                });
+            }
+            value |= ((byte & 0x7f) as u64).wrapping_shl(shift);
+            shift = shift.saturating_add(7);
+            if byte & 0x80 == 0 {
+                return Ok((value, shift));
+            }
+        }
+    }
+
+    pub(super) fn uleb128(&mut self) -> Result<u64, ElfParserError> {
+        self.leb128().map(|(value, _bits)| value)
+    }
+
+    pub(super) fn sleb128(&mut self) -> Result<i64, ElfParserError> {
+        let (value, bits) = self.leb128()?;
+        let unused_bits = u64::BITS.saturating_sub(bits);
+        Ok((value as i64)
+            .wrapping_shl(unused_bits)
+            .wrapping_shr(unused_bits))
+    }
+}
@@ -3,6 +3,7 @@
 pub type Elf64Half = u16;
 pub type Elf64Word = u32;
 pub type Elf64Xword = u64;
+pub type Elf64Sxword = i64;
 pub type Elf64Addr = u64;
 pub type Elf64Off = u64;
 pub type Elf64Section = u16;
@@ -77,6 +78,49 @@ pub struct Elf64Sym {
     pub st_size: Elf64Xword,
 }
 
+/// Type of a symbol, the lower nibble of st_info
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolType {
+    /// STT_NOTYPE
+    NoType,
+    /// STT_OBJECT
+    Object,
+    /// STT_FUNC
+    Function,
+    /// STT_SECTION
+    Section,
+    /// STT_FILE
+    File,
+    /// Any other type
+    Other(u8),
+}
+
+/// Binding of a symbol, the upper nibble of st_info
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolBinding {
+    /// STB_LOCAL
+    Local,
+    /// STB_GLOBAL
+    Global,
+    /// STB_WEAK
+    Weak,
+    /// Any other binding
+    Other(u8),
+}
+
+/// Visibility of a symbol, the lower two bits of st_other
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolVisibility {
+    /// STV_DEFAULT
+    Default,
+    /// STV_INTERNAL
+    Internal,
+    /// STV_HIDDEN
+    Hidden,
+    /// STV_PROTECTED
+    Protected,
+}
+
 #[derive(Debug, Clone)]
 #[repr(C)]
 pub struct Elf64Dyn {
@@ -90,3 +134,54 @@ pub struct Elf64Rel {
     pub r_offset: Elf64Addr,
     pub r_info: Elf64Xword,
 }
+
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct Elf64Rela {
+    pub r_offset: Elf64Addr,
+    pub r_info: Elf64Xword,
+    pub r_addend: Elf64Sxword,
+}
+
+/// Reverses the byte order of all fields
+///
+/// Files are little endian, this converts the headers and tables to the native layout of big
+/// endian hosts, see [super::swap_byte_order].
+#[cfg_attr(target_endian = "little", allow(dead_code))]
+pub(crate) trait SwapBytes {
+    fn swap_bytes(&mut self);
+}
+
+macro_rules! impl_swap_bytes {
+    ($($ty:ty $({ $($field:ident),* })?;)*) => {
+        $(impl SwapBytes for $ty {
+            fn swap_bytes(&mut self) {
+                impl_swap_bytes!(@fields self, $ty $({ $($field),* })?);
+            }
+        })*
+    };
+    (@fields $self:ident, $ty:ty { $($field:ident),* }) => {
+        $($self.$field = $self.$field.swap_bytes();)*
+    };
+    (@fields $self:ident, $ty:ty) => {
+        *$self = <$ty>::swap_bytes(*$self);
+    };
+}
+
+impl_swap_bytes! {
+    u32;
+    u64;
+    Elf64Ehdr {
+        e_type, e_machine, e_version, e_entry, e_phoff, e_shoff, e_flags, e_ehsize,
+        e_phentsize, e_phnum, e_shentsize, e_shnum, e_shstrndx
+    };
+    Elf64Phdr { p_type, p_flags, p_offset, p_vaddr, p_paddr, p_filesz, p_memsz, p_align };
+    Elf64Shdr {
+        sh_name, sh_type, sh_flags, sh_addr, sh_offset, sh_size, sh_link, sh_info,
+        sh_addralign, sh_entsize
+    };
+    Elf64Sym { st_name, st_shndx, st_value, st_size };
+    Elf64Dyn { d_tag, d_val };
+    Elf64Rel { r_offset, r_info };
+    Elf64Rela { r_offset, r_info, r_addend };
+}
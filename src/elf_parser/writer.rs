@@ -0,0 +1,125 @@
+//! Patching the bytes of a parsed ELF file in place
+
+use core::{mem, ops::Range};
+
+use super::{
+    checked_range,
+    consts::PT_LOAD,
+    saturating_usize,
+    types::{Elf64Addr, Elf64Shdr, Elf64Xword},
+    Elf64, ElfParserError,
+};
+
+/// Offset of sh_flags in a section header
+const SH_FLAGS_OFFSET: usize = 8;
+
+/// Writes to a copy of the bytes an [Elf64] was parsed from
+///
+/// Virtual addresses are translated the same way as [Elf64::vaddr_to_file_offset], except that
+/// only the file backed bytes of PT_LOAD segments can be written. The headers and tables of
+/// the [Elf64] are not updated, parse the patched bytes again to see the changes.
+#[derive(Debug)]
+pub struct ElfPatcher<'a> {
+    elf: &'a Elf64<'a>,
+    elf_bytes: &'a mut [u8],
+}
+
+impl<'a> ElfPatcher<'a> {
+    /// Patches `elf_bytes`, which must be a copy of the bytes `elf` was parsed from
+    ///
+    /// Fails with [ElfParserError::InvalidSize] if the lengths differ.
+    pub fn new(elf: &'a Elf64<'a>, elf_bytes: &'a mut [u8]) -> Result<Self, ElfParserError> {
+        if elf_bytes.len() != elf.elf_bytes.len() {
+            return Err(ElfParserError::InvalidSize);
+        }
+        Ok(Self { elf, elf_bytes })
+    }
+
+    /// Returns the patched bytes
+    pub fn bytes(&self) -> &[u8] {
+        self.elf_bytes
+    }
+
+    /// Writes `value` in little endian at the virtual address `vaddr`
+    pub fn write_u32_at_vaddr(
+        &mut self,
+        vaddr: Elf64Addr,
+        value: u32,
+    ) -> Result<(), ElfParserError> {
+        self.write_at_vaddr(vaddr, &value.to_le_bytes())
+    }
+
+    /// Writes `value` in little endian at the virtual address `vaddr`
+    pub fn write_u64_at_vaddr(
+        &mut self,
+        vaddr: Elf64Addr,
+        value: u64,
+    ) -> Result<(), ElfParserError> {
+        self.write_at_vaddr(vaddr, &value.to_le_bytes())
+    }
+
+    /// Replaces the sh_flags of the section header at `index`
+    pub fn set_section_flags(
+        &mut self,
+        index: usize,
+        sh_flags: Elf64Xword,
+    ) -> Result<(), ElfParserError> {
+        if index >= self.elf.section_header_table.len() {
+            return Err(ElfParserError::InvalidSectionHeader { index });
+        }
+        let section_header_offset = saturating_usize(self.elf.file_header.e_shoff)
+            .saturating_add(index.saturating_mul(mem::size_of::<Elf64Shdr>()));
+        let range = checked_range(
+            section_header_offset.saturating_add(SH_FLAGS_OFFSET),
+            mem::size_of::<Elf64Xword>(),
+        )?;
+        self.write_at(range, &sh_flags.to_le_bytes())
+    }
+
+    fn write_at_vaddr(&mut self, vaddr: Elf64Addr, bytes: &[u8]) -> Result<(), ElfParserError> {
+        let range = self.load_segment_range(vaddr, bytes.len())?;
+        self.write_at(range, bytes)
+    }
+
+    fn write_at(&mut self, range: Range<usize>, bytes: &[u8]) -> Result<(), ElfParserError> {
+        let (offset, len) = (range.start, range.len());
+        self.elf_bytes
+            .get_mut(range)
+            .ok_or(ElfParserError::OutOfBounds { offset, len })?
+            .copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Translates `vaddr` to a file range inside of the file backed bytes of a PT_LOAD segment
+    fn load_segment_range(
+        &self,
+        vaddr: Elf64Addr,
+        len: usize,
+    ) -> Result<Range<usize>, ElfParserError> {
+        let program_header = self
+            .elf
+            .program_header_table
+            .iter()
+            .find(|program_header| {
+                program_header.p_type == PT_LOAD && program_header.vm_range().contains(&vaddr)
+            })
+            .ok_or(ElfParserError::OutOfBounds {
+                offset: saturating_usize(vaddr),
+                len,
+            })?;
+        // The program header contains vaddr, so this does not underflow
+        let offset_in_segment = vaddr.saturating_sub(program_header.p_vaddr);
+        let range = checked_range(
+            saturating_usize(program_header.p_offset)
+                .saturating_add(saturating_usize(offset_in_segment)),
+            len,
+        )?;
+        if (len as u64) > program_header.p_filesz.saturating_sub(offset_in_segment) {
+            return Err(ElfParserError::OutOfBounds {
+                offset: range.start,
+                len,
+            });
+        }
+        Ok(range)
+    }
+}
@@ -9,7 +9,12 @@
 use alloc::boxed::Box;
 
 use {
-    crate::{elf::ElfError, memory_region::AccessType, verifier::VerifierError},
+    crate::{
+        elf::ElfError,
+        memory_region::{AccessType, AccessViolationContext, RegionSizes},
+        verifier::VerifierError,
+        vm::ConfigError,
+    },
     core::error::Error,
     alloc::{vec::Vec, string::String}
 };
@@ -25,8 +30,14 @@ pub enum EbpfError {
     #[error("function #{0} was already registered")]
     FunctionAlreadyRegistered(usize),
     /// Exceeded max BPF to BPF call depth
-    #[error("exceeded max BPF to BPF call depth")]
-    CallDepthExceeded,
+    #[error("exceeded max BPF to BPF call depth at BPF instruction {pc}")]
+    CallDepthExceeded {
+        /// Program counter of the call which exceeded the limit
+        pc: usize,
+        /// Return addresses of the active call frames, the outermost first, the last one is
+        /// the return address of the offending call
+        return_addresses: Vec<u64>,
+    },
     /// Attempt to exit from root call frame
     #[error("attempted to exit root call frame")]
     ExitRootCallFrame,
@@ -52,11 +63,17 @@ pub enum EbpfError {
     #[error("Invalid memory region at index {0}")]
     InvalidMemoryRegion(usize),
     /// Access violation (general)
-    #[error("Access violation in {3} section at address {1:#x} of size {2:?}")]
-    AccessViolation(AccessType, u64, u64, &'static str),
+    #[error("Access violation in {3} section at address {1:#x} of size {2:?}{4}")]
+    AccessViolation(
+        AccessType,
+        u64,
+        u64,
+        &'static str,
+        Box<AccessViolationContext>,
+    ),
     /// Access violation (stack specific)
-    #[error("Access violation in stack frame {3} at address {1:#x} of size {2:?}")]
-    StackAccessViolation(AccessType, u64, u64, i64),
+    #[error("Access violation in stack frame {3} at address {1:#x} of size {2:?}{4}")]
+    StackAccessViolation(AccessType, u64, u64, i64, Box<AccessViolationContext>),
     /// Invalid instruction
     #[error("invalid BPF instruction")]
     InvalidInstruction,
@@ -75,6 +92,62 @@ pub enum EbpfError {
     /// Syscall error
     #[error("Syscall error: {0}")]
     SyscallError(Box<dyn Error>),
+    /// Call to a function banned by the installed [crate::vm::CallFilter]
+    #[error("call to function at BPF instruction {target_pc} was filtered")]
+    CallFiltered {
+        /// Program counter of the banned function
+        target_pc: usize,
+    },
+    /// Memory regions exceed [crate::vm::Config::max_writable_bytes] or
+    /// [crate::vm::Config::max_total_region_bytes]
+    #[error("Memory regions exceed the configured limits: {0}")]
+    MemoryLimitExceeded(RegionSizes),
+    /// Guest address of a value is not aligned, see
+    /// [crate::vm::Config::reject_unaligned_guest_types]
+    #[error("unaligned guest address {vm_addr:#x}, alignment {align} required")]
+    UnalignedGuestType {
+        /// Guest address of the value
+        vm_addr: u64,
+        /// Alignment of the value type
+        align: usize,
+    },
+    /// No NUL terminator within the maximum length, see
+    /// [crate::memory_region::MemoryMapping::translate_string]
+    #[error("no NUL terminator within {max_len} bytes of guest address {vm_addr:#x}")]
+    UnterminatedString {
+        /// Guest address of the string
+        vm_addr: u64,
+        /// Maximum length of the string, excluding the NUL terminator
+        max_len: u64,
+    },
+    /// The heap has no room left for an allocation, see
+    /// [crate::allocator::BumpAllocator::alloc]
+    #[error("heap exhausted allocating {size} bytes aligned to {align}")]
+    HeapExhausted {
+        /// Size of the allocation
+        size: usize,
+        /// Alignment of the allocation
+        align: usize,
+    },
+    /// A breakpoint is outside of the text section or in the second slot of an lddw, see
+    /// [crate::interpreter::Interpreter::set_breakpoint]
+    #[error("invalid breakpoint at BPF instruction {pc}")]
+    InvalidBreakpoint {
+        /// Program counter of the breakpoint
+        pc: usize,
+    },
+    /// [crate::vm::ContextObject::trace_hook] returned [crate::vm::TraceControl::Stop]
+    #[error("execution stopped by the trace hook")]
+    TraceStopped,
+    /// The [crate::vm::Config] failed [crate::vm::Config::validate]
+    #[error("Config error: {0}")]
+    ConfigError(#[from] ConfigError),
+    /// JIT compilation exceeded [crate::vm::Config::max_machine_code_length]
+    #[error("exceeded the machine code length limit at BPF instruction {0}")]
+    ExceededMaxMachineCodeLength(usize),
+    /// Register other than r0 to r10, see [crate::ebpf::Insn::new]
+    #[error("invalid register r{0}")]
+    InvalidRegister(u8),
 }
 
 /// crate's internal errors
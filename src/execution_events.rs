@@ -0,0 +1,204 @@
+// Licensed under the Apache License, Version 2.0 <http://www.apache.org/licenses/LICENSE-2.0> or
+// the MIT license <http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Ordered stream of the events of an execution, for timelines in embedder UIs.
+//!
+//! The interpreter emits the frame, syscall and error events to the sink returned by
+//! [ContextObject::execution_events], builtins add the log and memory events. Compiled programs
+//! do not emit events.
+//!
+//! Events arrive in exact execution order:
+//! - An execution starts with the [ExecutionEvent::FrameEnter] of depth 0 for the entrypoint,
+//!   and a successful one ends with the matching [ExecutionEvent::FrameExit].
+//! - Every frame entered is exited before the frame around it, unless the execution fails.
+//! - Everything a builtin emits, including the events of nested executions sharing the sink,
+//!   comes between the [ExecutionEvent::SyscallEnter] and [ExecutionEvent::SyscallExit] of its
+//!   syscall.
+//! - A failed execution ends with an [ExecutionEvent::Error], without exiting the open frames.
+//!
+//! [ContextObject::execution_events]: crate::vm::ContextObject::execution_events
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{fmt::Write, mem};
+
+/// An event of an execution
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExecutionEvent {
+    /// A message logged by a builtin
+    Log(String),
+    /// A syscall is about to be invoked
+    SyscallEnter {
+        /// Pc of the syscall instruction
+        pc: usize,
+        /// Name the builtin is registered under in the loader
+        name: Vec<u8>,
+    },
+    /// A syscall returned
+    SyscallExit {
+        /// Pc of the syscall instruction
+        pc: usize,
+        /// Return value, `None` if the builtin failed
+        return_value: Option<u64>,
+    },
+    /// A function was entered
+    FrameEnter {
+        /// Pc of the first instruction of the function
+        target_pc: usize,
+        /// Call depth inside the function, 0 for the entrypoint
+        depth: u64,
+    },
+    /// A function returned
+    FrameExit {
+        /// Pc of the return instruction
+        pc: usize,
+        /// Call depth inside the function which returned
+        depth: u64,
+    },
+    /// A builtin grew or added the memory region at `vm_addr`
+    MemoryGrow {
+        /// Start of the region
+        vm_addr: u64,
+        /// New length of the region
+        len: u64,
+    },
+    /// The execution failed
+    Error(String),
+}
+
+impl ExecutionEvent {
+    /// Approximate number of bytes the event occupies, including its heap allocations
+    pub fn mem_size(&self) -> usize {
+        mem::size_of::<Self>().saturating_add(match self {
+            ExecutionEvent::Log(message) | ExecutionEvent::Error(message) => message.len(),
+            ExecutionEvent::SyscallEnter { name, .. } => name.len(),
+            _ => 0,
+        })
+    }
+}
+
+/// Receives the events of an execution, see the [module documentation](self)
+pub trait ExecutionEvents {
+    /// Receives `event`, which happened after `instruction_count` instructions
+    ///
+    /// The count includes the instruction causing the event.
+    fn event(&mut self, instruction_count: u64, event: ExecutionEvent);
+
+    /// Returns the instruction count of the last event received
+    fn instruction_count(&self) -> u64;
+
+    /// Receives an event of a builtin
+    ///
+    /// Builtins don't know the instruction count, their events happen at the one of the
+    /// enclosing [ExecutionEvent::SyscallEnter].
+    fn builtin_event(&mut self, event: ExecutionEvent) {
+        let instruction_count = self.instruction_count();
+        self.event(instruction_count, event);
+    }
+}
+
+/// Stores the events of executions, up to a budget of bytes
+///
+/// Events exceeding the budget are counted but not stored, see [ExecutionEvent::mem_size].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExecutionEventLog {
+    /// The stored events along with their instruction counts, in order
+    pub events: Vec<(u64, ExecutionEvent)>,
+    /// Number of events received after the budget was exhausted
+    pub dropped_events: usize,
+    byte_budget: usize,
+    used_bytes: usize,
+    instruction_count: u64,
+}
+
+impl ExecutionEventLog {
+    /// Creates an empty log storing up to `byte_budget` bytes of events
+    pub fn new(byte_budget: usize) -> Self {
+        Self {
+            byte_budget,
+            ..Self::default()
+        }
+    }
+
+    /// Renders the events as an interleaved timeline, one event per line
+    ///
+    /// Each line starts with the instruction count, followed by the event indented by the
+    /// number of frames and syscalls it happened in.
+    pub fn render_timeline(&self) -> String {
+        let mut timeline = String::new();
+        let mut nesting = 0usize;
+        for (instruction_count, event) in &self.events {
+            if matches!(
+                event,
+                ExecutionEvent::FrameExit { .. } | ExecutionEvent::SyscallExit { .. }
+            ) {
+                nesting = nesting.saturating_sub(1);
+            }
+            let description = match event {
+                ExecutionEvent::Log(message) => format!("log: {message}"),
+                ExecutionEvent::SyscallEnter { pc, name } => {
+                    format!("syscall {} at pc {pc}", String::from_utf8_lossy(name))
+                }
+                ExecutionEvent::SyscallExit {
+                    return_value: Some(return_value),
+                    ..
+                } => format!("syscall returned {return_value:#x}"),
+                ExecutionEvent::SyscallExit {
+                    return_value: None,
+                    ..
+                } => "syscall failed".to_string(),
+                ExecutionEvent::FrameEnter { target_pc, depth } => {
+                    format!("enter frame {depth} at pc {target_pc}")
+                }
+                ExecutionEvent::FrameExit { pc, depth } => {
+                    format!("exit frame {depth} at pc {pc}")
+                }
+                ExecutionEvent::MemoryGrow { vm_addr, len } => {
+                    format!("memory region at {vm_addr:#x} grew to {len} bytes")
+                }
+                ExecutionEvent::Error(message) => format!("error: {message}"),
+            };
+            let _ = writeln!(
+                timeline,
+                "{instruction_count:>8} {:indent$}{description}",
+                "",
+                indent = nesting.saturating_mul(2),
+            );
+            if matches!(
+                event,
+                ExecutionEvent::FrameEnter { .. } | ExecutionEvent::SyscallEnter { .. }
+            ) {
+                nesting = nesting.saturating_add(1);
+            }
+        }
+        if self.dropped_events > 0 {
+            let _ = writeln!(
+                timeline,
+                "{} more events exceeded the budget",
+                self.dropped_events
+            );
+        }
+        timeline
+    }
+}
+
+impl ExecutionEvents for ExecutionEventLog {
+    fn event(&mut self, instruction_count: u64, event: ExecutionEvent) {
+        self.instruction_count = instruction_count;
+        let used_bytes = self.used_bytes.saturating_add(event.mem_size());
+        if self.dropped_events > 0 || used_bytes > self.byte_budget {
+            self.dropped_events = self.dropped_events.saturating_add(1);
+            return;
+        }
+        self.used_bytes = used_bytes;
+        self.events.push((instruction_count, event));
+    }
+
+    fn instruction_count(&self) -> u64 {
+        self.instruction_count
+    }
+}
@@ -12,53 +12,57 @@
 
 //! Interpreter for eBPF programs.
 
+use alloc::{collections::BTreeSet, string::ToString, vec::Vec};
+use core::ops::Range;
+
 use crate::{
     ebpf,
     elf::Executable,
     error::{EbpfError, ProgramResult},
-    program::BuiltinFunction,
-    vm::{Config, ContextObject, EbpfVm},
+    execution_events::ExecutionEvent,
+    memory_region::{AccessType, MemoryMapping},
+    program::{BuiltinFunction, SBPFVersion, SyscallMetadata},
+    verifier::lddw_second_slots,
+    vm::{call_depth_exceeded, Config, ContextObject, EbpfVm, TraceControl},
 };
 
 /// Virtual memory operation helper.
 macro_rules! translate_memory_access {
-    (_impl, $self:ident, $op:ident, $vm_addr:ident, $T:ty, $($rest:expr),*) => {
-        match $self.vm.memory_mapping.$op::<$T>(
+    (_impl, $state:ident, $op:ident, $vm_addr:ident, $T:ty, $($rest:expr),*) => {
+        match $state.memory_mapping.$op::<$T>(
             $($rest,)*
             $vm_addr,
         ) {
             ProgramResult::Ok(v) => v,
-            ProgramResult::Err(err) => {
-                throw_error!($self, err);
-            },
+            ProgramResult::Err(err) => return Err(err),
         }
     };
 
     // MemoryMapping::load()
-    ($self:ident, load, $vm_addr:ident, $T:ty) => {
-        translate_memory_access!(_impl, $self, load, $vm_addr, $T,)
+    ($state:ident, load, $vm_addr:ident, $T:ty) => {
+        translate_memory_access!(_impl, $state, load, $vm_addr, $T,)
     };
 
     // MemoryMapping::store()
-    ($self:ident, store, $value:expr, $vm_addr:ident, $T:ty) => {
-        translate_memory_access!(_impl, $self, store, $vm_addr, $T, ($value) as $T);
+    ($state:ident, store, $value:expr, $vm_addr:ident, $T:ty) => {
+        translate_memory_access!(_impl, $state, store, $vm_addr, $T, ($value) as $T);
     };
 }
 
 macro_rules! throw_error {
     ($self:expr, $err:expr) => {{
-        $self.vm.registers[11] = $self.reg[11];
+        $self.vm.registers = $self.reg;
         $self.vm.program_result = ProgramResult::Err($err);
         return false;
     }};
-    (DivideByZero; $self:expr, $src:expr, $ty:ty) => {
+    (DivideByZero; $src:expr, $ty:ty) => {
         if $src as $ty == 0 {
-            throw_error!($self, EbpfError::DivideByZero);
+            return Err(EbpfError::DivideByZero);
         }
     };
-    (DivideOverflow; $self:expr, $src:expr, $dst:expr, $ty:ty) => {
+    (DivideOverflow; $src:expr, $dst:expr, $ty:ty) => {
         if $dst as $ty == <$ty>::MIN && $src as $ty == -1 {
-            throw_error!($self, EbpfError::DivideOverflow);
+            return Err(EbpfError::DivideOverflow);
         }
     };
 }
@@ -81,6 +85,470 @@ macro_rules! check_pc {
     };
 }
 
+/// Outcome of [Interpreter::step]
+#[derive(Debug)]
+pub enum StepResult {
+    /// The program continues at [Interpreter::pc]
+    Continue,
+    /// The program exited with this return value
+    Exited(u64),
+    /// The program was aborted
+    Error(EbpfError),
+    /// A breakpoint or watchpoint was hit, stepping again resumes the program
+    Paused(PauseReason),
+}
+
+/// Why [Interpreter::step] returned [StepResult::Paused]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PauseReason {
+    /// The instruction at `pc` has a breakpoint and was not executed yet
+    Breakpoint {
+        /// Program counter of the breakpoint
+        pc: usize,
+    },
+    /// The instruction at `pc` was executed and accessed memory inside of a watchpoint
+    Watchpoint {
+        /// Program counter of the accessing instruction
+        pc: usize,
+        /// Whether the instruction was a load or a store
+        access_type: AccessType,
+        /// Guest address of the access
+        vm_addr: u64,
+        /// Number of bytes accessed
+        len: u64,
+        /// Value in memory before the access
+        old_value: u64,
+        /// Value in memory after the access
+        new_value: u64,
+    },
+}
+
+/// Breakpoints and watchpoints of an [Interpreter]
+#[derive(Debug, Default)]
+struct DebugHooks {
+    breakpoints: BTreeSet<usize>,
+    watchpoints: Vec<(Range<u64>, AccessType)>,
+    /// Breakpoint which paused the program, it does not pause again when resuming
+    resumed_breakpoint: Option<usize>,
+}
+
+/// Guest memory read or written by a load or store instruction
+fn memory_access(
+    insn: &ebpf::Insn,
+    reg: &[u64; 12],
+    sbpf_version: SBPFVersion,
+) -> Option<(AccessType, u64, u64)> {
+    let (access_type, base, len) = if sbpf_version.move_memory_instruction_classes() {
+        match insn.opc {
+            ebpf::LD_1B_REG => (AccessType::Load, reg[insn.src as usize], 1),
+            ebpf::LD_2B_REG => (AccessType::Load, reg[insn.src as usize], 2),
+            ebpf::LD_4B_REG => (AccessType::Load, reg[insn.src as usize], 4),
+            ebpf::LD_8B_REG => (AccessType::Load, reg[insn.src as usize], 8),
+            ebpf::ST_1B_IMM | ebpf::ST_1B_REG => (AccessType::Store, reg[insn.dst as usize], 1),
+            ebpf::ST_2B_IMM | ebpf::ST_2B_REG => (AccessType::Store, reg[insn.dst as usize], 2),
+            ebpf::ST_4B_IMM | ebpf::ST_4B_REG => (AccessType::Store, reg[insn.dst as usize], 4),
+            ebpf::ST_8B_IMM | ebpf::ST_8B_REG => (AccessType::Store, reg[insn.dst as usize], 8),
+            _ => return None,
+        }
+    } else {
+        match insn.opc {
+            ebpf::LD_B_REG => (AccessType::Load, reg[insn.src as usize], 1),
+            ebpf::LD_H_REG => (AccessType::Load, reg[insn.src as usize], 2),
+            ebpf::LD_W_REG => (AccessType::Load, reg[insn.src as usize], 4),
+            ebpf::LD_DW_REG => (AccessType::Load, reg[insn.src as usize], 8),
+            ebpf::ST_B_IMM | ebpf::ST_B_REG => (AccessType::Store, reg[insn.dst as usize], 1),
+            ebpf::ST_H_IMM | ebpf::ST_H_REG => (AccessType::Store, reg[insn.dst as usize], 2),
+            ebpf::ST_W_IMM | ebpf::ST_W_REG => (AccessType::Store, reg[insn.dst as usize], 4),
+            ebpf::ST_DW_IMM | ebpf::ST_DW_REG => (AccessType::Store, reg[insn.dst as usize], 8),
+            _ => return None,
+        }
+    };
+    let vm_addr = (base as i64).wrapping_add(insn.off as i64) as u64;
+    Some((access_type, vm_addr, len))
+}
+
+/// Reads `len` bytes at `vm_addr`, or `None` if they are not mapped
+fn load_value(memory_mapping: &MemoryMapping, vm_addr: u64, len: u64) -> Option<u64> {
+    let result = match len {
+        1 => memory_mapping.load::<u8>(vm_addr),
+        2 => memory_mapping.load::<u16>(vm_addr),
+        4 => memory_mapping.load::<u32>(vm_addr),
+        _ => memory_mapping.load::<u64>(vm_addr),
+    };
+    match result {
+        ProgramResult::Ok(value) => Some(value),
+        ProgramResult::Err(_) => None,
+    }
+}
+
+/// Decodes every instruction slot of `program`
+///
+/// `LD_DW_IMM` is combined with its second slot, which stays in place undecoded so that the
+/// index of each instruction is its pc.
+pub(crate) fn predecode_instructions(program: &[u8], sbpf_version: SBPFVersion) -> Vec<ebpf::Insn> {
+    let insn_count = program.len() / ebpf::INSN_SIZE;
+    let mut insns = Vec::with_capacity(insn_count);
+    for pc in 0..insn_count {
+        let mut insn = ebpf::get_insn_unchecked(program, pc);
+        if insn.opc == ebpf::LD_DW_IMM
+            && !sbpf_version.disable_lddw()
+            && pc.saturating_add(1) < insn_count
+        {
+            ebpf::augment_lddw_unchecked(program, &mut insn);
+        }
+        insns.push(insn);
+    }
+    insns
+}
+
+/// Machine state a single instruction is executed against
+pub struct InsnState<'a, 'b> {
+    /// General purpose registers and pc
+    pub reg: &'a mut [u64; 12],
+    /// Memory accessed by load and store instructions
+    pub memory_mapping: &'a MemoryMapping<'b>,
+    /// Version which decides the availability and semantics of opcodes
+    pub sbpf_version: SBPFVersion,
+}
+
+fn sign_extension(sbpf_version: SBPFVersion, value: i32) -> u64 {
+    if sbpf_version.explicit_sign_extension_of_results() {
+        value as u32 as u64
+    } else {
+        value as i64 as u64
+    }
+}
+
+/// Executes a single instruction which neither calls nor returns
+///
+/// This covers the memory, ALU32, ALU64, PQR and jump classes and is the same code the
+/// interpreter runs for them, minus instruction metering and tracing. An `LD_DW_IMM` must
+/// already be combined with its second slot (see [`ebpf::augment_lddw_unchecked`]).
+///
+/// On success the pc (`reg[11]`) is advanced to the next instruction, on failure the state is
+/// left untouched. Calls, exits, syscalls and unknown opcodes fail with
+/// [`EbpfError::UnsupportedInstruction`].
+#[rustfmt::skip]
+#[inline(always)]
+pub fn exec_one(insn: &ebpf::Insn, state: &mut InsnState) -> Result<(), EbpfError> {
+    let mut next_pc = state.reg[11] + 1;
+    let dst = insn.dst as usize;
+    let src = insn.src as usize;
+
+    match insn.opc {
+        ebpf::LD_DW_IMM if !state.sbpf_version.disable_lddw() => {
+            state.reg[dst] = insn.imm as u64;
+            next_pc += 1;
+        },
+
+        // BPF_LDX class
+        ebpf::LD_B_REG  if !state.sbpf_version.move_memory_instruction_classes() => {
+            let vm_addr = (state.reg[src] as i64).wrapping_add(insn.off as i64) as u64;
+            state.reg[dst] = translate_memory_access!(state, load, vm_addr, u8);
+        },
+        ebpf::LD_H_REG  if !state.sbpf_version.move_memory_instruction_classes() => {
+            let vm_addr = (state.reg[src] as i64).wrapping_add(insn.off as i64) as u64;
+            state.reg[dst] = translate_memory_access!(state, load, vm_addr, u16);
+        },
+        ebpf::LD_W_REG  if !state.sbpf_version.move_memory_instruction_classes() => {
+            let vm_addr = (state.reg[src] as i64).wrapping_add(insn.off as i64) as u64;
+            state.reg[dst] = translate_memory_access!(state, load, vm_addr, u32);
+        },
+        ebpf::LD_DW_REG if !state.sbpf_version.move_memory_instruction_classes() => {
+            let vm_addr = (state.reg[src] as i64).wrapping_add(insn.off as i64) as u64;
+            state.reg[dst] = translate_memory_access!(state, load, vm_addr, u64);
+        },
+
+        // BPF_ST class
+        ebpf::ST_B_IMM  if !state.sbpf_version.move_memory_instruction_classes() => {
+            let vm_addr = (state.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
+            translate_memory_access!(state, store, insn.imm, vm_addr, u8);
+        },
+        ebpf::ST_H_IMM  if !state.sbpf_version.move_memory_instruction_classes() => {
+            let vm_addr = (state.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
+            translate_memory_access!(state, store, insn.imm, vm_addr, u16);
+        },
+        ebpf::ST_W_IMM  if !state.sbpf_version.move_memory_instruction_classes() => {
+            let vm_addr = (state.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
+            translate_memory_access!(state, store, insn.imm, vm_addr, u32);
+        },
+        ebpf::ST_DW_IMM if !state.sbpf_version.move_memory_instruction_classes() => {
+            let vm_addr = (state.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
+            translate_memory_access!(state, store, insn.imm, vm_addr, u64);
+        },
+
+        // BPF_STX class
+        ebpf::ST_B_REG  if !state.sbpf_version.move_memory_instruction_classes() => {
+            let vm_addr = (state.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
+            translate_memory_access!(state, store, state.reg[src], vm_addr, u8);
+        },
+        ebpf::ST_H_REG  if !state.sbpf_version.move_memory_instruction_classes() => {
+            let vm_addr = (state.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
+            translate_memory_access!(state, store, state.reg[src], vm_addr, u16);
+        },
+        ebpf::ST_W_REG  if !state.sbpf_version.move_memory_instruction_classes() => {
+            let vm_addr = (state.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
+            translate_memory_access!(state, store, state.reg[src], vm_addr, u32);
+        },
+        ebpf::ST_DW_REG if !state.sbpf_version.move_memory_instruction_classes() => {
+            let vm_addr = (state.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
+            translate_memory_access!(state, store, state.reg[src], vm_addr, u64);
+        },
+
+        // BPF_ALU32_LOAD class
+        ebpf::ADD32_IMM  => state.reg[dst] = sign_extension(state.sbpf_version, (state.reg[dst] as i32).wrapping_add(insn.imm as i32)),
+        ebpf::ADD32_REG  => state.reg[dst] = sign_extension(state.sbpf_version, (state.reg[dst] as i32).wrapping_add(state.reg[src] as i32)),
+        ebpf::SUB32_IMM  => if state.sbpf_version.swap_sub_reg_imm_operands() {
+                            state.reg[dst] = sign_extension(state.sbpf_version, (insn.imm as i32).wrapping_sub(state.reg[dst] as i32))
+        } else {
+                            state.reg[dst] = sign_extension(state.sbpf_version, (state.reg[dst] as i32).wrapping_sub(insn.imm as i32))
+        },
+        ebpf::SUB32_REG  => state.reg[dst] = sign_extension(state.sbpf_version, (state.reg[dst] as i32).wrapping_sub(state.reg[src] as i32)),
+        ebpf::MUL32_IMM  if !state.sbpf_version.enable_pqr() => state.reg[dst] = (state.reg[dst] as i32).wrapping_mul(insn.imm as i32)      as u64,
+        ebpf::MUL32_REG  if !state.sbpf_version.enable_pqr() => state.reg[dst] = (state.reg[dst] as i32).wrapping_mul(state.reg[src] as i32) as u64,
+        ebpf::LD_1B_REG  if state.sbpf_version.move_memory_instruction_classes() => {
+            let vm_addr = (state.reg[src] as i64).wrapping_add(insn.off as i64) as u64;
+            state.reg[dst] = translate_memory_access!(state, load, vm_addr, u8);
+        },
+        ebpf::DIV32_IMM  if !state.sbpf_version.enable_pqr() => state.reg[dst] = (state.reg[dst] as u32             / insn.imm as u32)      as u64,
+        ebpf::DIV32_REG  if !state.sbpf_version.enable_pqr() => {
+            throw_error!(DivideByZero; state.reg[src], u32);
+                            state.reg[dst] = (state.reg[dst] as u32             / state.reg[src] as u32) as u64;
+        },
+        ebpf::LD_2B_REG  if state.sbpf_version.move_memory_instruction_classes() => {
+            let vm_addr = (state.reg[src] as i64).wrapping_add(insn.off as i64) as u64;
+            state.reg[dst] = translate_memory_access!(state, load, vm_addr, u16);
+        },
+        ebpf::OR32_IMM   => state.reg[dst] = (state.reg[dst] as u32             | insn.imm as u32)      as u64,
+        ebpf::OR32_REG   => state.reg[dst] = (state.reg[dst] as u32             | state.reg[src] as u32) as u64,
+        ebpf::AND32_IMM  => state.reg[dst] = (state.reg[dst] as u32             & insn.imm as u32)      as u64,
+        ebpf::AND32_REG  => state.reg[dst] = (state.reg[dst] as u32             & state.reg[src] as u32) as u64,
+        ebpf::LSH32_IMM  => state.reg[dst] = (state.reg[dst] as u32).wrapping_shl(insn.imm as u32)      as u64,
+        ebpf::LSH32_REG  => state.reg[dst] = (state.reg[dst] as u32).wrapping_shl(state.reg[src] as u32) as u64,
+        ebpf::RSH32_IMM  => state.reg[dst] = (state.reg[dst] as u32).wrapping_shr(insn.imm as u32)      as u64,
+        ebpf::RSH32_REG  => state.reg[dst] = (state.reg[dst] as u32).wrapping_shr(state.reg[src] as u32) as u64,
+        ebpf::NEG32      if !state.sbpf_version.disable_neg() => state.reg[dst] = (state.reg[dst] as i32).wrapping_neg()                     as u64 & (u32::MAX as u64),
+        ebpf::LD_4B_REG  if state.sbpf_version.move_memory_instruction_classes() => {
+            let vm_addr = (state.reg[src] as i64).wrapping_add(insn.off as i64) as u64;
+            state.reg[dst] = translate_memory_access!(state, load, vm_addr, u32);
+        },
+        ebpf::MOD32_IMM  if !state.sbpf_version.enable_pqr() => state.reg[dst] = (state.reg[dst] as u32             % insn.imm as u32)      as u64,
+        ebpf::MOD32_REG  if !state.sbpf_version.enable_pqr() => {
+            throw_error!(DivideByZero; state.reg[src], u32);
+                            state.reg[dst] = (state.reg[dst] as u32             % state.reg[src] as u32) as u64;
+        },
+        ebpf::LD_8B_REG  if state.sbpf_version.move_memory_instruction_classes() => {
+            let vm_addr = (state.reg[src] as i64).wrapping_add(insn.off as i64) as u64;
+            state.reg[dst] = translate_memory_access!(state, load, vm_addr, u64);
+        },
+        ebpf::XOR32_IMM  => state.reg[dst] = (state.reg[dst] as u32             ^ insn.imm as u32)      as u64,
+        ebpf::XOR32_REG  => state.reg[dst] = (state.reg[dst] as u32             ^ state.reg[src] as u32) as u64,
+        ebpf::MOV32_IMM  => state.reg[dst] = insn.imm as u32 as u64,
+        ebpf::MOV32_REG  => state.reg[dst] = if state.sbpf_version.explicit_sign_extension_of_results() {
+            state.reg[src] as i32 as i64 as u64
+        } else {
+            state.reg[src] as u32 as u64
+        },
+        ebpf::ARSH32_IMM => state.reg[dst] = (state.reg[dst] as i32).wrapping_shr(insn.imm as u32)      as u32 as u64,
+        ebpf::ARSH32_REG => state.reg[dst] = (state.reg[dst] as i32).wrapping_shr(state.reg[src] as u32) as u32 as u64,
+        ebpf::LE if !state.sbpf_version.disable_le() => {
+            state.reg[dst] = match insn.imm {
+                16 => (state.reg[dst] as u16).to_le() as u64,
+                32 => (state.reg[dst] as u32).to_le() as u64,
+                64 =>  state.reg[dst].to_le(),
+                _  => {
+                    return Err(EbpfError::InvalidInstruction);
+                }
+            };
+        },
+        ebpf::BE         => {
+            state.reg[dst] = match insn.imm {
+                16 => (state.reg[dst] as u16).to_be() as u64,
+                32 => (state.reg[dst] as u32).to_be() as u64,
+                64 =>  state.reg[dst].to_be(),
+                _  => {
+                    return Err(EbpfError::InvalidInstruction);
+                }
+            };
+        },
+
+        // BPF_ALU64_STORE class
+        ebpf::ADD64_IMM  => state.reg[dst] =  state.reg[dst].wrapping_add(insn.imm as u64),
+        ebpf::ADD64_REG  => state.reg[dst] =  state.reg[dst].wrapping_add(state.reg[src]),
+        ebpf::SUB64_IMM  => if state.sbpf_version.swap_sub_reg_imm_operands() {
+                            state.reg[dst] =  (insn.imm as u64).wrapping_sub(state.reg[dst])
+        } else {
+                            state.reg[dst] =  state.reg[dst].wrapping_sub(insn.imm as u64)
+        },
+        ebpf::SUB64_REG  => state.reg[dst] =  state.reg[dst].wrapping_sub(state.reg[src]),
+        ebpf::MUL64_IMM  if !state.sbpf_version.enable_pqr() => state.reg[dst] =  state.reg[dst].wrapping_mul(insn.imm as u64),
+        ebpf::ST_1B_IMM  if state.sbpf_version.move_memory_instruction_classes() => {
+            let vm_addr = (state.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
+            translate_memory_access!(state, store, insn.imm, vm_addr, u8);
+        },
+        ebpf::MUL64_REG  if !state.sbpf_version.enable_pqr() => state.reg[dst] =  state.reg[dst].wrapping_mul(state.reg[src]),
+        ebpf::ST_1B_REG  if state.sbpf_version.move_memory_instruction_classes() => {
+            let vm_addr = (state.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
+            translate_memory_access!(state, store, state.reg[src], vm_addr, u8);
+        },
+        ebpf::DIV64_IMM  if !state.sbpf_version.enable_pqr() => state.reg[dst] /= insn.imm as u64,
+        ebpf::ST_2B_IMM  if state.sbpf_version.move_memory_instruction_classes() => {
+            let vm_addr = (state.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
+            translate_memory_access!(state, store, insn.imm, vm_addr, u16);
+        },
+        ebpf::DIV64_REG  if !state.sbpf_version.enable_pqr() => {
+            throw_error!(DivideByZero; state.reg[src], u64);
+                            state.reg[dst] /= state.reg[src];
+        },
+        ebpf::ST_2B_REG  if state.sbpf_version.move_memory_instruction_classes() => {
+            let vm_addr = (state.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
+            translate_memory_access!(state, store, state.reg[src], vm_addr, u16);
+        },
+        ebpf::OR64_IMM   => state.reg[dst] |= insn.imm as u64,
+        ebpf::OR64_REG   => state.reg[dst] |= state.reg[src],
+        ebpf::AND64_IMM  => state.reg[dst] &= insn.imm as u64,
+        ebpf::AND64_REG  => state.reg[dst] &= state.reg[src],
+        ebpf::LSH64_IMM  => state.reg[dst] =  state.reg[dst].wrapping_shl(insn.imm as u32),
+        ebpf::LSH64_REG  => state.reg[dst] =  state.reg[dst].wrapping_shl(state.reg[src] as u32),
+        ebpf::RSH64_IMM  => state.reg[dst] =  state.reg[dst].wrapping_shr(insn.imm as u32),
+        ebpf::RSH64_REG  => state.reg[dst] =  state.reg[dst].wrapping_shr(state.reg[src] as u32),
+        ebpf::ST_4B_IMM  if state.sbpf_version.move_memory_instruction_classes() => {
+            let vm_addr = (state.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
+            translate_memory_access!(state, store, insn.imm, vm_addr, u32);
+        },
+        ebpf::NEG64      if !state.sbpf_version.disable_neg() => state.reg[dst] = (state.reg[dst] as i64).wrapping_neg() as u64,
+        ebpf::ST_4B_REG  if state.sbpf_version.move_memory_instruction_classes() => {
+            let vm_addr = (state.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
+            translate_memory_access!(state, store, state.reg[src], vm_addr, u32);
+        },
+        ebpf::MOD64_IMM  if !state.sbpf_version.enable_pqr() => state.reg[dst] %= insn.imm as u64,
+        ebpf::ST_8B_IMM  if state.sbpf_version.move_memory_instruction_classes() => {
+            let vm_addr = (state.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
+            translate_memory_access!(state, store, insn.imm, vm_addr, u64);
+        },
+        ebpf::MOD64_REG  if !state.sbpf_version.enable_pqr() => {
+            throw_error!(DivideByZero; state.reg[src], u64);
+                            state.reg[dst] %= state.reg[src];
+        },
+        ebpf::ST_8B_REG  if state.sbpf_version.move_memory_instruction_classes() => {
+            let vm_addr = (state.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
+            translate_memory_access!(state, store, state.reg[src], vm_addr, u64);
+        },
+        ebpf::XOR64_IMM  => state.reg[dst] ^= insn.imm as u64,
+        ebpf::XOR64_REG  => state.reg[dst] ^= state.reg[src],
+        ebpf::MOV64_IMM  => state.reg[dst] =  insn.imm as u64,
+        ebpf::MOV64_REG  => state.reg[dst] =  state.reg[src],
+        ebpf::ARSH64_IMM => state.reg[dst] = (state.reg[dst] as i64).wrapping_shr(insn.imm as u32)      as u64,
+        ebpf::ARSH64_REG => state.reg[dst] = (state.reg[dst] as i64).wrapping_shr(state.reg[src] as u32) as u64,
+        ebpf::HOR64_IMM if state.sbpf_version.disable_lddw() => {
+            state.reg[dst] |= (insn.imm as u64).wrapping_shl(32);
+        }
+
+        // BPF_PQR class
+        ebpf::LMUL32_IMM if state.sbpf_version.enable_pqr() => state.reg[dst] = (state.reg[dst] as u32).wrapping_mul(insn.imm as u32) as u64,
+        ebpf::LMUL32_REG if state.sbpf_version.enable_pqr() => state.reg[dst] = (state.reg[dst] as u32).wrapping_mul(state.reg[src] as u32) as u64,
+        ebpf::LMUL64_IMM if state.sbpf_version.enable_pqr() => state.reg[dst] = state.reg[dst].wrapping_mul(insn.imm as u64),
+        ebpf::LMUL64_REG if state.sbpf_version.enable_pqr() => state.reg[dst] = state.reg[dst].wrapping_mul(state.reg[src]),
+        ebpf::UHMUL64_IMM if state.sbpf_version.enable_pqr() => state.reg[dst] = (state.reg[dst] as u128).wrapping_mul(insn.imm as u32 as u128).wrapping_shr(64) as u64,
+        ebpf::UHMUL64_REG if state.sbpf_version.enable_pqr() => state.reg[dst] = (state.reg[dst] as u128).wrapping_mul(state.reg[src] as u128).wrapping_shr(64) as u64,
+        ebpf::SHMUL64_IMM if state.sbpf_version.enable_pqr() => state.reg[dst] = (state.reg[dst] as i64 as i128).wrapping_mul(insn.imm as i128).wrapping_shr(64) as u64,
+        ebpf::SHMUL64_REG if state.sbpf_version.enable_pqr() => state.reg[dst] = (state.reg[dst] as i64 as i128).wrapping_mul(state.reg[src] as i64 as i128).wrapping_shr(64) as u64,
+        ebpf::UDIV32_IMM if state.sbpf_version.enable_pqr() => {
+                            state.reg[dst] = (state.reg[dst] as u32 / insn.imm as u32)      as u64;
+        }
+        ebpf::UDIV32_REG if state.sbpf_version.enable_pqr() => {
+            throw_error!(DivideByZero; state.reg[src], u32);
+                            state.reg[dst] = (state.reg[dst] as u32 / state.reg[src] as u32) as u64;
+        },
+        ebpf::UDIV64_IMM if state.sbpf_version.enable_pqr() => {
+                            state.reg[dst] /= insn.imm as u32 as u64;
+        }
+        ebpf::UDIV64_REG if state.sbpf_version.enable_pqr() => {
+            throw_error!(DivideByZero; state.reg[src], u64);
+                            state.reg[dst] /= state.reg[src];
+        },
+        ebpf::UREM32_IMM if state.sbpf_version.enable_pqr() => {
+                            state.reg[dst] = (state.reg[dst] as u32 % insn.imm as u32)      as u64;
+        }
+        ebpf::UREM32_REG if state.sbpf_version.enable_pqr() => {
+            throw_error!(DivideByZero; state.reg[src], u32);
+                            state.reg[dst] = (state.reg[dst] as u32 % state.reg[src] as u32) as u64;
+        },
+        ebpf::UREM64_IMM if state.sbpf_version.enable_pqr() => {
+                            state.reg[dst] %= insn.imm as u32 as u64;
+        }
+        ebpf::UREM64_REG if state.sbpf_version.enable_pqr() => {
+            throw_error!(DivideByZero; state.reg[src], u64);
+                            state.reg[dst] %= state.reg[src];
+        },
+        ebpf::SDIV32_IMM if state.sbpf_version.enable_pqr() => {
+            throw_error!(DivideOverflow; insn.imm, state.reg[dst], i32);
+                            state.reg[dst] = (state.reg[dst] as i32 / insn.imm as i32)      as u32 as u64;
+        }
+        ebpf::SDIV32_REG if state.sbpf_version.enable_pqr() => {
+            throw_error!(DivideByZero; state.reg[src], i32);
+            throw_error!(DivideOverflow; state.reg[src], state.reg[dst], i32);
+                            state.reg[dst] = (state.reg[dst] as i32 / state.reg[src] as i32) as u32 as u64;
+        },
+        ebpf::SDIV64_IMM if state.sbpf_version.enable_pqr() => {
+            throw_error!(DivideOverflow; insn.imm, state.reg[dst], i64);
+                            state.reg[dst] = (state.reg[dst] as i64 / insn.imm)             as u64;
+        }
+        ebpf::SDIV64_REG if state.sbpf_version.enable_pqr() => {
+            throw_error!(DivideByZero; state.reg[src], i64);
+            throw_error!(DivideOverflow; state.reg[src], state.reg[dst], i64);
+                            state.reg[dst] = (state.reg[dst] as i64 / state.reg[src] as i64) as u64;
+        },
+        ebpf::SREM32_IMM if state.sbpf_version.enable_pqr() => {
+            throw_error!(DivideOverflow; insn.imm, state.reg[dst], i32);
+                            state.reg[dst] = (state.reg[dst] as i32 % insn.imm as i32)      as u32 as u64;
+        }
+        ebpf::SREM32_REG if state.sbpf_version.enable_pqr() => {
+            throw_error!(DivideByZero; state.reg[src], i32);
+            throw_error!(DivideOverflow; state.reg[src], state.reg[dst], i32);
+                            state.reg[dst] = (state.reg[dst] as i32 % state.reg[src] as i32) as u32 as u64;
+        },
+        ebpf::SREM64_IMM if state.sbpf_version.enable_pqr() => {
+            throw_error!(DivideOverflow; insn.imm, state.reg[dst], i64);
+                            state.reg[dst] = (state.reg[dst] as i64 % insn.imm)             as u64;
+        }
+        ebpf::SREM64_REG if state.sbpf_version.enable_pqr() => {
+            throw_error!(DivideByZero; state.reg[src], i64);
+            throw_error!(DivideOverflow; state.reg[src], state.reg[dst], i64);
+                            state.reg[dst] = (state.reg[dst] as i64 % state.reg[src] as i64) as u64;
+        },
+
+        // BPF_JMP class
+        ebpf::JA         =>                                                   { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
+        ebpf::JEQ_IMM    => if  state.reg[dst] == insn.imm as u64              { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
+        ebpf::JEQ_REG    => if  state.reg[dst] == state.reg[src]                { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
+        ebpf::JGT_IMM    => if  state.reg[dst] >  insn.imm as u64              { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
+        ebpf::JGT_REG    => if  state.reg[dst] >  state.reg[src]                { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
+        ebpf::JGE_IMM    => if  state.reg[dst] >= insn.imm as u64              { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
+        ebpf::JGE_REG    => if  state.reg[dst] >= state.reg[src]                { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
+        ebpf::JLT_IMM    => if  state.reg[dst] <  insn.imm as u64              { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
+        ebpf::JLT_REG    => if  state.reg[dst] <  state.reg[src]                { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
+        ebpf::JLE_IMM    => if  state.reg[dst] <= insn.imm as u64              { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
+        ebpf::JLE_REG    => if  state.reg[dst] <= state.reg[src]                { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
+        ebpf::JSET_IMM   => if  state.reg[dst] &  insn.imm as u64 != 0         { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
+        ebpf::JSET_REG   => if  state.reg[dst] &  state.reg[src] != 0           { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
+        ebpf::JNE_IMM    => if  state.reg[dst] != insn.imm as u64              { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
+        ebpf::JNE_REG    => if  state.reg[dst] != state.reg[src]                { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
+        ebpf::JSGT_IMM   => if (state.reg[dst] as i64) >  insn.imm             { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
+        ebpf::JSGT_REG   => if (state.reg[dst] as i64) >  state.reg[src] as i64 { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
+        ebpf::JSGE_IMM   => if (state.reg[dst] as i64) >= insn.imm             { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
+        ebpf::JSGE_REG   => if (state.reg[dst] as i64) >= state.reg[src] as i64 { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
+        ebpf::JSLT_IMM   => if (state.reg[dst] as i64) <  insn.imm             { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
+        ebpf::JSLT_REG   => if (state.reg[dst] as i64) <  state.reg[src] as i64 { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
+        ebpf::JSLE_IMM   => if (state.reg[dst] as i64) <= insn.imm             { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
+        ebpf::JSLE_REG   => if (state.reg[dst] as i64) <= state.reg[src] as i64 { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
+        _ => return Err(EbpfError::UnsupportedInstruction),
+    }
+
+    state.reg[11] = next_pc;
+    Ok(())
+}
+
 /// State of the interpreter during a debugging session
 #[cfg(feature = "debugger")]
 pub enum DebugState {
@@ -96,9 +564,18 @@ pub struct Interpreter<'a, 'b, C: ContextObject> {
     pub(crate) executable: &'a Executable<C>,
     pub(crate) program: &'a [u8],
     pub(crate) program_vm_addr: u64,
+    /// See [Executable::predecode]
+    predecoded_instructions: Option<&'a [ebpf::Insn]>,
 
     /// General purpose registers and pc
     pub reg: [u64; 12],
+    /// Number of instructions stepped, the timestamp of the [ExecutionEvent]s
+    pub(crate) instruction_count: u64,
+    /// Set if the instruction meter ran out before the instruction at the pc was executed
+    pub(crate) meter_exhausted: bool,
+    /// Set if there are any breakpoints or watchpoints, so [Interpreter::step] checks them
+    has_debug_hooks: bool,
+    debug_hooks: DebugHooks,
 
     #[cfg(feature = "debugger")]
     pub(crate) debug_state: DebugState,
@@ -119,7 +596,12 @@ impl<'a, 'b, C: ContextObject> Interpreter<'a, 'b, C> {
             executable,
             program,
             program_vm_addr,
+            predecoded_instructions: executable.get_predecoded_instructions(),
             reg: registers,
+            instruction_count: 0,
+            meter_exhausted: false,
+            has_debug_hooks: false,
+            debug_hooks: DebugHooks::default(),
             #[cfg(feature = "debugger")]
             debug_state: DebugState::Continue,
             #[cfg(feature = "debugger")]
@@ -142,8 +624,10 @@ impl<'a, 'b, C: ContextObject> Interpreter<'a, 'b, C> {
         frame.target_pc = self.reg[11] + 1;
 
         self.vm.call_depth += 1;
+        self.vm.max_observed_call_depth = self.vm.max_observed_call_depth.max(self.vm.call_depth);
         if self.vm.call_depth as usize == config.max_call_depth {
-            throw_error!(self, EbpfError::CallDepthExceeded);
+            let call_frames = &self.vm.call_frames[..self.vm.call_depth as usize];
+            throw_error!(self, call_depth_exceeded(self.reg[11], call_frames));
         }
 
         if !self.executable.get_sbpf_version().dynamic_stack_frames() {
@@ -156,351 +640,225 @@ impl<'a, 'b, C: ContextObject> Interpreter<'a, 'b, C> {
         true
     }
 
-    fn sign_extension(&self, value: i32) -> u64 {
-        if self
-            .executable
-            .get_sbpf_version()
-            .explicit_sign_extension_of_results()
-        {
-            value as u32 as u64
+    fn emit_event(&mut self, event: impl FnOnce() -> ExecutionEvent) {
+        let instruction_count = self.instruction_count;
+        if let Some(events) = self.vm.context_object_pointer.execution_events() {
+            events.event(instruction_count, event());
+        }
+    }
+
+    fn is_call_filtered(&self, target_pc: u64) -> bool {
+        self.vm
+            .call_filter
+            .is_some_and(|call_filter| call_filter.is_banned(target_pc as usize))
+    }
+
+    /// Program counter of the next instruction
+    pub fn pc(&self) -> usize {
+        self.reg[11] as usize
+    }
+
+    /// General purpose registers and pc
+    pub fn registers(&self) -> &[u64; 12] {
+        &self.reg
+    }
+
+    /// Instructions the program can still execute before it exceeds the instruction meter
+    pub fn remaining_instruction_meter(&self) -> u64 {
+        if self.executable.get_config().enable_instruction_meter {
+            self.vm
+                .previous_instruction_meter
+                .saturating_sub(self.vm.due_insn_count)
         } else {
-            value as i64 as u64
+            self.vm.context_object_pointer.get_remaining()
         }
     }
 
-    /// Advances the interpreter state by one instruction
+    /// Executes the next instruction
     ///
-    /// Returns false if the program terminated or threw an error.
-    #[rustfmt::skip]
-    pub fn step(&mut self) -> bool {
-        let config = &self.executable.get_config();
-
-        if config.enable_instruction_meter && self.vm.due_insn_count >= self.vm.previous_instruction_meter {
-            throw_error!(self, EbpfError::ExceededMaxInstructions);
+    /// Once the program exited or was aborted the instructions it executed are consumed from
+    /// the instruction meter, the same as at the end of [EbpfVm::execute_program]. Stepping
+    /// further is not supported.
+    pub fn step(&mut self) -> StepResult {
+        if self.has_debug_hooks {
+            return self.step_with_debug_hooks();
         }
-        self.vm.due_insn_count += 1;
-        if self.reg[11] as usize * ebpf::INSN_SIZE >= self.program.len() {
-            throw_error!(self, EbpfError::ExecutionOverrun);
+        if self.advance() {
+            StepResult::Continue
+        } else {
+            self.finish()
         }
-        let mut next_pc = self.reg[11] + 1;
-        let mut insn = ebpf::get_insn_unchecked(self.program, self.reg[11] as usize);
-        let dst = insn.dst as usize;
-        let src = insn.src as usize;
+    }
 
-        if config.enable_instruction_tracing {
-            self.vm.context_object_pointer.trace(self.reg);
+    /// Steps until the program exits, is aborted or pauses
+    pub fn run(&mut self) -> StepResult {
+        loop {
+            match self.step() {
+                StepResult::Continue => {}
+                result => return result,
+            }
         }
+    }
 
-        match insn.opc {
-            ebpf::LD_DW_IMM if !self.executable.get_sbpf_version().disable_lddw() => {
-                ebpf::augment_lddw_unchecked(self.program, &mut insn);
-                self.reg[dst] = insn.imm as u64;
-                self.reg[11] += 1;
-                next_pc += 1;
-            },
+    /// Pauses the program before it executes the instruction at `pc`
+    ///
+    /// Fails with [EbpfError::InvalidBreakpoint] if `pc` is outside of the text section or the
+    /// second slot of an lddw.
+    pub fn set_breakpoint(&mut self, pc: usize) -> Result<(), EbpfError> {
+        let second_slots = lddw_second_slots(self.program, self.executable.get_sbpf_version());
+        if second_slots.get(pc).copied().unwrap_or(true) {
+            return Err(EbpfError::InvalidBreakpoint { pc });
+        }
+        self.debug_hooks.breakpoints.insert(pc);
+        self.update_has_debug_hooks();
+        Ok(())
+    }
 
-            // BPF_LDX class
-            ebpf::LD_B_REG  if !self.executable.get_sbpf_version().move_memory_instruction_classes() => {
-                let vm_addr = (self.reg[src] as i64).wrapping_add(insn.off as i64) as u64;
-                self.reg[dst] = translate_memory_access!(self, load, vm_addr, u8);
-            },
-            ebpf::LD_H_REG  if !self.executable.get_sbpf_version().move_memory_instruction_classes() => {
-                let vm_addr = (self.reg[src] as i64).wrapping_add(insn.off as i64) as u64;
-                self.reg[dst] = translate_memory_access!(self, load, vm_addr, u16);
-            },
-            ebpf::LD_W_REG  if !self.executable.get_sbpf_version().move_memory_instruction_classes() => {
-                let vm_addr = (self.reg[src] as i64).wrapping_add(insn.off as i64) as u64;
-                self.reg[dst] = translate_memory_access!(self, load, vm_addr, u32);
-            },
-            ebpf::LD_DW_REG if !self.executable.get_sbpf_version().move_memory_instruction_classes() => {
-                let vm_addr = (self.reg[src] as i64).wrapping_add(insn.off as i64) as u64;
-                self.reg[dst] = translate_memory_access!(self, load, vm_addr, u64);
-            },
+    /// Removes the breakpoint at `pc`, returns false if there was none
+    pub fn clear_breakpoint(&mut self, pc: usize) -> bool {
+        let removed = self.debug_hooks.breakpoints.remove(&pc);
+        self.update_has_debug_hooks();
+        removed
+    }
 
-            // BPF_ST class
-            ebpf::ST_B_IMM  if !self.executable.get_sbpf_version().move_memory_instruction_classes() => {
-                let vm_addr = (self.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
-                translate_memory_access!(self, store, insn.imm, vm_addr, u8);
-            },
-            ebpf::ST_H_IMM  if !self.executable.get_sbpf_version().move_memory_instruction_classes() => {
-                let vm_addr = (self.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
-                translate_memory_access!(self, store, insn.imm, vm_addr, u16);
-            },
-            ebpf::ST_W_IMM  if !self.executable.get_sbpf_version().move_memory_instruction_classes() => {
-                let vm_addr = (self.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
-                translate_memory_access!(self, store, insn.imm, vm_addr, u32);
-            },
-            ebpf::ST_DW_IMM if !self.executable.get_sbpf_version().move_memory_instruction_classes() => {
-                let vm_addr = (self.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
-                translate_memory_access!(self, store, insn.imm, vm_addr, u64);
-            },
+    /// Pauses the program after a load or store instruction of `access_type` accesses any byte
+    /// in `vm_range`
+    ///
+    /// Memory accessed by syscalls is not watched.
+    pub fn set_watchpoint(&mut self, vm_range: Range<u64>, access_type: AccessType) {
+        self.debug_hooks.watchpoints.push((vm_range, access_type));
+        self.update_has_debug_hooks();
+    }
 
-            // BPF_STX class
-            ebpf::ST_B_REG  if !self.executable.get_sbpf_version().move_memory_instruction_classes() => {
-                let vm_addr = (self.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
-                translate_memory_access!(self, store, self.reg[src], vm_addr, u8);
-            },
-            ebpf::ST_H_REG  if !self.executable.get_sbpf_version().move_memory_instruction_classes() => {
-                let vm_addr = (self.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
-                translate_memory_access!(self, store, self.reg[src], vm_addr, u16);
-            },
-            ebpf::ST_W_REG  if !self.executable.get_sbpf_version().move_memory_instruction_classes() => {
-                let vm_addr = (self.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
-                translate_memory_access!(self, store, self.reg[src], vm_addr, u32);
-            },
-            ebpf::ST_DW_REG if !self.executable.get_sbpf_version().move_memory_instruction_classes() => {
-                let vm_addr = (self.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
-                translate_memory_access!(self, store, self.reg[src], vm_addr, u64);
-            },
+    /// Removes a watchpoint added by [Interpreter::set_watchpoint], returns false if there was
+    /// none
+    pub fn clear_watchpoint(&mut self, vm_range: Range<u64>, access_type: AccessType) -> bool {
+        let watchpoints = &mut self.debug_hooks.watchpoints;
+        let len = watchpoints.len();
+        watchpoints.retain(|watchpoint| *watchpoint != (vm_range.clone(), access_type));
+        let removed = watchpoints.len() != len;
+        self.update_has_debug_hooks();
+        removed
+    }
 
-            // BPF_ALU32_LOAD class
-            ebpf::ADD32_IMM  => self.reg[dst] = self.sign_extension((self.reg[dst] as i32).wrapping_add(insn.imm as i32)),
-            ebpf::ADD32_REG  => self.reg[dst] = self.sign_extension((self.reg[dst] as i32).wrapping_add(self.reg[src] as i32)),
-            ebpf::SUB32_IMM  => if self.executable.get_sbpf_version().swap_sub_reg_imm_operands() {
-                                self.reg[dst] = self.sign_extension((insn.imm as i32).wrapping_sub(self.reg[dst] as i32))
-            } else {
-                                self.reg[dst] = self.sign_extension((self.reg[dst] as i32).wrapping_sub(insn.imm as i32))
-            },
-            ebpf::SUB32_REG  => self.reg[dst] = self.sign_extension((self.reg[dst] as i32).wrapping_sub(self.reg[src] as i32)),
-            ebpf::MUL32_IMM  if !self.executable.get_sbpf_version().enable_pqr() => self.reg[dst] = (self.reg[dst] as i32).wrapping_mul(insn.imm as i32)      as u64,
-            ebpf::MUL32_REG  if !self.executable.get_sbpf_version().enable_pqr() => self.reg[dst] = (self.reg[dst] as i32).wrapping_mul(self.reg[src] as i32) as u64,
-            ebpf::LD_1B_REG  if self.executable.get_sbpf_version().move_memory_instruction_classes() => {
-                let vm_addr = (self.reg[src] as i64).wrapping_add(insn.off as i64) as u64;
-                self.reg[dst] = translate_memory_access!(self, load, vm_addr, u8);
-            },
-            ebpf::DIV32_IMM  if !self.executable.get_sbpf_version().enable_pqr() => self.reg[dst] = (self.reg[dst] as u32             / insn.imm as u32)      as u64,
-            ebpf::DIV32_REG  if !self.executable.get_sbpf_version().enable_pqr() => {
-                throw_error!(DivideByZero; self, self.reg[src], u32);
-                                self.reg[dst] = (self.reg[dst] as u32             / self.reg[src] as u32) as u64;
-            },
-            ebpf::LD_2B_REG  if self.executable.get_sbpf_version().move_memory_instruction_classes() => {
-                let vm_addr = (self.reg[src] as i64).wrapping_add(insn.off as i64) as u64;
-                self.reg[dst] = translate_memory_access!(self, load, vm_addr, u16);
-            },
-            ebpf::OR32_IMM   => self.reg[dst] = (self.reg[dst] as u32             | insn.imm as u32)      as u64,
-            ebpf::OR32_REG   => self.reg[dst] = (self.reg[dst] as u32             | self.reg[src] as u32) as u64,
-            ebpf::AND32_IMM  => self.reg[dst] = (self.reg[dst] as u32             & insn.imm as u32)      as u64,
-            ebpf::AND32_REG  => self.reg[dst] = (self.reg[dst] as u32             & self.reg[src] as u32) as u64,
-            ebpf::LSH32_IMM  => self.reg[dst] = (self.reg[dst] as u32).wrapping_shl(insn.imm as u32)      as u64,
-            ebpf::LSH32_REG  => self.reg[dst] = (self.reg[dst] as u32).wrapping_shl(self.reg[src] as u32) as u64,
-            ebpf::RSH32_IMM  => self.reg[dst] = (self.reg[dst] as u32).wrapping_shr(insn.imm as u32)      as u64,
-            ebpf::RSH32_REG  => self.reg[dst] = (self.reg[dst] as u32).wrapping_shr(self.reg[src] as u32) as u64,
-            ebpf::NEG32      if !self.executable.get_sbpf_version().disable_neg() => self.reg[dst] = (self.reg[dst] as i32).wrapping_neg()                     as u64 & (u32::MAX as u64),
-            ebpf::LD_4B_REG  if self.executable.get_sbpf_version().move_memory_instruction_classes() => {
-                let vm_addr = (self.reg[src] as i64).wrapping_add(insn.off as i64) as u64;
-                self.reg[dst] = translate_memory_access!(self, load, vm_addr, u32);
-            },
-            ebpf::MOD32_IMM  if !self.executable.get_sbpf_version().enable_pqr() => self.reg[dst] = (self.reg[dst] as u32             % insn.imm as u32)      as u64,
-            ebpf::MOD32_REG  if !self.executable.get_sbpf_version().enable_pqr() => {
-                throw_error!(DivideByZero; self, self.reg[src], u32);
-                                self.reg[dst] = (self.reg[dst] as u32             % self.reg[src] as u32) as u64;
-            },
-            ebpf::LD_8B_REG  if self.executable.get_sbpf_version().move_memory_instruction_classes() => {
-                let vm_addr = (self.reg[src] as i64).wrapping_add(insn.off as i64) as u64;
-                self.reg[dst] = translate_memory_access!(self, load, vm_addr, u64);
-            },
-            ebpf::XOR32_IMM  => self.reg[dst] = (self.reg[dst] as u32             ^ insn.imm as u32)      as u64,
-            ebpf::XOR32_REG  => self.reg[dst] = (self.reg[dst] as u32             ^ self.reg[src] as u32) as u64,
-            ebpf::MOV32_IMM  => self.reg[dst] = insn.imm as u32 as u64,
-            ebpf::MOV32_REG  => self.reg[dst] = if self.executable.get_sbpf_version().explicit_sign_extension_of_results() {
-                self.reg[src] as i32 as i64 as u64
-            } else {
-                self.reg[src] as u32 as u64
-            },
-            ebpf::ARSH32_IMM => self.reg[dst] = (self.reg[dst] as i32).wrapping_shr(insn.imm as u32)      as u32 as u64,
-            ebpf::ARSH32_REG => self.reg[dst] = (self.reg[dst] as i32).wrapping_shr(self.reg[src] as u32) as u32 as u64,
-            ebpf::LE if !self.executable.get_sbpf_version().disable_le() => {
-                self.reg[dst] = match insn.imm {
-                    16 => (self.reg[dst] as u16).to_le() as u64,
-                    32 => (self.reg[dst] as u32).to_le() as u64,
-                    64 =>  self.reg[dst].to_le(),
-                    _  => {
-                        throw_error!(self, EbpfError::InvalidInstruction);
-                    }
-                };
-            },
-            ebpf::BE         => {
-                self.reg[dst] = match insn.imm {
-                    16 => (self.reg[dst] as u16).to_be() as u64,
-                    32 => (self.reg[dst] as u32).to_be() as u64,
-                    64 =>  self.reg[dst].to_be(),
-                    _  => {
-                        throw_error!(self, EbpfError::InvalidInstruction);
-                    }
-                };
-            },
+    fn update_has_debug_hooks(&mut self) {
+        self.has_debug_hooks =
+            !self.debug_hooks.breakpoints.is_empty() || !self.debug_hooks.watchpoints.is_empty();
+    }
 
-            // BPF_ALU64_STORE class
-            ebpf::ADD64_IMM  => self.reg[dst] =  self.reg[dst].wrapping_add(insn.imm as u64),
-            ebpf::ADD64_REG  => self.reg[dst] =  self.reg[dst].wrapping_add(self.reg[src]),
-            ebpf::SUB64_IMM  => if self.executable.get_sbpf_version().swap_sub_reg_imm_operands() {
-                                self.reg[dst] =  (insn.imm as u64).wrapping_sub(self.reg[dst])
-            } else {
-                                self.reg[dst] =  self.reg[dst].wrapping_sub(insn.imm as u64)
-            },
-            ebpf::SUB64_REG  => self.reg[dst] =  self.reg[dst].wrapping_sub(self.reg[src]),
-            ebpf::MUL64_IMM  if !self.executable.get_sbpf_version().enable_pqr() => self.reg[dst] =  self.reg[dst].wrapping_mul(insn.imm as u64),
-            ebpf::ST_1B_IMM  if self.executable.get_sbpf_version().move_memory_instruction_classes() => {
-                let vm_addr = (self.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
-                translate_memory_access!(self, store, insn.imm, vm_addr, u8);
-            },
-            ebpf::MUL64_REG  if !self.executable.get_sbpf_version().enable_pqr() => self.reg[dst] =  self.reg[dst].wrapping_mul(self.reg[src]),
-            ebpf::ST_1B_REG  if self.executable.get_sbpf_version().move_memory_instruction_classes() => {
-                let vm_addr = (self.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
-                translate_memory_access!(self, store, self.reg[src], vm_addr, u8);
-            },
-            ebpf::DIV64_IMM  if !self.executable.get_sbpf_version().enable_pqr() => self.reg[dst] /= insn.imm as u64,
-            ebpf::ST_2B_IMM  if self.executable.get_sbpf_version().move_memory_instruction_classes() => {
-                let vm_addr = (self.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
-                translate_memory_access!(self, store, insn.imm, vm_addr, u16);
-            },
-            ebpf::DIV64_REG  if !self.executable.get_sbpf_version().enable_pqr() => {
-                throw_error!(DivideByZero; self, self.reg[src], u64);
-                                self.reg[dst] /= self.reg[src];
-            },
-            ebpf::ST_2B_REG  if self.executable.get_sbpf_version().move_memory_instruction_classes() => {
-                let vm_addr = (self.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
-                translate_memory_access!(self, store, self.reg[src], vm_addr, u16);
-            },
-            ebpf::OR64_IMM   => self.reg[dst] |= insn.imm as u64,
-            ebpf::OR64_REG   => self.reg[dst] |= self.reg[src],
-            ebpf::AND64_IMM  => self.reg[dst] &= insn.imm as u64,
-            ebpf::AND64_REG  => self.reg[dst] &= self.reg[src],
-            ebpf::LSH64_IMM  => self.reg[dst] =  self.reg[dst].wrapping_shl(insn.imm as u32),
-            ebpf::LSH64_REG  => self.reg[dst] =  self.reg[dst].wrapping_shl(self.reg[src] as u32),
-            ebpf::RSH64_IMM  => self.reg[dst] =  self.reg[dst].wrapping_shr(insn.imm as u32),
-            ebpf::RSH64_REG  => self.reg[dst] =  self.reg[dst].wrapping_shr(self.reg[src] as u32),
-            ebpf::ST_4B_IMM  if self.executable.get_sbpf_version().move_memory_instruction_classes() => {
-                let vm_addr = (self.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
-                translate_memory_access!(self, store, insn.imm, vm_addr, u32);
-            },
-            ebpf::NEG64      if !self.executable.get_sbpf_version().disable_neg() => self.reg[dst] = (self.reg[dst] as i64).wrapping_neg() as u64,
-            ebpf::ST_4B_REG  if self.executable.get_sbpf_version().move_memory_instruction_classes() => {
-                let vm_addr = (self.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
-                translate_memory_access!(self, store, self.reg[src], vm_addr, u32);
-            },
-            ebpf::MOD64_IMM  if !self.executable.get_sbpf_version().enable_pqr() => self.reg[dst] %= insn.imm as u64,
-            ebpf::ST_8B_IMM  if self.executable.get_sbpf_version().move_memory_instruction_classes() => {
-                let vm_addr = (self.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
-                translate_memory_access!(self, store, insn.imm, vm_addr, u64);
-            },
-            ebpf::MOD64_REG  if !self.executable.get_sbpf_version().enable_pqr() => {
-                throw_error!(DivideByZero; self, self.reg[src], u64);
-                                self.reg[dst] %= self.reg[src];
-            },
-            ebpf::ST_8B_REG  if self.executable.get_sbpf_version().move_memory_instruction_classes() => {
-                let vm_addr = (self.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
-                translate_memory_access!(self, store, self.reg[src], vm_addr, u64);
-            },
-            ebpf::XOR64_IMM  => self.reg[dst] ^= insn.imm as u64,
-            ebpf::XOR64_REG  => self.reg[dst] ^= self.reg[src],
-            ebpf::MOV64_IMM  => self.reg[dst] =  insn.imm as u64,
-            ebpf::MOV64_REG  => self.reg[dst] =  self.reg[src],
-            ebpf::ARSH64_IMM => self.reg[dst] = (self.reg[dst] as i64).wrapping_shr(insn.imm as u32)      as u64,
-            ebpf::ARSH64_REG => self.reg[dst] = (self.reg[dst] as i64).wrapping_shr(self.reg[src] as u32) as u64,
-            ebpf::HOR64_IMM if self.executable.get_sbpf_version().disable_lddw() => {
-                self.reg[dst] |= (insn.imm as u64).wrapping_shl(32);
+    fn step_with_debug_hooks(&mut self) -> StepResult {
+        let pc = self.pc();
+        if self.debug_hooks.resumed_breakpoint.take() != Some(pc)
+            && self.debug_hooks.breakpoints.contains(&pc)
+        {
+            self.debug_hooks.resumed_breakpoint = Some(pc);
+            return StepResult::Paused(PauseReason::Breakpoint { pc });
+        }
+        let watched_access = if pc.saturating_mul(ebpf::INSN_SIZE) < self.program.len() {
+            let insn = ebpf::get_insn_unchecked(self.program, pc);
+            memory_access(&insn, &self.reg, self.executable.get_sbpf_version()).filter(
+                |(access_type, vm_addr, len)| {
+                    let access = *vm_addr..vm_addr.saturating_add(*len);
+                    self.debug_hooks
+                        .watchpoints
+                        .iter()
+                        .any(|(vm_range, watched_type)| {
+                            watched_type == access_type
+                                && access.start < vm_range.end
+                                && vm_range.start < access.end
+                        })
+                },
+            )
+        } else {
+            None
+        };
+        let old_value = watched_access
+            .and_then(|(_, vm_addr, len)| load_value(&self.vm.memory_mapping, vm_addr, len));
+        if !self.advance() {
+            return self.finish();
+        }
+        match (watched_access, old_value) {
+            (Some((access_type, vm_addr, len)), Some(old_value)) => {
+                let new_value =
+                    load_value(&self.vm.memory_mapping, vm_addr, len).unwrap_or(old_value);
+                StepResult::Paused(PauseReason::Watchpoint {
+                    pc,
+                    access_type,
+                    vm_addr,
+                    len,
+                    old_value,
+                    new_value,
+                })
             }
+            _ => StepResult::Continue,
+        }
+    }
 
-            // BPF_PQR class
-            ebpf::LMUL32_IMM if self.executable.get_sbpf_version().enable_pqr() => self.reg[dst] = (self.reg[dst] as u32).wrapping_mul(insn.imm as u32) as u64,
-            ebpf::LMUL32_REG if self.executable.get_sbpf_version().enable_pqr() => self.reg[dst] = (self.reg[dst] as u32).wrapping_mul(self.reg[src] as u32) as u64,
-            ebpf::LMUL64_IMM if self.executable.get_sbpf_version().enable_pqr() => self.reg[dst] = self.reg[dst].wrapping_mul(insn.imm as u64),
-            ebpf::LMUL64_REG if self.executable.get_sbpf_version().enable_pqr() => self.reg[dst] = self.reg[dst].wrapping_mul(self.reg[src]),
-            ebpf::UHMUL64_IMM if self.executable.get_sbpf_version().enable_pqr() => self.reg[dst] = (self.reg[dst] as u128).wrapping_mul(insn.imm as u32 as u128).wrapping_shr(64) as u64,
-            ebpf::UHMUL64_REG if self.executable.get_sbpf_version().enable_pqr() => self.reg[dst] = (self.reg[dst] as u128).wrapping_mul(self.reg[src] as u128).wrapping_shr(64) as u64,
-            ebpf::SHMUL64_IMM if self.executable.get_sbpf_version().enable_pqr() => self.reg[dst] = (self.reg[dst] as i64 as i128).wrapping_mul(insn.imm as i128).wrapping_shr(64) as u64,
-            ebpf::SHMUL64_REG if self.executable.get_sbpf_version().enable_pqr() => self.reg[dst] = (self.reg[dst] as i64 as i128).wrapping_mul(self.reg[src] as i64 as i128).wrapping_shr(64) as u64,
-            ebpf::UDIV32_IMM if self.executable.get_sbpf_version().enable_pqr() => {
-                                self.reg[dst] = (self.reg[dst] as u32 / insn.imm as u32)      as u64;
-            }
-            ebpf::UDIV32_REG if self.executable.get_sbpf_version().enable_pqr() => {
-                throw_error!(DivideByZero; self, self.reg[src], u32);
-                                self.reg[dst] = (self.reg[dst] as u32 / self.reg[src] as u32) as u64;
-            },
-            ebpf::UDIV64_IMM if self.executable.get_sbpf_version().enable_pqr() => {
-                                self.reg[dst] /= insn.imm as u32 as u64;
-            }
-            ebpf::UDIV64_REG if self.executable.get_sbpf_version().enable_pqr() => {
-                throw_error!(DivideByZero; self, self.reg[src], u64);
-                                self.reg[dst] /= self.reg[src];
-            },
-            ebpf::UREM32_IMM if self.executable.get_sbpf_version().enable_pqr() => {
-                                self.reg[dst] = (self.reg[dst] as u32 % insn.imm as u32)      as u64;
-            }
-            ebpf::UREM32_REG if self.executable.get_sbpf_version().enable_pqr() => {
-                throw_error!(DivideByZero; self, self.reg[src], u32);
-                                self.reg[dst] = (self.reg[dst] as u32 % self.reg[src] as u32) as u64;
-            },
-            ebpf::UREM64_IMM if self.executable.get_sbpf_version().enable_pqr() => {
-                                self.reg[dst] %= insn.imm as u32 as u64;
-            }
-            ebpf::UREM64_REG if self.executable.get_sbpf_version().enable_pqr() => {
-                throw_error!(DivideByZero; self, self.reg[src], u64);
-                                self.reg[dst] %= self.reg[src];
-            },
-            ebpf::SDIV32_IMM if self.executable.get_sbpf_version().enable_pqr() => {
-                throw_error!(DivideOverflow; self, insn.imm, self.reg[dst], i32);
-                                self.reg[dst] = (self.reg[dst] as i32 / insn.imm as i32)      as u32 as u64;
+    /// Consumes the executed instructions and takes the result of the terminated program
+    fn finish(&mut self) -> StepResult {
+        self.emit_error_event();
+        if self.executable.get_config().enable_instruction_meter {
+            self.vm
+                .context_object_pointer
+                .consume(self.vm.due_insn_count);
+            self.vm.due_insn_count = 0;
+            self.vm.previous_instruction_meter = self.vm.context_object_pointer.get_remaining();
+        }
+        match core::mem::replace(&mut self.vm.program_result, ProgramResult::Ok(0)) {
+            ProgramResult::Ok(value) => StepResult::Exited(value),
+            ProgramResult::Err(err) => StepResult::Error(err),
+        }
+    }
+
+    pub(crate) fn emit_error_event(&mut self) {
+        if let ProgramResult::Err(err) = &self.vm.program_result {
+            let message = err.to_string();
+            let instruction_count = self.instruction_count;
+            if let Some(events) = self.vm.context_object_pointer.execution_events() {
+                events.event(instruction_count, ExecutionEvent::Error(message));
             }
-            ebpf::SDIV32_REG if self.executable.get_sbpf_version().enable_pqr() => {
-                throw_error!(DivideByZero; self, self.reg[src], i32);
-                throw_error!(DivideOverflow; self, self.reg[src], self.reg[dst], i32);
-                                self.reg[dst] = (self.reg[dst] as i32 / self.reg[src] as i32) as u32 as u64;
-            },
-            ebpf::SDIV64_IMM if self.executable.get_sbpf_version().enable_pqr() => {
-                throw_error!(DivideOverflow; self, insn.imm, self.reg[dst], i64);
-                                self.reg[dst] = (self.reg[dst] as i64 / insn.imm)             as u64;
+        }
+    }
+
+    /// Advances the interpreter state by one instruction
+    ///
+    /// Returns false if the program terminated or threw an error.
+    #[rustfmt::skip]
+    pub(crate) fn advance(&mut self) -> bool {
+        let config = &self.executable.get_config();
+
+        if config.enable_instruction_meter && self.vm.due_insn_count >= self.vm.previous_instruction_meter {
+            self.meter_exhausted = true;
+            throw_error!(self, EbpfError::ExceededMaxInstructions);
+        }
+        self.vm.due_insn_count += 1;
+        self.instruction_count += 1;
+        let decoded_insn;
+        let insn = if let Some(predecoded_instructions) = self.predecoded_instructions {
+            match predecoded_instructions.get(self.reg[11] as usize) {
+                Some(insn) => insn,
+                None => throw_error!(self, EbpfError::ExecutionOverrun),
             }
-            ebpf::SDIV64_REG if self.executable.get_sbpf_version().enable_pqr() => {
-                throw_error!(DivideByZero; self, self.reg[src], i64);
-                throw_error!(DivideOverflow; self, self.reg[src], self.reg[dst], i64);
-                                self.reg[dst] = (self.reg[dst] as i64 / self.reg[src] as i64) as u64;
-            },
-            ebpf::SREM32_IMM if self.executable.get_sbpf_version().enable_pqr() => {
-                throw_error!(DivideOverflow; self, insn.imm, self.reg[dst], i32);
-                                self.reg[dst] = (self.reg[dst] as i32 % insn.imm as i32)      as u32 as u64;
+        } else {
+            if self.reg[11] as usize * ebpf::INSN_SIZE >= self.program.len() {
+                throw_error!(self, EbpfError::ExecutionOverrun);
             }
-            ebpf::SREM32_REG if self.executable.get_sbpf_version().enable_pqr() => {
-                throw_error!(DivideByZero; self, self.reg[src], i32);
-                throw_error!(DivideOverflow; self, self.reg[src], self.reg[dst], i32);
-                                self.reg[dst] = (self.reg[dst] as i32 % self.reg[src] as i32) as u32 as u64;
-            },
-            ebpf::SREM64_IMM if self.executable.get_sbpf_version().enable_pqr() => {
-                throw_error!(DivideOverflow; self, insn.imm, self.reg[dst], i64);
-                                self.reg[dst] = (self.reg[dst] as i64 % insn.imm)             as u64;
+            let mut insn = ebpf::get_insn_unchecked(self.program, self.reg[11] as usize);
+            if insn.opc == ebpf::LD_DW_IMM && !self.executable.get_sbpf_version().disable_lddw() {
+                ebpf::augment_lddw_unchecked(self.program, &mut insn);
             }
-            ebpf::SREM64_REG if self.executable.get_sbpf_version().enable_pqr() => {
-                throw_error!(DivideByZero; self, self.reg[src], i64);
-                throw_error!(DivideOverflow; self, self.reg[src], self.reg[dst], i64);
-                                self.reg[dst] = (self.reg[dst] as i64 % self.reg[src] as i64) as u64;
-            },
+            decoded_insn = insn;
+            &decoded_insn
+        };
+        let mut next_pc = self.reg[11] + 1;
+        let src = insn.src as usize;
 
-            // BPF_JMP class
-            ebpf::JA         =>                                                   { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
-            ebpf::JEQ_IMM    => if  self.reg[dst] == insn.imm as u64              { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
-            ebpf::JEQ_REG    => if  self.reg[dst] == self.reg[src]                { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
-            ebpf::JGT_IMM    => if  self.reg[dst] >  insn.imm as u64              { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
-            ebpf::JGT_REG    => if  self.reg[dst] >  self.reg[src]                { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
-            ebpf::JGE_IMM    => if  self.reg[dst] >= insn.imm as u64              { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
-            ebpf::JGE_REG    => if  self.reg[dst] >= self.reg[src]                { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
-            ebpf::JLT_IMM    => if  self.reg[dst] <  insn.imm as u64              { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
-            ebpf::JLT_REG    => if  self.reg[dst] <  self.reg[src]                { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
-            ebpf::JLE_IMM    => if  self.reg[dst] <= insn.imm as u64              { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
-            ebpf::JLE_REG    => if  self.reg[dst] <= self.reg[src]                { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
-            ebpf::JSET_IMM   => if  self.reg[dst] &  insn.imm as u64 != 0         { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
-            ebpf::JSET_REG   => if  self.reg[dst] &  self.reg[src] != 0           { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
-            ebpf::JNE_IMM    => if  self.reg[dst] != insn.imm as u64              { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
-            ebpf::JNE_REG    => if  self.reg[dst] != self.reg[src]                { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
-            ebpf::JSGT_IMM   => if (self.reg[dst] as i64) >  insn.imm             { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
-            ebpf::JSGT_REG   => if (self.reg[dst] as i64) >  self.reg[src] as i64 { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
-            ebpf::JSGE_IMM   => if (self.reg[dst] as i64) >= insn.imm             { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
-            ebpf::JSGE_REG   => if (self.reg[dst] as i64) >= self.reg[src] as i64 { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
-            ebpf::JSLT_IMM   => if (self.reg[dst] as i64) <  insn.imm             { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
-            ebpf::JSLT_REG   => if (self.reg[dst] as i64) <  self.reg[src] as i64 { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
-            ebpf::JSLE_IMM   => if (self.reg[dst] as i64) <= insn.imm             { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
-            ebpf::JSLE_REG   => if (self.reg[dst] as i64) <= self.reg[src] as i64 { next_pc = (next_pc as i64 + insn.off as i64) as u64; },
+        if config.enable_instruction_tracing
+            && self.vm.context_object_pointer.trace_hook(self.reg[11] as usize, &self.reg) == TraceControl::Stop {
+            throw_error!(self, EbpfError::TraceStopped);
+        }
 
+        match insn.opc {
             ebpf::CALL_REG   => {
                 let target_pc = if self.executable.get_sbpf_version().callx_uses_src_reg() {
                     self.reg[src]
@@ -511,21 +869,29 @@ impl<'a, 'b, C: ContextObject> Interpreter<'a, 'b, C> {
                     return false;
                 }
                 check_pc!(self, next_pc, target_pc.wrapping_sub(self.program_vm_addr) / ebpf::INSN_SIZE as u64);
+                if self.is_call_filtered(next_pc) {
+                    throw_error!(self, EbpfError::CallFiltered { target_pc: next_pc as usize });
+                }
                 if self.executable.get_sbpf_version().static_syscalls() && self.executable.get_function_registry().lookup_by_key(next_pc as u32).is_none() {
                     throw_error!(self, EbpfError::UnsupportedInstruction);
                 }
+                let depth = self.vm.call_depth;
+                self.emit_event(|| ExecutionEvent::FrameEnter { target_pc: next_pc as usize, depth });
             },
 
             // Do not delegate the check to the verifier, since self.registered functions can be
             // changed after the program has been verified.
             ebpf::CALL_IMM => {
-                if let (false, Some((_, function))) =
+                if let (false, Some((name, function, metadata))) =
                         (self.executable.get_sbpf_version().static_syscalls(),
-                            self.executable.get_loader().get_function_registry().lookup_by_key(insn.imm as u32)) {
+                            self.executable.get_loader().lookup_by_key(insn.imm as u32)) {
                     // SBPFv0 syscall
-                    self.reg[0] = match self.dispatch_syscall(function) {
+                    self.reg[0] = match self.dispatch_syscall(name, function, metadata) {
                         ProgramResult::Ok(value) => *value,
-                        ProgramResult::Err(_err) => return false,
+                        ProgramResult::Err(_err) => {
+                            self.vm.registers = self.reg;
+                            return false;
+                        }
                     };
                 } else if let Some((_, target_pc)) =
                         self.executable
@@ -540,17 +906,25 @@ impl<'a, 'b, C: ContextObject> Interpreter<'a, 'b, C> {
                     if !self.push_frame(config) {
                         return false;
                     }
+                    if self.is_call_filtered(target_pc as u64) {
+                        throw_error!(self, EbpfError::CallFiltered { target_pc });
+                    }
                     check_pc!(self, next_pc, target_pc as u64);
+                    let depth = self.vm.call_depth;
+                    self.emit_event(|| ExecutionEvent::FrameEnter { target_pc, depth });
                 } else {
                     throw_error!(self, EbpfError::UnsupportedInstruction);
                 }
             }
             ebpf::SYSCALL if self.executable.get_sbpf_version().static_syscalls() => {
-                if let Some((_, function)) = self.executable.get_loader().get_function_registry().lookup_by_key(insn.imm as u32) {
+                if let Some((name, function, metadata)) = self.executable.get_loader().lookup_by_key(insn.imm as u32) {
                     // SBPFv3 syscall
-                    self.reg[0] = match self.dispatch_syscall(function) {
+                    self.reg[0] = match self.dispatch_syscall(name, function, metadata) {
                         ProgramResult::Ok(value) => *value,
-                        ProgramResult::Err(_err) => return false,
+                        ProgramResult::Err(_err) => {
+                            self.vm.registers = self.reg;
+                            return false;
+                        }
                     };
                 } else {
                     debug_assert!(false, "Invalid syscall should have been detected in the verifier.");
@@ -568,6 +942,8 @@ impl<'a, 'b, C: ContextObject> Interpreter<'a, 'b, C> {
                         throw_error!(self, EbpfError::ExceededMaxInstructions);
                     }
                     self.vm.program_result = ProgramResult::Ok(self.reg[0]);
+                    let pc = self.reg[11] as usize;
+                    self.emit_event(|| ExecutionEvent::FrameExit { pc, depth: 0 });
                     return false;
                 }
                 // Return from BPF to BPF call
@@ -578,19 +954,66 @@ impl<'a, 'b, C: ContextObject> Interpreter<'a, 'b, C> {
                     ..ebpf::FIRST_SCRATCH_REG + ebpf::SCRATCH_REGS]
                     .copy_from_slice(&frame.caller_saved_registers);
                 check_pc!(self, next_pc, frame.target_pc);
+                let (pc, depth) = (self.reg[11] as usize, self.vm.call_depth + 1);
+                self.emit_event(|| ExecutionEvent::FrameExit { pc, depth });
+            }
+            _ => {
+                let access = if self.vm.memory_access_stats.is_some() {
+                    memory_access(insn, &self.reg, self.executable.get_sbpf_version())
+                } else {
+                    None
+                };
+                let mut state = InsnState {
+                    reg: &mut self.reg,
+                    memory_mapping: &self.vm.memory_mapping,
+                    sbpf_version: self.executable.get_sbpf_version(),
+                };
+                if let Err(err) = exec_one(insn, &mut state) {
+                    throw_error!(self, err);
+                }
+                if let (Some(stats), Some((access_type, vm_addr, len))) =
+                    (self.vm.memory_access_stats.as_mut(), access)
+                {
+                    stats.record(access_type, vm_addr, len);
+                }
+                if insn.opc == ebpf::ADD64_IMM && insn.dst as usize == ebpf::FRAME_PTR_REG {
+                    // Only dynamic stack frames allow programs to move the frame pointer
+                    self.vm.lowest_stack_pointer =
+                        self.vm.lowest_stack_pointer.min(self.reg[ebpf::FRAME_PTR_REG]);
+                }
+                return true;
             }
-            _ => throw_error!(self, EbpfError::UnsupportedInstruction),
         }
 
         self.reg[11] = next_pc;
         true
     }
 
-    fn dispatch_syscall(&mut self, function: BuiltinFunction<C>) -> &ProgramResult {
+    fn dispatch_syscall(
+        &mut self,
+        name: &[u8],
+        function: BuiltinFunction<C>,
+        metadata: SyscallMetadata,
+    ) -> &ProgramResult {
+        let config = self.executable.get_config();
+        if config.enable_instruction_meter && config.charge_syscall_base_costs {
+            self.vm.due_insn_count = self.vm.due_insn_count.saturating_add(metadata.base_cost);
+            if self.vm.due_insn_count > self.vm.previous_instruction_meter {
+                self.vm.program_result = ProgramResult::Err(EbpfError::ExceededMaxInstructions);
+                return &self.vm.program_result;
+            }
+        }
+        let pc = self.reg[11] as usize;
+        self.emit_event(|| ExecutionEvent::SyscallEnter { pc, name: name.to_vec() });
         self.vm.due_insn_count = self.vm.previous_instruction_meter - self.vm.due_insn_count;
         self.vm.registers[0..6].copy_from_slice(&self.reg[0..6]);
         self.vm.invoke_function(function);
         self.vm.due_insn_count = 0;
+        let return_value = match self.vm.program_result {
+            ProgramResult::Ok(return_value) => Some(return_value),
+            ProgramResult::Err(_) => None,
+        };
+        self.emit_event(|| ExecutionEvent::SyscallExit { pc, return_value });
         &self.vm.program_result
     }
 }
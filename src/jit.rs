@@ -25,19 +25,19 @@ use rand::{
     SeedableRng,
 };
 use core::{fmt::Debug, mem, ptr};
-use alloc::{vec, vec::Vec};
+use alloc::{format, string::String, vec, vec::Vec};
 use jam_pvm_common::{info};
 
 use crate::{
     ebpf::{self, FIRST_SCRATCH_REG, FRAME_PTR_REG, INSN_SIZE, SCRATCH_REGS},
     elf::Executable,
-    error::{EbpfError, ProgramResult},
+    error::{EbpfError, InternalError, ProgramResult},
     memory_management::{
         allocate_pages, free_pages, get_system_page_size, protect_pages, round_to_page_size,
     },
     memory_region::MemoryMapping,
-    program::BuiltinFunction,
-    vm::{get_runtime_environment_key, Config, ContextObject, EbpfVm, RuntimeEnvironmentSlot},
+    program::{BuiltinFunction, FunctionRegistry, SyscallMetadata},
+    vm::{call_depth_exceeded, get_runtime_environment_key, CallFrame, Config, ContextObject, EbpfVm, RuntimeEnvironmentSlot, TraceControl},
     x86::{
         FenceType, X86IndirectAccess, X86Instruction,
         X86Register::{self, *},
@@ -53,6 +53,136 @@ pub const MAX_MACHINE_CODE_LENGTH_PER_INSTRUCTION: usize = 110;
 pub const MACHINE_CODE_PER_INSTRUCTION_METER_CHECKPOINT: usize = 24;
 /// The maximum machine code length of the randomized padding
 pub const MAX_START_PADDING_LENGTH: usize = 256;
+/// The maximum machine code length in bytes of the instruction tracing of a single guest instruction
+pub const MACHINE_CODE_PER_INSTRUCTION_TRACE: usize = 17;
+/// The maximum machine code length in bytes of a single guest instruction by opcode
+///
+/// Excludes instruction meter checkpoints and instruction tracing. An LDDW counts both of its
+/// slots. Opcodes which no [crate::program::SBPFVersion] supports are zero. Debug builds of the
+/// compiler assert that every instruction stays within its length.
+#[rustfmt::skip]
+pub const MAX_MACHINE_CODE_LENGTH_PER_OPCODE: [usize; 256] = [
+      0,   0,   0,   0,  23,  28,   0,  40,   0,   0,   0,   0,   6,   0,   0,   3, // 0x00
+      0,   0,   0,   0,  23,  66,   0,  23,  30,   0,   0,   0,   6,  49,   0,   3, // 0x10
+      0,   0,   0,   0,  33,  66,   0,  46,   0,   0,   0,   0,  31,  49,   0,  36, // 0x20
+      0,   0,   0,   0,  32,  66,  41,  47,   0,   0,   0,   0,  33,  49,  16,  37, // 0x30
+      0,   0,   0,   0,  20,  66,  43,  20,   0,   0,   0,   0,   3,  49,  33,   3, // 0x40
+      0,   0,   0,   0,  20,  66,  44,  20,   0,   0,   0,   0,   3,  49,  34,   3, // 0x50
+      0,  31,  46,  36,   4,  66,  43,   4,   0,  31,  47,  37,  15,  49,  33,  13, // 0x60
+      0,  31,  46,  36,   4,  66,  44,   4,   0,  31,  46,  36,  15,  49,  34,  13, // 0x70
+      0,   0,   0,   0,   3, 100,  30,  46,   0,   0,   0,   0,  31,  50,  16,  36, // 0x80
+      0,   0,   0,   0,  32,  85,  30,  46,   0,   0,   0,   0,  33,  45,  16,  36, // 0x90
+      0,   0,   0,   0,  20,  66,   0,  20,   0,   0,   0,   0,   3,  49,   0,   3, // 0xA0
+      0,   0,   0,   0,  23,  66,  30,  17,   0,   0,   0,   0,   3,  49,  16,   3, // 0xB0
+      0,   0,   0,   0,   4,  66,  43,   4,   0,   0,   0,   0,  15,  49,  78,  13, // 0xC0
+      0,   0,   0,   0,   7,  66,  47,   0,   0,   0,   0,   0,  12,  49,  82,   0, // 0xD0
+      0,   0,   0,   0,   0,   0,  43,   0,   0,   0,   0,   0,   0,   0,  78,   0, // 0xE0
+      0,   0,   0,   0,   0,   0,  47,  21,   0,   0,   0,   0,   0,   0,  82,   0, // 0xF0
+];
+
+/// Upper bound of the machine code length in bytes of the compiled executable
+///
+/// Is calculated from the text section and the config without compiling, so that hosts can
+/// reject programs before spending memory on them, see [Config::max_machine_code_length].
+/// With [Config::noop_instruction_rate] (and without [Config::deterministic_jit_output]) the
+/// bound doubles, because in the worst case every host instruction is followed by a no-op.
+pub fn estimate_machine_code_size<C: ContextObject>(executable: &Executable<C>) -> usize {
+    let config = executable.get_config();
+    let (_program_vm_addr, program) = executable.get_text_bytes();
+    let block_ends = if config.enable_instruction_meter {
+        instruction_meter_block_ends(executable)
+    } else {
+        Vec::new()
+    };
+    let mut machine_code_length = MAX_EMPTY_PROGRAM_MACHINE_CODE_LENGTH;
+    let mut pc = 0;
+    while (pc + 1) * ebpf::INSN_SIZE <= program.len() {
+        let opcode = program[pc * ebpf::INSN_SIZE];
+        machine_code_length += MAX_MACHINE_CODE_LENGTH_PER_OPCODE[opcode as usize];
+        if config.enable_instruction_tracing {
+            machine_code_length += MACHINE_CODE_PER_INSTRUCTION_TRACE;
+        }
+        if config.enable_instruction_meter && is_instruction_meter_block_start(&block_ends, pc) {
+            machine_code_length += MACHINE_CODE_PER_INSTRUCTION_METER_CHECKPOINT;
+        }
+        pc += if opcode == ebpf::LD_DW_IMM && !executable.get_sbpf_version().disable_lddw() {
+            2
+        } else {
+            1
+        };
+    }
+    if config.noop_instruction_rate != 0 && !config.deterministic_jit_output {
+        machine_code_length = machine_code_length * 2 + MAX_START_PADDING_LENGTH;
+    }
+    machine_code_length
+}
+
+/// Last pc of the instruction meter block of every pc
+///
+/// Blocks are straight-line code which is only entered at its start, so the JIT validates the
+/// instruction meter once for the whole block there. They end at branches, calls, exits and
+/// lddw, before jump and call targets and after `instruction_meter_checkpoint_distance`
+/// instructions. With instruction tracing every instruction is a block of its own.
+fn instruction_meter_block_ends<C: ContextObject>(executable: &Executable<C>) -> Vec<u32> {
+    let config = executable.get_config();
+    let sbpf_version = executable.get_sbpf_version();
+    let (_program_vm_addr, program) = executable.get_text_bytes();
+    let instruction_count = program.len() / ebpf::INSN_SIZE;
+    let mut is_block_start = vec![config.enable_instruction_tracing; instruction_count + 1];
+    let mut mark_block_start = |pc: usize| {
+        if let Some(is_block_start) = is_block_start.get_mut(pc) {
+            *is_block_start = true;
+        }
+    };
+    mark_block_start(0);
+    mark_block_start(executable.get_entrypoint_instruction_offset());
+    for (_key, (_name, target_pc)) in executable.get_function_registry().iter() {
+        mark_block_start(target_pc);
+    }
+    let is_lddw = |pc: usize| {
+        ebpf::get_insn_unchecked(program, pc).opc == ebpf::LD_DW_IMM && !sbpf_version.disable_lddw()
+    };
+    let mut pc = 0;
+    while pc < instruction_count {
+        let insn = ebpf::get_insn_unchecked(program, pc);
+        if is_lddw(pc) {
+            mark_block_start(pc + 1);
+            mark_block_start(pc + 2);
+        } else if insn.opc & ebpf::BPF_CLS_MASK == ebpf::BPF_JMP {
+            mark_block_start(pc + 1);
+            if let Some(target_pc) = (pc as isize).checked_add(insn.off as isize + 1) {
+                mark_block_start(target_pc as usize);
+            }
+        }
+        pc += if is_lddw(pc) { 2 } else { 1 };
+    }
+    // Split long blocks
+    let mut block_length = 0;
+    let mut pc = 0;
+    while pc < instruction_count {
+        if !is_block_start[pc] && block_length >= config.instruction_meter_checkpoint_distance {
+            is_block_start[pc] = true;
+        }
+        if is_block_start[pc] {
+            block_length = 0;
+        }
+        block_length += 1;
+        pc += if is_lddw(pc) { 2 } else { 1 };
+    }
+    let mut block_ends = vec![0; instruction_count];
+    for pc in (0..instruction_count).rev() {
+        block_ends[pc] = if is_block_start[pc + 1] || pc + 1 == instruction_count {
+            pc as u32
+        } else {
+            block_ends[pc + 1]
+        };
+    }
+    block_ends
+}
+
+fn is_instruction_meter_block_start(block_ends: &[u32], pc: usize) -> bool {
+    pc == 0 || block_ends[pc - 1] < pc as u32
+}
 
 /// The program compiled to native host machinecode
 pub struct JitProgram {
@@ -62,33 +192,80 @@ pub struct JitProgram {
     pc_section: &'static mut [u32],
     /// The x86 machinecode
     text_section: &'static mut [u8],
+    /// Byte offset in the text_section where the code of the last instruction ends
+    instructions_end: usize,
+    /// Byte offset in the text_section for each BPF instruction, `None` for the second slot of
+    /// lddw. Only recorded if [Config::record_jit_instruction_offsets] is enabled.
+    instruction_offsets: Option<Vec<Option<u32>>>,
+    /// Last pc of the instruction meter block of each BPF instruction, empty if the instruction
+    /// meter is disabled. Follows the pc_section in the same pages.
+    instruction_meter_block_ends: &'static mut [u32],
+    /// First pc of the instruction meter block of each BPF instruction, empty if the instruction
+    /// meter is disabled. Follows the instruction_meter_block_ends in the same pages.
+    instruction_meter_block_starts: &'static mut [u32],
+}
+
+/// Host machine code of a JIT compiled function, see [JitProgram::symbols]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JitSymbol {
+    /// Name in the function registry, `fn_<pc>` if it has none
+    pub name: String,
+    /// Program counter of the first instruction, `None` for code shared by all functions
+    pub pc: Option<usize>,
+    /// Start host address
+    pub host_addr: u64,
+    /// Length in bytes
+    pub len: usize,
 }
 
 impl JitProgram {
-    fn new(pc: usize, code_size: usize) -> Result<Self, EbpfError> {
+    fn new(pc: usize, code_size: usize, instruction_meter_blocks: bool) -> Result<Self, EbpfError> {
         let page_size = get_system_page_size();
-        let pc_loc_table_size = round_to_page_size(pc * core::mem::size_of::<u32>(), page_size);
+        let block_table_len = if instruction_meter_blocks { pc } else { 0 };
+        let pc_loc_table_size = round_to_page_size(
+            (pc + block_table_len * 2) * core::mem::size_of::<u32>(),
+            page_size,
+        );
         let over_allocated_code_size = round_to_page_size(code_size, page_size);
         unsafe {
             let raw = allocate_pages(pc_loc_table_size + over_allocated_code_size)?;
             Ok(Self {
                 page_size,
                 pc_section: core::slice::from_raw_parts_mut(raw.cast::<u32>(), pc),
+                instruction_meter_block_ends: core::slice::from_raw_parts_mut(
+                    raw.cast::<u32>().add(pc),
+                    block_table_len,
+                ),
+                instruction_meter_block_starts: core::slice::from_raw_parts_mut(
+                    raw.cast::<u32>().add(pc + block_table_len),
+                    block_table_len,
+                ),
                 text_section: core::slice::from_raw_parts_mut(
                     raw.add(pc_loc_table_size),
                     over_allocated_code_size,
                 ),
+                instructions_end: 0,
+                instruction_offsets: None,
             })
         }
     }
 
+    /// Size in bytes of the pc_section and the instruction meter block tables
+    fn pc_loc_table_size(&self) -> usize {
+        round_to_page_size(
+            core::mem::size_of_val(self.pc_section)
+                + core::mem::size_of_val(self.instruction_meter_block_ends)
+                + core::mem::size_of_val(self.instruction_meter_block_starts),
+            self.page_size,
+        )
+    }
+
     fn seal(&mut self, text_section_usage: usize) -> Result<(), EbpfError> {
         if self.page_size == 0 {
             return Ok(());
         }
         let raw = self.pc_section.as_ptr() as *mut u8;
-        let pc_loc_table_size =
-            round_to_page_size(core::mem::size_of_val(self.pc_section), self.page_size);
+        let pc_loc_table_size = self.pc_loc_table_size();
         let over_allocated_code_size = round_to_page_size(self.text_section.len(), self.page_size);
         let code_size = round_to_page_size(text_section_usage, self.page_size);
         unsafe {
@@ -181,19 +358,158 @@ impl JitProgram {
         self.text_section.len()
     }
 
+    /// The host machinecode
+    pub fn machine_code(&self) -> &[u8] {
+        self.text_section
+    }
+
+    /// Byte offset in [JitProgram::machine_code] where the code of the instruction at `pc` starts
+    ///
+    /// The offsets increase monotonically with `pc`. Returns `None` for the second slot of lddw,
+    /// for `pc` beyond the end of the program and unless
+    /// [Config::record_jit_instruction_offsets] was enabled during compilation.
+    pub fn pc_to_host_offset(&self, pc: usize) -> Option<usize> {
+        self.instruction_offsets
+            .as_ref()?
+            .get(pc)
+            .copied()
+            .flatten()
+            .map(|offset| offset as usize)
+    }
+
+    /// The host machinecode generated for the instruction at `pc`
+    ///
+    /// This includes instruction meter checkpoints and tracing preceding the instruction.
+    /// See [JitProgram::pc_to_host_offset].
+    pub fn instruction_machine_code(&self, pc: usize) -> Option<&[u8]> {
+        let start = self.pc_to_host_offset(pc)?;
+        let end = self.instruction_offsets.as_ref()?[pc + 1..]
+            .iter()
+            .find_map(|offset| *offset)
+            .map_or(self.instructions_end, |offset| offset as usize);
+        self.text_section.get(start..end)
+    }
+
     /// The total memory used in bytes rounded up to page boundaries
     pub fn mem_size(&self) -> usize {
-        let pc_loc_table_size =
-            round_to_page_size(core::mem::size_of_val(self.pc_section), self.page_size);
+        let pc_loc_table_size = self.pc_loc_table_size();
         let code_size = round_to_page_size(self.text_section.len(), self.page_size);
         pc_loc_table_size + code_size
     }
+
+    /// The host machine code of each function in the `function_registry`, in ascending order
+    ///
+    /// A function spans from its first instruction to the next function, including its
+    /// instruction meter checkpoints. The subroutines (anchors) which precede the first
+    /// instruction are reported as `sbpf_jit_subroutines` and the code following the last
+    /// instruction as `sbpf_jit_epilogue`, so that no address is attributed to the wrong function.
+    pub fn symbols(&self, function_registry: &FunctionRegistry<usize>) -> Vec<JitSymbol> {
+        let text_section_start = self.text_section.as_ptr() as u64;
+        let offset_of = |pc: usize| (self.pc_section[pc] & i32::MAX as u32) as usize;
+        let mut functions = function_registry
+            .iter()
+            .map(|(_key, (name, pc))| (pc, name))
+            .filter(|(pc, _name)| *pc < self.pc_section.len())
+            .collect::<Vec<_>>();
+        functions.sort_by_key(|(pc, _name)| *pc);
+        functions.dedup_by_key(|(pc, _name)| *pc);
+        if !self.pc_section.is_empty() && functions.first().map(|(pc, _name)| *pc) != Some(0) {
+            functions.insert(0, (0, &[]));
+        }
+        let functions_start = functions.first().map_or(self.instructions_end, |(pc, _name)| offset_of(*pc));
+        let mut symbols = vec![JitSymbol {
+            name: String::from("sbpf_jit_subroutines"),
+            pc: None,
+            host_addr: text_section_start,
+            len: functions_start,
+        }];
+        for (index, (pc, name)) in functions.iter().enumerate() {
+            let start = offset_of(*pc);
+            let end = functions.get(index + 1).map_or(self.instructions_end, |(next_pc, _name)| offset_of(*next_pc));
+            symbols.push(JitSymbol {
+                name: match core::str::from_utf8(name) {
+                    Ok(name) if !name.is_empty() => String::from(name),
+                    _ => format!("fn_{pc}"),
+                },
+                pc: Some(*pc),
+                host_addr: text_section_start + start as u64,
+                len: end.saturating_sub(start),
+            });
+        }
+        symbols.push(JitSymbol {
+            name: String::from("sbpf_jit_epilogue"),
+            pc: None,
+            host_addr: text_section_start + self.instructions_end as u64,
+            len: self.text_section.len() - self.instructions_end,
+        });
+        symbols.retain(|symbol| symbol.len > 0);
+        symbols
+    }
+
+    /// Writes the [JitProgram::symbols] in the format of `/tmp/perf-<pid>.map` files
+    pub fn write_perf_map<W: crate::utils::Write>(
+        &self,
+        function_registry: &FunctionRegistry<usize>,
+        output: &mut W,
+    ) -> Result<(), InternalError> {
+        for symbol in self.symbols(function_registry) {
+            writeln!(output, "{:x} {:x} {}", symbol.host_addr, symbol.len, symbol.name)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the [JitProgram::symbols] and their machine code in the jitdump format
+    ///
+    /// See `tools/perf/Documentation/jitdump-specification.txt` in the Linux sources. This crate
+    /// has no access to the process id and clock, so the host passes its `pid` and a
+    /// `CLOCK_MONOTONIC` `timestamp` in nanoseconds. For `perf inject --jit` to find the file
+    /// it has to be named `jit-<pid>.dump` and be mapped executable by the profiled process.
+    pub fn write_jitdump<W: crate::utils::Write>(
+        &self,
+        function_registry: &FunctionRegistry<usize>,
+        pid: u32,
+        timestamp: u64,
+        output: &mut W,
+    ) -> Result<(), InternalError> {
+        const JITDUMP_MAGIC: u32 = 0x4A695444;
+        const JITDUMP_VERSION: u32 = 1;
+        const EM_X86_64: u32 = 62;
+        const JIT_CODE_LOAD: u32 = 0;
+        // File header: magic, version, header size, machine, padding, pid, timestamp, flags
+        output.write_all(&JITDUMP_MAGIC.to_le_bytes())?;
+        output.write_all(&JITDUMP_VERSION.to_le_bytes())?;
+        output.write_all(&40u32.to_le_bytes())?;
+        output.write_all(&EM_X86_64.to_le_bytes())?;
+        output.write_all(&0u32.to_le_bytes())?;
+        output.write_all(&pid.to_le_bytes())?;
+        output.write_all(&timestamp.to_le_bytes())?;
+        output.write_all(&0u64.to_le_bytes())?;
+        let text_section_start = self.text_section.as_ptr() as u64;
+        for (code_index, symbol) in self.symbols(function_registry).iter().enumerate() {
+            // Record header (id, size, timestamp), pid, tid, vma, code_addr, code_size,
+            // code_index, NUL terminated name and the code itself
+            let record_size = 16 + 4 + 4 + 8 * 4 + symbol.name.len() + 1 + symbol.len;
+            output.write_all(&JIT_CODE_LOAD.to_le_bytes())?;
+            output.write_all(&(record_size as u32).to_le_bytes())?;
+            output.write_all(&timestamp.to_le_bytes())?;
+            output.write_all(&pid.to_le_bytes())?;
+            output.write_all(&pid.to_le_bytes())?;
+            output.write_all(&symbol.host_addr.to_le_bytes())?;
+            output.write_all(&symbol.host_addr.to_le_bytes())?;
+            output.write_all(&(symbol.len as u64).to_le_bytes())?;
+            output.write_all(&(code_index as u64).to_le_bytes())?;
+            output.write_all(symbol.name.as_bytes())?;
+            output.write_all(&[0])?;
+            let offset = (symbol.host_addr - text_section_start) as usize;
+            output.write_all(&self.text_section[offset..offset + symbol.len])?;
+        }
+        Ok(())
+    }
 }
 
 impl Drop for JitProgram {
     fn drop(&mut self) {
-        let pc_loc_table_size =
-            round_to_page_size(core::mem::size_of_val(self.pc_section), self.page_size);
+        let pc_loc_table_size = self.pc_loc_table_size();
         let code_size = round_to_page_size(self.text_section.len(), self.page_size);
         if pc_loc_table_size + code_size > 0 {
             unsafe {
@@ -235,8 +551,16 @@ const ANCHOR_CALL_UNSUPPORTED_INSTRUCTION: usize = 11;
 const ANCHOR_EXTERNAL_FUNCTION_CALL: usize = 12;
 const ANCHOR_INTERNAL_FUNCTION_CALL_PROLOGUE: usize = 13;
 const ANCHOR_INTERNAL_FUNCTION_CALL_REG: usize = 14;
-const ANCHOR_TRANSLATE_MEMORY_ADDRESS: usize = 21;
-const ANCHOR_COUNT: usize = 34; // Update me when adding or removing anchors
+const ANCHOR_CALL_FILTER: usize = 15;
+const ANCHOR_CALL_IMM_FILTERED: usize = 16;
+const ANCHOR_CALL_REG_FILTERED: usize = 17;
+const ANCHOR_CALL_FILTERED: usize = 18;
+const ANCHOR_TRACE_STOPPED: usize = 19;
+const ANCHOR_SYSCALL_BASE_COST_EXCEEDED: usize = 20;
+const ANCHOR_INTERPRET_BLOCK: usize = 21;
+const ANCHOR_INTERPRETER_FALLBACK: usize = 22;
+const ANCHOR_TRANSLATE_MEMORY_ADDRESS: usize = 23;
+const ANCHOR_COUNT: usize = 36; // Update me when adding or removing anchors
 
 const REGISTER_MAP: [X86Register; 11] = [
     CALLER_SAVED_REGISTERS[0], // RAX
@@ -299,7 +623,8 @@ struct Jump {
     executed (profiling) and second, limit this number by stopping the program with an exception
     once a given threshold is reached (validation). One approach would be to increment and
     validate the instruction meter before each instruction. However, this would heavily impact
-    performance. Thus, we only profile and validate the instruction meter at branches.
+    performance. Thus, we only profile the instruction meter at branches and validate it at the
+    start of blocks of straight-line code.
 
     For this, we implicitly sum up all the instructions between two branches.
     It is easy to know the end of such a slice of instructions, but how do we know where it
@@ -340,6 +665,15 @@ struct Jump {
     and then after returning use the undo profiling. The trick is, that the undo profiling now
     has the current pc which is the BPF return address. The virtual target pc we count towards
     and undo again can be anything, so we just set it to zero.
+
+    Validation happens once per block, at its start, for all of its instructions: If the maximum
+    pc we can reach is not beyond the last pc of the block, some but not all of the block could
+    still execute. As the JIT can not stop in the middle of a block, it hands the execution over
+    to the interpreter at the start of the block instead, which then stops at the exact
+    instruction or raises any other exception which happens before. Blocks are the instructions
+    between branches, branch targets, calls and function starts, but never longer than
+    `instruction_meter_checkpoint_distance`, see `instruction_meter_block_ends()`. Because callx
+    can jump into the middle of a block, it validates the rest of that block itself.
 */
 
 /// Temporary object which stores the compilation context
@@ -353,9 +687,9 @@ pub struct JitCompiler<'a, C: ContextObject> {
     program_vm_addr: u64,
     config: &'a Config,
     pc: usize,
-    last_instruction_meter_validation_pc: usize,
     next_noop_insertion: u32,
     noop_range: Uniform<u32>,
+    noop_instruction_rate: u32,
     runtime_environment_key: i32,
     immediate_value_key: i64,
     diversification_rng: SmallRng,
@@ -383,22 +717,36 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
             pc = program.len() / ebpf::INSN_SIZE;
         }
 
-        let mut code_length_estimate = MAX_EMPTY_PROGRAM_MACHINE_CODE_LENGTH + MAX_START_PADDING_LENGTH + MAX_MACHINE_CODE_LENGTH_PER_INSTRUCTION * pc;
-        if config.noop_instruction_rate != 0 {
-            code_length_estimate += code_length_estimate / config.noop_instruction_rate as usize;
-        }
-        if config.instruction_meter_checkpoint_distance != 0 {
-            code_length_estimate += pc / config.instruction_meter_checkpoint_distance * MACHINE_CODE_PER_INSTRUCTION_METER_CHECKPOINT;
+        let mut code_length_estimate = estimate_machine_code_size(executable);
+        if let Some(max_machine_code_length) = config.max_machine_code_length {
+            // Leave room for the subroutines and the instruction which crosses the limit
+            code_length_estimate = code_length_estimate.min(max_machine_code_length.max(MAX_EMPTY_PROGRAM_MACHINE_CODE_LENGTH + MAX_START_PADDING_LENGTH) + MAX_MACHINE_CODE_LENGTH_PER_INSTRUCTION * 3);
         }
         // Relative jump destinations limit the maximum output size
         debug_assert!(code_length_estimate < (i32::MAX as usize));
 
         let runtime_environment_key = get_runtime_environment_key();
         let mut diversification_rng = SmallRng::seed_from_u64(1);
-        let immediate_value_key = diversification_rng.random::<i64>();
+        let immediate_value_key = if config.deterministic_jit_output { 0 } else { diversification_rng.random::<i64>() };
+        let noop_instruction_rate = if config.deterministic_jit_output { 0 } else { config.noop_instruction_rate };
+
+        let result = JitProgram::new(pc, code_length_estimate, config.enable_instruction_meter)?;
+        if config.enable_instruction_meter {
+            let block_ends = instruction_meter_block_ends(executable);
+            // A truncated lddw at the end adds a pc which is not in the text section
+            result.instruction_meter_block_ends.fill(u32::MAX);
+            result.instruction_meter_block_ends[..block_ends.len()].copy_from_slice(&block_ends);
+            let mut block_start = 0;
+            for pc in 0..pc {
+                if is_instruction_meter_block_start(result.instruction_meter_block_ends, pc) {
+                    block_start = pc as u32;
+                }
+                result.instruction_meter_block_starts[pc] = block_start;
+            }
+        }
 
         Ok(Self {
-            result: JitProgram::new(pc, code_length_estimate)?,
+            result,
             text_section_jumps: vec![],
             anchors: [core::ptr::null(); ANCHOR_COUNT],
             offset_in_text_section: 0,
@@ -407,9 +755,9 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
             program,
             config,
             pc: 0,
-            last_instruction_meter_validation_pc: 0,
-            next_noop_insertion: if config.noop_instruction_rate == 0 { u32::MAX } else { diversification_rng.random_range(0..config.noop_instruction_rate * 2) },
-            noop_range: Uniform::new_inclusive(0, config.noop_instruction_rate * 2).map_err(|_err| EbpfError::JitNotCompiled)?,
+            next_noop_insertion: if noop_instruction_rate == 0 { u32::MAX } else { diversification_rng.random_range(0..noop_instruction_rate * 2) },
+            noop_range: Uniform::new_inclusive(0, noop_instruction_rate * 2).map_err(|_err| EbpfError::JitNotCompiled)?,
+            noop_instruction_rate,
             runtime_environment_key,
             immediate_value_key,
             diversification_rng,
@@ -420,7 +768,7 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
     /// Compiles the given executable, consuming the compiler
     pub fn compile(mut self) -> Result<JitProgram, EbpfError> {
         // Randomized padding at the start before random intervals begin
-        if self.config.noop_instruction_rate != 0 {
+        if self.noop_instruction_rate != 0 {
             for _ in 0..self.diversification_rng.random_range(0..MAX_START_PADDING_LENGTH) {
                 // X86Instruction::noop().emit(self)?;
                 self.emit::<u8>(0x90);
@@ -429,13 +777,22 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
 
         self.emit_subroutines();
 
+        if self.config.record_jit_instruction_offsets {
+            self.result.instruction_offsets = Some(Vec::with_capacity(self.result.pc_section.len()));
+        }
         let mut function_iter = self.executable.get_function_registry().keys().map(|insn_ptr| insn_ptr as usize).peekable();
         while self.pc * ebpf::INSN_SIZE < self.program.len() {
+            self.check_machine_code_length()?;
             if self.offset_in_text_section + MAX_MACHINE_CODE_LENGTH_PER_INSTRUCTION * 2 >= self.result.text_section.len() {
                 return Err(EbpfError::ExhaustedTextSegment(self.pc));
             }
             let mut insn = ebpf::get_insn_unchecked(self.program, self.pc);
+            let instruction_start = self.offset_in_text_section;
+            let mut max_instruction_length = MAX_MACHINE_CODE_LENGTH_PER_OPCODE[insn.opc as usize];
             self.result.pc_section[self.pc] = self.offset_in_text_section as u32;
+            if let Some(instruction_offsets) = &mut self.result.instruction_offsets {
+                instruction_offsets.push(Some(self.offset_in_text_section as u32));
+            }
             if self.executable.get_sbpf_version().static_syscalls() {
                 if function_iter.peek() == Some(&self.pc) {
                     function_iter.next();
@@ -444,15 +801,17 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
                 }
             }
 
-            // Regular instruction meter checkpoints to prevent long linear runs from exceeding their budget
-            if self.last_instruction_meter_validation_pc + self.config.instruction_meter_checkpoint_distance <= self.pc {
-                self.emit_validate_instruction_count(Some(self.pc));
+            // Validate the instruction meter for the whole block at its start
+            if self.config.enable_instruction_meter && is_instruction_meter_block_start(self.result.instruction_meter_block_ends, self.pc) {
+                self.emit_validate_instruction_meter_block(self.result.instruction_meter_block_ends[self.pc] as usize);
+                max_instruction_length += MACHINE_CODE_PER_INSTRUCTION_METER_CHECKPOINT;
             }
 
             if self.config.enable_instruction_tracing {
                 self.emit_ins(X86Instruction::load_immediate(REGISTER_SCRATCH, self.pc as i64));
                 self.emit_ins(X86Instruction::call_immediate(self.relative_to_anchor(ANCHOR_TRACE, 5)));
                 self.emit_ins(X86Instruction::load_immediate(REGISTER_SCRATCH, 0));
+                max_instruction_length += MACHINE_CODE_PER_INSTRUCTION_TRACE;
             }
 
             let dst = REGISTER_MAP[insn.dst as usize];
@@ -461,9 +820,12 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
 
             match insn.opc {
                 ebpf::LD_DW_IMM if !self.executable.get_sbpf_version().disable_lddw() => {
-                    self.emit_validate_and_profile_instruction_count(Some(self.pc + 2));
+                    self.emit_profile_instruction_count(Some(self.pc + 2));
                     self.pc += 1;
                     self.result.pc_section[self.pc] = unsafe { self.anchors[ANCHOR_CALL_UNSUPPORTED_INSTRUCTION].offset_from(self.result.text_section.as_ptr()) as u32 };
+                    if let Some(instruction_offsets) = &mut self.result.instruction_offsets {
+                        instruction_offsets.push(None);
+                    }
                     ebpf::augment_lddw_unchecked(self.program, &mut insn);
                     if self.should_sanitize_constant(insn.imm) {
                         self.emit_sanitized_load_immediate(dst, insn.imm);
@@ -630,7 +992,17 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
                 },
 
                 // BPF_ALU64_STORE class
-                ebpf::ADD64_IMM  => self.emit_sanitized_alu(OperandSize::S64, 0x01, 0, dst, insn.imm),
+                ebpf::ADD64_IMM  => {
+                    self.emit_sanitized_alu(OperandSize::S64, 0x01, 0, dst, insn.imm);
+                    if insn.dst as usize == FRAME_PTR_REG {
+                        // Only dynamic stack frames allow programs to move the frame pointer
+                        let lowest_stack_pointer_access = X86IndirectAccess::Offset(self.slot_in_vm(RuntimeEnvironmentSlot::LowestStackPointer));
+                        self.emit_ins(X86Instruction::cmp(OperandSize::S64, dst, REGISTER_PTR_TO_VM, Some(lowest_stack_pointer_access)));
+                        let not_lower = self.emit_forward_conditional_jump(0x86);
+                        self.emit_ins(X86Instruction::store(OperandSize::S64, dst, REGISTER_PTR_TO_VM, lowest_stack_pointer_access)); // env.lowest_stack_pointer = REGISTER_MAP[FRAME_PTR_REG];
+                        self.resolve_forward_jump(not_lower);
+                    }
+                },
                 ebpf::ADD64_REG  => self.emit_ins(X86Instruction::alu(OperandSize::S64, 0x01, src, dst, None)),
                 ebpf::SUB64_IMM  => {
                     if self.executable.get_sbpf_version().swap_sub_reg_imm_operands() {
@@ -740,7 +1112,7 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
 
                 // BPF_JMP class
                 ebpf::JA         => {
-                    self.emit_validate_and_profile_instruction_count(Some(target_pc));
+                    self.emit_profile_instruction_count(Some(target_pc));
                     self.emit_ins(X86Instruction::load_immediate(REGISTER_SCRATCH, target_pc as i64));
                     let jump_offset = self.relative_to_target_pc(target_pc, 5);
                     self.emit_ins(X86Instruction::jump_immediate(jump_offset));
@@ -769,11 +1141,11 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
                 ebpf::JSLE_REG   => self.emit_conditional_branch_reg(0x8e, false, src, dst, target_pc),
                 ebpf::CALL_IMM => {
                     // For JIT, external functions MUST be registered at compile time.
-                    if let (false, Some((_, function))) =
+                    if let (false, Some((_, function, metadata))) =
                             (self.executable.get_sbpf_version().static_syscalls(),
-                                self.executable.get_loader().get_function_registry().lookup_by_key(insn.imm as u32)) {
+                                self.executable.get_loader().lookup_by_key(insn.imm as u32)) {
                         // SBPFv0 syscall
-                        self.emit_syscall_dispatch(function);
+                        self.emit_syscall_dispatch(function, metadata);
                     } else if let Some((_function_name, target_pc)) =
                             self.executable
                                 .get_function_registry()
@@ -791,8 +1163,8 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
                     }
                 },
                 ebpf::SYSCALL if self.executable.get_sbpf_version().static_syscalls() => {
-                    if let Some((_, function)) = self.executable.get_loader().get_function_registry().lookup_by_key(insn.imm as u32) {
-                        self.emit_syscall_dispatch(function);
+                    if let Some((_, function, metadata)) = self.executable.get_loader().lookup_by_key(insn.imm as u32) {
+                        self.emit_syscall_dispatch(function, metadata);
                     } else {
                         debug_assert!(false, "Invalid syscall should have been detected in the verifier.")
                     }
@@ -811,7 +1183,7 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
                         || (insn.opc == ebpf::RETURN && !self.executable.get_sbpf_version().static_syscalls()) {
                         return Err(EbpfError::UnsupportedInstruction);
                     }
-                    self.emit_validate_and_profile_instruction_count(Some(0));
+                    self.emit_profile_instruction_count(Some(0));
 
                     let call_depth_access = X86IndirectAccess::Offset(self.slot_in_vm(RuntimeEnvironmentSlot::CallDepth));
                     // If env.call_depth == 0, we've reached the exit instruction of the entry point
@@ -829,25 +1201,42 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
                 _               => return Err(EbpfError::UnsupportedInstruction),
             }
 
+            // Update `MAX_MACHINE_CODE_LENGTH_PER_OPCODE` if this fails
+            debug_assert!(
+                self.noop_instruction_rate != 0 || self.offset_in_text_section - instruction_start <= max_instruction_length,
+                "opcode {:#04x} emitted {} bytes of machine code", insn.opc, self.offset_in_text_section - instruction_start,
+            );
             self.pc += 1;
         }
 
         // Bumper in case there was no final exit
+        self.check_machine_code_length()?;
         if self.offset_in_text_section + MAX_MACHINE_CODE_LENGTH_PER_INSTRUCTION * 2 >= self.result.text_section.len() {
             return Err(EbpfError::ExhaustedTextSegment(self.pc));
         }
+        self.result.instructions_end = self.offset_in_text_section;
         self.emit_validate_and_profile_instruction_count(Some(self.pc + 1));
         self.emit_ins(X86Instruction::load_immediate(REGISTER_SCRATCH, self.pc as i64)); // Save pc
         self.emit_set_exception_kind(EbpfError::ExecutionOverrun);
         self.emit_ins(X86Instruction::jump_immediate(self.relative_to_anchor(ANCHOR_THROW_EXCEPTION, 5)));
 
         self.resolve_jumps();
+        self.check_machine_code_length()?;
         self.result.seal(self.offset_in_text_section)?;
         Ok(self.result)
     }
 
+    fn check_machine_code_length(&self) -> Result<(), EbpfError> {
+        match self.config.max_machine_code_length {
+            Some(max_machine_code_length) if self.offset_in_text_section > max_machine_code_length => {
+                Err(EbpfError::ExceededMaxMachineCodeLength(self.pc))
+            }
+            _ => Ok(()),
+        }
+    }
+
     fn should_sanitize_constant(&self, value: i64) -> bool {
-        if !self.config.sanitize_user_provided_values {
+        if !self.config.sanitize_user_provided_values || self.config.deterministic_jit_output {
             return false;
         }
 
@@ -961,7 +1350,6 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
         }
         // Update `MACHINE_CODE_PER_INSTRUCTION_METER_CHECKPOINT` if you change the code generation here
         if let Some(pc) = pc {
-            self.last_instruction_meter_validation_pc = pc;
             self.emit_sanitized_load_immediate(REGISTER_SCRATCH, pc as i64);
         }
         // If instruction_meter >= pc, throw ExceededMaxInstructions
@@ -969,6 +1357,14 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
         self.emit_ins(X86Instruction::conditional_jump_immediate(0x86, self.relative_to_anchor(ANCHOR_THROW_EXCEEDED_MAX_INSTRUCTIONS, 6)));
     }
 
+    fn emit_validate_instruction_meter_block(&mut self, block_end: usize) {
+        // Update `MACHINE_CODE_PER_INSTRUCTION_METER_CHECKPOINT` if you change the code generation here
+        self.emit_sanitized_load_immediate(REGISTER_SCRATCH, block_end as i64);
+        // If instruction_meter <= block_end, let the interpreter run the block
+        self.emit_ins(X86Instruction::cmp(OperandSize::S64, REGISTER_SCRATCH, REGISTER_INSTRUCTION_METER, None));
+        self.emit_ins(X86Instruction::conditional_jump_immediate(0x86, self.relative_to_anchor(ANCHOR_INTERPRET_BLOCK, 6)));
+    }
+
     fn emit_profile_instruction_count(&mut self, target_pc: Option<usize>) {
         if !self.config.enable_instruction_meter {
             return;
@@ -1102,9 +1498,19 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
     fn emit_internal_call(&mut self, dst: Value) {
         // Store PC in case the bounds check fails
         self.emit_ins(X86Instruction::load_immediate(REGISTER_SCRATCH, self.pc as i64));
-        self.last_instruction_meter_validation_pc = self.pc;
         self.emit_ins(X86Instruction::call_immediate(self.relative_to_anchor(ANCHOR_INTERNAL_FUNCTION_CALL_PROLOGUE, 5)));
 
+        if let Value::Constant64(target_pc, _) = dst {
+            // If a call filter is installed, check the target before the instruction meter is profiled.
+            // The check of Value::Register() targets happens in ANCHOR_INTERNAL_FUNCTION_CALL_REG.
+            self.emit_ins(X86Instruction::cmp_immediate(OperandSize::S64, REGISTER_PTR_TO_VM, 0, Some(X86IndirectAccess::Offset(self.slot_in_vm(RuntimeEnvironmentSlot::CallFilter)))));
+            let skip_call_filter = self.emit_forward_conditional_jump(0x84);
+            self.emit_ins(X86Instruction::push_immediate(OperandSize::S64, target_pc as i32));
+            self.emit_ins(X86Instruction::call_immediate(self.relative_to_anchor(ANCHOR_CALL_FILTER, 5)));
+            self.emit_ins(X86Instruction::alu_immediate(OperandSize::S64, 0x81, 0, RSP, 8, None));
+            self.resolve_forward_jump(skip_call_filter);
+        }
+
         match dst {
             Value::Register(reg) => {
                 // REGISTER_SCRATCH contains self.pc, and we must store it for proper error handling.
@@ -1140,10 +1546,31 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
         }
     }
 
-    fn emit_syscall_dispatch(&mut self, function: BuiltinFunction<C>) {
-        self.emit_validate_and_profile_instruction_count(Some(0));
+    fn emit_syscall_dispatch(&mut self, function: BuiltinFunction<C>, metadata: SyscallMetadata) {
+        self.emit_validate_instruction_count(Some(self.pc));
+        if self.config.enable_instruction_meter && self.config.charge_syscall_base_costs && metadata.base_cost > 0 {
+            // If instruction_meter <= pc + base_cost, throw ExceededMaxInstructions without invoking the syscall
+            let pc_plus_base_cost = (self.pc as u64).saturating_add(metadata.base_cost);
+            if pc_plus_base_cost <= i32::MAX as u64 {
+                self.emit_ins(X86Instruction::cmp_immediate(OperandSize::S64, REGISTER_INSTRUCTION_METER, pc_plus_base_cost as i64, None));
+            } else {
+                self.emit_ins(X86Instruction::load_immediate(REGISTER_SCRATCH, pc_plus_base_cost as i64));
+                self.emit_ins(X86Instruction::cmp(OperandSize::S64, REGISTER_SCRATCH, REGISTER_INSTRUCTION_METER, None));
+                self.emit_ins(X86Instruction::load_immediate(REGISTER_SCRATCH, self.pc as i64)); // Does not modify the flags
+            }
+            self.emit_ins(X86Instruction::conditional_jump_immediate(0x86, self.relative_to_anchor(ANCHOR_SYSCALL_BASE_COST_EXCEEDED, 6)));
+            if metadata.base_cost <= i32::MAX as u64 {
+                self.emit_ins(X86Instruction::alu_immediate(OperandSize::S64, 0x81, 5, REGISTER_INSTRUCTION_METER, metadata.base_cost as i64, None)); // instruction_meter -= base_cost;
+            } else {
+                self.emit_ins(X86Instruction::load_immediate(REGISTER_SCRATCH, metadata.base_cost as i64));
+                self.emit_ins(X86Instruction::alu(OperandSize::S64, 0x29, REGISTER_SCRATCH, REGISTER_INSTRUCTION_METER, None)); // instruction_meter -= base_cost;
+            }
+        }
+        self.emit_profile_instruction_count(Some(0));
+        self.emit_ins(X86Instruction::push_immediate(OperandSize::S64, self.pc as i32)); // Used as PC value in error case, acts as stack padding otherwise
         self.emit_ins(X86Instruction::load_immediate(REGISTER_SCRATCH, function as usize as i64));
         self.emit_ins(X86Instruction::call_immediate(self.relative_to_anchor(ANCHOR_EXTERNAL_FUNCTION_CALL, 5)));
+        self.emit_ins(X86Instruction::alu_immediate(OperandSize::S64, 0x81, 0, RSP, 8, None));
         self.emit_undo_profile_instruction_count(0);
     }
 
@@ -1214,7 +1641,7 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
     }
 
     fn emit_conditional_branch_reg(&mut self, op: u8, bitwise: bool, first_operand: X86Register, second_operand: X86Register, target_pc: usize) {
-        self.emit_validate_and_profile_instruction_count(Some(target_pc));
+        self.emit_profile_instruction_count(Some(target_pc));
         if bitwise { // Logical
             self.emit_ins(X86Instruction::test(OperandSize::S64, first_operand, second_operand, None));
         } else { // Arithmetic
@@ -1227,7 +1654,7 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
     }
 
     fn emit_conditional_branch_imm(&mut self, op: u8, bitwise: bool, immediate: i64, second_operand: X86Register, target_pc: usize) {
-        self.emit_validate_and_profile_instruction_count(Some(target_pc));
+        self.emit_profile_instruction_count(Some(target_pc));
         if self.should_sanitize_constant(immediate) {
             self.emit_sanitized_load_immediate(REGISTER_SCRATCH, immediate);
             if bitwise { // Logical
@@ -1366,9 +1793,16 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
     fn emit_set_exception_kind(&mut self, err: EbpfError) {
         let err_kind = unsafe { *core::ptr::addr_of!(err).cast::<u64>() };
         let err_discriminant = ProgramResult::Err(err).discriminant();
-        self.emit_ins(X86Instruction::lea(OperandSize::S64, REGISTER_PTR_TO_VM, REGISTER_MAP[0], Some(X86IndirectAccess::Offset(self.slot_in_vm(RuntimeEnvironmentSlot::ProgramResult)))));
-        self.emit_ins(X86Instruction::store_immediate(OperandSize::S64, REGISTER_MAP[0], X86IndirectAccess::Offset(0), err_discriminant as i64)); // result.discriminant = err_discriminant;
-        self.emit_ins(X86Instruction::store_immediate(OperandSize::S64, REGISTER_MAP[0], X86IndirectAccess::Offset(core::mem::size_of::<u64>() as i32), err_kind as i64)); // err.kind = err_kind;
+        // Does not clobber REGISTER_MAP[0], so that the exception handlers can record the registers
+        self.emit_ins(X86Instruction::store_immediate(OperandSize::S64, REGISTER_PTR_TO_VM, X86IndirectAccess::Offset(self.slot_in_vm(RuntimeEnvironmentSlot::ProgramResult)), err_discriminant as i64)); // result.discriminant = err_discriminant;
+        self.emit_ins(X86Instruction::store_immediate(OperandSize::S64, REGISTER_PTR_TO_VM, X86IndirectAccess::Offset(self.slot_in_vm(RuntimeEnvironmentSlot::ProgramResult) + core::mem::size_of::<u64>() as i32), err_kind as i64)); // err.kind = err_kind;
+    }
+
+    // Stores the guest registers r0 to r10 into EbpfVm::registers
+    fn emit_store_registers(&mut self) {
+        for (i, reg) in REGISTER_MAP.iter().enumerate() {
+            self.emit_ins(X86Instruction::store(OperandSize::S64, *reg, REGISTER_PTR_TO_VM, X86IndirectAccess::Offset(self.slot_in_vm(RuntimeEnvironmentSlot::Registers) + (i * core::mem::size_of::<u64>()) as i32))); // registers[i] = REGISTER_MAP[i];
+        }
     }
 
     fn emit_result_is_err(&mut self, destination: X86Register) {
@@ -1379,28 +1813,6 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
     }
 
     fn emit_subroutines(&mut self) {
-        // Routine for instruction tracing
-        if self.config.enable_instruction_tracing {
-            self.set_anchor(ANCHOR_TRACE);
-            // Save registers on stack
-            self.emit_ins(X86Instruction::push(REGISTER_SCRATCH, None));
-            for reg in REGISTER_MAP.iter().rev() {
-                self.emit_ins(X86Instruction::push(*reg, None));
-            }
-            self.emit_ins(X86Instruction::mov(OperandSize::S64, RSP, REGISTER_MAP[0]));
-            self.emit_ins(X86Instruction::alu_immediate(OperandSize::S64, 0x81, 0, RSP, - 8 * 3, None)); // RSP -= 8 * 3;
-            self.emit_rust_call(Value::Constant64(C::trace as *const u8 as i64, false), &[
-                Argument { index: 1, value: Value::Register(REGISTER_MAP[0]) }, // registers
-                Argument { index: 0, value: Value::RegisterIndirect(REGISTER_PTR_TO_VM, self.slot_in_vm(RuntimeEnvironmentSlot::ContextObjectPointer), false) },
-            ], None);
-            // Pop stack and return
-            self.emit_ins(X86Instruction::alu_immediate(OperandSize::S64, 0x81, 0, RSP, 8 * 3, None)); // RSP += 8 * 3;
-            self.emit_ins(X86Instruction::pop(REGISTER_MAP[0]));
-            self.emit_ins(X86Instruction::alu_immediate(OperandSize::S64, 0x81, 0, RSP, 8 * (REGISTER_MAP.len() - 1) as i64, None)); // RSP += 8 * (REGISTER_MAP.len() - 1);
-            self.emit_ins(X86Instruction::pop(REGISTER_SCRATCH));
-            self.emit_ins(X86Instruction::return_near());
-        }
-
         // Epilogue
         self.set_anchor(ANCHOR_EPILOGUE);
         if self.config.enable_instruction_meter {
@@ -1433,6 +1845,7 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
 
         // Epilogue for errors
         self.set_anchor(ANCHOR_THROW_EXCEPTION_UNCHECKED);
+        self.emit_store_registers();
         self.emit_ins(X86Instruction::store(OperandSize::S64, REGISTER_SCRATCH, REGISTER_PTR_TO_VM, X86IndirectAccess::Offset(self.slot_in_vm(RuntimeEnvironmentSlot::Registers) + 11 * core::mem::size_of::<u64>() as i32))); // registers[11] = pc;
         self.emit_ins(X86Instruction::jump_immediate(self.relative_to_anchor(ANCHOR_EPILOGUE, 5)));
 
@@ -1452,17 +1865,92 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
         self.emit_validate_instruction_count(None);
         self.emit_ins(X86Instruction::jump_immediate(self.relative_to_anchor(ANCHOR_THROW_EXCEPTION_UNCHECKED, 5)));
 
+        // Handler for EbpfError::TraceStopped
+        if self.config.enable_instruction_tracing {
+            self.set_anchor(ANCHOR_TRACE_STOPPED);
+            self.emit_set_exception_kind(EbpfError::TraceStopped);
+            self.emit_ins(X86Instruction::load(OperandSize::S64, RSP, REGISTER_MAP[0], X86IndirectAccess::OffsetIndexShift(8 * 3, RSP, 0))); // Restore the REGISTER_MAP[0] clobbered by the trace hook
+            self.emit_ins(X86Instruction::jump_immediate(self.relative_to_anchor(ANCHOR_THROW_EXCEPTION, 5)));
+        }
+
+        // Routine for instruction tracing
+        if self.config.enable_instruction_tracing {
+            self.set_anchor(ANCHOR_TRACE);
+            // Save registers on stack
+            self.emit_ins(X86Instruction::push(REGISTER_SCRATCH, None));
+            for reg in REGISTER_MAP.iter().rev() {
+                self.emit_ins(X86Instruction::push(*reg, None));
+            }
+            self.emit_ins(X86Instruction::mov(OperandSize::S64, RSP, REGISTER_MAP[0]));
+            self.emit_ins(X86Instruction::alu_immediate(OperandSize::S64, 0x81, 0, RSP, - 8 * 3, None)); // RSP -= 8 * 3;
+            self.emit_rust_call(Value::Constant64(C::trace_hook as *const u8 as i64, false), &[
+                Argument { index: 2, value: Value::Register(REGISTER_MAP[0]) }, // registers
+                Argument { index: 1, value: Value::Register(REGISTER_SCRATCH) }, // pc
+                Argument { index: 0, value: Value::RegisterIndirect(REGISTER_PTR_TO_VM, self.slot_in_vm(RuntimeEnvironmentSlot::ContextObjectPointer), false) },
+            ], Some(REGISTER_MAP[0]));
+            // The epilogue restores the stack pointer, so the saved registers can stay on the stack
+            self.emit_ins(X86Instruction::cmp_immediate(OperandSize::S64, REGISTER_MAP[0], TraceControl::Continue as i64, None));
+            self.emit_ins(X86Instruction::conditional_jump_immediate(0x85, self.relative_to_anchor(ANCHOR_TRACE_STOPPED, 6)));
+            // Pop stack and return
+            self.emit_ins(X86Instruction::alu_immediate(OperandSize::S64, 0x81, 0, RSP, 8 * 3, None)); // RSP += 8 * 3;
+            self.emit_ins(X86Instruction::pop(REGISTER_MAP[0]));
+            self.emit_ins(X86Instruction::alu_immediate(OperandSize::S64, 0x81, 0, RSP, 8 * (REGISTER_MAP.len() - 1) as i64, None)); // RSP += 8 * (REGISTER_MAP.len() - 1);
+            self.emit_ins(X86Instruction::pop(REGISTER_SCRATCH));
+            self.emit_ins(X86Instruction::return_near());
+        }
+
         // Handler for EbpfError::CallDepthExceeded
+        fn throw_call_depth_exceeded(result: &mut ProgramResult, call_frames: *const CallFrame, call_depth: u64, pc: u64) {
+            let call_frames = unsafe { core::slice::from_raw_parts(call_frames, call_depth as usize) };
+            *result = ProgramResult::Err(call_depth_exceeded(pc, call_frames));
+        }
         self.set_anchor(ANCHOR_CALL_DEPTH_EXCEEDED);
-        self.emit_set_exception_kind(EbpfError::CallDepthExceeded);
-        self.emit_ins(X86Instruction::jump_immediate(self.relative_to_anchor(ANCHOR_THROW_EXCEPTION, 5)));
+        // Validate before the error is built, ANCHOR_THROW_EXCEEDED_MAX_INSTRUCTIONS would leak it
+        self.emit_validate_instruction_count(None);
+        // The error is allocated on the heap, so align the stack. The epilogue restores it.
+        self.emit_ins(X86Instruction::alu_immediate(OperandSize::S64, 0x81, 4, RSP, -16, None)); // RSP &= -16;
+        self.emit_ins(X86Instruction::alu_immediate(OperandSize::S64, 0x81, 5, RSP, 8, None)); // RSP -= 8; // emit_rust_call() pushes an odd number of registers
+        self.emit_rust_call(Value::Constant64(throw_call_depth_exceeded as *const u8 as i64, false), &[
+            Argument { index: 3, value: Value::Register(REGISTER_SCRATCH) }, // pc
+            Argument { index: 2, value: Value::RegisterIndirect(REGISTER_PTR_TO_VM, self.slot_in_vm(RuntimeEnvironmentSlot::CallDepth), false) },
+            Argument { index: 1, value: Value::RegisterIndirect(REGISTER_PTR_TO_VM, self.slot_in_vm(RuntimeEnvironmentSlot::CallFramesPointer), false) },
+            Argument { index: 0, value: Value::RegisterPlusConstant32(REGISTER_PTR_TO_VM, self.slot_in_vm(RuntimeEnvironmentSlot::ProgramResult), false) },
+        ], None);
+        self.emit_ins(X86Instruction::jump_immediate(self.relative_to_anchor(ANCHOR_THROW_EXCEPTION_UNCHECKED, 5)));
 
         // Handler for EbpfError::CallOutsideTextSegment
         self.set_anchor(ANCHOR_CALL_OUTSIDE_TEXT_SEGMENT);
         self.emit_set_exception_kind(EbpfError::CallOutsideTextSegment);
         self.emit_ins(X86Instruction::load(OperandSize::S64, RSP, REGISTER_SCRATCH, X86IndirectAccess::OffsetIndexShift(-8, RSP, 0)));
+        self.emit_ins(X86Instruction::pop(REGISTER_MAP[0])); // Restore the clobbered REGISTER_MAP[0]
         self.emit_ins(X86Instruction::jump_immediate(self.relative_to_anchor(ANCHOR_THROW_EXCEPTION, 5)));
 
+        // Handler for EbpfError::ExceededMaxInstructions raised by emit_syscall_dispatch()
+        self.set_anchor(ANCHOR_SYSCALL_BASE_COST_EXCEEDED);
+        self.emit_set_exception_kind(EbpfError::ExceededMaxInstructions);
+        self.emit_ins(X86Instruction::mov(OperandSize::S64, REGISTER_SCRATCH, REGISTER_INSTRUCTION_METER)); // REGISTER_INSTRUCTION_METER = pc; // Makes the epilogue exhaust the instruction meter
+        self.emit_ins(X86Instruction::jump_immediate(self.relative_to_anchor(ANCHOR_THROW_EXCEPTION_UNCHECKED, 5)));
+
+        if self.config.enable_instruction_meter {
+            // Routine for blocks which exceed the instruction meter, see emit_validate_instruction_meter_block()
+            // Inputs: Guest block end pc in REGISTER_SCRATCH
+            self.set_anchor(ANCHOR_INTERPRET_BLOCK);
+            self.emit_ins(X86Instruction::push(REGISTER_MAP[0], None));
+            self.emit_ins(X86Instruction::load_immediate(REGISTER_MAP[0], self.result.instruction_meter_block_starts.as_ptr() as i64)); // block_start = self.result.instruction_meter_block_starts;
+            self.emit_ins(X86Instruction::load(OperandSize::S32, REGISTER_MAP[0], REGISTER_MAP[0], X86IndirectAccess::OffsetIndexShift(0, REGISTER_SCRATCH, 2))); // block_start = self.result.instruction_meter_block_starts[block_end];
+            self.emit_ins(X86Instruction::mov(OperandSize::S64, REGISTER_MAP[0], REGISTER_SCRATCH)); // REGISTER_SCRATCH = block_start;
+            self.emit_ins(X86Instruction::pop(REGISTER_MAP[0]));
+            // Fall through
+
+            // Leaves the rest of the execution to the interpreter, see EbpfVm::interpreter_fallback
+            // Inputs: Guest pc to resume at in REGISTER_SCRATCH
+            self.set_anchor(ANCHOR_INTERPRETER_FALLBACK);
+            self.emit_ins(X86Instruction::store_immediate(OperandSize::S64, REGISTER_PTR_TO_VM, X86IndirectAccess::Offset(self.slot_in_vm(RuntimeEnvironmentSlot::InterpreterFallback)), 1)); // vm.interpreter_fallback = 1;
+            // The instruction at pc did not run yet, so the epilogue must not charge it
+            self.emit_ins(X86Instruction::alu_immediate(OperandSize::S64, 0x81, 0, REGISTER_INSTRUCTION_METER, 1, None)); // REGISTER_INSTRUCTION_METER += 1;
+            self.emit_ins(X86Instruction::jump_immediate(self.relative_to_anchor(ANCHOR_THROW_EXCEPTION_UNCHECKED, 5)));
+        }
+
         // Handler for EbpfError::DivideByZero
         self.set_anchor(ANCHOR_DIV_BY_ZERO);
         self.emit_set_exception_kind(EbpfError::DivideByZero);
@@ -1487,9 +1975,43 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
         self.emit_set_exception_kind(EbpfError::UnsupportedInstruction);
         self.emit_ins(X86Instruction::jump_immediate(self.relative_to_anchor(ANCHOR_THROW_EXCEPTION, 5)));
 
+        // Handler for EbpfError::CallFiltered raised by ANCHOR_INTERNAL_FUNCTION_CALL_REG
+        self.set_anchor(ANCHOR_CALL_REG_FILTERED);
+        self.emit_ins(X86Instruction::store(OperandSize::S64, REGISTER_SCRATCH, REGISTER_PTR_TO_VM, X86IndirectAccess::Offset(self.slot_in_vm(RuntimeEnvironmentSlot::ProgramResult) + 2 * core::mem::size_of::<u64>() as i32))); // err.target_pc = guest_target_pc;
+        self.emit_ins(X86Instruction::load(OperandSize::S64, RSP, REGISTER_SCRATCH, X86IndirectAccess::OffsetIndexShift(-8, RSP, 0))); // Retrieve the current program counter from the stack
+        self.emit_ins(X86Instruction::pop(REGISTER_MAP[0])); // Restore the clobbered REGISTER_MAP[0]
+        // Fall through
+
+        // Handler for EbpfError::CallFiltered
+        self.set_anchor(ANCHOR_CALL_FILTERED);
+        self.emit_set_exception_kind(EbpfError::CallFiltered { target_pc: 0 });
+        self.emit_ins(X86Instruction::jump_immediate(self.relative_to_anchor(ANCHOR_THROW_EXCEPTION, 5)));
+
+        // Handler for EbpfError::CallFiltered raised by ANCHOR_CALL_FILTER
+        self.set_anchor(ANCHOR_CALL_IMM_FILTERED);
+        self.emit_ins(X86Instruction::push(REGISTER_MAP[0], None));
+        self.emit_ins(X86Instruction::load(OperandSize::S64, RSP, REGISTER_MAP[0], X86IndirectAccess::OffsetIndexShift(16, RSP, 0))); // Retrieve the guest target pc from the stack
+        self.emit_ins(X86Instruction::store(OperandSize::S64, REGISTER_MAP[0], REGISTER_PTR_TO_VM, X86IndirectAccess::Offset(self.slot_in_vm(RuntimeEnvironmentSlot::ProgramResult) + 2 * core::mem::size_of::<u64>() as i32))); // err.target_pc = guest_target_pc;
+        self.emit_ins(X86Instruction::pop(REGISTER_MAP[0]));
+        self.emit_ins(X86Instruction::jump_immediate(self.relative_to_anchor(ANCHOR_CALL_FILTERED, 5)));
+
+        // Routine for the call filter check of emit_internal_call(Value::Constant64())
+        // Inputs: Guest current pc in REGISTER_SCRATCH, Guest target pc in X86IndirectAccess::OffsetIndexShift(8, RSP, 0)
+        self.set_anchor(ANCHOR_CALL_FILTER);
+        self.emit_ins(X86Instruction::push(REGISTER_MAP[0], None));
+        self.emit_ins(X86Instruction::load(OperandSize::S64, REGISTER_PTR_TO_VM, REGISTER_MAP[0], X86IndirectAccess::Offset(self.slot_in_vm(RuntimeEnvironmentSlot::CallFilter)))); // call_filter = vm.call_filter;
+        self.emit_ins(X86Instruction::load(OperandSize::S64, REGISTER_MAP[0], REGISTER_MAP[0], X86IndirectAccess::Offset(0))); // bitmap = call_filter.bitmap_ptr;
+        self.emit_ins(X86Instruction::push(REGISTER_MAP[1], None));
+        self.emit_ins(X86Instruction::load(OperandSize::S64, RSP, REGISTER_MAP[1], X86IndirectAccess::OffsetIndexShift(24, RSP, 0))); // Retrieve the guest target pc from the stack
+        self.emit_ins(X86Instruction::bit_test(OperandSize::S64, REGISTER_MAP[1], REGISTER_MAP[0], Some(X86IndirectAccess::Offset(0)))); // bitmap[guest_target_pc]
+        self.emit_ins(X86Instruction::pop(REGISTER_MAP[1]));
+        self.emit_ins(X86Instruction::pop(REGISTER_MAP[0]));
+        self.emit_ins(X86Instruction::conditional_jump_immediate(0x82, self.relative_to_anchor(ANCHOR_CALL_IMM_FILTERED, 6)));
+        self.emit_ins(X86Instruction::return_near());
+
         // Routine for external functions
+        // Inputs: Guest current pc in X86IndirectAccess::OffsetIndexShift(8, RSP, 0), Host target address in REGISTER_SCRATCH
         self.set_anchor(ANCHOR_EXTERNAL_FUNCTION_CALL);
-        self.emit_ins(X86Instruction::push_immediate(OperandSize::S64, -1)); // Used as PC value in error case, acts as stack padding otherwise
         if self.config.enable_instruction_meter {
             self.emit_ins(X86Instruction::store(OperandSize::S64, REGISTER_INSTRUCTION_METER, REGISTER_PTR_TO_VM, X86IndirectAccess::Offset(self.slot_in_vm(RuntimeEnvironmentSlot::DueInsnCount)))); // *DueInsnCount = REGISTER_INSTRUCTION_METER;
         }
@@ -1507,8 +2029,14 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
 
         // Test if result indicates that an error occured
         self.emit_result_is_err(REGISTER_SCRATCH);
-        self.emit_ins(X86Instruction::pop(REGISTER_SCRATCH));
-        self.emit_ins(X86Instruction::conditional_jump_immediate(0x85, self.relative_to_anchor(ANCHOR_EPILOGUE, 6)));
+        let no_error = self.emit_forward_conditional_jump(0x84);
+        self.emit_store_registers();
+        self.emit_ins(X86Instruction::load(OperandSize::S64, RSP, REGISTER_SCRATCH, X86IndirectAccess::OffsetIndexShift(8, RSP, 0)));
+        self.emit_ins(X86Instruction::store(OperandSize::S64, REGISTER_SCRATCH, REGISTER_PTR_TO_VM, X86IndirectAccess::Offset(self.slot_in_vm(RuntimeEnvironmentSlot::Registers) + 11 * core::mem::size_of::<u64>() as i32))); // registers[11] = pc;
+        // The syscall consumed the instruction meter already, so the epilogue must not charge any pc
+        self.emit_ins(X86Instruction::load_immediate(REGISTER_SCRATCH, -1));
+        self.emit_ins(X86Instruction::jump_immediate(self.relative_to_anchor(ANCHOR_EPILOGUE, 5)));
+        self.resolve_forward_jump(no_error);
         // Store Ok value in result register
         self.emit_ins(X86Instruction::lea(OperandSize::S64, REGISTER_PTR_TO_VM, REGISTER_SCRATCH, Some(X86IndirectAccess::Offset(self.slot_in_vm(RuntimeEnvironmentSlot::ProgramResult)))));
         self.emit_ins(X86Instruction::load(OperandSize::S64, REGISTER_SCRATCH, REGISTER_MAP[0], X86IndirectAccess::Offset(8)));
@@ -1526,9 +2054,32 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
         // Push the caller's frame pointer. The code to restore it is emitted at the end of emit_internal_call().
         self.emit_ins(X86Instruction::store(OperandSize::S64, REGISTER_MAP[FRAME_PTR_REG], RSP, X86IndirectAccess::OffsetIndexShift(8, RSP, 0)));
         self.emit_ins(X86Instruction::xchg(OperandSize::S64, REGISTER_SCRATCH, RSP, Some(X86IndirectAccess::OffsetIndexShift(0, RSP, 0)))); // Push return address and restore original REGISTER_SCRATCH
-        // Increase env.call_depth
+        // Record the call frame in env.call_frames[env.call_depth], the same way the interpreter does
+        debug_assert_eq!(mem::size_of::<CallFrame>(), 3 << 4);
         let call_depth_access = X86IndirectAccess::Offset(self.slot_in_vm(RuntimeEnvironmentSlot::CallDepth));
+        self.emit_ins(X86Instruction::push(REGISTER_MAP[0], None));
+        self.emit_ins(X86Instruction::load(OperandSize::S64, REGISTER_PTR_TO_VM, REGISTER_MAP[0], call_depth_access)); // frame = env.call_depth;
+        self.emit_ins(X86Instruction::lea(OperandSize::S64, REGISTER_MAP[0], REGISTER_MAP[0], Some(X86IndirectAccess::OffsetIndexShift(0, REGISTER_MAP[0], 1)))); // frame *= 3;
+        self.emit_ins(X86Instruction::alu_immediate(OperandSize::S64, 0xc1, 4, REGISTER_MAP[0], 4, None)); // frame <<= 4;
+        self.emit_ins(X86Instruction::alu(OperandSize::S64, 0x03, REGISTER_MAP[0], REGISTER_PTR_TO_VM, Some(X86IndirectAccess::Offset(self.slot_in_vm(RuntimeEnvironmentSlot::CallFramesPointer))))); // frame += env.call_frames_pointer;
+        for (i, reg) in REGISTER_MAP.iter().skip(FIRST_SCRATCH_REG).take(SCRATCH_REGS).enumerate() {
+            self.emit_ins(X86Instruction::store(OperandSize::S64, *reg, REGISTER_MAP[0], X86IndirectAccess::Offset(8 * i as i32))); // frame.caller_saved_registers[i] = SCRATCH_REG;
+        }
+        self.emit_ins(X86Instruction::store(OperandSize::S64, REGISTER_MAP[FRAME_PTR_REG], REGISTER_MAP[0], X86IndirectAccess::Offset(8 * SCRATCH_REGS as i32))); // frame.frame_pointer = REGISTER_MAP[FRAME_PTR_REG];
+        self.emit_ins(X86Instruction::store(OperandSize::S64, REGISTER_SCRATCH, REGISTER_MAP[0], X86IndirectAccess::Offset(8 * (SCRATCH_REGS + 1) as i32))); // frame.target_pc = pc;
+        self.emit_ins(X86Instruction::alu_immediate(OperandSize::S64, 0x81, 0, REGISTER_MAP[0], 1, Some(X86IndirectAccess::Offset(8 * (SCRATCH_REGS + 1) as i32)))); // frame.target_pc += 1;
+        self.emit_ins(X86Instruction::pop(REGISTER_MAP[0]));
+        // Increase env.call_depth
         self.emit_ins(X86Instruction::alu_immediate(OperandSize::S64, 0x81, 0, REGISTER_PTR_TO_VM, 1, Some(call_depth_access))); // env.call_depth += 1;
+        // Raise env.max_observed_call_depth to env.call_depth
+        let max_observed_call_depth_access = X86IndirectAccess::Offset(self.slot_in_vm(RuntimeEnvironmentSlot::MaxObservedCallDepth));
+        self.emit_ins(X86Instruction::push(REGISTER_MAP[0], None));
+        self.emit_ins(X86Instruction::load(OperandSize::S64, REGISTER_PTR_TO_VM, REGISTER_MAP[0], call_depth_access));
+        self.emit_ins(X86Instruction::cmp(OperandSize::S64, REGISTER_MAP[0], REGISTER_PTR_TO_VM, Some(max_observed_call_depth_access)));
+        let not_deeper = self.emit_forward_conditional_jump(0x83);
+        self.emit_ins(X86Instruction::store(OperandSize::S64, REGISTER_MAP[0], REGISTER_PTR_TO_VM, max_observed_call_depth_access)); // env.max_observed_call_depth = env.call_depth;
+        self.resolve_forward_jump(not_deeper);
+        self.emit_ins(X86Instruction::pop(REGISTER_MAP[0]));
         // If env.call_depth == self.config.max_call_depth, throw CallDepthExceeded
         self.emit_ins(X86Instruction::cmp_immediate(OperandSize::S32, REGISTER_PTR_TO_VM, self.config.max_call_depth as i64, Some(call_depth_access)));
         self.emit_ins(X86Instruction::conditional_jump_immediate(0x83, self.relative_to_anchor(ANCHOR_CALL_DEPTH_EXCEEDED, 6)));
@@ -1560,6 +2111,14 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
         let shift_amount = INSN_SIZE.trailing_zeros();
         debug_assert_eq!(INSN_SIZE, 1 << shift_amount);
         self.emit_ins(X86Instruction::alu_immediate(OperandSize::S64, 0xc1, 5, REGISTER_SCRATCH, shift_amount as i64, None)); // guest_target_pc /= INSN_SIZE;
+        // If a call filter is installed and bans guest_target_pc, throw CallFiltered
+        self.emit_ins(X86Instruction::load(OperandSize::S64, REGISTER_PTR_TO_VM, REGISTER_MAP[0], X86IndirectAccess::Offset(self.slot_in_vm(RuntimeEnvironmentSlot::CallFilter)))); // call_filter = vm.call_filter;
+        self.emit_ins(X86Instruction::test(OperandSize::S64, REGISTER_MAP[0], REGISTER_MAP[0], None));
+        let skip_call_filter = self.emit_forward_conditional_jump(0x84);
+        self.emit_ins(X86Instruction::load(OperandSize::S64, REGISTER_MAP[0], REGISTER_MAP[0], X86IndirectAccess::Offset(0))); // bitmap = call_filter.bitmap_ptr;
+        self.emit_ins(X86Instruction::bit_test(OperandSize::S64, REGISTER_SCRATCH, REGISTER_MAP[0], Some(X86IndirectAccess::Offset(0)))); // bitmap[guest_target_pc]
+        self.emit_ins(X86Instruction::conditional_jump_immediate(0x82, self.relative_to_anchor(ANCHOR_CALL_REG_FILTERED, 6)));
+        self.resolve_forward_jump(skip_call_filter);
         // Load host_target_address offset from self.result.pc_section
         self.emit_ins(X86Instruction::load_immediate(REGISTER_MAP[0], self.result.pc_section.as_ptr() as i64)); // host_target_address = self.result.pc_section;
         self.emit_ins(X86Instruction::load(OperandSize::S32, REGISTER_MAP[0], REGISTER_MAP[0], X86IndirectAccess::OffsetIndexShift(0, REGISTER_SCRATCH, 2))); // host_target_address = self.result.pc_section[guest_target_pc];
@@ -1571,6 +2130,19 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
         self.emit_ins(X86Instruction::alu(OperandSize::S64, 0x2b, REGISTER_INSTRUCTION_METER, RSP, Some(X86IndirectAccess::OffsetIndexShift(-8, RSP, 0)))); // instruction_meter -= guest_current_pc;
         self.emit_ins(X86Instruction::alu_immediate(OperandSize::S64, 0x81, 5, REGISTER_INSTRUCTION_METER, 1, None)); // instruction_meter -= 1;
         self.emit_ins(X86Instruction::alu(OperandSize::S64, 0x01, REGISTER_SCRATCH, REGISTER_INSTRUCTION_METER, None)); // instruction_meter += guest_target_pc;
+        if self.config.enable_instruction_meter && !self.executable.get_sbpf_version().static_syscalls() {
+            // The target can be in the middle of a block, so validate the rest of the block
+            // If instruction_meter <= block_ends[guest_target_pc], let the interpreter run it
+            self.emit_ins(X86Instruction::push(REGISTER_MAP[0], None));
+            self.emit_ins(X86Instruction::load_immediate(REGISTER_MAP[0], self.result.instruction_meter_block_ends.as_ptr() as i64)); // block_end = self.result.instruction_meter_block_ends;
+            self.emit_ins(X86Instruction::load(OperandSize::S32, REGISTER_MAP[0], REGISTER_MAP[0], X86IndirectAccess::OffsetIndexShift(0, REGISTER_SCRATCH, 2))); // block_end = self.result.instruction_meter_block_ends[guest_target_pc];
+            self.emit_ins(X86Instruction::cmp(OperandSize::S64, REGISTER_MAP[0], REGISTER_INSTRUCTION_METER, None));
+            self.emit_ins(X86Instruction::pop(REGISTER_MAP[0]));
+            let within_budget = self.emit_forward_conditional_jump(0x87);
+            self.emit_ins(X86Instruction::pop(REGISTER_MAP[0])); // Restore the clobbered REGISTER_MAP[0]
+            self.emit_ins(X86Instruction::jump_immediate(self.relative_to_anchor(ANCHOR_INTERPRETER_FALLBACK, 5)));
+            self.resolve_forward_jump(within_budget);
+        }
         // Offset host_target_address by self.result.text_section
         self.emit_ins(X86Instruction::mov_mmx(OperandSize::S64, REGISTER_SCRATCH, MM0));
         self.emit_ins(X86Instruction::load_immediate(REGISTER_SCRATCH, self.result.text_section.as_ptr() as i64)); // REGISTER_SCRATCH = self.result.text_section;
@@ -1640,6 +2212,20 @@ impl<'a, C: ContextObject> JitCompiler<'a, C> {
         }
     }
 
+    // Emits a conditional jump to the next call of resolve_forward_jump()
+    fn emit_forward_conditional_jump(&mut self, opcode: u8) -> usize {
+        self.emit_ins(X86Instruction::conditional_jump_immediate(opcode, 0));
+        self.offset_in_text_section
+    }
+
+    fn resolve_forward_jump(&mut self, jump_end: usize) {
+        let relative_destination = (self.offset_in_text_section - jump_end) as i32;
+        unsafe {
+            let location = self.result.text_section.as_mut_ptr().add(jump_end - mem::size_of::<i32>());
+            ptr::write_unaligned(location.cast::<i32>(), relative_destination);
+        }
+    }
+
     fn set_anchor(&mut self, anchor: usize) {
         self.anchors[anchor] = unsafe { self.result.text_section.as_ptr().add(self.offset_in_text_section) };
     }
@@ -28,60 +28,33 @@ extern crate thiserror;
 
 pub mod utils;
 pub mod aligned_memory;
+pub mod allocator;
 mod asm_parser;
 pub mod assembler;
-#[cfg(feature = "debugger")]
+#[cfg(all(feature = "debugger", target_pointer_width = "64", target_endian = "little"))]
 pub mod debugger;
 pub mod disassembler;
 pub mod ebpf;
 pub mod elf;
 pub mod elf_parser;
 pub mod error;
+pub mod execution_events;
 pub mod insn_builder;
+#[cfg(all(target_pointer_width = "64", target_endian = "little"))]
 pub mod interpreter;
 #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
 pub mod jit;
 #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
 mod memory_management;
 pub mod memory_region;
+pub mod platform;
 pub mod program;
+pub mod report;
 pub mod static_analysis;
+pub mod syscall_replay;
 pub mod verifier;
 pub mod vm;
 #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
 mod x86;
 
-trait ErrCheckedArithmetic: Sized {
-    fn err_checked_add(self, other: Self) -> Result<Self, ArithmeticOverflow>;
-    fn err_checked_sub(self, other: Self) -> Result<Self, ArithmeticOverflow>;
-    fn err_checked_mul(self, other: Self) -> Result<Self, ArithmeticOverflow>;
-    #[allow(dead_code)]
-    fn err_checked_div(self, other: Self) -> Result<Self, ArithmeticOverflow>;
-}
-struct ArithmeticOverflow;
-
-macro_rules! impl_err_checked_arithmetic {
-    ($($ty:ty),*) => {
-        $(
-            impl ErrCheckedArithmetic for $ty {
-                fn err_checked_add(self, other: $ty) -> Result<Self, ArithmeticOverflow> {
-                    self.checked_add(other).ok_or(ArithmeticOverflow)
-                }
-
-                fn err_checked_sub(self, other: $ty) -> Result<Self, ArithmeticOverflow> {
-                    self.checked_sub(other).ok_or(ArithmeticOverflow)
-                }
-
-                fn err_checked_mul(self, other: $ty) -> Result<Self, ArithmeticOverflow> {
-                    self.checked_mul(other).ok_or(ArithmeticOverflow)
-                }
-
-                fn err_checked_div(self, other: $ty) -> Result<Self, ArithmeticOverflow> {
-                    self.checked_div(other).ok_or(ArithmeticOverflow)
-                }
-            }
-        )*
-    }
-}
-
-impl_err_checked_arithmetic!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+pub use disassembler::disassemble_program;
@@ -3,7 +3,7 @@
 use alloc::boxed::Box;
 
 use crate::{
-    aligned_memory::Pod,
+    aligned_memory::{AnyBitPattern, Pod},
     ebpf,
     error::{EbpfError, ProgramResult},
     program::SBPFVersion,
@@ -17,7 +17,7 @@ use core::{
     ptr::{self, copy_nonoverlapping},
 };
 
-use alloc::{vec, vec::Vec};
+use alloc::{collections::BTreeMap, vec, vec::Vec};
 
 /* Explanation of the Gapped Memory
 
@@ -62,30 +62,53 @@ pub struct MemoryRegion {
     pub writable: Cell<bool>,
     /// User defined payload for the [MemoryCowCallback]
     pub cow_callback_payload: u32,
+    /// What the region holds, see [MemoryRegion::with_kind]
+    pub kind: MemoryRegionKind,
+    /// Was created by [MemoryRegion::new_readonly_from_raw] or
+    /// [MemoryRegion::new_writable_from_raw], see [SendMemoryMapping]
+    pub(crate) from_raw_parts: bool,
 }
 
 impl MemoryRegion {
     fn new(slice: &[u8], vm_addr: u64, vm_gap_size: u64, writable: bool) -> Self {
-        let mut vm_addr_end = vm_addr.saturating_add(slice.len() as u64);
+        Self::new_raw(
+            slice.as_ptr() as u64,
+            slice.len() as u64,
+            vm_addr,
+            vm_gap_size,
+            writable,
+        )
+    }
+
+    fn new_raw(host_addr: u64, len: u64, vm_addr: u64, vm_gap_size: u64, writable: bool) -> Self {
+        let mut vm_addr_end = vm_addr.saturating_add(len);
         let mut vm_gap_shift = (core::mem::size_of::<u64>() as u8)
             .saturating_mul(8)
             .saturating_sub(1);
         if vm_gap_size > 0 {
-            vm_addr_end = vm_addr_end.saturating_add(slice.len() as u64);
+            vm_addr_end = vm_addr_end.saturating_add(len);
             vm_gap_shift = vm_gap_shift.saturating_sub(vm_gap_size.leading_zeros() as u8);
             debug_assert_eq!(Some(vm_gap_size), 1_u64.checked_shl(vm_gap_shift as u32));
         };
         MemoryRegion {
-            host_addr: Cell::new(slice.as_ptr() as u64),
+            host_addr: Cell::new(host_addr),
             vm_addr,
             vm_addr_end,
-            len: slice.len() as u64,
+            len,
             vm_gap_shift,
             writable: Cell::new(writable),
             cow_callback_payload: u32::MAX,
+            kind: MemoryRegionKind::Unnamed,
+            from_raw_parts: false,
         }
     }
 
+    /// Tags the region with what it holds, for diagnostics
+    pub fn with_kind(mut self, kind: MemoryRegionKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
     /// Only to be used in tests and benches
     pub fn new_for_testing(slice: &[u8], vm_addr: u64, vm_gap_size: u64, writable: bool) -> Self {
         Self::new(slice, vm_addr, vm_gap_size, writable)
@@ -101,6 +124,45 @@ impl MemoryRegion {
         Self::new(&*slice, vm_addr, 0, true)
     }
 
+    /// Creates a new readonly MemoryRegion from a pointer and a length
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for reads of `len` bytes for as long as any [MemoryMapping] or
+    /// [crate::vm::EbpfVm] containing the region exists, and the memory must not be written
+    /// through other pointers while the VM executes. The memory may be accessed from whichever
+    /// thread the mapping is moved to, see [SendMemoryMapping].
+    pub unsafe fn new_readonly_from_raw(ptr: *const u8, len: u64, vm_addr: u64) -> Self {
+        let mut region = Self::new_raw(ptr as u64, len, vm_addr, 0, false);
+        region.from_raw_parts = true;
+        region
+    }
+
+    /// Creates a new writable MemoryRegion from a pointer and a length
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for reads and writes of `len` bytes for as long as any
+    /// [MemoryMapping] or [crate::vm::EbpfVm] containing the region exists, and the memory must
+    /// not be accessed through other pointers while the VM executes. The memory may be accessed
+    /// from whichever thread the mapping is moved to, see [SendMemoryMapping].
+    pub unsafe fn new_writable_from_raw(ptr: *mut u8, len: u64, vm_addr: u64) -> Self {
+        let mut region = Self::new_raw(ptr as u64, len, vm_addr, 0, true);
+        region.from_raw_parts = true;
+        region
+    }
+
+    /// Creates a new copy-on-write MemoryRegion from a slice
+    ///
+    /// The region starts out readonly. The first store into it invokes the
+    /// [MemoryCowCallback] of the [MemoryMapping] with `cow_callback_payload`,
+    /// which has to return the host address of a writable copy or refuse.
+    pub fn new_cow(slice: &[u8], vm_addr: u64, cow_callback_payload: u32) -> Self {
+        let mut region = Self::new(slice, vm_addr, 0, false);
+        region.cow_callback_payload = cow_callback_payload;
+        region
+    }
+
     /// Creates a new writable gapped MemoryRegion from a mutable slice
     pub fn new_writable_gapped(slice: &mut [u8], vm_addr: u64, vm_gap_size: u64) -> Self {
         Self::new(&*slice, vm_addr, vm_gap_size, true)
@@ -158,6 +220,220 @@ impl core::cmp::Ord for MemoryRegion {
     }
 }
 
+/// What a [MemoryRegion] holds, named in the diagnostics of access violations
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MemoryRegionKind {
+    /// Not tagged
+    #[default]
+    Unnamed,
+    /// Read-only data of the executable
+    Rodata,
+    /// Stack
+    Stack,
+    /// Heap
+    Heap,
+    /// Input, aka parameters
+    Input,
+}
+
+impl fmt::Display for MemoryRegionKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Unnamed => "unnamed",
+            Self::Rodata => "rodata",
+            Self::Stack => "stack",
+            Self::Heap => "heap",
+            Self::Input => "input",
+        })
+    }
+}
+
+/// A region next to the address of an access violation, see [AccessViolationContext]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NearbyRegion {
+    /// What the region holds
+    pub kind: MemoryRegionKind,
+    /// Start virtual address
+    pub vm_addr: u64,
+    /// End virtual address
+    pub vm_addr_end: u64,
+    /// Bytes between the region and the address
+    pub distance: u64,
+}
+
+/// Where an access violation happened relative to the mapped regions
+///
+/// Part of [EbpfError::AccessViolation] and [EbpfError::StackAccessViolation].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AccessViolationContext {
+    /// Nearest non-empty region starting at or below the address
+    pub below: Option<NearbyRegion>,
+    /// Nearest non-empty region starting above the address
+    pub above: Option<NearbyRegion>,
+    /// The address is within the bounds of `below`, so the access failed for another reason
+    /// like a store to a readonly region
+    pub in_region: bool,
+    /// The address is in a gap between the frames of a gapped region, see
+    /// [Config::enable_stack_frame_gaps]
+    pub in_stack_frame_gap: bool,
+}
+
+impl AccessViolationContext {
+    fn nearest_regions(regions: &[MemoryRegion], vm_addr: u64) -> Self {
+        let mut below: Option<&MemoryRegion> = None;
+        let mut above: Option<&MemoryRegion> = None;
+        for region in regions.iter().filter(|region| region.len > 0) {
+            if region.vm_addr <= vm_addr {
+                if below.is_none_or(|below| below.vm_addr < region.vm_addr) {
+                    below = Some(region);
+                }
+            } else if above.is_none_or(|above| region.vm_addr < above.vm_addr) {
+                above = Some(region);
+            }
+        }
+        let in_region = below.is_some_and(|region| vm_addr < region.vm_addr_end);
+        // Gapped regions spread their frames over every other frame sized slot
+        let in_stack_frame_gap = in_region
+            && below.is_some_and(|region| {
+                vm_addr
+                    .saturating_sub(region.vm_addr)
+                    .checked_shr(region.vm_gap_shift as u32)
+                    .unwrap_or(0)
+                    & 1
+                    == 1
+            });
+        Self {
+            below: below.map(|region| NearbyRegion {
+                kind: region.kind,
+                vm_addr: region.vm_addr,
+                vm_addr_end: region.vm_addr_end,
+                distance: vm_addr.saturating_sub(region.vm_addr_end),
+            }),
+            above: above.map(|region| NearbyRegion {
+                kind: region.kind,
+                vm_addr: region.vm_addr,
+                vm_addr_end: region.vm_addr_end,
+                distance: region.vm_addr.saturating_sub(vm_addr),
+            }),
+            in_region,
+            in_stack_frame_gap,
+        }
+    }
+}
+
+impl fmt::Display for AccessViolationContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.in_stack_frame_gap {
+            write!(f, "; access in stack frame gap")?;
+        }
+        if let Some(below) = &self.below {
+            if self.in_region {
+                write!(f, "; inside")?;
+            } else {
+                write!(f, "; {} bytes after", below.distance)?;
+            }
+            write!(
+                f,
+                " {} region {:#x}..{:#x}",
+                below.kind, below.vm_addr, below.vm_addr_end
+            )?;
+        }
+        if let Some(above) = &self.above {
+            write!(
+                f,
+                "; {} bytes before {} region {:#x}..{:#x}",
+                above.distance, above.kind, above.vm_addr, above.vm_addr_end
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Sizes of the memory regions handed to a VM
+///
+/// See [Config::max_writable_bytes] and [Config::max_total_region_bytes].
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct RegionSizes {
+    /// Writable bytes in the stack region
+    pub stack: u64,
+    /// Writable bytes in the heap region
+    pub heap: u64,
+    /// Writable bytes in the input regions
+    pub input: u64,
+    /// Writable bytes in all other regions
+    pub other: u64,
+    /// Bytes in all regions, including the read-only ones
+    pub total: u64,
+}
+
+impl RegionSizes {
+    /// Sums up the lengths of `regions`, categorized by their virtual address
+    pub fn of_regions<'a>(regions: impl IntoIterator<Item = &'a MemoryRegion>) -> Self {
+        let mut sizes = Self::default();
+        for region in regions {
+            sizes.total = sizes.total.saturating_add(region.len);
+            if !region.writable.get() {
+                continue;
+            }
+            let category = if region.vm_addr >= ebpf::MM_INPUT_START {
+                &mut sizes.input
+            } else if region.vm_addr >= ebpf::MM_HEAP_START {
+                &mut sizes.heap
+            } else if region.vm_addr >= ebpf::MM_STACK_START {
+                &mut sizes.stack
+            } else {
+                &mut sizes.other
+            };
+            *category = category.saturating_add(region.len);
+        }
+        sizes
+    }
+
+    /// Returns the number of writable bytes in all regions
+    pub fn writable(&self) -> u64 {
+        self.stack
+            .saturating_add(self.heap)
+            .saturating_add(self.input)
+            .saturating_add(self.other)
+    }
+
+    /// Checks the sizes against the limits in `config`
+    pub fn check(&self, config: &Config) -> Result<(), EbpfError> {
+        if self.writable() > config.max_writable_bytes || self.total > config.max_total_region_bytes
+        {
+            return Err(EbpfError::MemoryLimitExceeded(*self));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for RegionSizes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} writable bytes (stack: {}, heap: {}, input: {}, other: {}) of {} bytes in total",
+            self.writable(),
+            self.stack,
+            self.heap,
+            self.input,
+            self.other,
+            self.total,
+        )
+    }
+}
+
+/// Iterates `regions` as if `regions[index]` was `replacement`
+fn replaced_regions<'a>(
+    regions: &'a [MemoryRegion],
+    index: usize,
+    replacement: &'a MemoryRegion,
+) -> impl Iterator<Item = &'a MemoryRegion> {
+    regions
+        .iter()
+        .enumerate()
+        .map(move |(i, region)| if i == index { replacement } else { region })
+}
+
 /// Type of memory access
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum AccessType {
@@ -167,6 +443,58 @@ pub enum AccessType {
     Store,
 }
 
+/// Accesses of load and store instructions to one region, see [MemoryAccessStats]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RegionAccessStats {
+    /// Number of loads
+    pub loads: u64,
+    /// Number of stores
+    pub stores: u64,
+    /// Number of bytes loaded
+    pub bytes_loaded: u64,
+    /// Number of bytes stored
+    pub bytes_stored: u64,
+}
+
+/// Memory accesses of the load and store instructions of an execution
+///
+/// Accesses made by syscalls are not included, see
+/// [crate::vm::EbpfVm::memory_access_stats].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MemoryAccessStats {
+    /// Accesses per region, by region index (`vm_addr >> ebpf::VIRTUAL_ADDRESS_BITS`)
+    pub regions: BTreeMap<u64, RegionAccessStats>,
+    /// Number of accesses of 1, 2, 4 and 8 bytes
+    pub access_sizes: [u64; 4],
+}
+
+impl MemoryAccessStats {
+    /// Records an access of `len` bytes at `vm_addr`
+    pub fn record(&mut self, access_type: AccessType, vm_addr: u64, len: u64) {
+        let region = self
+            .regions
+            .entry(
+                vm_addr
+                    .checked_shr(ebpf::VIRTUAL_ADDRESS_BITS as u32)
+                    .unwrap_or(0),
+            )
+            .or_default();
+        let (count, bytes) = match access_type {
+            AccessType::Load => (&mut region.loads, &mut region.bytes_loaded),
+            AccessType::Store => (&mut region.stores, &mut region.bytes_stored),
+        };
+        *count = count.saturating_add(1);
+        *bytes = bytes.saturating_add(len);
+        if let Some(accesses) = self
+            .access_sizes
+            .get_mut(len.trailing_zeros() as usize)
+            .filter(|_| len.is_power_of_two())
+        {
+            *accesses = accesses.saturating_add(1);
+        }
+    }
+}
+
 /// Memory mapping based on eytzinger search.
 pub struct UnalignedMemoryMapping<'a> {
     /// Mapped memory regions
@@ -181,6 +509,8 @@ pub struct UnalignedMemoryMapping<'a> {
     sbpf_version: SBPFVersion,
     /// Access violation handler
     cow_cb: MemoryCowCallback,
+    /// Pages written since the last [MemoryMapping::snapshot]
+    dirty_pages: DirtyPages,
 }
 
 impl fmt::Debug for UnalignedMemoryMapping<'_> {
@@ -225,6 +555,7 @@ impl<'a> UnalignedMemoryMapping<'a> {
         sbpf_version: SBPFVersion,
         cow_cb: MemoryCowCallback,
     ) -> Result<Self, EbpfError> {
+        RegionSizes::of_regions(&regions).check(config)?;
         regions.sort();
         for index in 1..regions.len() {
             let first = &regions[index.saturating_sub(1)];
@@ -243,6 +574,7 @@ impl<'a> UnalignedMemoryMapping<'a> {
             config,
             sbpf_version,
             cow_cb,
+            dirty_pages: DirtyPages::default(),
         };
         result.construct_eytzinger_order(&mut regions, 0, 0);
         Ok(result)
@@ -306,12 +638,13 @@ impl<'a> UnalignedMemoryMapping<'a> {
         // guaranteed to be unique.
         let cache = unsafe { &mut *self.cache.get() };
 
-        let region = match self.find_region(cache, vm_addr) {
-            Some((_region_index, region)) => region,
+        let (region_index, region) = match self.find_region(cache, vm_addr) {
+            Some(found) => found,
             None => {
                 return generate_access_violation(
                     self.config,
                     self.sbpf_version,
+                    &self.regions,
                     access_type,
                     vm_addr,
                     len,
@@ -321,11 +654,21 @@ impl<'a> UnalignedMemoryMapping<'a> {
 
         if access_type == AccessType::Load || ensure_writable_region(region, &self.cow_cb) {
             if let Some(host_addr) = region.vm_to_host(vm_addr, len) {
+                if access_type == AccessType::Store {
+                    self.dirty_pages.mark(region_index, region, host_addr, len);
+                }
                 return ProgramResult::Ok(host_addr);
             }
         }
 
-        generate_access_violation(self.config, self.sbpf_version, access_type, vm_addr, len)
+        generate_access_violation(
+            self.config,
+            self.sbpf_version,
+            &self.regions,
+            access_type,
+            vm_addr,
+            len,
+        )
     }
 
     /// Loads `size_of::<T>()` bytes from the given address.
@@ -357,6 +700,7 @@ impl<'a> UnalignedMemoryMapping<'a> {
                 return generate_access_violation(
                     self.config,
                     self.sbpf_version,
+                    &self.regions,
                     AccessType::Load,
                     vm_addr,
                     len,
@@ -400,6 +744,7 @@ impl<'a> UnalignedMemoryMapping<'a> {
         generate_access_violation(
             self.config,
             self.sbpf_version,
+            &self.regions,
             AccessType::Load,
             initial_vm_addr,
             initial_len,
@@ -421,22 +766,24 @@ impl<'a> UnalignedMemoryMapping<'a> {
 
         let mut src = core::ptr::addr_of!(value).cast::<u8>();
 
-        let mut region = match self.find_region(cache, vm_addr) {
-            Some((_region_index, region)) if ensure_writable_region(region, &self.cow_cb) => {
+        let (mut region_index, mut region) = match self.find_region(cache, vm_addr) {
+            Some((region_index, region)) if ensure_writable_region(region, &self.cow_cb) => {
                 // fast path
                 if let Some(host_addr) = region.vm_to_host(vm_addr, len) {
                     // Safety:
                     // vm_to_host() succeeded so we know there's enough space to
                     // store `value`
                     unsafe { ptr::write_unaligned(host_addr as *mut _, value) };
+                    self.dirty_pages.mark(region_index, region, host_addr, len);
                     return ProgramResult::Ok(host_addr);
                 }
-                region
+                (region_index, region)
             }
             _ => {
                 return generate_access_violation(
                     self.config,
                     self.sbpf_version,
+                    &self.regions,
                     AccessType::Store,
                     vm_addr,
                     len,
@@ -461,14 +808,16 @@ impl<'a> UnalignedMemoryMapping<'a> {
                 // Safety:
                 // vm_to_host() succeeded so we have enough space for write_len
                 unsafe { copy_nonoverlapping(src, host_addr as *mut _, write_len as usize) };
+                self.dirty_pages
+                    .mark(region_index, region, host_addr, write_len);
                 len = len.saturating_sub(write_len);
                 if len == 0 {
                     return ProgramResult::Ok(host_addr);
                 }
                 src = unsafe { src.add(write_len as usize) };
                 vm_addr = vm_addr.saturating_add(write_len);
-                region = match self.find_region(cache, vm_addr) {
-                    Some((_region_index, region)) => region,
+                (region_index, region) = match self.find_region(cache, vm_addr) {
+                    Some(found) => found,
                     None => break,
                 };
             } else {
@@ -479,6 +828,7 @@ impl<'a> UnalignedMemoryMapping<'a> {
         generate_access_violation(
             self.config,
             self.sbpf_version,
+            &self.regions,
             AccessType::Store,
             initial_vm_addr,
             initial_len,
@@ -500,11 +850,21 @@ impl<'a> UnalignedMemoryMapping<'a> {
             if (region.vm_addr..region.vm_addr_end).contains(&vm_addr)
                 && (access_type == AccessType::Load || ensure_writable_region(region, &self.cow_cb))
             {
+                if access_type == AccessType::Store {
+                    self.dirty_pages.mark_all(region_index);
+                }
                 return Ok((region_index, region));
             }
         }
         Err(
-            generate_access_violation(self.config, self.sbpf_version, access_type, vm_addr, 0)
+            generate_access_violation(
+                self.config,
+                self.sbpf_version,
+                &self.regions,
+                access_type,
+                vm_addr,
+                0,
+            )
                 .unwrap_err(),
         )
     }
@@ -519,8 +879,19 @@ impl<'a> UnalignedMemoryMapping<'a> {
         if index >= self.regions.len() || self.regions[index].vm_addr != region.vm_addr {
             return Err(EbpfError::InvalidMemoryRegion(index));
         }
+        let overlaps = self.regions.iter().enumerate().any(|(other_index, other)| {
+            other_index != index
+                && other.vm_addr < region.vm_addr_end
+                && region.vm_addr < other.vm_addr_end
+        });
+        if overlaps {
+            return Err(EbpfError::InvalidMemoryRegion(index));
+        }
+        RegionSizes::of_regions(replaced_regions(&self.regions, index, &region))
+            .check(self.config)?;
         self.regions[index] = region;
         self.cache.get_mut().flush();
+        self.dirty_pages.mark_all(index);
         Ok(())
     }
 }
@@ -536,6 +907,8 @@ pub struct AlignedMemoryMapping<'a> {
     sbpf_version: SBPFVersion,
     /// Access violation handler
     cow_cb: MemoryCowCallback,
+    /// Pages written since the last [MemoryMapping::snapshot]
+    dirty_pages: DirtyPages,
 }
 
 impl fmt::Debug for AlignedMemoryMapping<'_> {
@@ -555,6 +928,7 @@ impl<'a> AlignedMemoryMapping<'a> {
         sbpf_version: SBPFVersion,
         cow_cb: MemoryCowCallback,
     ) -> Result<Self, EbpfError> {
+        RegionSizes::of_regions(&regions).check(config)?;
         regions.insert(0, MemoryRegion::new_readonly(&[], 0));
         regions.sort();
         for (index, region) in regions.iter().enumerate() {
@@ -572,6 +946,7 @@ impl<'a> AlignedMemoryMapping<'a> {
             config,
             sbpf_version,
             cow_cb,
+            dirty_pages: DirtyPages::default(),
         })
     }
 
@@ -600,11 +975,21 @@ impl<'a> AlignedMemoryMapping<'a> {
             let region = &self.regions[index];
             if access_type == AccessType::Load || ensure_writable_region(region, &self.cow_cb) {
                 if let Some(host_addr) = region.vm_to_host(vm_addr, len) {
+                    if access_type == AccessType::Store {
+                        self.dirty_pages.mark(index, region, host_addr, len);
+                    }
                     return ProgramResult::Ok(host_addr);
                 }
             }
         }
-        generate_access_violation(self.config, self.sbpf_version, access_type, vm_addr, len)
+        generate_access_violation(
+            self.config,
+            self.sbpf_version,
+            &self.regions,
+            access_type,
+            vm_addr,
+            len,
+        )
     }
 
     /// Loads `size_of::<T>()` bytes from the given address.
@@ -657,11 +1042,21 @@ impl<'a> AlignedMemoryMapping<'a> {
             if (region.vm_addr..region.vm_addr_end).contains(&vm_addr)
                 && (access_type == AccessType::Load || ensure_writable_region(region, &self.cow_cb))
             {
+                if access_type == AccessType::Store {
+                    self.dirty_pages.mark_all(index);
+                }
                 return Ok((index, region));
             }
         }
         Err(
-            generate_access_violation(self.config, self.sbpf_version, access_type, vm_addr, 0)
+            generate_access_violation(
+                self.config,
+                self.sbpf_version,
+                &self.regions,
+                access_type,
+                vm_addr,
+                0,
+            )
                 .unwrap_err(),
         )
     }
@@ -688,7 +1083,10 @@ impl<'a> AlignedMemoryMapping<'a> {
         if begin_index != index || end_index != index {
             return Err(EbpfError::InvalidMemoryRegion(index));
         }
+        RegionSizes::of_regions(replaced_regions(&self.regions, index, &region))
+            .check(self.config)?;
         self.regions[index] = region;
+        self.dirty_pages.mark_all(index);
         Ok(())
     }
 }
@@ -783,6 +1181,283 @@ impl<'a> MemoryMapping<'a> {
         }
     }
 
+    /// Copies a `T` out of guest memory at `vm_addr`
+    ///
+    /// The value is read byte-wise, so an unaligned guest address never turns into an unaligned
+    /// host reference. Unless [Config::reject_unaligned_guest_types] is set, in which case the
+    /// guest address must be aligned to `align_of::<T>()`. The guest address is checked rather
+    /// than the host address, so the outcome does not depend on where the host allocated the
+    /// regions.
+    pub fn translate_type<T: AnyBitPattern>(&self, vm_addr: u64) -> Result<T, EbpfError> {
+        let config = match self {
+            MemoryMapping::Identity => None,
+            MemoryMapping::Aligned(m) => Some(m.config),
+            MemoryMapping::Unaligned(m) => Some(m.config),
+        };
+        let align = mem::align_of::<T>();
+        if matches!(config, Some(config) if config.reject_unaligned_guest_types)
+            && vm_addr.checked_rem(align as u64) != Some(0)
+        {
+            return Err(EbpfError::UnalignedGuestType { vm_addr, align });
+        }
+        let host_addr: Result<u64, EbpfError> = self
+            .map(AccessType::Load, vm_addr, mem::size_of::<T>() as u64)
+            .into();
+        // Safety:
+        // map() succeeded so there are size_of::<T>() readable bytes, and any bit pattern is a
+        // valid T
+        Ok(unsafe { ptr::read_unaligned(host_addr? as *const T) })
+    }
+
+    /// Borrows `len` consecutive values of type `T` at `vm_addr`
+    ///
+    /// The values must lie within one region. With `check_aligned` the guest address has to be
+    /// aligned to `align_of::<T>()`. The host address always has to be, as the slice refers to
+    /// host memory directly. Both fail with [EbpfError::UnalignedGuestType]. A `len` of zero
+    /// succeeds without translating anything.
+    pub fn translate_slice<T: AnyBitPattern>(
+        &self,
+        vm_addr: u64,
+        len: u64,
+        check_aligned: bool,
+    ) -> Result<&[T], EbpfError> {
+        let host_addr =
+            self.translate_slice_host_addr::<T>(AccessType::Load, vm_addr, len, check_aligned)?;
+        // Safety:
+        // map() succeeded so there are len values in host memory, the address is aligned and
+        // any bit pattern is a valid T
+        Ok(unsafe { core::slice::from_raw_parts(host_addr as *const T, len as usize) })
+    }
+
+    /// Like [MemoryMapping::translate_slice], but for modifying the values
+    ///
+    /// The region has to be writable, copy-on-write regions are made writable.
+    pub fn translate_slice_mut<T: AnyBitPattern>(
+        &mut self,
+        vm_addr: u64,
+        len: u64,
+        check_aligned: bool,
+    ) -> Result<&mut [T], EbpfError> {
+        let host_addr =
+            self.translate_slice_host_addr::<T>(AccessType::Store, vm_addr, len, check_aligned)?;
+        // Safety:
+        // map() succeeded so there are len writable values in host memory, the address is
+        // aligned, any bit pattern is a valid T and the mapping is borrowed mutably
+        Ok(unsafe { core::slice::from_raw_parts_mut(host_addr as *mut T, len as usize) })
+    }
+
+    fn translate_slice_host_addr<T>(
+        &self,
+        access_type: AccessType,
+        vm_addr: u64,
+        len: u64,
+        check_aligned: bool,
+    ) -> Result<u64, EbpfError> {
+        let align = mem::align_of::<T>();
+        if len == 0 {
+            return Ok(ptr::NonNull::<T>::dangling().as_ptr() as u64);
+        }
+        if check_aligned && vm_addr.checked_rem(align as u64) != Some(0) {
+            return Err(EbpfError::UnalignedGuestType { vm_addr, align });
+        }
+        let size = len.saturating_mul(mem::size_of::<T>() as u64);
+        let host_addr: Result<u64, EbpfError> = self.map(access_type, vm_addr, size).into();
+        let host_addr = host_addr?;
+        if host_addr.checked_rem(align as u64) != Some(0) {
+            return Err(EbpfError::UnalignedGuestType { vm_addr, align });
+        }
+        Ok(host_addr)
+    }
+
+    /// Borrows the NUL terminated string at `vm_addr`, without the NUL terminator
+    ///
+    /// The NUL terminator has to be among the first `max_len` bytes, otherwise this fails with
+    /// [EbpfError::UnterminatedString]. Unless the string runs into memory which is not
+    /// readable before, then this fails like [MemoryMapping::map] would for `max_len` bytes.
+    /// The string has to lie within one region.
+    pub fn translate_string(&self, vm_addr: u64, max_len: u64) -> Result<&[u8], EbpfError> {
+        if max_len == 0 {
+            return Err(EbpfError::UnterminatedString { vm_addr, max_len });
+        }
+        let (host_addr, len) = self
+            .contiguous_piece(AccessType::Load, vm_addr, max_len)
+            .filter(|(_host_addr, len)| *len > 0)
+            .ok_or_else(|| self.access_violation(AccessType::Load, vm_addr, max_len))?;
+        // Safety:
+        // contiguous_piece() succeeded so there are len readable bytes in host memory
+        let bytes = unsafe { core::slice::from_raw_parts(host_addr as *const u8, len as usize) };
+        match bytes.iter().position(|byte| *byte == 0) {
+            Some(nul) => Ok(&bytes[..nul]),
+            None if len < max_len => Err(self.access_violation(AccessType::Load, vm_addr, max_len)),
+            None => Err(EbpfError::UnterminatedString { vm_addr, max_len }),
+        }
+    }
+
+    /// Copies `dst.len()` bytes of guest memory at `vm_addr` into `dst`
+    ///
+    /// The range may span several regions. If any part of it is not readable, nothing is copied
+    /// and the error is the one [MemoryMapping::map] reports for the whole range.
+    pub fn copy_from_vm(&self, vm_addr: u64, dst: &mut [u8]) -> Result<(), EbpfError> {
+        let len = dst.len() as u64;
+        let mut remaining = dst;
+        self.for_each_region_slice(vm_addr, len, |_vm_addr, slice| {
+            let (head, tail) = mem::take(&mut remaining).split_at_mut(slice.len());
+            head.copy_from_slice(slice);
+            remaining = tail;
+        })
+    }
+
+    /// Copies `src` into guest memory at `vm_addr`
+    ///
+    /// The range may span several regions, copy-on-write regions are made writable. If any part
+    /// of it is not writable, nothing is copied and the error is the one [MemoryMapping::map]
+    /// reports for the whole range.
+    pub fn copy_to_vm(&self, vm_addr: u64, src: &[u8]) -> Result<(), EbpfError> {
+        let mut remaining = src;
+        self.for_each_region_slice_mut(vm_addr, src.len() as u64, |_vm_addr, slice| {
+            let (head, tail) = remaining.split_at(slice.len());
+            slice.copy_from_slice(head);
+            remaining = tail;
+        })
+    }
+
+    /// Calls `f` with the guest address and host memory of every piece of the range `len` bytes
+    /// at `vm_addr` splits into, in ascending order
+    ///
+    /// A piece ends where its region or, for gapped regions, its frame ends. The whole range is
+    /// checked for readability before `f` is called the first time, see
+    /// [MemoryMapping::copy_from_vm].
+    pub fn for_each_region_slice<F: FnMut(u64, &[u8])>(
+        &self,
+        vm_addr: u64,
+        len: u64,
+        mut f: F,
+    ) -> Result<(), EbpfError> {
+        self.region_slices(AccessType::Load, vm_addr, len, |vm_addr, host_addr, len| {
+            // Safety:
+            // region_slices() checked that the piece is mapped
+            f(vm_addr, unsafe {
+                core::slice::from_raw_parts(host_addr as *const u8, len as usize)
+            })
+        })
+    }
+
+    /// Like [MemoryMapping::for_each_region_slice], but for writing to the range
+    ///
+    /// See [MemoryMapping::copy_to_vm].
+    pub fn for_each_region_slice_mut<F: FnMut(u64, &mut [u8])>(
+        &self,
+        vm_addr: u64,
+        len: u64,
+        mut f: F,
+    ) -> Result<(), EbpfError> {
+        self.region_slices(
+            AccessType::Store,
+            vm_addr,
+            len,
+            |vm_addr, host_addr, len| {
+                // Safety:
+                // region_slices() checked that the piece is mapped and writable
+                f(vm_addr, unsafe {
+                    core::slice::from_raw_parts_mut(host_addr as *mut u8, len as usize)
+                })
+            },
+        )
+    }
+
+    /// Splits a range of guest memory into pieces which are contiguous in host memory
+    ///
+    /// Calls `f` with the guest address, host address and length of each piece, but only once
+    /// all of them were translated successfully.
+    fn region_slices<F: FnMut(u64, u64, u64)>(
+        &self,
+        access_type: AccessType,
+        vm_addr: u64,
+        len: u64,
+        mut f: F,
+    ) -> Result<(), EbpfError> {
+        let piece_at = |offset: u64| {
+            let piece_vm_addr = vm_addr.checked_add(offset)?;
+            self.contiguous_piece(access_type, piece_vm_addr, len.saturating_sub(offset))
+                .filter(|(_host_addr, piece_len)| *piece_len > 0)
+                .map(|(host_addr, piece_len)| (piece_vm_addr, host_addr, piece_len))
+        };
+        let mut offset = 0;
+        while offset < len {
+            match piece_at(offset) {
+                Some((_vm_addr, _host_addr, piece_len)) => {
+                    offset = offset.saturating_add(piece_len);
+                }
+                None => return Err(self.access_violation(access_type, vm_addr, len)),
+            }
+        }
+        let mut offset = 0;
+        while offset < len {
+            // All pieces were translated above
+            let Some((piece_vm_addr, host_addr, piece_len)) = piece_at(offset) else {
+                break;
+            };
+            f(piece_vm_addr, host_addr, piece_len);
+            offset = offset.saturating_add(piece_len);
+        }
+        Ok(())
+    }
+
+    /// Returns the host address and length of the memory at `vm_addr` which is contiguous in
+    /// both guest and host, up to `max_len` bytes
+    ///
+    /// The memory ends where its region or, for gapped regions, its frame ends.
+    fn contiguous_piece(
+        &self,
+        access_type: AccessType,
+        vm_addr: u64,
+        max_len: u64,
+    ) -> Option<(u64, u64)> {
+        if let MemoryMapping::Identity = self {
+            return Some((vm_addr, max_len));
+        }
+        // Stores are translated by map() below, which only marks the written pages as dirty
+        let (_index, region) = self.region(AccessType::Load, vm_addr).ok()?;
+        // Continuous regions consist of a single frame
+        let frame_end = vm_addr
+            .saturating_sub(region.vm_addr)
+            .checked_shr(region.vm_gap_shift as u32)
+            .unwrap_or(0)
+            .saturating_add(1)
+            .checked_shl(region.vm_gap_shift as u32)
+            .unwrap_or(u64::MAX);
+        let end = region
+            .vm_addr_end
+            .min(region.vm_addr.saturating_add(frame_end));
+        let len = max_len.min(end.saturating_sub(vm_addr));
+        match access_type {
+            AccessType::Load => Some((region.vm_to_host(vm_addr, len)?, len)),
+            AccessType::Store => {
+                Result::<u64, EbpfError>::from(self.map(access_type, vm_addr, len))
+                    .ok()
+                    .map(|host_addr| (host_addr, len))
+            }
+        }
+    }
+
+    /// Returns the error [MemoryMapping::map] reports for the range
+    fn access_violation(&self, access_type: AccessType, vm_addr: u64, len: u64) -> EbpfError {
+        let (config, sbpf_version) = match self {
+            MemoryMapping::Identity => return EbpfError::InvalidMemoryRegion(0),
+            MemoryMapping::Aligned(m) => (m.config, m.sbpf_version),
+            MemoryMapping::Unaligned(m) => (m.config, m.sbpf_version),
+        };
+        generate_access_violation(
+            config,
+            sbpf_version,
+            self.get_regions(),
+            access_type,
+            vm_addr,
+            len,
+        )
+        .unwrap_err()
+    }
+
     /// Returns the `MemoryRegion` corresponding to the given address.
     pub fn region(
         &self,
@@ -813,39 +1488,302 @@ impl<'a> MemoryMapping<'a> {
             MemoryMapping::Unaligned(m) => m.replace_region(index, region),
         }
     }
-}
 
-// Ensure that the given region is writable.
-//
-// If the region is readonly, cow_cb is called.
-fn ensure_writable_region(region: &MemoryRegion, cow_cb: &MemoryCowCallback) -> bool {
-    if region.writable.get() {
-        return true;
+    /// Changes the length of the `MemoryRegion` at the given index
+    ///
+    /// The region keeps its start address and host buffer, and is revalidated
+    /// like in [MemoryMapping::replace_region].
+    ///
+    /// # Safety
+    ///
+    /// The host buffer of the region must be valid for `new_len` bytes.
+    pub unsafe fn resize_region(&mut self, index: usize, new_len: u64) -> Result<(), EbpfError> {
+        let mut region = self
+            .get_regions()
+            .get(index)
+            .ok_or(EbpfError::InvalidMemoryRegion(index))?
+            .clone();
+        let is_gapped = region.vm_addr_end.saturating_sub(region.vm_addr) != region.len;
+        region.len = new_len;
+        region.vm_addr_end = region
+            .vm_addr
+            .saturating_add(new_len.saturating_mul(if is_gapped { 2 } else { 1 }));
+        self.replace_region(index, region)
     }
-    if region.cow_callback_payload == u32::MAX {
-        return false;
+
+    /// Replaces the input region, see [crate::vm::MemoryLayoutProfile::input_start]
+    ///
+    /// This allows reusing a mapping, and the [crate::vm::EbpfVm] owning it,
+    /// for another input buffer without reconstructing the other regions.
+    pub fn replace_input_region(&mut self, region: MemoryRegion) -> Result<(), EbpfError> {
+        let input_start = match self {
+            MemoryMapping::Identity => return Err(EbpfError::InvalidMemoryRegion(0)),
+            MemoryMapping::Aligned(m) => m.config.memory_layout.input_start(),
+            MemoryMapping::Unaligned(m) => m.config.memory_layout.input_start(),
+        };
+        let index = self
+            .get_regions()
+            .iter()
+            .position(|region| region.vm_addr == input_start)
+            .unwrap_or(
+                input_start
+                    .checked_shr(ebpf::VIRTUAL_ADDRESS_BITS as u32)
+                    .unwrap_or(0) as usize,
+            );
+        if region.vm_addr != input_start {
+            return Err(EbpfError::InvalidMemoryRegion(index));
+        }
+        self.replace_region(index, region)
     }
-    if let Ok(host_addr) = cow_cb(region.cow_callback_payload) {
-        region.host_addr.replace(host_addr);
-        region.writable.replace(true);
-        true
-    } else {
-        false
+
+    /// Copies the contents of all writable regions
+    ///
+    /// With [Config::enable_dirty_page_tracking] the mapping records which pages are written
+    /// from now on, so that [MemoryMapping::restore] only needs to copy those back.
+    /// Copy-on-write regions which are still readonly are not part of the snapshot.
+    pub fn snapshot(&mut self) -> MemorySnapshot {
+        let (config, regions, dirty_pages) = match self {
+            MemoryMapping::Identity => return MemorySnapshot::default(),
+            MemoryMapping::Aligned(m) => (m.config, &m.regions, &mut m.dirty_pages),
+            MemoryMapping::Unaligned(m) => (m.config, &m.regions, &mut m.dirty_pages),
+        };
+        let id = if config.enable_dirty_page_tracking {
+            dirty_pages.reset(regions)
+        } else {
+            0
+        };
+        MemorySnapshot {
+            id,
+            regions: regions
+                .iter()
+                .enumerate()
+                .filter(|(_index, region)| region.writable.get())
+                .map(|(index, region)| {
+                    // Safety:
+                    // the region is mapped, so its host memory is valid for region.len bytes
+                    let contents = unsafe {
+                        core::slice::from_raw_parts(
+                            region.host_addr.get() as *const u8,
+                            region.len as usize,
+                        )
+                    };
+                    (index, region.vm_addr, contents.to_vec())
+                })
+                .collect(),
+        }
+    }
+
+    /// Restores the contents of the writable regions captured by [MemoryMapping::snapshot]
+    ///
+    /// Fails with [EbpfError::InvalidMemoryRegion] without restoring anything if a region of
+    /// the snapshot was moved or resized since. The snapshot can be restored multiple times.
+    /// Only the latest snapshot benefits from dirty page tracking, older ones are restored by
+    /// copying all of their contents. A snapshot must only be restored to the mapping it was
+    /// taken of.
+    pub fn restore(&mut self, snapshot: &MemorySnapshot) -> Result<(), EbpfError> {
+        let (regions, dirty_pages) = match self {
+            MemoryMapping::Identity if snapshot.regions.is_empty() => return Ok(()),
+            MemoryMapping::Identity => return Err(EbpfError::InvalidMemoryRegion(0)),
+            MemoryMapping::Aligned(m) => (&m.regions, &m.dirty_pages),
+            MemoryMapping::Unaligned(m) => (&m.regions, &m.dirty_pages),
+        };
+        let matches = |(index, vm_addr, contents): &(usize, u64, Vec<u8>)| {
+            regions
+                .get(*index)
+                .filter(|region| region.vm_addr == *vm_addr && region.len == contents.len() as u64)
+        };
+        if let Some((index, _vm_addr, _contents)) = snapshot
+            .regions
+            .iter()
+            .find(|region_snapshot| matches(region_snapshot).is_none())
+        {
+            return Err(EbpfError::InvalidMemoryRegion(*index));
+        }
+        for region_snapshot in snapshot.regions.iter() {
+            let Some(region) = matches(region_snapshot) else {
+                continue;
+            };
+            let (index, _vm_addr, contents) = region_snapshot;
+            for (page, chunk) in contents.chunks(DirtyPages::PAGE_SIZE).enumerate() {
+                if snapshot.id != dirty_pages.snapshot_id || dirty_pages.is_dirty(*index, page) {
+                    // Safety:
+                    // the region is mapped and as long as the snapshot, so the page is in bounds
+                    unsafe {
+                        copy_nonoverlapping(
+                            chunk.as_ptr(),
+                            (region.host_addr.get() as *mut u8)
+                                .add(page.saturating_mul(DirtyPages::PAGE_SIZE)),
+                            chunk.len(),
+                        );
+                    }
+                }
+            }
+        }
+        dirty_pages.clear();
+        Ok(())
     }
 }
 
-/// Helper for map to generate errors
-fn generate_access_violation(
-    config: &Config,
-    sbpf_version: SBPFVersion,
-    access_type: AccessType,
-    vm_addr: u64,
-    len: u64,
+/// Contents of the writable regions of a [MemoryMapping], see [MemoryMapping::snapshot]
+#[derive(Debug, Default, Clone)]
+pub struct MemorySnapshot {
+    /// Identifies the snapshot for dirty page tracking, zero if untracked
+    id: u64,
+    /// Index, start virtual address and contents of each writable region
+    regions: Vec<(usize, u64, Vec<u8>)>,
+}
+
+impl MemorySnapshot {
+    /// Number of bytes captured
+    pub fn len(&self) -> usize {
+        self.regions
+            .iter()
+            .map(|(_index, _vm_addr, contents)| contents.len())
+            .sum()
+    }
+
+    /// Is nothing captured
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Pages of each region written since the last [MemoryMapping::snapshot], see
+/// [Config::enable_dirty_page_tracking]
+///
+/// Before the first snapshot nothing is tracked and every page counts as dirty.
+#[derive(Debug, Default)]
+struct DirtyPages {
+    /// The [MemorySnapshot] the pages were written since
+    snapshot_id: u64,
+    /// One bit per page, indexed like the regions of the mapping
+    bitmaps: Vec<Vec<Cell<u64>>>,
+}
+
+impl DirtyPages {
+    const PAGE_SIZE: usize = 4096;
+
+    /// Starts tracking for a new snapshot and returns its id
+    fn reset(&mut self, regions: &[MemoryRegion]) -> u64 {
+        self.snapshot_id = self.snapshot_id.saturating_add(1);
+        self.bitmaps = regions
+            .iter()
+            .map(|region| {
+                let pages = (region.len as usize).div_ceil(Self::PAGE_SIZE);
+                (0..pages.div_ceil(64)).map(|_| Cell::new(0)).collect()
+            })
+            .collect();
+        self.snapshot_id
+    }
+
+    fn clear(&self) {
+        for word in self.bitmaps.iter().flatten() {
+            word.set(0);
+        }
+    }
+
+    /// Marks the pages of the `len` bytes at `host_addr` in the region at `index`
+    #[inline]
+    fn mark(&self, index: usize, region: &MemoryRegion, host_addr: u64, len: u64) {
+        let Some(bitmap) = self.bitmaps.get(index) else {
+            return;
+        };
+        let offset = host_addr.saturating_sub(region.host_addr.get()) as usize;
+        let first_page = offset / Self::PAGE_SIZE;
+        let last_page = offset.saturating_add((len as usize).saturating_sub(1)) / Self::PAGE_SIZE;
+        for page in first_page..=last_page {
+            if let Some(word) = bitmap.get(page / 64) {
+                word.set(word.get() | 1u64.wrapping_shl(page as u32));
+            }
+        }
+    }
+
+    /// Marks all pages of the region at `index`, e.g. because it was replaced
+    fn mark_all(&self, index: usize) {
+        for word in self.bitmaps.get(index).into_iter().flatten() {
+            word.set(u64::MAX);
+        }
+    }
+
+    fn is_dirty(&self, index: usize, page: usize) -> bool {
+        match self.bitmaps.get(index) {
+            Some(bitmap) => bitmap
+                .get(page / 64)
+                .is_some_and(|word| word.get().wrapping_shr(page as u32) & 1 == 1),
+            None => true,
+        }
+    }
+}
+
+/// A [MemoryMapping] which can be moved to another thread
+///
+/// Only regions created by [MemoryRegion::new_readonly_from_raw] or
+/// [MemoryRegion::new_writable_from_raw] are accepted, as their safety contract already covers
+/// access from other threads. Regions created from slices stay bound to the thread which
+/// borrowed the slices.
+#[derive(Debug)]
+pub struct SendMemoryMapping<'a>(MemoryMapping<'a>);
+
+// Safety: the regions point to memory which the creators of the regions guarantee to be
+// accessible from any thread, the copy-on-write callback is Send and the mapping cache is owned.
+unsafe impl Send for SendMemoryMapping<'_> {}
+
+impl<'a> SendMemoryMapping<'a> {
+    /// Creates a new memory mapping, see [MemoryMapping::new_with_cow]
+    ///
+    /// Fails with [EbpfError::InvalidMemoryRegion] at the index of the first region which was
+    /// not created from raw parts.
+    pub fn new(
+        regions: Vec<MemoryRegion>,
+        config: &'a Config,
+        sbpf_version: SBPFVersion,
+        cow_cb: Box<dyn Fn(u32) -> Result<u64, ()> + Send>,
+    ) -> Result<Self, EbpfError> {
+        if let Some(index) = regions.iter().position(|region| !region.from_raw_parts) {
+            return Err(EbpfError::InvalidMemoryRegion(index));
+        }
+        MemoryMapping::new_with_cow(regions, config, sbpf_version, cow_cb).map(Self)
+    }
+
+    /// Unwraps the mapping on the thread which executes the VM
+    pub fn into_inner(self) -> MemoryMapping<'a> {
+        self.0
+    }
+}
+
+// Ensure that the given region is writable.
+//
+// If the region is readonly, cow_cb is called.
+fn ensure_writable_region(region: &MemoryRegion, cow_cb: &MemoryCowCallback) -> bool {
+    if region.writable.get() {
+        return true;
+    }
+    if region.cow_callback_payload == u32::MAX {
+        return false;
+    }
+    if let Ok(host_addr) = cow_cb(region.cow_callback_payload) {
+        region.host_addr.replace(host_addr);
+        region.writable.replace(true);
+        true
+    } else {
+        false
+    }
+}
+
+/// Helper for map to generate errors
+fn generate_access_violation(
+    config: &Config,
+    sbpf_version: SBPFVersion,
+    regions: &[MemoryRegion],
+    access_type: AccessType,
+    vm_addr: u64,
+    len: u64,
 ) -> ProgramResult {
     let stack_frame = (vm_addr as i64)
         .saturating_sub(ebpf::MM_STACK_START as i64)
         .checked_div(config.stack_frame_size as i64)
         .unwrap_or(0);
+    let context = AccessViolationContext::nearest_regions(regions, vm_addr);
     if !sbpf_version.dynamic_stack_frames()
         && (-1..(config.max_call_depth as i64).saturating_add(1)).contains(&stack_frame)
     {
@@ -854,6 +1792,7 @@ fn generate_access_violation(
             vm_addr,
             len,
             stack_frame,
+            Box::new(context),
         ))
     } else {
         let region_name = match vm_addr & (!ebpf::MM_RODATA_START.saturating_sub(1)) {
@@ -868,6 +1807,7 @@ fn generate_access_violation(
             vm_addr,
             len,
             region_name,
+            Box::new(context),
         ))
     }
 }
@@ -929,9 +1869,9 @@ impl MappingCache {
 #[cfg(test)]
 mod test {
     use alloc::rc::Rc;
-    use alloc::format;
+    use alloc::{format, string::ToString};
     use core::{cell::RefCell};
-    use test_utils::assert_error;
+    use test_utils::{assert_error, MemoryDiff};
 
     use super::*;
 
@@ -1039,6 +1979,51 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_access_violation_context() {
+        for aligned_memory_mapping in [false, true] {
+            let config = Config {
+                aligned_memory_mapping,
+                ..Config::default()
+            };
+            let mut stack = vec![0xff; 8];
+            let m = MemoryMapping::new(
+                vec![
+                    MemoryRegion::new_readonly(&[0; 8], ebpf::MM_RODATA_START)
+                        .with_kind(MemoryRegionKind::Rodata),
+                    MemoryRegion::new_writable_gapped(&mut stack, ebpf::MM_STACK_START, 4)
+                        .with_kind(MemoryRegionKind::Stack),
+                ],
+                &config,
+                SBPFVersion::V3,
+            )
+            .unwrap();
+            assert_eq!(
+                m.map(AccessType::Load, ebpf::MM_STACK_START + 4, 1)
+                    .unwrap_err()
+                    .to_string(),
+                "Access violation in stack section at address 0x200000004 of size 1; \
+                 access in stack frame gap; inside stack region 0x200000000..0x200000010"
+            );
+            assert_eq!(
+                m.map(AccessType::Store, ebpf::MM_RODATA_START, 1)
+                    .unwrap_err()
+                    .to_string(),
+                "Access violation in program section at address 0x100000000 of size 1; \
+                 inside rodata region 0x100000000..0x100000008; \
+                 4294967296 bytes before stack region 0x200000000..0x200000010"
+            );
+            assert_eq!(
+                m.map(AccessType::Load, ebpf::MM_RODATA_START + 0x10, 1)
+                    .unwrap_err()
+                    .to_string(),
+                "Access violation in program section at address 0x100000010 of size 1; \
+                 8 bytes after rodata region 0x100000000..0x100000008; \
+                 4294967280 bytes before stack region 0x200000000..0x200000010"
+            );
+        }
+    }
+
     #[test]
     fn test_unaligned_map_overlap() {
         let config = Config::default();
@@ -1421,6 +2406,11 @@ mod test {
 
         m.store(0xEEFFu16, ebpf::MM_INPUT_START + 6).unwrap();
         assert_eq!(m.load::<u16>(ebpf::MM_INPUT_START + 6).unwrap(), 0xEEFF);
+
+        MemoryDiff::between(&[0xFF; 7], &mem1)
+            .assert_changes(&[(0, &[0x88, 0x77, 0x66, 0x55, 0xDD, 0xCC])]);
+        MemoryDiff::between_at(ebpf::MM_INPUT_START + 7, &[0xFF], &mem2)
+            .assert_changes(&[(0, &[0xEE])]);
     }
 
     #[test]
@@ -1443,6 +2433,7 @@ mod test {
         // this gets us line coverage for the case where we're completely
         // outside the address space (the case above is just on the edge)
         assert_error!(m.store(0x11u8, ebpf::MM_INPUT_START + 2), "AccessViolation");
+        MemoryDiff::between(&[0xFF], &mem1).assert_changes(&[(0, &[0x11])]);
 
         let mut mem1 = vec![0xFF; 4];
         let mut mem2 = vec![0xDD; 4];
@@ -1461,6 +2452,9 @@ mod test {
             m.load::<u64>(ebpf::MM_INPUT_START).unwrap(),
             0x1122334455667788u64
         );
+        MemoryDiff::between(&[0xFF; 4], &mem1).assert_changes(&[(0, &[0x88, 0x77, 0x66, 0x55])]);
+        MemoryDiff::between_at(ebpf::MM_INPUT_START + 4, &[0xDD; 4], &mem2)
+            .assert_changes(&[(0, &[0x44, 0x33, 0x22, 0x11])]);
         assert_error!(
             m.store(0x1122334455667788u64, ebpf::MM_INPUT_START + 1),
             "AccessViolation"
@@ -1504,6 +2498,282 @@ mod test {
         assert_error!(m.load::<u64>(ebpf::MM_INPUT_START + 1), "AccessViolation");
     }
 
+    #[test]
+    fn test_translate_type() {
+        let mem = [0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa];
+        for aligned_memory_mapping in [false, true] {
+            for reject_unaligned_guest_types in [false, true] {
+                let config = Config {
+                    aligned_memory_mapping,
+                    reject_unaligned_guest_types,
+                    ..Config::default()
+                };
+                let m = MemoryMapping::new(
+                    vec![MemoryRegion::new_readonly(&mem, ebpf::MM_RODATA_START)],
+                    &config,
+                    SBPFVersion::V3,
+                )
+                .unwrap();
+                assert_eq!(
+                    m.translate_type::<u64>(ebpf::MM_RODATA_START).unwrap(),
+                    0x8877665544332211
+                );
+                assert_eq!(
+                    m.translate_type::<u8>(ebpf::MM_RODATA_START + 1).unwrap(),
+                    0x22
+                );
+                assert_eq!(
+                    m.translate_type::<[u16; 4]>(ebpf::MM_RODATA_START + 2)
+                        .unwrap(),
+                    [0x4433, 0x6655, 0x8877, 0xaa99]
+                );
+                let unaligned = m.translate_type::<u64>(ebpf::MM_RODATA_START + 1);
+                if reject_unaligned_guest_types {
+                    assert!(matches!(
+                        unaligned,
+                        Err(EbpfError::UnalignedGuestType { vm_addr, align })
+                            if vm_addr == ebpf::MM_RODATA_START + 1
+                                && align == core::mem::align_of::<u64>()
+                    ));
+                } else {
+                    assert_eq!(unaligned.unwrap(), 0x9988776655443322);
+                }
+                assert_error!(
+                    m.translate_type::<u64>(ebpf::MM_RODATA_START + 8),
+                    "AccessViolation"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_translate_slice() {
+        for aligned_memory_mapping in [false, true] {
+            let config = Config {
+                aligned_memory_mapping,
+                ..Config::default()
+            };
+            // Backed by u64s, so the host memory is aligned
+            let mut mem = [1u64, 2, 3];
+            let mem = unsafe { core::slice::from_raw_parts_mut(mem.as_mut_ptr().cast(), 24) };
+            let mut m = MemoryMapping::new(
+                vec![
+                    MemoryRegion::new_readonly(&[1, 2, 3, 4], ebpf::MM_RODATA_START),
+                    MemoryRegion::new_writable(mem, ebpf::MM_STACK_START),
+                ],
+                &config,
+                SBPFVersion::V3,
+            )
+            .unwrap();
+
+            assert_eq!(
+                m.translate_slice::<u64>(ebpf::MM_STACK_START, 3, true)
+                    .unwrap(),
+                [1, 2, 3]
+            );
+            assert_eq!(
+                m.translate_slice::<u8>(ebpf::MM_RODATA_START + 1, 3, true)
+                    .unwrap(),
+                [2, 3, 4]
+            );
+            m.translate_slice_mut::<u64>(ebpf::MM_STACK_START + 8, 2, true)
+                .unwrap()
+                .copy_from_slice(&[5, 6]);
+            assert_eq!(
+                m.translate_slice::<u64>(ebpf::MM_STACK_START, 3, false)
+                    .unwrap(),
+                [1, 5, 6]
+            );
+
+            // Misaligned, whether or not the guest address is checked
+            for check_aligned in [false, true] {
+                assert!(matches!(
+                    m.translate_slice::<u32>(ebpf::MM_STACK_START + 2, 1, check_aligned),
+                    Err(EbpfError::UnalignedGuestType { vm_addr, align: 4 })
+                        if vm_addr == ebpf::MM_STACK_START + 2
+                ));
+            }
+            assert_error!(
+                m.translate_slice::<u64>(ebpf::MM_STACK_START + 8, 3, true),
+                "AccessViolation"
+            );
+            assert_error!(
+                m.translate_slice_mut::<u8>(ebpf::MM_RODATA_START, 1, true),
+                "AccessViolation"
+            );
+
+            // Zero-length slices do not even need to be mapped, nor aligned
+            assert!(m
+                .translate_slice::<u64>(ebpf::MM_HEAP_START + 1, 0, true)
+                .unwrap()
+                .is_empty());
+            assert!(m
+                .translate_slice_mut::<u64>(ebpf::MM_RODATA_START, 0, true)
+                .unwrap()
+                .is_empty());
+        }
+    }
+
+    #[test]
+    fn test_translate_string() {
+        for aligned_memory_mapping in [false, true] {
+            let config = Config {
+                aligned_memory_mapping,
+                ..Config::default()
+            };
+            let m = MemoryMapping::new(
+                vec![MemoryRegion::new_readonly(
+                    b"foo\0bar",
+                    ebpf::MM_RODATA_START,
+                )],
+                &config,
+                SBPFVersion::V3,
+            )
+            .unwrap();
+            assert_eq!(
+                m.translate_string(ebpf::MM_RODATA_START, 4).unwrap(),
+                b"foo"
+            );
+            assert_eq!(
+                m.translate_string(ebpf::MM_RODATA_START + 3, 1).unwrap(),
+                b""
+            );
+            for max_len in [0, 3] {
+                assert!(matches!(
+                    m.translate_string(ebpf::MM_RODATA_START, max_len),
+                    Err(EbpfError::UnterminatedString { vm_addr, max_len: error_max_len })
+                        if vm_addr == ebpf::MM_RODATA_START && error_max_len == max_len
+                ));
+            }
+            // Runs into the end of the region before finding a NUL terminator
+            assert_eq!(
+                format!("{:?}", m.translate_string(ebpf::MM_RODATA_START + 4, 8)),
+                format!(
+                    "{:?}",
+                    m.map(AccessType::Load, ebpf::MM_RODATA_START + 4, 8)
+                ),
+            );
+            assert_error!(
+                m.translate_string(ebpf::MM_HEAP_START, 8),
+                "AccessViolation"
+            );
+        }
+    }
+
+    #[test]
+    fn test_copy_across_regions() {
+        for aligned_memory_mapping in [false, true] {
+            let config = Config {
+                aligned_memory_mapping,
+                ..Config::default()
+            };
+            let rodata = [1, 2, 3, 4];
+            let mut stack = [5, 6, 7, 8];
+            let stack_ptr = stack.as_mut_ptr();
+            let m = MemoryMapping::new(
+                vec![
+                    MemoryRegion::new_readonly(&rodata, ebpf::MM_STACK_START - 4),
+                    MemoryRegion::new_writable(&mut stack, ebpf::MM_STACK_START),
+                ],
+                &config,
+                SBPFVersion::V3,
+            )
+            .unwrap();
+            let vm_addr = ebpf::MM_STACK_START - 2;
+
+            let mut buf = [0; 4];
+            m.copy_from_vm(vm_addr, &mut buf).unwrap();
+            assert_eq!(buf, [3, 4, 5, 6]);
+            let mut pieces = Vec::new();
+            m.for_each_region_slice(vm_addr, 4, |vm_addr, slice| {
+                pieces.push((vm_addr, slice.to_vec()))
+            })
+            .unwrap();
+            assert_eq!(
+                pieces,
+                [(vm_addr, vec![3, 4]), (ebpf::MM_STACK_START, vec![5, 6])]
+            );
+
+            // The rodata is not writable, so the stack stays untouched as well
+            assert_eq!(
+                format!("{:?}", m.copy_to_vm(vm_addr, &[9; 4]).unwrap_err()),
+                format!("{:?}", m.map(AccessType::Store, vm_addr, 4).unwrap_err()),
+            );
+            m.copy_to_vm(ebpf::MM_STACK_START + 2, &[9; 2]).unwrap();
+            assert_eq!(
+                unsafe { core::slice::from_raw_parts(stack_ptr, 4) },
+                [5, 6, 9, 9]
+            );
+        }
+    }
+
+    #[test]
+    fn test_copy_ending_in_gap() {
+        for aligned_memory_mapping in [false, true] {
+            let config = Config {
+                aligned_memory_mapping,
+                ..Config::default()
+            };
+            let mut stack = [1, 2, 3, 4, 5, 6, 7, 8];
+            let rodata = [9; 4];
+            let m = MemoryMapping::new(
+                vec![
+                    MemoryRegion::new_readonly(&rodata, ebpf::MM_RODATA_START),
+                    MemoryRegion::new_writable_gapped(&mut stack, ebpf::MM_STACK_START, 4),
+                ],
+                &config,
+                SBPFVersion::V3,
+            )
+            .unwrap();
+
+            // The frames are contiguous on the host, but not in the guest
+            let mut buf = [0; 4];
+            m.copy_from_vm(ebpf::MM_STACK_START + 2, &mut buf[..2])
+                .unwrap();
+            assert_eq!(buf, [3, 4, 0, 0]);
+            let access_violation = |access_type| {
+                format!(
+                    "{:?}",
+                    Err::<(), _>(EbpfError::AccessViolation(
+                        access_type,
+                        ebpf::MM_STACK_START + 2,
+                        4,
+                        "stack",
+                        Box::new(AccessViolationContext {
+                            below: Some(NearbyRegion {
+                                kind: MemoryRegionKind::Unnamed,
+                                vm_addr: ebpf::MM_STACK_START,
+                                vm_addr_end: ebpf::MM_STACK_START + 16,
+                                distance: 0,
+                            }),
+                            above: None,
+                            in_region: true,
+                            in_stack_frame_gap: false,
+                        }),
+                    ))
+                )
+            };
+            assert_eq!(
+                format!("{:?}", m.copy_from_vm(ebpf::MM_STACK_START + 2, &mut buf)),
+                access_violation(AccessType::Load),
+            );
+            assert_eq!(
+                format!("{:?}", m.copy_to_vm(ebpf::MM_STACK_START + 2, &buf)),
+                access_violation(AccessType::Store),
+            );
+            assert_eq!(buf, [3, 4, 0, 0]);
+
+            // Beyond the end of the last region
+            assert_eq!(
+                format!("{:?}", m.copy_from_vm(ebpf::MM_RODATA_START + 2, &mut buf)),
+                format!(
+                    "{:?}",
+                    m.map(AccessType::Load, ebpf::MM_RODATA_START + 2, 4)
+                ),
+            );
+        }
+    }
+
     #[test]
     #[should_panic(expected = "AccessViolation")]
     fn test_store_readonly() {
@@ -1648,6 +2918,335 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_unaligned_map_replace_region_overlap() {
+        let config = Config::default();
+        let mem1 = [11];
+        let mem2 = [22, 22];
+        let mem3 = [33, 33];
+        let mut m = UnalignedMemoryMapping::new(
+            vec![
+                MemoryRegion::new_readonly(&mem1, ebpf::MM_INPUT_START),
+                MemoryRegion::new_readonly(&mem2, ebpf::MM_INPUT_START + mem1.len() as u64),
+            ],
+            &config,
+            SBPFVersion::V3,
+        )
+        .unwrap();
+        let region_index = m
+            .get_regions()
+            .iter()
+            .position(|mem| mem.vm_addr == ebpf::MM_INPUT_START)
+            .unwrap();
+
+        // new.vm_addr_end > next.vm_addr
+        assert_error!(
+            m.replace_region(
+                region_index,
+                MemoryRegion::new_readonly(&mem3, ebpf::MM_INPUT_START)
+            ),
+            "InvalidMemoryRegion({})",
+            region_index
+        );
+    }
+
+    #[test]
+    fn test_replace_input_region() {
+        for aligned_memory_mapping in [true, false] {
+            let config = Config {
+                aligned_memory_mapping,
+                ..Config::default()
+            };
+            let rodata = [0; 8];
+            let mut stack = [0; 8];
+            let mut heap = [0; 8];
+            let mut old_input = [11; 8];
+            let mut new_input = [22; 4];
+            let mut m = MemoryMapping::new(
+                vec![
+                    MemoryRegion::new_readonly(&rodata, ebpf::MM_RODATA_START),
+                    MemoryRegion::new_writable(&mut stack, ebpf::MM_STACK_START),
+                    MemoryRegion::new_writable(&mut heap, ebpf::MM_HEAP_START),
+                    MemoryRegion::new_writable(&mut old_input, ebpf::MM_INPUT_START),
+                ],
+                &config,
+                SBPFVersion::V3,
+            )
+            .unwrap();
+            assert_eq!(m.load::<u8>(ebpf::MM_INPUT_START + 4).unwrap(), 11);
+
+            // The input region has to start at the configured address
+            assert_error!(
+                m.replace_input_region(MemoryRegion::new_writable(
+                    &mut new_input,
+                    ebpf::MM_INPUT_START + 1
+                )),
+                "InvalidMemoryRegion"
+            );
+
+            m.replace_input_region(MemoryRegion::new_writable(
+                &mut new_input,
+                ebpf::MM_INPUT_START,
+            ))
+            .unwrap();
+            // Previous translations are not reused
+            assert_eq!(m.load::<u8>(ebpf::MM_INPUT_START).unwrap(), 22);
+            assert_error!(
+                m.load::<u8>(ebpf::MM_INPUT_START + 4),
+                "AccessViolation(Load, {}, 1, \"input\", AccessViolationContext {{ below: Some(NearbyRegion {{ kind: Unnamed, vm_addr: {}, vm_addr_end: {}, distance: 0 }}), above: None, in_region: false",
+                ebpf::MM_INPUT_START + 4,
+                ebpf::MM_INPUT_START,
+                ebpf::MM_INPUT_START + 4
+            );
+            m.store(33u8, ebpf::MM_INPUT_START).unwrap();
+            MemoryDiff::between(&[11; 8], &old_input).assert_unchanged();
+            MemoryDiff::between(&[22; 4], &new_input).assert_changes(&[(0, &[33])]);
+        }
+    }
+
+    #[test]
+    fn test_resize_region() {
+        for aligned_memory_mapping in [true, false] {
+            let config = Config {
+                aligned_memory_mapping,
+                ..Config::default()
+            };
+            let rodata = [0; 8];
+            let mut stack = [0; 8];
+            let mut heap = [0; 8];
+            let mut input = [11; 8];
+            let mut m = MemoryMapping::new(
+                vec![
+                    MemoryRegion::new_readonly(&rodata, ebpf::MM_RODATA_START),
+                    MemoryRegion::new_writable(&mut stack, ebpf::MM_STACK_START),
+                    MemoryRegion::new_writable(&mut heap, ebpf::MM_HEAP_START),
+                    MemoryRegion::new_writable(&mut input[..4], ebpf::MM_INPUT_START),
+                ],
+                &config,
+                SBPFVersion::V3,
+            )
+            .unwrap();
+            let index = m
+                .get_regions()
+                .iter()
+                .position(|region| region.vm_addr == ebpf::MM_INPUT_START)
+                .unwrap();
+            assert!(m.load::<u8>(ebpf::MM_INPUT_START + 4).is_err());
+
+            unsafe { m.resize_region(index, 8) }.unwrap();
+            assert_eq!(m.get_regions()[index].vm_addr_end, ebpf::MM_INPUT_START + 8);
+            assert_eq!(m.load::<u8>(ebpf::MM_INPUT_START + 4).unwrap(), 11);
+
+            unsafe { m.resize_region(index, 2) }.unwrap();
+            assert_eq!(m.load::<u8>(ebpf::MM_INPUT_START + 1).unwrap(), 11);
+            assert!(m.load::<u8>(ebpf::MM_INPUT_START + 2).is_err());
+
+            // Neither the next region nor the next 4 GiB may be overlapped
+            let stack_index = m
+                .get_regions()
+                .iter()
+                .position(|region| region.vm_addr == ebpf::MM_STACK_START)
+                .unwrap();
+            assert_error!(
+                unsafe {
+                    m.resize_region(stack_index, ebpf::MM_HEAP_START - ebpf::MM_STACK_START + 1)
+                },
+                "InvalidMemoryRegion({})",
+                stack_index
+            );
+            assert_error!(unsafe { m.resize_region(7, 1) }, "InvalidMemoryRegion(7)");
+        }
+    }
+
+    #[test]
+    fn test_snapshot_restore() {
+        for (aligned_memory_mapping, enable_dirty_page_tracking) in
+            [(false, false), (false, true), (true, false), (true, true)]
+        {
+            let config = Config {
+                aligned_memory_mapping,
+                enable_dirty_page_tracking,
+                ..Config::default()
+            };
+            let mut stack = [0; 8];
+            let mut heap = [0; 8];
+            let mut input = vec![0; 3 * 4096];
+            let mut m = MemoryMapping::new(
+                vec![
+                    MemoryRegion::new_readonly(&[0; 8], ebpf::MM_RODATA_START),
+                    MemoryRegion::new_writable(&mut stack, ebpf::MM_STACK_START),
+                    MemoryRegion::new_writable(&mut heap, ebpf::MM_HEAP_START),
+                    MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START),
+                ],
+                &config,
+                SBPFVersion::V3,
+            )
+            .unwrap();
+            let snapshot = m.snapshot();
+            assert_eq!(snapshot.len(), 8 + 8 + 3 * 4096);
+            m.store::<u8>(1, ebpf::MM_INPUT_START).unwrap();
+            m.store::<u64>(1, ebpf::MM_STACK_START).unwrap();
+            m.copy_to_vm(ebpf::MM_INPUT_START + 2 * 4096, &[2; 2])
+                .unwrap();
+            // Not written through the mapping, so only restored without dirty page tracking
+            input[4096] = 3;
+            for _ in 0..2 {
+                m.restore(&snapshot).unwrap();
+                MemoryDiff::between_at(ebpf::MM_STACK_START, &[0; 8], &stack).assert_unchanged();
+                MemoryDiff::between(&[0; 3 * 4096], &input).assert_changes(
+                    if enable_dirty_page_tracking {
+                        &[(4096, &[3])]
+                    } else {
+                        &[]
+                    },
+                );
+            }
+
+            // Replaced regions are restored completely
+            let index = m
+                .get_regions()
+                .iter()
+                .position(|region| region.vm_addr == ebpf::MM_INPUT_START)
+                .unwrap();
+            m.replace_region(
+                index,
+                MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START),
+            )
+            .unwrap();
+            input[4096] = 4;
+            m.restore(&snapshot).unwrap();
+            assert_eq!(input[4096], 0);
+
+            unsafe { m.resize_region(index, 4096) }.unwrap();
+            input[0] = 5;
+            assert_error!(m.restore(&snapshot), "InvalidMemoryRegion({})", index);
+            assert_eq!(input[0], 5);
+        }
+    }
+
+    #[test]
+    fn test_region_sizes() {
+        let mut stack = [0; 4];
+        let mut heap = [0; 8];
+        let mut input = [0; 16];
+        let mut other = [0; 32];
+        let regions = [
+            MemoryRegion::new_readonly(&[0; 64], ebpf::MM_BYTECODE_START),
+            MemoryRegion::new_writable(&mut other, ebpf::MM_RODATA_START),
+            MemoryRegion::new_writable_gapped(&mut stack, ebpf::MM_STACK_START, 2),
+            MemoryRegion::new_writable(&mut heap, ebpf::MM_HEAP_START),
+            MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START),
+            MemoryRegion::new_readonly(&[0; 128], ebpf::MM_INPUT_START + 16),
+        ];
+        let sizes = RegionSizes::of_regions(&regions);
+        assert_eq!(
+            sizes,
+            RegionSizes {
+                stack: 4,
+                heap: 8,
+                input: 16,
+                other: 32,
+                total: 252,
+            }
+        );
+        assert_eq!(sizes.writable(), 60);
+        assert_eq!(
+            format!("{sizes}"),
+            "60 writable bytes (stack: 4, heap: 8, input: 16, other: 32) of 252 bytes in total"
+        );
+    }
+
+    #[test]
+    fn test_map_memory_limits() {
+        for aligned_memory_mapping in [true, false] {
+            let mut stack = [0; 8];
+            let mut heap = [0; 8];
+            let mut input = [0; 9];
+            let rodata = [0; 9];
+            let mut new_mapping = |config: &Config, input: &mut [u8], rodata: &[u8]| {
+                MemoryMapping::new(
+                    vec![
+                        MemoryRegion::new_readonly(rodata, ebpf::MM_RODATA_START),
+                        MemoryRegion::new_writable(&mut stack, ebpf::MM_STACK_START),
+                        MemoryRegion::new_writable(&mut heap, ebpf::MM_HEAP_START),
+                        MemoryRegion::new_writable(input, ebpf::MM_INPUT_START),
+                    ],
+                    config,
+                    SBPFVersion::V3,
+                )
+                .map(|_| ())
+            };
+            let config = Config {
+                aligned_memory_mapping,
+                max_writable_bytes: 24,
+                max_total_region_bytes: 32,
+                ..Config::default()
+            };
+
+            new_mapping(&config, &mut input[..8], &rodata[..8]).unwrap();
+            assert_error!(
+                new_mapping(&config, &mut input, &rodata[..8]),
+                "MemoryLimitExceeded(RegionSizes {{ stack: 8, heap: 8, input: 9, other: 0, total: 33 }})"
+            );
+            // Read-only regions only count towards the total
+            assert_error!(
+                new_mapping(&config, &mut input[..8], &rodata),
+                "MemoryLimitExceeded(RegionSizes {{ stack: 8, heap: 8, input: 8, other: 0, total: 33 }})"
+            );
+            let config = Config {
+                max_total_region_bytes: 33,
+                ..config
+            };
+            new_mapping(&config, &mut input[..8], &rodata).unwrap();
+
+            let config = Config {
+                aligned_memory_mapping,
+                max_writable_bytes: u64::MAX,
+                max_total_region_bytes: u64::MAX,
+                ..Config::default()
+            };
+            new_mapping(&config, &mut input, &rodata).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_replace_region_memory_limit() {
+        for aligned_memory_mapping in [true, false] {
+            let config = Config {
+                aligned_memory_mapping,
+                max_writable_bytes: 8,
+                ..Config::default()
+            };
+            let mut mem1 = [0; 8];
+            let mut mem2 = [0; 9];
+            let mut m = MemoryMapping::new(
+                vec![MemoryRegion::new_writable(&mut mem1, ebpf::MM_RODATA_START)],
+                &config,
+                SBPFVersion::V3,
+            )
+            .unwrap();
+            let index = if aligned_memory_mapping { 1 } else { 0 };
+
+            assert_error!(
+                m.replace_region(
+                    index,
+                    MemoryRegion::new_writable(&mut mem2, ebpf::MM_RODATA_START)
+                ),
+                "MemoryLimitExceeded"
+            );
+            m.replace_region(
+                index,
+                MemoryRegion::new_readonly(&mem2, ebpf::MM_RODATA_START),
+            )
+            .unwrap();
+            m.replace_region(
+                index,
+                MemoryRegion::new_writable(&mut mem2[..8], ebpf::MM_RODATA_START),
+            )
+            .unwrap();
+        }
+    }
+
     #[test]
     fn test_cow_map() {
         for aligned_memory_mapping in [true, false] {
@@ -1717,7 +3316,9 @@ mod test {
             assert!(copied.borrow().is_empty());
 
             m.store(33u8, ebpf::MM_RODATA_START).unwrap();
-            assert_eq!(original[0], 11);
+            MemoryDiff::between_at(ebpf::MM_RODATA_START, &[11, 22], &original).assert_unchanged();
+            MemoryDiff::between_at(ebpf::MM_RODATA_START, &original, &copied.borrow())
+                .assert_changes(&[(0, &[33])]);
             assert_eq!(m.load::<u8>(ebpf::MM_RODATA_START).unwrap(), 33);
             assert_eq!(m.load::<u8>(ebpf::MM_RODATA_START + 1).unwrap(), 22);
         }
@@ -0,0 +1,59 @@
+// Copyright 2026 Solana Maintainers <maintainers@solana.com>
+//
+// Licensed under the Apache License, Version 2.0 <http://www.apache.org/licenses/LICENSE-2.0> or
+// the MIT license <http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Host platform requirements
+//!
+//! | Modules                                                      | Supported targets             |
+//! |--------------------------------------------------------------|-------------------------------|
+//! | `elf_parser`, `elf`                                          | all                           |
+//! | `verifier`, `static_analysis`, `disassembler`, `assembler`   | all                           |
+//! | `memory_region`, `vm` (without execution)                    | all                           |
+//! | `interpreter`, `debugger`, execution methods of `vm`         | 64 bit, little-endian         |
+//! | `jit`                                                        | x86_64 System V (not Windows) |
+//!
+//! Items a target does not support are left out with `cfg`, so code using them fails to build
+//! there instead of misbehaving at runtime:
+//!
+//! - Little-endian byte order: The memory mapping loads and stores guest values in host byte
+//!   order. The ELF parser casts the headers and tables of the file in place as well, on
+//!   big-endian targets [AlignedElfBytes](crate::elf_parser::AlignedElfBytes) and the loader
+//!   convert a copy of them to the host byte order first.
+//! - 64 bit pointers: Builtin functions find the [EbpfVm](crate::vm::EbpfVm) by offsetting the
+//!   pointer they are passed by [get_runtime_environment_key](crate::vm::get_runtime_environment_key)
+//!   words, which can reach beyond a 32 bit address space.
+//!
+//! Loading, verifying and analyzing executables works on 32 bit targets too, offsets and sizes
+//! from the file which do not fit a `usize` are rejected as out of bounds.
+//!
+//! The `jit` feature is enabled by default and does nothing on targets without JIT support;
+//! [EbpfVm::execute_program](crate::vm::EbpfVm::execute_program) then reports
+//! [EbpfError::JitNotCompiled](crate::error::EbpfError::JitNotCompiled). Use [JIT_SUPPORTED]
+//! and [EXECUTION_SUPPORTED] to check for them.
+
+use crate::elf_parser::types::{
+    Elf64Dyn, Elf64Ehdr, Elf64Phdr, Elf64Rel, Elf64Rela, Elf64Shdr, Elf64Sym,
+};
+use core::mem;
+
+/// True if programs can be executed on this target, by the interpreter at least
+pub const EXECUTION_SUPPORTED: bool =
+    cfg!(all(target_pointer_width = "64", target_endian = "little"));
+
+/// True if the JIT compiler is available on this target with the enabled features
+pub const JIT_SUPPORTED: bool = cfg!(all(
+    feature = "jit",
+    not(target_os = "windows"),
+    target_arch = "x86_64"
+));
+
+// The ELF parser casts these types directly from the file bytes
+const _: () = assert!(mem::size_of::<Elf64Ehdr>() == 64);
+const _: () = assert!(mem::size_of::<Elf64Phdr>() == 56);
+const _: () = assert!(mem::size_of::<Elf64Shdr>() == 64);
+const _: () = assert!(mem::size_of::<Elf64Sym>() == 24);
+const _: () = assert!(mem::size_of::<Elf64Dyn>() == 16);
+const _: () = assert!(mem::size_of::<Elf64Rel>() == 16);
+const _: () = assert!(mem::size_of::<Elf64Rela>() == 24);
@@ -1,17 +1,22 @@
 //! Common interface for built-in and user supplied programs
-use alloc::boxed::Box;
+use alloc::{boxed::Box, string::String};
 use {
     crate::{
         ebpf,
         elf::ElfError,
         vm::{Config, ContextObject, EbpfVm},
     },
-    alloc::collections::{btree_map::Entry, BTreeMap},
+    alloc::collections::{btree_map::Entry, BTreeMap, BTreeSet},
     alloc::{vec::Vec},
+    core::{fmt, str::FromStr},
+    thiserror::Error,
 };
 
+/// Whether a feature is enabled in a given [SBPFVersion], see [SBPFVersion::FEATURES]
+pub type SBPFFeaturePredicate = fn(SBPFVersion) -> bool;
+
 /// Defines a set of sbpf_version of an executable
-#[derive(Debug, PartialEq, PartialOrd, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, PartialOrd, Eq, Clone, Copy, Hash)]
 pub enum SBPFVersion {
     /// The legacy format
     V0,
@@ -99,6 +104,91 @@ impl SBPFVersion {
             imm as u32
         }
     }
+
+    /// The name and predicate of every feature which depends on the SBPF version
+    pub const FEATURES: &'static [(&'static str, SBPFFeaturePredicate)] = &[
+        ("dynamic_stack_frames", SBPFVersion::dynamic_stack_frames),
+        ("enable_pqr", SBPFVersion::enable_pqr),
+        (
+            "explicit_sign_extension_of_results",
+            SBPFVersion::explicit_sign_extension_of_results,
+        ),
+        (
+            "swap_sub_reg_imm_operands",
+            SBPFVersion::swap_sub_reg_imm_operands,
+        ),
+        ("disable_neg", SBPFVersion::disable_neg),
+        ("callx_uses_src_reg", SBPFVersion::callx_uses_src_reg),
+        ("disable_lddw", SBPFVersion::disable_lddw),
+        ("disable_le", SBPFVersion::disable_le),
+        (
+            "move_memory_instruction_classes",
+            SBPFVersion::move_memory_instruction_classes,
+        ),
+        ("static_syscalls", SBPFVersion::static_syscalls),
+        (
+            "enable_stricter_elf_headers",
+            SBPFVersion::enable_stricter_elf_headers,
+        ),
+        (
+            "enable_lower_bytecode_vaddr",
+            SBPFVersion::enable_lower_bytecode_vaddr,
+        ),
+        (
+            "reject_rodata_stack_overlap",
+            SBPFVersion::reject_rodata_stack_overlap,
+        ),
+        ("enable_elf_vaddr", SBPFVersion::enable_elf_vaddr),
+    ];
+
+    /// Returns the name of every feature in [SBPFVersion::FEATURES] and whether it is enabled
+    pub fn features(self) -> impl Iterator<Item = (&'static str, bool)> {
+        Self::FEATURES
+            .iter()
+            .map(move |(name, predicate)| (*name, predicate(self)))
+    }
+}
+
+impl fmt::Display for SBPFVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SBPFVersion::V0 => "v0",
+            SBPFVersion::V1 => "v1",
+            SBPFVersion::V2 => "v2",
+            SBPFVersion::V3 => "v3",
+            SBPFVersion::Reserved => "v4",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Error parsing an [SBPFVersion] from text which is not one of "v0" to "v4"
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("invalid SBPF version {0:?}, expected one of v0, v1, v2, v3 or v4")]
+pub struct ParseSBPFVersionError(pub String);
+
+impl FromStr for SBPFVersion {
+    type Err = ParseSBPFVersionError;
+
+    /// Parses the [Display](fmt::Display) form of a version, ignoring ASCII case
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "v0" => Ok(SBPFVersion::V0),
+            "v1" => Ok(SBPFVersion::V1),
+            "v2" => Ok(SBPFVersion::V2),
+            "v3" => Ok(SBPFVersion::V3),
+            "v4" => Ok(SBPFVersion::Reserved),
+            _ => Err(ParseSBPFVersionError(s.into())),
+        }
+    }
+}
+
+/// Key of a syscall in the registry of a [BuiltinProgram], which `syscall` and `call`
+/// instructions dispatch on
+///
+/// The same as [ebpf::hash_symbol_name], for hosts which pre-compute keys.
+pub fn syscall_key(name: &[u8]) -> u32 {
+    ebpf::hash_symbol_name(name)
 }
 
 /// Holds the function symbols of an Executable
@@ -117,18 +207,54 @@ impl<T> Default for FunctionRegistry<T> {
 
 impl<T: Copy + PartialEq> FunctionRegistry<T> {
     /// Register a symbol with an explicit key
+    ///
+    /// Registering the same name and value again is a no-op. If the key is already taken by
+    /// another name this fails with [ElfError::SyscallKeyCollision], and by another value of
+    /// the same name with [ElfError::SymbolHashCollision]. Use [FunctionRegistry::replace] to
+    /// overwrite it.
     pub fn register_function(
         &mut self,
         key: u32,
         name: impl Into<Vec<u8>>,
         value: T,
+    ) -> Result<(), ElfError> {
+        self.insert(key, name.into(), value, false)
+    }
+
+    /// Like [FunctionRegistry::register_function], but keeps the first name of a value
+    ///
+    /// Used where the same function is found under several names, e.g. a label and the
+    /// generated name of a call to it.
+    pub(crate) fn register_function_keep_name(
+        &mut self,
+        key: u32,
+        name: impl Into<Vec<u8>>,
+        value: T,
+    ) -> Result<(), ElfError> {
+        self.insert(key, name.into(), value, true)
+    }
+
+    fn insert(
+        &mut self,
+        key: u32,
+        name: Vec<u8>,
+        value: T,
+        keep_name: bool,
     ) -> Result<(), ElfError> {
         match self.map.entry(key) {
             Entry::Vacant(entry) => {
-                entry.insert((name.into(), value));
+                entry.insert((name, value));
             }
             Entry::Occupied(entry) => {
-                if entry.get().1 != value {
+                let (registered_name, registered_value) = entry.get();
+                if !keep_name && *registered_name != name {
+                    return Err(ElfError::SyscallKeyCollision {
+                        existing: registered_name.as_slice().into(),
+                        new: name.into(),
+                        key,
+                    });
+                }
+                if *registered_value != value {
                     return Err(ElfError::SymbolHashCollision(key));
                 }
             }
@@ -136,6 +262,18 @@ impl<T: Copy + PartialEq> FunctionRegistry<T> {
         Ok(())
     }
 
+    /// Register a symbol, overwriting whatever was registered under the key before
+    ///
+    /// Returns the previous name and value.
+    pub fn replace(
+        &mut self,
+        key: u32,
+        name: impl Into<Vec<u8>>,
+        value: T,
+    ) -> Option<(Vec<u8>, T)> {
+        self.map.insert(key, (name.into(), value))
+    }
+
     /// Used for transitioning from SBPFv0 to SBPFv3
     pub(crate) fn register_function_hashed_legacy<C: ContextObject>(
         &mut self,
@@ -162,7 +300,7 @@ impl<T: Copy + PartialEq> FunctionRegistry<T> {
         } else {
             usize::from(value) as u32
         };
-        self.register_function(
+        self.register_function_keep_name(
             key,
             if config.enable_symbol_and_section_labels || name == b"entrypoint" {
                 name
@@ -179,6 +317,33 @@ impl<T: Copy + PartialEq> FunctionRegistry<T> {
         self.map.remove(&key);
     }
 
+    /// Check that the name of every function hashes to a key no other function occupies
+    ///
+    /// Only meaningful for registries keyed by [syscall_key], like the one of a
+    /// [BuiltinProgram]. [FunctionRegistry::register_function] already rejects a second name
+    /// under a key, but [FunctionRegistry::replace] can register a name under any key. Fails
+    /// with [ElfError::SyscallKeyCollision] for the first name which would dispatch to another
+    /// function. Unnamed functions are skipped.
+    pub fn verify_no_collisions(&self) -> Result<(), ElfError> {
+        for (key, (name, _value)) in self.map.iter() {
+            if name.is_empty() {
+                continue;
+            }
+            let hashed_key = syscall_key(name);
+            if hashed_key == *key {
+                continue;
+            }
+            if let Some((existing, _value)) = self.map.get(&hashed_key) {
+                return Err(ElfError::SyscallKeyCollision {
+                    existing: existing.as_slice().into(),
+                    new: name.as_slice().into(),
+                    key: hashed_key,
+                });
+            }
+        }
+        Ok(())
+    }
+
     /// Iterate over all keys
     pub fn keys(&self) -> impl Iterator<Item = u32> + '_ {
         self.map.keys().copied()
@@ -225,6 +390,24 @@ impl<T: Copy + PartialEq> FunctionRegistry<T> {
 /// Syscall function without context
 pub type BuiltinFunction<C> = fn(*mut EbpfVm<C>, u64, u64, u64, u64, u64);
 
+/// Host defined properties of a syscall, see [BuiltinProgramBuilder::syscall_with_metadata]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SyscallMetadata {
+    /// Compute units the host charges for every invocation
+    pub base_cost: u64,
+}
+
+/// A syscall which differs between two [BuiltinProgram]s, see [BuiltinProgram::diff]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyscallDifference {
+    /// Only registered in the first program
+    OnlyInSelf(Vec<u8>),
+    /// Only registered in the second program
+    OnlyInOther(Vec<u8>),
+    /// Registered in both, but with another function or metadata
+    Changed(Vec<u8>),
+}
+
 /// Represents the interface to a fixed functionality program
 #[derive(Eq)]
 pub struct BuiltinProgram<C: ContextObject> {
@@ -232,11 +415,15 @@ pub struct BuiltinProgram<C: ContextObject> {
     config: Option<Box<Config>>,
     /// Function pointers by symbol with sparse indexing
     sparse_registry: FunctionRegistry<BuiltinFunction<C>>,
+    /// Metadata of the syscalls which have any, by key
+    syscall_metadata: BTreeMap<u32, SyscallMetadata>,
 }
 
 impl<C: ContextObject> PartialEq for BuiltinProgram<C> {
     fn eq(&self, other: &Self) -> bool {
-        self.config.eq(&other.config) && self.sparse_registry.eq(&other.sparse_registry)
+        self.config.eq(&other.config)
+            && self.sparse_registry.eq(&other.sparse_registry)
+            && self.syscall_metadata.eq(&other.syscall_metadata)
     }
 }
 
@@ -246,6 +433,7 @@ impl<C: ContextObject> BuiltinProgram<C> {
         Self {
             config: Some(Box::new(config)),
             sparse_registry: FunctionRegistry::default(),
+            syscall_metadata: BTreeMap::new(),
         }
     }
 
@@ -254,6 +442,7 @@ impl<C: ContextObject> BuiltinProgram<C> {
         Self {
             config: None,
             sparse_registry: FunctionRegistry::default(),
+            syscall_metadata: BTreeMap::new(),
         }
     }
 
@@ -262,6 +451,7 @@ impl<C: ContextObject> BuiltinProgram<C> {
         Self {
             config: Some(Box::default()),
             sparse_registry: FunctionRegistry::default(),
+            syscall_metadata: BTreeMap::new(),
         }
     }
 
@@ -275,6 +465,39 @@ impl<C: ContextObject> BuiltinProgram<C> {
         &self.sparse_registry
     }
 
+    /// Get a syscall and its metadata by key
+    ///
+    /// Syscalls registered without metadata report the default.
+    pub fn lookup_by_key(&self, key: u32) -> Option<(&[u8], BuiltinFunction<C>, SyscallMetadata)> {
+        let (name, function) = self.sparse_registry.lookup_by_key(key)?;
+        let metadata = self.syscall_metadata.get(&key).copied().unwrap_or_default();
+        Some((name, function, metadata))
+    }
+
+    /// Compare the syscalls of two programs, ordered by key
+    ///
+    /// Helps finding out why a program loads with one loader but not with another. The
+    /// configs are not compared.
+    pub fn diff(&self, other: &Self) -> Vec<SyscallDifference> {
+        let keys = self
+            .sparse_registry
+            .keys()
+            .chain(other.sparse_registry.keys())
+            .collect::<BTreeSet<u32>>();
+        keys.into_iter()
+            .filter_map(
+                |key| match (self.lookup_by_key(key), other.lookup_by_key(key)) {
+                    (Some((name, ..)), None) => Some(SyscallDifference::OnlyInSelf(name.to_vec())),
+                    (None, Some((name, ..))) => Some(SyscallDifference::OnlyInOther(name.to_vec())),
+                    (Some(entry), Some(other_entry)) if entry != other_entry => {
+                        Some(SyscallDifference::Changed(entry.0.to_vec()))
+                    }
+                    _ => None,
+                },
+            )
+            .collect()
+    }
+
     /// Calculate memory size
     pub fn mem_size(&self) -> usize {
         core::mem::size_of::<Self>()
@@ -284,19 +507,145 @@ impl<C: ContextObject> BuiltinProgram<C> {
                 0
             })
             .saturating_add(self.sparse_registry.mem_size())
+            .saturating_add(
+                self.syscall_metadata
+                    .len()
+                    .saturating_mul(core::mem::size_of::<(u32, SyscallMetadata)>()),
+            )
     }
 
     /// Register a function both in the sparse and dense registries
+    ///
+    /// Fails with [ElfError::SyscallKeyCollision] if the name hashes to the key of another
+    /// function, see [FunctionRegistry::register_function].
     pub fn register_function(
         &mut self,
         name: &str,
         value: BuiltinFunction<C>,
     ) -> Result<(), ElfError> {
-        let key = ebpf::hash_symbol_name(name.as_bytes());
+        let key = syscall_key(name.as_bytes());
         self.sparse_registry
             .register_function(key, name, value)
             .map(|_| ())
     }
+
+    /// Register a function, overwriting the one registered under the same key before
+    ///
+    /// Returns the previous name and function, see [FunctionRegistry::replace].
+    pub fn replace_function(
+        &mut self,
+        name: &str,
+        value: BuiltinFunction<C>,
+    ) -> Option<(Vec<u8>, BuiltinFunction<C>)> {
+        let key = syscall_key(name.as_bytes());
+        self.sparse_registry.replace(key, name, value)
+    }
+
+    /// Unregister a function again, along with its metadata
+    pub fn unregister_function(&mut self, name: &str) {
+        let key = syscall_key(name.as_bytes());
+        self.sparse_registry.unregister_function(key);
+        self.syscall_metadata.remove(&key);
+    }
+}
+
+/// Collects the syscalls of a [BuiltinProgram] and freezes them in one step
+///
+/// Each syscall is added with a flag telling whether it is enabled, e.g. by a feature gate.
+/// Disabled syscalls are left out of the program, but still take part in the collision check
+/// of [BuiltinProgramBuilder::build], so that enabling them later can not fail. A name can be
+/// added more than once, e.g. for the implementations before and after a feature, as long as
+/// at most one of them is enabled.
+pub struct BuiltinProgramBuilder<C: ContextObject> {
+    config: Option<Box<Config>>,
+    syscalls: Vec<BuilderSyscall<C>>,
+}
+
+struct BuilderSyscall<C: ContextObject> {
+    name: Vec<u8>,
+    function: BuiltinFunction<C>,
+    enabled: bool,
+    metadata: Option<SyscallMetadata>,
+}
+
+impl<C: ContextObject> BuiltinProgramBuilder<C> {
+    /// Starts a loader built-in program, see [BuiltinProgram::new_loader]
+    pub fn new_loader(config: Config) -> Self {
+        Self {
+            config: Some(Box::new(config)),
+            syscalls: Vec::new(),
+        }
+    }
+
+    /// Starts a built-in program, see [BuiltinProgram::new_builtin]
+    pub fn new_builtin() -> Self {
+        Self {
+            config: None,
+            syscalls: Vec::new(),
+        }
+    }
+
+    /// Adds a syscall, which is only registered if `enabled`
+    pub fn syscall(self, name: &str, function: BuiltinFunction<C>, enabled: bool) -> Self {
+        self.push(name, function, enabled, None)
+    }
+
+    /// Adds a syscall with metadata, see [BuiltinProgram::lookup_by_key]
+    pub fn syscall_with_metadata(
+        self,
+        name: &str,
+        function: BuiltinFunction<C>,
+        enabled: bool,
+        metadata: SyscallMetadata,
+    ) -> Self {
+        self.push(name, function, enabled, Some(metadata))
+    }
+
+    fn push(
+        mut self,
+        name: &str,
+        function: BuiltinFunction<C>,
+        enabled: bool,
+        metadata: Option<SyscallMetadata>,
+    ) -> Self {
+        self.syscalls.push(BuilderSyscall {
+            name: name.as_bytes().to_vec(),
+            function,
+            enabled,
+            metadata,
+        });
+        self
+    }
+
+    /// Checks all names for key collisions and registers the enabled syscalls
+    ///
+    /// Fails with [ElfError::SyscallKeyCollision] if two names share a key, whether enabled or
+    /// not, and with [ElfError::SymbolHashCollision] if a name is enabled more than once with
+    /// different functions.
+    pub fn build(self) -> Result<BuiltinProgram<C>, ElfError> {
+        let mut names = FunctionRegistry::<()>::default();
+        for syscall in self.syscalls.iter() {
+            names.register_function(syscall_key(&syscall.name), syscall.name.as_slice(), ())?;
+        }
+        let mut program = BuiltinProgram {
+            config: self.config,
+            sparse_registry: FunctionRegistry::default(),
+            syscall_metadata: BTreeMap::new(),
+        };
+        for syscall in self.syscalls {
+            if !syscall.enabled {
+                continue;
+            }
+            let key = syscall_key(&syscall.name);
+            program
+                .sparse_registry
+                .register_function(key, syscall.name, syscall.function)?;
+            if let Some(metadata) = syscall.metadata {
+                program.syscall_metadata.insert(key, metadata);
+            }
+        }
+        Ok(program)
+    }
 }
 
 impl<C: ContextObject> alloc::fmt::Debug for BuiltinProgram<C> {
@@ -361,6 +710,7 @@ macro_rules! declare_builtin_function {
                 if config.enable_instruction_meter {
                     vm.context_object_pointer.consume(vm.previous_instruction_meter - vm.due_insn_count);
                 }
+                let remaining = vm.syscall_recorder.is_some().then(|| vm.context_object_pointer.get_remaining());
                 let converted_result: $crate::error::ProgramResult = Self::rust $(::<$($generic_ident),+>)?(
                     vm.context_object_pointer, $arg_a, $arg_b, $arg_c, $arg_d, $arg_e, &mut vm.memory_mapping,
                 ).map_err(|err| $crate::error::EbpfError::SyscallError(err)).into();
@@ -368,6 +718,9 @@ macro_rules! declare_builtin_function {
                 if config.enable_instruction_meter {
                     vm.previous_instruction_meter = vm.context_object_pointer.get_remaining();
                 }
+                if let Some(remaining) = remaining {
+                    vm.record_syscall(Self::vm $(::<$($generic_ident),+>)?, [$arg_a, $arg_b, $arg_c, $arg_d, $arg_e], remaining);
+                }
             }
         }
     };
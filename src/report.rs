@@ -0,0 +1,274 @@
+// Copyright 2026 Solana Maintainers <maintainers@solana.com>
+//
+// Licensed under the Apache License, Version 2.0 <http://www.apache.org/licenses/LICENSE-2.0> or
+// the MIT license <http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Summary of an executable for dashboards and tooling.
+
+use crate::{
+    ebpf,
+    elf::Executable,
+    program::SBPFVersion,
+    static_analysis::Analysis,
+    verifier::{RequisiteVerifier, Verifier, VerifierConfig},
+    vm::ContextObject,
+};
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::{self, Write};
+
+/// Version of the JSON schema emitted by [ProgramReport::to_json]
+///
+/// Incremented whenever a field is renamed, removed or changes its meaning.
+pub const REPORT_SCHEMA_VERSION: u32 = 1;
+
+/// A function of the executable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionReport {
+    /// Symbol name, or a generated label if the executable has no symbols
+    pub name: String,
+    /// Instruction offset of the first instruction
+    pub pc: usize,
+    /// Number of instruction slots up to the next function or the end of the text section
+    pub instruction_slots: usize,
+}
+
+/// Self-describing summary of an executable
+///
+/// The fields are collected from the executable, its [Analysis] and the [RequisiteVerifier].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgramReport {
+    /// Length of the ELF file in bytes
+    pub elf_size: usize,
+    /// Length of the text section in bytes
+    pub text_size: usize,
+    /// Length of the read-only section in bytes
+    pub ro_section_size: usize,
+    /// SBPF version of the executable
+    pub sbpf_version: SBPFVersion,
+    /// Number of instructions, counting `lddw` once
+    pub instruction_count: usize,
+    /// Number of instructions by opcode
+    pub instruction_histogram: BTreeMap<u8, usize>,
+    /// Number of functions in the function registry
+    pub function_count: usize,
+    /// The function spanning the most instruction slots
+    pub largest_function: Option<FunctionReport>,
+    /// Names of the syscalls called by the program, sorted
+    ///
+    /// Calls to keys which are not registered in the loader are listed as hexadecimal keys.
+    pub syscalls: Vec<String>,
+    /// Error of the [RequisiteVerifier], if it rejects the program
+    pub verification_error: Option<String>,
+    /// Newer SBPF versions whose verifier accepts the bytecode unchanged
+    pub compatible_versions: Vec<SBPFVersion>,
+}
+
+impl ProgramReport {
+    /// Collects the report of an executable from an existing analysis of it
+    pub fn new<C: ContextObject>(executable: &Executable<C>, analysis: &Analysis) -> Self {
+        let sbpf_version = executable.get_sbpf_version();
+        let (_text_vaddr, text_bytes) = executable.get_text_bytes();
+        let text_slots = text_bytes.len() / ebpf::INSN_SIZE;
+
+        let mut instruction_histogram = BTreeMap::new();
+        let mut syscalls = BTreeSet::new();
+        for insn in &analysis.instructions {
+            let count = instruction_histogram.entry(insn.opc).or_insert(0usize);
+            *count = count.saturating_add(1);
+            let syscall_key = match insn.opc {
+                ebpf::SYSCALL if sbpf_version.static_syscalls() => Some(insn.imm as u32),
+                ebpf::CALL_IMM if !sbpf_version.static_syscalls() => {
+                    let key = sbpf_version.calculate_call_imm_target_pc(insn.ptr, insn.imm);
+                    executable
+                        .get_function_registry()
+                        .lookup_by_key(key)
+                        .is_none()
+                        .then_some(insn.imm as u32)
+                }
+                _ => None,
+            };
+            if let Some(key) = syscall_key {
+                syscalls.insert(
+                    match executable
+                        .get_loader()
+                        .get_function_registry()
+                        .lookup_by_key(key)
+                    {
+                        Some((name, _function)) => String::from_utf8_lossy(name).to_string(),
+                        None => format!("{key:#x}"),
+                    },
+                );
+            }
+        }
+
+        let function_pcs = analysis.functions.keys().copied().collect::<Vec<_>>();
+        let largest_function = function_pcs
+            .iter()
+            .enumerate()
+            .map(|(index, pc)| {
+                let end = function_pcs
+                    .get(index.saturating_add(1))
+                    .copied()
+                    .unwrap_or(text_slots);
+                FunctionReport {
+                    name: analysis.functions[pc].1.clone(),
+                    pc: *pc,
+                    instruction_slots: end.saturating_sub(*pc),
+                }
+            })
+            .fold(None, |largest: Option<FunctionReport>, function| match largest {
+                Some(largest) if largest.instruction_slots >= function.instruction_slots => {
+                    Some(largest)
+                }
+                _ => Some(function),
+            });
+
+        let verify = |sbpf_version| {
+            RequisiteVerifier::verify(
+                text_bytes,
+                executable.get_config(),
+                &VerifierConfig::default(),
+                sbpf_version,
+                executable.get_function_registry(),
+                executable.get_loader().get_function_registry(),
+            )
+        };
+        let compatible_versions = [SBPFVersion::V1, SBPFVersion::V2, SBPFVersion::V3]
+            .iter()
+            .copied()
+            .filter(|version| *version > sbpf_version && verify(*version).is_ok())
+            .collect();
+
+        Self {
+            elf_size: executable.get_elf_bytes().len(),
+            text_size: text_bytes.len(),
+            ro_section_size: executable.get_ro_section().len(),
+            sbpf_version,
+            instruction_count: analysis.instructions.len(),
+            instruction_histogram,
+            function_count: executable.get_function_registry().iter().count(),
+            largest_function,
+            syscalls: syscalls.into_iter().collect(),
+            verification_error: verify(sbpf_version).err().map(|err| err.to_string()),
+            compatible_versions,
+        }
+    }
+
+    /// Serializes the report as a JSON object
+    ///
+    /// The keys are emitted in a fixed order, see [REPORT_SCHEMA_VERSION].
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        let _ = self.write_json(&mut json);
+        json
+    }
+
+    fn write_json(&self, output: &mut String) -> fmt::Result {
+        write!(output, "{{\"schema_version\":{REPORT_SCHEMA_VERSION}")?;
+        write!(output, ",\"elf_size\":{}", self.elf_size)?;
+        write!(output, ",\"text_size\":{}", self.text_size)?;
+        write!(output, ",\"ro_section_size\":{}", self.ro_section_size)?;
+        write!(output, ",\"sbpf_version\":\"{:?}\"", self.sbpf_version)?;
+        write!(output, ",\"instruction_count\":{}", self.instruction_count)?;
+        output.push_str(",\"instruction_histogram\":{");
+        for (index, (opcode, count)) in self.instruction_histogram.iter().enumerate() {
+            if index > 0 {
+                output.push(',');
+            }
+            write!(output, "\"{opcode:#04x}\":{count}")?;
+        }
+        write!(output, "}},\"function_count\":{}", self.function_count)?;
+        output.push_str(",\"largest_function\":");
+        match &self.largest_function {
+            Some(function) => {
+                output.push_str("{\"name\":");
+                write_json_string(output, &function.name);
+                write!(
+                    output,
+                    ",\"pc\":{},\"instruction_slots\":{}}}",
+                    function.pc, function.instruction_slots
+                )?;
+            }
+            None => output.push_str("null"),
+        }
+        output.push_str(",\"syscalls\":[");
+        for (index, name) in self.syscalls.iter().enumerate() {
+            if index > 0 {
+                output.push(',');
+            }
+            write_json_string(output, name);
+        }
+        output.push_str("],\"verification_error\":");
+        match &self.verification_error {
+            Some(error) => write_json_string(output, error),
+            None => output.push_str("null"),
+        }
+        output.push_str(",\"compatible_versions\":[");
+        for (index, version) in self.compatible_versions.iter().enumerate() {
+            if index > 0 {
+                output.push(',');
+            }
+            write!(output, "\"{version:?}\"")?;
+        }
+        output.push_str("]}");
+        Ok(())
+    }
+}
+
+pub(crate) fn write_json_string(output: &mut String, string: &str) {
+    output.push('"');
+    for character in string.chars() {
+        match character {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            character if (character as u32) < 0x20 => {
+                let _ = write!(output, "\\u{:04x}", character as u32);
+            }
+            character => output.push(character),
+        }
+    }
+    output.push('"');
+}
+
+impl fmt::Display for ProgramReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "SBPF version:       {:?}", self.sbpf_version)?;
+        writeln!(f, "ELF size:           {} bytes", self.elf_size)?;
+        writeln!(f, "Text section:       {} bytes", self.text_size)?;
+        writeln!(f, "Read-only section:  {} bytes", self.ro_section_size)?;
+        writeln!(f, "Instructions:       {}", self.instruction_count)?;
+        writeln!(f, "Functions:          {}", self.function_count)?;
+        if let Some(function) = &self.largest_function {
+            writeln!(
+                f,
+                "Largest function:   {} at pc {} ({} instruction slots)",
+                function.name, function.pc, function.instruction_slots
+            )?;
+        }
+        writeln!(f, "Syscalls:           {}", self.syscalls.join(", "))?;
+        match &self.verification_error {
+            Some(error) => writeln!(f, "Verification:       failed: {error}")?,
+            None => writeln!(f, "Verification:       passed")?,
+        }
+        let compatible_versions = self
+            .compatible_versions
+            .iter()
+            .map(|version| format!("{version:?}"))
+            .collect::<Vec<_>>();
+        writeln!(f, "Compatible with:    {}", compatible_versions.join(", "))?;
+        writeln!(f, "Instruction histogram:")?;
+        for (opcode, count) in &self.instruction_histogram {
+            writeln!(f, "  {opcode:#04x}: {count}")?;
+        }
+        Ok(())
+    }
+}
@@ -1,7 +1,11 @@
 #![allow(clippy::arithmetic_side_effects)]
 //! Static Byte Code Analysis
 
-use crate::disassembler::disassemble_instruction;
+use crate::disassembler::{
+    disassemble_instruction, disassemble_instruction_ex, disassemble_instruction_with_options,
+    decode, disassembly_record, fill_str, reassemblable_labels, DisassembledInstruction,
+    DisassemblyOptions, DisassemblyRecord, DisassemblyStyle, Operands,
+};
 use crate::error::InternalError;
 use crate::utils;
 use crate::{
@@ -14,6 +18,7 @@ use crate::{
 use alloc::{format, vec, vec::Vec, string::{String, ToString}};
 use rustc_demangle::demangle;
 use alloc::collections::{BTreeMap, BTreeSet,};
+use core::convert::TryFrom;
 use hashbrown::{HashMap, HashSet};
 
 /// Register state recorded after executing one instruction
@@ -68,6 +73,8 @@ pub struct CfgNode {
     pub dominator_parent: usize,
     /// All basic blocks which can only be reached through this one
     pub dominated_children: Vec<usize>,
+    /// Statically known target of the `callx` ending this basic block
+    pub indirect_call_target: Option<usize>,
 }
 
 /// An instruction or Φ node of the data-flow graph
@@ -114,6 +121,124 @@ pub struct DfgEdge {
     pub resource: DataResource,
 }
 
+/// Upper bound of the number of instructions an execution can take
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Bound {
+    /// No execution takes more instructions than this
+    Exact(u64),
+    /// The number of instructions is not statically bounded
+    Unbounded {
+        /// Loop headers, the basic blocks through which the cycles of the CFG are entered
+        loops: Vec<usize>,
+        /// Cycles of the call graph, each one as the entry points of the functions involved
+        recursion: Vec<Vec<usize>>,
+        /// `callx` instructions and calls to targets which are not basic blocks
+        indirect_calls: Vec<usize>,
+    },
+}
+
+/// Calls made by a function, see [Analysis::call_graph]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FunctionCalls {
+    /// Entry points of the functions called
+    pub functions: BTreeSet<usize>,
+    /// Keys of the syscalls called
+    pub syscalls: BTreeSet<u32>,
+    /// Calls whose target is not known, `callx` or calls to pcs which are not basic blocks
+    pub unknown_callees: Vec<usize>,
+}
+
+/// Number of stack frames a program needs, see [Analysis::max_call_depth]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallDepth {
+    /// No execution needs more stack frames than this
+    Exact(usize),
+    /// Calls with unknown targets can need more stack frames than this
+    AtLeast(usize),
+}
+
+/// A natural loop, see [Analysis::loops]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loop {
+    /// Basic block through which the loop is entered, it dominates the entire body
+    pub header: usize,
+    /// Basic blocks of the loop, including the header
+    pub body: BTreeSet<usize>,
+}
+
+/// A cycle of the CFG which can be entered at more than one basic block
+///
+/// As no basic block dominates the others, it is not a natural loop.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IrreducibleCycle {
+    /// Basic blocks of the cycle which can be jumped to from outside of it
+    pub entries: BTreeSet<usize>,
+    /// Basic blocks of the cycle, including the entries
+    pub body: BTreeSet<usize>,
+}
+
+/// Values derived from the frame pointer (r10), see [Analysis::stack_pointer_leaks]
+///
+/// Every entry holds the pc of the earliest instruction reading r10 which the value depends on.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct StackPointerTaint {
+    /// Registers r0 to r9
+    registers: [Option<usize>; ebpf::FRAME_PTR_REG],
+    /// Any location of the stack
+    stack: Option<usize>,
+}
+
+fn join_taint_source(a: Option<usize>, b: Option<usize>) -> Option<usize> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+impl StackPointerTaint {
+    /// Merges `other` into `self` and returns whether anything changed
+    fn join(&mut self, other: &Self) -> bool {
+        let joined = Self {
+            registers: core::array::from_fn(|reg| {
+                join_taint_source(self.registers[reg], other.registers[reg])
+            }),
+            stack: join_taint_source(self.stack, other.stack),
+        };
+        let changed = joined != *self;
+        *self = joined;
+        changed
+    }
+
+    /// The state after returning to `caller` from a function exiting with `self`
+    ///
+    /// r6 to r9 are restored from the caller, the stack is shared by all frames.
+    fn returned_to(&self, caller: &Self) -> Self {
+        let mut state = self.clone();
+        state.registers[ebpf::FIRST_SCRATCH_REG..]
+            .copy_from_slice(&caller.registers[ebpf::FIRST_SCRATCH_REG..]);
+        state.stack = join_taint_source(self.stack, caller.stack);
+        state
+    }
+}
+
+fn merge_taint(
+    states: &mut BTreeMap<usize, StackPointerTaint>,
+    worklist: &mut BTreeSet<usize>,
+    cfg_node_start: usize,
+    taint: &StackPointerTaint,
+) {
+    let changed = match states.get_mut(&cfg_node_start) {
+        Some(state) => state.join(taint),
+        None => {
+            states.insert(cfg_node_start, taint.clone());
+            true
+        }
+    };
+    if changed {
+        worklist.insert(cfg_node_start);
+    }
+}
+
 impl Default for CfgNode {
     fn default() -> Self {
         Self {
@@ -124,6 +249,7 @@ impl Default for CfgNode {
             topo_index: TopologicalIndex::default(),
             dominator_parent: usize::MAX,
             dominated_children: Vec::new(),
+            indirect_call_target: None,
         }
     }
 }
@@ -160,6 +286,8 @@ pub struct Analysis<'a> {
     pub dfg_forward_edges: BTreeMap<DfgNode, BTreeSet<DfgEdge>>,
     /// Data flow edges (the keys are DfgEdge destinations)
     pub dfg_reverse_edges: BTreeMap<DfgNode, BTreeSet<DfgEdge>>,
+    /// Immediate dominators, see [Analysis::dominators]
+    dominators: BTreeMap<usize, usize>,
 }
 
 impl<'a> Analysis<'a> {
@@ -185,7 +313,7 @@ impl<'a> Analysis<'a> {
         let mut insn_ptr: usize = 0;
         while insn_ptr * ebpf::INSN_SIZE < program.len() {
             let mut insn = ebpf::get_insn_unchecked(program, insn_ptr);
-            if insn.opc == ebpf::LD_DW_IMM {
+            if insn.opc == ebpf::LD_DW_IMM && !executable.get_sbpf_version().disable_lddw() {
                 insn_ptr += 1;
                 if insn_ptr * ebpf::INSN_SIZE >= program.len() {
                     break;
@@ -208,16 +336,22 @@ impl<'a> Analysis<'a> {
             super_root: insn_ptr,
             dfg_forward_edges: BTreeMap::new(),
             dfg_reverse_edges: BTreeMap::new(),
+            dominators: BTreeMap::new(),
         };
-        result.split_into_basic_blocks(false, executable.get_sbpf_version());
-        result.control_flow_graph_tarjan();
-        result.control_flow_graph_dominance_hierarchy();
-        result.label_basic_blocks();
-        let basic_block_outputs = result.intra_basic_block_data_flow();
-        result.inter_basic_block_data_flow(basic_block_outputs);
+        result.build_graphs();
         Ok(result)
     }
 
+    /// Builds the control-flow graph and the data flow of the functions
+    fn build_graphs(&mut self) {
+        self.split_into_basic_blocks(false, self.executable.get_sbpf_version());
+        self.control_flow_graph_tarjan();
+        self.control_flow_graph_dominance_hierarchy();
+        self.label_basic_blocks();
+        let basic_block_outputs = self.intra_basic_block_data_flow();
+        self.inter_basic_block_data_flow(basic_block_outputs);
+    }
+
     fn link_cfg_edges(&mut self, cfg_edges: Vec<(usize, Vec<usize>)>, both_directions: bool) {
         for (source, destinations) in cfg_edges {
             if both_directions {
@@ -316,6 +450,25 @@ impl<'a> Analysis<'a> {
                 _ => {}
             }
         }
+        // Resolving a callx can split the basic block of another one
+        let indirect_call_targets = loop {
+            let indirect_call_targets = self.indirect_call_targets(sbpf_version);
+            let mut new_cfg_nodes = false;
+            for target_pc in indirect_call_targets.values() {
+                if !self.cfg_nodes.contains_key(target_pc) {
+                    self.cfg_nodes.insert(*target_pc, CfgNode::default());
+                    new_cfg_nodes = true;
+                }
+            }
+            if !new_cfg_nodes {
+                break indirect_call_targets;
+            }
+        };
+        if flatten_call_graph {
+            for (callx_ptr, target_pc) in indirect_call_targets.iter() {
+                cfg_edges.insert(*callx_ptr, (ebpf::CALL_REG, vec![callx_ptr + 1, *target_pc]));
+            }
+        }
         {
             let mut cfg_nodes = BTreeMap::new();
             core::mem::swap(&mut self.cfg_nodes, &mut cfg_nodes);
@@ -349,7 +502,7 @@ impl<'a> Analysis<'a> {
             let mut instruction_index = 0;
             let mut cfg_node_iter = self.cfg_nodes.iter_mut().peekable();
             let mut cfg_edge_iter = cfg_edges.iter_mut().peekable();
-            while let Some((cfg_node_start, cfg_node)) = cfg_node_iter.next() {
+            while let Some((_, cfg_node)) = cfg_node_iter.next() {
                 let cfg_node_end = if let Some(next_cfg_node) = cfg_node_iter.peek() {
                     *next_cfg_node.0 - 1
                 } else {
@@ -372,12 +525,17 @@ impl<'a> Analysis<'a> {
                     }
                 }
                 if let Some(next_cfg_node) = cfg_node_iter.peek() {
-                    if !self.functions.contains_key(cfg_node_start) {
+                    if !self.functions.contains_key(next_cfg_node.0) {
                         cfg_node.destinations.push(*next_cfg_node.0);
                     }
                 }
             }
         }
+        for (callx_ptr, target_pc) in indirect_call_targets.iter() {
+            if let Some((_, cfg_node)) = self.cfg_nodes.range_mut(..=callx_ptr).next_back() {
+                cfg_node.indirect_call_target = Some(*target_pc);
+            }
+        }
         self.link_cfg_edges(
             self.cfg_nodes
                 .iter()
@@ -410,6 +568,96 @@ impl<'a> Analysis<'a> {
         }
     }
 
+    /// Finds the `callx` instructions whose target register holds a constant
+    ///
+    /// The constant must be loaded by a `lddw` or `mov` (and `hor64`) in the same basic block
+    /// as the `callx`, without the register being redefined in between. Returns the pc of each
+    /// `callx` along with the pc of the instruction its target address points to.
+    fn indirect_call_targets(&self, sbpf_version: SBPFVersion) -> BTreeMap<usize, usize> {
+        let (program_vm_addr, _program) = self.executable.get_text_bytes();
+        let mut indirect_call_targets = BTreeMap::new();
+        for (index, insn) in self.instructions.iter().enumerate() {
+            if insn.opc != ebpf::CALL_REG {
+                continue;
+            }
+            let reg = if sbpf_version.callx_uses_src_reg() {
+                insn.src
+            } else {
+                insn.imm as u8
+            };
+            let value = match self.constant_register_value(index, reg, sbpf_version) {
+                Some(value) => value,
+                None => continue,
+            };
+            let target_pc = value.wrapping_sub(program_vm_addr) / ebpf::INSN_SIZE as u64;
+            let target_pc = match usize::try_from(target_pc) {
+                Ok(target_pc) => target_pc,
+                Err(_) => continue,
+            };
+            if self
+                .instructions
+                .binary_search_by(|insn| insn.ptr.cmp(&target_pc))
+                .is_ok()
+            {
+                indirect_call_targets.insert(insn.ptr, target_pc);
+            }
+        }
+        indirect_call_targets
+    }
+
+    /// Looks for the constant `reg` holds before the instruction at `index`
+    ///
+    /// Only the instructions of the current basic block are considered, so the cfg_nodes of
+    /// the basic block starts must already exist.
+    fn constant_register_value(
+        &self,
+        mut index: usize,
+        mut reg: u8,
+        sbpf_version: SBPFVersion,
+    ) -> Option<u64> {
+        // Bits set by hor64 instructions after the definition
+        let mut high_bits = 0;
+        while index > 0 && !self.cfg_nodes.contains_key(&self.instructions[index].ptr) {
+            index -= 1;
+            let insn = &self.instructions[index];
+            let is_store = if sbpf_version.move_memory_instruction_classes() {
+                matches!(
+                    insn.opc,
+                    ebpf::ST_1B_IMM
+                        | ebpf::ST_2B_IMM
+                        | ebpf::ST_4B_IMM
+                        | ebpf::ST_8B_IMM
+                        | ebpf::ST_1B_REG
+                        | ebpf::ST_2B_REG
+                        | ebpf::ST_4B_REG
+                        | ebpf::ST_8B_REG
+                )
+            } else {
+                matches!(insn.opc & ebpf::BPF_CLS_MASK, ebpf::BPF_ST | ebpf::BPF_STX)
+            };
+            match insn.opc {
+                ebpf::CALL_IMM | ebpf::CALL_REG | ebpf::SYSCALL => {
+                    // Calls clobber the argument and return registers
+                    if (reg as usize) < ebpf::FIRST_SCRATCH_REG {
+                        return None;
+                    }
+                }
+                _ if insn.dst != reg || is_store => {}
+                ebpf::LD_DW_IMM if !sbpf_version.disable_lddw() => {
+                    return Some(insn.imm as u64 | high_bits)
+                }
+                ebpf::HOR64_IMM if sbpf_version.disable_lddw() => {
+                    high_bits |= (insn.imm as u64) << 32;
+                }
+                ebpf::MOV32_IMM => return Some(insn.imm as u32 as u64 | high_bits),
+                ebpf::MOV64_IMM => return Some(insn.imm as u64 | high_bits),
+                ebpf::MOV64_REG => reg = insn.src,
+                _ => return None,
+            }
+        }
+        None
+    }
+
     /// Gives the basic blocks names
     pub fn label_basic_blocks(&mut self) {
         for (pc, cfg_node) in self.cfg_nodes.iter_mut() {
@@ -424,6 +672,77 @@ impl<'a> Analysis<'a> {
         }
     }
 
+    /// Recovers the function boundaries of programs whose function registry lacks them
+    ///
+    /// Every target of an internal call starts a function, so does every pc following an
+    /// `exit`, `return` or `ja` if it is a jump or call target. The recovered functions are
+    /// named `fn_<pc>`, the functions of the registry keep their names unless the name is empty,
+    /// as it is in stripped ELFs. The control-flow graph and the data flow are rebuilt
+    /// afterwards, so the listings group the basic blocks under the recovered functions.
+    pub fn recover_functions(&mut self) {
+        let sbpf_version = self.executable.get_sbpf_version();
+        let function_registry = self.executable.get_function_registry();
+        let mut call_targets = BTreeSet::new();
+        let mut jump_targets = BTreeSet::new();
+        for insn in self.instructions.iter() {
+            match decode(insn, sbpf_version).operands {
+                Operands::Call { key } if sbpf_version.static_syscalls() => {
+                    call_targets.insert(key as usize);
+                }
+                Operands::Call { key } => {
+                    if let Some((_function_name, target_pc)) = function_registry.lookup_by_key(key)
+                    {
+                        call_targets.insert(target_pc);
+                    }
+                }
+                Operands::Jump { target_pc }
+                | Operands::JumpImmediate { target_pc, .. }
+                | Operands::JumpRegister { target_pc, .. } => {
+                    jump_targets.insert(target_pc as usize);
+                }
+                _ => {}
+            }
+        }
+        let mut function_starts = call_targets.clone();
+        for insn in self.instructions.iter() {
+            let next_pc = insn.ptr + 1;
+            if matches!(
+                decode(insn, sbpf_version).mnemonic,
+                "exit" | "return" | "ja"
+            ) && (call_targets.contains(&next_pc) || jump_targets.contains(&next_pc))
+            {
+                function_starts.insert(next_pc);
+            }
+        }
+        for function_start in function_starts {
+            if self
+                .instructions
+                .binary_search_by(|insn| insn.ptr.cmp(&function_start))
+                .is_err()
+            {
+                continue;
+            }
+            let name = format!("fn_{function_start}");
+            let registry_key = self
+                .functions
+                .get(&function_start)
+                .map(|(key, _name)| *key)
+                .filter(|key| function_registry.lookup_by_key(*key).is_some());
+            let key = match registry_key {
+                Some(_) if !self.functions[&function_start].1.is_empty() => continue,
+                Some(key) => key,
+                None => ebpf::hash_symbol_name(name.as_bytes()),
+            };
+            self.functions.insert(function_start, (key, name));
+        }
+        self.cfg_nodes.clear();
+        self.topological_order.clear();
+        self.dfg_forward_edges.clear();
+        self.dfg_reverse_edges.clear();
+        self.dominators.clear();
+        self.build_graphs();
+    }
+
     /// Generates labels for assembler code
     pub fn disassemble_label<W: crate::utils::Write>(
         &self,
@@ -447,90 +766,1002 @@ impl<'a> Analysis<'a> {
                 pc
             };
         }
-        Ok(())
+        Ok(())
+    }
+
+    /// Generates assembler code for a single instruction
+    pub fn disassemble_instruction(&self, insn: &ebpf::Insn, pc: usize) -> String {
+        disassemble_instruction(
+            insn,
+            pc,
+            &self.cfg_nodes,
+            self.executable.get_function_registry(),
+            self.executable.get_loader(),
+            self.executable.get_sbpf_version(),
+        )
+    }
+
+    /// Disassembles a single instruction along with its pc and encoding
+    pub fn disassemble_instruction_ex(
+        &self,
+        insn: &ebpf::Insn,
+        pc: usize,
+    ) -> DisassembledInstruction {
+        disassemble_instruction_ex(
+            insn,
+            pc,
+            &self.cfg_nodes,
+            self.executable.get_function_registry(),
+            self.executable.get_loader(),
+            self.executable.get_sbpf_version(),
+        )
+    }
+
+    /// Disassembles a single instruction with the given [DisassemblyOptions]
+    pub fn disassemble_instruction_with_options(
+        &self,
+        insn: &ebpf::Insn,
+        pc: usize,
+        options: DisassemblyOptions,
+    ) -> DisassembledInstruction {
+        disassemble_instruction_with_options(
+            insn,
+            pc,
+            &self.cfg_nodes,
+            self.executable.get_function_registry(),
+            self.executable.get_loader(),
+            self.executable.get_sbpf_version(),
+            options,
+        )
+    }
+
+    /// Returns the structured form of every instruction, see [disassembly_record]
+    pub fn disassembly_records(&self) -> Vec<DisassemblyRecord> {
+        self.instructions
+            .iter()
+            .map(|insn| {
+                disassembly_record(
+                    insn,
+                    &self.cfg_nodes,
+                    self.executable.get_function_registry(),
+                    self.executable.get_loader(),
+                    self.executable.get_sbpf_version(),
+                )
+            })
+            .collect()
+    }
+
+    /// Generates assembler code for the analyzed executable
+    pub fn disassemble<W: crate::utils::Write>(&self, output: &mut W) -> Result<(), InternalError> {
+        self.disassemble_listing(output, false, DisassemblyOptions::default())
+    }
+
+    /// Generates assembler code for the analyzed executable, each instruction prefixed with
+    /// its pc and encoding as `pc: bytes  mnemonic operands`
+    pub fn disassemble_with_encoding<W: crate::utils::Write>(
+        &self,
+        output: &mut W,
+    ) -> Result<(), InternalError> {
+        self.disassemble_listing(output, true, DisassemblyOptions::default())
+    }
+
+    /// Generates assembler code for the analyzed executable in the given [DisassemblyStyle]
+    ///
+    /// In [DisassemblyStyle::Reassemblable] the labels of the basic blocks and functions are
+    /// replaced by `lbb_N` labels declared for every jump and call target, assembling the output
+    /// with the same loader reproduces the text section byte for byte.
+    pub fn disassemble_with_style<W: crate::utils::Write>(
+        &self,
+        output: &mut W,
+        style: DisassemblyStyle,
+    ) -> Result<(), InternalError> {
+        self.disassemble_with_options(
+            output,
+            DisassemblyOptions {
+                style,
+                ..DisassemblyOptions::default()
+            },
+        )
+    }
+
+    /// Generates assembler code for the analyzed executable with the given [DisassemblyOptions]
+    pub fn disassemble_with_options<W: crate::utils::Write>(
+        &self,
+        output: &mut W,
+        options: DisassemblyOptions,
+    ) -> Result<(), InternalError> {
+        match options.style {
+            DisassemblyStyle::Readable => self.disassemble_listing(output, false, options),
+            DisassemblyStyle::Reassemblable => self.disassemble_reassemblable(output, options),
+        }
+    }
+
+    fn disassemble_reassemblable<W: crate::utils::Write>(
+        &self,
+        output: &mut W,
+        options: DisassemblyOptions,
+    ) -> Result<(), InternalError> {
+        let (_program_vm_addr, program) = self.executable.get_text_bytes();
+        let sbpf_version = self.executable.get_sbpf_version();
+        let end_pc = program.len() / ebpf::INSN_SIZE;
+        let labels = reassemblable_labels(&self.instructions, end_pc, sbpf_version);
+        let mut next_pc = 0;
+        for insn in self.instructions.iter() {
+            if labels.contains_key(&insn.ptr) {
+                writeln!(output, "lbb_{}:", insn.ptr)?;
+            }
+            let instruction = disassemble_instruction_with_options(
+                insn,
+                insn.ptr,
+                &labels,
+                self.executable.get_function_registry(),
+                self.executable.get_loader(),
+                sbpf_version,
+                options,
+            );
+            next_pc = insn.ptr + instruction.bytes.len() / ebpf::INSN_SIZE;
+            let slots = &program[insn.ptr * ebpf::INSN_SIZE..next_pc * ebpf::INSN_SIZE];
+            if insn.opc == ebpf::LD_DW_IMM && instruction.bytes != slots {
+                // The second slot of the lddw is not the one the assembler generates
+                for slot in slots.chunks_exact(ebpf::INSN_SIZE) {
+                    writeln!(output, "    {}", fill_str(slot))?;
+                }
+            } else {
+                for line in instruction.to_string().lines() {
+                    writeln!(output, "    {line}")?;
+                }
+            }
+        }
+        // Slots which are not part of an instruction, like an lddw missing its second slot
+        for slot in program[next_pc * ebpf::INSN_SIZE..].chunks_exact(ebpf::INSN_SIZE) {
+            writeln!(output, "    {}", fill_str(slot))?;
+        }
+        if labels.contains_key(&end_pc) {
+            writeln!(output, "lbb_{end_pc}:")?;
+        }
+        Ok(())
+    }
+
+    fn disassemble_listing<W: crate::utils::Write>(
+        &self,
+        output: &mut W,
+        with_encoding: bool,
+        options: DisassemblyOptions,
+    ) -> Result<(), InternalError> {
+        let mut last_basic_block = usize::MAX;
+        for (pc, insn) in self.instructions.iter().enumerate() {
+            self.disassemble_label(
+                output,
+                Some(insn) == self.instructions.first(),
+                insn.ptr,
+                &mut last_basic_block,
+            )?;
+            if options.block_costs && insn.ptr != self.super_root {
+                if let Some(cfg_node) = self.cfg_nodes.get(&insn.ptr) {
+                    writeln!(output, "    ; block cost: {}", cfg_node.instructions.len())?;
+                }
+            }
+            let instruction = self.disassemble_instruction_with_options(insn, pc, options);
+            // Flags all opcodes the verifier would reject, see Executable::unsupported_opcodes
+            let line = if instruction.mnemonic == "unknown" {
+                format!(
+                    "{instruction} ; unsupported in SBPF {}",
+                    self.executable.get_sbpf_version()
+                )
+            } else {
+                instruction.to_string()
+            };
+            if with_encoding {
+                let bytes = instruction
+                    .bytes
+                    .iter()
+                    .map(|byte| format!("{byte:02x}"))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                // Wide enough for the two slots of lddw
+                writeln!(
+                    output,
+                    "    {:5}: {:47}  {}",
+                    instruction.pc, bytes, line
+                )?;
+            } else {
+                writeln!(output, "    {}", line)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Use this method to print the trace log
+    pub fn disassemble_trace_log<W: utils::Write>(
+        &self,
+        output: &mut W,
+        trace_log: &[TraceLogEntry],
+    ) -> Result<(), InternalError> {
+        let mut pc_to_insn_index = vec![
+            0usize;
+            self.instructions
+                .last()
+                .map(|insn| insn.ptr + 2)
+                .unwrap_or(0)
+        ];
+        for (index, insn) in self.instructions.iter().enumerate() {
+            pc_to_insn_index[insn.ptr] = index;
+            pc_to_insn_index[insn.ptr + 1] = index;
+        }
+        for (index, entry) in trace_log.iter().enumerate() {
+            let pc = entry[11] as usize;
+            let insn = &self.instructions[pc_to_insn_index[pc]];
+            writeln!(
+                output,
+                "{:5?} {:016X?} {:5?}: {}",
+                index,
+                &entry[0..11],
+                pc,
+                self.disassemble_instruction(insn, pc),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Iterates over the cfg_nodes while providing the PC range of the function they belong to.
+    pub fn iter_cfg_by_function(
+        &self,
+    ) -> impl Iterator<Item = (core::ops::Range<usize>, usize, &CfgNode)> + '_ {
+        let mut function_iter = self.functions.keys().peekable();
+        let mut function_start = *function_iter.next().unwrap();
+        self.cfg_nodes
+            .iter()
+            .map(move |(cfg_node_start, cfg_node)| {
+                if Some(&cfg_node_start) == function_iter.peek() {
+                    function_start = *function_iter.next().unwrap();
+                }
+                let function_end = if let Some(next_function) = function_iter.peek() {
+                    **next_function
+                } else {
+                    self.instructions.last().unwrap().ptr + 1
+                };
+                (function_start..function_end, *cfg_node_start, cfg_node)
+            })
+    }
+
+    /// Returns the function the basic block at `cfg_node_start` calls at its end
+    ///
+    /// `Err` holds the pc of the call instruction if the target is unknown.
+    fn call_at_end_of(&self, cfg_node_start: usize) -> Option<Result<usize, usize>> {
+        if cfg_node_start == self.super_root {
+            return None;
+        }
+        let cfg_node = &self.cfg_nodes[&cfg_node_start];
+        let insn = &self.instructions[cfg_node.instructions.end.checked_sub(1)?];
+        match insn.opc {
+            ebpf::CALL_IMM => {
+                let sbpf_version = self.executable.get_sbpf_version();
+                let key = sbpf_version.calculate_call_imm_target_pc(insn.ptr, insn.imm);
+                let (_function_name, target_pc) =
+                    self.executable.get_function_registry().lookup_by_key(key)?;
+                Some(if self.cfg_nodes.contains_key(&target_pc) {
+                    Ok(target_pc)
+                } else {
+                    Err(insn.ptr)
+                })
+            }
+            ebpf::CALL_REG => Some(Err(insn.ptr)),
+            _ => None,
+        }
+    }
+
+    /// Computes an upper bound on the number of instructions executed from the entrypoint
+    ///
+    /// Only the code reachable from the entrypoint is considered. For loop-free, non-recursive
+    /// programs without indirect calls the bound is the longest path through the CFG, where
+    /// every call adds the bound of the called function. Syscalls count as one instruction,
+    /// regardless of what they consume from the instruction meter.
+    pub fn max_execution_bound(&self) -> Bound {
+        let calls = self
+            .cfg_nodes
+            .keys()
+            .filter_map(|cfg_node_start| {
+                self.call_at_end_of(*cfg_node_start)
+                    .map(|call| (*cfg_node_start, call))
+            })
+            .collect::<BTreeMap<usize, Result<usize, usize>>>();
+        let successors = |cfg_node_start: usize| {
+            self.cfg_nodes[&cfg_node_start]
+                .destinations
+                .iter()
+                .copied()
+                .chain(calls.get(&cfg_node_start).and_then(|call| call.ok()))
+        };
+        if !self.cfg_nodes.contains_key(&self.entrypoint) {
+            return Bound::Exact(0);
+        }
+
+        // Basic blocks reachable from the entrypoint, following jumps and calls
+        let mut reachable = BTreeSet::new();
+        let mut stack = vec![self.entrypoint];
+        while let Some(cfg_node_start) = stack.pop() {
+            if reachable.insert(cfg_node_start) {
+                stack.extend(successors(cfg_node_start));
+            }
+        }
+
+        // Cycles inside of functions are strongly connected components of the CFG
+        let mut loops = Vec::new();
+        let mut scc_sizes = BTreeMap::<usize, usize>::new();
+        for cfg_node_start in reachable.iter() {
+            *scc_sizes
+                .entry(self.cfg_nodes[cfg_node_start].topo_index.scc_id)
+                .or_default() += 1;
+        }
+        for cfg_node_start in reachable.iter() {
+            let cfg_node = &self.cfg_nodes[cfg_node_start];
+            let scc_id = cfg_node.topo_index.scc_id;
+            let is_cyclic =
+                scc_sizes[&scc_id] > 1 || cfg_node.destinations.contains(cfg_node_start);
+            if is_cyclic
+                && cfg_node
+                    .sources
+                    .iter()
+                    .any(|source| self.cfg_nodes[source].topo_index.scc_id != scc_id)
+            {
+                loops.push(*cfg_node_start);
+            }
+        }
+
+        // Cycles of the call graph, a function calls everything called from the basic blocks
+        // it can jump to
+        let mut call_graph = BTreeMap::<usize, BTreeSet<usize>>::new();
+        let mut functions = vec![self.entrypoint];
+        while let Some(function) = functions.pop() {
+            if call_graph.contains_key(&function) {
+                continue;
+            }
+            let mut callees = BTreeSet::new();
+            let mut visited = BTreeSet::new();
+            let mut stack = vec![function];
+            while let Some(cfg_node_start) = stack.pop() {
+                if visited.insert(cfg_node_start) {
+                    stack.extend(self.cfg_nodes[&cfg_node_start].destinations.iter().copied());
+                    if let Some(Ok(callee)) = calls.get(&cfg_node_start) {
+                        callees.insert(*callee);
+                    }
+                }
+            }
+            functions.extend(callees.iter().copied());
+            call_graph.insert(function, callees);
+        }
+        let mut recursion = BTreeSet::new();
+        let mut finished = BTreeSet::new();
+        let mut call_stack = vec![self.entrypoint];
+        let mut callee_iters = vec![call_graph[&self.entrypoint].iter()];
+        while let Some(callee_iter) = callee_iters.last_mut() {
+            match callee_iter.next() {
+                Some(callee) if finished.contains(callee) => {}
+                Some(callee) => {
+                    if let Some(position) = call_stack.iter().position(|function| function == callee) {
+                        // Rotate the cycle so that it starts with its lowest pc
+                        let mut cycle = call_stack[position..].to_vec();
+                        let lowest = (0..cycle.len()).min_by_key(|index| cycle[*index]).unwrap();
+                        cycle.rotate_left(lowest);
+                        recursion.insert(cycle);
+                    } else {
+                        call_stack.push(*callee);
+                        callee_iters.push(call_graph[callee].iter());
+                    }
+                }
+                None => {
+                    finished.insert(call_stack.pop().unwrap());
+                    callee_iters.pop();
+                }
+            }
+        }
+
+        let indirect_calls = reachable
+            .iter()
+            .filter_map(|cfg_node_start| calls.get(cfg_node_start)?.err())
+            .collect::<Vec<usize>>();
+        if !loops.is_empty() || !recursion.is_empty() || !indirect_calls.is_empty() {
+            return Bound::Unbounded {
+                loops,
+                recursion: recursion.into_iter().collect(),
+                indirect_calls,
+            };
+        }
+
+        // Longest path, the bound of every basic block is memoized across all call sites
+        let mut bounds = BTreeMap::<usize, u64>::new();
+        let mut stack = vec![(self.entrypoint, false)];
+        while let Some((cfg_node_start, successors_done)) = stack.pop() {
+            if bounds.contains_key(&cfg_node_start) {
+                continue;
+            }
+            if !successors_done {
+                stack.push((cfg_node_start, true));
+                stack.extend(
+                    successors(cfg_node_start)
+                        .filter(|successor| !bounds.contains_key(successor))
+                        .map(|successor| (successor, false)),
+                );
+                continue;
+            }
+            let cfg_node = &self.cfg_nodes[&cfg_node_start];
+            let callee_bound = match calls.get(&cfg_node_start) {
+                Some(Ok(callee)) => bounds[callee],
+                _ => 0,
+            };
+            let successor_bound = cfg_node
+                .destinations
+                .iter()
+                .map(|destination| bounds[destination])
+                .max()
+                .unwrap_or(0);
+            bounds.insert(
+                cfg_node_start,
+                (cfg_node.instructions.len() as u64)
+                    .saturating_add(callee_bound)
+                    .saturating_add(successor_bound),
+            );
+        }
+        Bound::Exact(bounds[&self.entrypoint])
+    }
+
+    /// Counts the instructions of each basic block, keyed by its start
+    ///
+    /// This is what the instruction meter charges for executing the basic block once: `lddw`
+    /// counts as one instruction even though it occupies two slots, and a syscall counts as one
+    /// instruction regardless of what it consumes itself.
+    pub fn block_costs(&self) -> BTreeMap<usize, u64> {
+        self.cfg_nodes
+            .iter()
+            .filter(|(cfg_node_start, _cfg_node)| **cfg_node_start != self.super_root)
+            .map(|(cfg_node_start, cfg_node)| (*cfg_node_start, cfg_node.instructions.len() as u64))
+            .collect()
+    }
+
+    /// Sums the [Analysis::block_costs] of each function, keyed by the function entry
+    ///
+    /// This is the cost of executing every instruction of the function once, excluding the
+    /// functions it calls. Loops and calls multiply it at runtime, see
+    /// [Analysis::max_execution_bound] for an upper bound of a whole execution.
+    pub fn function_costs(&self) -> BTreeMap<usize, u64> {
+        let mut function_costs = BTreeMap::<usize, u64>::new();
+        for (function_range, cfg_node_start, cfg_node) in self.iter_cfg_by_function() {
+            if cfg_node_start != self.super_root {
+                let function_cost = function_costs.entry(function_range.start).or_default();
+                *function_cost = function_cost.saturating_add(cfg_node.instructions.len() as u64);
+            }
+        }
+        function_costs
+    }
+
+    /// Computes which functions and syscalls each function calls, keyed by the function entry
+    ///
+    /// A `callx` is resolved to the function containing its target if the target is known from
+    /// the constants of its basic block, see [CfgNode::indirect_call_target].
+    pub fn call_graph(&self) -> BTreeMap<usize, FunctionCalls> {
+        let sbpf_version = self.executable.get_sbpf_version();
+        let containing_function = |pc: usize| {
+            self.functions
+                .range(..=pc)
+                .next_back()
+                .map(|(function_start, _)| *function_start)
+        };
+        let mut call_graph = self
+            .functions
+            .keys()
+            .map(|function_start| (*function_start, FunctionCalls::default()))
+            .collect::<BTreeMap<usize, FunctionCalls>>();
+        for (function_range, cfg_node_start, cfg_node) in self.iter_cfg_by_function() {
+            if cfg_node_start == self.super_root {
+                continue;
+            }
+            let function_calls = call_graph.entry(function_range.start).or_default();
+            for insn in self.instructions[cfg_node.instructions.clone()].iter() {
+                if self.is_syscall(insn) {
+                    function_calls.syscalls.insert(insn.imm as u32);
+                    continue;
+                }
+                let callee = match insn.opc {
+                    ebpf::CALL_IMM => {
+                        let key = sbpf_version.calculate_call_imm_target_pc(insn.ptr, insn.imm);
+                        self.executable
+                            .get_function_registry()
+                            .lookup_by_key(key)
+                            .map(|(_function_name, target_pc)| target_pc)
+                            .filter(|target_pc| self.cfg_nodes.contains_key(target_pc))
+                            .and_then(containing_function)
+                    }
+                    ebpf::CALL_REG => cfg_node.indirect_call_target.and_then(containing_function),
+                    _ => continue,
+                };
+                match callee {
+                    Some(callee) => {
+                        function_calls.functions.insert(callee);
+                    }
+                    None => function_calls.unknown_callees.push(insn.ptr),
+                }
+            }
+        }
+        call_graph
+    }
+
+    /// Whether any function can call itself, directly or through other functions
+    pub fn is_recursive(&self) -> bool {
+        let call_graph = self.call_graph();
+        let mut finished = BTreeSet::new();
+        for root in call_graph.keys() {
+            let mut call_stack = vec![*root];
+            let mut callee_iters = vec![call_graph[root].functions.iter()];
+            while let Some(callee_iter) = callee_iters.last_mut() {
+                match callee_iter.next() {
+                    Some(callee) if finished.contains(callee) => {}
+                    Some(callee) if call_stack.contains(callee) => return true,
+                    Some(callee) => {
+                        call_stack.push(*callee);
+                        callee_iters.push(call_graph[callee].functions.iter());
+                    }
+                    None => {
+                        finished.insert(call_stack.pop().unwrap());
+                        callee_iters.pop();
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    /// Computes the number of stack frames of the deepest chain of calls from the entrypoint
+    ///
+    /// The entrypoint counts as one frame, so the program can run with a
+    /// [Config::max_call_depth](crate::vm::Config::max_call_depth) of at least the returned
+    /// depth. Returns `None` if the program is recursive, see [Analysis::is_recursive].
+    pub fn max_call_depth(&self) -> Option<CallDepth> {
+        if self.is_recursive() {
+            return None;
+        }
+        let call_graph = self.call_graph();
+        let entrypoint = self
+            .functions
+            .range(..=self.entrypoint)
+            .next_back()
+            .map(|(function_start, _)| *function_start)?;
+        let mut depths = BTreeMap::<usize, (usize, bool)>::new();
+        let mut stack = vec![(entrypoint, false)];
+        while let Some((function, callees_done)) = stack.pop() {
+            if depths.contains_key(&function) {
+                continue;
+            }
+            let function_calls = &call_graph[&function];
+            if !callees_done {
+                stack.push((function, true));
+                stack.extend(
+                    function_calls
+                        .functions
+                        .iter()
+                        .filter(|callee| !depths.contains_key(callee))
+                        .map(|callee| (*callee, false)),
+                );
+                continue;
+            }
+            let (callee_depth, has_unknown_callees) = function_calls.functions.iter().fold(
+                (0, !function_calls.unknown_callees.is_empty()),
+                |(depth, has_unknown_callees), callee| {
+                    let (callee_depth, callee_has_unknown_callees) = depths[callee];
+                    (
+                        depth.max(callee_depth),
+                        has_unknown_callees || callee_has_unknown_callees,
+                    )
+                },
+            );
+            depths.insert(
+                function,
+                (callee_depth.saturating_add(1), has_unknown_callees),
+            );
+        }
+        Some(match depths[&entrypoint] {
+            (depth, false) => CallDepth::Exact(depth),
+            (depth, true) => CallDepth::AtLeast(depth),
+        })
+    }
+
+    /// Whether `insn` calls a syscall rather than a function of the executable
+    fn is_syscall(&self, insn: &ebpf::Insn) -> bool {
+        let sbpf_version = self.executable.get_sbpf_version();
+        match insn.opc {
+            ebpf::SYSCALL => sbpf_version.static_syscalls(),
+            ebpf::CALL_IMM if !sbpf_version.static_syscalls() => {
+                let key = sbpf_version.calculate_call_imm_target_pc(insn.ptr, insn.imm);
+                self.executable
+                    .get_function_registry()
+                    .lookup_by_key(key)
+                    .is_none()
+            }
+            _ => false,
+        }
+    }
+
+    /// Applies the effect of a single instruction to the values derived from r10
+    ///
+    /// Records a leak for every syscall argument and every store outside of the stack which
+    /// is derived from r10.
+    fn propagate_stack_pointer_taint(
+        &self,
+        insn: &ebpf::Insn,
+        taint: &mut StackPointerTaint,
+        leaks: &mut BTreeSet<(usize, usize)>,
+    ) {
+        let sbpf_version = self.executable.get_sbpf_version();
+        let moved_memory_classes = sbpf_version.move_memory_instruction_classes();
+        let value = |taint: &StackPointerTaint, reg: u8| {
+            if reg as usize == ebpf::FRAME_PTR_REG {
+                Some(insn.ptr)
+            } else {
+                taint.registers.get(reg as usize).copied().flatten()
+            }
+        };
+        // r10 itself is not tracked, it is the source of all taint
+        let set_dst = |taint: &mut StackPointerTaint, source: Option<usize>| {
+            if let Some(register) = taint.registers.get_mut(insn.dst as usize) {
+                *register = source;
+            }
+        };
+        if self.is_syscall(insn) {
+            for reg in 1..ebpf::FIRST_SCRATCH_REG {
+                if let Some(source) = taint.registers[reg] {
+                    leaks.insert((source, insn.ptr));
+                }
+            }
+            taint.registers[0] = None;
+            return;
+        }
+        match insn.opc {
+            ebpf::LD_B_REG | ebpf::LD_H_REG | ebpf::LD_W_REG | ebpf::LD_DW_REG
+                if !moved_memory_classes =>
+            {
+                let source = value(taint, insn.src).and(taint.stack);
+                set_dst(taint, source);
+            }
+            ebpf::LD_1B_REG | ebpf::LD_2B_REG | ebpf::LD_4B_REG | ebpf::LD_8B_REG
+                if moved_memory_classes =>
+            {
+                let source = value(taint, insn.src).and(taint.stack);
+                set_dst(taint, source);
+            }
+            ebpf::ST_B_IMM | ebpf::ST_H_IMM | ebpf::ST_W_IMM | ebpf::ST_DW_IMM
+                if !moved_memory_classes => {}
+            ebpf::ST_1B_IMM | ebpf::ST_2B_IMM | ebpf::ST_4B_IMM | ebpf::ST_8B_IMM
+                if moved_memory_classes => {}
+            ebpf::ST_B_REG | ebpf::ST_H_REG | ebpf::ST_W_REG | ebpf::ST_DW_REG
+                if !moved_memory_classes =>
+            {
+                if let Some(source) = value(taint, insn.src) {
+                    if value(taint, insn.dst).is_some() {
+                        taint.stack = join_taint_source(taint.stack, Some(source));
+                    } else {
+                        leaks.insert((source, insn.ptr));
+                    }
+                }
+            }
+            ebpf::ST_1B_REG | ebpf::ST_2B_REG | ebpf::ST_4B_REG | ebpf::ST_8B_REG
+                if moved_memory_classes =>
+            {
+                if let Some(source) = value(taint, insn.src) {
+                    if value(taint, insn.dst).is_some() {
+                        taint.stack = join_taint_source(taint.stack, Some(source));
+                    } else {
+                        leaks.insert((source, insn.ptr));
+                    }
+                }
+            }
+            ebpf::LD_DW_IMM | ebpf::MOV32_IMM | ebpf::MOV64_IMM => set_dst(taint, None),
+            ebpf::MOV32_REG | ebpf::MOV64_REG => {
+                let source = value(taint, insn.src);
+                set_dst(taint, source);
+            }
+            _ if matches!(
+                insn.opc & ebpf::BPF_CLS_MASK,
+                ebpf::BPF_ALU32_LOAD | ebpf::BPF_PQR | ebpf::BPF_ALU64_STORE
+            ) =>
+            {
+                let mut source = value(taint, insn.dst);
+                if insn.opc & ebpf::BPF_X != 0 && insn.opc & ebpf::BPF_ALU_OP_MASK != ebpf::BPF_END
+                {
+                    source = join_taint_source(source, value(taint, insn.src));
+                }
+                set_dst(taint, source);
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the registers `insn` reads and writes, as bit masks
+    ///
+    /// Calls read the argument registers r1 to r5 and write the return value r0, `exit` and
+    /// `return` read r0.
+    fn register_uses_and_defs(&self, insn: &ebpf::Insn) -> (u16, u16) {
+        const ARGUMENTS: u16 = 0b11_1110;
+        let reg = |reg: u8| 1u16.checked_shl(reg as u32).unwrap_or(0);
+        let decoded = decode(insn, self.executable.get_sbpf_version());
+        let is_mov = matches!(decoded.mnemonic, "mov32" | "mov64");
+        match decoded.operands {
+            Operands::None => (reg(0), 0),
+            Operands::Register { dst } => (reg(dst), reg(dst)),
+            Operands::RegisterImmediate { dst, .. } if is_mov => (0, reg(dst)),
+            Operands::RegisterImmediate { dst, .. } => (reg(dst), reg(dst)),
+            Operands::RegisterRegister { dst, src } if is_mov => (reg(src), reg(dst)),
+            Operands::RegisterRegister { dst, src } => (reg(dst) | reg(src), reg(dst)),
+            Operands::Immediate64 { dst, .. } => (0, reg(dst)),
+            Operands::Load { dst, src, .. } => (reg(src), reg(dst)),
+            Operands::StoreImmediate { dst, .. } => (reg(dst), 0),
+            Operands::StoreRegister { dst, src, .. } => (reg(dst) | reg(src), 0),
+            Operands::Jump { .. } => (0, 0),
+            Operands::JumpImmediate { dst, .. } => (reg(dst), 0),
+            Operands::JumpRegister { dst, src, .. } => (reg(dst) | reg(src), 0),
+            Operands::Call { .. } | Operands::Syscall { .. } => (ARGUMENTS, reg(0)),
+            Operands::CallRegister { reg: target } => (ARGUMENTS | reg(target), reg(0)),
+            Operands::InvalidByteSwap { .. } | Operands::Unknown { .. } => (0, 0),
+        }
+    }
+
+    /// Computes the registers which are live after each instruction, keyed by pc
+    ///
+    /// A register is live if it can be read before being written again. The flow continues
+    /// after calls at the next instruction, callees are analyzed as separate functions.
+    pub fn liveness(&self) -> BTreeMap<usize, BTreeSet<u8>> {
+        // Registers live at the start of each basic block
+        let mut live_in = BTreeMap::<usize, u16>::new();
+        let live_out = |live_in: &BTreeMap<usize, u16>, cfg_node: &CfgNode| {
+            cfg_node.destinations.iter().fold(0, |live, destination| {
+                live | live_in.get(destination).copied().unwrap_or(0)
+            })
+        };
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (cfg_node_start, cfg_node) in self.cfg_nodes.iter().rev() {
+                if *cfg_node_start == self.super_root {
+                    continue;
+                }
+                let mut live = live_out(&live_in, cfg_node);
+                for insn in self.instructions[cfg_node.instructions.clone()]
+                    .iter()
+                    .rev()
+                {
+                    let (uses, defs) = self.register_uses_and_defs(insn);
+                    live = (live & !defs) | uses;
+                }
+                if live_in.insert(*cfg_node_start, live) != Some(live) {
+                    changed = true;
+                }
+            }
+        }
+        let mut liveness = BTreeMap::new();
+        for (cfg_node_start, cfg_node) in self.cfg_nodes.iter() {
+            if *cfg_node_start == self.super_root {
+                continue;
+            }
+            let mut live = live_out(&live_in, cfg_node);
+            for insn in self.instructions[cfg_node.instructions.clone()]
+                .iter()
+                .rev()
+            {
+                liveness.insert(
+                    insn.ptr,
+                    (0..=ebpf::FRAME_PTR_REG as u8)
+                        .filter(|reg| live & (1 << reg) != 0)
+                        .collect(),
+                );
+                let (uses, defs) = self.register_uses_and_defs(insn);
+                live = (live & !defs) | uses;
+            }
+        }
+        liveness
     }
 
-    /// Generates assembler code for a single instruction
-    pub fn disassemble_instruction(&self, insn: &ebpf::Insn, pc: usize) -> String {
-        disassemble_instruction(
-            insn,
-            pc,
-            &self.cfg_nodes,
-            self.executable.get_function_registry(),
-            self.executable.get_loader(),
-            self.executable.get_sbpf_version(),
-        )
+    /// Finds the instructions writing a register which is not read before being written again
+    ///
+    /// Calls are not reported, even if their return value is unused. The frame pointer is never
+    /// written and r0 is read by `exit` and `return`, so neither is reported at the end of a
+    /// function. Returns the pcs of the instructions, see [Analysis::liveness].
+    pub fn dead_stores(&self) -> Vec<usize> {
+        let liveness = self.liveness();
+        self.instructions
+            .iter()
+            .filter(|insn| {
+                let (_uses, defs) = self.register_uses_and_defs(insn);
+                let is_call = matches!(
+                    decode(insn, self.executable.get_sbpf_version()).operands,
+                    Operands::Call { .. }
+                        | Operands::CallRegister { .. }
+                        | Operands::Syscall { .. }
+                );
+                let live = match liveness.get(&insn.ptr) {
+                    Some(live) => live,
+                    None => return false,
+                };
+                defs != 0
+                    && !is_call
+                    && (0..ebpf::FRAME_PTR_REG as u8)
+                        .all(|reg| defs & (1 << reg) == 0 || !live.contains(&reg))
+            })
+            .map(|insn| insn.ptr)
+            .collect()
     }
 
-    /// Generates assembler code for the analyzed executable
-    pub fn disassemble<W: crate::utils::Write>(&self, output: &mut W) -> Result<(), InternalError> {
-        let mut last_basic_block = usize::MAX;
-        for (pc, insn) in self.instructions.iter().enumerate() {
-            self.disassemble_label(
-                output,
-                Some(insn) == self.instructions.first(),
-                insn.ptr,
-                &mut last_basic_block,
-            )?;
-            writeln!(output, "    {}", self.disassemble_instruction(insn, pc))?;
+    /// Finds the values derived from the frame pointer (r10) which leave the program
+    ///
+    /// Returns pairs of the pc of the instruction reading r10 and the pc of the instruction
+    /// through which the derived value leaves the program. These are stores through pointers
+    /// which are not derived from r10 (assumed to target the input region), syscalls taking
+    /// it in one of their argument registers and exits of the entrypoint function returning it
+    /// in r0. Loads and stores addressed relative to r10 do not taint the accessed value.
+    ///
+    /// The analysis is conservative: The stack is tracked as a whole, calls are not context
+    /// sensitive and `callx` may call any function. Only explicit data flow is considered,
+    /// branching on a derived value does not taint anything.
+    pub fn stack_pointer_leaks(&self) -> Vec<(usize, usize)> {
+        if !self.cfg_nodes.contains_key(&self.entrypoint) {
+            return Vec::new();
         }
-        Ok(())
-    }
-
-    /// Use this method to print the trace log
-    pub fn disassemble_trace_log<W: utils::Write>(
-        &self,
-        output: &mut W,
-        trace_log: &[TraceLogEntry],
-    ) -> Result<(), InternalError> {
-        let mut pc_to_insn_index = vec![
-            0usize;
-            self.instructions
-                .last()
-                .map(|insn| insn.ptr + 2)
+        let sbpf_version = self.executable.get_sbpf_version();
+        let exit_opcode = if sbpf_version.static_syscalls() {
+            ebpf::RETURN
+        } else {
+            ebpf::EXIT
+        };
+        let function_of = |cfg_node_start: usize| {
+            self.functions
+                .range(..=cfg_node_start)
+                .next_back()
+                .map(|(function_start, _)| *function_start)
                 .unwrap_or(0)
-        ];
-        for (index, insn) in self.instructions.iter().enumerate() {
-            pc_to_insn_index[insn.ptr] = index;
-            pc_to_insn_index[insn.ptr + 1] = index;
+        };
+        let ends_with_exit = |cfg_node: &CfgNode| {
+            cfg_node
+                .instructions
+                .end
+                .checked_sub(1)
+                .map(|index| self.instructions[index].opc == exit_opcode)
+                .unwrap_or(false)
+        };
+
+        // Fixpoint of the taint at the start of every basic block reachable from the entrypoint
+        let mut entry_states = BTreeMap::new();
+        let mut exit_states = BTreeMap::<usize, StackPointerTaint>::new();
+        let mut call_states = BTreeMap::<usize, StackPointerTaint>::new();
+        let mut call_sites = BTreeMap::<usize, BTreeSet<usize>>::new();
+        let mut worklist = BTreeSet::new();
+        let mut ignored_leaks = BTreeSet::new();
+        merge_taint(
+            &mut entry_states,
+            &mut worklist,
+            self.entrypoint,
+            &StackPointerTaint::default(),
+        );
+        while let Some(cfg_node_start) = worklist.iter().next().copied() {
+            worklist.remove(&cfg_node_start);
+            let cfg_node = &self.cfg_nodes[&cfg_node_start];
+            let mut taint = entry_states[&cfg_node_start].clone();
+            for insn in self.instructions[cfg_node.instructions.clone()].iter() {
+                self.propagate_stack_pointer_taint(insn, &mut taint, &mut ignored_leaks);
+            }
+            if let Some(call) = self.call_at_end_of(cfg_node_start) {
+                let callees = match call {
+                    Ok(callee) => vec![callee],
+                    Err(_pc) => self.functions.keys().copied().collect(),
+                };
+                for callee in callees {
+                    call_sites.entry(callee).or_default().insert(cfg_node_start);
+                    merge_taint(&mut entry_states, &mut worklist, callee, &taint);
+                    if let Some(exit_state) = exit_states.get(&callee) {
+                        let returned = exit_state.returned_to(&taint);
+                        for destination in cfg_node.destinations.iter() {
+                            merge_taint(&mut entry_states, &mut worklist, *destination, &returned);
+                        }
+                    }
+                }
+                call_states.insert(cfg_node_start, taint);
+            } else if ends_with_exit(cfg_node) {
+                let function = function_of(cfg_node_start);
+                let changed = match exit_states.get_mut(&function) {
+                    Some(exit_state) => exit_state.join(&taint),
+                    None => {
+                        exit_states.insert(function, taint);
+                        true
+                    }
+                };
+                if changed {
+                    let exit_state = &exit_states[&function];
+                    for caller in call_sites.get(&function).into_iter().flatten() {
+                        let returned = exit_state.returned_to(&call_states[caller]);
+                        for destination in self.cfg_nodes[caller].destinations.iter() {
+                            merge_taint(&mut entry_states, &mut worklist, *destination, &returned);
+                        }
+                    }
+                }
+            } else {
+                for destination in cfg_node.destinations.iter() {
+                    merge_taint(&mut entry_states, &mut worklist, *destination, &taint);
+                }
+            }
         }
-        for (index, entry) in trace_log.iter().enumerate() {
-            let pc = entry[11] as usize;
-            let insn = &self.instructions[pc_to_insn_index[pc]];
-            writeln!(
-                output,
-                "{:5?} {:016X?} {:5?}: {}",
-                index,
-                &entry[0..11],
-                pc,
-                self.disassemble_instruction(insn, pc),
-            )?;
+
+        // Collect the leaks from the final states only
+        let entry_function = function_of(self.entrypoint);
+        let mut leaks = BTreeSet::new();
+        for (cfg_node_start, taint) in entry_states.iter() {
+            let cfg_node = &self.cfg_nodes[cfg_node_start];
+            let mut taint = taint.clone();
+            for insn in self.instructions[cfg_node.instructions.clone()].iter() {
+                self.propagate_stack_pointer_taint(insn, &mut taint, &mut leaks);
+            }
+            if ends_with_exit(cfg_node) && function_of(*cfg_node_start) == entry_function {
+                if let Some(source) = taint.registers[0] {
+                    let exit_insn = &self.instructions[cfg_node.instructions.end - 1];
+                    leaks.insert((source, exit_insn.ptr));
+                }
+            }
         }
-        Ok(())
+        leaks.into_iter().collect()
     }
 
-    /// Iterates over the cfg_nodes while providing the PC range of the function they belong to.
-    pub fn iter_cfg_by_function(
-        &self,
-    ) -> impl Iterator<Item = (core::ops::Range<usize>, usize, &CfgNode)> + '_ {
-        let mut function_iter = self.functions.keys().peekable();
-        let mut function_start = *function_iter.next().unwrap();
-        self.cfg_nodes
+    /// Returns the basic blocks reachable from the entrypoint, a registered function or a call
+    ///
+    /// Tarjan's algorithm makes every basic block without predecessors a function, even if
+    /// nothing calls it. `callx` only counts if its target is known statically.
+    fn statically_reachable_cfg_nodes(&self) -> BTreeSet<usize> {
+        let mut worklist = self
+            .executable
+            .get_function_registry()
             .iter()
-            .map(move |(cfg_node_start, cfg_node)| {
-                if Some(&cfg_node_start) == function_iter.peek() {
-                    function_start = *function_iter.next().unwrap();
-                }
-                let function_end = if let Some(next_function) = function_iter.peek() {
-                    **next_function
-                } else {
-                    self.instructions.last().unwrap().ptr + 1
-                };
-                (function_start..function_end, *cfg_node_start, cfg_node)
-            })
+            .map(|(_key, (_function_name, pc))| pc)
+            .chain(core::iter::once(self.entrypoint))
+            .filter(|pc| self.cfg_nodes.contains_key(pc))
+            .collect::<Vec<usize>>();
+        let mut reachable = BTreeSet::new();
+        while let Some(cfg_node_start) = worklist.pop() {
+            if !reachable.insert(cfg_node_start) {
+                continue;
+            }
+            let cfg_node = &self.cfg_nodes[&cfg_node_start];
+            worklist.extend(cfg_node.destinations.iter().copied());
+            if let Some(Ok(target_pc)) = self.call_at_end_of(cfg_node_start) {
+                worklist.push(target_pc);
+            }
+            worklist.extend(
+                cfg_node
+                    .indirect_call_target
+                    .filter(|target_pc| self.cfg_nodes.contains_key(target_pc)),
+            );
+        }
+        reachable
+    }
+
+    /// Returns whether the edge from `cfg_node` to `destination` is taken by a jump
+    fn is_taken_edge(&self, cfg_node: &CfgNode, destination: usize) -> bool {
+        let insn = match cfg_node
+            .instructions
+            .end
+            .checked_sub(1)
+            .and_then(|index| self.instructions.get(index))
+        {
+            Some(insn) => insn,
+            None => return false,
+        };
+        match decode(insn, self.executable.get_sbpf_version()).operands {
+            Operands::Jump { target_pc }
+            | Operands::JumpImmediate { target_pc, .. }
+            | Operands::JumpRegister { target_pc, .. } => target_pc == destination as isize,
+            _ => false,
+        }
     }
 
     /// Generates a graphviz DOT of the analyzed executable
+    ///
+    /// Every function is a cluster of its basic blocks, which list their instructions. Edges are
+    /// labelled `fallthrough`, `taken` or `call`, or with the number of times they were taken
+    /// if a [DynamicAnalysis] is given. Basic blocks which are not reachable from the entrypoint,
+    /// a registered function or a resolved call are filled in pink.
     pub fn visualize_graphically<W: crate::utils::Write>(
         &self,
         output: &mut W,
@@ -542,6 +1773,8 @@ impl<'a> Analysis<'a> {
                 .replace('<', "&lt;")
                 .replace('>', "&gt;")
                 .replace('\"', "&quot;")
+                .replace('{', "&#123;")
+                .replace('}', "&#125;")
         }
         fn emit_cfg_node<W: crate::utils::Write>(
             output: &mut W,
@@ -549,21 +1782,28 @@ impl<'a> Analysis<'a> {
             analysis: &Analysis,
             function_range: core::ops::Range<usize>,
             alias_nodes: &mut HashSet<usize>,
+            reachable: &BTreeSet<usize>,
             cfg_node_start: usize,
         ) -> Result<(), InternalError> {
             let cfg_node = &analysis.cfg_nodes[&cfg_node_start];
-            writeln!(output, "    lbb_{} [label=<<table border=\"0\" cellborder=\"0\" cellpadding=\"3\">{}</table>>];",
+            let fillcolor = if reachable.contains(&cfg_node_start) {
+                ""
+            } else {
+                "fillcolor=lightpink; "
+            };
+            writeln!(output, "    lbb_{} [{}label=<<table border=\"0\" cellborder=\"0\" cellpadding=\"3\">{}</table>>];",
                 cfg_node_start,
+                fillcolor,
                 analysis.instructions[cfg_node.instructions.clone()].iter()
-                .enumerate().map(|(pc, insn)| {
+                .map(|insn| {
                     let desc = analysis.disassemble_instruction(
-                        insn, pc
+                        insn, insn.ptr
                     );
                     if let Some(split_index) = desc.find(' ') {
                         let mut rest = desc[split_index+1..].to_string();
-                        if rest.len() > MAX_CELL_CONTENT_LENGTH + 1 {
-                            rest.truncate(MAX_CELL_CONTENT_LENGTH);
-                            rest = format!("{rest}…");
+                        if rest.chars().count() > MAX_CELL_CONTENT_LENGTH + 1 {
+                            rest = rest.chars().take(MAX_CELL_CONTENT_LENGTH).collect();
+                            rest.push('…');
                         }
                         format!("<tr><td align=\"left\">{}</td><td align=\"left\">{}</td></tr>", html_escape(&desc[..split_index]), html_escape(&rest))
                     } else {
@@ -581,16 +1821,6 @@ impl<'a> Analysis<'a> {
                     }
                 }
             }
-            for child in &cfg_node.dominated_children {
-                emit_cfg_node(
-                    output,
-                    dynamic_analysis,
-                    analysis,
-                    function_range.clone(),
-                    alias_nodes,
-                    *child,
-                )?;
-            }
             Ok(())
         }
         writeln!(
@@ -613,6 +1843,7 @@ impl<'a> Analysis<'a> {
   ];"
         )?;
         const MAX_CELL_CONTENT_LENGTH: usize = 15;
+        let reachable = self.statically_reachable_cfg_nodes();
         let mut function_iter = self.functions.keys().peekable();
         while let Some(function_start) = function_iter.next() {
             let function_end = if let Some(next_function) = function_iter.peek() {
@@ -628,14 +1859,18 @@ impl<'a> Analysis<'a> {
                 html_escape(&self.cfg_nodes[function_start].label)
             )?;
             writeln!(output, "    tooltip=lbb_{};", *function_start)?;
-            emit_cfg_node(
-                output,
-                dynamic_analysis,
-                self,
-                *function_start..function_end,
-                &mut alias_nodes,
-                *function_start,
-            )?;
+            for cfg_node_start in self.cfg_nodes.range(*function_start..function_end).map(|(pc, _)| *pc)
+            {
+                emit_cfg_node(
+                    output,
+                    dynamic_analysis,
+                    self,
+                    *function_start..function_end,
+                    &mut alias_nodes,
+                    &reachable,
+                    cfg_node_start,
+                )?;
+            }
             for alias_node in alias_nodes.iter() {
                 writeln!(
                     output,
@@ -650,7 +1885,13 @@ impl<'a> Analysis<'a> {
             writeln!(output, "  }}")?;
         }
         for (function_range, cfg_node_start, cfg_node) in self.iter_cfg_by_function() {
-            if cfg_node_start != cfg_node.dominator_parent {
+            // The super root is virtual and not drawn
+            if cfg_node_start == self.super_root {
+                continue;
+            }
+            if cfg_node_start != cfg_node.dominator_parent
+                && cfg_node.dominator_parent != self.super_root
+            {
                 writeln!(
                     output,
                     "  lbb_{} -> lbb_{} [style=dotted; arrowhead=none];",
@@ -675,17 +1916,18 @@ impl<'a> Analysis<'a> {
                 }
             }
             let counter_sum: usize = edges.values().sum();
-            if counter_sum == 0 && !edges.is_empty() {
-                writeln!(
-                    output,
-                    "  lbb_{} -> {{{}}};",
-                    cfg_node_start,
-                    edges
-                        .keys()
-                        .map(|destination| format!("lbb_{}", *destination))
-                        .collect::<Vec<String>>()
-                        .join(" ")
-                )?;
+            if counter_sum == 0 {
+                for destination in edges.keys() {
+                    let kind = if self.is_taken_edge(cfg_node, *destination) {
+                        "taken"
+                    } else {
+                        "fallthrough"
+                    };
+                    writeln!(
+                        output,
+                        "  lbb_{cfg_node_start} -> lbb_{destination} [label=\"{kind}\"];"
+                    )?;
+                }
             } else if let Some(dynamic_analysis) = dynamic_analysis {
                 for (destination, counter) in edges {
                     write!(output, "  lbb_{cfg_node_start} -> ")?;
@@ -708,6 +1950,18 @@ impl<'a> Analysis<'a> {
                     )?;
                 }
             }
+            let call_target = match self.call_at_end_of(cfg_node_start) {
+                Some(Ok(target_pc)) => Some(target_pc),
+                _ => cfg_node
+                    .indirect_call_target
+                    .filter(|target_pc| self.cfg_nodes.contains_key(target_pc)),
+            };
+            if let Some(target_pc) = call_target {
+                writeln!(
+                    output,
+                    "  lbb_{cfg_node_start} -> lbb_{target_pc} [label=\"call\"; style=dashed];"
+                )?;
+            }
         }
         writeln!(output, "}}")?;
         Ok(())
@@ -917,7 +2171,144 @@ impl<'a> Analysis<'a> {
             let p = cfg_node.dominator_parent;
             let dominator_cfg_node = self.cfg_nodes.get_mut(&p).unwrap();
             dominator_cfg_node.dominated_children.push(*b);
+            self.dominators.insert(*b, p);
+        }
+    }
+
+    /// Returns the immediate dominator of every basic block
+    ///
+    /// The entries of functions, and basic blocks reachable from more than one of them, are
+    /// dominated by the [Analysis::super_root], which has no entry itself.
+    pub fn dominators(&self) -> &BTreeMap<usize, usize> {
+        &self.dominators
+    }
+
+    /// Returns whether every path from the super root to `cfg_node_start` passes `dominator`
+    fn dominates(&self, dominator: usize, mut cfg_node_start: usize) -> bool {
+        loop {
+            if cfg_node_start == dominator {
+                return true;
+            }
+            match self.dominators.get(&cfg_node_start) {
+                Some(parent) => cfg_node_start = *parent,
+                None => return false,
+            }
+        }
+    }
+
+    /// Returns whether the edge from `source` to `destination` closes a natural loop
+    fn is_back_edge(&self, source: usize, destination: usize) -> bool {
+        self.dominates(destination, source)
+    }
+
+    /// Finds the natural loops, one for each header, in the order of their headers
+    ///
+    /// A back edge is an edge whose destination, the header, dominates its source. The body
+    /// consists of the basic blocks which can reach the source without passing the header.
+    /// Loops sharing a header are merged. Cycles without a dominating header are not natural
+    /// loops, see [Analysis::irreducible_cycles].
+    pub fn loops(&self) -> Vec<Loop> {
+        let mut loops = BTreeMap::<usize, BTreeSet<usize>>::new();
+        for (source, cfg_node) in self.cfg_nodes.iter() {
+            for header in cfg_node.destinations.iter() {
+                if !self.is_back_edge(*source, *header) {
+                    continue;
+                }
+                let body = loops
+                    .entry(*header)
+                    .or_insert_with(|| BTreeSet::from([*header]));
+                let mut stack = vec![*source];
+                while let Some(cfg_node_start) = stack.pop() {
+                    if body.insert(cfg_node_start) {
+                        stack.extend(self.cfg_nodes[&cfg_node_start].sources.iter().copied());
+                    }
+                }
+            }
+        }
+        loops
+            .into_iter()
+            .map(|(header, body)| Loop { header, body })
+            .collect()
+    }
+
+    /// Finds the cycles of the CFG which are not natural loops
+    ///
+    /// These are the strongly connected components which remain after removing the back edges
+    /// of the natural loops. The result is empty if the control flow is reducible.
+    pub fn irreducible_cycles(&self) -> Vec<IrreducibleCycle> {
+        // Iterative version of Tarjan's algorithm on the CFG without back edges
+        let successors = |cfg_node_start: usize| {
+            self.cfg_nodes[&cfg_node_start]
+                .destinations
+                .iter()
+                .copied()
+                .filter(move |destination| !self.is_back_edge(cfg_node_start, *destination))
+        };
+        let mut index = BTreeMap::<usize, (usize, usize)>::new();
+        let mut on_stack = BTreeSet::new();
+        let mut stack = Vec::new();
+        let mut components = Vec::new();
+        for root in self.cfg_nodes.keys() {
+            if index.contains_key(root) {
+                continue;
+            }
+            let mut dfs = vec![(*root, successors(*root).collect::<Vec<_>>())];
+            index.insert(*root, (index.len(), index.len()));
+            stack.push(*root);
+            on_stack.insert(*root);
+            while let Some((v, pending)) = dfs.last_mut() {
+                let v = *v;
+                if let Some(w) = pending.pop() {
+                    if !index.contains_key(&w) {
+                        index.insert(w, (index.len(), index.len()));
+                        stack.push(w);
+                        on_stack.insert(w);
+                        dfs.push((w, successors(w).collect()));
+                    } else if on_stack.contains(&w) {
+                        let w_index = index[&w].0;
+                        let v_entry = index.get_mut(&v).unwrap();
+                        v_entry.1 = v_entry.1.min(w_index);
+                    }
+                    continue;
+                }
+                dfs.pop();
+                let (v_index, v_lowlink) = index[&v];
+                if let Some((parent, _)) = dfs.last() {
+                    let parent_entry = index.get_mut(parent).unwrap();
+                    parent_entry.1 = parent_entry.1.min(v_lowlink);
+                }
+                if v_index == v_lowlink {
+                    let mut component = BTreeSet::new();
+                    while let Some(w) = stack.pop() {
+                        on_stack.remove(&w);
+                        component.insert(w);
+                        if w == v {
+                            break;
+                        }
+                    }
+                    components.push(component);
+                }
+            }
         }
+        let mut cycles = components
+            .into_iter()
+            .filter(|body| body.len() > 1)
+            .map(|body| IrreducibleCycle {
+                entries: body
+                    .iter()
+                    .copied()
+                    .filter(|cfg_node_start| {
+                        self.cfg_nodes[cfg_node_start]
+                            .sources
+                            .iter()
+                            .any(|source| !body.contains(source))
+                    })
+                    .collect(),
+                body,
+            })
+            .collect::<Vec<_>>();
+        cycles.sort_by_key(|cycle| cycle.body.first().copied());
+        cycles
     }
 
     /// Connect the dependencies between the instructions inside of the basic blocks
@@ -1187,3 +2578,206 @@ impl<'a> Analysis<'a> {
         }
     }
 }
+
+/// Counts of a call stack in a [Profiler]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StackCounts {
+    /// How often the innermost function was entered with this call stack
+    pub entries: u64,
+    /// Number of instructions retired while this call stack was active
+    pub instructions: u64,
+}
+
+/// Call stacks of a recorded trace, in the folded format of flame graph tools
+///
+/// Frames are pushed by the traced function calls and popped by the traced returns, so the
+/// profile is the same for the interpreter and the JIT.
+pub struct Profiler<'a> {
+    analysis: &'a Analysis<'a>,
+    /// Counts by call stack, given as the function pcs from the outermost function inwards
+    pub stacks: BTreeMap<Vec<usize>, StackCounts>,
+}
+
+impl<'a> Profiler<'a> {
+    /// Accumulates a trace
+    pub fn new(trace_log: &[TraceLogEntry], analysis: &'a Analysis<'a>) -> Self {
+        let sbpf_version = analysis.executable.get_sbpf_version();
+        let return_opcode = if sbpf_version.static_syscalls() {
+            ebpf::RETURN
+        } else {
+            ebpf::EXIT
+        };
+        let function_of = |pc: usize| {
+            analysis
+                .functions
+                .range(..=pc)
+                .next_back()
+                .map(|(function_start, _)| *function_start)
+                .unwrap_or(0)
+        };
+        let mut pc_to_insn_index = vec![
+            usize::MAX;
+            analysis
+                .instructions
+                .last()
+                .map(|insn| insn.ptr + 2)
+                .unwrap_or(0)
+        ];
+        for (index, insn) in analysis.instructions.iter().enumerate() {
+            pc_to_insn_index[insn.ptr] = index;
+            pc_to_insn_index[insn.ptr + 1] = index;
+        }
+        let mut stacks = BTreeMap::<Vec<usize>, StackCounts>::new();
+        let mut stack = Vec::new();
+        for (index, entry) in trace_log.iter().enumerate() {
+            let pc = entry[11] as usize;
+            if stack.is_empty() {
+                stack.push(function_of(pc));
+                stacks.entry(stack.clone()).or_default().entries += 1;
+            }
+            if let Some(counts) = stacks.get_mut(stack.as_slice()) {
+                counts.instructions += 1;
+            }
+            let insn = match pc_to_insn_index.get(pc) {
+                Some(insn_index) if *insn_index != usize::MAX => {
+                    &analysis.instructions[*insn_index]
+                }
+                _ => continue,
+            };
+            let is_call = match insn.opc {
+                ebpf::CALL_REG => true,
+                ebpf::CALL_IMM => !analysis.is_syscall(insn),
+                _ => false,
+            };
+            if is_call {
+                // The next traced instruction is the first one of the callee
+                if let Some(next_entry) = trace_log.get(index + 1) {
+                    stack.push(next_entry[11] as usize);
+                    stacks.entry(stack.clone()).or_default().entries += 1;
+                }
+            } else if insn.opc == return_opcode && stack.len() > 1 {
+                stack.pop();
+            }
+        }
+        Self { analysis, stacks }
+    }
+
+    /// Writes one line per call stack, weighted by how often it was entered
+    ///
+    /// The frames are separated by `;` and followed by the count, e.g. `main;helper 3`.
+    pub fn write_folded<W: utils::Write>(&self, output: &mut W) -> Result<(), InternalError> {
+        self.write_weighted(output, |counts| counts.entries)
+    }
+
+    /// Writes one line per call stack, weighted by the number of instructions retired
+    pub fn write_collapsed_by_instruction_counts<W: utils::Write>(
+        &self,
+        output: &mut W,
+    ) -> Result<(), InternalError> {
+        self.write_weighted(output, |counts| counts.instructions)
+    }
+
+    fn write_weighted<W: utils::Write>(
+        &self,
+        output: &mut W,
+        weight: impl Fn(&StackCounts) -> u64,
+    ) -> Result<(), InternalError> {
+        for (stack, counts) in self.stacks.iter() {
+            let weight = weight(counts);
+            if weight == 0 {
+                continue;
+            }
+            let frames = stack
+                .iter()
+                .map(|pc| match self.analysis.functions.get(pc) {
+                    Some((_key, name)) => name.clone(),
+                    None => format!("function_{pc}"),
+                })
+                .collect::<Vec<_>>();
+            writeln!(output, "{} {}", frames.join(";"), weight)?;
+        }
+        Ok(())
+    }
+}
+
+/// First entry at which two traces differ, see [compare_traces]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DivergenceReport {
+    /// Index of the first entry which differs
+    pub index: usize,
+    /// Entry of the first trace, `None` if the trace ended before
+    pub a: Option<TraceLogEntry>,
+    /// Entry of the second trace, `None` if the trace ended before
+    pub b: Option<TraceLogEntry>,
+}
+
+/// Finds the first entry at which the registers or the pc of two traces differ
+///
+/// If one trace is a prefix of the other, they diverge where the shorter one ends. Returns
+/// `None` if the traces are equal.
+pub fn compare_traces(a: &[TraceLogEntry], b: &[TraceLogEntry]) -> Option<DivergenceReport> {
+    let index = a
+        .iter()
+        .zip(b.iter())
+        .position(|(a, b)| a != b)
+        .unwrap_or_else(|| a.len().min(b.len()));
+    if index == a.len() && index == b.len() {
+        return None;
+    }
+    Some(DivergenceReport {
+        index,
+        a: a.get(index).copied(),
+        b: b.get(index).copied(),
+    })
+}
+
+impl DivergenceReport {
+    /// Writes the instructions both traces are at, followed by their registers side by side
+    ///
+    /// The registers which differ are marked with `<-`. The instructions are disassembled by
+    /// `analysis`, which has to be of the executable the traces were recorded from.
+    pub fn write<W: utils::Write>(
+        &self,
+        output: &mut W,
+        analysis: &Analysis,
+    ) -> Result<(), InternalError> {
+        writeln!(output, "traces diverge at entry {}", self.index)?;
+        for (name, entry) in [("a", &self.a), ("b", &self.b)] {
+            match entry {
+                Some(entry) => {
+                    let pc = entry[11] as usize;
+                    let instruction = analysis
+                        .instructions
+                        .binary_search_by(|insn| insn.ptr.cmp(&pc))
+                        .map(|index| {
+                            analysis.disassemble_instruction(&analysis.instructions[index], pc)
+                        })
+                        .unwrap_or_else(|_| "[no instruction]".to_string());
+                    writeln!(output, "{name}: {pc:5}: {instruction}")?;
+                }
+                None => writeln!(output, "{name}: end of trace")?,
+            }
+        }
+        writeln!(output, "{:3}  {:18}  b", "", "a")?;
+        for (index, register) in (0..=ebpf::FRAME_PTR_REG)
+            .map(|reg| format!("r{reg}"))
+            .chain(core::iter::once("pc".to_string()))
+            .enumerate()
+        {
+            let a = self.a.map(|entry| entry[index]);
+            let b = self.b.map(|entry| entry[index]);
+            let column = |value: Option<u64>| match value {
+                Some(value) => format!("{value:#018x}"),
+                None => "-".to_string(),
+            };
+            writeln!(
+                output,
+                "{register:3}  {:18}  {}{}",
+                column(a),
+                column(b),
+                if a != b { "  <-" } else { "" }
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+#![allow(clippy::arithmetic_side_effects)]
+// Licensed under the Apache License, Version 2.0 <http://www.apache.org/licenses/LICENSE-2.0> or
+// the MIT license <http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Recording of the syscalls of an execution, and replaying them without the host.
+//!
+//! Setting [EbpfVm::syscall_recorder] collects a [SyscallRecord] for every syscall, in the
+//! interpreter and the JIT alike. Builtins declared with [declare_builtin_function] record
+//! themselves, other [BuiltinFunction]s are not recorded.
+//!
+//! A [ReplayContextObject] with the loader of [ReplayContextObject::loader] answers the syscalls
+//! of later executions from a recording, in order. Only return values, errors and consumed
+//! units are replayed, memory written by the original syscalls is not part of the recording.
+//!
+//! [EbpfVm::syscall_recorder]: crate::vm::EbpfVm::syscall_recorder
+//! [BuiltinFunction]: crate::program::BuiltinFunction
+
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::{
+    declare_builtin_function,
+    elf::ElfError,
+    memory_region::MemoryMapping,
+    program::BuiltinProgram,
+    vm::{Config, ContextObject},
+};
+
+/// A syscall made by a program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyscallRecord {
+    /// Key the syscall is registered under in the loader
+    pub key: u32,
+    /// Name the syscall is registered under in the loader
+    pub name: Vec<u8>,
+    /// Arguments in r1 to r5
+    pub arguments: [u64; 5],
+    /// Return value in r0, or the message of the error the syscall failed with
+    pub result: Result<u64, String>,
+    /// Units the syscall consumed from the instruction meter, besides its instruction
+    pub consumed_units: u64,
+}
+
+/// Reasons for a replayed syscall to fail
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum ReplayError {
+    /// The program made more syscalls than were recorded
+    #[error("syscall #{index} was not recorded")]
+    NotRecorded {
+        /// Index of the syscall in the execution
+        index: usize,
+    },
+    /// The program passed other arguments than recorded
+    #[error(
+        "syscall #{index} to {name} diverged: recorded arguments {recorded:?}, got {actual:?}"
+    )]
+    ArgumentsDiverged {
+        /// Index of the syscall in the execution
+        index: usize,
+        /// Name of the recorded syscall
+        name: String,
+        /// Arguments of the recorded syscall
+        recorded: [u64; 5],
+        /// Arguments of the replayed syscall
+        actual: [u64; 5],
+    },
+    /// The recorded syscall failed with this message
+    #[error("{0}")]
+    Recorded(String),
+}
+
+/// Context object which answers syscalls from a recording
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplayContextObject {
+    /// Recorded syscalls, in the order they are answered
+    pub records: Vec<SyscallRecord>,
+    /// Index of the record answering the next syscall
+    pub next_record: usize,
+    /// Maximal amount of instructions which still can be executed
+    pub remaining: u64,
+    /// Registers of every traced instruction, see [Config::enable_instruction_tracing]
+    pub trace_log: Vec<[u64; 12]>,
+}
+
+impl ContextObject for ReplayContextObject {
+    fn trace(&mut self, state: [u64; 12]) {
+        self.trace_log.push(state);
+    }
+
+    fn consume(&mut self, amount: u64) {
+        self.remaining = self.remaining.saturating_sub(amount);
+    }
+
+    fn get_remaining(&self) -> u64 {
+        self.remaining
+    }
+}
+
+impl ReplayContextObject {
+    /// Replays `records` with an instruction meter of `remaining`
+    pub fn new(records: Vec<SyscallRecord>, remaining: u64) -> Self {
+        Self {
+            records,
+            remaining,
+            ..Self::default()
+        }
+    }
+
+    /// Creates a loader which has [SyscallReplay] registered under every recorded syscall name
+    pub fn loader(&self, config: Config) -> Result<BuiltinProgram<Self>, ElfError> {
+        let mut loader = BuiltinProgram::new_loader(config);
+        for record in self.records.iter() {
+            if loader
+                .get_function_registry()
+                .lookup_by_key(record.key)
+                .is_none()
+            {
+                loader
+                    .register_function(&String::from_utf8_lossy(&record.name), SyscallReplay::vm)?;
+            }
+        }
+        Ok(loader)
+    }
+
+    /// Returns true if every recorded syscall was replayed
+    pub fn is_complete(&self) -> bool {
+        self.next_record == self.records.len()
+    }
+}
+
+declare_builtin_function!(
+    /// Answers a syscall with the next record of a [ReplayContextObject]
+    SyscallReplay,
+    fn rust(
+        context_object: &mut ReplayContextObject,
+        arg_a: u64,
+        arg_b: u64,
+        arg_c: u64,
+        arg_d: u64,
+        arg_e: u64,
+        _memory_mapping: &mut MemoryMapping,
+    ) -> Result<u64, Box<dyn core::error::Error>> {
+        let index = context_object.next_record;
+        let record = context_object
+            .records
+            .get(index)
+            .ok_or(ReplayError::NotRecorded { index })?;
+        let actual = [arg_a, arg_b, arg_c, arg_d, arg_e];
+        if record.arguments != actual {
+            return Err(Box::new(ReplayError::ArgumentsDiverged {
+                index,
+                name: String::from_utf8_lossy(&record.name).to_string(),
+                recorded: record.arguments,
+                actual,
+            }));
+        }
+        let consumed_units = record.consumed_units;
+        let result = record.result.clone();
+        context_object.next_record += 1;
+        context_object.consume(consumed_units);
+        result.map_err(|message| ReplayError::Recorded(message).into())
+    }
+);
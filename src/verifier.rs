@@ -13,10 +13,13 @@
 //! Verifies that the bytecode is valid for the given config.
 
 use crate::{
+    disassembler::{decode, DecodedInsn},
     ebpf,
     program::{BuiltinFunction, FunctionRegistry, SBPFVersion},
     vm::{Config, ContextObject},
 };
+use alloc::{boxed::Box, vec, vec::Vec};
+use core::marker::PhantomData;
 use thiserror::Error;
 
 /// Error definitions
@@ -32,44 +35,109 @@ pub enum VerifierError {
     #[error("no program set, call prog_set() to load one")]
     NoProgram,
     /// Division by zero
-    #[error("division by 0 (insn #{0})")]
-    DivisionByZero(usize),
+    #[error("division by 0 (insn #{pc}: {insn})")]
+    DivisionByZero {
+        /// Pc of the instruction
+        pc: usize,
+        /// The instruction
+        insn: Box<DecodedInsn>,
+    },
     /// UnsupportedLEBEArgument
-    #[error("unsupported argument for LE/BE (insn #{0})")]
-    UnsupportedLEBEArgument(usize),
+    #[error("unsupported argument for LE/BE (insn #{pc}: {insn})")]
+    UnsupportedLEBEArgument {
+        /// Pc of the instruction
+        pc: usize,
+        /// The instruction
+        insn: Box<DecodedInsn>,
+    },
     /// LDDWCannotBeLast
     #[error("LD_DW instruction cannot be last in program")]
     LDDWCannotBeLast,
     /// IncompleteLDDW
-    #[error("incomplete LD_DW instruction (insn #{0})")]
-    IncompleteLDDW(usize),
+    #[error("incomplete LD_DW instruction (insn #{pc}: {insn})")]
+    IncompleteLDDW {
+        /// Pc of the instruction
+        pc: usize,
+        /// The instruction
+        insn: Box<DecodedInsn>,
+    },
     /// InfiniteLoop
     #[error("infinite loop (insn #{0})")]
     InfiniteLoop(usize),
     /// JumpOutOfCode
-    #[error("jump out of code to #{0} (insn #{1})")]
-    JumpOutOfCode(usize, usize),
-    /// JumpToMiddleOfLDDW
-    #[error("jump to middle of LD_DW at #{0} (insn #{1})")]
-    JumpToMiddleOfLDDW(usize, usize),
+    #[error("jump out of code to #{target_pc} (insn #{pc}: {insn})")]
+    JumpOutOfCode {
+        /// Pc of the jump target
+        target_pc: usize,
+        /// Pc of the instruction
+        pc: usize,
+        /// The instruction
+        insn: Box<DecodedInsn>,
+    },
+    /// Jump or call into the second slot of an LD_DW_IMM
+    #[error("jump to middle of LD_DW at #{target_pc} (insn #{pc}: {insn})")]
+    JumpToMiddleOfLDDW {
+        /// Pc of the jump target
+        target_pc: usize,
+        /// Pc of the instruction
+        pc: usize,
+        /// The instruction
+        insn: Box<DecodedInsn>,
+    },
     /// InvalidSourceRegister
-    #[error("invalid source register (insn #{0})")]
-    InvalidSourceRegister(usize),
+    #[error("invalid source register (insn #{pc}: {insn})")]
+    InvalidSourceRegister {
+        /// Pc of the instruction
+        pc: usize,
+        /// The instruction
+        insn: Box<DecodedInsn>,
+    },
     /// CannotWriteR10
-    #[error("cannot write into register r10 (insn #{0})")]
-    CannotWriteR10(usize),
+    #[error("cannot write into register r10 (insn #{pc}: {insn})")]
+    CannotWriteR10 {
+        /// Pc of the instruction
+        pc: usize,
+        /// The instruction
+        insn: Box<DecodedInsn>,
+    },
     /// InvalidDestinationRegister
-    #[error("invalid destination register (insn #{0})")]
-    InvalidDestinationRegister(usize),
+    #[error("invalid destination register (insn #{pc}: {insn})")]
+    InvalidDestinationRegister {
+        /// Pc of the instruction
+        pc: usize,
+        /// The instruction
+        insn: Box<DecodedInsn>,
+    },
     /// UnknownOpCode
-    #[error("unknown eBPF opcode {0:#2x} (insn #{1:?})")]
-    UnknownOpCode(u8, usize),
+    #[error("unknown eBPF opcode {opc:#2x} (insn #{pc}: {insn})")]
+    UnknownOpCode {
+        /// Opcode of the instruction
+        opc: u8,
+        /// Pc of the instruction
+        pc: usize,
+        /// The instruction
+        insn: Box<DecodedInsn>,
+    },
     /// Shift with overflow
-    #[error("Shift with overflow of {0}-bit value by {1} (insn #{2:?})")]
-    ShiftWithOverflow(u64, u64, usize),
+    #[error("Shift with overflow of {bits}-bit value by {shift} (insn #{pc}: {insn})")]
+    ShiftWithOverflow {
+        /// Shift amount
+        shift: u64,
+        /// Width of the shifted value
+        bits: u64,
+        /// Pc of the instruction
+        pc: usize,
+        /// The instruction
+        insn: Box<DecodedInsn>,
+    },
     /// Invalid register specified
-    #[error("Invalid register specified at instruction {0}")]
-    InvalidRegister(usize),
+    #[error("Invalid register specified at instruction {pc}: {insn}")]
+    InvalidRegister {
+        /// Pc of the instruction
+        pc: usize,
+        /// The instruction
+        insn: Box<DecodedInsn>,
+    },
     /// Invalid function
     #[error("Invalid function at instruction {0}")]
     InvalidFunction(usize),
@@ -77,8 +145,78 @@ pub enum VerifierError {
     #[error("Invalid syscall code {0}")]
     InvalidSyscall(u32),
     /// Unaligned immediate
-    #[error("Unaligned immediate (insn #{0})")]
-    UnalignedImmediate(usize),
+    #[error("Unaligned immediate (insn #{pc}: {insn})")]
+    UnalignedImmediate {
+        /// Pc of the instruction
+        pc: usize,
+        /// The instruction
+        insn: Box<DecodedInsn>,
+    },
+    /// The number of executed instructions is not statically bounded
+    #[error("Unbounded execution because of a loop, recursion or indirect call (insn #{0})")]
+    UnboundedExecution(usize),
+    /// The static bound of executed instructions exceeds the budget
+    #[error("Execution bound of {0} instructions exceeds the budget of {1}")]
+    ExecutionBoundExceeded(u64, u64),
+    /// A value derived from the frame pointer leaves the program
+    #[error("Value derived from the frame pointer at insn #{0} leaks at insn #{1}")]
+    StackPointerLeak(usize, usize),
+    /// callx is rejected by [VerifierConfig::allow_callx]
+    #[error("callx is not allowed (insn #{pc}: {insn})")]
+    CallxNotAllowed {
+        /// Pc of the instruction
+        pc: usize,
+        /// The instruction
+        insn: Box<DecodedInsn>,
+    },
+    /// The program is longer than [VerifierConfig::max_insn_count]
+    #[error("program has {0} instruction slots, more than the maximum of {1}")]
+    TooManyInstructions(usize, usize),
+    /// No function reaches the instruction, see [VerifierConfig::reject_unreachable_code]
+    #[error("unreachable instruction (insn #{pc}: {insn})")]
+    UnreachableInstruction {
+        /// Pc of the instruction
+        pc: usize,
+        /// The instruction
+        insn: Box<DecodedInsn>,
+    },
+    /// Rejected by one of the verifiers of a [VerifierChain]
+    #[error("{verifier}: {error}")]
+    RejectedBy {
+        /// Type name of the verifier, see [core::any::type_name]
+        verifier: &'static str,
+        /// The error it returned
+        error: Box<VerifierError>,
+    },
+}
+
+/// Adjustable rules of the [RequisiteVerifier]
+///
+/// The default enforces the same rules as before this existed. Divisions by a constant zero
+/// are always rejected, because the interpreter and the JIT rely on that.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerifierConfig {
+    /// Accept `callx` instructions
+    pub allow_callx: bool,
+    /// Maximum length of the program in instruction slots, `lddw` taking two
+    pub max_insn_count: Option<usize>,
+    /// Reject instructions which can not be reached from any function
+    ///
+    /// Execution starts at the entrypoint and the other functions of the registry, which also
+    /// holds the targets of `call`. The targets of `callx` are not known, so the functions only
+    /// called that way have to be in the registry. The second slot of `lddw` is not an
+    /// instruction and is never reported.
+    pub reject_unreachable_code: bool,
+}
+
+impl Default for VerifierConfig {
+    fn default() -> Self {
+        Self {
+            allow_callx: true,
+            max_insn_count: None,
+            reject_unreachable_code: false,
+        }
+    }
 }
 
 /// eBPF Verifier
@@ -94,14 +232,66 @@ pub trait Verifier {
     fn verify<C: ContextObject>(
         prog: &[u8],
         config: &Config,
+        verifier_config: &VerifierConfig,
         sbpf_version: SBPFVersion,
         function_registry: &FunctionRegistry<usize>,
         syscall_registry: &FunctionRegistry<BuiltinFunction<C>>,
     ) -> Result<(), VerifierError>;
 }
 
+/// Runs the verifier `A` and then `B`, stopping at the first error
+///
+/// The chain is a [Verifier] itself, so it can be nested to run more than two, e.g.
+/// `VerifierChain<RequisiteVerifier, VerifierChain<PolicyA, PolicyB>>`. Errors are wrapped in
+/// [VerifierError::RejectedBy], naming the verifier which returned them.
+#[derive(Debug)]
+pub struct VerifierChain<A, B> {
+    _verifiers: PhantomData<(A, B)>,
+}
+
+impl<A: Verifier, B: Verifier> Verifier for VerifierChain<A, B> {
+    fn verify<C: ContextObject>(
+        prog: &[u8],
+        config: &Config,
+        verifier_config: &VerifierConfig,
+        sbpf_version: SBPFVersion,
+        function_registry: &FunctionRegistry<usize>,
+        syscall_registry: &FunctionRegistry<BuiltinFunction<C>>,
+    ) -> Result<(), VerifierError> {
+        A::verify(
+            prog,
+            config,
+            verifier_config,
+            sbpf_version,
+            function_registry,
+            syscall_registry,
+        )
+        .map_err(rejected_by::<A>)?;
+        B::verify(
+            prog,
+            config,
+            verifier_config,
+            sbpf_version,
+            function_registry,
+            syscall_registry,
+        )
+        .map_err(rejected_by::<B>)
+    }
+}
+
+/// Attributes `error` to the verifier `V`, unless a nested [VerifierChain] already did
+fn rejected_by<V>(error: VerifierError) -> VerifierError {
+    match error {
+        VerifierError::RejectedBy { .. } => error,
+        error => VerifierError::RejectedBy {
+            verifier: core::any::type_name::<V>(),
+            error: Box::new(error),
+        },
+    }
+}
+
 fn check_prog_len(prog: &[u8]) -> Result<(), VerifierError> {
-    if prog.len() % ebpf::INSN_SIZE != 0 {
+    if !prog.len().is_multiple_of(ebpf::INSN_SIZE) {
         return Err(VerifierError::ProgramLengthNotMultiple);
     }
     if prog.is_empty() {
@@ -110,64 +300,119 @@ fn check_prog_len(prog: &[u8]) -> Result<(), VerifierError> {
     Ok(())
 }
 
-fn check_imm_nonzero(insn: &ebpf::Insn, insn_ptr: usize) -> Result<(), VerifierError> {
-    if insn.imm == 0 {
-        return Err(VerifierError::DivisionByZero(insn_ptr));
-    }
-    Ok(())
+/// Decodes the instruction an error is reported for
+///
+/// Boxed so that [VerifierError] does not grow [crate::error::ProgramResult], whose size is part
+/// of the runtime environment layout the JIT relies on.
+fn decode_boxed(insn: &ebpf::Insn, sbpf_version: SBPFVersion) -> Box<DecodedInsn> {
+    Box::new(decode(insn, sbpf_version))
 }
 
-fn check_imm_endian(insn: &ebpf::Insn, insn_ptr: usize) -> Result<(), VerifierError> {
-    match insn.imm {
-        16 | 32 | 64 => Ok(()),
-        _ => Err(VerifierError::UnsupportedLEBEArgument(insn_ptr)),
+/// Check the immediate against the range of its opcode
+fn check_imm_range(
+    insn: &ebpf::Insn,
+    insn_ptr: usize,
+    sbpf_version: SBPFVersion,
+) -> Result<(), VerifierError> {
+    let range = ebpf::ImmediateRange::of_opcode(insn.opc, sbpf_version);
+    if range.contains(insn.imm) {
+        return Ok(());
     }
+    let (pc, decoded) = (insn_ptr, decode_boxed(insn, sbpf_version));
+    Err(match range {
+        ebpf::ImmediateRange::Any => unreachable!(),
+        ebpf::ImmediateRange::NonZero => VerifierError::DivisionByZero { pc, insn: decoded },
+        ebpf::ImmediateRange::ShiftAmount(bits) => VerifierError::ShiftWithOverflow {
+            shift: insn.imm as u64,
+            bits,
+            pc,
+            insn: decoded,
+        },
+        ebpf::ImmediateRange::ByteSwapWidth => {
+            VerifierError::UnsupportedLEBEArgument { pc, insn: decoded }
+        }
+    })
 }
 
 fn check_imm_aligned(
     insn: &ebpf::Insn,
     insn_ptr: usize,
     alignment: i64,
+    sbpf_version: SBPFVersion,
 ) -> Result<(), VerifierError> {
     if (insn.imm & (alignment - 1)) == 0 {
         Ok(())
     } else {
-        Err(VerifierError::UnalignedImmediate(insn_ptr))
+        Err(VerifierError::UnalignedImmediate {
+            pc: insn_ptr,
+            insn: decode_boxed(insn, sbpf_version),
+        })
     }
 }
 
-fn check_load_dw(prog: &[u8], insn_ptr: usize) -> Result<(), VerifierError> {
+fn check_load_dw(
+    prog: &[u8],
+    insn_ptr: usize,
+    sbpf_version: SBPFVersion,
+) -> Result<(), VerifierError> {
     if (insn_ptr + 1) * ebpf::INSN_SIZE >= prog.len() {
         // Last instruction cannot be LD_DW because there would be no 2nd DW
         return Err(VerifierError::LDDWCannotBeLast);
     }
     let next_insn = ebpf::get_insn(prog, insn_ptr + 1);
     if next_insn.opc != 0 {
-        return Err(VerifierError::IncompleteLDDW(insn_ptr));
+        return Err(VerifierError::IncompleteLDDW {
+            pc: insn_ptr,
+            insn: decode_boxed(&ebpf::get_insn(prog, insn_ptr), sbpf_version),
+        });
     }
     Ok(())
 }
 
+/// Marks the second slot of every LD_DW_IMM, for the versions which have it
+pub(crate) fn lddw_second_slots(prog: &[u8], sbpf_version: SBPFVersion) -> Vec<bool> {
+    let insn_count = prog.len() / ebpf::INSN_SIZE;
+    let mut second_slots = vec![false; insn_count];
+    if sbpf_version.disable_lddw() {
+        return second_slots;
+    }
+    let mut insn_ptr = 0;
+    while insn_ptr < insn_count {
+        if ebpf::get_insn(prog, insn_ptr).opc == ebpf::LD_DW_IMM {
+            // An LD_DW_IMM in the last slot is rejected by check_load_dw
+            if let Some(second_slot) = second_slots.get_mut(insn_ptr + 1) {
+                *second_slot = true;
+            }
+            insn_ptr += 1;
+        }
+        insn_ptr += 1;
+    }
+    second_slots
+}
+
 fn check_jmp_offset(
     prog: &[u8],
     insn_ptr: usize,
     function_range: &core::ops::Range<usize>,
+    lddw_second_slots: &[bool],
+    sbpf_version: SBPFVersion,
 ) -> Result<(), VerifierError> {
     let insn = ebpf::get_insn(prog, insn_ptr);
 
     let dst_insn_ptr = insn_ptr as isize + 1 + insn.off as isize;
     if dst_insn_ptr < 0 || !function_range.contains(&(dst_insn_ptr as usize)) {
-        return Err(VerifierError::JumpOutOfCode(
-            dst_insn_ptr as usize,
-            insn_ptr,
-        ));
+        return Err(VerifierError::JumpOutOfCode {
+            target_pc: dst_insn_ptr as usize,
+            pc: insn_ptr,
+            insn: decode_boxed(&insn, sbpf_version),
+        });
     }
-    let dst_insn = ebpf::get_insn(prog, dst_insn_ptr as usize);
-    if dst_insn.opc == 0 {
-        return Err(VerifierError::JumpToMiddleOfLDDW(
-            dst_insn_ptr as usize,
-            insn_ptr,
-        ));
+    if lddw_second_slots[dst_insn_ptr as usize] {
+        return Err(VerifierError::JumpToMiddleOfLDDW {
+            target_pc: dst_insn_ptr as usize,
+            pc: insn_ptr,
+            insn: decode_boxed(&insn, sbpf_version),
+        });
     }
     Ok(())
 }
@@ -193,27 +438,26 @@ fn check_registers(
     insn_ptr: usize,
     sbpf_version: SBPFVersion,
 ) -> Result<(), VerifierError> {
+    let (pc, decoded) = (insn_ptr, || decode_boxed(insn, sbpf_version));
     if insn.src > 10 {
-        return Err(VerifierError::InvalidSourceRegister(insn_ptr));
+        return Err(VerifierError::InvalidSourceRegister {
+            pc,
+            insn: decoded(),
+        });
     }
 
     match (insn.dst, store) {
         (0..=9, _) | (10, true) => Ok(()),
         (10, false) if sbpf_version.dynamic_stack_frames() && insn.opc == ebpf::ADD64_IMM => Ok(()),
-        (10, false) => Err(VerifierError::CannotWriteR10(insn_ptr)),
-        (_, _) => Err(VerifierError::InvalidDestinationRegister(insn_ptr)),
-    }
-}
-
-/// Check that the imm is a valid shift operand
-fn check_imm_shift(insn: &ebpf::Insn, insn_ptr: usize, imm_bits: u64) -> Result<(), VerifierError> {
-    let shift_by = insn.imm as u64;
-    if insn.imm < 0 || shift_by >= imm_bits {
-        return Err(VerifierError::ShiftWithOverflow(
-            shift_by, imm_bits, insn_ptr,
-        ));
+        (10, false) => Err(VerifierError::CannotWriteR10 {
+            pc,
+            insn: decoded(),
+        }),
+        (_, _) => Err(VerifierError::InvalidDestinationRegister {
+            pc,
+            insn: decoded(),
+        }),
     }
-    Ok(())
 }
 
 /// Check that callx has a valid register number
@@ -228,20 +472,85 @@ fn check_callx_register(
         insn.imm
     };
     if !(0..10).contains(&reg) {
-        return Err(VerifierError::InvalidRegister(insn_ptr));
+        return Err(VerifierError::InvalidRegister {
+            pc: insn_ptr,
+            insn: decode_boxed(insn, sbpf_version),
+        });
     }
     Ok(())
 }
 
+/// Check that every instruction is reachable from the entry of a function
+///
+/// Follows the same jump targets as [check_jmp_offset], which has already accepted them.
+fn check_reachability(
+    prog: &[u8],
+    sbpf_version: SBPFVersion,
+    function_registry: &FunctionRegistry<usize>,
+) -> Result<(), VerifierError> {
+    let insn_count = prog.len() / ebpf::INSN_SIZE;
+    let mut reachable = vec![false; insn_count];
+    let mut stack = function_registry
+        .iter()
+        .map(|(_key, (_name, insn_ptr))| insn_ptr)
+        .collect::<Vec<usize>>();
+    while let Some(insn_ptr) = stack.pop() {
+        if insn_ptr >= insn_count || reachable[insn_ptr] {
+            continue;
+        }
+        reachable[insn_ptr] = true;
+        let insn = ebpf::get_insn(prog, insn_ptr);
+        let target_ptr = (insn_ptr as isize + 1 + insn.off as isize) as usize;
+        match insn.opc {
+            ebpf::LD_DW_IMM if !sbpf_version.disable_lddw() => {
+                reachable[insn_ptr + 1] = true;
+                stack.push(insn_ptr + 2);
+                continue;
+            }
+            ebpf::JA => {
+                stack.push(target_ptr);
+                continue;
+            }
+            ebpf::EXIT if !sbpf_version.static_syscalls() => continue,
+            ebpf::RETURN if sbpf_version.static_syscalls() => continue,
+            ebpf::CALL_IMM => {
+                let key = sbpf_version.calculate_call_imm_target_pc(insn_ptr, insn.imm);
+                if let Some((_name, target_ptr)) = function_registry.lookup_by_key(key) {
+                    stack.push(target_ptr);
+                }
+            }
+            ebpf::CALL_REG => {}
+            ebpf::SYSCALL if sbpf_version.static_syscalls() => {}
+            _ if insn.opc & ebpf::BPF_CLS_MASK == ebpf::BPF_JMP => stack.push(target_ptr),
+            _ => {}
+        }
+        stack.push(insn_ptr + 1);
+    }
+    match reachable.iter().position(|reachable| !reachable) {
+        Some(insn_ptr) => Err(VerifierError::UnreachableInstruction {
+            pc: insn_ptr,
+            insn: decode_boxed(&ebpf::get_insn(prog, insn_ptr), sbpf_version),
+        }),
+        None => Ok(()),
+    }
+}
+
 /// Mandatory verifier for solana programs to run on-chain
 #[derive(Debug)]
 pub struct RequisiteVerifier {}
 impl Verifier for RequisiteVerifier {
     /// Check the program against the verifier's rules
     #[rustfmt::skip]
-    fn verify<C: ContextObject>(prog: &[u8], _config: &Config, sbpf_version: SBPFVersion, function_registry: &FunctionRegistry<usize>, syscall_registry: &FunctionRegistry<BuiltinFunction<C>>) -> Result<(), VerifierError> {
+    fn verify<C: ContextObject>(prog: &[u8], _config: &Config, verifier_config: &VerifierConfig, sbpf_version: SBPFVersion, function_registry: &FunctionRegistry<usize>, syscall_registry: &FunctionRegistry<BuiltinFunction<C>>) -> Result<(), VerifierError> {
         check_prog_len(prog)?;
+        if let Some(max_insn_count) = verifier_config.max_insn_count {
+            let insn_count = prog.len() / ebpf::INSN_SIZE;
+            if insn_count > max_insn_count {
+                return Err(VerifierError::TooManyInstructions(insn_count, max_insn_count));
+            }
+        }
 
+        let lddw_second_slots = lddw_second_slots(prog, sbpf_version);
         let program_range = 0..prog.len() / ebpf::INSN_SIZE;
         let mut function_iter = function_registry.keys().map(|insn_ptr| insn_ptr as usize).peekable();
         let mut function_range = program_range.start..program_range.end;
@@ -264,7 +573,7 @@ impl Verifier for RequisiteVerifier {
 
             match insn.opc {
                 ebpf::LD_DW_IMM if !sbpf_version.disable_lddw() => {
-                    check_load_dw(prog, insn_ptr)?;
+                    check_load_dw(prog, insn_ptr, sbpf_version)?;
                     insn_ptr += 1;
                 },
 
@@ -294,34 +603,34 @@ impl Verifier for RequisiteVerifier {
                 ebpf::MUL32_IMM  if !sbpf_version.enable_pqr() => {},
                 ebpf::MUL32_REG  if !sbpf_version.enable_pqr() => {},
                 ebpf::LD_1B_REG  if sbpf_version.move_memory_instruction_classes() => {},
-                ebpf::DIV32_IMM  if !sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
+                ebpf::DIV32_IMM  if !sbpf_version.enable_pqr() => {},
                 ebpf::DIV32_REG  if !sbpf_version.enable_pqr() => {},
                 ebpf::LD_2B_REG  if sbpf_version.move_memory_instruction_classes() => {},
                 ebpf::OR32_IMM   => {},
                 ebpf::OR32_REG   => {},
                 ebpf::AND32_IMM  => {},
                 ebpf::AND32_REG  => {},
-                ebpf::LSH32_IMM  => { check_imm_shift(&insn, insn_ptr, 32)?; },
+                ebpf::LSH32_IMM  => {},
                 ebpf::LSH32_REG  => {},
-                ebpf::RSH32_IMM  => { check_imm_shift(&insn, insn_ptr, 32)?; },
+                ebpf::RSH32_IMM  => {},
                 ebpf::RSH32_REG  => {},
                 ebpf::NEG32      if !sbpf_version.disable_neg() => {},
                 ebpf::LD_4B_REG  if sbpf_version.move_memory_instruction_classes() => {},
-                ebpf::MOD32_IMM  if !sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
+                ebpf::MOD32_IMM  if !sbpf_version.enable_pqr() => {},
                 ebpf::MOD32_REG  if !sbpf_version.enable_pqr() => {},
                 ebpf::LD_8B_REG  if sbpf_version.move_memory_instruction_classes() => {},
                 ebpf::XOR32_IMM  => {},
                 ebpf::XOR32_REG  => {},
                 ebpf::MOV32_IMM  => {},
                 ebpf::MOV32_REG  => {},
-                ebpf::ARSH32_IMM => { check_imm_shift(&insn, insn_ptr, 32)?; },
+                ebpf::ARSH32_IMM => {},
                 ebpf::ARSH32_REG => {},
-                ebpf::LE         if !sbpf_version.disable_le() => { check_imm_endian(&insn, insn_ptr)?; },
-                ebpf::BE         => { check_imm_endian(&insn, insn_ptr)?; },
+                ebpf::LE         if !sbpf_version.disable_le() => {},
+                ebpf::BE         => {},
 
                 // BPF_ALU64_STORE class
                 ebpf::ADD64_IMM  if insn.dst == ebpf::FRAME_PTR_REG as u8 && sbpf_version.dynamic_stack_frames() => {
-                    check_imm_aligned(&insn, insn_ptr, 64)?;
+                    check_imm_aligned(&insn, insn_ptr, 64, sbpf_version)?;
                 },
                 ebpf::ADD64_IMM  => {},
                 ebpf::ADD64_REG  => {},
@@ -331,7 +640,7 @@ impl Verifier for RequisiteVerifier {
                 ebpf::ST_1B_IMM  if sbpf_version.move_memory_instruction_classes() => store = true,
                 ebpf::MUL64_REG  if !sbpf_version.enable_pqr() => {},
                 ebpf::ST_1B_REG  if sbpf_version.move_memory_instruction_classes() => store = true,
-                ebpf::DIV64_IMM  if !sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
+                ebpf::DIV64_IMM  if !sbpf_version.enable_pqr() => {},
                 ebpf::ST_2B_IMM  if sbpf_version.move_memory_instruction_classes() => store = true,
                 ebpf::DIV64_REG  if !sbpf_version.enable_pqr() => {},
                 ebpf::ST_2B_REG  if sbpf_version.move_memory_instruction_classes() => store = true,
@@ -339,14 +648,14 @@ impl Verifier for RequisiteVerifier {
                 ebpf::OR64_REG   => {},
                 ebpf::AND64_IMM  => {},
                 ebpf::AND64_REG  => {},
-                ebpf::LSH64_IMM  => { check_imm_shift(&insn, insn_ptr, 64)?; },
+                ebpf::LSH64_IMM  => {},
                 ebpf::LSH64_REG  => {},
-                ebpf::RSH64_IMM  => { check_imm_shift(&insn, insn_ptr, 64)?; },
+                ebpf::RSH64_IMM  => {},
                 ebpf::RSH64_REG  => {},
                 ebpf::ST_4B_IMM  if sbpf_version.move_memory_instruction_classes() => store = true,
                 ebpf::NEG64      if !sbpf_version.disable_neg() => {},
                 ebpf::ST_4B_REG  if sbpf_version.move_memory_instruction_classes() => store = true,
-                ebpf::MOD64_IMM  if !sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
+                ebpf::MOD64_IMM  if !sbpf_version.enable_pqr() => {},
                 ebpf::ST_8B_IMM  if sbpf_version.move_memory_instruction_classes() => store = true,
                 ebpf::MOD64_REG  if !sbpf_version.enable_pqr() => {},
                 ebpf::ST_8B_REG  if sbpf_version.move_memory_instruction_classes() => store = true,
@@ -354,7 +663,7 @@ impl Verifier for RequisiteVerifier {
                 ebpf::XOR64_REG  => {},
                 ebpf::MOV64_IMM  => {},
                 ebpf::MOV64_REG  => {},
-                ebpf::ARSH64_IMM => { check_imm_shift(&insn, insn_ptr, 64)?; },
+                ebpf::ARSH64_IMM => {},
                 ebpf::ARSH64_REG => {},
                 ebpf::HOR64_IMM  if sbpf_version.disable_lddw() => {},
 
@@ -367,47 +676,47 @@ impl Verifier for RequisiteVerifier {
                 ebpf::UHMUL64_REG if sbpf_version.enable_pqr() => {},
                 ebpf::SHMUL64_IMM if sbpf_version.enable_pqr() => {},
                 ebpf::SHMUL64_REG if sbpf_version.enable_pqr() => {},
-                ebpf::UDIV32_IMM if sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
+                ebpf::UDIV32_IMM if sbpf_version.enable_pqr() => {},
                 ebpf::UDIV32_REG if sbpf_version.enable_pqr() => {},
-                ebpf::UDIV64_IMM if sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
+                ebpf::UDIV64_IMM if sbpf_version.enable_pqr() => {},
                 ebpf::UDIV64_REG if sbpf_version.enable_pqr() => {},
-                ebpf::UREM32_IMM if sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
+                ebpf::UREM32_IMM if sbpf_version.enable_pqr() => {},
                 ebpf::UREM32_REG if sbpf_version.enable_pqr() => {},
-                ebpf::UREM64_IMM if sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
+                ebpf::UREM64_IMM if sbpf_version.enable_pqr() => {},
                 ebpf::UREM64_REG if sbpf_version.enable_pqr() => {},
-                ebpf::SDIV32_IMM if sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
+                ebpf::SDIV32_IMM if sbpf_version.enable_pqr() => {},
                 ebpf::SDIV32_REG if sbpf_version.enable_pqr() => {},
-                ebpf::SDIV64_IMM if sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
+                ebpf::SDIV64_IMM if sbpf_version.enable_pqr() => {},
                 ebpf::SDIV64_REG if sbpf_version.enable_pqr() => {},
-                ebpf::SREM32_IMM if sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
+                ebpf::SREM32_IMM if sbpf_version.enable_pqr() => {},
                 ebpf::SREM32_REG if sbpf_version.enable_pqr() => {},
-                ebpf::SREM64_IMM if sbpf_version.enable_pqr() => { check_imm_nonzero(&insn, insn_ptr)?; },
+                ebpf::SREM64_IMM if sbpf_version.enable_pqr() => {},
                 ebpf::SREM64_REG if sbpf_version.enable_pqr() => {},
 
                 // BPF_JMP class
-                ebpf::JA         => { check_jmp_offset(prog, insn_ptr, &function_range)?; },
-                ebpf::JEQ_IMM    => { check_jmp_offset(prog, insn_ptr, &function_range)?; },
-                ebpf::JEQ_REG    => { check_jmp_offset(prog, insn_ptr, &function_range)?; },
-                ebpf::JGT_IMM    => { check_jmp_offset(prog, insn_ptr, &function_range)?; },
-                ebpf::JGT_REG    => { check_jmp_offset(prog, insn_ptr, &function_range)?; },
-                ebpf::JGE_IMM    => { check_jmp_offset(prog, insn_ptr, &function_range)?; },
-                ebpf::JGE_REG    => { check_jmp_offset(prog, insn_ptr, &function_range)?; },
-                ebpf::JLT_IMM    => { check_jmp_offset(prog, insn_ptr, &function_range)?; },
-                ebpf::JLT_REG    => { check_jmp_offset(prog, insn_ptr, &function_range)?; },
-                ebpf::JLE_IMM    => { check_jmp_offset(prog, insn_ptr, &function_range)?; },
-                ebpf::JLE_REG    => { check_jmp_offset(prog, insn_ptr, &function_range)?; },
-                ebpf::JSET_IMM   => { check_jmp_offset(prog, insn_ptr, &function_range)?; },
-                ebpf::JSET_REG   => { check_jmp_offset(prog, insn_ptr, &function_range)?; },
-                ebpf::JNE_IMM    => { check_jmp_offset(prog, insn_ptr, &function_range)?; },
-                ebpf::JNE_REG    => { check_jmp_offset(prog, insn_ptr, &function_range)?; },
-                ebpf::JSGT_IMM   => { check_jmp_offset(prog, insn_ptr, &function_range)?; },
-                ebpf::JSGT_REG   => { check_jmp_offset(prog, insn_ptr, &function_range)?; },
-                ebpf::JSGE_IMM   => { check_jmp_offset(prog, insn_ptr, &function_range)?; },
-                ebpf::JSGE_REG   => { check_jmp_offset(prog, insn_ptr, &function_range)?; },
-                ebpf::JSLT_IMM   => { check_jmp_offset(prog, insn_ptr, &function_range)?; },
-                ebpf::JSLT_REG   => { check_jmp_offset(prog, insn_ptr, &function_range)?; },
-                ebpf::JSLE_IMM   => { check_jmp_offset(prog, insn_ptr, &function_range)?; },
-                ebpf::JSLE_REG   => { check_jmp_offset(prog, insn_ptr, &function_range)?; },
+                ebpf::JA         => { check_jmp_offset(prog, insn_ptr, &function_range, &lddw_second_slots, sbpf_version)?; },
+                ebpf::JEQ_IMM    => { check_jmp_offset(prog, insn_ptr, &function_range, &lddw_second_slots, sbpf_version)?; },
+                ebpf::JEQ_REG    => { check_jmp_offset(prog, insn_ptr, &function_range, &lddw_second_slots, sbpf_version)?; },
+                ebpf::JGT_IMM    => { check_jmp_offset(prog, insn_ptr, &function_range, &lddw_second_slots, sbpf_version)?; },
+                ebpf::JGT_REG    => { check_jmp_offset(prog, insn_ptr, &function_range, &lddw_second_slots, sbpf_version)?; },
+                ebpf::JGE_IMM    => { check_jmp_offset(prog, insn_ptr, &function_range, &lddw_second_slots, sbpf_version)?; },
+                ebpf::JGE_REG    => { check_jmp_offset(prog, insn_ptr, &function_range, &lddw_second_slots, sbpf_version)?; },
+                ebpf::JLT_IMM    => { check_jmp_offset(prog, insn_ptr, &function_range, &lddw_second_slots, sbpf_version)?; },
+                ebpf::JLT_REG    => { check_jmp_offset(prog, insn_ptr, &function_range, &lddw_second_slots, sbpf_version)?; },
+                ebpf::JLE_IMM    => { check_jmp_offset(prog, insn_ptr, &function_range, &lddw_second_slots, sbpf_version)?; },
+                ebpf::JLE_REG    => { check_jmp_offset(prog, insn_ptr, &function_range, &lddw_second_slots, sbpf_version)?; },
+                ebpf::JSET_IMM   => { check_jmp_offset(prog, insn_ptr, &function_range, &lddw_second_slots, sbpf_version)?; },
+                ebpf::JSET_REG   => { check_jmp_offset(prog, insn_ptr, &function_range, &lddw_second_slots, sbpf_version)?; },
+                ebpf::JNE_IMM    => { check_jmp_offset(prog, insn_ptr, &function_range, &lddw_second_slots, sbpf_version)?; },
+                ebpf::JNE_REG    => { check_jmp_offset(prog, insn_ptr, &function_range, &lddw_second_slots, sbpf_version)?; },
+                ebpf::JSGT_IMM   => { check_jmp_offset(prog, insn_ptr, &function_range, &lddw_second_slots, sbpf_version)?; },
+                ebpf::JSGT_REG   => { check_jmp_offset(prog, insn_ptr, &function_range, &lddw_second_slots, sbpf_version)?; },
+                ebpf::JSGE_IMM   => { check_jmp_offset(prog, insn_ptr, &function_range, &lddw_second_slots, sbpf_version)?; },
+                ebpf::JSGE_REG   => { check_jmp_offset(prog, insn_ptr, &function_range, &lddw_second_slots, sbpf_version)?; },
+                ebpf::JSLT_IMM   => { check_jmp_offset(prog, insn_ptr, &function_range, &lddw_second_slots, sbpf_version)?; },
+                ebpf::JSLT_REG   => { check_jmp_offset(prog, insn_ptr, &function_range, &lddw_second_slots, sbpf_version)?; },
+                ebpf::JSLE_IMM   => { check_jmp_offset(prog, insn_ptr, &function_range, &lddw_second_slots, sbpf_version)?; },
+                ebpf::JSLE_REG   => { check_jmp_offset(prog, insn_ptr, &function_range, &lddw_second_slots, sbpf_version)?; },
                 ebpf::CALL_IMM   if sbpf_version.static_syscalls() => {
                     let target_pc = sbpf_version.calculate_call_imm_target_pc(insn_ptr, insn.imm);
                     check_call_target(
@@ -416,7 +725,17 @@ impl Verifier for RequisiteVerifier {
                         VerifierError::InvalidFunction(target_pc as usize)
                     )?;
                 },
-                ebpf::CALL_IMM   => {},
+                ebpf::CALL_IMM   => {
+                    // Keys which are not functions are syscalls or fail to resolve at runtime
+                    if let Some((_name, target_pc)) = function_registry.lookup_by_key(insn.imm as u32) {
+                        if lddw_second_slots.get(target_pc) == Some(&true) {
+                            return Err(VerifierError::JumpToMiddleOfLDDW { target_pc, pc: insn_ptr, insn: decode_boxed(&insn, sbpf_version) });
+                        }
+                    }
+                },
+                ebpf::CALL_REG   if !verifier_config.allow_callx => {
+                    return Err(VerifierError::CallxNotAllowed { pc: insn_ptr, insn: decode_boxed(&insn, sbpf_version) });
+                },
                 ebpf::CALL_REG   => { check_callx_register(&insn, insn_ptr, sbpf_version)?; },
                 ebpf::EXIT       if !sbpf_version.static_syscalls()   => {},
                 ebpf::RETURN     if sbpf_version.static_syscalls()    => {},
@@ -428,10 +747,11 @@ impl Verifier for RequisiteVerifier {
                 },
 
                 _                => {
-                    return Err(VerifierError::UnknownOpCode(insn.opc, insn_ptr));
+                    return Err(VerifierError::UnknownOpCode { opc: insn.opc, pc: insn_ptr, insn: decode_boxed(&insn, sbpf_version) });
                 }
             }
 
+            check_imm_range(&insn, insn_ptr, sbpf_version)?;
             check_registers(&insn, store, insn_ptr, sbpf_version)?;
 
             insn_ptr += 1;
@@ -439,7 +759,12 @@ impl Verifier for RequisiteVerifier {
 
         // insn_ptr should now be equal to number of instructions.
         if insn_ptr != prog.len() / ebpf::INSN_SIZE {
-            return Err(VerifierError::JumpOutOfCode(insn_ptr, insn_ptr));
+            // The last instruction was an lddw overrunning the program
+            let last_insn = ebpf::get_insn(prog, insn_ptr.saturating_sub(2));
+            return Err(VerifierError::JumpOutOfCode { target_pc: insn_ptr, pc: last_insn.ptr, insn: decode_boxed(&last_insn, sbpf_version) });
+        }
+        if verifier_config.reject_unreachable_code {
+            check_reachability(prog, sbpf_version, function_registry)?;
         }
 
         Ok(())
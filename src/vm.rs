@@ -12,17 +12,22 @@
 
 //! Virtual machine for eBPF programs.
 
-use alloc::{vec, vec::Vec, collections::BTreeMap};
+use alloc::{vec, vec::Vec, collections::BTreeMap, string::ToString};
 use core::fmt::Debug;
 use spin;
+#[cfg(all(target_pointer_width = "64", target_endian = "little"))]
+use crate::{execution_events::ExecutionEvent, interpreter::Interpreter};
 use crate::{
+    aligned_memory::AlignedMemory,
+    allocator::BumpAllocator,
     ebpf,
     elf::Executable,
     error::{EbpfError, ProgramResult},
-    interpreter::Interpreter,
-    memory_region::MemoryMapping,
+    execution_events::ExecutionEvents,
+    memory_region::{MemoryAccessStats, MemoryMapping, MemoryRegion, MemoryRegionKind},
     program::{BuiltinFunction, BuiltinProgram, FunctionRegistry, SBPFVersion},
     static_analysis::Analysis,
+    syscall_replay::SyscallRecord,
 };
 
 #[cfg(not(feature = "shuttle-test"))]
@@ -49,8 +54,72 @@ pub fn get_runtime_environment_key() -> i32 {
         .call_once(|| SmallRng::seed_from_u64(1).random::<i32>() >> PROGRAM_ENVIRONMENT_KEY_SHIFT)
 }
 
+/// Versioned set of the memory layout parameters programs can observe
+///
+/// Covers the stack size, the stack frame model and the base addresses of the heap and the input
+/// region. Released profiles never change, so replaying old executions only needs the profile
+/// they ran with. Select one with [Config::with_memory_layout].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum MemoryLayoutProfile {
+    /// Historical defaults: 20 gapped stack frames of 4 KiB
+    V0,
+    /// Current defaults: 64 gapped stack frames of 4 KiB
+    V1,
+}
+
+impl MemoryLayoutProfile {
+    /// The profile of [Config::default]
+    pub const CURRENT: Self = Self::V1;
+
+    /// Version number of the profile
+    pub fn version(self) -> u32 {
+        match self {
+            Self::V0 => 0,
+            Self::V1 => 1,
+        }
+    }
+
+    /// Maximum call depth, which also determines the stack size
+    pub fn max_call_depth(self) -> usize {
+        match self {
+            Self::V0 => 20,
+            Self::V1 => 64,
+        }
+    }
+
+    /// Size of a stack frame in bytes
+    pub fn stack_frame_size(self) -> usize {
+        4_096
+    }
+
+    /// Whether there are gaps in VM address space between the stack frames
+    pub fn enable_stack_frame_gaps(self) -> bool {
+        true
+    }
+
+    /// Returns the size of the stack memory region
+    pub fn stack_size(self) -> usize {
+        self.stack_frame_size().saturating_mul(self.max_call_depth())
+    }
+
+    /// VM address of the stack region
+    pub fn stack_start(self) -> u64 {
+        ebpf::MM_STACK_START
+    }
+
+    /// VM address of the heap region
+    pub fn heap_start(self) -> u64 {
+        ebpf::MM_HEAP_START
+    }
+
+    /// VM address of the input region, which is passed to the program in r1
+    pub fn input_start(self) -> u64 {
+        ebpf::MM_INPUT_START
+    }
+}
+
 /// VM configuration settings
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Config {
     /// Maximum call depth
     pub max_call_depth: usize,
@@ -60,7 +129,7 @@ pub struct Config {
     pub enable_address_translation: bool,
     /// Enables gaps in VM address space between the stack frames
     pub enable_stack_frame_gaps: bool,
-    /// Maximal pc distance after which a new instruction meter validation is emitted by the JIT
+    /// Maximal number of instructions the JIT validates the instruction meter for at once
     pub instruction_meter_checkpoint_distance: usize,
     /// Enable instruction meter and limiting
     pub enable_instruction_meter: bool,
@@ -78,24 +147,197 @@ pub struct Config {
     pub optimize_rodata: bool,
     /// Use aligned memory mapping
     pub aligned_memory_mapping: bool,
+    /// Maximum number of writable bytes in the memory regions of a VM (stack, heap and input),
+    /// `u64::MAX` for no limit
+    pub max_writable_bytes: u64,
+    /// Maximum number of bytes in all memory regions of a VM, `u64::MAX` for no limit
+    pub max_total_region_bytes: u64,
+    /// Record every modification made by the relocation pass, see
+    /// [Executable::get_relocation_patches]
+    pub record_relocation_patches: bool,
+    /// Skip dynamic relocations of unknown types outside of the text section instead of
+    /// rejecting the ELF, see [Executable::get_skipped_relocations]
+    pub skip_unknown_relocations: bool,
+    /// Record where the JIT compiler placed the machine code of each instruction, see
+    /// [crate::jit::JitProgram::pc_to_host_offset]
+    pub record_jit_instruction_offsets: bool,
+    /// Makes JIT compilation fail with [EbpfError::ExceededMaxMachineCodeLength] once the machine
+    /// code exceeds this many bytes, see [crate::jit::estimate_machine_code_size]
+    pub max_machine_code_length: Option<usize>,
+    /// Makes the JIT emit the same machine code on every compilation, for debugging and for
+    /// comparing the output of the code generator
+    ///
+    /// Overrides `noop_instruction_rate` and `sanitize_user_provided_values` and uses a fixed
+    /// key of zero for the constants which are always blinded. The key of the runtime environment
+    /// pointer is shared by all VMs of the process, see [get_runtime_environment_key]. This is
+    /// insecure, never enable it for untrusted programs.
+    pub deterministic_jit_output: bool,
+    /// Makes [Executable::verify] reject programs unless their
+    /// [Analysis::max_execution_bound] is at most this many instructions
+    pub max_execution_bound: Option<u64>,
+    /// Makes [Executable::verify] reject programs which pass values derived from r10 to
+    /// syscalls, the input region or the return value, see [Analysis::stack_pointer_leaks]
+    pub reject_stack_pointer_leaks: bool,
+    /// Reject ELF files whose e_flags encode no known SBPF version instead of loading them as
+    /// SBPFv0 or [SBPFVersion::Reserved]
+    pub reject_unknown_e_flags: bool,
+    /// Makes [MemoryMapping::translate_type] reject guest addresses which are not aligned to
+    /// the type, instead of copying the value byte-wise
+    pub reject_unaligned_guest_types: bool,
+    /// Charge the [base_cost](crate::program::SyscallMetadata::base_cost) of a syscall to the
+    /// instruction meter before invoking it
+    ///
+    /// If the remaining budget does not cover the base cost, the syscall is not invoked and the
+    /// program fails with [EbpfError::ExceededMaxInstructions]. Has no effect without
+    /// `enable_instruction_meter`.
+    pub charge_syscall_base_costs: bool,
+    /// Count the memory accesses of load and store instructions in the interpreter, see
+    /// [EbpfVm::memory_access_stats]
+    pub enable_memory_access_stats: bool,
+    /// Record which pages are written after [MemoryMapping::snapshot], so that
+    /// [MemoryMapping::restore] only copies those back
+    pub enable_dirty_page_tracking: bool,
+    /// Memory layout the regions are placed in, see [Config::with_memory_layout]
+    ///
+    /// Setting this field alone only moves the region base addresses, the stack parameters
+    /// are the `max_call_depth`, `stack_frame_size` and `enable_stack_frame_gaps` fields.
+    pub memory_layout: MemoryLayoutProfile,
     /// Allowed [SBPFVersion]s
     pub enabled_sbpf_versions: core::ops::RangeInclusive<SBPFVersion>,
 }
 
+/// Reasons for [Config::validate] to reject a [Config]
+#[derive(Debug, thiserror::Error, PartialEq, Eq, Clone, Copy)]
+pub enum ConfigError {
+    /// `stack_frame_size` is zero
+    #[error("stack frame size must not be zero")]
+    ZeroStackFrameSize,
+    /// `max_call_depth` is zero
+    #[error("max call depth must not be zero")]
+    ZeroCallDepth,
+    /// `stack_frame_size` is not a multiple of [ebpf::HOST_ALIGN]
+    #[error("stack frame size {0} is not a multiple of {align}", align = ebpf::HOST_ALIGN)]
+    UnalignedStackFrameSize(usize),
+    /// `stack_frame_size` is not a power of two, which the gaps between stack frames require
+    #[error("stack frame size {0} is not a power of two, but stack frame gaps are enabled")]
+    StackFrameSizeNotPowerOfTwo(usize),
+    /// The stack frames, including their gaps, do not fit before the heap region
+    #[error("stack spans {stack_span} bytes, but the stack region is {region_size} bytes")]
+    StackExceedsRegion {
+        /// Bytes of VM address space the stack frames and their gaps span
+        stack_span: u64,
+        /// Bytes between the start of the stack region and the start of the heap region
+        region_size: u64,
+    },
+    /// The heap does not fit before the input region, see [Config::validate_heap_size]
+    #[error("heap of {heap_size} bytes exceeds the heap region of {region_size} bytes")]
+    HeapExceedsRegion {
+        /// Requested size of the heap
+        heap_size: u64,
+        /// Bytes between the start of the heap region and the start of the input region
+        region_size: u64,
+    },
+    /// `enabled_sbpf_versions` is an empty range
+    #[error("no SBPF version is enabled")]
+    NoSbpfVersionEnabled,
+}
+
 impl Config {
     /// Returns the size of the stack memory region
     pub fn stack_size(&self) -> usize {
         self.stack_frame_size * self.max_call_depth
     }
+
+    /// Checks that the stack parameters are consistent with each other and the memory layout
+    ///
+    /// The smallest valid stack is a single frame of [ebpf::HOST_ALIGN] bytes. With
+    /// `enable_stack_frame_gaps` the frame size must also be a power of two, and the stack
+    /// occupies twice its size in VM address space. This is called by
+    /// [Executable::from_elf], [Executable::from_text_bytes] and [EbpfVm::try_new].
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.stack_frame_size == 0 {
+            return Err(ConfigError::ZeroStackFrameSize);
+        }
+        if self.max_call_depth == 0 {
+            return Err(ConfigError::ZeroCallDepth);
+        }
+        if !self.stack_frame_size.is_multiple_of(ebpf::HOST_ALIGN) {
+            return Err(ConfigError::UnalignedStackFrameSize(self.stack_frame_size));
+        }
+        if self.enable_stack_frame_gaps && !self.stack_frame_size.is_power_of_two() {
+            return Err(ConfigError::StackFrameSizeNotPowerOfTwo(
+                self.stack_frame_size,
+            ));
+        }
+        let stack_span = (self.stack_frame_size as u64)
+            .saturating_mul(self.max_call_depth as u64)
+            .saturating_mul(if self.enable_stack_frame_gaps { 2 } else { 1 });
+        let region_size = self
+            .memory_layout
+            .heap_start()
+            .saturating_sub(self.memory_layout.stack_start());
+        if stack_span > region_size {
+            return Err(ConfigError::StackExceedsRegion {
+                stack_span,
+                region_size,
+            });
+        }
+        if self.enabled_sbpf_versions.is_empty() {
+            return Err(ConfigError::NoSbpfVersionEnabled);
+        }
+        Ok(())
+    }
+
+    /// Checks that a heap of `heap_size` bytes fits before the input region
+    pub fn validate_heap_size(&self, heap_size: usize) -> Result<(), ConfigError> {
+        let region_size = self
+            .memory_layout
+            .input_start()
+            .saturating_sub(self.memory_layout.heap_start());
+        if heap_size as u64 > region_size {
+            return Err(ConfigError::HeapExceedsRegion {
+                heap_size: heap_size as u64,
+                region_size,
+            });
+        }
+        Ok(())
+    }
+
+    /// Sets `max_call_depth` and `stack_frame_size`, so that [Config::stack_size] is
+    /// `max_call_depth * stack_frame_size`, and validates the result
+    pub fn with_stack(
+        self,
+        max_call_depth: usize,
+        stack_frame_size: usize,
+    ) -> Result<Self, ConfigError> {
+        let config = Self {
+            max_call_depth,
+            stack_frame_size,
+            ..self
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Selects `memory_layout` along with the stack parameters it defines
+    pub fn with_memory_layout(self, memory_layout: MemoryLayoutProfile) -> Self {
+        Self {
+            max_call_depth: memory_layout.max_call_depth(),
+            stack_frame_size: memory_layout.stack_frame_size(),
+            enable_stack_frame_gaps: memory_layout.enable_stack_frame_gaps(),
+            memory_layout,
+            ..self
+        }
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
-            max_call_depth: 64,
-            stack_frame_size: 4_096,
+            max_call_depth: MemoryLayoutProfile::CURRENT.max_call_depth(),
+            stack_frame_size: MemoryLayoutProfile::CURRENT.stack_frame_size(),
             enable_address_translation: true,
-            enable_stack_frame_gaps: true,
+            enable_stack_frame_gaps: MemoryLayoutProfile::CURRENT.enable_stack_frame_gaps(),
             instruction_meter_checkpoint_distance: 10000,
             enable_instruction_meter: true,
             enable_instruction_tracing: false,
@@ -105,6 +347,21 @@ impl Default for Config {
             sanitize_user_provided_values: true,
             optimize_rodata: true,
             aligned_memory_mapping: true,
+            max_writable_bytes: 1 << 30,
+            max_total_region_bytes: 1 << 32,
+            record_relocation_patches: false,
+            skip_unknown_relocations: false,
+            record_jit_instruction_offsets: false,
+            max_machine_code_length: None,
+            deterministic_jit_output: false,
+            max_execution_bound: None,
+            reject_stack_pointer_leaks: false,
+            reject_unknown_e_flags: false,
+            reject_unaligned_guest_types: false,
+            charge_syscall_base_costs: false,
+            enable_memory_access_stats: false,
+            enable_dirty_page_tracking: false,
+            memory_layout: MemoryLayoutProfile::CURRENT,
             enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V3,
         }
     }
@@ -114,6 +371,7 @@ impl Default for Config {
 impl<C: ContextObject> Executable<C> {
     /// Creates an executable from an ELF file
     pub fn from_elf(elf_bytes: &[u8], loader: Arc<BuiltinProgram<C>>) -> Result<Self, EbpfError> {
+        loader.get_config().validate()?;
         let executable = Executable::load(elf_bytes, loader)?;
         Ok(executable)
     }
@@ -124,19 +382,66 @@ impl<C: ContextObject> Executable<C> {
         sbpf_version: SBPFVersion,
         function_registry: FunctionRegistry<usize>,
     ) -> Result<Self, EbpfError> {
+        loader.get_config().validate()?;
         Executable::new_from_text_bytes(text_bytes, loader, sbpf_version, function_registry)
             .map_err(EbpfError::ElfError)
     }
+    /// Creates an executable from machine code and read-only data, see
+    /// [Executable::new_from_text_and_rodata]
+    pub fn from_text_and_rodata(
+        text_bytes: &[u8],
+        rodata: Vec<u8>,
+        rodata_vaddr: u64,
+        loader: Arc<BuiltinProgram<C>>,
+        sbpf_version: SBPFVersion,
+        function_registry: FunctionRegistry<usize>,
+    ) -> Result<Self, EbpfError> {
+        loader.get_config().validate()?;
+        Executable::new_from_text_and_rodata(
+            text_bytes,
+            rodata,
+            rodata_vaddr,
+            loader,
+            sbpf_version,
+            function_registry,
+        )
+        .map_err(EbpfError::ElfError)
+    }
 }
 
 /// Runtime context
 pub trait ContextObject {
     /// Called for every instruction executed when tracing is enabled
     fn trace(&mut self, state: [u64; 12]);
+    /// Called for every instruction before it is executed when
+    /// [Config::enable_instruction_tracing] is set, by the interpreter and the JIT alike
+    ///
+    /// `registers[11]` is the `pc` as well. Returning [TraceControl::Stop] aborts the
+    /// execution with [EbpfError::TraceStopped]. The default passes every instruction to
+    /// [ContextObject::trace] and continues.
+    fn trace_hook(&mut self, pc: usize, registers: &[u64; 12]) -> TraceControl {
+        let _ = pc;
+        self.trace(*registers);
+        TraceControl::Continue
+    }
     /// Consume instructions from meter
     fn consume(&mut self, amount: u64);
     /// Get the number of remaining instructions allowed
     fn get_remaining(&self) -> u64;
+    /// Returns the sink for the events of interpreted executions, if they are recorded
+    fn execution_events(&mut self) -> Option<&mut dyn ExecutionEvents> {
+        None
+    }
+}
+
+/// Decision of a [ContextObject::trace_hook]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u64)] // returned in a register to the JIT
+pub enum TraceControl {
+    /// Execute the instruction
+    Continue = 0,
+    /// Abort the execution with [EbpfError::TraceStopped]
+    Stop = 1,
 }
 
 /// Statistic of taken branches (from a recorded trace)
@@ -174,7 +479,10 @@ impl DynamicAnalysis {
 }
 
 /// A call frame used for function calls inside the Interpreter
-#[derive(Clone, Default)]
+///
+/// The JIT fills in the same fields, at fixed offsets.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
 pub struct CallFrame {
     /// The caller saved registers
     pub caller_saved_registers: [u64; ebpf::SCRATCH_REGS],
@@ -184,6 +492,123 @@ pub struct CallFrame {
     pub target_pc: u64,
 }
 
+/// Builds [EbpfError::CallDepthExceeded] for the call at `pc` which pushed the last of
+/// `call_frames`
+#[cfg(all(target_pointer_width = "64", target_endian = "little"))]
+pub(crate) fn call_depth_exceeded(pc: u64, call_frames: &[CallFrame]) -> EbpfError {
+    EbpfError::CallDepthExceeded {
+        pc: pc as usize,
+        return_addresses: call_frames.iter().map(|frame| frame.target_pc).collect(),
+    }
+}
+
+/// State of an interpreted execution which ran out of instruction meter, see
+/// [EbpfVm::execute_program_resumable]
+///
+/// The memory is not part of it, it stays in the [MemoryMapping] of the VM.
+#[derive(Clone, Debug)]
+pub struct SuspendedExecution {
+    /// General purpose registers and the pc of the next instruction
+    pub registers: [u64; 12],
+    /// Active call frames, the innermost last
+    pub call_frames: Vec<CallFrame>,
+    /// Instructions consumed from the meter until the suspension
+    pub instruction_count: u64,
+}
+
+/// Outcome of [EbpfVm::execute_program_resumable] and [EbpfVm::resume]
+#[derive(Debug)]
+pub enum ResumableResult {
+    /// The program terminated, with the instructions consumed over all resumptions
+    Completed(u64, ProgramResult),
+    /// The instruction meter ran out, continue with [EbpfVm::resume] after refilling it
+    Suspended(SuspendedExecution),
+}
+
+/// Set of internal functions which must not be called, consulted on every BPF to BPF call
+///
+/// Calls to a banned target pc fail with [EbpfError::CallFiltered] instead of entering the
+/// function. Programs which never call a banned function are not affected.
+///
+/// The banned pcs are kept in a bitmap with one bit per instruction of the program, which the
+/// interpreter and the JIT index by the target pc of the call. A bitmap indexed by function
+/// ordinal would be smaller, but `callx` targets are only known as pcs at runtime. Mapping them
+/// to ordinals needs a table with an entry per instruction and a second dependent load on every
+/// call, which costs more than the bit per instruction it saves.
+#[derive(Debug)]
+#[repr(C)]
+pub struct CallFilter {
+    /// Points to `bitmap`, read by the JIT. Must stay the first field.
+    bitmap_ptr: *const u64,
+    /// One bit per instruction of the program, set if the pc is a banned call target
+    bitmap: Vec<u64>,
+    /// Number of instructions covered by `bitmap`
+    instruction_count: usize,
+    /// Banned target pcs and their function names, sorted by pc
+    banned: Vec<(usize, Vec<u8>)>,
+}
+
+impl CallFilter {
+    /// Creates a call filter for `executable` banning calls to `target_pcs`
+    pub fn new<C: ContextObject>(
+        executable: &Executable<C>,
+        target_pcs: impl IntoIterator<Item = usize>,
+    ) -> Result<Self, EbpfError> {
+        let (_, text_bytes) = executable.get_text_bytes();
+        let instruction_count = text_bytes.len() / ebpf::INSN_SIZE;
+        // The JIT tests the bits with 64 bit accesses, so the bitmap is made of whole words
+        let mut bitmap = vec![0u64; instruction_count.div_ceil(64)];
+        let mut banned = Vec::new();
+        for target_pc in target_pcs {
+            if target_pc >= instruction_count {
+                return Err(EbpfError::CallOutsideTextSegment);
+            }
+            bitmap[target_pc / 64] |= 1 << (target_pc % 64);
+            let name = executable
+                .get_function_registry()
+                .iter()
+                .find(|(_, (_, pc))| *pc == target_pc)
+                .map(|(_, (name, _))| name.to_vec())
+                .unwrap_or_default();
+            banned.push((target_pc, name));
+        }
+        banned.sort_unstable();
+        banned.dedup_by_key(|(target_pc, _)| *target_pc);
+        Ok(Self {
+            bitmap_ptr: bitmap.as_ptr(),
+            bitmap,
+            instruction_count,
+            banned,
+        })
+    }
+
+    /// Returns true if calls to `target_pc` are banned
+    pub fn is_banned(&self, target_pc: usize) -> bool {
+        target_pc < self.instruction_count
+            && self.bitmap[target_pc / 64] & (1 << (target_pc % 64)) != 0
+    }
+
+    /// Iterates over the banned target pcs in ascending order
+    pub fn banned_pcs(&self) -> impl Iterator<Item = usize> + '_ {
+        self.banned.iter().map(|(target_pc, _)| *target_pc)
+    }
+
+    /// Returns the name of the banned function at `target_pc`
+    ///
+    /// The name is empty if the executable was loaded without symbol labels.
+    pub fn function_name(&self, target_pc: usize) -> Option<&[u8]> {
+        self.banned
+            .binary_search_by_key(&target_pc, |(pc, _)| *pc)
+            .ok()
+            .map(|index| self.banned[index].1.as_slice())
+    }
+
+    /// Number of instructions covered by this filter
+    pub fn instruction_count(&self) -> usize {
+        self.instruction_count
+    }
+}
+
 /// Indices of slots inside [EbpfVm]
 pub enum RuntimeEnvironmentSlot {
     /// [EbpfVm::host_stack_pointer]
@@ -204,8 +629,18 @@ pub enum RuntimeEnvironmentSlot {
     Registers = 7,
     /// [EbpfVm::program_result]
     ProgramResult = 19,
+    /// [EbpfVm::call_filter]
+    CallFilter = 27,
+    /// [EbpfVm::call_frames_pointer]
+    CallFramesPointer = 28,
+    /// [EbpfVm::max_observed_call_depth]
+    MaxObservedCallDepth = 29,
+    /// [EbpfVm::lowest_stack_pointer]
+    LowestStackPointer = 30,
+    /// [EbpfVm::interpreter_fallback]
+    InterpreterFallback = 31,
     /// [EbpfVm::memory_mapping]
-    MemoryMapping = 27,
+    MemoryMapping = 32,
 }
 
 /// A virtual machine to run eBPF programs.
@@ -254,11 +689,21 @@ pub enum RuntimeEnvironmentSlot {
 ///
 /// let memory_mapping = MemoryMapping::new(regions, executable.get_config(), sbpf_version).unwrap();
 ///
-/// let mut vm = EbpfVm::new(loader, sbpf_version, &mut context_object, memory_mapping, stack_len);
+/// let mut vm = EbpfVm::try_new(
+///     loader,
+///     sbpf_version,
+///     &mut context_object,
+///     memory_mapping,
+///     stack_len,
+/// )
+/// .unwrap();
 ///
+/// # #[cfg(all(target_pointer_width = "64", target_endian = "little"))]
+/// # {
 /// let (instruction_count, result) = vm.execute_program(&executable, true);
 /// assert_eq!(instruction_count, 1);
 /// assert_eq!(result.unwrap(), 0);
+/// # }
 /// ```
 #[repr(C)]
 pub struct EbpfVm<'a, C: ContextObject> {
@@ -280,15 +725,35 @@ pub struct EbpfVm<'a, C: ContextObject> {
     /// Number of times the stop watch was used
     pub stopwatch_denominator: u64,
     /// Registers inlined
+    ///
+    /// Hold the registers at the fault once an execution failed, see [EbpfVm::registers()].
     pub registers: [u64; 12],
     /// ProgramResult inlined
     pub program_result: ProgramResult,
+    /// Call filter consulted by BPF to BPF calls
+    pub call_filter: Option<&'a CallFilter>,
+    /// Start of [EbpfVm::call_frames], for the JIT to record its frames
+    pub call_frames_pointer: *mut CallFrame,
+    /// Deepest call depth of the current execution, see [EbpfVm::max_observed_call_depth()]
+    pub max_observed_call_depth: u64,
+    /// Lowest frame pointer of the current execution, see [EbpfVm::peak_stack_usage()]
+    pub lowest_stack_pointer: u64,
+    /// Non-zero if the JIT ran out of instruction meter within a block, which the interpreter
+    /// finishes then, starting at the pc in [EbpfVm::registers]
+    pub interpreter_fallback: u64,
     /// MemoryMapping inlined
     pub memory_mapping: MemoryMapping<'a>,
-    /// Stack of CallFrames used by the Interpreter
+    /// Stack of CallFrames used by the Interpreter and recorded by the JIT
     pub call_frames: Vec<CallFrame>,
+    /// Registers every execution starts with, besides r1 and the pc
+    pub initial_registers: [u64; 12],
     /// Loader built-in program
     pub loader: Arc<BuiltinProgram<C>>,
+    /// Collects every syscall made by builtins of [crate::declare_builtin_function], see
+    /// [crate::syscall_replay]
+    pub syscall_recorder: Option<&'a mut Vec<SyscallRecord>>,
+    /// Memory accesses of the current interpreted execution, see [EbpfVm::memory_access_stats()]
+    pub(crate) memory_access_stats: Option<MemoryAccessStats>,
     /// TCP port for the debugger interface
     #[cfg(feature = "debugger")]
     pub debug_port: Option<u16>,
@@ -296,6 +761,10 @@ pub struct EbpfVm<'a, C: ContextObject> {
 
 impl<'a, C: ContextObject> EbpfVm<'a, C> {
     /// Creates a new virtual machine instance.
+    ///
+    /// The [Config] of the `loader` must pass [Config::validate], which is only asserted in
+    /// debug builds. Executables only load with a valid one, use [EbpfVm::try_new] with a
+    /// loader which did not load the executable.
     pub fn new(
         loader: Arc<BuiltinProgram<C>>,
         sbpf_version: SBPFVersion,
@@ -304,9 +773,10 @@ impl<'a, C: ContextObject> EbpfVm<'a, C> {
         stack_len: usize,
     ) -> Self {
         let config = loader.get_config();
+        debug_assert_eq!(config.validate(), Ok(()));
         let mut registers = [0u64; 12];
         registers[ebpf::FRAME_PTR_REG] =
-            ebpf::MM_STACK_START.saturating_add(if sbpf_version.dynamic_stack_frames() {
+            config.memory_layout.stack_start().saturating_add(if sbpf_version.dynamic_stack_frames() {
                 // the stack is fully descending, frames start as empty and change size anytime r11 is modified
                 stack_len
             } else {
@@ -326,42 +796,111 @@ impl<'a, C: ContextObject> EbpfVm<'a, C> {
             stopwatch_denominator: 0,
             registers,
             program_result: ProgramResult::Ok(0),
+            call_filter: None,
+            call_frames_pointer: core::ptr::null_mut(),
+            max_observed_call_depth: 0,
+            lowest_stack_pointer: registers[ebpf::FRAME_PTR_REG],
+            interpreter_fallback: 0,
             memory_mapping,
             call_frames: vec![CallFrame::default(); config.max_call_depth],
+            initial_registers: registers,
             loader,
+            syscall_recorder: None,
+            memory_access_stats: None,
             #[cfg(feature = "debugger")]
             debug_port: None,
         }
     }
 
+    /// Creates a new virtual machine instance, if the [Config] of the `loader` passes
+    /// [Config::validate]
+    pub fn try_new(
+        loader: Arc<BuiltinProgram<C>>,
+        sbpf_version: SBPFVersion,
+        context_object: &'a mut C,
+        memory_mapping: MemoryMapping<'a>,
+        stack_len: usize,
+    ) -> Result<Self, ConfigError> {
+        loader.get_config().validate()?;
+        Ok(Self::new(
+            loader,
+            sbpf_version,
+            context_object,
+            memory_mapping,
+            stack_len,
+        ))
+    }
+
+    /// Creates a new virtual machine instance with the usual memory regions
+    ///
+    /// These are the read-only data of the `executable`, the `stack`, the heap
+    /// region of the `allocator` (or an empty one) and the `additional_regions`.
+    pub fn new_with_regions(
+        executable: &'a Executable<C>,
+        context_object: &'a mut C,
+        stack: &mut AlignedMemory<{ ebpf::HOST_ALIGN }>,
+        allocator: Option<&mut BumpAllocator>,
+        additional_regions: Vec<MemoryRegion>,
+    ) -> Result<Self, EbpfError> {
+        let config = executable.get_config();
+        let sbpf_version = executable.get_sbpf_version();
+        let stack_len = stack.len();
+        let heap_region = match allocator {
+            Some(allocator) => allocator.region(),
+            None => MemoryRegion::new_writable(&mut [], config.memory_layout.heap_start())
+                .with_kind(MemoryRegionKind::Heap),
+        };
+        let regions = vec![
+            executable.get_ro_region(),
+            MemoryRegion::new_writable_gapped(
+                stack.as_slice_mut(),
+                config.memory_layout.stack_start(),
+                if !sbpf_version.dynamic_stack_frames() && config.enable_stack_frame_gaps {
+                    config.stack_frame_size as u64
+                } else {
+                    0
+                },
+            )
+            .with_kind(MemoryRegionKind::Stack),
+            heap_region,
+        ]
+        .into_iter()
+        .chain(additional_regions)
+        .collect();
+        let memory_mapping = MemoryMapping::new(regions, config, sbpf_version)?;
+        Ok(Self::try_new(
+            executable.get_loader().clone(),
+            sbpf_version,
+            context_object,
+            memory_mapping,
+            stack_len,
+        )?)
+    }
+
     /// Execute the program
     ///
     /// If interpreted = `false` then the JIT compiled executable is used.
+    #[cfg(all(target_pointer_width = "64", target_endian = "little"))]
     pub fn execute_program(
         &mut self,
         executable: &Executable<C>,
         interpreted: bool,
     ) -> (u64, ProgramResult) {
-        debug_assert!(Arc::ptr_eq(&self.loader, executable.get_loader()));
-        self.registers[1] = ebpf::MM_INPUT_START;
-        self.registers[11] = executable.get_entrypoint_instruction_offset() as u64;
+        let initial_insn_count = self.prepare_execution(executable);
         let config = executable.get_config();
-        let initial_insn_count = self.context_object_pointer.get_remaining();
-        self.previous_instruction_meter = initial_insn_count;
-        self.due_insn_count = 0;
-        self.program_result = ProgramResult::Ok(0);
         if interpreted {
             #[cfg(feature = "debugger")]
             let debug_port = self.debug_port.clone();
-            let mut interpreter = Interpreter::new(self, executable, self.registers);
+            let mut interpreter = self.enter_interpreter(executable);
             #[cfg(feature = "debugger")]
             if let Some(debug_port) = debug_port {
                 crate::debugger::execute(&mut interpreter, debug_port);
             } else {
-                while interpreter.step() {}
+                while interpreter.advance() {}
             }
             #[cfg(not(feature = "debugger"))]
-            while interpreter.step() {}
+            while interpreter.advance() {}
+            interpreter.emit_error_event();
         } else {
             #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
             {
@@ -373,6 +912,15 @@ impl<'a, C: ContextObject> EbpfVm<'a, C> {
                     Err(error) => return (0, ProgramResult::Err(error)),
                 };
                 compiled_program.invoke(config, self, self.registers);
+                if self.interpreter_fallback != 0 {
+                    // Only the interpreter can stop in the middle of a block
+                    self.interpreter_fallback = 0;
+                    self.program_result = ProgramResult::Ok(0);
+                    let registers = self.registers;
+                    let mut interpreter = Interpreter::new(self, executable, registers);
+                    while interpreter.advance() {}
+                    interpreter.emit_error_event();
+                }
             }
             #[cfg(not(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64")))]
             {
@@ -390,7 +938,233 @@ impl<'a, C: ContextObject> EbpfVm<'a, C> {
         (instruction_count, result)
     }
 
+    /// Interprets the program like [EbpfVm::execute_program], but suspends it instead of
+    /// failing with [EbpfError::ExceededMaxInstructions] when the instruction meter runs out
+    ///
+    /// Only the interpreter can suspend programs. Syscalls which fail because of the
+    /// instruction meter still terminate the program.
+    #[cfg(all(target_pointer_width = "64", target_endian = "little"))]
+    pub fn execute_program_resumable(&mut self, executable: &Executable<C>) -> ResumableResult {
+        self.prepare_execution(executable);
+        let interpreter = self.enter_interpreter(executable);
+        Self::run_resumable(interpreter, 0)
+    }
+
+    /// Continues a program suspended by [EbpfVm::execute_program_resumable] or a previous
+    /// [EbpfVm::resume]
+    ///
+    /// The VM must be the one the program was suspended in, with the instruction meter of its
+    /// context object refilled. More call frames than [Config::max_call_depth] allows complete
+    /// with [EbpfError::CallDepthExceeded].
+    #[cfg(all(target_pointer_width = "64", target_endian = "little"))]
+    pub fn resume(
+        &mut self,
+        executable: &Executable<C>,
+        suspended: SuspendedExecution,
+    ) -> ResumableResult {
+        debug_assert!(Arc::ptr_eq(&self.loader, executable.get_loader()));
+        if suspended.call_frames.len() > self.call_frames.len() {
+            return ResumableResult::Completed(
+                suspended.instruction_count,
+                ProgramResult::Err(call_depth_exceeded(
+                    suspended.registers[11],
+                    &suspended.call_frames,
+                )),
+            );
+        }
+        self.previous_instruction_meter = self.context_object_pointer.get_remaining();
+        self.due_insn_count = 0;
+        self.program_result = ProgramResult::Ok(0);
+        self.call_depth = suspended.call_frames.len() as u64;
+        self.call_frames[..suspended.call_frames.len()].clone_from_slice(&suspended.call_frames);
+        let interpreter = Interpreter::new(self, executable, suspended.registers);
+        Self::run_resumable(interpreter, suspended.instruction_count)
+    }
+
+    #[cfg(all(target_pointer_width = "64", target_endian = "little"))]
+    fn run_resumable(
+        mut interpreter: Interpreter<'_, 'a, C>,
+        instruction_count: u64,
+    ) -> ResumableResult {
+        let initial_insn_count = interpreter.vm.previous_instruction_meter;
+        while interpreter.advance() {}
+        let suspend = interpreter.meter_exhausted;
+        if !suspend {
+            interpreter.emit_error_event();
+        }
+        let registers = interpreter.reg;
+        let vm = interpreter.vm;
+        let instruction_count = if vm.loader.get_config().enable_instruction_meter {
+            vm.context_object_pointer.consume(vm.due_insn_count);
+            vm.due_insn_count = 0;
+            instruction_count.saturating_add(
+                initial_insn_count.saturating_sub(vm.context_object_pointer.get_remaining()),
+            )
+        } else {
+            0
+        };
+        let mut result = ProgramResult::Ok(0);
+        core::mem::swap(&mut result, &mut vm.program_result);
+        if suspend {
+            ResumableResult::Suspended(SuspendedExecution {
+                registers,
+                call_frames: vm.call_frames[..vm.call_depth as usize].to_vec(),
+                instruction_count,
+            })
+        } else {
+            ResumableResult::Completed(instruction_count, result)
+        }
+    }
+
+    /// Registers r0 to r10 and the pc at the fault of the last failed execution
+    ///
+    /// Both, the interpreter and the JIT, record them when an execution fails. After an
+    /// execution which succeeded they are unspecified.
+    pub fn registers(&self) -> &[u64; 12] {
+        &self.registers
+    }
+
+    /// Frames of the BPF to BPF calls which were active at the fault of the last failed
+    /// execution, the outermost first
+    ///
+    /// [CallFrame::target_pc] is the return address of a frame, [Executable::function_containing_pc]
+    /// and [Executable::get_function_registry] resolve it to the name of the caller.
+    pub fn call_frames(&self) -> &[CallFrame] {
+        &self.call_frames[..(self.call_depth as usize).min(self.call_frames.len())]
+    }
+
+    /// Deepest BPF to BPF call depth the last execution reached
+    ///
+    /// The entrypoint runs at depth zero. A call which fails with
+    /// [EbpfError::CallDepthExceeded] counts as reaching [Config::max_call_depth].
+    pub fn max_observed_call_depth(&self) -> u64 {
+        self.max_observed_call_depth
+    }
+
+    /// Bytes the frame pointer descended below the one the last execution started with, at most
+    ///
+    /// Only SBPF versions with dynamic stack frames move the frame pointer inside of a frame,
+    /// with fixed stack frames this is zero.
+    pub fn peak_stack_usage(&self) -> u64 {
+        self.initial_registers[ebpf::FRAME_PTR_REG].saturating_sub(self.lowest_stack_pointer)
+    }
+
+    /// Memory accesses of the load and store instructions of the last execution
+    ///
+    /// Only collected by the interpreter and only if [Config::enable_memory_access_stats] is
+    /// set, `None` otherwise.
+    pub fn memory_access_stats(&self) -> Option<&MemoryAccessStats> {
+        self.memory_access_stats.as_ref()
+    }
+
+    /// Prepares the program like [EbpfVm::execute_program] and returns an interpreter to
+    /// execute it one instruction at a time with [Interpreter::step]
+    ///
+    /// The instruction meter is charged the same way as by an interpreted
+    /// [EbpfVm::execute_program], once [Interpreter::step] stops returning
+    /// [StepResult::Continue](crate::interpreter::StepResult::Continue).
+    #[cfg(all(target_pointer_width = "64", target_endian = "little"))]
+    pub fn step_program<'c>(&'c mut self, executable: &'c Executable<C>) -> Interpreter<'c, 'a, C> {
+        self.prepare_execution(executable);
+        self.enter_interpreter(executable)
+    }
+
+    /// Resets the execution state and returns the remaining instruction meter
+    #[cfg(all(target_pointer_width = "64", target_endian = "little"))]
+    fn prepare_execution(&mut self, executable: &Executable<C>) -> u64 {
+        debug_assert!(Arc::ptr_eq(&self.loader, executable.get_loader()));
+        if let Some(call_filter) = self.call_filter {
+            // The JIT indexes the bitmap by target pc without a bounds check
+            assert_eq!(
+                call_filter.instruction_count(),
+                executable.get_text_bytes().1.len() / ebpf::INSN_SIZE,
+                "call filter was created for a different executable",
+            );
+        }
+        let config = executable.get_config();
+        // The JIT records call frames without a bounds check
+        assert!(
+            self.call_frames.len() >= config.max_call_depth,
+            "call frames are fewer than the max call depth",
+        );
+        self.call_depth = 0;
+        self.call_frames_pointer = self.call_frames.as_mut_ptr();
+        self.max_observed_call_depth = 0;
+        self.lowest_stack_pointer = self.initial_registers[ebpf::FRAME_PTR_REG];
+        self.interpreter_fallback = 0;
+        self.memory_access_stats = None;
+        self.registers = self.initial_registers;
+        self.registers[1] = config.memory_layout.input_start();
+        self.registers[11] = executable.get_entrypoint_instruction_offset() as u64;
+        let initial_insn_count = self.context_object_pointer.get_remaining();
+        self.previous_instruction_meter = initial_insn_count;
+        self.due_insn_count = 0;
+        self.program_result = ProgramResult::Ok(0);
+        initial_insn_count
+    }
+
+    #[cfg(all(target_pointer_width = "64", target_endian = "little"))]
+    fn enter_interpreter<'c>(
+        &'c mut self,
+        executable: &'c Executable<C>,
+    ) -> Interpreter<'c, 'a, C> {
+        let entrypoint_pc = self.registers[11] as usize;
+        if let Some(events) = self.context_object_pointer.execution_events() {
+            events.event(
+                0,
+                ExecutionEvent::FrameEnter {
+                    target_pc: entrypoint_pc,
+                    depth: 0,
+                },
+            );
+        }
+        if executable.get_config().enable_memory_access_stats {
+            self.memory_access_stats = Some(MemoryAccessStats::default());
+        }
+        let registers = self.registers;
+        Interpreter::new(self, executable, registers)
+    }
+
+    /// Appends a [SyscallRecord] to [EbpfVm::syscall_recorder], called by the builtins of
+    /// [crate::declare_builtin_function] once they returned
+    ///
+    /// `remaining` is the instruction meter before the builtin ran.
+    pub fn record_syscall(
+        &mut self,
+        function: BuiltinFunction<C>,
+        arguments: [u64; 5],
+        remaining: u64,
+    ) {
+        let (key, name) = self
+            .loader
+            .get_function_registry()
+            .iter()
+            .find(|(_key, (_name, registered))| *registered as usize == function as usize)
+            .map(|(key, (name, _function))| (key, name.to_vec()))
+            .unwrap_or_default();
+        let consumed_units = if self.loader.get_config().enable_instruction_meter {
+            remaining.saturating_sub(self.context_object_pointer.get_remaining())
+        } else {
+            0
+        };
+        let result = match &self.program_result {
+            ProgramResult::Ok(value) => Ok(*value),
+            ProgramResult::Err(EbpfError::SyscallError(err)) => Err(err.to_string()),
+            ProgramResult::Err(err) => Err(err.to_string()),
+        };
+        if let Some(recorder) = self.syscall_recorder.as_mut() {
+            recorder.push(SyscallRecord {
+                key,
+                name,
+                arguments,
+                result,
+                consumed_units,
+            });
+        }
+    }
+
     /// Invokes a built-in function
+    #[cfg(all(target_pointer_width = "64", target_endian = "little"))]
     pub fn invoke_function(&mut self, function: BuiltinFunction<C>) {
         function(
             unsafe {
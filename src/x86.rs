@@ -412,6 +412,28 @@ impl X86Instruction {
         }
     }
 
+    /// Test the bit of destination at the offset in source, sets the carry flag to the bit
+    ///
+    /// With an indirect destination the offset is not limited to the operand size, so it
+    /// addresses a bit string starting at the indirect address.
+    pub const fn bit_test(
+        size: OperandSize,
+        source: X86Register,
+        destination: X86Register,
+        indirect: Option<X86IndirectAccess>,
+    ) -> Self {
+        exclude_operand_sizes!(size, OperandSize::S0 | OperandSize::S8);
+        Self {
+            size,
+            opcode_escape_sequence: 1,
+            opcode: 0xa3,
+            first_operand: source as u8,
+            second_operand: destination as u8,
+            indirect,
+            ..Self::DEFAULT
+        }
+    }
+
     /// Compare source and destination
     pub const fn cmp(
         size: OperandSize,
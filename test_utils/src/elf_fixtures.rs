@@ -0,0 +1,439 @@
+#![allow(clippy::arithmetic_side_effects)]
+// Licensed under the Apache License, Version 2.0 <http://www.apache.org/licenses/LICENSE-2.0> or
+// the MIT license <http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Synthetic ELF files for exercising the loader at scales the toolchain fixtures don't reach.
+
+use solana_sbpf::{
+    ebpf,
+    elf_parser::consts::{
+        DT_GNU_HASH, DT_REL, DT_RELENT, DT_RELSZ, DT_STRSZ, DT_STRTAB, DT_SYMENT, DT_SYMTAB,
+        ELFCLASS64, ELFDATA2LSB, ELFMAG, EM_BPF, ET_DYN, EV_CURRENT, PT_DYNAMIC, PT_LOAD,
+        R_X86_64_32, SHF_ALLOC, SHF_EXECINSTR, SHF_WRITE, SHT_DYNAMIC, SHT_DYNSYM, SHT_GNU_HASH,
+        SHT_PROGBITS, SHT_REL, SHT_STRTAB, STT_FUNC,
+    },
+    elf_parser::gnu_hash,
+};
+
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+const SHDR_SIZE: usize = 64;
+const SYM_SIZE: usize = 24;
+const REL_SIZE: usize = 16;
+const DYN_SIZE: usize = 16;
+const STB_GLOBAL: u8 = 1;
+
+/// Layout of a file produced by [`create_elf_with_call_relocations`]
+#[derive(Debug, Clone)]
+pub struct CallRelocationsElf {
+    /// The file
+    pub elf_bytes: Vec<u8>,
+    /// Virtual address (and file offset) of `.text`
+    pub text_addr: u64,
+    /// Number of call instructions starting at pc 0, each with an `R_BPF_64_32` relocation
+    pub calls: usize,
+    /// Pc of each internal function `function_{i}`
+    pub function_pcs: Vec<usize>,
+    /// Dynamic symbol called by each call instruction, as `(name, Some(pc))` for internal
+    /// functions and `(name, None)` for syscalls
+    pub call_targets: Vec<(Vec<u8>, Option<usize>)>,
+}
+
+fn push_u16(bytes: &mut Vec<u8>, value: u16) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_u64(bytes: &mut Vec<u8>, value: u64) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+fn align(bytes: &mut Vec<u8>) {
+    bytes.resize((bytes.len() + 7) & !7, 0);
+}
+
+/// Builds an SBPFv0 shared object whose `.text` starts with `calls` call instructions.
+///
+/// Call `i` is relocated against `function_{i % functions}` for the first `functions` call
+/// instructions out of every `functions + syscalls.len()`, and against the syscalls after that.
+/// Every internal function consists of a single `exit`.
+pub fn create_elf_with_call_relocations(
+    calls: usize,
+    functions: usize,
+    syscalls: &[&str],
+) -> CallRelocationsElf {
+    create_elf(calls, functions, syscalls, false, None, &[])
+}
+
+/// Same as [`create_elf_with_call_relocations`], with a `.gnu.hash` table of the functions.
+///
+/// The syscalls come first in `.dynsym` and are not hashed, like undefined symbols in files
+/// produced by the linker.
+pub fn create_elf_with_gnu_hash(
+    calls: usize,
+    functions: usize,
+    syscalls: &[&str],
+) -> CallRelocationsElf {
+    create_elf(calls, functions, syscalls, true, None, &[])
+}
+
+/// Same as [`create_elf_with_call_relocations`] with one call to one function, plus an empty
+/// segment of `p_type` and `p_flags` after the others, like the `PT_TLS` or `PT_GNU_STACK`
+/// segments some link flags produce.
+pub fn create_elf_with_segment(p_type: u32, p_flags: u32) -> CallRelocationsElf {
+    create_elf(1, 1, &[], false, Some((p_type, p_flags)), &[])
+}
+
+/// Same as [`create_elf_with_call_relocations`] with `functions` functions, plus an
+/// allocated `.eh_frame` section holding `eh_frame`.
+///
+/// The section comes right before `.shstrtab`, so its address is not known up front. Tests
+/// resolving pc-relative pointers look it up in the section header table.
+pub fn create_elf_with_eh_frame(functions: usize, eh_frame: &[u8]) -> CallRelocationsElf {
+    create_elf_with_sections(
+        functions,
+        &[(".eh_frame", SHT_PROGBITS, SHF_ALLOC, eh_frame)],
+    )
+}
+
+/// Same as [`create_elf_with_call_relocations`] with `functions` functions, plus a section
+/// for each name, `sh_type`, `sh_flags` and contents in `sections`.
+///
+/// The sections come in order right before `.shstrtab`. Sections without `SHF_ALLOC` have
+/// an address of 0, like the debug sections.
+pub fn create_elf_with_sections(
+    functions: usize,
+    sections: &[(&str, u32, u64, &[u8])],
+) -> CallRelocationsElf {
+    create_elf(functions, functions, &[], false, None, sections)
+}
+
+/// Builds the `.gnu.hash` table of `hashes`, which are in `.dynsym` order starting at
+/// `symbol_offset`, and sorted by bucket.
+fn gnu_hash_table(hashes: &[u32], symbol_offset: usize, bucket_count: usize) -> Vec<u8> {
+    const BLOOM_SHIFT: u32 = 6;
+    let bloom_size = (hashes.len() / 64 + 1).next_power_of_two();
+    let mut bloom = vec![0u64; bloom_size];
+    let mut buckets = vec![0u32; bucket_count];
+    let mut chains = Vec::with_capacity(hashes.len());
+    for (i, hash) in hashes.iter().enumerate() {
+        bloom[(*hash as usize / 64) % bloom_size] |=
+            1 << (hash % 64) | 1 << ((hash >> BLOOM_SHIFT) % 64);
+        let bucket = *hash as usize % bucket_count;
+        if buckets[bucket] == 0 {
+            buckets[bucket] = (symbol_offset + i) as u32;
+        }
+        let last_in_chain = hashes
+            .get(i + 1)
+            .map_or(true, |next| *next as usize % bucket_count != bucket);
+        chains.push(hash & !1 | last_in_chain as u32);
+    }
+
+    let mut table = Vec::new();
+    push_u32(&mut table, bucket_count as u32);
+    push_u32(&mut table, symbol_offset as u32);
+    push_u32(&mut table, bloom_size as u32);
+    push_u32(&mut table, BLOOM_SHIFT);
+    for word in bloom {
+        push_u64(&mut table, word);
+    }
+    for bucket in buckets {
+        push_u32(&mut table, bucket);
+    }
+    for chain in chains {
+        push_u32(&mut table, chain);
+    }
+    table
+}
+
+fn create_elf(
+    calls: usize,
+    functions: usize,
+    syscalls: &[&str],
+    with_gnu_hash: bool,
+    extra_segment: Option<(u32, u32)>,
+    extra_sections: &[(&str, u32, u64, &[u8])],
+) -> CallRelocationsElf {
+    let symbols = functions + syscalls.len();
+    assert!(symbols > 0);
+
+    let program_header_count = 3 + extra_segment.iter().count();
+    let text_addr = (EHDR_SIZE + program_header_count * PHDR_SIZE + 7) & !7;
+    let function_pcs = (0..functions).map(|i| calls + 1 + i).collect::<Vec<_>>();
+    let mut text = Vec::new();
+    for _ in 0..calls {
+        text.extend_from_slice(&[ebpf::CALL_IMM, 0, 0, 0, 0xff, 0xff, 0xff, 0xff]);
+    }
+    for _ in 0..=functions {
+        text.extend_from_slice(&[ebpf::EXIT, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    // The hash table requires the hashed symbols to be grouped by bucket, so symbol `i` of the
+    // calls ends up at `dynsym_index[i]` in `.dynsym`
+    let bucket_count = (functions / 4).max(1);
+    let mut order = (0..symbols).collect::<Vec<_>>();
+    if with_gnu_hash {
+        order.sort_by_key(|i| {
+            if *i < functions {
+                let hash = gnu_hash(format!("function_{i}").as_bytes());
+                (1, hash as usize % bucket_count)
+            } else {
+                (0, 0)
+            }
+        });
+    }
+    let mut dynsym_index = vec![0; symbols];
+    for (index, i) in order.iter().enumerate() {
+        dynsym_index[*i] = index + 1;
+    }
+
+    let mut dynstr = vec![0u8];
+    let mut dynsym = vec![0u8; SYM_SIZE];
+    let mut names = vec![Vec::new(); symbols];
+    let mut hashes = Vec::new();
+    for i in order.iter().copied() {
+        let (name, st_info, st_shndx, st_value) = if i < functions {
+            (
+                format!("function_{i}").into_bytes(),
+                STB_GLOBAL << 4 | STT_FUNC,
+                1,
+                (text_addr + function_pcs[i] * ebpf::INSN_SIZE) as u64,
+            )
+        } else {
+            (
+                syscalls[i - functions].as_bytes().to_vec(),
+                STB_GLOBAL << 4,
+                0,
+                0,
+            )
+        };
+        push_u32(&mut dynsym, dynstr.len() as u32);
+        dynsym.push(st_info);
+        dynsym.push(0);
+        push_u16(&mut dynsym, st_shndx);
+        push_u64(&mut dynsym, st_value);
+        push_u64(&mut dynsym, 0);
+        dynstr.extend_from_slice(&name);
+        dynstr.push(0);
+        if i < functions {
+            hashes.push(gnu_hash(&name));
+        }
+        names[i] = name;
+    }
+    let gnu_hash = if with_gnu_hash {
+        gnu_hash_table(&hashes, 1 + syscalls.len(), bucket_count)
+    } else {
+        Vec::new()
+    };
+
+    let mut rel_dyn = Vec::with_capacity(calls * REL_SIZE);
+    let mut call_targets = Vec::with_capacity(calls);
+    for i in 0..calls {
+        let symbol = i % symbols;
+        push_u64(&mut rel_dyn, (text_addr + i * ebpf::INSN_SIZE) as u64);
+        push_u64(
+            &mut rel_dyn,
+            ((dynsym_index[symbol] as u64) << 32) | R_X86_64_32 as u64,
+        );
+        call_targets.push((names[symbol].clone(), function_pcs.get(symbol).copied()));
+    }
+
+    let mut shstrtab =
+        b"\0.text\0.dynamic\0.dynsym\0.dynstr\0.rel.dyn\0.gnu.hash\0.shstrtab\0".to_vec();
+    let mut extra_section_names = Vec::with_capacity(extra_sections.len());
+    for (name, _, _, _) in extra_sections {
+        extra_section_names.push(shstrtab.len() as u32);
+        shstrtab.extend_from_slice(name.as_bytes());
+        shstrtab.push(0);
+    }
+    let section_count = 7 + with_gnu_hash as usize + extra_sections.len();
+
+    // Lay out the sections
+    let dynamic_addr = text_addr + text.len();
+    let dynamic_len = 9 * DYN_SIZE;
+    let dynsym_addr = dynamic_addr + dynamic_len;
+    let dynstr_addr = dynsym_addr + dynsym.len();
+    let rel_dyn_addr = (dynstr_addr + dynstr.len() + 7) & !7;
+    let gnu_hash_addr = rel_dyn_addr + rel_dyn.len();
+    let mut extra_section_addrs = Vec::with_capacity(extra_sections.len());
+    let mut shstrtab_offset = gnu_hash_addr + gnu_hash.len();
+    for (_, _, _, bytes) in extra_sections {
+        extra_section_addrs.push(shstrtab_offset);
+        shstrtab_offset += bytes.len();
+    }
+    let shdr_offset = (shstrtab_offset + shstrtab.len() + 7) & !7;
+
+    let mut dynamic = Vec::with_capacity(dynamic_len);
+    for (tag, value) in [
+        (DT_REL, rel_dyn_addr as u64),
+        (DT_RELSZ, rel_dyn.len() as u64),
+        (DT_RELENT, REL_SIZE as u64),
+        (DT_SYMTAB, dynsym_addr as u64),
+        (DT_SYMENT, SYM_SIZE as u64),
+        (DT_STRTAB, dynstr_addr as u64),
+        (DT_STRSZ, dynstr.len() as u64),
+        if with_gnu_hash {
+            (DT_GNU_HASH, gnu_hash_addr as u64)
+        } else {
+            (0, 0)
+        },
+        (0, 0),
+    ] {
+        push_u64(&mut dynamic, tag);
+        push_u64(&mut dynamic, value);
+    }
+
+    let mut elf_bytes = Vec::with_capacity(shdr_offset + section_count * SHDR_SIZE);
+    // File header
+    elf_bytes.extend_from_slice(&ELFMAG);
+    elf_bytes.extend_from_slice(&[ELFCLASS64, ELFDATA2LSB, EV_CURRENT as u8]);
+    elf_bytes.resize(16, 0);
+    push_u16(&mut elf_bytes, ET_DYN);
+    push_u16(&mut elf_bytes, EM_BPF);
+    push_u32(&mut elf_bytes, EV_CURRENT);
+    push_u64(&mut elf_bytes, text_addr as u64);
+    push_u64(&mut elf_bytes, EHDR_SIZE as u64);
+    push_u64(&mut elf_bytes, shdr_offset as u64);
+    push_u32(&mut elf_bytes, 0);
+    push_u16(&mut elf_bytes, EHDR_SIZE as u16);
+    push_u16(&mut elf_bytes, PHDR_SIZE as u16);
+    push_u16(&mut elf_bytes, program_header_count as u16);
+    push_u16(&mut elf_bytes, SHDR_SIZE as u16);
+    push_u16(&mut elf_bytes, section_count as u16);
+    push_u16(&mut elf_bytes, section_count as u16 - 1);
+
+    // Program headers
+    for (p_type, p_flags, offset, len) in [
+        (PT_LOAD, 5, text_addr, text.len()),
+        (PT_LOAD, 6, dynamic_addr, shstrtab_offset - dynamic_addr),
+        (PT_DYNAMIC, 6, dynamic_addr, dynamic_len),
+    ]
+    .iter()
+    .copied()
+    .chain(
+        extra_segment
+            .iter()
+            .map(|(p_type, p_flags)| (*p_type, *p_flags, 0, 0)),
+    ) {
+        push_u32(&mut elf_bytes, p_type);
+        push_u32(&mut elf_bytes, p_flags);
+        push_u64(&mut elf_bytes, offset as u64);
+        push_u64(&mut elf_bytes, offset as u64);
+        push_u64(&mut elf_bytes, offset as u64);
+        push_u64(&mut elf_bytes, len as u64);
+        push_u64(&mut elf_bytes, len as u64);
+        push_u64(&mut elf_bytes, 8);
+    }
+    align(&mut elf_bytes);
+    assert_eq!(elf_bytes.len(), text_addr);
+
+    // Section contents
+    elf_bytes.extend_from_slice(&text);
+    elf_bytes.extend_from_slice(&dynamic);
+    elf_bytes.extend_from_slice(&dynsym);
+    elf_bytes.extend_from_slice(&dynstr);
+    align(&mut elf_bytes);
+    elf_bytes.extend_from_slice(&rel_dyn);
+    elf_bytes.extend_from_slice(&gnu_hash);
+    for (_, _, _, bytes) in extra_sections {
+        elf_bytes.extend_from_slice(bytes);
+    }
+    elf_bytes.extend_from_slice(&shstrtab);
+    align(&mut elf_bytes);
+
+    // Section headers
+    elf_bytes.resize(elf_bytes.len() + SHDR_SIZE, 0);
+    let mut section_headers = vec![
+        (
+            1,
+            SHT_PROGBITS,
+            SHF_ALLOC | SHF_EXECINSTR,
+            text_addr,
+            text.len(),
+            0,
+            0,
+        ),
+        (
+            7,
+            SHT_DYNAMIC,
+            SHF_ALLOC | SHF_WRITE,
+            dynamic_addr,
+            dynamic_len,
+            4,
+            DYN_SIZE,
+        ),
+        (
+            16,
+            SHT_DYNSYM,
+            SHF_ALLOC,
+            dynsym_addr,
+            dynsym.len(),
+            4,
+            SYM_SIZE,
+        ),
+        (24, SHT_STRTAB, SHF_ALLOC, dynstr_addr, dynstr.len(), 0, 0),
+        (
+            32,
+            SHT_REL,
+            SHF_ALLOC,
+            rel_dyn_addr,
+            rel_dyn.len(),
+            3,
+            REL_SIZE,
+        ),
+    ];
+    if with_gnu_hash {
+        section_headers.push((
+            41,
+            SHT_GNU_HASH,
+            SHF_ALLOC,
+            gnu_hash_addr,
+            gnu_hash.len(),
+            3,
+            0,
+        ));
+    }
+    for (i, (_, sh_type, sh_flags, bytes)) in extra_sections.iter().enumerate() {
+        section_headers.push((
+            extra_section_names[i],
+            *sh_type,
+            *sh_flags,
+            extra_section_addrs[i],
+            bytes.len(),
+            0,
+            0,
+        ));
+    }
+    section_headers.push((51, SHT_STRTAB, 0, shstrtab_offset, shstrtab.len(), 0, 0));
+    for (sh_name, sh_type, sh_flags, addr, len, sh_link, sh_entsize) in section_headers {
+        push_u32(&mut elf_bytes, sh_name);
+        push_u32(&mut elf_bytes, sh_type);
+        push_u64(&mut elf_bytes, sh_flags);
+        push_u64(
+            &mut elf_bytes,
+            if sh_flags & SHF_ALLOC != 0 {
+                addr as u64
+            } else {
+                0
+            },
+        );
+        push_u64(&mut elf_bytes, addr as u64);
+        push_u64(&mut elf_bytes, len as u64);
+        push_u32(&mut elf_bytes, sh_link);
+        push_u32(&mut elf_bytes, 0);
+        push_u64(&mut elf_bytes, 8);
+        push_u64(&mut elf_bytes, sh_entsize as u64);
+    }
+
+    CallRelocationsElf {
+        elf_bytes,
+        text_addr: text_addr as u64,
+        calls,
+        function_pcs,
+        call_targets,
+    }
+}
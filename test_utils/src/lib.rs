@@ -8,21 +8,29 @@
 
 #![allow(dead_code)]
 
-use std::io::Write;
+use std::{io::Write, ops::Range};
 
 use solana_sbpf::{
     aligned_memory::AlignedMemory,
-    ebpf::{self, HOST_ALIGN},
+    ebpf::HOST_ALIGN,
     elf::Executable,
     error::EbpfError,
-    memory_region::{MemoryCowCallback, MemoryMapping, MemoryRegion},
+    execution_events::{ExecutionEventLog, ExecutionEvents},
+    memory_region::{
+        AccessViolationContext, MemoryCowCallback, MemoryMapping, MemoryRegion, MemoryRegionKind,
+        NearbyRegion,
+    },
     static_analysis::TraceLogEntry,
     vm::ContextObject,
     error::InternalError
 };
 
+pub mod elf_fixtures;
+pub mod memory_diff;
 pub mod syscalls;
 
+pub use memory_diff::MemoryDiff;
+
 pub struct StdoutLockWrapper<'a>(pub std::io::StdoutLock<'a>);
 
 impl<'a> solana_sbpf::utils::Write for StdoutLockWrapper<'a> {
@@ -39,6 +47,8 @@ pub struct TestContextObject {
     pub trace_log: Vec<TraceLogEntry>,
     /// Maximal amount of instructions which still can be executed
     pub remaining: u64,
+    /// Events of interpreted executions, if they are recorded
+    pub execution_events: Option<ExecutionEventLog>,
 }
 
 impl ContextObject for TestContextObject {
@@ -53,6 +63,12 @@ impl ContextObject for TestContextObject {
     fn get_remaining(&self) -> u64 {
         self.remaining
     }
+
+    fn execution_events(&mut self) -> Option<&mut dyn ExecutionEvents> {
+        self.execution_events
+            .as_mut()
+            .map(|events| events as &mut dyn ExecutionEvents)
+    }
 }
 
 impl TestContextObject {
@@ -61,6 +77,7 @@ impl TestContextObject {
         Self {
             trace_log: Vec::new(),
             remaining,
+            execution_events: None,
         }
     }
 
@@ -218,6 +235,29 @@ pub const TCP_SACK_NOMATCH: [u8; 66] = [
     0x9e, 0x27, //
 ];
 
+/// Builds the [AccessViolationContext] of an access violation for comparisons
+///
+/// `below` and `above` are the kind, address range and distance of the nearest regions.
+pub fn access_violation_context(
+    below: Option<(MemoryRegionKind, Range<u64>, u64)>,
+    above: Option<(MemoryRegionKind, Range<u64>, u64)>,
+    in_region: bool,
+    in_stack_frame_gap: bool,
+) -> Box<AccessViolationContext> {
+    let nearby = |(kind, vm_range, distance): (MemoryRegionKind, Range<u64>, u64)| NearbyRegion {
+        kind,
+        vm_addr: vm_range.start,
+        vm_addr_end: vm_range.end,
+        distance,
+    };
+    Box::new(AccessViolationContext {
+        below: below.map(nearby),
+        above: above.map(nearby),
+        in_region,
+        in_stack_frame_gap,
+    })
+}
+
 pub fn create_memory_mapping<'a, C: ContextObject>(
     executable: &'a Executable<C>,
     stack: &'a mut AlignedMemory<{ HOST_ALIGN }>,
@@ -231,14 +271,16 @@ pub fn create_memory_mapping<'a, C: ContextObject>(
         executable.get_ro_region(),
         MemoryRegion::new_writable_gapped(
             stack.as_slice_mut(),
-            ebpf::MM_STACK_START,
+            config.memory_layout.stack_start(),
             if !sbpf_version.dynamic_stack_frames() && config.enable_stack_frame_gaps {
                 config.stack_frame_size as u64
             } else {
                 0
             },
-        ),
-        MemoryRegion::new_writable(heap.as_slice_mut(), ebpf::MM_HEAP_START),
+        )
+        .with_kind(MemoryRegionKind::Stack),
+        MemoryRegion::new_writable(heap.as_slice_mut(), config.memory_layout.heap_start())
+            .with_kind(MemoryRegionKind::Heap),
     ]
     .into_iter()
     .chain(additional_regions.into_iter())
@@ -267,13 +309,14 @@ macro_rules! create_vm {
             $cow_cb,
         )
         .unwrap();
-        let mut $vm_name = solana_sbpf::vm::EbpfVm::new(
+        let mut $vm_name = solana_sbpf::vm::EbpfVm::try_new(
             $verified_executable.get_loader().clone(),
             $verified_executable.get_sbpf_version(),
             $context_object,
             memory_mapping,
             stack_len,
-        );
+        )
+        .unwrap();
     };
 }
 
@@ -297,7 +340,11 @@ macro_rules! test_interpreter_and_jit {
         $executable.verify::<RequisiteVerifier>().unwrap();
         let (instruction_count_interpreter, result_interpreter, interpreter_final_pc, _tracer_interpreter) = {
             let mut mem = $mem;
-            let mem_region = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
+            let mem_region = MemoryRegion::new_writable(
+                &mut mem,
+                $executable.get_config().memory_layout.input_start(),
+            )
+            .with_kind(solana_sbpf::memory_region::MemoryRegionKind::Input);
             let mut context_object = context_object.clone();
             create_vm!(
                 vm,
@@ -316,12 +363,52 @@ macro_rules! test_interpreter_and_jit {
                 vm.context_object_pointer.clone(),
             )
         };
+        {
+            $executable.predecode().unwrap();
+            let mut mem = $mem;
+            let mem_region = MemoryRegion::new_writable(
+                &mut mem,
+                $executable.get_config().memory_layout.input_start(),
+            )
+            .with_kind(solana_sbpf::memory_region::MemoryRegionKind::Input);
+            let mut context_object = context_object.clone();
+            create_vm!(
+                vm,
+                &$executable,
+                &mut context_object,
+                stack,
+                heap,
+                vec![mem_region],
+                None
+            );
+            let (instruction_count_predecoded, result_predecoded) = vm.execute_program(&$executable, true);
+            assert_eq!(
+                format!("{:?}", result_interpreter), format!("{:?}", result_predecoded),
+                "Result of interpreter and predecoded interpreter diverged",
+            );
+            assert_eq!(
+                instruction_count_interpreter, instruction_count_predecoded,
+                "Instruction meter of interpreter and predecoded interpreter diverged",
+            );
+            assert_eq!(
+                interpreter_final_pc, vm.registers[11],
+                "Final PC of interpreter and predecoded interpreter diverged",
+            );
+            assert!(
+                TestContextObject::compare_trace_log(&_tracer_interpreter, &vm.context_object_pointer),
+                "Trace log of interpreter and predecoded interpreter diverged",
+            );
+        }
         #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
         {
             #[allow(unused_mut)]
             let compilation_result = $executable.jit_compile();
             let mut mem = $mem;
-            let mem_region = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
+            let mem_region = MemoryRegion::new_writable(
+                &mut mem,
+                $executable.get_config().memory_layout.input_start(),
+            )
+            .with_kind(solana_sbpf::memory_region::MemoryRegionKind::Input);
             create_vm!(
                 vm,
                 &$executable,
@@ -0,0 +1,207 @@
+#![allow(clippy::arithmetic_side_effects)]
+// Licensed under the Apache License, Version 2.0 <http://www.apache.org/licenses/LICENSE-2.0> or
+// the MIT license <http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Helpers for asserting which bytes of guest memory a program modified.
+//!
+//! Tests snapshot a region before execution, run the program and then compare the snapshot to
+//! the region contents afterwards. Differences are reported with vm addresses so that failures
+//! read like the access violations produced by the memory mapping.
+
+use solana_sbpf::ebpf;
+use std::{fmt, ops::Range};
+
+/// Number of bytes printed per line of a hex dump.
+const HEX_DUMP_WIDTH: usize = 16;
+
+/// A contiguous range of bytes which differs between two snapshots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangedRange {
+    /// Offset of the first changed byte relative to the start of the region.
+    pub offset: usize,
+    /// Bytes before execution.
+    pub old: Vec<u8>,
+    /// Bytes after execution.
+    pub new: Vec<u8>,
+}
+
+impl ChangedRange {
+    /// Offsets covered by this range, relative to the start of the region.
+    pub fn range(&self) -> Range<usize> {
+        self.offset..self.offset + self.new.len()
+    }
+}
+
+/// Coalesced differences between two snapshots of the same memory region.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryDiff {
+    vm_addr: u64,
+    changes: Vec<ChangedRange>,
+}
+
+impl MemoryDiff {
+    /// Compares two snapshots of the input region (mapped at `MM_INPUT_START`).
+    pub fn between(before: &[u8], after: &[u8]) -> Self {
+        Self::between_at(ebpf::MM_INPUT_START, before, after)
+    }
+
+    /// Compares two snapshots of a region mapped at `vm_addr`.
+    pub fn between_at(vm_addr: u64, before: &[u8], after: &[u8]) -> Self {
+        assert_eq!(
+            before.len(),
+            after.len(),
+            "Snapshots of the region at {:#x} differ in length",
+            vm_addr,
+        );
+        let mut changes: Vec<ChangedRange> = Vec::new();
+        for (offset, (old, new)) in before.iter().zip(after.iter()).enumerate() {
+            if old == new {
+                continue;
+            }
+            match changes.last_mut() {
+                Some(change) if change.range().end == offset => {
+                    change.old.push(*old);
+                    change.new.push(*new);
+                }
+                _ => changes.push(ChangedRange {
+                    offset,
+                    old: vec![*old],
+                    new: vec![*new],
+                }),
+            }
+        }
+        Self { vm_addr, changes }
+    }
+
+    /// Virtual address the compared region is mapped at.
+    pub fn vm_addr(&self) -> u64 {
+        self.vm_addr
+    }
+
+    /// All coalesced changed ranges, in ascending order.
+    pub fn changes(&self) -> &[ChangedRange] {
+        &self.changes
+    }
+
+    /// Returns true if both snapshots are identical.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Panics if any byte changed.
+    pub fn assert_unchanged(&self) {
+        self.assert_only_changed([]);
+    }
+
+    /// Panics if a byte outside of `allowed` (offsets relative to the region) changed.
+    pub fn assert_only_changed(&self, allowed: impl IntoIterator<Item = Range<usize>>) {
+        let allowed = allowed.into_iter().collect::<Vec<_>>();
+        let allowed = allowed.as_slice();
+        let unexpected = self
+            .changes
+            .iter()
+            .flat_map(|change| {
+                change.range().filter_map(move |offset| {
+                    let index = offset - change.offset;
+                    (!allowed.iter().any(|range| range.contains(&offset))).then(|| ChangedRange {
+                        offset,
+                        old: vec![change.old[index]],
+                        new: vec![change.new[index]],
+                    })
+                })
+            })
+            .fold(Vec::<ChangedRange>::new(), |mut acc, byte| {
+                match acc.last_mut() {
+                    Some(change) if change.range().end == byte.offset => {
+                        change.old.extend(byte.old);
+                        change.new.extend(byte.new);
+                    }
+                    _ => acc.push(byte),
+                }
+                acc
+            });
+        if !unexpected.is_empty() {
+            panic!(
+                "Unexpected changes to {} memory:\n{}",
+                region_name(self.vm_addr),
+                MemoryDiff {
+                    vm_addr: self.vm_addr,
+                    changes: unexpected,
+                },
+            );
+        }
+    }
+
+    /// Panics unless the changed ranges are exactly `expected`.
+    ///
+    /// Each entry is the offset of a coalesced range relative to the region and its new bytes.
+    pub fn assert_changes(&self, expected: &[(usize, &[u8])]) {
+        let matches = self.changes.len() == expected.len()
+            && self
+                .changes
+                .iter()
+                .zip(expected.iter())
+                .all(|(change, (offset, new))| change.offset == *offset && change.new == *new);
+        if !matches {
+            let mut message = format!(
+                "Unexpected changes to {} memory:\n{}expected:\n",
+                region_name(self.vm_addr),
+                self,
+            );
+            for (offset, new) in expected.iter() {
+                message.push_str(&format!(
+                    "  + {:#018x}: {}\n",
+                    self.vm_addr + *offset as u64,
+                    hex_dump(new),
+                ));
+            }
+            panic!("{}", message);
+        }
+    }
+}
+
+impl fmt::Display for MemoryDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in self.changes.iter() {
+            let vm_addr = self.vm_addr + change.offset as u64;
+            writeln!(
+                f,
+                "{:#x}..{:#x} ({} bytes):",
+                vm_addr,
+                vm_addr + change.new.len() as u64,
+                change.new.len(),
+            )?;
+            for (line, (old, new)) in change
+                .old
+                .chunks(HEX_DUMP_WIDTH)
+                .zip(change.new.chunks(HEX_DUMP_WIDTH))
+                .enumerate()
+            {
+                let line_addr = vm_addr + (line * HEX_DUMP_WIDTH) as u64;
+                writeln!(f, "  - {:#018x}: {}", line_addr, hex_dump(old))?;
+                writeln!(f, "  + {:#018x}: {}", line_addr, hex_dump(new))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn hex_dump(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn region_name(vm_addr: u64) -> &'static str {
+    match vm_addr & !ebpf::MM_RODATA_START.saturating_sub(1) {
+        ebpf::MM_BYTECODE_START => "bytecode",
+        ebpf::MM_RODATA_START => "program",
+        ebpf::MM_STACK_START => "stack",
+        ebpf::MM_HEAP_START => "heap",
+        ebpf::MM_INPUT_START => "input",
+        _ => "unknown",
+    }
+}
@@ -25,7 +25,9 @@ use crate::TestContextObject;
 use solana_sbpf::{
     declare_builtin_function,
     error::EbpfError,
+    execution_events::ExecutionEvent,
     memory_region::{AccessType, MemoryMapping},
+    vm::ContextObject,
 };
 use std::{slice::from_raw_parts, str::from_utf8};
 
@@ -123,24 +125,21 @@ declare_builtin_function!(
         if arg1 == 0 || arg2 == 0 {
             return Ok(u64::MAX);
         }
-        let a: Result<u64, EbpfError> = memory_mapping.map(AccessType::Load, arg1, 1).into();
-        let mut a = a?;
-        let b: Result<u64, EbpfError> = memory_mapping.map(AccessType::Load, arg2, 1).into();
-        let mut b = b?;
-        unsafe {
-            let mut a_val = *(a as *const u8);
-            let mut b_val = *(b as *const u8);
-            while a_val == b_val && a_val != 0 && b_val != 0 {
-                a += 1;
-                b += 1;
-                a_val = *(a as *const u8);
-                b_val = *(b as *const u8);
-            }
-            if a_val >= b_val {
-                Ok((a_val - b_val) as u64)
-            } else {
-                Ok((b_val - a_val) as u64)
-            }
+        // Every byte is translated on its own, the strings may cross region boundaries
+        let mut a = arg1;
+        let mut b = arg2;
+        let mut a_val = memory_mapping.translate_type::<u8>(a)?;
+        let mut b_val = memory_mapping.translate_type::<u8>(b)?;
+        while a_val == b_val && a_val != 0 && b_val != 0 {
+            a += 1;
+            b += 1;
+            a_val = memory_mapping.translate_type::<u8>(a)?;
+            b_val = memory_mapping.translate_type::<u8>(b)?;
+        }
+        if a_val >= b_val {
+            Ok((a_val - b_val) as u64)
+        } else {
+            Ok((b_val - a_val) as u64)
         }
     }
 );
@@ -149,7 +148,7 @@ declare_builtin_function!(
     /// Prints a NULL-terminated UTF-8 string.
     SyscallString,
     fn rust(
-        _context_object: &mut TestContextObject,
+        context_object: &mut TestContextObject,
         vm_addr: u64,
         len: u64,
         _arg3: u64,
@@ -165,6 +164,9 @@ declare_builtin_function!(
             let len = c_buf.iter().position(|c| *c == 0).unwrap_or(len as usize);
             let message = from_utf8(&c_buf[0..len]).unwrap_or("Invalid UTF-8 String");
             println!("log: {message}");
+            if let Some(events) = context_object.execution_events() {
+                events.builtin_event(ExecutionEvent::Log(message.to_string()));
+            }
         }
         Ok(0)
     }
@@ -189,3 +191,20 @@ declare_builtin_function!(
         Ok(0)
     }
 );
+
+declare_builtin_function!(
+    /// Returns the `u64` at the address in the first argument, which does not have to be
+    /// aligned. Arguments 2 to 5 are unused.
+    SyscallReadU64,
+    fn rust(
+        _context_object: &mut TestContextObject,
+        vm_addr: u64,
+        _arg2: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        memory_mapping: &mut MemoryMapping,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        Ok(memory_mapping.translate_type::<u64>(vm_addr)?)
+    }
+);
@@ -12,7 +12,11 @@ extern crate test_utils;
 
 use solana_sbpf::program::SBPFVersion;
 use solana_sbpf::vm::Config;
-use solana_sbpf::{assembler::assemble, ebpf, program::BuiltinProgram};
+use solana_sbpf::{
+    assembler::{assemble, assemble_with_diagnostics, AssemblerDiagnostic},
+    ebpf,
+    program::BuiltinProgram,
+};
 use std::sync::Arc;
 use test_utils::{TestContextObject, TCP_SACK_ASM, TCP_SACK_BIN};
 
@@ -20,6 +24,16 @@ fn asm(src: &str) -> Result<Vec<ebpf::Insn>, String> {
     asm_with_config(src, Config::default())
 }
 
+fn asm_v0(src: &str) -> Result<Vec<ebpf::Insn>, String> {
+    asm_with_config(
+        src,
+        Config {
+            enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+            ..Config::default()
+        },
+    )
+}
+
 fn asm_with_config(src: &str, config: Config) -> Result<Vec<ebpf::Insn>, String> {
     let loader = BuiltinProgram::new_loader(config);
     let executable = assemble::<TestContextObject>(src, Arc::new(loader))?;
@@ -118,7 +132,10 @@ fn test_add64() {
 // Example for InstructionType::AluUnary.
 #[test]
 fn test_neg64() {
-    assert_eq!(asm("neg64 r1"), Ok(vec![insn(0, ebpf::NEG64, 1, 0, 0, 0)]));
+    assert_eq!(
+        asm_v0("neg64 r1"),
+        Ok(vec![insn(0, ebpf::NEG64, 1, 0, 0, 0)])
+    );
 }
 
 // Example for InstructionType::JumpUnconditional.
@@ -168,14 +185,14 @@ fn test_be32() {
 #[test]
 fn test_lddw() {
     assert_eq!(
-        asm("lddw r1, 0x1234abcd5678eeff"),
+        asm_v0("lddw r1, 0x1234abcd5678eeff"),
         Ok(vec![
             insn(0, ebpf::LD_DW_IMM, 1, 0, 0, 0x5678eeff),
             insn(1, 0, 0, 0, 0, 0x1234abcd)
         ])
     );
     assert_eq!(
-        asm("lddw r1, 0xff11ee22dd33cc44"),
+        asm_v0("lddw r1, 0xff11ee22dd33cc44"),
         Ok(vec![
             insn(0, ebpf::LD_DW_IMM, 1, 0, 0, 0xffffffffdd33cc44u64 as i64),
             insn(1, 0, 0, 0, 0, 0xffffffffff11ee22u64 as i64)
@@ -187,7 +204,8 @@ fn test_lddw() {
 #[test]
 fn test_alu_binary() {
     assert_eq!(
-        asm("add r1, r2
+        asm_v0(
+            "add r1, r2
              sub r1, r2
              mul r1, r2
              div r1, r2
@@ -198,7 +216,8 @@ fn test_alu_binary() {
              mod r1, r2
              xor r1, r2
              mov r1, r2
-             arsh r1, r2"),
+             arsh r1, r2"
+        ),
         Ok(vec![
             insn(0, ebpf::ADD64_REG, 1, 2, 0, 0),
             insn(1, ebpf::SUB64_REG, 1, 2, 0, 0),
@@ -216,7 +235,8 @@ fn test_alu_binary() {
     );
 
     assert_eq!(
-        asm("add r1, 2
+        asm_v0(
+            "add r1, 2
              sub r1, 2
              mul r1, 2
              div r1, 2
@@ -227,7 +247,8 @@ fn test_alu_binary() {
              mod r1, 2
              xor r1, 2
              mov r1, 2
-             arsh r1, 2"),
+             arsh r1, 2"
+        ),
         Ok(vec![
             insn(0, ebpf::ADD64_IMM, 1, 0, 0, 2),
             insn(1, ebpf::SUB64_IMM, 1, 0, 0, 2),
@@ -245,7 +266,8 @@ fn test_alu_binary() {
     );
 
     assert_eq!(
-        asm("add64 r1, r2
+        asm_v0(
+            "add64 r1, r2
              sub64 r1, r2
              mul64 r1, r2
              div64 r1, r2
@@ -256,7 +278,8 @@ fn test_alu_binary() {
              mod64 r1, r2
              xor64 r1, r2
              mov64 r1, r2
-             arsh64 r1, r2"),
+             arsh64 r1, r2"
+        ),
         Ok(vec![
             insn(0, ebpf::ADD64_REG, 1, 2, 0, 0),
             insn(1, ebpf::SUB64_REG, 1, 2, 0, 0),
@@ -274,7 +297,8 @@ fn test_alu_binary() {
     );
 
     assert_eq!(
-        asm("add64 r1, 2
+        asm_v0(
+            "add64 r1, 2
              sub64 r1, 2
              mul64 r1, 2
              div64 r1, 2
@@ -285,7 +309,8 @@ fn test_alu_binary() {
              mod64 r1, 2
              xor64 r1, 2
              mov64 r1, 2
-             arsh64 r1, 2"),
+             arsh64 r1, 2"
+        ),
         Ok(vec![
             insn(0, ebpf::ADD64_IMM, 1, 0, 0, 2),
             insn(1, ebpf::SUB64_IMM, 1, 0, 0, 2),
@@ -303,7 +328,8 @@ fn test_alu_binary() {
     );
 
     assert_eq!(
-        asm("add32 r1, r2
+        asm_v0(
+            "add32 r1, r2
              sub32 r1, r2
              mul32 r1, r2
              div32 r1, r2
@@ -314,7 +340,8 @@ fn test_alu_binary() {
              mod32 r1, r2
              xor32 r1, r2
              mov32 r1, r2
-             arsh32 r1, r2"),
+             arsh32 r1, r2"
+        ),
         Ok(vec![
             insn(0, ebpf::ADD32_REG, 1, 2, 0, 0),
             insn(1, ebpf::SUB32_REG, 1, 2, 0, 0),
@@ -332,7 +359,8 @@ fn test_alu_binary() {
     );
 
     assert_eq!(
-        asm("add32 r1, 2
+        asm_v0(
+            "add32 r1, 2
              sub32 r1, 2
              mul32 r1, 2
              div32 r1, 2
@@ -343,7 +371,8 @@ fn test_alu_binary() {
              mod32 r1, 2
              xor32 r1, 2
              mov32 r1, 2
-             arsh32 r1, 2"),
+             arsh32 r1, 2"
+        ),
         Ok(vec![
             insn(0, ebpf::ADD32_IMM, 1, 0, 0, 2),
             insn(1, ebpf::SUB32_IMM, 1, 0, 0, 2),
@@ -365,9 +394,11 @@ fn test_alu_binary() {
 #[test]
 fn test_alu_unary() {
     assert_eq!(
-        asm("neg r1
+        asm_v0(
+            "neg r1
              neg64 r1
-             neg32 r1"),
+             neg32 r1"
+        ),
         Ok(vec![
             insn(0, ebpf::NEG64, 1, 0, 0, 0),
             insn(1, ebpf::NEG64, 1, 0, 0, 0),
@@ -489,12 +520,14 @@ fn test_jump_conditional() {
 #[test]
 fn test_endian() {
     assert_eq!(
-        asm("be16 r1
+        asm_v0(
+            "be16 r1
              be32 r1
              be64 r1
              le16 r1
              le32 r1
-             le64 r1"),
+             le64 r1"
+        ),
         Ok(vec![
             insn(0, ebpf::BE, 1, 0, 0, 16),
             insn(1, ebpf::BE, 1, 0, 0, 32),
@@ -533,14 +566,17 @@ fn test_tcp_sack() {
 
 #[test]
 fn test_error_invalid_instruction() {
-    assert_eq!(asm("abcd"), Err("Invalid instruction \"abcd\"".to_string()));
+    assert_eq!(
+        asm("abcd"),
+        Err("Invalid instruction \"abcd\" at line 1".to_string())
+    );
 }
 
 #[test]
 fn test_error_unexpected_operands() {
     assert_eq!(
         asm("add 1, 2"),
-        Err("Unexpected operands: [Integer(1), Integer(2)]".to_string())
+        Err("Unexpected operands: [Integer(1), Integer(2)] at line 1".to_string())
     );
 }
 
@@ -548,24 +584,369 @@ fn test_error_unexpected_operands() {
 fn test_error_operands_out_of_range() {
     assert_eq!(
         asm("add r16, r2"),
-        Err("Invalid destination register 16".to_string())
+        Err("Invalid destination register 16 at line 1".to_string())
     );
     assert_eq!(
         asm("add r1, r16"),
-        Err("Invalid source register 16".to_string())
+        Err("Invalid source register 16 at line 1".to_string())
+    );
+    assert_eq!(
+        asm("ja -32769"),
+        Err("Invalid offset -32769 at line 1".to_string())
+    );
+    assert_eq!(
+        asm("ja 32768"),
+        Err("Invalid offset 32768 at line 1".to_string())
     );
-    assert_eq!(asm("ja -32769"), Err("Invalid offset -32769".to_string()));
-    assert_eq!(asm("ja 32768"), Err("Invalid offset 32768".to_string()));
     assert_eq!(
         asm("add r1, 4294967296"),
-        Err("Invalid immediate 4294967296".to_string())
+        Err("Invalid immediate 4294967296 at line 1".to_string())
     );
     assert_eq!(
         asm("add r1, 2147483648"),
-        Err("Invalid immediate 2147483648".to_string())
+        Err("Invalid immediate 2147483648 at line 1".to_string())
     );
     assert_eq!(
         asm("add r1, -2147483649"),
-        Err("Invalid immediate -2147483649".to_string())
+        Err("Invalid immediate -2147483649 at line 1".to_string())
+    );
+}
+
+#[test]
+fn test_rodata() {
+    let data = "
+        .rodata
+        bytes:
+        .byte 1, -1
+        .short 0x1234
+        .word -2
+        .align 8
+        .quad 0x0102030405060708
+        string:
+        .asciz \"a\\n\", \"b\"";
+    let rodata = [
+        1, 0xff, 0x34, 0x12, 0xfe, 0xff, 0xff, 0xff, //
+        8, 7, 6, 5, 4, 3, 2, 1, //
+        b'a', b'\n', 0, b'b', 0,
+    ];
+    for sbpf_version in [SBPFVersion::V0, SBPFVersion::V3] {
+        let config = Config {
+            enabled_sbpf_versions: sbpf_version..=sbpf_version,
+            ..Config::default()
+        };
+        // Versions without lddw can not refer to data labels
+        let src = if sbpf_version.disable_lddw() {
+            format!("mov64 r1, 0\nhor64 r1, 1\nexit{data}")
+        } else {
+            format!("lddw r1, bytes\nlddw r2, string\nexit{data}")
+        };
+        let executable =
+            assemble::<TestContextObject>(&src, Arc::new(BuiltinProgram::new_loader(config)))
+                .unwrap();
+        let (_text_vm_addr, text) = executable.get_text_bytes();
+        // The read-only data directly follows the text section, unless it lives in its own region
+        if sbpf_version == SBPFVersion::V0 {
+            assert_eq!(text.len(), 5 * ebpf::INSN_SIZE);
+            assert_eq!(&executable.get_ro_section()[..text.len()], text);
+            assert_eq!(&executable.get_ro_section()[text.len()..], &rodata);
+            let rodata_vm_addr = ebpf::MM_RODATA_START + text.len() as u64;
+            let lddw_value = |insn_ptr| {
+                let insn = ebpf::get_insn(text, insn_ptr);
+                (insn.imm as u32 as u64) | ((ebpf::get_insn(text, insn_ptr + 1).imm as u64) << 32)
+            };
+            assert_eq!(lddw_value(0), rodata_vm_addr);
+            assert_eq!(lddw_value(2), rodata_vm_addr + 16);
+        } else {
+            assert_eq!(executable.get_ro_section(), &rodata);
+        }
+        assert_eq!(executable.get_ro_region().vm_addr, ebpf::MM_RODATA_START);
+    }
+}
+
+#[test]
+fn test_error_rodata() {
+    assert_eq!(
+        asm(".byte 1"),
+        Err("Directive \"byte\" not allowed in the .text section at line 1".to_string())
+    );
+    assert_eq!(
+        asm(".rodata\n.fill 1, 0"),
+        Err("Directive \"fill\" not allowed in the .rodata section at line 2".to_string())
+    );
+    assert_eq!(
+        asm(".rodata\nexit"),
+        Err("Instruction \"exit\" not allowed in the .rodata section at line 2".to_string())
+    );
+    assert_eq!(
+        asm(".rodata\n.text\nadd64 r1, 1"),
+        Ok(vec![insn(0, ebpf::ADD64_IMM, 1, 0, 0, 1)])
+    );
+    assert_eq!(
+        asm(".rodata\n.byte 256"),
+        Err("Invalid .byte value 256 at line 2".to_string())
+    );
+    assert_eq!(
+        asm(".rodata\n.short -32769"),
+        Err("Invalid .short value -32769 at line 2".to_string())
+    );
+    assert_eq!(
+        asm(".rodata\n.align 3"),
+        Err("Invalid alignment 3 at line 2".to_string())
+    );
+    assert_eq!(
+        asm(".rodata\n.ascii 1"),
+        Err("Unexpected operands: [Integer(1)] at line 2".to_string())
+    );
+    assert_eq!(
+        asm_v0("lddw r1, missing\n.rodata\n.byte 0"),
+        Err("Label not found missing at line 1".to_string())
+    );
+    assert_eq!(
+        asm(".data"),
+        Err("Invalid directive \"data\" at line 1".to_string())
+    );
+}
+
+#[test]
+fn test_equ_and_expressions() {
+    assert_eq!(
+        asm("
+            .equ FRAME, 0x40
+            .equ SLOT, FRAME - 8
+            mov64 r1, SLOT * (2 + -1) << 2
+            stxdw [r10 - FRAME], r1
+            ldxdw r2, [r10 - SLOT + 8]
+            jeq r1, (FRAME >> 3) * -3, +1
+            add64 r1, -(-(1))
+            .equ COUNT, 2
+            .fill COUNT, 0x1007"),
+        Ok(vec![
+            insn(0, ebpf::MOV64_IMM, 1, 0, 0, 0xe0),
+            insn(1, ebpf::ST_8B_REG, 10, 1, -0x40, 0),
+            insn(2, ebpf::LD_8B_REG, 2, 10, -0x30, 0),
+            insn(3, ebpf::JEQ_IMM, 1, 0, 1, -24),
+            insn(4, ebpf::ADD64_IMM, 1, 0, 0, 1),
+            insn(5, ebpf::ADD64_IMM, 0, 1, 0, 0),
+            insn(6, ebpf::ADD64_IMM, 0, 1, 0, 0),
+        ])
+    );
+}
+
+#[test]
+fn test_error_equ() {
+    assert_eq!(
+        asm(".equ A, 1\n.equ A, 2"),
+        Err("Constant \"A\" redefined at line 2".to_string())
+    );
+    assert_eq!(
+        asm("mov64 r1, A + 1\n.equ A, 1"),
+        Err("Undefined constant A at line 1".to_string())
+    );
+    assert_eq!(
+        asm(".equ A, B"),
+        Err("Undefined constant B at line 1".to_string())
+    );
+    assert_eq!(
+        asm("\n\nmov64 r1, 1 << 64"),
+        Err("Invalid shift amount 64 at line 3".to_string())
+    );
+    assert_eq!(
+        asm(".equ A, 1 << 31\nmov64 r1, A"),
+        Err("Invalid immediate 2147483648 at line 2".to_string())
+    );
+}
+
+#[test]
+fn test_macros() {
+    assert_eq!(
+        asm("
+            .macro clear_r1
+            mov64 r1, 0
+            .endm
+            .macro clear_twice
+            clear_r1
+            add64 r1, 1
+            clear_r1
+            .endm
+            clear_twice
+            exit"),
+        asm("
+            mov64 r1, 0
+            add64 r1, 1
+            mov64 r1, 0
+            exit")
+    );
+}
+
+#[test]
+fn test_error_macros() {
+    assert_eq!(
+        asm("
+            spill
+            .macro spill
+            stxdw [r10-8], r1
+            .endm"),
+        Err("Macro \"spill\" used before its definition at line 2".to_string())
+    );
+    assert_eq!(
+        asm("
+            .macro inner
+            mov64 r16, 0
+            .endm
+            .macro outer
+            inner
+            .endm
+            exit
+            outer"),
+        Err(
+            "Invalid destination register 16 at line 3, in macro \"inner\" invoked at line 6, \
+             in macro \"outer\" invoked at line 9"
+                .to_string()
+        )
     );
+    assert_eq!(
+        asm(".macro a\n.endm\n.macro a\n.endm"),
+        Err("Macro \"a\" redefined at line 3".to_string())
+    );
+    assert_eq!(
+        asm(".macro loop\nloop\n.endm\nloop"),
+        Err(
+            "Macro \"loop\" invokes itself at line 2, in macro \"loop\" invoked at line 4"
+                .to_string()
+        )
+    );
+    assert_eq!(
+        asm(".macro exit\n.endm"),
+        Err("Macro \"exit\" has the name of an instruction at line 1".to_string())
+    );
+    assert_eq!(
+        asm(".macro a\n.macro b\n.endm\n.endm"),
+        Err("Nested macro definition at line 2\n.endm without .macro at line 4".to_string())
+    );
+    assert_eq!(
+        asm("\n.macro a\nexit"),
+        Err(".macro without .endm at line 2".to_string())
+    );
+    assert_eq!(
+        asm(".endm"),
+        Err(".endm without .macro at line 1".to_string())
+    );
+}
+
+#[test]
+fn test_diagnostics() {
+    let loader = Arc::new(BuiltinProgram::new_loader(Config::default()));
+    let src = "ad64 r1, 2\nmov64 r1, r16\nja missing\nexit";
+    let diagnostic = |line, columns, message: &str, suggestion: Option<&str>| AssemblerDiagnostic {
+        line,
+        columns,
+        message: message.to_string(),
+        suggestion: suggestion.map(str::to_string),
+        expansions: Vec::new(),
+    };
+    assert_eq!(
+        assemble_with_diagnostics::<TestContextObject>(src, loader.clone()).unwrap_err(),
+        vec![
+            diagnostic(1, 1..5, "Invalid instruction \"ad64\"", Some("add64")),
+            diagnostic(2, 11..14, "Invalid source register 16", None),
+            diagnostic(3, 4..11, "Label not found missing", None),
+        ]
+    );
+    assert_eq!(
+        asm(src),
+        Err(
+            "Invalid instruction \"ad64\" at line 1, did you mean \"add64\"?\n\
+             Invalid source register 16 at line 2\n\
+             Label not found missing at line 3"
+                .to_string()
+        )
+    );
+
+    // Statements expanded from a macro point into its body and are ordered by the invocation
+    let diagnostics = assemble_with_diagnostics::<TestContextObject>(
+        ".macro m\nstxdw [r16+1], r1\n.endm\nmov64 r1, r17\nm",
+        loader,
+    )
+    .unwrap_err();
+    assert_eq!(
+        diagnostics,
+        vec![
+            diagnostic(4, 11..14, "Invalid source register 17", None),
+            AssemblerDiagnostic {
+                expansions: vec![("m".to_string(), 5)],
+                ..diagnostic(2, 7..14, "Invalid destination register 16", None)
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_version_gated_mnemonics() {
+    let gated = "neg64 r1\nlddw r1, 1\nhor64 r1, 1\nle16 r1\nmul64 r1, 2\nudiv64 r1, 2";
+    let shared = "ldxw r1, [r2+1]\nstw [r1+2], 3\nstxdw [r1-8], r2\nstb [r1], 1\nexit";
+    for sbpf_version in [
+        SBPFVersion::V0,
+        SBPFVersion::V1,
+        SBPFVersion::V2,
+        SBPFVersion::V3,
+    ] {
+        let config = Config {
+            enabled_sbpf_versions: sbpf_version..=sbpf_version,
+            ..Config::default()
+        };
+        let unavailable = |name: &str, feature: &str, state: &str, line: usize| {
+            format!(
+                "Instruction {name:?} is not available in SBPF {sbpf_version} because {feature} \
+                 is {state} at line {line}"
+            )
+        };
+        let errors = if sbpf_version < SBPFVersion::V2 {
+            [
+                unavailable("hor64", "disable_lddw", "disabled", 3),
+                unavailable("udiv64", "enable_pqr", "disabled", 6),
+            ]
+            .join("\n")
+        } else {
+            [
+                unavailable("neg64", "disable_neg", "enabled", 1),
+                unavailable("lddw", "disable_lddw", "enabled", 2),
+                unavailable("le16", "disable_le", "enabled", 4),
+                unavailable("mul64", "enable_pqr", "enabled", 5),
+            ]
+            .join("\n")
+        };
+        assert_eq!(asm_with_config(gated, config.clone()), Err(errors));
+
+        let (load, store_imm, store_reg, store_byte) =
+            if sbpf_version.move_memory_instruction_classes() {
+                (
+                    ebpf::LD_4B_REG,
+                    ebpf::ST_4B_IMM,
+                    ebpf::ST_8B_REG,
+                    ebpf::ST_1B_IMM,
+                )
+            } else {
+                (
+                    ebpf::LD_W_REG,
+                    ebpf::ST_W_IMM,
+                    ebpf::ST_DW_REG,
+                    ebpf::ST_B_IMM,
+                )
+            };
+        let exit = if sbpf_version.static_syscalls() {
+            ebpf::RETURN
+        } else {
+            ebpf::EXIT
+        };
+        assert_eq!(
+            asm_with_config(shared, config),
+            Ok(vec![
+                insn(0, load, 1, 2, 1, 0),
+                insn(1, store_imm, 1, 0, 2, 3),
+                insn(2, store_reg, 1, 2, -8, 0),
+                insn(3, store_byte, 1, 0, 0, 1),
+                insn(4, exit, 0, 0, 0, 0),
+            ])
+        );
+    }
 }
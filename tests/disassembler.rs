@@ -11,10 +11,22 @@
 extern crate solana_sbpf;
 use solana_sbpf::program::SBPFVersion;
 use solana_sbpf::{
-    assembler::assemble, program::BuiltinProgram, static_analysis::Analysis, vm::Config,
+    assembler::assemble,
+    disassembler::{
+        decode, disassemble_program_records, disassembly_records_to_json, DisassembledInstruction,
+        DisassemblyOptions, DisassemblyRecord, DisassemblyStyle, Operands, Radix,
+    },
+    ebpf,
+    elf::Executable,
+    program::{BuiltinProgram, FunctionRegistry},
+    static_analysis::{Analysis, CallDepth, FunctionCalls, IrreducibleCycle, Loop},
+    vm::Config,
 };
-use std::sync::Arc;
-use test_utils::TestContextObject;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+use test_utils::{syscalls, TestContextObject};
 
 // Using a macro to keep actual line numbers in failure output
 macro_rules! disasm {
@@ -69,6 +81,37 @@ fn test_static_syscall() {
     disasm!("entrypoint:\n    syscall 5\n", config);
 }
 
+#[test]
+fn test_syscall_names() {
+    for (sbpf_version, unregistered) in [
+        (SBPFVersion::V0, "syscall [invalid] imm=0x5"),
+        (SBPFVersion::V3, "syscall 5"),
+    ] {
+        let config = Config {
+            enabled_sbpf_versions: sbpf_version..=sbpf_version,
+            ..Config::default()
+        };
+        let mut loader = BuiltinProgram::new_loader(config);
+        loader
+            .register_function("log", syscalls::SyscallString::vm)
+            .unwrap();
+        let executable = assemble::<TestContextObject>(
+            "entrypoint:\n    syscall log\n    syscall 5\n",
+            Arc::new(loader),
+        )
+        .unwrap();
+        let analysis = Analysis::from_executable(&executable).unwrap();
+        assert_eq!(
+            analysis.disassemble_instruction(&analysis.instructions[0], 0),
+            "syscall log"
+        );
+        assert_eq!(
+            analysis.disassemble_instruction(&analysis.instructions[1], 1),
+            unregistered
+        );
+    }
+}
+
 // Example for InstructionType::AluBinary.
 #[test]
 fn test_add64() {
@@ -150,8 +193,16 @@ fn test_be32() {
 // Example for InstructionType::LoadImm.
 #[test]
 fn test_lddw() {
-    disasm!("entrypoint:\n    lddw r1, 0x1234abcd5678eeff\n");
-    disasm!("entrypoint:\n    lddw r1, 0xff11ee22dd33cc44\n");
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+        enable_symbol_and_section_labels: true,
+        ..Config::default()
+    };
+    disasm!(
+        "entrypoint:\n    lddw r1, 0x1234abcd5678eeff\n",
+        config.clone()
+    );
+    disasm!("entrypoint:\n    lddw r1, 0xff11ee22dd33cc44\n", config);
 }
 
 // Example for InstructionType::LoadReg.
@@ -351,6 +402,11 @@ lbb_11:
 // Test all supported Endian mnemonics.
 #[test]
 fn test_endian() {
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+        enable_symbol_and_section_labels: true,
+        ..Config::default()
+    };
     disasm!(
         "entrypoint:
     be16 r1
@@ -359,8 +415,48 @@ fn test_endian() {
     le16 r1
     le32 r1
     le64 r1
-"
+",
+        config
+    );
+}
+
+#[test]
+fn test_invalid_byteswap_width() {
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+        ..Config::default()
+    };
+    let executable = assemble::<TestContextObject>(
+        "
+    .fill 1, 0x00000000000003d4
+    .fill 1, 0x00000008000003d4
+    .fill 1, 0x00000040000003d4
+    .fill 1, 0x00000000000003dc
+    .fill 1, 0x00000008000003dc
+    .fill 1, 0x00000040000003dc",
+        Arc::new(BuiltinProgram::new_loader(config)),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let disassembled = analysis
+        .instructions
+        .iter()
+        .enumerate()
+        .map(|(pc, insn)| analysis.disassemble_instruction(insn, pc))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        disassembled,
+        vec![
+            "le?? r3, imm=0",
+            "le?? r3, imm=8",
+            "le64 r3",
+            "be?? r3, imm=0",
+            "be?? r3, imm=8",
+            "be64 r3",
+        ]
     );
+    let be8 = analysis.disassemble_instruction_ex(&analysis.instructions[4], 4);
+    assert_eq!((be8.mnemonic, be8.operands.as_str()), ("be??", "r3, imm=8"));
 }
 
 #[test]
@@ -368,3 +464,1055 @@ fn test_large_immediate() {
     disasm!("entrypoint:\n    add64 r1, -1\n");
     disasm!("entrypoint:\n    add64 r1, -1\n");
 }
+
+#[test]
+fn test_disassemble_with_encoding() {
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+        ..Config::default()
+    };
+    let loader = BuiltinProgram::new_loader(config);
+    let executable = assemble::<TestContextObject>(
+        "entrypoint:\n    lddw r1, 0x1234abcd5678eeff\n    be32 r1\n    exit\n",
+        Arc::new(loader),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+
+    let lddw = analysis.disassemble_instruction_ex(&analysis.instructions[0], 0);
+    assert_eq!(
+        lddw,
+        DisassembledInstruction {
+            pc: 0,
+            bytes: vec![
+                0x18, 0x01, 0x00, 0x00, 0xff, 0xee, 0x78, 0x56, 0x00, 0x00, 0x00, 0x00, 0xcd, 0xab,
+                0x34, 0x12,
+            ],
+            mnemonic: "lddw",
+            operands: "r1, 0x1234abcd5678eeff".to_string(),
+        }
+    );
+    assert_eq!(&lddw.bytes, &executable.get_text_bytes().1[..16]);
+    let be32 = analysis.disassemble_instruction_ex(&analysis.instructions[1], 1);
+    assert_eq!(
+        (be32.pc, be32.mnemonic, be32.operands.as_str()),
+        (2, "be32", "r1")
+    );
+    assert_eq!(
+        be32.to_string(),
+        analysis.disassemble_instruction(&analysis.instructions[1], 1)
+    );
+    let exit = analysis.disassemble_instruction_ex(&analysis.instructions[2], 2);
+    assert_eq!((exit.mnemonic, exit.operands.as_str()), ("exit", ""));
+
+    let mut listing = Vec::new();
+    analysis.disassemble_with_encoding(&mut listing).unwrap();
+    assert_eq!(
+        String::from_utf8(listing).unwrap(),
+        "entrypoint:
+        0: 18 01 00 00 ff ee 78 56 00 00 00 00 cd ab 34 12  lddw r1, 0x1234abcd5678eeff
+        2: dc 01 00 00 20 00 00 00                          be32 r1
+        3: 95 00 00 00 00 00 00 00                          exit
+"
+    );
+}
+
+#[test]
+fn test_reassemble_all_instruction_forms() {
+    let src = "
+entrypoint:
+    mov64 r1, 2
+    mov32 r2, -1
+    add64 r1, r2
+    add32 r1, 0x7fffffff
+    sub64 r1, -0x80000000
+    sub32 r1, r2
+    mul64 r1, 3
+    mul32 r1, r2
+    div64 r1, r2
+    div32 r1, 5
+    mod64 r1, 6
+    mod32 r1, r2
+    or64 r1, r2
+    or32 r1, 7
+    and64 r1, 8
+    and32 r1, r2
+    lsh64 r1, r2
+    lsh32 r1, 9
+    rsh64 r1, 10
+    rsh32 r1, r2
+    xor64 r1, r2
+    xor32 r1, 11
+    arsh64 r1, 12
+    arsh32 r1, r2
+    hor64 r1, 0x10
+    lmul64 r1, r2
+    lmul32 r1, 13
+    uhmul64 r1, r2
+    shmul64 r1, 14
+    udiv64 r1, r2
+    udiv32 r1, 15
+    urem64 r1, 16
+    urem32 r1, r2
+    sdiv64 r1, -17
+    sdiv32 r1, r2
+    srem64 r1, r2
+    srem32 r1, -18
+    neg64 r1
+    neg32 r1
+    lddw r1, 0x1234abcd5678eeff
+    lddw r2, -1
+    ldxb r1, [r2+0x10]
+    ldxh r1, [r2-0x8]
+    ldxw r1, [r2]
+    ldxdw r1, [r2-0x8000]
+    stb [r1+1], 5
+    sth [r1-2], -3
+    stw [r1+0x7fff], 0x7fffffff
+    stdw [r1], -0x80000000
+    stxb [r1+3], r2
+    stxh [r1-4], r2
+    stxw [r1], r2
+    stxdw [r1+5], r2
+    le16 r1
+    le32 r1
+    le64 r1
+    be16 r1
+    be32 r1
+    be64 r1
+    ja forward
+    ja +1
+    jeq r1, 1, forward
+    jeq r1, r2, -3
+    jgt r1, 2, forward
+    jgt r1, r2, forward
+    jge r1, -3, forward
+    jge r1, r2, +0
+    jlt r1, 4, forward
+    jlt r1, r2, forward
+    jle r1, 5, forward
+    jle r1, r2, forward
+    jset r1, 6, forward
+    jset r1, r2, forward
+    jne r1, 7, forward
+    jne r1, r2, forward
+    jsgt r1, 8, forward
+    jsgt r1, r2, forward
+    jsge r1, 9, forward
+    jsge r1, r2, forward
+    jslt r1, 10, forward
+    jslt r1, r2, forward
+    jsle r1, 11, forward
+    jsle r1, r2, +0x20
+forward:
+    call function_foo
+    call 2
+    call entrypoint
+    callx r3
+    syscall log
+    syscall 7
+    .fill 1, 0xff
+    .fill 1, 0x10107
+    .fill 2, 0x1234000000000095
+    exit
+function_foo:
+    return
+    lddw r0, 1
+    .fill 1, 0x1
+    ja function_foo
+    lddw r3, 2
+";
+    for sbpf_version in [
+        SBPFVersion::V0,
+        SBPFVersion::V1,
+        SBPFVersion::V2,
+        SBPFVersion::V3,
+    ] {
+        // The assembler rejects the mnemonics which are not available in the version
+        let unavailable: &[&str] = if sbpf_version.enable_pqr() {
+            &[
+                "mul64", "mul32", "div64", "div32", "mod64", "mod32", "neg64", "neg32", "lddw",
+                "le16", "le32", "le64",
+            ]
+        } else {
+            &[
+                "hor64", "lmul64", "lmul32", "uhmul64", "shmul64", "udiv64", "udiv32", "urem64",
+                "urem32", "sdiv64", "sdiv32", "srem64", "srem32",
+            ]
+        };
+        let src = src
+            .lines()
+            .filter(|line| {
+                !unavailable.contains(&line.split_whitespace().next().unwrap_or_default())
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let config = Config {
+            enabled_sbpf_versions: sbpf_version..=sbpf_version,
+            ..Config::default()
+        };
+        let mut loader = BuiltinProgram::new_loader(config);
+        loader
+            .register_function("log", syscalls::SyscallString::vm)
+            .unwrap();
+        let loader = Arc::new(loader);
+        let executable = assemble::<TestContextObject>(&src, loader.clone()).unwrap();
+        let analysis = Analysis::from_executable(&executable).unwrap();
+        let mut reassemblable = Vec::new();
+        analysis
+            .disassemble_with_style(&mut reassemblable, DisassemblyStyle::Reassemblable)
+            .unwrap();
+        let reassemblable = String::from_utf8(reassemblable).unwrap();
+        assert!(!reassemblable.contains("[invalid]"));
+        assert!(reassemblable.contains("    syscall log\n"));
+        let reassembled = assemble::<TestContextObject>(&reassemblable, loader)
+            .unwrap_or_else(|err| panic!("{:?}: {}\n{}", sbpf_version, err, reassemblable));
+        assert_eq!(
+            executable.get_text_bytes().1,
+            reassembled.get_text_bytes().1,
+            "{:?}\n{}",
+            sbpf_version,
+            reassemblable
+        );
+    }
+}
+
+#[test]
+fn test_disassemble_program() {
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+        ..Config::default()
+    };
+    let executable = assemble::<TestContextObject>(
+        "
+    lddw r1, 0x100000000
+    jeq r1, 0, +1
+    call function_foo
+    ja +7
+    exit
+function_foo:
+    exit",
+        Arc::new(BuiltinProgram::new_loader(config)),
+    )
+    .unwrap();
+    assert_eq!(
+        solana_sbpf::disassemble_program(&executable).unwrap(),
+        vec![
+            "entrypoint:",
+            "    lddw r1, 0x100000000",
+            "    jeq r1, 0, lbb_4",
+            "    call function_foo",
+            "lbb_4:",
+            "    ja 12",
+            "",
+            "function_5:",
+            "    exit",
+            "",
+            "function_foo:",
+            "    exit",
+        ]
+    );
+}
+
+#[test]
+fn test_immediate_radix() {
+    let src = "entrypoint:
+    and64 r1, -1
+    mov32 r1, -2147483648
+    add64 r1, 2147483647
+    stw [r1+0x8], -1
+    jeq r1, -2147483648, lbb_6
+    jne r1, 2147483647, lbb_6
+lbb_6:
+    exit
+";
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+        ..Config::default()
+    };
+    let loader = Arc::new(BuiltinProgram::new_loader(config));
+    let executable = assemble::<TestContextObject>(src, loader.clone()).unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let hex = DisassemblyOptions {
+        imm_radix: Radix::Hexadecimal,
+        ..DisassemblyOptions::default()
+    };
+    let disassemble = |options| {
+        let mut output = Vec::new();
+        analysis
+            .disassemble_with_options(&mut output, options)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    };
+    assert_eq!(disassemble(DisassemblyOptions::default()), src);
+    assert_eq!(
+        disassemble(hex),
+        "entrypoint:
+    and64 r1, 0xffffffff
+    mov32 r1, 0x80000000
+    add64 r1, 0x7fffffff
+    stw [r1+0x8], 0xffffffff
+    jeq r1, 0x80000000, lbb_6
+    jne r1, 0x7fffffff, lbb_6
+lbb_6:
+    exit
+"
+    );
+    assert_eq!(
+        analysis
+            .disassemble_instruction_with_options(&analysis.instructions[1], 1, hex)
+            .operands,
+        "r1, 0x80000000"
+    );
+
+    let reassemblable = disassemble(DisassemblyOptions {
+        imm_radix: Radix::Hexadecimal,
+        style: DisassemblyStyle::Reassemblable,
+        ..DisassemblyOptions::default()
+    });
+    assert_eq!(
+        reassemblable,
+        "    and64 r1, -0x1
+    mov32 r1, -0x80000000
+    add64 r1, 0x7fffffff
+    stw [r1+0x8], -0x1
+    jeq r1, -0x80000000, lbb_6
+    jne r1, 0x7fffffff, lbb_6
+lbb_6:
+    exit
+"
+    );
+    let reassembled = assemble::<TestContextObject>(&reassemblable, loader).unwrap();
+    assert_eq!(
+        executable.get_text_bytes().1,
+        reassembled.get_text_bytes().1
+    );
+}
+
+#[test]
+fn test_disassembly_records_json() {
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V3..=SBPFVersion::V3,
+        ..Config::default()
+    };
+    let mut loader = BuiltinProgram::new_loader(config);
+    loader
+        .register_function("log", syscalls::SyscallString::vm)
+        .unwrap();
+    let executable = assemble::<TestContextObject>(
+        "
+entrypoint:
+    mov64 r1, -0x12345678
+    add64 r1, -16
+    mov32 r2, r1
+    ldxw r3, [r1-0x4]
+    stb [r10-0x8], 7
+    stxdw [r10+0x10], r3
+    jne r3, 0, lbb_10
+    call function_foo
+    callx r2
+    syscall log
+lbb_10:
+    return
+function_foo:
+    return",
+        Arc::new(loader),
+    )
+    .unwrap();
+    let records = disassemble_program_records(&executable).unwrap();
+    assert_eq!(
+        records[0],
+        DisassemblyRecord {
+            pc: 0,
+            opcode: 0xb7,
+            dst: 1,
+            src: 0,
+            off: 0,
+            imm: -0x12345678,
+            mnemonic: "mov64",
+            target: None,
+        }
+    );
+    assert_eq!(
+        records
+            .iter()
+            .map(|record| (record.mnemonic, record.target.as_deref()))
+            .collect::<Vec<_>>(),
+        vec![
+            ("mov64", None),
+            ("add64", None),
+            ("mov32", None),
+            ("ldxw", None),
+            ("stb", None),
+            ("stxdw", None),
+            ("jne", Some("lbb_10")),
+            ("call", Some("function_foo")),
+            ("callx", None),
+            ("syscall", Some("log")),
+            ("return", None),
+            ("return", None),
+        ]
+    );
+
+    let parsed = json::parse(&disassembly_records_to_json(&records)).unwrap();
+    assert_eq!(parsed.len(), records.len());
+    for (record, parsed) in records.iter().zip(parsed.members()) {
+        assert_eq!(parsed["pc"].as_usize(), Some(record.pc));
+        assert_eq!(parsed["opcode"].as_u8(), Some(record.opcode));
+        assert_eq!(parsed["dst"].as_u8(), Some(record.dst));
+        assert_eq!(parsed["src"].as_u8(), Some(record.src));
+        assert_eq!(parsed["off"].as_i16(), Some(record.off));
+        assert_eq!(parsed["imm"].as_i64(), Some(record.imm));
+        assert_eq!(parsed["mnemonic"].as_str(), Some(record.mnemonic));
+        assert_eq!(parsed["target"].as_str(), record.target.as_deref());
+    }
+}
+
+#[test]
+fn test_callx_known_targets() {
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+        ..Config::default()
+    };
+    let executable = assemble::<TestContextObject>(
+        "
+    lddw r5, 0x100000040
+    callx r5
+    lddw r5, 0x100000040
+    add64 r5, 8
+    callx r5
+    exit
+    lddw r6, 0x100000068
+    mov64 r5, r6
+    callx r5
+    exit
+    exit",
+        Arc::new(BuiltinProgram::new_loader(config)),
+    )
+    .unwrap();
+    assert_eq!(
+        solana_sbpf::disassemble_program(&executable).unwrap(),
+        vec![
+            "entrypoint:",
+            "    lddw r5, 0x100000040",
+            "    callx r5 ; -> function_8",
+            "    lddw r5, 0x100000040",
+            "    add64 r5, 8",
+            "    callx r5",
+            "    exit",
+            "",
+            "function_8:",
+            "    lddw r6, 0x100000068",
+            "    mov64 r5, r6",
+            "    callx r5 ; -> function_13",
+            "    exit",
+            "",
+            "function_13:",
+            "    exit",
+        ]
+    );
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let indirect_call_targets = analysis
+        .cfg_nodes
+        .iter()
+        .filter_map(|(pc, cfg_node)| Some((*pc, cfg_node.indirect_call_target?)))
+        .collect::<Vec<_>>();
+    assert_eq!(indirect_call_targets, vec![(0, 8), (8, 13)]);
+
+    // Since SBPFv2 the program starts at address 0 and callx uses the src register
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V3..=SBPFVersion::V3,
+        ..Config::default()
+    };
+    let executable = assemble::<TestContextObject>(
+        "
+    mov32 r5, 0x38
+    hor64 r5, 0
+    callx r5
+    mov64 r5, 0x38
+    mov32 r5, r1
+    callx r5
+    return
+    return",
+        Arc::new(BuiltinProgram::new_loader(config)),
+    )
+    .unwrap();
+    assert_eq!(
+        solana_sbpf::disassemble_program(&executable).unwrap(),
+        vec![
+            "entrypoint:",
+            "    mov32 r5, 56",
+            "    hor64 r5, 0",
+            "    callx r5 ; -> function_7",
+            "    mov64 r5, 56",
+            "    mov32 r5, r1",
+            "    callx r5",
+            "    return",
+            "",
+            "function_7:",
+            "    return",
+        ]
+    );
+}
+
+#[test]
+fn test_memory_widths() {
+    let src = "entrypoint:
+    ldxb r1, [r2+0x10]
+    ldxh r1, [r2+0x10]
+    ldxw r1, [r2+0x10]
+    ldxdw r1, [r2+0x10]
+    stb [r1+0x8], 1
+    sth [r1+0x8], 1
+    stw [r1+0x8], 1
+    stdw [r1+0x8], 1
+    stxb [r1+0x8], r2
+    stxh [r1+0x8], r2
+    stxw [r1+0x8], r2
+    stxdw [r1+0x8], r2
+    return
+";
+    let disassemble = |sbpf_version: SBPFVersion, options| {
+        let config = Config {
+            enabled_sbpf_versions: sbpf_version..=sbpf_version,
+            ..Config::default()
+        };
+        let executable =
+            assemble::<TestContextObject>(src, Arc::new(BuiltinProgram::new_loader(config)))
+                .unwrap();
+        let analysis = Analysis::from_executable(&executable).unwrap();
+        let mut output = Vec::new();
+        analysis
+            .disassemble_with_options(&mut output, options)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    };
+    let memory_widths = DisassemblyOptions {
+        memory_widths: true,
+        ..DisassemblyOptions::default()
+    };
+    assert_eq!(
+        disassemble(SBPFVersion::V3, DisassemblyOptions::default()),
+        src
+    );
+    assert_eq!(
+        disassemble(SBPFVersion::V1, memory_widths),
+        "entrypoint:
+    ldxb r1, [r2+0x10] ; 1 byte, opcode 0x71
+    ldxh r1, [r2+0x10] ; 2 bytes, opcode 0x69
+    ldxw r1, [r2+0x10] ; 4 bytes, opcode 0x61
+    ldxdw r1, [r2+0x10] ; 8 bytes, opcode 0x79
+    stb [r1+0x8], 1 ; 1 byte, opcode 0x72
+    sth [r1+0x8], 1 ; 2 bytes, opcode 0x6a
+    stw [r1+0x8], 1 ; 4 bytes, opcode 0x62
+    stdw [r1+0x8], 1 ; 8 bytes, opcode 0x7a
+    stxb [r1+0x8], r2 ; 1 byte, opcode 0x73
+    stxh [r1+0x8], r2 ; 2 bytes, opcode 0x6b
+    stxw [r1+0x8], r2 ; 4 bytes, opcode 0x63
+    stxdw [r1+0x8], r2 ; 8 bytes, opcode 0x7b
+    exit
+"
+    );
+    assert_eq!(
+        disassemble(SBPFVersion::V3, memory_widths),
+        "entrypoint:
+    ldxb r1, [r2+0x10] ; 1 byte, opcode 0x2c
+    ldxh r1, [r2+0x10] ; 2 bytes, opcode 0x3c
+    ldxw r1, [r2+0x10] ; 4 bytes, opcode 0x8c
+    ldxdw r1, [r2+0x10] ; 8 bytes, opcode 0x9c
+    stb [r1+0x8], 1 ; 1 byte, opcode 0x27
+    sth [r1+0x8], 1 ; 2 bytes, opcode 0x37
+    stw [r1+0x8], 1 ; 4 bytes, opcode 0x87
+    stdw [r1+0x8], 1 ; 8 bytes, opcode 0x97
+    stxb [r1+0x8], r2 ; 1 byte, opcode 0x2f
+    stxh [r1+0x8], r2 ; 2 bytes, opcode 0x3f
+    stxw [r1+0x8], r2 ; 4 bytes, opcode 0x8f
+    stxdw [r1+0x8], r2 ; 8 bytes, opcode 0x9f
+    return
+"
+    );
+    let reassemblable = DisassemblyOptions {
+        style: DisassemblyStyle::Reassemblable,
+        ..memory_widths
+    };
+    assert!(!disassemble(SBPFVersion::V3, reassemblable).contains(';'));
+}
+
+#[test]
+fn test_decode() {
+    let program = [
+        0x18, 0x01, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, // lddw r1, 0x2000000010
+        0x00, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00, 0x00, //
+        0x61, 0x21, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, // ldxw r1, [r2+0x10]
+        0x15, 0x01, 0xfe, 0xff, 0x02, 0x00, 0x00, 0x00, // jeq r1, 2, -2
+        0x85, 0x00, 0x00, 0x00, 0x2a, 0x00, 0x00, 0x00, // call 0x2a
+        0xd4, 0x01, 0x00, 0x00, 0x07, 0x00, 0x00, 0x00, // le r1 with width 7
+        0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // unknown opcode
+    ];
+    let mut lddw = ebpf::get_insn(&program, 0);
+    ebpf::augment_lddw_unchecked(&program, &mut lddw);
+    let decoded = decode(&lddw, SBPFVersion::V0);
+    assert_eq!(decoded.mnemonic, "lddw");
+    assert_eq!(
+        decoded.operands,
+        Operands::Immediate64 {
+            dst: 1,
+            imm: 0x2000000010
+        }
+    );
+    assert_eq!(decoded.to_string(), "lddw r1, 0x2000000010");
+
+    let decoded = (2..7)
+        .map(|pc| {
+            let insn = ebpf::get_insn(&program, pc);
+            let decoded = decode(&insn, SBPFVersion::V0);
+            (decoded.operands, decoded.to_string())
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        decoded,
+        vec![
+            (
+                Operands::Load {
+                    dst: 1,
+                    src: 2,
+                    off: 0x10
+                },
+                "ldxw r1, [r2+0x10]".to_string()
+            ),
+            (
+                Operands::JumpImmediate {
+                    dst: 1,
+                    imm: 2,
+                    target_pc: 2
+                },
+                "jeq r1, 2, 2".to_string()
+            ),
+            (Operands::Call { key: 0x2a }, "call 0x2a".to_string()),
+            (
+                Operands::InvalidByteSwap { dst: 1, imm: 7 },
+                "le?? r1, imm=7".to_string()
+            ),
+            (
+                Operands::Unknown { opc: 0x06 },
+                "unknown opcode=0x6".to_string()
+            ),
+        ]
+    );
+
+    // The same opcode decodes differently across SBPF versions
+    let insn = ebpf::get_insn(&program, 2);
+    assert_eq!(decode(&insn, SBPFVersion::V3).mnemonic, "unknown");
+}
+
+#[test]
+fn test_visualize_graphically() {
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V3..=SBPFVersion::V3,
+        ..Config::default()
+    };
+    let mut loader = BuiltinProgram::new_loader(config.clone());
+    loader
+        .register_function("log", syscalls::SyscallString::vm)
+        .unwrap();
+    let executable = assemble::<TestContextObject>(
+        "
+    jeq r1, 0, +2
+    ja +2
+    mov64 r0, 1
+    syscall log
+    call function_foo
+    mov64 r0, 0
+    return
+function_foo:
+    return",
+        Arc::new(loader),
+    )
+    .unwrap();
+
+    // The assembler does not accept quotes and braces in syscall names, patch the key instead
+    let name = "log{\"x\"}";
+    let mut text = executable.get_text_bytes().1.to_vec();
+    text[3 * ebpf::INSN_SIZE + 4..4 * ebpf::INSN_SIZE]
+        .copy_from_slice(&ebpf::hash_symbol_name(name.as_bytes()).to_le_bytes());
+    let mut loader = BuiltinProgram::new_loader(config);
+    loader
+        .register_function(name, syscalls::SyscallString::vm)
+        .unwrap();
+    let mut function_registry = FunctionRegistry::default();
+    function_registry
+        .register_function(7, "function_foo", 7)
+        .unwrap();
+    let executable = Executable::<TestContextObject>::from_text_bytes(
+        &text,
+        Arc::new(loader),
+        SBPFVersion::V3,
+        function_registry,
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let mut output = Vec::new();
+    analysis.visualize_graphically(&mut output, None).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        include_str!("golden/visualize_graphically.dot")
+    );
+}
+
+#[test]
+fn test_loops() {
+    let loader = Arc::new(BuiltinProgram::new_loader(Config {
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+        ..Config::default()
+    }));
+
+    // Counted loop
+    let executable = assemble::<TestContextObject>(
+        "
+    mov64 r1, 10
+    sub64 r1, 1
+    jne r1, 0, -2
+    exit",
+        loader.clone(),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    assert_eq!(
+        analysis.dominators(),
+        &BTreeMap::from([(0, analysis.super_root), (1, 0), (3, 1)])
+    );
+    assert_eq!(
+        analysis.loops(),
+        vec![Loop {
+            header: 1,
+            body: BTreeSet::from([1]),
+        }]
+    );
+    assert!(analysis.irreducible_cycles().is_empty());
+
+    // Nested loops
+    let executable = assemble::<TestContextObject>(
+        "
+    mov64 r1, 3
+    mov64 r2, 3
+    sub64 r2, 1
+    jne r2, 0, -2
+    sub64 r1, 1
+    jne r1, 0, -5
+    exit",
+        loader.clone(),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    assert_eq!(
+        analysis.dominators(),
+        &BTreeMap::from([(0, analysis.super_root), (1, 0), (2, 1), (4, 2), (6, 4)])
+    );
+    assert_eq!(
+        analysis.loops(),
+        vec![
+            Loop {
+                header: 1,
+                body: BTreeSet::from([1, 2, 4]),
+            },
+            Loop {
+                header: 2,
+                body: BTreeSet::from([2]),
+            },
+        ]
+    );
+    assert!(analysis.irreducible_cycles().is_empty());
+
+    // Jump into the body of a loop, neither block of the cycle dominates the other
+    let executable = assemble::<TestContextObject>(
+        "
+    jeq r1, 0, +2
+    add64 r0, 1
+    add64 r0, 2
+    sub64 r1, 1
+    jne r1, 0, -4
+    exit",
+        loader,
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    assert_eq!(
+        analysis.dominators(),
+        &BTreeMap::from([(0, analysis.super_root), (1, 0), (3, 0), (5, 3)])
+    );
+    assert!(analysis.loops().is_empty());
+    assert_eq!(
+        analysis.irreducible_cycles(),
+        vec![IrreducibleCycle {
+            entries: BTreeSet::from([1, 3]),
+            body: BTreeSet::from([1, 3]),
+        }]
+    );
+}
+
+#[test]
+fn test_liveness() {
+    let loader = Arc::new(BuiltinProgram::new_loader(Config {
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+        ..Config::default()
+    }));
+
+    // r2 is only live along the taken edge of the branch
+    let executable = assemble::<TestContextObject>(
+        "
+    mov64 r2, 5
+    jeq r1, 0, +1
+    exit
+    mov64 r0, r2
+    exit",
+        loader.clone(),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    assert_eq!(
+        analysis.liveness(),
+        BTreeMap::from([
+            (0, BTreeSet::from([0, 1, 2])),
+            (1, BTreeSet::from([0, 2])),
+            (2, BTreeSet::new()),
+            (3, BTreeSet::from([0])),
+            (4, BTreeSet::new()),
+        ])
+    );
+    assert!(analysis.dead_stores().is_empty());
+
+    // The first write to r6 is overwritten, r1 is an argument of the call
+    let executable = assemble::<TestContextObject>(
+        "
+    mov64 r6, 1
+    mov64 r6, 2
+    mov64 r1, r6
+    call function_foo
+    exit
+function_foo:
+    mov64 r0, r1
+    exit",
+        loader,
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let liveness = analysis.liveness();
+    assert_eq!(liveness[&2], BTreeSet::from([1, 2, 3, 4, 5]));
+    assert_eq!(liveness[&3], BTreeSet::from([0]));
+    assert_eq!(analysis.dead_stores(), vec![0]);
+}
+
+#[test]
+fn test_call_graph() {
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+        ..Config::default()
+    };
+    let mut loader = BuiltinProgram::new_loader(config.clone());
+    loader
+        .register_function("log", syscalls::SyscallString::vm)
+        .unwrap();
+    let loader = Arc::new(loader);
+
+    // The callx in function_bar is resolved, the one in function_foo is not
+    let executable = assemble::<TestContextObject>(
+        "
+    call function_foo
+    call function_bar
+    exit
+function_foo:
+    callx r1
+    call function_bar
+    exit
+function_bar:
+    syscall log
+    lddw r5, 0x100000058
+    callx r5
+    exit
+function_baz:
+    exit",
+        loader.clone(),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let key = ebpf::hash_symbol_name(b"log");
+    assert_eq!(
+        analysis.call_graph(),
+        BTreeMap::from([
+            (
+                0,
+                FunctionCalls {
+                    functions: BTreeSet::from([3, 6]),
+                    ..FunctionCalls::default()
+                }
+            ),
+            (
+                3,
+                FunctionCalls {
+                    functions: BTreeSet::from([6]),
+                    unknown_callees: vec![3],
+                    ..FunctionCalls::default()
+                }
+            ),
+            (
+                6,
+                FunctionCalls {
+                    functions: BTreeSet::from([11]),
+                    syscalls: BTreeSet::from([key]),
+                    ..FunctionCalls::default()
+                }
+            ),
+            (11, FunctionCalls::default()),
+        ])
+    );
+    assert!(!analysis.is_recursive());
+    assert_eq!(analysis.max_call_depth(), Some(CallDepth::AtLeast(4)));
+
+    // Without the unresolved callx the depth is exact
+    let executable = assemble::<TestContextObject>(
+        "
+    call function_foo
+    exit
+function_foo:
+    exit",
+        loader.clone(),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    assert!(!analysis.is_recursive());
+    assert_eq!(analysis.max_call_depth(), Some(CallDepth::Exact(2)));
+
+    // Mutual recursion
+    let executable = assemble::<TestContextObject>(
+        "
+    call function_foo
+    exit
+function_foo:
+    call function_bar
+    exit
+function_bar:
+    call function_foo
+    exit",
+        loader,
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    assert!(analysis.is_recursive());
+    assert_eq!(analysis.max_call_depth(), None);
+}
+
+#[test]
+fn test_block_costs() {
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+        ..Config::default()
+    };
+    let executable = assemble::<TestContextObject>(
+        "
+    lddw r1, 0x1
+    jeq r1, 0, +1
+    call function_foo
+    exit
+function_foo:
+    mov64 r0, 0
+    exit",
+        Arc::new(BuiltinProgram::new_loader(config)),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    assert_eq!(
+        analysis.block_costs(),
+        BTreeMap::from([(0, 2), (3, 1), (4, 1), (5, 2)])
+    );
+    assert_eq!(analysis.function_costs(), BTreeMap::from([(0, 4), (5, 2)]));
+    let mut output = Vec::new();
+    analysis
+        .disassemble_with_options(
+            &mut output,
+            DisassemblyOptions {
+                block_costs: true,
+                ..DisassemblyOptions::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "entrypoint:
+    ; block cost: 2
+    lddw r1, 0x1
+    jeq r1, 0, lbb_4
+    ; block cost: 1
+    call function_foo
+lbb_4:
+    ; block cost: 1
+    exit
+
+function_foo:
+    ; block cost: 2
+    mov64 r0, 0
+    exit
+"
+    );
+}
+
+#[test]
+fn test_recover_functions() {
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V3..=SBPFVersion::V3,
+        ..Config::default()
+    };
+    let loader = Arc::new(BuiltinProgram::new_loader(config));
+    let executable = assemble::<TestContextObject>(
+        "
+    call function_foo
+    mov64 r0, 0
+    return
+function_foo:
+    jeq r1, 0, function_bar
+    mov64 r0, 2
+    return
+function_bar:
+    mov64 r0, 1
+    return",
+        loader.clone(),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let known_starts = analysis.functions.keys().copied().collect::<Vec<_>>();
+    assert_eq!(known_starts, vec![0, 3, 6]);
+
+    // Strip the symbols, leaving only the entrypoint
+    let (_program_vm_addr, text) = executable.get_text_bytes();
+    let stripped = Executable::<TestContextObject>::from_text_bytes(
+        text,
+        loader,
+        SBPFVersion::V3,
+        FunctionRegistry::default(),
+    )
+    .unwrap();
+    let mut analysis = Analysis::from_executable(&stripped).unwrap();
+    analysis.recover_functions();
+    assert_eq!(
+        analysis.functions.keys().copied().collect::<Vec<_>>(),
+        known_starts
+    );
+    let mut output = Vec::new();
+    analysis.disassemble(&mut output).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "entrypoint:
+    call fn_3
+    mov64 r0, 0
+    return
+
+fn_3:
+    jeq r1, 0, fn_6
+    mov64 r0, 2
+    return
+
+fn_6:
+    mov64 r0, 1
+    return
+"
+    );
+}
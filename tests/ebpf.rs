@@ -0,0 +1,121 @@
+// Copyright 2026 Solana Maintainers <maintainers@solana.com>
+//
+// Licensed under the Apache License, Version 2.0 <http://www.apache.org/licenses/LICENSE-2.0> or
+// the MIT license <http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use solana_sbpf::{
+    disassembler::{decode, Operands},
+    ebpf::{self, Insn},
+    error::EbpfError,
+    program::SBPFVersion,
+};
+
+/// Every opcode which decodes to a known instruction in any version
+fn defined_opcodes() -> Vec<u8> {
+    (0..=u8::MAX)
+        .filter(|opc| {
+            [SBPFVersion::V0, SBPFVersion::V3]
+                .iter()
+                .any(|sbpf_version| {
+                    let insn = Insn {
+                        opc: *opc,
+                        ..Insn::default()
+                    };
+                    !matches!(
+                        decode(&insn, *sbpf_version).operands,
+                        Operands::Unknown { .. }
+                    )
+                })
+        })
+        .collect()
+}
+
+fn random_insn(prng: &mut SmallRng, opc: u8) -> Insn {
+    Insn::new(
+        opc,
+        prng.random_range(0..=ebpf::FRAME_PTR_REG as u8),
+        prng.random_range(0..=ebpf::FRAME_PTR_REG as u8),
+        prng.random(),
+        prng.random(),
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_insn_round_trip() {
+    let opcodes = defined_opcodes();
+    assert!(opcodes.contains(&ebpf::LD_DW_IMM));
+    assert!(opcodes.contains(&ebpf::ADD64_IMM));
+    assert!(opcodes.contains(&ebpf::EXIT));
+    assert!(!opcodes.contains(&0));
+    let mut prng = SmallRng::seed_from_u64(0);
+    for _ in 0..64 {
+        for opc in opcodes.iter() {
+            let insn = random_insn(&mut prng, *opc);
+            assert_eq!(ebpf::get_insn(&insn.to_array(), 0), insn);
+            assert_eq!(insn.to_vec(), insn.to_array());
+        }
+    }
+}
+
+#[test]
+fn test_encode_program_round_trip() {
+    let mut prng = SmallRng::seed_from_u64(0);
+    let opcodes = defined_opcodes();
+    let mut insns = Vec::new();
+    let mut lddw_values = Vec::new();
+    for _ in 0..1024 {
+        let opc = opcodes[prng.random_range(0..opcodes.len())];
+        if opc == ebpf::LD_DW_IMM {
+            let value = prng.random::<u64>();
+            lddw_values.push((insns.len(), value));
+            insns.extend(Insn::new_lddw(prng.random_range(0..=10), value).unwrap());
+        } else {
+            insns.push(random_insn(&mut prng, opc));
+        }
+    }
+    let prog = ebpf::encode_program(&insns);
+    assert_eq!(prog.len(), insns.len() * ebpf::INSN_SIZE);
+    for (pc, insn) in insns.iter().enumerate() {
+        let decoded = ebpf::get_insn(&prog, pc);
+        assert_eq!(decoded.ptr, pc);
+        assert_eq!(
+            Insn {
+                ptr: insn.ptr,
+                ..decoded
+            },
+            *insn
+        );
+    }
+    assert!(!lddw_values.is_empty());
+    for (pc, value) in lddw_values {
+        let mut insn = ebpf::get_insn(&prog, pc);
+        ebpf::augment_lddw_unchecked(&prog, &mut insn);
+        assert_eq!(insn.imm as u64, value);
+    }
+}
+
+#[test]
+fn test_insn_new_rejects_registers() {
+    for register in ebpf::FRAME_PTR_REG as u8 + 1..=u8::MAX {
+        assert!(matches!(
+            Insn::new(ebpf::MOV64_REG, register, 0, 0, 0),
+            Err(EbpfError::InvalidRegister(r)) if r == register
+        ));
+        assert!(matches!(
+            Insn::new(ebpf::MOV64_REG, 0, register, 0, 0),
+            Err(EbpfError::InvalidRegister(r)) if r == register
+        ));
+        assert!(matches!(
+            Insn::new_lddw(register, 0),
+            Err(EbpfError::InvalidRegister(r)) if r == register
+        ));
+    }
+    let insn = Insn::new(ebpf::MOV64_REG, 10, 10, -1, i32::MIN).unwrap();
+    assert_eq!(
+        insn.to_array(),
+        [0xbf, 0xaa, 0xff, 0xff, 0x00, 0x00, 0x00, 0x80]
+    );
+}
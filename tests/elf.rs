@@ -3,18 +3,30 @@
 use byteorder::{ByteOrder, LittleEndian};
 use solana_sbpf::{
     ebpf,
-    elf::{get_ro_region, ElfError, Executable, Section},
+    elf::{get_ro_region, ElfError, Executable, Section, SkippedRelocation},
     elf_parser::{
-        consts::{ELFCLASS32, ELFCLASS64, ELFDATA2LSB, ELFDATA2MSB, ELFOSABI_NONE, EM_BPF, ET_REL},
+        consts::{
+            DT_NULL, DT_REL, DT_RELA, DT_RELAENT, DT_RELASZ, DT_RELENT, DT_RELSZ, DT_STRSZ,
+            DT_STRTAB, DT_SYMENT, DT_SYMTAB, ELFCLASS32, ELFCLASS64, ELFDATA2LSB, ELFDATA2MSB,
+            ELFOSABI_NONE, EM_BPF, ET_REL, NT_GNU_BUILD_ID, PF_R, PF_W, PF_X, PT_DYNAMIC,
+            PT_GNU_STACK, PT_NOTE, PT_TLS, R_X86_64_32, SHF_ALLOC, SHN_ABS, SHN_XINDEX, SHT_NOTE,
+            SHT_PROGBITS,
+        },
+        debug_line::{DebugLine, LineRow},
+        eh_frame::CfaRule,
+        note::Note,
+        owned::Elf64Owned,
         types::{Elf64Ehdr, Elf64Phdr, Elf64Shdr, Elf64Sym},
-        Elf64, ElfParserError, SECTION_NAME_LENGTH_MAXIMUM,
+        writer::ElfPatcher,
+        AlignedElfBytes, Elf64, ElfParserError, ElfParserOptions, SECTION_NAME_LENGTH_MAXIMUM,
     },
     memory_region::{AccessType, MemoryMapping},
-    program::{BuiltinProgram, SBPFVersion},
+    program::{BuiltinProgram, FunctionRegistry, SBPFVersion},
+    verifier::RequisiteVerifier,
     vm::Config,
 };
-use std::{fs::File, io::Read, sync::Arc};
-use test_utils::{assert_error, syscalls, TestContextObject};
+use std::{fs::File, io::Read, mem, sync::Arc};
+use test_utils::{assert_error, elf_fixtures, syscalls, TestContextObject};
 
 type ElfExecutable = Executable<TestContextObject>;
 
@@ -55,7 +67,13 @@ fn test_strict_header() {
 
     // Check that an empty file fails
     let err = ElfExecutable::load_with_strict_parser(&[], loader.clone()).unwrap_err();
-    assert_eq!(err, ElfParserError::OutOfBounds);
+    assert_eq!(
+        err,
+        ElfParserError::OutOfBounds {
+            offset: 0,
+            len: std::mem::size_of::<Elf64Ehdr>()
+        }
+    );
 
     // Break the file header one byte at a time
     let expected_results = std::iter::repeat_n(&Err(ElfParserError::InvalidFileHeader), 40)
@@ -77,56 +95,44 @@ fn test_strict_header() {
     }
 
     // Break the program header table one byte at a time
-    let expected_results_readonly =
-        std::iter::repeat_n(&Err(ElfParserError::InvalidProgramHeader), 48)
-            .chain(std::iter::repeat_n(&Ok(()), 8))
-            .collect::<Vec<_>>();
-    let expected_results_writable =
-        std::iter::repeat_n(&Err(ElfParserError::InvalidProgramHeader), 40)
-            .chain(std::iter::repeat_n(&Ok(()), 4))
-            .chain(std::iter::repeat_n(
-                &Err(ElfParserError::InvalidProgramHeader),
-                4,
-            ))
-            .chain(std::iter::repeat_n(&Ok(()), 8))
-            .collect::<Vec<_>>();
-    let expected_results = vec![
-        expected_results_readonly.iter(),
-        expected_results_readonly.iter(),
-        expected_results_writable.iter(),
-        expected_results_writable.iter(),
-        expected_results_readonly.iter(),
-    ];
-    for (header_index, expected_results) in expected_results.into_iter().enumerate() {
-        for (offset, expected) in (std::mem::size_of::<Elf64Ehdr>()
-            + std::mem::size_of::<Elf64Phdr>() * header_index
-            ..std::mem::size_of::<Elf64Ehdr>()
-                + std::mem::size_of::<Elf64Phdr>() * (header_index + 1))
-            .zip(expected_results)
-        {
+    for index in 0..5 {
+        let header_offset =
+            std::mem::size_of::<Elf64Ehdr>() + std::mem::size_of::<Elf64Phdr>() * index;
+        let writable = index == 2 || index == 3;
+        for offset in header_offset..header_offset + std::mem::size_of::<Elf64Phdr>() {
             let mut elf_bytes = elf_bytes.clone();
             elf_bytes[offset] = 0xAF;
+            // p_align is not checked, neither is the lower half of p_memsz of writable segments
+            let expected = match offset - header_offset {
+                48..=55 => Ok(()),
+                40..=43 if writable => Ok(()),
+                _ => Err(ElfParserError::InvalidProgramHeader { index }),
+            };
             let result =
                 ElfExecutable::load_with_strict_parser(&elf_bytes, loader.clone()).map(|_| ());
-            assert_eq!(&&result, expected);
+            assert_eq!(result, expected);
         }
     }
 
     // Break the dynamic symbol table one byte at a time
     for index in 1..3 {
-        let expected_results = std::iter::repeat_n(&Ok(()), 8)
-            .chain(std::iter::repeat_n(&Err(ElfParserError::OutOfBounds), 8))
-            .chain(std::iter::repeat_n(&Err(ElfParserError::InvalidSize), 1))
-            .chain(std::iter::repeat_n(&Err(ElfParserError::OutOfBounds), 7));
-        for (offset, expected) in (0x1d0 + std::mem::size_of::<Elf64Sym>() * index
-            ..0x1d0 + std::mem::size_of::<Elf64Sym>() * (index + 1))
-            .zip(expected_results)
-        {
+        let symbol_offset = 0x1d0 + std::mem::size_of::<Elf64Sym>() * index;
+        for offset in symbol_offset..symbol_offset + std::mem::size_of::<Elf64Sym>() {
             let mut elf_bytes = elf_bytes.clone();
             elf_bytes[offset] = 0xAF;
+            let read_u64 = |at: usize| LittleEndian::read_u64(&elf_bytes[at..]) as usize;
+            // The symbol range is reported with the corrupted st_value and st_size
+            let expected = match offset - symbol_offset {
+                0..=7 => Ok(()),
+                16 => Err(ElfParserError::InvalidSize),
+                _ => Err(ElfParserError::OutOfBounds {
+                    offset: read_u64(symbol_offset + 8),
+                    len: read_u64(symbol_offset + 16),
+                }),
+            };
             let result =
                 ElfExecutable::load_with_strict_parser(&elf_bytes, loader.clone()).map(|_| ());
-            assert_eq!(&result, expected);
+            assert_eq!(result, expected);
         }
     }
 
@@ -143,7 +149,8 @@ fn test_strict_header() {
         let mut elf_bytes = elf_bytes.clone();
         elf_bytes[0x210] = 0x08;
         let err = ElfExecutable::load_with_strict_parser(&elf_bytes, loader.clone()).unwrap_err();
-        assert_eq!(err, ElfParserError::OutOfBounds);
+        // The last 8 bytes of the bytecode at 40 are not covered any more
+        assert_eq!(err, ElfParserError::OutOfBounds { offset: 40, len: 8 });
     }
 
     // Check that an entrypoint not covered by function symbols fails
@@ -237,6 +244,85 @@ fn test_load_unaligned() {
     ElfExecutable::load(&elf_bytes[1..], loader()).expect("validation failed");
 }
 
+#[test]
+fn test_sbpf_version_e_flags() {
+    const E_FLAGS_OFFSET: usize = 48;
+    let strict_options = ElfParserOptions {
+        reject_unknown_e_flags: true,
+        ..ElfParserOptions::default()
+    };
+
+    // V0 files have no flags
+    let v0_bytes = std::fs::read("tests/elfs/relative_call_sbpfv0.so").unwrap();
+    let aligned = AlignedElfBytes::new(&v0_bytes);
+    let elf = aligned.parse().unwrap();
+    assert_eq!(elf.file_header().e_flags, 0);
+    assert_eq!(elf.sbpf_version(), Ok(SBPFVersion::V0));
+    Elf64::parse_with_options(aligned.as_slice(), strict_options).unwrap();
+
+    // Versions up to V2 keep the layout of V0 files
+    for (e_flags, sbpf_version) in [(1, SBPFVersion::V1), (2, SBPFVersion::V2)] {
+        let mut elf_bytes = v0_bytes.clone();
+        LittleEndian::write_u32(&mut elf_bytes[E_FLAGS_OFFSET..], e_flags);
+        let aligned = AlignedElfBytes::new(&elf_bytes);
+        assert_eq!(aligned.parse().unwrap().sbpf_version(), Ok(sbpf_version));
+        Elf64::parse_with_options(aligned.as_slice(), strict_options).unwrap();
+    }
+
+    // Current version files load in strict mode
+    let v3_bytes = std::fs::read("tests/elfs/strict_header.so").unwrap();
+    let strict_loader = Arc::new(BuiltinProgram::new_loader(Config {
+        reject_unknown_e_flags: true,
+        ..Config::default()
+    }));
+    let executable = ElfExecutable::load(&v3_bytes, strict_loader).unwrap();
+    assert_eq!(executable.get_sbpf_version(), SBPFVersion::V3);
+
+    // Unknown flags are only rejected in strict mode
+    let mut unknown_bytes = v0_bytes.clone();
+    LittleEndian::write_u32(&mut unknown_bytes[E_FLAGS_OFFSET..], 0x10);
+    let aligned = AlignedElfBytes::new(&unknown_bytes);
+    let elf = aligned.parse().unwrap();
+    assert_eq!(elf.sbpf_version(), Err(ElfParserError::InvalidFileHeader));
+    assert_eq!(
+        Elf64::parse_with_options(aligned.as_slice(), strict_options).err(),
+        Some(ElfParserError::InvalidFileHeader)
+    );
+
+    // The loader treats unknown flags as V0 if only V0 is enabled, unless configured otherwise
+    let v0_config = Config {
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+        ..Config::default()
+    };
+    let executable = ElfExecutable::load(
+        &unknown_bytes,
+        Arc::new(BuiltinProgram::new_loader(v0_config.clone())),
+    )
+    .unwrap();
+    assert_eq!(executable.get_sbpf_version(), SBPFVersion::V0);
+    let strict_loader = Arc::new(BuiltinProgram::new_loader(Config {
+        reject_unknown_e_flags: true,
+        ..v0_config
+    }));
+    assert_eq!(
+        ElfExecutable::load(&unknown_bytes, strict_loader).err(),
+        Some(ElfError::FailedToParse("invalid file header".to_string()))
+    );
+
+    // The strict parser rejects unknown flags even if the reserved version is enabled
+    let mut unknown_bytes = v3_bytes.clone();
+    LittleEndian::write_u32(&mut unknown_bytes[E_FLAGS_OFFSET..], 0x10);
+    let strict_loader = Arc::new(BuiltinProgram::new_loader(Config {
+        reject_unknown_e_flags: true,
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::Reserved,
+        ..Config::default()
+    }));
+    assert_eq!(
+        ElfExecutable::load(&unknown_bytes, strict_loader).err(),
+        Some(ElfError::FailedToParse("invalid file header".to_string()))
+    );
+}
+
 #[test]
 fn test_entrypoint() {
     let loader = loader();
@@ -332,7 +418,7 @@ fn test_owned_ro_sections_not_contiguous() {
             sections,
             &elf_bytes,
         ),
-        Ok(Section::Owned(offset, data)) if offset == ebpf::MM_RODATA_START as usize + 10 && data.len() == 30
+        Ok(Section::Owned(offset, data)) if offset == ebpf::MM_RODATA_START + 10 && data.len() == 30
     ));
 }
 
@@ -359,7 +445,7 @@ fn test_owned_ro_sections_with_sh_offset() {
             sections,
             &elf_bytes,
         ),
-        Ok(Section::Owned(offset, data)) if offset == ebpf::MM_RODATA_START as usize + 10 && data.len() == 20
+        Ok(Section::Owned(offset, data)) if offset == ebpf::MM_RODATA_START + 10 && data.len() == 20
     ));
 }
 
@@ -453,10 +539,7 @@ fn test_borrowed_ro_sections_with_constant_sh_offset() {
         [(Some(b".text"), &s1), (Some(b".rodata"), &s2)];
     assert_eq!(
         ElfExecutable::parse_ro_sections(&config, &SBPFVersion::V3, sections, &elf_bytes),
-        Ok(Section::Borrowed(
-            ebpf::MM_RODATA_START as usize + 10,
-            100..120
-        ))
+        Ok(Section::Borrowed(ebpf::MM_RODATA_START + 10, 100..120))
     );
 }
 
@@ -638,7 +721,7 @@ fn test_borrowed_ro_sections_disabled() {
             sections,
             &elf_bytes,
         ),
-        Ok(Section::Owned(offset, data)) if offset == ebpf::MM_RODATA_START as usize && data.len() == 20
+        Ok(Section::Owned(offset, data)) if offset == ebpf::MM_RODATA_START && data.len() == 20
     ));
 }
 
@@ -662,10 +745,7 @@ fn test_borrowed_ro_sections() {
         ];
         assert_eq!(
             ElfExecutable::parse_ro_sections(&config, &sbpf_version, sections, &elf_bytes),
-            Ok(Section::Borrowed(
-                ebpf::MM_RODATA_START as usize + 20,
-                20..50
-            ))
+            Ok(Section::Borrowed(ebpf::MM_RODATA_START + 20, 20..50))
         );
     }
 }
@@ -881,3 +961,1568 @@ fn test_long_section_name() {
         SECTION_NAME_LENGTH_MAXIMUM
     );
 }
+
+/// Replaces the dynamic table with `entries` followed by DT_NULL
+fn rewrite_dynamic_table(elf_bytes: &mut [u8], entries: &[(u64, u64)]) {
+    let (offset, size) = {
+        let elf = Elf64::parse(elf_bytes).unwrap();
+        let header = elf
+            .program_header_table()
+            .iter()
+            .find(|header| header.p_type == PT_DYNAMIC)
+            .unwrap();
+        (header.p_offset as usize, header.p_filesz as usize)
+    };
+    assert!((entries.len() + 1) * 16 <= size);
+    for (index, (tag, value)) in entries.iter().chain([(DT_NULL, 0)].iter()).enumerate() {
+        let entry = offset + index * 16;
+        LittleEndian::write_u64(&mut elf_bytes[entry..entry + 8], *tag);
+        LittleEndian::write_u64(&mut elf_bytes[entry + 8..entry + 16], *value);
+    }
+}
+
+fn write_rela(elf_bytes: &mut [u8], offset: usize, r_offset: u64, r_info: u64, r_addend: i64) {
+    LittleEndian::write_u64(&mut elf_bytes[offset..offset + 8], r_offset);
+    LittleEndian::write_u64(&mut elf_bytes[offset + 8..offset + 16], r_info);
+    LittleEndian::write_i64(&mut elf_bytes[offset + 16..offset + 24], r_addend);
+}
+
+fn lddw_imm(executable: &ElfExecutable, pc: usize) -> u64 {
+    let (_, text_bytes) = executable.get_text_bytes();
+    let offset = pc * ebpf::INSN_SIZE;
+    LittleEndian::read_u32(&text_bytes[offset + 4..offset + 8]) as u64
+        | (LittleEndian::read_u32(&text_bytes[offset + 12..offset + 16]) as u64) << 32
+}
+
+/// reloc_64_relative_sbpfv0.so with its single R_BPF_64_RELATIVE relocation
+/// converted from DT_REL to DT_RELA. The 24 byte entry spills into .comment.
+fn reloc_64_relative_sbpfv0_rela(r_addend: i64) -> Vec<u8> {
+    let mut elf_bytes =
+        std::fs::read("tests/elfs/reloc_64_relative_sbpfv0.so").expect("failed to read elf file");
+    rewrite_dynamic_table(
+        &mut elf_bytes,
+        &[
+            (DT_RELA, 0x238),
+            (DT_RELASZ, 24),
+            (DT_RELAENT, 24),
+            (DT_SYMTAB, 0x1f8),
+            (DT_SYMENT, 24),
+            (DT_STRTAB, 0x228),
+            (DT_STRSZ, 12),
+        ],
+    );
+    write_rela(&mut elf_bytes, 0x238, 0x120, 8, r_addend);
+    // Clear the implicit addend so that only the explicit one can be used
+    elf_bytes[0x124..0x128].fill(0);
+    elf_bytes[0x12c..0x130].fill(0);
+    elf_bytes
+}
+
+#[test]
+fn test_rela_relocation() {
+    let elf_bytes = reloc_64_relative_sbpfv0_rela(0x138);
+    {
+        let elf = Elf64::parse(&elf_bytes).unwrap();
+        assert!(elf.dynamic_relocations_table().is_none());
+        assert_eq!(elf.dynamic_relocations_rela_table().unwrap().len(), 1);
+        let relocations = elf.dynamic_relocations().collect::<Vec<_>>();
+        assert_eq!(relocations.len(), 1);
+        assert_eq!(relocations[0].r_offset(), 0x120);
+        assert_eq!(relocations[0].r_addend(), Some(0x138));
+    }
+    let executable = ElfExecutable::load(&elf_bytes, loader()).unwrap();
+    assert_eq!(lddw_imm(&executable, 0), ebpf::MM_RODATA_START + 0x138);
+}
+
+#[test]
+fn test_rela_relocation_addend_out_of_bounds() {
+    for r_addend in [-1, 0x10_0000, i64::MAX] {
+        let elf_bytes = reloc_64_relative_sbpfv0_rela(r_addend);
+        assert_error!(
+            ElfExecutable::load(&elf_bytes, loader()),
+            "{:?}",
+            ElfError::ValueOutOfBounds
+        );
+    }
+}
+
+#[test]
+fn test_rela_invalid_entry_size() {
+    let mut elf_bytes = reloc_64_relative_sbpfv0_rela(0x138);
+    let elf = Elf64::parse(&elf_bytes).unwrap();
+    let offset = elf
+        .program_header_table()
+        .iter()
+        .find(|header| header.p_type == PT_DYNAMIC)
+        .unwrap()
+        .p_offset as usize;
+    // DT_RELAENT is the third entry
+    LittleEndian::write_u64(&mut elf_bytes[offset + 2 * 16 + 8..offset + 3 * 16], 16);
+    assert_eq!(
+        Elf64::parse(&elf_bytes).err(),
+        Some(ElfParserError::InvalidDynamicSectionTable)
+    );
+}
+
+#[test]
+fn test_rel_and_rela_relocations() {
+    // reloc_64_relative_data_sbpfv0.so has two DT_REL entries: an
+    // R_BPF_64_RELATIVE in .data.rel.ro and an R_BPF_64_64 in .text. Keep the
+    // former as DT_REL and move the latter into a DT_RELA table.
+    let mut elf_bytes = std::fs::read("tests/elfs/reloc_64_relative_data_sbpfv0.so")
+        .expect("failed to read elf file");
+    rewrite_dynamic_table(
+        &mut elf_bytes,
+        &[
+            (DT_REL, 0x278),
+            (DT_RELSZ, 16),
+            (DT_RELENT, 16),
+            (DT_RELA, 0x288),
+            (DT_RELASZ, 24),
+            (DT_RELAENT, 24),
+            (DT_SYMTAB, 0x218),
+            (DT_SYMENT, 24),
+            (DT_STRTAB, 0x260),
+            (DT_STRSZ, 17),
+        ],
+    );
+    write_rela(&mut elf_bytes, 0x288, 0x120, 2 << 32 | 1, 4);
+    // An implicit addend which must be ignored
+    LittleEndian::write_u32(&mut elf_bytes[0x124..0x128], 0x40);
+
+    {
+        let elf = Elf64::parse(&elf_bytes).unwrap();
+        let addends = elf
+            .dynamic_relocations()
+            .map(|relocation| (relocation.r_offset(), relocation.r_addend()))
+            .collect::<Vec<_>>();
+        assert_eq!(addends, vec![(0x160, None), (0x120, Some(4))]);
+    }
+
+    let executable = ElfExecutable::load(&elf_bytes, loader()).unwrap();
+    // FILE is at 0x160
+    assert_eq!(lddw_imm(&executable, 0), ebpf::MM_RODATA_START + 0x164);
+    let config = Config::default();
+    let memory_mapping = MemoryMapping::new(
+        vec![executable.get_ro_region()],
+        &config,
+        executable.get_sbpf_version(),
+    )
+    .unwrap();
+    assert_eq!(
+        memory_mapping
+            .load::<u64>(ebpf::MM_RODATA_START + 0x160)
+            .unwrap(),
+        ebpf::MM_RODATA_START + 0x140
+    );
+}
+
+/// R_BPF_64_ABS64, which the loader does not support
+const R_BPF_64_ABS64: u32 = 2;
+
+/// reloc_64_relative_data_sbpfv0.so with the type of one of its two DT_REL entries replaced by
+/// R_BPF_64_ABS64: the R_BPF_64_RELATIVE in .data.rel.ro (index 0) or the R_BPF_64_64 in .text
+/// (index 1)
+fn reloc_64_relative_data_sbpfv0_unknown_type(index: usize) -> Vec<u8> {
+    let mut elf_bytes = std::fs::read("tests/elfs/reloc_64_relative_data_sbpfv0.so")
+        .expect("failed to read elf file");
+    let r_info = 0x278 + index * 16 + 8;
+    LittleEndian::write_u32(&mut elf_bytes[r_info..r_info + 4], R_BPF_64_ABS64);
+    elf_bytes
+}
+
+#[test]
+fn test_unknown_relocation_type() {
+    let lenient_loader = Arc::new(BuiltinProgram::new_loader(Config {
+        skip_unknown_relocations: true,
+        ..Config::default()
+    }));
+
+    // Relocations which would patch .text are rejected in either mode
+    let elf_bytes = reloc_64_relative_data_sbpfv0_unknown_type(1);
+    for loader in [loader(), lenient_loader.clone()] {
+        assert_eq!(
+            ElfExecutable::load(&elf_bytes, loader).err(),
+            Some(ElfError::UnknownRelocation {
+                index: 1,
+                r_offset: 0x120,
+                r_type: R_BPF_64_ABS64,
+                symbol_name: Some("FILE".to_string()),
+            })
+        );
+    }
+
+    // Relocations of data are only skipped in the lenient mode
+    let elf_bytes = reloc_64_relative_data_sbpfv0_unknown_type(0);
+    let error = ElfExecutable::load(&elf_bytes, loader()).unwrap_err();
+    assert_eq!(
+        error,
+        ElfError::UnknownRelocation {
+            index: 0,
+            r_offset: 0x160,
+            r_type: R_BPF_64_ABS64,
+            symbol_name: None,
+        }
+    );
+    assert_eq!(
+        error.to_string(),
+        "Relocation failed, unknown type 2 of relocation #0 at 0x160 (symbol None)"
+    );
+    let executable = ElfExecutable::load(&elf_bytes, lenient_loader.clone()).unwrap();
+    assert_eq!(
+        executable.get_skipped_relocations(),
+        &[SkippedRelocation {
+            index: 0,
+            r_offset: 0x160,
+            r_type: R_BPF_64_ABS64,
+            symbol_name: None,
+        }]
+    );
+    // The remaining relocation is still applied, the skipped one leaves the data as is
+    assert_eq!(lddw_imm(&executable, 0), ebpf::MM_RODATA_START + 0x160);
+    let memory_mapping = MemoryMapping::new(
+        vec![executable.get_ro_region()],
+        executable.get_config(),
+        executable.get_sbpf_version(),
+    )
+    .unwrap();
+    assert_eq!(
+        memory_mapping
+            .load::<u64>(ebpf::MM_RODATA_START + 0x160)
+            .unwrap(),
+        LittleEndian::read_u64(&elf_bytes[0x160..0x168])
+    );
+
+    // Nothing is skipped unless there is something unknown
+    let elf_bytes = std::fs::read("tests/elfs/reloc_64_relative_data_sbpfv0.so").unwrap();
+    let executable = ElfExecutable::load(&elf_bytes, lenient_loader).unwrap();
+    assert!(executable.get_skipped_relocations().is_empty());
+}
+
+#[test]
+fn test_call_relocations_registry() {
+    let fixture = elf_fixtures::create_elf_with_call_relocations(1000, 7, &["log", "log_64"]);
+    let loader = Arc::new(BuiltinProgram::new_loader(Config {
+        enable_symbol_and_section_labels: true,
+        reject_broken_elfs: true,
+        ..Config::default()
+    }));
+    let mut loader = Arc::try_unwrap(loader).unwrap();
+    loader
+        .register_function("log", syscalls::SyscallString::vm)
+        .unwrap();
+    loader
+        .register_function("log_64", syscalls::SyscallU64::vm)
+        .unwrap();
+    let executable = ElfExecutable::load(&fixture.elf_bytes, Arc::new(loader)).unwrap();
+
+    // Every internal function is registered exactly once, next to the entrypoint
+    let mut expected = fixture
+        .function_pcs
+        .iter()
+        .enumerate()
+        .map(|(i, pc)| {
+            (
+                ebpf::hash_symbol_name(&pc.to_le_bytes()),
+                (format!("function_{i}").into_bytes(), *pc),
+            )
+        })
+        .collect::<Vec<_>>();
+    expected.push((
+        ebpf::hash_symbol_name(b"entrypoint"),
+        (b"entrypoint".to_vec(), 0),
+    ));
+    expected.sort();
+    let registry = executable
+        .get_function_registry()
+        .iter()
+        .map(|(key, (name, pc))| (key, (name.to_vec(), pc)))
+        .collect::<Vec<_>>();
+    assert_eq!(registry, expected);
+
+    // Every call site was patched with the key of its target
+    let (_, text) = executable.get_text_bytes();
+    for (pc, (name, target_pc)) in fixture.call_targets.iter().enumerate() {
+        let insn = ebpf::get_insn(text, pc);
+        let key = match target_pc {
+            Some(target_pc) => ebpf::hash_symbol_name(&target_pc.to_le_bytes()),
+            None => ebpf::hash_symbol_name(name),
+        };
+        assert_eq!(insn.imm as u32, key, "call at pc {}", pc);
+    }
+    assert_eq!(fixture.call_targets.len(), fixture.calls);
+}
+
+#[test]
+fn test_parse_unaligned() {
+    let elf_bytes = std::fs::read("tests/elfs/relative_call_sbpfv0.so").unwrap();
+    let expected = format!("{:?}", Elf64::parse(&elf_bytes).unwrap());
+    let mut buffer = vec![0u8; elf_bytes.len() + 24];
+    let base = buffer.as_ptr().align_offset(16);
+    for offset in 0..8 {
+        let start = base + offset;
+        buffer[start..start + elf_bytes.len()].copy_from_slice(&elf_bytes);
+        let embedded = &buffer[start..start + elf_bytes.len()];
+        let is_aligned = offset % mem::align_of::<Elf64Ehdr>() == 0;
+        if !is_aligned {
+            assert_error!(Elf64::parse(embedded), "InvalidAlignment");
+        }
+
+        let aligned = AlignedElfBytes::new(embedded);
+        assert_eq!(
+            matches!(aligned, AlignedElfBytes::Borrowed(_)),
+            is_aligned && cfg!(target_endian = "little")
+        );
+        let elf = aligned.parse().unwrap();
+        assert_eq!(format!("{:?}", elf), expected);
+        assert_eq!(
+            elf.dynamic_relocations()
+                .map(|relocation| (relocation.r_offset(), relocation.r_type()))
+                .collect::<Vec<_>>(),
+            Elf64::parse(&elf_bytes)
+                .unwrap()
+                .dynamic_relocations()
+                .map(|relocation| (relocation.r_offset(), relocation.r_type()))
+                .collect::<Vec<_>>(),
+        );
+        assert!(elf.find_section(b".text").is_some());
+    }
+}
+
+#[cfg(target_endian = "big")]
+#[test]
+fn test_big_endian_host() {
+    let elf_bytes = std::fs::read("tests/elfs/reloc_64_64_sbpfv0.so").unwrap();
+    let aligned_bytes =
+        solana_sbpf::aligned_memory::AlignedMemory::<{ ebpf::HOST_ALIGN }>::from_slice(&elf_bytes);
+    assert_error!(Elf64::parse(aligned_bytes.as_slice()), "InvalidFileHeader");
+
+    let aligned = AlignedElfBytes::new(aligned_bytes.as_slice());
+    assert!(matches!(aligned, AlignedElfBytes::Copied(_)));
+    let elf = aligned.parse().unwrap();
+    assert_eq!(
+        elf.file_header().e_machine,
+        solana_sbpf::elf_parser::consts::EM_SBPF
+    );
+    assert!(elf.find_section(b".text").is_some());
+    assert!(elf.dynamic_symbol_table().is_some());
+    assert!(elf.dynamic_relocations().next().is_some());
+    assert!(Elf64Owned::parse(&elf_bytes).is_ok());
+    let executable = Executable::<TestContextObject>::from_elf(&elf_bytes, loader()).unwrap();
+    executable.verify::<RequisiteVerifier>().unwrap();
+
+    // The tables only referenced by the dynamic section are converted as well
+    let fixture = elf_fixtures::create_elf_with_gnu_hash(1, 100, &["log"]);
+    let aligned = AlignedElfBytes::new(&fixture.elf_bytes);
+    let elf = aligned.parse().unwrap();
+    assert!(elf.lookup_dynamic_symbol(b"function_99").unwrap().is_some());
+    assert_eq!(
+        elf.dynamic_relocations().count(),
+        fixture.call_targets.len()
+    );
+    assert!(ElfExecutable::load(&fixture.elf_bytes, loader()).is_ok());
+
+    // The stricter layout locates the dynamic symbol table through a program header
+    let elf_bytes = std::fs::read("tests/elfs/strict_header.so").unwrap();
+    let loader = Arc::new(BuiltinProgram::new_loader(Config {
+        enable_symbol_and_section_labels: true,
+        ..Config::default()
+    }));
+    let executable = ElfExecutable::load(&elf_bytes, loader).unwrap();
+    assert_eq!(executable.get_sbpf_version(), SBPFVersion::V3);
+    let (name, _pc) = executable.get_function_registry().lookup_by_key(4).unwrap();
+    assert_eq!(name, b"entrypoint");
+    executable.verify::<RequisiteVerifier>().unwrap();
+}
+
+#[test]
+fn test_parse_error_context() {
+    let elf_bytes = elf_fixtures::create_elf_with_call_relocations(1, 1, &[]).elf_bytes;
+    let elf = Elf64::parse(&elf_bytes).unwrap();
+    let program_header_offset =
+        |index: usize| elf.file_header().e_phoff as usize + mem::size_of::<Elf64Phdr>() * index;
+    let section_header_offset =
+        |index: usize| elf.file_header().e_shoff as usize + mem::size_of::<Elf64Shdr>() * index;
+
+    // PT_LOAD segments with descending addresses
+    let mut corrupt_bytes = elf_bytes.clone();
+    LittleEndian::write_u64(&mut corrupt_bytes[program_header_offset(1) + 16..], 0);
+    let err = Elf64::parse(&corrupt_bytes).unwrap_err();
+    assert_eq!(err, ElfParserError::InvalidProgramHeader { index: 1 });
+    assert_eq!(err.to_string(), "invalid program header #1");
+
+    // Segment extending beyond the end of the file
+    let mut corrupt_bytes = elf_bytes.clone();
+    LittleEndian::write_u64(&mut corrupt_bytes[program_header_offset(1) + 32..], 0x10000);
+    let err = Elf64::parse(&corrupt_bytes).unwrap_err();
+    let p_offset = elf.program_header_table()[1].p_offset as usize;
+    assert_eq!(
+        err,
+        ElfParserError::OutOfBounds {
+            offset: p_offset,
+            len: 0x10000
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        format!("value out of bounds: 65536 bytes at {p_offset:#x}")
+    );
+
+    // Second section named .dynstr
+    let mut corrupt_bytes = elf_bytes.clone();
+    let dynstr_sh_name = elf.section_header_table()[4].sh_name;
+    LittleEndian::write_u32(
+        &mut corrupt_bytes[section_header_offset(5)..],
+        dynstr_sh_name,
+    );
+    let err = Elf64::parse(&corrupt_bytes).unwrap_err();
+    assert_eq!(err, ElfParserError::InvalidSectionHeader { index: 5 });
+    assert_eq!(err.to_string(), "invalid section header #5");
+
+    // Section header of the wrong type
+    let dynsym_section_header = elf.find_section(b".dynsym").unwrap();
+    assert_eq!(
+        Elf64::get_string_in_section(&elf_bytes, dynsym_section_header, 0, 16),
+        Err(ElfParserError::InvalidSectionHeader { index: 3 })
+    );
+}
+
+#[test]
+fn test_vaddr_to_file_offset() {
+    let fixture = elf_fixtures::create_elf_with_call_relocations(4, 2, &[]);
+    let text_addr = fixture.text_addr as usize;
+    let elf = Elf64::parse(&fixture.elf_bytes).unwrap();
+
+    assert_eq!(
+        elf.vaddr_to_file_offset(fixture.text_addr + 8, 16),
+        Ok(text_addr + 8..text_addr + 24)
+    );
+    assert_eq!(
+        elf.bytes_at_vaddr(fixture.text_addr, ebpf::INSN_SIZE)
+            .unwrap(),
+        &fixture.elf_bytes[text_addr..text_addr + ebpf::INSN_SIZE]
+    );
+    assert_eq!(
+        elf.vaddr_to_file_offset(fixture.text_addr, 0),
+        Ok(text_addr..text_addr)
+    );
+
+    // Beyond the end of the text segment
+    let text_len = elf.find_section(b".text").unwrap().sh_size;
+    assert_error!(
+        elf.vaddr_to_file_offset(fixture.text_addr, text_len as usize + 1),
+        "OutOfBounds"
+    );
+    assert_error!(elf.vaddr_to_file_offset(0, 1), "OutOfBounds");
+    assert_error!(elf.bytes_at_vaddr(u64::MAX, 1), "OutOfBounds");
+}
+
+#[test]
+fn test_vaddr_to_file_offset_bss() {
+    let mut fixture = elf_fixtures::create_elf_with_call_relocations(4, 2, &[]);
+    // Grow p_memsz of the data segment beyond its p_filesz
+    let phdr_offset = mem::size_of::<Elf64Ehdr>() + mem::size_of::<Elf64Phdr>();
+    let p_filesz = LittleEndian::read_u64(&fixture.elf_bytes[phdr_offset + 32..]);
+    LittleEndian::write_u64(
+        &mut fixture.elf_bytes[phdr_offset + 40..],
+        p_filesz + 0x1000,
+    );
+    let elf = Elf64::parse(&fixture.elf_bytes).unwrap();
+    let program_header = &elf.program_header_table()[1];
+    let filesz_end = program_header.p_vaddr + program_header.p_filesz;
+
+    assert!(elf.vaddr_to_file_offset(filesz_end - 8, 8).is_ok());
+    assert_error!(elf.vaddr_to_file_offset(filesz_end - 8, 9), "OutOfBounds");
+    assert_error!(elf.vaddr_to_file_offset(filesz_end, 1), "OutOfBounds");
+    assert_error!(elf.bytes_at_vaddr(filesz_end + 0x800, 8), "OutOfBounds");
+}
+
+#[test]
+fn test_vaddr_to_file_offset_section_fallback() {
+    let mut fixture = elf_fixtures::create_elf_with_call_relocations(4, 2, &[]);
+    // Move the data segment away, so that DT_REL is not contained in any program segment
+    let phdr_offset = mem::size_of::<Elf64Ehdr>() + mem::size_of::<Elf64Phdr>();
+    LittleEndian::write_u64(&mut fixture.elf_bytes[phdr_offset + 16..], 0x1_0000_0000);
+    let elf = Elf64::parse(&fixture.elf_bytes).unwrap();
+    assert_eq!(elf.dynamic_relocations().count(), fixture.calls);
+
+    let rel_dyn = elf.find_section(b".rel.dyn").unwrap();
+    let rel_dyn_range = rel_dyn.file_range().unwrap();
+    assert_eq!(
+        elf.vaddr_to_file_offset(rel_dyn.sh_addr, rel_dyn.sh_size as usize),
+        Ok(rel_dyn_range.clone())
+    );
+    assert_eq!(
+        elf.bytes_at_vaddr(rel_dyn.sh_addr, rel_dyn.sh_size as usize)
+            .unwrap(),
+        &fixture.elf_bytes[rel_dyn_range]
+    );
+    assert_error!(
+        elf.vaddr_to_file_offset(rel_dyn.sh_addr, rel_dyn.sh_size as usize + 1),
+        "OutOfBounds"
+    );
+    // Only the start of a section can be translated
+    assert_error!(
+        elf.vaddr_to_file_offset(rel_dyn.sh_addr + 8, 8),
+        "OutOfBounds"
+    );
+}
+
+#[test]
+fn test_exported_functions() {
+    let fixture = elf_fixtures::create_elf_with_call_relocations(3, 2, &["log"]);
+    let elf = Elf64::parse(&fixture.elf_bytes).unwrap();
+    let log = elf.lookup_dynamic_symbol(b"log").unwrap().unwrap();
+    assert!(log.is_global() && log.is_undefined());
+    assert_eq!(
+        elf.exported_functions()
+            .unwrap()
+            .map(|(name, symbol)| (name, symbol.st_value))
+            .collect::<Vec<_>>(),
+        [
+            (
+                &b"function_0"[..],
+                fixture.text_addr + fixture.function_pcs[0] as u64 * ebpf::INSN_SIZE as u64
+            ),
+            (
+                &b"function_1"[..],
+                fixture.text_addr + fixture.function_pcs[1] as u64 * ebpf::INSN_SIZE as u64
+            ),
+        ]
+    );
+
+    // Weak, local and undefined functions are not exported
+    let mut elf_bytes = fixture.elf_bytes.clone();
+    let dynsym_offset = elf.find_section(b".dynsym").unwrap().sh_offset as usize;
+    for (index, st_info) in [(1, 0x22), (2, 0x02), (3, 0x12)] {
+        elf_bytes[dynsym_offset + index * mem::size_of::<Elf64Sym>() + 4] = st_info;
+    }
+    let elf = Elf64::parse(&elf_bytes).unwrap();
+    let symbols = elf.dynamic_symbol_table().unwrap();
+    assert!(symbols[1].is_weak() && !symbols[1].is_undefined());
+    assert!(symbols[3].is_function() && symbols[3].is_undefined());
+    assert_eq!(elf.exported_functions().unwrap().count(), 0);
+}
+
+#[test]
+fn test_elf_patcher() {
+    let fixture = elf_fixtures::create_elf_with_call_relocations(4, 2, &[]);
+    let elf = Elf64::parse(&fixture.elf_bytes).unwrap();
+    let mut patched_bytes = fixture.elf_bytes.clone();
+    assert_error!(
+        ElfPatcher::new(&elf, &mut patched_bytes[1..]),
+        "InvalidSize"
+    );
+    let mut patcher = ElfPatcher::new(&elf, &mut patched_bytes).unwrap();
+    patcher
+        .write_u32_at_vaddr(fixture.text_addr + 4, 0x1234_5678)
+        .unwrap();
+    patcher
+        .write_u64_at_vaddr(fixture.text_addr + 8, 0x0123_4567_89ab_cdef)
+        .unwrap();
+    let dynamic_index = elf
+        .section_headers()
+        .position(|(name, _)| name == Ok(&b".dynamic"[..]))
+        .unwrap();
+    patcher.set_section_flags(dynamic_index, SHF_ALLOC).unwrap();
+
+    // Outside of the file backed bytes of the PT_LOAD segments
+    let text_len = elf.find_section(b".text").unwrap().sh_size;
+    assert_error!(
+        patcher.write_u64_at_vaddr(fixture.text_addr + text_len - 4, 0),
+        "OutOfBounds"
+    );
+    assert_error!(patcher.write_u32_at_vaddr(0, 0), "OutOfBounds");
+    assert_error!(
+        patcher.set_section_flags(elf.section_header_table().len(), 0),
+        "InvalidSectionHeader"
+    );
+    assert_eq!(patcher.bytes().len(), fixture.elf_bytes.len());
+
+    let patched = Elf64::parse(&patched_bytes).unwrap();
+    assert_eq!(
+        patched.bytes_at_vaddr(fixture.text_addr + 4, 4).unwrap(),
+        &0x1234_5678u32.to_le_bytes()
+    );
+    assert_eq!(
+        patched.bytes_at_vaddr(fixture.text_addr + 8, 8).unwrap(),
+        &0x0123_4567_89ab_cdefu64.to_le_bytes()
+    );
+    assert_eq!(
+        patched.bytes_at_vaddr(fixture.text_addr, 4).unwrap(),
+        elf.bytes_at_vaddr(fixture.text_addr, 4).unwrap()
+    );
+    let dynamic = patched.find_section(b".dynamic").unwrap();
+    assert_eq!(dynamic.sh_flags, SHF_ALLOC);
+    assert!(!dynamic.is_writable());
+
+    // The section fallback of Elf64::vaddr_to_file_offset does not apply
+    let mut fixture = fixture;
+    let phdr_offset = mem::size_of::<Elf64Ehdr>() + mem::size_of::<Elf64Phdr>();
+    LittleEndian::write_u64(&mut fixture.elf_bytes[phdr_offset + 16..], 0x1_0000_0000);
+    let elf = Elf64::parse(&fixture.elf_bytes).unwrap();
+    let rel_dyn = elf.find_section(b".rel.dyn").unwrap();
+    assert!(elf.bytes_at_vaddr(rel_dyn.sh_addr, 8).is_ok());
+    let mut patched_bytes = fixture.elf_bytes.clone();
+    let mut patcher = ElfPatcher::new(&elf, &mut patched_bytes).unwrap();
+    assert_error!(
+        patcher.write_u64_at_vaddr(rel_dyn.sh_addr, 0),
+        "OutOfBounds"
+    );
+}
+
+fn recording_loader() -> Arc<BuiltinProgram<TestContextObject>> {
+    let mut loader = BuiltinProgram::new_loader(Config {
+        record_relocation_patches: true,
+        ..Config::default()
+    });
+    loader
+        .register_function("log", syscalls::SyscallString::vm)
+        .unwrap();
+    loader
+        .register_function("log_64", syscalls::SyscallU64::vm)
+        .unwrap();
+    Arc::new(loader)
+}
+
+#[test]
+fn test_relocation_patches() {
+    for file in [
+        "relative_call_sbpfv0",
+        "reloc_64_64_sbpfv0",
+        "reloc_64_relative_sbpfv0",
+        "reloc_64_relative_data_sbpfv0",
+        "rodata_section_sbpfv0",
+        "syscall_reloc_64_32_sbpfv0",
+    ] {
+        let elf_bytes = std::fs::read(format!("tests/elfs/{file}.so")).unwrap();
+        let executable = ElfExecutable::load(&elf_bytes, recording_loader()).unwrap();
+        let patches = executable.get_relocation_patches().unwrap();
+        assert!(!patches.is_empty(), "{}", file);
+        for patch in patches {
+            assert_eq!(patch.old_bytes.len(), patch.new_bytes.len());
+        }
+        executable.verify_relocation_patches(&elf_bytes).unwrap();
+
+        // Without the patches the loaded text and rodata can not be reproduced
+        let unpatched = ElfExecutable::load(&elf_bytes, loader()).unwrap();
+        assert_eq!(unpatched.get_relocation_patches(), None);
+        assert_error!(
+            unpatched.verify_relocation_patches(&elf_bytes),
+            "RelocationPatchesNotRecorded"
+        );
+        let mut tampered = elf_bytes.clone();
+        let patch = &patches[0];
+        tampered[patch.file_offset] ^= 0xff;
+        assert_error!(
+            executable.verify_relocation_patches(&tampered),
+            "RelocationPatchMismatch"
+        );
+    }
+}
+
+#[test]
+fn test_relocation_patches_call_relocations() {
+    let fixture = elf_fixtures::create_elf_with_call_relocations(100, 3, &["log"]);
+    let executable = ElfExecutable::load(&fixture.elf_bytes, recording_loader()).unwrap();
+    let patches = executable.get_relocation_patches().unwrap();
+    assert_eq!(patches.len(), fixture.calls);
+    for (i, (patch, (name, target_pc))) in
+        patches.iter().zip(fixture.call_targets.iter()).enumerate()
+    {
+        assert_eq!(
+            patch.file_offset,
+            fixture.text_addr as usize + i * ebpf::INSN_SIZE + 4
+        );
+        assert_eq!(patch.old_bytes, (-1i32).to_le_bytes());
+        assert_eq!(patch.relocation_type, Some(R_X86_64_32));
+        assert_eq!(patch.symbol_name.as_ref(), Some(name));
+        let key = match target_pc {
+            Some(target_pc) => ebpf::hash_symbol_name(&target_pc.to_le_bytes()),
+            None => ebpf::hash_symbol_name(name),
+        };
+        assert_eq!(patch.new_bytes, key.to_le_bytes());
+    }
+    executable
+        .verify_relocation_patches(&fixture.elf_bytes)
+        .unwrap();
+}
+
+#[test]
+fn test_relocation_patches_strict_parser() {
+    let elf_bytes = std::fs::read("tests/elfs/strict_header.so").unwrap();
+    let executable = ElfExecutable::load(&elf_bytes, recording_loader()).unwrap();
+    assert_eq!(executable.get_relocation_patches(), Some(&[][..]));
+    executable.verify_relocation_patches(&elf_bytes).unwrap();
+}
+
+#[test]
+fn test_lookup_symbol() {
+    let elf_bytes = std::fs::read("tests/elfs/relative_call_sbpfv0.so").unwrap();
+    let elf = Elf64::parse(&elf_bytes).unwrap();
+    let symbol_map = elf.symbol_map().unwrap();
+    let symbols = elf.symbol_table().unwrap().unwrap();
+    assert!(!symbol_map.is_empty());
+    for symbol in symbols {
+        let name = elf.symbol_name(symbol.st_name).unwrap();
+        let first = symbols
+            .iter()
+            .find(|other| elf.symbol_name(other.st_name).unwrap() == name);
+        assert!(std::ptr::eq(
+            elf.lookup_symbol(name).unwrap().unwrap(),
+            first.unwrap()
+        ));
+        assert!(std::ptr::eq(symbol_map[name], first.unwrap()));
+    }
+    assert_eq!(
+        elf.lookup_dynamic_symbol(b"entrypoint")
+            .unwrap()
+            .map(|symbol| symbol.st_value),
+        Some(elf.file_header().e_entry)
+    );
+    assert!(elf.lookup_symbol(b"does_not_exist").unwrap().is_none());
+    assert!(elf.lookup_dynamic_symbol(b"entry").unwrap().is_none());
+    assert!(elf
+        .lookup_symbol(&[b'a'; SECTION_NAME_LENGTH_MAXIMUM * 4])
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn test_lookup_dynamic_symbol_long_name() {
+    let mut fixture = elf_fixtures::create_elf_with_call_relocations(1, 10, &[]);
+    let elf = Elf64::parse(&fixture.elf_bytes).unwrap();
+    let dynstr = elf.find_section(b".dynstr").unwrap().file_range().unwrap();
+    // Join the names of all but the last function into a single one which is too long
+    let last_terminator = dynstr.end - 1;
+    for byte in &mut fixture.elf_bytes[dynstr.start + 1..last_terminator - b"function_9".len()] {
+        if *byte == 0 {
+            *byte = b'_';
+        }
+    }
+
+    let elf = Elf64::parse(&fixture.elf_bytes).unwrap();
+    let symbols = elf.dynamic_symbol_table().unwrap();
+    assert_error!(elf.dynamic_symbol_name(symbols[1].st_name), "StringTooLong");
+    assert!(elf.lookup_dynamic_symbol(b"function_0").unwrap().is_none());
+    assert!(std::ptr::eq(
+        elf.lookup_dynamic_symbol(b"function_9").unwrap().unwrap(),
+        &symbols[10]
+    ));
+}
+
+#[test]
+fn test_relocations() {
+    let mut fixture = elf_fixtures::create_elf_with_call_relocations(6, 2, &["log"]);
+    let elf = Elf64::parse(&fixture.elf_bytes).unwrap();
+    let symbols = elf.dynamic_symbol_table().unwrap();
+    for (index, relocation) in elf.relocations().enumerate() {
+        let relocation = relocation.unwrap();
+        assert_eq!(
+            relocation.r_offset,
+            fixture.text_addr + (index * ebpf::INSN_SIZE) as u64
+        );
+        assert_eq!(relocation.r_type, R_X86_64_32);
+        assert_eq!(relocation.r_addend, None);
+        assert!(std::ptr::eq(
+            relocation.symbol.unwrap(),
+            &symbols[relocation.r_sym as usize]
+        ));
+        assert_eq!(
+            relocation.symbol_name,
+            Some(&fixture.call_targets[index].0[..])
+        );
+    }
+
+    // An out of range symbol index only fails its own relocation
+    let rel_dyn = elf.find_section(b".rel.dyn").unwrap().file_range().unwrap();
+    LittleEndian::write_u32(&mut fixture.elf_bytes[rel_dyn.start + 16 + 12..], 100);
+    let elf = Elf64::parse(&fixture.elf_bytes).unwrap();
+    let relocations = elf.relocations().collect::<Vec<_>>();
+    assert_eq!(relocations.len(), 6);
+    assert_error!(relocations[1], "InvalidSymbolIndex(100)");
+    assert!(relocations
+        .iter()
+        .enumerate()
+        .all(|(index, relocation)| index == 1 || relocation.is_ok()));
+    assert_error!(
+        ElfExecutable::load(&fixture.elf_bytes, loader()),
+        "UnknownSymbol(100)"
+    );
+}
+
+#[test]
+fn test_gnu_hash_table() {
+    let fixture = elf_fixtures::create_elf_with_gnu_hash(1, 1000, &["log", "log_64"]);
+    let elf = Elf64::parse(&fixture.elf_bytes).unwrap();
+    let symbols = elf.dynamic_symbol_table().unwrap();
+    let gnu_hash_table = elf.gnu_hash_table().unwrap().unwrap();
+    assert_eq!(gnu_hash_table.symbol_offset(), 3);
+    let names = elf.find_section(b".dynstr").unwrap().file_range().unwrap();
+    let names = &fixture.elf_bytes[names];
+
+    // Every symbol is found through the table and agrees with the linear search
+    let linear_fixture =
+        elf_fixtures::create_elf_with_call_relocations(1, 1000, &["log", "log_64"]);
+    let linear_elf = Elf64::parse(&linear_fixture.elf_bytes).unwrap();
+    assert!(linear_elf.gnu_hash_table().unwrap().is_none());
+    for symbol in &symbols[gnu_hash_table.symbol_offset()..] {
+        let name = elf.dynamic_symbol_name(symbol.st_name).unwrap();
+        assert!(std::ptr::eq(
+            gnu_hash_table.lookup(name, symbols, names).unwrap(),
+            symbol
+        ));
+        assert!(std::ptr::eq(
+            elf.lookup_dynamic_symbol(name).unwrap().unwrap(),
+            symbol
+        ));
+        assert_eq!(
+            linear_elf
+                .lookup_dynamic_symbol(name)
+                .unwrap()
+                .map(|symbol| symbol.st_value),
+            Some(symbol.st_value)
+        );
+    }
+
+    // The unhashed symbols are found by the linear search
+    assert!(gnu_hash_table.lookup(b"log", symbols, names).is_none());
+    assert!(std::ptr::eq(
+        elf.lookup_dynamic_symbol(b"log").unwrap().unwrap(),
+        &symbols[1]
+    ));
+    assert!(elf
+        .lookup_dynamic_symbol(b"function_1000")
+        .unwrap()
+        .is_none());
+
+    // The relocations still resolve to the right functions
+    let executable = ElfExecutable::load(&fixture.elf_bytes, loader()).unwrap();
+    let target_pc = fixture.call_targets[0].1;
+    assert!(executable
+        .get_function_registry()
+        .lookup_by_key(ebpf::hash_symbol_name(&target_pc.unwrap().to_le_bytes()))
+        .is_some());
+}
+
+#[test]
+fn test_gnu_hash_table_truncated() {
+    let mut fixture = elf_fixtures::create_elf_with_gnu_hash(1, 100, &[]);
+    let elf = Elf64::parse(&fixture.elf_bytes).unwrap();
+    let gnu_hash = elf
+        .find_section(b".gnu.hash")
+        .unwrap()
+        .file_range()
+        .unwrap();
+    let p_offset = elf.program_header_table()[1].p_offset;
+
+    // Cut off the last chain entry
+    let phdr_offset = mem::size_of::<Elf64Ehdr>() + mem::size_of::<Elf64Phdr>();
+    for field_offset in [32, 40].iter().copied() {
+        LittleEndian::write_u64(
+            &mut fixture.elf_bytes[phdr_offset + field_offset..],
+            (gnu_hash.end - 1) as u64 - p_offset,
+        );
+    }
+    let elf = Elf64::parse(&fixture.elf_bytes).unwrap();
+    assert_error!(elf.gnu_hash_table(), "OutOfBounds");
+    assert_error!(elf.lookup_dynamic_symbol(b"function_0"), "OutOfBounds");
+}
+
+#[test]
+fn test_eh_frame_entries() {
+    #[rustfmt::skip]
+    let eh_frame = [
+        // CIE: version 1, augmentation "zR", code alignment 1, data alignment -8, return
+        // address register 10, pc-relative sdata4 pointers, DW_CFA_def_cfa r10+0
+        16, 0, 0, 0, 0, 0, 0, 0, 1, b'z', b'R', 0, 1, 0x78, 10, 1, 0x1b, 0x0c, 10, 0,
+        // FDE: pc -0x10 relative to the field, 16 bytes, DW_CFA_def_cfa_offset 64,
+        // DW_CFA_advance_loc 1, DW_CFA_def_cfa_offset 128, DW_CFA_offset r6
+        24, 0, 0, 0, 24, 0, 0, 0, 0xf0, 0xff, 0xff, 0xff, 16, 0, 0, 0, 0,
+        0x0e, 64, 0x41, 0x0e, 0x80, 0x01, 0x86, 0x02, 0, 0, 0,
+        // CIE: version 3, no augmentation, code alignment 8, absolute pointers,
+        // DW_CFA_def_cfa r10+16
+        12, 0, 0, 0, 0, 0, 0, 0, 3, 0, 8, 0x78, 10, 0x0c, 10, 16,
+        // FDE: pc 0x1000, 0x40 bytes, DW_CFA_def_cfa_register r6, DW_CFA_advance_loc 1,
+        // DW_CFA_def_cfa r10+0
+        28, 0, 0, 0, 20, 0, 0, 0, 0, 0x10, 0, 0, 0, 0, 0, 0, 0x40, 0, 0, 0, 0, 0, 0, 0,
+        0x0d, 6, 0x41, 0x0c, 10, 0, 0, 0,
+        // Terminator
+        0, 0, 0, 0,
+    ];
+    let mut elf_bytes = elf_fixtures::create_elf_with_eh_frame(1, &eh_frame).elf_bytes;
+    let elf = Elf64::parse(&elf_bytes).unwrap();
+    let section_header = elf.find_section(b".eh_frame").unwrap();
+    let (sh_offset, sh_addr) = (section_header.sh_offset as usize, section_header.sh_addr);
+    let pc_begin = sh_addr + 28 - 0x10;
+    assert_eq!(
+        elf.eh_frame_entries()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap(),
+        [
+            (
+                pc_begin..pc_begin + 16,
+                CfaRule {
+                    register: 10,
+                    offset: 64
+                }
+            ),
+            (
+                0x1000..0x1040,
+                CfaRule {
+                    register: 6,
+                    offset: 16
+                }
+            ),
+        ]
+    );
+
+    // DW_CFA_remember_state in place of the advance of the second FDE
+    elf_bytes[sh_offset + 90] = 0x0a;
+    let elf = Elf64::parse(&elf_bytes).unwrap();
+    let mut entries = elf.eh_frame_entries().unwrap();
+    assert!(entries.next().unwrap().is_ok());
+    assert_eq!(
+        entries.next(),
+        Some(Err(ElfParserError::UnsupportedCallFrameInstruction(0x0a)))
+    );
+    assert_eq!(entries.next(), None);
+
+    // CIE pointer of the second FDE before the start of the section
+    LittleEndian::write_u32(&mut elf_bytes[sh_offset + 68..], 0xff00);
+    let elf = Elf64::parse(&elf_bytes).unwrap();
+    let mut entries = elf.eh_frame_entries().unwrap();
+    assert!(entries.next().unwrap().is_ok());
+    assert_eq!(
+        entries.next(),
+        Some(Err(ElfParserError::InvalidCallFrameInformation))
+    );
+
+    // No .eh_frame section
+    let elf_bytes = elf_fixtures::create_elf_with_call_relocations(1, 1, &[]).elf_bytes;
+    let elf = Elf64::parse(&elf_bytes).unwrap();
+    assert_eq!(elf.eh_frame_entries().unwrap().count(), 0);
+}
+
+#[test]
+fn test_debug_line() {
+    // Emitted by llvm-mc for `.loc 1 3 0`, `.loc 1 4 5`, `.loc 2 10 1` and `.loc 1 7 0` in
+    // front of four instructions, file 1 being "/src/lib.rs" and file 2 "/src/util.rs"
+    #[rustfmt::skip]
+    let debug_line_v4 = [
+        0x53, 0x00, 0x00, 0x00, 0x04, 0x00, 0x2e, 0x00, 0x00, 0x00, 0x08, 0x01,
+        0x01, 0xfb, 0x0e, 0x0d, 0x00, 0x01, 0x01, 0x01, 0x01, 0x00, 0x00, 0x00,
+        0x01, 0x00, 0x00, 0x01, 0x2f, 0x73, 0x72, 0x63, 0x00, 0x00, 0x6c, 0x69,
+        0x62, 0x2e, 0x72, 0x73, 0x00, 0x01, 0x00, 0x00, 0x75, 0x74, 0x69, 0x6c,
+        0x2e, 0x72, 0x73, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x09, 0x02, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x14, 0x05, 0x05, 0x0a, 0x21,
+        0x04, 0x02, 0x05, 0x01, 0x26, 0x04, 0x01, 0x05, 0x00, 0x1d, 0x02, 0x01,
+        0x00, 0x01, 0x01,
+    ];
+    #[rustfmt::skip]
+    let debug_line_v5 = [
+        0x5a, 0x00, 0x00, 0x00, 0x05, 0x00, 0x08, 0x00, 0x33, 0x00, 0x00, 0x00,
+        0x08, 0x01, 0x01, 0xfb, 0x0e, 0x0d, 0x00, 0x01, 0x01, 0x01, 0x01, 0x00,
+        0x00, 0x00, 0x01, 0x00, 0x00, 0x01, 0x01, 0x01, 0x1f, 0x02, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x01, 0x1f, 0x02, 0x0f, 0x03,
+        0x05, 0x00, 0x00, 0x00, 0x01, 0x05, 0x00, 0x00, 0x00, 0x01, 0x0c, 0x00,
+        0x00, 0x00, 0x01, 0x00, 0x09, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x14, 0x05, 0x05, 0x0a, 0x21, 0x04, 0x02, 0x05, 0x01, 0x26,
+        0x04, 0x01, 0x05, 0x00, 0x1d, 0x02, 0x01, 0x00, 0x01, 0x01,
+    ];
+    let debug_line_str_v5 = b"/src\0lib.rs\0util.rs\0";
+    let row = |address, file, line, column, end_sequence| LineRow {
+        address,
+        file,
+        line,
+        column,
+        end_sequence,
+    };
+    let expected_rows = [
+        row(0x00, "lib.rs", 3, 0, false),
+        row(0x08, "lib.rs", 4, 5, false),
+        row(0x10, "util.rs", 10, 1, false),
+        row(0x18, "lib.rs", 7, 0, false),
+        row(0x20, "lib.rs", 7, 0, true),
+    ];
+    for sections in [
+        [(".debug_line", &debug_line_v4[..]), (".comment", &[][..])],
+        [
+            (".debug_line", &debug_line_v5[..]),
+            (".debug_line_str", &debug_line_str_v5[..]),
+        ],
+    ] {
+        let sections = sections.map(|(name, bytes)| (name, SHT_PROGBITS, 0, bytes));
+        let elf_bytes = elf_fixtures::create_elf_with_sections(1, &sections).elf_bytes;
+        let elf = Elf64::parse(&elf_bytes).unwrap();
+        let debug_line = elf.debug_line().unwrap().unwrap();
+        assert_eq!(
+            debug_line.rows().collect::<Result<Vec<_>, _>>().unwrap(),
+            expected_rows
+        );
+        assert_eq!(debug_line.line_for_address(0x00), Some(("lib.rs", 3)));
+        assert_eq!(debug_line.line_for_address(0x0f), Some(("lib.rs", 4)));
+        assert_eq!(debug_line.line_for_address(0x10), Some(("util.rs", 10)));
+        assert_eq!(debug_line.line_for_address(0x1f), Some(("lib.rs", 7)));
+        assert_eq!(debug_line.line_for_address(0x20), None);
+    }
+
+    // Truncated programs fail without panicking
+    for len in 1..debug_line_v5.len() {
+        let debug_line = DebugLine::new(&debug_line_v5[..len], debug_line_str_v5);
+        assert!(debug_line.rows().any(|row| row.is_err()));
+        assert_eq!(debug_line.line_for_address(0x00), None);
+    }
+
+    // Zero line_range
+    let mut debug_line_v4 = debug_line_v4;
+    debug_line_v4[14] = 0;
+    let debug_line = DebugLine::new(&debug_line_v4, &[]);
+    assert_eq!(
+        debug_line.rows().next(),
+        Some(Err(ElfParserError::InvalidLineNumberProgram))
+    );
+
+    // No .debug_line section
+    let elf_bytes = elf_fixtures::create_elf_with_call_relocations(1, 1, &[]).elf_bytes;
+    let elf = Elf64::parse(&elf_bytes).unwrap();
+    assert!(elf.debug_line().unwrap().is_none());
+}
+
+#[test]
+fn test_notes() {
+    #[rustfmt::skip]
+    let notes = [
+        // NT_GNU_BUILD_ID with an 8 byte descriptor
+        4, 0, 0, 0, 8, 0, 0, 0, 3, 0, 0, 0, b'G', b'N', b'U', 0,
+        1, 2, 3, 4, 5, 6, 7, 8,
+        // Type 1 of "SBPF", name and descriptor padded to 4 bytes
+        5, 0, 0, 0, 3, 0, 0, 0, 1, 0, 0, 0, b'S', b'B', b'P', b'F', 0, 0, 0, 0,
+        b'v', b'3', 0, 0,
+    ];
+    let build_id = [1, 2, 3, 4, 5, 6, 7, 8];
+    let expected_notes = [
+        Note {
+            name: b"GNU",
+            n_type: NT_GNU_BUILD_ID,
+            desc: &build_id,
+        },
+        Note {
+            name: b"SBPF",
+            n_type: 1,
+            desc: b"v3\0",
+        },
+    ];
+    let mut elf_bytes =
+        elf_fixtures::create_elf_with_sections(1, &[(".note", SHT_NOTE, SHF_ALLOC, &notes)])
+            .elf_bytes;
+    let elf = Elf64::parse(&elf_bytes).unwrap();
+    assert_eq!(
+        elf.notes().collect::<Result<Vec<_>, _>>().unwrap(),
+        expected_notes
+    );
+    assert_eq!(elf.build_id(), Ok(Some(&build_id[..])));
+    let sh_offset = elf.find_section(b".note").unwrap().sh_offset;
+    let sh_type_offset = elf.file_header().e_shoff as usize + 6 * mem::size_of::<Elf64Shdr>() + 4;
+
+    // A PT_NOTE segment covered by the section does not repeat the notes
+    let phdr_offset = mem::size_of::<Elf64Ehdr>() + 2 * mem::size_of::<Elf64Phdr>();
+    LittleEndian::write_u32(&mut elf_bytes[phdr_offset..], PT_NOTE);
+    LittleEndian::write_u64(&mut elf_bytes[phdr_offset + 8..], sh_offset);
+    LittleEndian::write_u64(&mut elf_bytes[phdr_offset + 32..], notes.len() as u64);
+    let elf = Elf64::parse(&elf_bytes).unwrap();
+    assert_eq!(
+        elf.notes().collect::<Result<Vec<_>, _>>().unwrap(),
+        expected_notes
+    );
+
+    // Without the section the notes come from the segment
+    LittleEndian::write_u32(&mut elf_bytes[sh_type_offset..], SHT_PROGBITS);
+    let elf = Elf64::parse(&elf_bytes).unwrap();
+    assert_eq!(
+        elf.notes().collect::<Result<Vec<_>, _>>().unwrap(),
+        expected_notes
+    );
+    LittleEndian::write_u32(&mut elf_bytes[sh_type_offset..], SHT_NOTE);
+
+    // descsz exceeding the section
+    LittleEndian::write_u32(&mut elf_bytes[sh_offset as usize + 4..], 0x100);
+    let elf = Elf64::parse(&elf_bytes).unwrap();
+    let mut notes = elf.notes();
+    assert_eq!(
+        notes.next(),
+        Some(Err(ElfParserError::OutOfBounds {
+            offset: 16,
+            len: 0x100
+        }))
+    );
+    assert_eq!(notes.next(), None);
+    assert_eq!(
+        elf.build_id(),
+        Err(ElfParserError::OutOfBounds {
+            offset: 16,
+            len: 0x100
+        })
+    );
+
+    // No notes
+    let elf_bytes = elf_fixtures::create_elf_with_call_relocations(1, 1, &[]).elf_bytes;
+    let elf = Elf64::parse(&elf_bytes).unwrap();
+    assert_eq!(elf.notes().count(), 0);
+    assert_eq!(elf.build_id(), Ok(None));
+}
+
+#[test]
+fn test_validate_dynamic_symbols() {
+    let validating_options = ElfParserOptions {
+        validate_dynamic_symbols: true,
+        ..ElfParserOptions::default()
+    };
+    let mut elf_bytes = elf_fixtures::create_elf_with_call_relocations(1, 1, &[]).elf_bytes;
+    let elf = Elf64::parse_with_options(&elf_bytes, validating_options).unwrap();
+    let symbol_offset =
+        elf.find_section(b".dynsym").unwrap().sh_offset as usize + mem::size_of::<Elf64Sym>();
+    let dynstr_size = elf.find_section(b".dynstr").unwrap().sh_size as u32;
+    let section_count = elf.file_header().e_shnum;
+
+    // st_name at the last byte of .dynstr, and one past it
+    LittleEndian::write_u32(&mut elf_bytes[symbol_offset..], dynstr_size - 1);
+    Elf64::parse_with_options(&elf_bytes, validating_options).unwrap();
+    LittleEndian::write_u32(&mut elf_bytes[symbol_offset..], dynstr_size);
+    assert_eq!(
+        Elf64::parse_with_options(&elf_bytes, validating_options).err(),
+        Some(ElfParserError::InvalidDynamicSymbol { index: 1 })
+    );
+    Elf64::parse(&elf_bytes).unwrap();
+    LittleEndian::write_u32(&mut elf_bytes[symbol_offset..], 1);
+
+    // st_shndx past e_shnum, and a reserved index
+    LittleEndian::write_u16(&mut elf_bytes[symbol_offset + 6..], section_count);
+    assert_eq!(
+        Elf64::parse_with_options(&elf_bytes, validating_options).err(),
+        Some(ElfParserError::InvalidDynamicSymbol { index: 1 })
+    );
+    Elf64::parse(&elf_bytes).unwrap();
+    LittleEndian::write_u16(&mut elf_bytes[symbol_offset + 6..], SHN_ABS);
+    Elf64::parse_with_options(&elf_bytes, validating_options).unwrap();
+}
+
+#[test]
+fn test_extended_section_numbering() {
+    const E_SHNUM_OFFSET: usize = 60;
+    const E_SHSTRNDX_OFFSET: usize = 62;
+    let mut elf_bytes = elf_fixtures::create_elf_with_call_relocations(1, 1, &[]).elf_bytes;
+    let elf = Elf64::parse(&elf_bytes).unwrap();
+    let e_shoff = elf.file_header().e_shoff as usize;
+    let (e_shnum, e_shstrndx) = (elf.file_header().e_shnum, elf.file_header().e_shstrndx);
+    let section_names = elf
+        .section_headers()
+        .map(|(name, _)| name.unwrap().to_vec())
+        .collect::<Vec<_>>();
+
+    // The counts escape to the sh_size and sh_link of the null section header
+    LittleEndian::write_u16(&mut elf_bytes[E_SHNUM_OFFSET..], 0);
+    LittleEndian::write_u16(&mut elf_bytes[E_SHSTRNDX_OFFSET..], SHN_XINDEX);
+    LittleEndian::write_u64(&mut elf_bytes[e_shoff + 32..], e_shnum as u64);
+    LittleEndian::write_u32(&mut elf_bytes[e_shoff + 40..], e_shstrndx as u32);
+    let elf = Elf64::parse(&elf_bytes).unwrap();
+    assert_eq!(elf.section_header_table().len(), e_shnum as usize);
+    assert_eq!(
+        elf.section_headers()
+            .map(|(name, _)| name.unwrap().to_vec())
+            .collect::<Vec<_>>(),
+        section_names
+    );
+    ElfExecutable::load(&elf_bytes, loader()).unwrap();
+
+    // Escaped e_shstrndx past the section count
+    LittleEndian::write_u32(&mut elf_bytes[e_shoff + 40..], e_shnum as u32);
+    assert_eq!(
+        Elf64::parse(&elf_bytes).err(),
+        Some(ElfParserError::InvalidFileHeader)
+    );
+    LittleEndian::write_u32(&mut elf_bytes[e_shoff + 40..], e_shstrndx as u32);
+
+    // Section count past the end of the file
+    LittleEndian::write_u64(&mut elf_bytes[e_shoff + 32..], 0x10000);
+    assert!(matches!(
+        Elf64::parse(&elf_bytes),
+        Err(ElfParserError::OutOfBounds { .. })
+    ));
+
+    // Without the escape an e_shnum of 0 has no sections
+    LittleEndian::write_u16(&mut elf_bytes[E_SHSTRNDX_OFFSET..], e_shstrndx);
+    LittleEndian::write_u64(&mut elf_bytes[e_shoff + 32..], 0);
+    assert_eq!(
+        Elf64::parse(&elf_bytes).err(),
+        Some(ElfParserError::InvalidFileHeader)
+    );
+}
+
+#[test]
+fn test_offsets_beyond_address_space() {
+    const E_SHOFF_OFFSET: usize = 40;
+    // Would wrap around to the valid offsets if truncated to 32 bits
+    const WRAP: u64 = 1 << 32;
+    let elf_bytes = elf_fixtures::create_elf_with_call_relocations(1, 1, &[]).elf_bytes;
+    let elf = Elf64::parse(&elf_bytes).unwrap();
+    let e_shoff = elf.file_header().e_shoff as usize;
+
+    let mut corrupt_bytes = elf_bytes.clone();
+    LittleEndian::write_u64(&mut corrupt_bytes[E_SHOFF_OFFSET..], e_shoff as u64 + WRAP);
+    assert!(matches!(
+        Elf64::parse(&corrupt_bytes),
+        Err(ElfParserError::OutOfBounds { .. })
+    ));
+
+    let mut corrupt_bytes = elf_bytes.clone();
+    let dynsym_sh_offset = e_shoff + 3 * mem::size_of::<Elf64Shdr>() + 24;
+    let dynsym_offset = elf.find_section(b".dynsym").unwrap().sh_offset;
+    LittleEndian::write_u64(&mut corrupt_bytes[dynsym_sh_offset..], dynsym_offset + WRAP);
+    assert!(matches!(
+        Elf64::parse(&corrupt_bytes),
+        Err(ElfParserError::OutOfBounds { .. })
+    ));
+}
+
+#[test]
+fn test_sections_not_in_order() {
+    let mut elf_bytes = elf_fixtures::create_elf_with_call_relocations(1, 1, &[]).elf_bytes;
+    let elf = Elf64::parse(&elf_bytes).unwrap();
+    let e_shoff = elf.file_header().e_shoff as usize;
+    let sh_offset = |index: usize| e_shoff + index * mem::size_of::<Elf64Shdr>() + 24;
+    let dynamic_offset = elf.find_section(b".dynamic").unwrap().sh_offset;
+    let rel_dyn_offset = elf.find_section(b".rel.dyn").unwrap().sh_offset;
+
+    // .dynsym starting inside .dynamic
+    let dynsym_offset = LittleEndian::read_u64(&elf_bytes[sh_offset(3)..]);
+    LittleEndian::write_u64(&mut elf_bytes[sh_offset(3)..], dynamic_offset + 8);
+    let err = Elf64::parse(&elf_bytes).err().unwrap();
+    assert_eq!(
+        err,
+        ElfParserError::SectionNotInOrder {
+            section: "3 (.dynsym)".to_string(),
+            previous_section: "2 (.dynamic)".to_string(),
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "section 3 (.dynsym) overlaps or precedes section 2 (.dynamic)"
+    );
+    LittleEndian::write_u64(&mut elf_bytes[sh_offset(3)..], dynsym_offset);
+
+    // The string table itself starting inside .rel.dyn, its names are not used
+    LittleEndian::write_u64(&mut elf_bytes[sh_offset(6)..], rel_dyn_offset);
+    assert_eq!(
+        Elf64::parse(&elf_bytes).err(),
+        Some(ElfParserError::SectionNotInOrder {
+            section: "6".to_string(),
+            previous_section: "5".to_string(),
+        })
+    );
+}
+
+#[test]
+fn test_parse_headers_only() {
+    let mut elf_bytes = elf_fixtures::create_elf_with_call_relocations(1, 1, &[]).elf_bytes;
+    let elf = Elf64::parse(&elf_bytes).unwrap();
+    let headers = Elf64::parse_headers_only(&elf_bytes).unwrap();
+    assert_eq!(headers.file_header().e_entry, elf.file_header().e_entry);
+    assert_eq!(headers.sbpf_version(), Ok(SBPFVersion::V0));
+    assert_eq!(
+        headers.program_header_table().len(),
+        elf.program_header_table().len()
+    );
+    assert_eq!(
+        headers.section_header_table().len(),
+        elf.section_header_table().len()
+    );
+    assert_eq!(
+        format!("{:?}", headers.finish_parse().unwrap()),
+        format!("{:?}", elf)
+    );
+
+    // Sections out of order and a corrupt dynamic section are only found by finish_parse
+    let e_shoff = elf.file_header().e_shoff as usize;
+    let dynamic_offset = elf.find_section(b".dynamic").unwrap().sh_offset;
+    let mut unordered_bytes = elf_bytes.clone();
+    LittleEndian::write_u64(
+        &mut unordered_bytes[e_shoff + 3 * mem::size_of::<Elf64Shdr>() + 24..],
+        dynamic_offset + 8,
+    );
+    let headers = Elf64::parse_headers_only(&unordered_bytes).unwrap();
+    assert_error!(headers.finish_parse(), "SectionNotInOrder");
+    // The d_val of the first entry, DT_REL pointing nowhere
+    LittleEndian::write_u64(&mut elf_bytes[dynamic_offset as usize + 8..], u64::MAX);
+    let headers = Elf64::parse_headers_only(&elf_bytes).unwrap();
+    let err = Elf64::parse(&elf_bytes).err();
+    assert!(err.is_some());
+    assert_eq!(headers.finish_parse().err(), err);
+
+    // The headers are still checked up front
+    elf_bytes[0] = 0;
+    assert_error!(Elf64::parse_headers_only(&elf_bytes), "InvalidFileHeader");
+    let mut unknown_e_flags_bytes = unordered_bytes;
+    LittleEndian::write_u32(&mut unknown_e_flags_bytes[48..], 0x10);
+    let options = ElfParserOptions {
+        reject_unknown_e_flags: true,
+        ..ElfParserOptions::default()
+    };
+    assert!(Elf64::parse_headers_only(&unknown_e_flags_bytes).is_ok());
+    assert_error!(
+        Elf64::parse_headers_only_with_options(&unknown_e_flags_bytes, options),
+        "InvalidFileHeader"
+    );
+}
+
+#[test]
+fn test_overlapping_segments() {
+    const E_FLAGS_OFFSET: usize = 48;
+    const P_MEMSZ_OFFSET: usize = mem::size_of::<Elf64Ehdr>() + 40;
+    let strict_options = ElfParserOptions {
+        reject_overlapping_segments: true,
+        ..ElfParserOptions::default()
+    };
+
+    // The two PT_LOAD segments of the fixture touch
+    let mut elf_bytes = elf_fixtures::create_elf_with_call_relocations(1, 1, &[]).elf_bytes;
+    Elf64::parse_with_options(&elf_bytes, strict_options).unwrap();
+
+    // Extending the first segment into the second one
+    let p_memsz = LittleEndian::read_u64(&elf_bytes[P_MEMSZ_OFFSET..]);
+    LittleEndian::write_u64(&mut elf_bytes[P_MEMSZ_OFFSET..], p_memsz + 8);
+    assert_eq!(
+        Elf64::parse_with_options(&elf_bytes, strict_options).err(),
+        Some(ElfParserError::SegmentVmOverlap {
+            index: 1,
+            other_index: 0
+        })
+    );
+    Elf64::parse(&elf_bytes).unwrap();
+
+    // The loader only accepts the overlap in V0 files
+    ElfExecutable::load(&elf_bytes, loader()).unwrap();
+    LittleEndian::write_u32(&mut elf_bytes[E_FLAGS_OFFSET..], 1);
+    let loader = Arc::new(BuiltinProgram::new_loader(Config {
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V2,
+        ..Config::default()
+    }));
+    assert_eq!(
+        ElfExecutable::load(&elf_bytes, loader).err(),
+        Some(ElfError::FailedToParse(
+            "program header 1 overlaps the vm range of program header 0".to_string()
+        ))
+    );
+}
+
+#[test]
+fn test_unsupported_segments() {
+    let fixture = elf_fixtures::create_elf_with_call_relocations(1, 1, &[]);
+    let aligned = AlignedElfBytes::new(&fixture.elf_bytes);
+    assert_eq!(aligned.parse().unwrap().gnu_stack_flags(), None);
+
+    // A non-executable stack loads
+    let fixture = elf_fixtures::create_elf_with_segment(PT_GNU_STACK, PF_R | PF_W);
+    let aligned = AlignedElfBytes::new(&fixture.elf_bytes);
+    assert_eq!(
+        aligned.parse().unwrap().gnu_stack_flags(),
+        Some(PF_R | PF_W)
+    );
+    ElfExecutable::load(&fixture.elf_bytes, loader()).unwrap();
+
+    // An executable stack parses, but the loader rejects it
+    let fixture = elf_fixtures::create_elf_with_segment(PT_GNU_STACK, PF_R | PF_W | PF_X);
+    let aligned = AlignedElfBytes::new(&fixture.elf_bytes);
+    assert_eq!(
+        aligned.parse().unwrap().gnu_stack_flags(),
+        Some(PF_R | PF_W | PF_X)
+    );
+    assert_eq!(
+        ElfExecutable::load(&fixture.elf_bytes, loader()).err(),
+        Some(ElfError::ExecutableStack)
+    );
+
+    // Thread local storage is rejected by the parser
+    let fixture = elf_fixtures::create_elf_with_segment(PT_TLS, PF_R);
+    let aligned = AlignedElfBytes::new(&fixture.elf_bytes);
+    assert_eq!(
+        aligned.parse().err(),
+        Some(ElfParserError::UnsupportedSegmentType(PT_TLS))
+    );
+    assert_eq!(
+        ElfExecutable::load(&fixture.elf_bytes, loader()).err(),
+        Some(ElfError::FailedToParse(
+            "unsupported segment type 0x7".to_string()
+        ))
+    );
+}
+
+#[test]
+fn test_elf64_owned() {
+    let fixture = elf_fixtures::create_elf_with_call_relocations(16, 3, &["log"]);
+    let owned = {
+        let elf_bytes = fixture.elf_bytes.clone();
+        Elf64Owned::parse(&elf_bytes).unwrap()
+    };
+    let aligned = AlignedElfBytes::new(&fixture.elf_bytes);
+    let elf = aligned.parse().unwrap();
+
+    assert_eq!(owned.file_header().e_entry, elf.file_header().e_entry);
+    assert_eq!(owned.sbpf_version(), elf.sbpf_version());
+    assert_eq!(
+        owned.program_header_table().len(),
+        elf.program_header_table().len()
+    );
+    assert_eq!(owned.gnu_stack_flags(), elf.gnu_stack_flags());
+    assert_eq!(
+        owned
+            .section_headers()
+            .map(|(name, section_header)| (name.unwrap().to_vec(), section_header.sh_offset))
+            .collect::<Vec<_>>(),
+        elf.section_headers()
+            .map(|(name, section_header)| (name.unwrap().to_vec(), section_header.sh_offset))
+            .collect::<Vec<_>>(),
+    );
+    assert_eq!(
+        owned.find_section(b".text").unwrap().sh_addr,
+        fixture.text_addr
+    );
+    assert!(owned.symbol_table().unwrap().is_none());
+    assert_eq!(
+        owned.dynamic_symbol_table().unwrap().len(),
+        elf.dynamic_symbol_table().unwrap().len()
+    );
+    for (name, _pc) in fixture.call_targets.iter() {
+        assert_eq!(
+            owned.lookup_dynamic_symbol(name).unwrap().unwrap().st_value,
+            elf.lookup_dynamic_symbol(name).unwrap().unwrap().st_value
+        );
+    }
+    assert!(owned.lookup_dynamic_symbol(b"missing").unwrap().is_none());
+    assert_eq!(
+        owned
+            .relocations()
+            .map(|relocation| {
+                let relocation = relocation.unwrap();
+                (
+                    relocation.r_offset,
+                    relocation.symbol_name.map(<[u8]>::to_vec),
+                )
+            })
+            .collect::<Vec<_>>(),
+        elf.relocations()
+            .map(|relocation| {
+                let relocation = relocation.unwrap();
+                (
+                    relocation.r_offset,
+                    relocation.symbol_name.map(<[u8]>::to_vec),
+                )
+            })
+            .collect::<Vec<_>>(),
+    );
+
+    // The copies do not grow with the contents of the file
+    let mut padded_bytes = fixture.elf_bytes.clone();
+    padded_bytes.resize(padded_bytes.len() + (1 << 20), 0);
+    assert_eq!(
+        Elf64Owned::parse(&padded_bytes).unwrap().mem_size(),
+        owned.mem_size()
+    );
+}
+
+#[test]
+fn test_program_report() {
+    let fixture = elf_fixtures::create_elf_with_call_relocations(4, 2, &["log"]);
+    let executable = ElfExecutable::load(&fixture.elf_bytes, loader()).unwrap();
+    let report = executable.report().unwrap();
+    assert_eq!(
+        report.to_json(),
+        concat!(
+            r#"{"schema_version":1,"elf_size":1136,"text_size":56,"ro_section_size":56,"#,
+            r#""sbpf_version":"V0","instruction_count":7,"#,
+            r#""instruction_histogram":{"0x85":4,"0x95":3},"function_count":3,"#,
+            r#""largest_function":{"name":"entrypoint","pc":0,"instruction_slots":5},"#,
+            r#""syscalls":["log"],"verification_error":null,"compatible_versions":["V1","V2"]}"#,
+        )
+    );
+    let parsed = json::parse(&report.to_json()).unwrap();
+    assert_eq!(parsed["elf_size"], fixture.elf_bytes.len());
+}
+
+#[test]
+fn test_function_containing_pc() {
+    // Entrypoint of 5 slots followed by two functions of one slot each
+    let fixture = elf_fixtures::create_elf_with_call_relocations(4, 2, &["log"]);
+    let executable = ElfExecutable::load(&fixture.elf_bytes, loader()).unwrap();
+    let function_registry = executable.get_function_registry();
+    let function_at = |pc| {
+        executable
+            .function_containing_pc(pc)
+            .map(|(key, range)| (function_registry.lookup_by_key(key).unwrap().1, range))
+    };
+    for pc in 0..5 {
+        assert_eq!(function_at(pc), Some((0, 0..5)));
+    }
+    assert_eq!(function_at(5), Some((5, 5..6)));
+    assert_eq!(function_at(6), Some((6, 6..7)));
+    assert_eq!(function_at(7), None);
+
+    // Stripped, only the entrypoint is known
+    let prog = [
+        0xb7, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+        0x95, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+        0xb7, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, //
+        0x95, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+    ];
+    let mut function_registry = FunctionRegistry::default();
+    function_registry
+        .register_function(ebpf::hash_symbol_name(b"entrypoint"), *b"entrypoint", 2)
+        .unwrap();
+    let executable =
+        ElfExecutable::from_text_bytes(&prog, loader(), SBPFVersion::V0, function_registry)
+            .unwrap();
+    let key = ebpf::hash_symbol_name(b"entrypoint");
+    assert_eq!(executable.function_containing_pc(1), None);
+    assert_eq!(executable.function_containing_pc(2), Some((key, 2..4)));
+    assert_eq!(executable.function_containing_pc(3), Some((key, 2..4)));
+    assert_eq!(executable.function_containing_pc(4), None);
+}
+
+#[test]
+fn test_unsupported_opcodes() {
+    let prog = [
+        0xb7, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // mov64 r0, 0
+        0x18, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // lddw r0, 0
+        0x84, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // neg32 r0, second slot of the lddw
+        0xd4, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, // le16 r0
+        0x95, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // exit
+    ];
+    let executable = |sbpf_version| {
+        let mut function_registry = FunctionRegistry::default();
+        function_registry
+            .register_function(ebpf::hash_symbol_name(b"entrypoint"), *b"entrypoint", 0)
+            .unwrap();
+        ElfExecutable::from_text_bytes(&prog, loader(), sbpf_version, function_registry).unwrap()
+    };
+    assert!(executable(SBPFVersion::V0).unsupported_opcodes().is_empty());
+
+    // All of them are reported, not just the first one the verifier stops at
+    let executable = executable(SBPFVersion::V2);
+    assert_eq!(
+        executable.unsupported_opcodes(),
+        [(1, 0x18), (2, 0x84), (3, 0xd4)]
+    );
+    assert_error!(
+        executable.verify::<RequisiteVerifier>(),
+        "UnknownOpCode {{ opc: 24, pc: 1"
+    );
+    let listing = solana_sbpf::disassemble_program(&executable).unwrap();
+    assert_eq!(
+        listing
+            .iter()
+            .filter(|line| line.ends_with("; unsupported in SBPF v2"))
+            .count(),
+        3
+    );
+}
@@ -18,20 +18,30 @@ use byteorder::{ByteOrder, LittleEndian};
 #[cfg(all(not(windows), target_arch = "x86_64"))]
 use rand::{rngs::SmallRng, RngCore, SeedableRng};
 use solana_sbpf::{
+    aligned_memory::AlignedMemory,
+    allocator::BumpAllocator,
     assembler::assemble,
     declare_builtin_function, ebpf,
-    elf::Executable,
+    elf::{ElfError, Executable},
     error::{EbpfError, ProgramResult},
-    memory_region::{AccessType, MemoryMapping, MemoryRegion},
-    program::{BuiltinProgram, FunctionRegistry, SBPFVersion},
+    execution_events::{ExecutionEvent, ExecutionEventLog},
+    memory_region::{
+        default_memory_cow_callback, AccessType, MemoryMapping, MemoryRegion, MemoryRegionKind,
+        RegionAccessStats, SendMemoryMapping,
+    },
+    program::{
+        syscall_key, BuiltinProgram, BuiltinProgramBuilder, FunctionRegistry, SBPFVersion,
+        SyscallMetadata,
+    },
     static_analysis::Analysis,
+    syscall_replay::{ReplayContextObject, SyscallRecord},
     verifier::RequisiteVerifier,
-    vm::{Config, ContextObject},
+    vm::{CallFilter, Config, ContextObject, EbpfVm, TraceControl},
 };
-use std::{fs::File, io::Read, sync::Arc};
+use std::{alloc::Layout, cell::RefCell, fs::File, io::Read, rc::Rc, sync::Arc};
 use test_utils::{
-    assert_error, create_vm, syscalls, test_interpreter_and_jit, test_interpreter_and_jit_asm,
-    test_interpreter_and_jit_elf, test_syscall_asm, TestContextObject, PROG_TCP_PORT_80,
+    access_violation_context, assert_error, create_vm, syscalls, test_interpreter_and_jit, test_interpreter_and_jit_asm,
+    test_interpreter_and_jit_elf, test_syscall_asm, MemoryDiff, TestContextObject, PROG_TCP_PORT_80,
     TCP_SACK_ASM, TCP_SACK_MATCH, TCP_SACK_NOMATCH,
 };
 
@@ -961,7 +971,17 @@ fn test_err_ldxdw_oob() {
             AccessType::Load,
             0x400000006,
             8,
-            "input"
+            "input",
+            access_violation_context(
+                Some((
+                    MemoryRegionKind::Input,
+                    ebpf::MM_INPUT_START..ebpf::MM_INPUT_START + 12,
+                    0
+                )),
+                None,
+                true,
+                false,
+            ),
         )),
     );
 }
@@ -978,7 +998,17 @@ fn test_err_ldxdw_nomem() {
             AccessType::Load,
             0x400000006,
             8,
-            "input"
+            "input",
+            access_violation_context(
+                Some((
+                    MemoryRegionKind::Stack,
+                    ebpf::MM_STACK_START..ebpf::MM_STACK_START + 0x40000,
+                    0x400000006 - ebpf::MM_STACK_START - 0x40000
+                )),
+                None,
+                false,
+                false,
+            ),
         )),
     );
 }
@@ -1257,6 +1287,57 @@ fn test_stxb_chain() {
     );
 }
 
+#[test]
+fn test_stx_memory_diff() {
+    let config = Config {
+        enable_instruction_tracing: true,
+        ..Config::default()
+    };
+    let mut executable = assemble::<TestContextObject>(
+        "
+        mov r2, 0x11
+        stxb [r1+1], r2
+        mov r2, 0x3344
+        stxh [r1+4], r2
+        exit",
+        Arc::new(BuiltinProgram::new_loader(config)),
+    )
+    .unwrap();
+    executable.verify::<RequisiteVerifier>().unwrap();
+    executable.jit_compile().unwrap();
+    let before = [0xff; 8];
+    for interpreted in [true, false] {
+        let mut mem = before;
+        let mem_region = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
+        let mut context_object = TestContextObject::new(5);
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            vec![mem_region],
+            None
+        );
+        let (_instruction_count, result) = vm.execute_program(&executable, interpreted);
+        assert!(result.is_ok());
+        let diff = MemoryDiff::between(&before, &mem);
+        diff.assert_changes(&[(1, &[0x11]), (4, &[0x44, 0x33])]);
+        diff.assert_only_changed([1..2, 4..6]);
+    }
+}
+
+#[test]
+#[should_panic(expected = "0x400000004..0x400000006 (2 bytes):\n  - 0x0000000400000004: ff ff\n  + 0x0000000400000004: 44 33")]
+fn test_memory_diff_unexpected_change() {
+    let before = [0xff; 8];
+    let mut after = before;
+    after[1] = 0x11;
+    after[4] = 0x44;
+    after[5] = 0x33;
+    MemoryDiff::between(&before, &after).assert_only_changed([0..2, 6..8]);
+}
+
 // BPF_JMP : Branches
 
 #[test]
@@ -1887,7 +1968,21 @@ fn test_err_dynamic_stack_out_of_bound() {
             AccessType::Store,
             ebpf::MM_STACK_START - 1,
             1,
-            "program"
+            "program",
+            access_violation_context(
+                Some((
+                    MemoryRegionKind::Rodata,
+                    ebpf::MM_RODATA_START..ebpf::MM_RODATA_START + 16,
+                    ebpf::MM_STACK_START - 1 - ebpf::MM_RODATA_START - 16
+                )),
+                Some((
+                    MemoryRegionKind::Stack,
+                    ebpf::MM_STACK_START..ebpf::MM_STACK_START + config.stack_size() as u64,
+                    1
+                )),
+                false,
+                false,
+            ),
         )),
     );
 
@@ -1903,7 +1998,17 @@ fn test_err_dynamic_stack_out_of_bound() {
             AccessType::Store,
             ebpf::MM_STACK_START + config.stack_size() as u64,
             1,
-            "stack"
+            "stack",
+            access_violation_context(
+                Some((
+                    MemoryRegionKind::Stack,
+                    ebpf::MM_STACK_START..ebpf::MM_STACK_START + config.stack_size() as u64,
+                    0
+                )),
+                None,
+                false,
+                false,
+            ),
         )),
     );
 }
@@ -1931,7 +2036,17 @@ fn test_err_dynamic_stack_ptr_overflow() {
             AccessType::Store,
             u64::MAX - 63,
             1,
-            "unknown"
+            "unknown",
+            access_violation_context(
+                Some((
+                    MemoryRegionKind::Stack,
+                    ebpf::MM_STACK_START..ebpf::MM_STACK_START + 0x40000,
+                    u64::MAX - 63 - ebpf::MM_STACK_START - 0x40000
+                )),
+                None,
+                false,
+                false,
+            ),
         )),
     );
 }
@@ -2082,7 +2197,10 @@ fn test_stack_call_depth_tracking() {
             config,
             [],
             TestContextObject::new(2),
-            ProgramResult::Err(EbpfError::CallDepthExceeded),
+            ProgramResult::Err(EbpfError::CallDepthExceeded {
+                pc: 2,
+                return_addresses: vec![1, 3],
+            }),
         );
     }
 }
@@ -2097,6 +2215,29 @@ fn test_err_mem_access_out_of_bound() {
     prog[24] = ebpf::RETURN;
     let loader = Arc::new(BuiltinProgram::new_mock());
     for address in [0x2u64, 0x8002u64, 0x80000002u64, 0x8000000000000002u64] {
+        let context = if address < ebpf::MM_RODATA_START {
+            access_violation_context(
+                None,
+                Some((
+                    MemoryRegionKind::Rodata,
+                    ebpf::MM_RODATA_START..ebpf::MM_RODATA_START + 32,
+                    ebpf::MM_RODATA_START - address,
+                )),
+                false,
+                false,
+            )
+        } else {
+            access_violation_context(
+                Some((
+                    MemoryRegionKind::Input,
+                    ebpf::MM_INPUT_START..ebpf::MM_INPUT_START + 512,
+                    address - ebpf::MM_INPUT_START - 512,
+                )),
+                None,
+                false,
+                false,
+            )
+        };
         LittleEndian::write_u32(&mut prog[4..], address as u32);
         LittleEndian::write_u32(&mut prog[12..], (address >> 32) as u32);
         #[allow(unused_mut)]
@@ -2115,7 +2256,8 @@ fn test_err_mem_access_out_of_bound() {
                 AccessType::Store,
                 address,
                 1,
-                "unknown"
+                "unknown",
+                context,
             )),
         );
     }
@@ -2343,7 +2485,10 @@ fn test_callx_unaligned_text_section() {
         [],
         (),
         TestContextObject::new(129),
-        ProgramResult::Err(EbpfError::CallDepthExceeded),
+        ProgramResult::Err(EbpfError::CallDepthExceeded {
+            pc: 2,
+            return_addresses: vec![3; Config::default().max_call_depth],
+        }),
     );
 }
 
@@ -2385,7 +2530,12 @@ fn test_bpf_to_bpf_depth() {
             config,
             [max_call_depth as u8 + 1],
             TestContextObject::new(max_call_depth as u64 * 3),
-            ProgramResult::Err(EbpfError::CallDepthExceeded),
+            ProgramResult::Err(EbpfError::CallDepthExceeded {
+                pc: 6,
+                return_addresses: std::iter::once(3)
+                    .chain(std::iter::repeat_n(7, max_call_depth - 1))
+                    .collect(),
+            }),
         );
     }
 }
@@ -2404,9 +2554,159 @@ fn test_err_reg_stack_depth() {
             config,
             [],
             TestContextObject::new(max_call_depth as u64),
-            ProgramResult::Err(EbpfError::CallDepthExceeded),
+            ProgramResult::Err(EbpfError::CallDepthExceeded {
+                pc: 0,
+                return_addresses: vec![1; max_call_depth],
+            }),
+        );
+    }
+}
+
+/// Runs `source` in the interpreter and the JIT with calls to `banned_functions` filtered
+fn test_call_filter_asm(
+    source: &str,
+    banned_functions: &[&[u8]],
+    context_object: TestContextObject,
+    expected_result: ProgramResult,
+) {
+    let loader = Arc::new(BuiltinProgram::new_loader(Config::default()));
+    #[allow(unused_mut)]
+    let mut executable = assemble::<TestContextObject>(source, loader).unwrap();
+    executable.verify::<RequisiteVerifier>().unwrap();
+    let banned_pcs = banned_functions
+        .iter()
+        .map(|name| {
+            executable
+                .get_function_registry()
+                .lookup_by_name(name)
+                .unwrap()
+                .1
+        })
+        .collect::<Vec<_>>();
+    let call_filter = CallFilter::new(&executable, banned_pcs.iter().copied()).unwrap();
+    assert_eq!(call_filter.banned_pcs().collect::<Vec<_>>(), banned_pcs);
+    if let ProgramResult::Err(EbpfError::CallFiltered { target_pc }) = expected_result {
+        assert_eq!(
+            call_filter.function_name(target_pc),
+            Some(banned_functions[0]),
+        );
+    }
+    #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
+    executable.jit_compile().unwrap();
+    let expected_instruction_count = context_object.get_remaining();
+    let mut results = Vec::new();
+    for interpreted in [
+        true,
+        #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
+        false,
+    ] {
+        let mut context_object = context_object.clone();
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            Vec::new(),
+            None
         );
+        vm.call_filter = Some(&call_filter);
+        let (instruction_count, result) = vm.execute_program(&executable, interpreted);
+        results.push(format!("{:?} {} {}", result, instruction_count, vm.registers[11]));
+        assert_eq!(
+            format!("{:?}", result),
+            format!("{:?}", expected_result),
+            "Unexpected result (interpreted: {})",
+            interpreted,
+        );
+        assert_eq!(instruction_count, expected_instruction_count);
     }
+    assert!(results.windows(2).all(|pair| pair[0] == pair[1]), "{:?}", results);
+}
+
+#[test]
+fn test_call_filter_call_imm() {
+    test_call_filter_asm(
+        "
+        mov64 r0, 0x1
+        call function_foo
+        exit
+        function_foo:
+        mov64 r0, 0x2A
+        exit",
+        &[b"function_foo"],
+        TestContextObject::new(2),
+        ProgramResult::Err(EbpfError::CallFiltered { target_pc: 3 }),
+    );
+}
+
+#[test]
+fn test_call_filter_callx() {
+    test_call_filter_asm(
+        "
+        mov64 r0, 0x0
+        or64 r8, 0x28
+        callx r8
+        exit
+        function_bar:
+        exit
+        function_foo:
+        mov64 r0, 0x2A
+        exit",
+        &[b"function_foo"],
+        TestContextObject::new(3),
+        ProgramResult::Err(EbpfError::CallFiltered { target_pc: 5 }),
+    );
+}
+
+#[test]
+fn test_call_filter_not_called() {
+    test_call_filter_asm(
+        "
+        mov64 r0, 0x0
+        call function_foo
+        or64 r8, 0x38
+        callx r8
+        exit
+        function_foo:
+        add64 r0, 0x2
+        exit
+        function_bar:
+        add64 r0, 0x28
+        exit
+        function_baz:
+        mov64 r0, 0x0
+        exit",
+        &[b"function_baz"],
+        TestContextObject::new(9),
+        ProgramResult::Ok(42),
+    );
+}
+
+#[test]
+fn test_call_filter_second_bitmap_word() {
+    // function_bar is at pc 63, the last bit of the first word, function_foo at pc 65
+    test_call_filter_asm(
+        &format!(
+            "
+            mov64 r0, 0x0
+            call function_bar
+            or64 r8, 0x208
+            callx r8
+            {}
+            exit
+            function_bar:
+            add64 r0, 0x1
+            exit
+            function_foo:
+            mov64 r0, 0x2A
+            exit",
+            "mov64 r0, 0x0\n".repeat(58),
+        ),
+        &[b"function_foo"],
+        TestContextObject::new(6),
+        ProgramResult::Err(EbpfError::CallFiltered { target_pc: 65 }),
+    );
 }
 
 // CALL_IMM : Syscalls
@@ -2449,7 +2749,22 @@ fn test_err_syscall_string() {
             "bpf_syscall_string" => syscalls::SyscallString::vm,
         ),
         TestContextObject::new(2),
-        ProgramResult::Err(EbpfError::SyscallError(Box::new(EbpfError::AccessViolation(AccessType::Load, 0, 0, "unknown")))),
+        ProgramResult::Err(EbpfError::SyscallError(Box::new(EbpfError::AccessViolation(
+            AccessType::Load,
+            0,
+            0,
+            "unknown",
+            access_violation_context(
+                None,
+                Some((
+                    MemoryRegionKind::Rodata,
+                    ebpf::MM_RODATA_START..ebpf::MM_RODATA_START + 32,
+                    ebpf::MM_RODATA_START
+                )),
+                false,
+                false,
+            ),
+        )))),
     );
 }
 
@@ -2470,6 +2785,149 @@ fn test_syscall_string() {
     );
 }
 
+#[test]
+fn test_text_and_rodata() {
+    let rodata = [
+        0, 0, 0, 0, 0, 0, 0, 0, 0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11,
+    ]
+    .to_vec();
+    let rodata_vaddr = ebpf::MM_RODATA_START + 0x100;
+    for (sbpf_version, load_address, instruction_count) in [
+        (SBPFVersion::V0, "lddw r1, 0x100000100", 3),
+        (
+            SBPFVersion::V3,
+            "mov64 r1, 0x100\n            hor64 r1, 1",
+            4,
+        ),
+    ] {
+        let config = Config {
+            enabled_sbpf_versions: sbpf_version..=sbpf_version,
+            enable_instruction_tracing: true,
+            ..Config::default()
+        };
+        let loader = Arc::new(BuiltinProgram::new_loader(config));
+        let text = assemble::<TestContextObject>(
+            &format!(
+                "
+            {load_address}
+            ldxdw r0, [r1+8]
+            exit"
+            ),
+            loader.clone(),
+        )
+        .unwrap()
+        .get_text_bytes()
+        .1
+        .to_vec();
+        let mut executable = Executable::<TestContextObject>::from_text_and_rodata(
+            &text,
+            rodata.clone(),
+            rodata_vaddr,
+            loader.clone(),
+            sbpf_version,
+            FunctionRegistry::default(),
+        )
+        .unwrap();
+        test_interpreter_and_jit!(
+            executable,
+            [],
+            TestContextObject::new(instruction_count),
+            ProgramResult::Ok(0x1122334455667788),
+        );
+
+        // The read-only data can neither leave the read-only region nor overlap the text section,
+        // if that is mapped into the read-only region too
+        let mut invalid_vaddrs = vec![
+            ebpf::MM_RODATA_START - 8,
+            ebpf::MM_STACK_START - 8,
+            u64::MAX,
+        ];
+        if !sbpf_version.enable_lower_bytecode_vaddr() {
+            invalid_vaddrs.push(ebpf::MM_RODATA_START + text.len() as u64 - 8);
+        }
+        for rodata_vaddr in invalid_vaddrs {
+            assert!(matches!(
+                Executable::<TestContextObject>::from_text_and_rodata(
+                    &text,
+                    rodata.clone(),
+                    rodata_vaddr,
+                    loader.clone(),
+                    sbpf_version,
+                    FunctionRegistry::default(),
+                ),
+                Err(EbpfError::ElfError(ElfError::InvalidRodataAddress(vaddr))) if vaddr == rodata_vaddr
+            ));
+        }
+    }
+}
+
+#[test]
+fn test_syscall_string_in_rodata() {
+    for (sbpf_version, load_message, instruction_count) in [
+        (SBPFVersion::V0, "lddw r6, message", 6),
+        // V3 has no lddw, its read-only data starts at MM_RODATA_START
+        (SBPFVersion::V3, "mov64 r6, 0\n        hor64 r6, 1", 7),
+    ] {
+        let config = Config {
+            enabled_sbpf_versions: sbpf_version..=sbpf_version,
+            enable_instruction_tracing: true,
+            ..Config::default()
+        };
+        let mut loader = BuiltinProgram::new_loader(config);
+        loader
+            .register_function("log", syscalls::SyscallString::vm)
+            .unwrap();
+        let mut executable = assemble::<TestContextObject>(
+            &format!(
+                "
+                {load_message}
+                mov64 r1, r6
+                mov64 r2, 0x5
+                syscall log
+                ldxh r0, [r6+0xa]
+                exit
+                .rodata
+                message:
+                .ascii \"Hello\"
+                .align 4
+                .short 0x1234, 0x5678"
+            ),
+            Arc::new(loader),
+        )
+        .unwrap();
+        test_interpreter_and_jit!(
+            executable,
+            [],
+            TestContextObject::new(instruction_count),
+            ProgramResult::Ok(0x5678),
+        );
+
+        let mut context_object = TestContextObject {
+            execution_events: Some(ExecutionEventLog::new(usize::MAX)),
+            ..TestContextObject::new(instruction_count)
+        };
+        {
+            create_vm!(
+                vm,
+                &executable,
+                &mut context_object,
+                stack,
+                heap,
+                Vec::new(),
+                None
+            );
+            let (_instruction_count, result) = vm.execute_program(&executable, true);
+            assert_eq!(result.unwrap(), 0x5678);
+        }
+        assert!(context_object
+            .execution_events
+            .unwrap()
+            .events
+            .iter()
+            .any(|(_, event)| *event == ExecutionEvent::Log("Hello".to_string())));
+    }
+}
+
 #[test]
 fn test_syscall() {
     test_syscall_asm!(
@@ -2549,11 +3007,13 @@ declare_builtin_function!(
             if throw == 0 {
                 (Result::Ok(42), ProgramResult::Ok(42))
             } else {
+                let error = || EbpfError::CallDepthExceeded {
+                    pc: 0,
+                    return_addresses: Vec::new(),
+                };
                 (
-                    Result::Err(Box::new(EbpfError::CallDepthExceeded)),
-                    ProgramResult::Err(EbpfError::SyscallError(Box::new(
-                        EbpfError::CallDepthExceeded,
-                    ))),
+                    Result::Err(Box::new(error())),
+                    ProgramResult::Err(EbpfError::SyscallError(Box::new(error()))),
                 )
             };
         #[allow(unused_mut)]
@@ -2993,6 +3453,175 @@ fn test_reloc_64_relative_data_sbpfv0() {
     );
 }
 
+#[test]
+fn test_serialized_executable() {
+    for (source, sbpf_version, instruction_count, expected_result) in [
+        ("tests/elfs/relative_call_sbpfv0.so", SBPFVersion::V0, 16, 3),
+        (
+            "tests/elfs/reloc_64_relative_data_sbpfv0.so",
+            SBPFVersion::V0,
+            3,
+            ebpf::MM_RODATA_START + 0x140,
+        ),
+        (
+            "tests/elfs/struct_func_pointer.so",
+            SBPFVersion::V3,
+            3,
+            0x102030405060708,
+        ),
+        (
+            "tests/elfs/reloc_64_relative_data.so",
+            SBPFVersion::V3,
+            4,
+            ebpf::MM_RODATA_START,
+        ),
+    ] {
+        let mut elf = Vec::new();
+        File::open(source).unwrap().read_to_end(&mut elf).unwrap();
+        let config = Config {
+            enabled_sbpf_versions: sbpf_version..=sbpf_version,
+            enable_instruction_tracing: true,
+            ..Config::default()
+        };
+        let loader = Arc::new(BuiltinProgram::new_loader(config.clone()));
+        let mut executable =
+            Executable::<TestContextObject>::from_elf(&elf, loader.clone()).unwrap();
+        let serialized = executable.serialize_rodata();
+        let mut deserialized =
+            Executable::<TestContextObject>::from_serialized(&serialized, &elf, loader.clone())
+                .unwrap();
+        assert_eq!(deserialized.get_text_bytes(), executable.get_text_bytes());
+        assert_eq!(deserialized.get_ro_section(), executable.get_ro_section());
+        assert_eq!(
+            deserialized.get_entrypoint_instruction_offset(),
+            executable.get_entrypoint_instruction_offset()
+        );
+        assert_eq!(
+            deserialized.get_function_registry(),
+            executable.get_function_registry()
+        );
+        assert_eq!(deserialized.serialize_rodata(), serialized);
+        test_interpreter_and_jit!(
+            executable,
+            [1],
+            TestContextObject::new(instruction_count),
+            ProgramResult::Ok(expected_result),
+        );
+        test_interpreter_and_jit!(
+            deserialized,
+            [1],
+            TestContextObject::new(instruction_count),
+            ProgramResult::Ok(expected_result),
+        );
+
+        // Artifacts of another ELF, config or crate version and corrupted ones are rejected
+        let rejected = |serialized: &[u8], elf: &[u8], config: Config| {
+            let loader = Arc::new(BuiltinProgram::new_loader(config));
+            let result = Executable::<TestContextObject>::from_serialized(serialized, elf, loader);
+            match result {
+                Err(EbpfError::ElfError(ElfError::InvalidSerializedExecutable(reason))) => reason,
+                result => panic!("{:?}", result),
+            }
+        };
+        let mut other_elf = elf.clone();
+        other_elf[0x10] ^= 1;
+        assert_eq!(
+            rejected(&serialized, &other_elf, config.clone()),
+            "ELF mismatch"
+        );
+        let other_config = Config {
+            optimize_rodata: !config.optimize_rodata,
+            ..config.clone()
+        };
+        assert_eq!(rejected(&serialized, &elf, other_config), "config mismatch");
+        let mut corrupted = serialized.clone();
+        *corrupted.last_mut().unwrap() ^= 1;
+        assert_eq!(
+            rejected(&corrupted, &elf, config.clone()),
+            "checksum mismatch"
+        );
+        assert_eq!(
+            rejected(&serialized[..4], &elf, config.clone()),
+            "truncated"
+        );
+
+        // The checksum does not stop tampering, so the contents are bounds checked too
+        let tampered = |offset: usize, value: u64| {
+            let mut tampered = serialized[..serialized.len() - 8].to_vec();
+            LittleEndian::write_u64(&mut tampered[offset..offset + 8], value);
+            let checksum = tampered
+                .iter()
+                .fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+                    (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+                });
+            tampered.extend_from_slice(&checksum.to_le_bytes());
+            tampered
+        };
+        let entry_pc_offset = 8
+            + 4
+            + 8
+            + env!("CARGO_PKG_VERSION").len()
+            + 8
+            + sbpf_version.to_string().len()
+            + 8
+            + 8;
+        let text_len = executable.get_text_bytes().1.len();
+        let ro_vaddr_offset = entry_pc_offset + 24 + text_len;
+        let first_pc_offset = ro_vaddr_offset + 16 + executable.get_ro_section().len() + 8 + 4;
+        assert_eq!(
+            rejected(
+                &tampered(entry_pc_offset + 8, u64::MAX - 7),
+                &elf,
+                config.clone()
+            ),
+            "text section out of bounds"
+        );
+        assert_eq!(
+            rejected(
+                &tampered(first_pc_offset, (text_len / ebpf::INSN_SIZE) as u64),
+                &elf,
+                config.clone()
+            ),
+            "function out of bounds"
+        );
+        for (offset, value, expected_error) in [
+            (
+                entry_pc_offset,
+                (text_len / ebpf::INSN_SIZE) as u64,
+                ElfError::EntrypointOutOfBounds,
+            ),
+            (ro_vaddr_offset, 0, ElfError::InvalidRodataAddress(0)),
+            (
+                ro_vaddr_offset,
+                ebpf::MM_STACK_START,
+                ElfError::InvalidRodataAddress(ebpf::MM_STACK_START),
+            ),
+        ] {
+            assert_eq!(
+                Executable::<TestContextObject>::from_serialized(
+                    &tampered(offset, value),
+                    &elf,
+                    loader.clone(),
+                )
+                .err()
+                .map(|error| error.to_string()),
+                Some(EbpfError::ElfError(expected_error).to_string())
+            );
+        }
+        assert!(matches!(
+            Executable::<TestContextObject>::from_serialized(
+                &serialized,
+                &elf,
+                Arc::new(BuiltinProgram::new_loader(Config {
+                    enabled_sbpf_versions: SBPFVersion::V1..=SBPFVersion::V2,
+                    ..config
+                })),
+            ),
+            Err(EbpfError::ElfError(ElfError::UnsupportedSBPFVersion))
+        ));
+    }
+}
+
 #[test]
 fn test_load_elf_rodata_sbpfv0() {
     let config = Config {
@@ -3458,7 +4087,21 @@ fn test_err_fixed_stack_out_of_bound() {
             AccessType::Store,
             0x1FFFFD000,
             1,
-            "program"
+            "program",
+            access_violation_context(
+                Some((
+                    MemoryRegionKind::Rodata,
+                    ebpf::MM_RODATA_START..ebpf::MM_RODATA_START + 16,
+                    0x1FFFFD000 - ebpf::MM_RODATA_START - 16
+                )),
+                Some((
+                    MemoryRegionKind::Stack,
+                    ebpf::MM_STACK_START..ebpf::MM_STACK_START + 2 * 3 * 0x1000,
+                    0x3000
+                )),
+                false,
+                false,
+            ),
         )),
     );
 }
@@ -4007,3 +4650,1036 @@ fn test_symbol_relocation() {
         ProgramResult::Ok(0),
     );
 }
+
+#[test]
+fn test_static_costs_match_instruction_meter() {
+    // Every instruction executes exactly once, lddw counts as one instruction
+    let source = "
+        lddw r1, 0x100000000
+        call function_foo
+        mov64 r0, r1
+        exit
+    function_foo:
+        lddw r2, 0x2
+        add64 r1, r2
+        exit";
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+        ..Config::default()
+    };
+    let executable =
+        assemble::<TestContextObject>(source, Arc::new(BuiltinProgram::new_loader(config.clone())))
+            .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let block_cost = analysis.block_costs().values().sum::<u64>();
+    assert_eq!(analysis.function_costs().values().sum::<u64>(), block_cost);
+    test_interpreter_and_jit_asm!(
+        source,
+        config,
+        [],
+        TestContextObject::new(block_cost),
+        ProgramResult::Ok(0x100000002),
+    );
+}
+
+/// Records the pcs of a range and stops the execution after a number of them
+struct FilteringTracer {
+    remaining: u64,
+    pc_range: std::ops::Range<usize>,
+    stop_after: usize,
+    pcs: Vec<usize>,
+}
+
+impl ContextObject for FilteringTracer {
+    fn trace(&mut self, _state: [u64; 12]) {
+        unreachable!();
+    }
+
+    fn trace_hook(&mut self, pc: usize, registers: &[u64; 12]) -> TraceControl {
+        assert_eq!(registers[11] as usize, pc);
+        if self.pc_range.contains(&pc) {
+            self.pcs.push(pc);
+        }
+        if self.pcs.len() == self.stop_after {
+            TraceControl::Stop
+        } else {
+            TraceControl::Continue
+        }
+    }
+
+    fn consume(&mut self, amount: u64) {
+        self.remaining = self.remaining.saturating_sub(amount);
+    }
+
+    fn get_remaining(&self) -> u64 {
+        self.remaining
+    }
+}
+
+#[test]
+fn test_trace_hook() {
+    let config = Config {
+        enable_instruction_tracing: true,
+        ..Config::default()
+    };
+    let mut executable = assemble::<FilteringTracer>(
+        "
+        mov64 r1, 4
+        loop:
+        add64 r1, -1
+        call function_foo
+        jne r1, 0, loop
+        exit
+        function_foo:
+        mov64 r0, r1
+        exit",
+        Arc::new(BuiltinProgram::new_loader(config)),
+    )
+    .unwrap();
+    executable.verify::<RequisiteVerifier>().unwrap();
+    executable.jit_compile().unwrap();
+    for (stop_after, expected_result) in [(usize::MAX, "Ok(0)"), (3, "Err(TraceStopped)")] {
+        let mut traces = Vec::new();
+        for interpreted in [true, false] {
+            let mut context_object = FilteringTracer {
+                remaining: 100,
+                pc_range: 5..7,
+                stop_after,
+                pcs: Vec::new(),
+            };
+            {
+                create_vm!(
+                    vm,
+                    &executable,
+                    &mut context_object,
+                    stack,
+                    heap,
+                    Vec::new(),
+                    None
+                );
+                let (_instruction_count, result) = vm.execute_program(&executable, interpreted);
+                assert_eq!(format!("{result:?}"), expected_result);
+                if stop_after == 3 {
+                    assert_eq!(vm.registers[11], 5);
+                }
+            }
+            traces.push(context_object.pcs);
+        }
+        assert_eq!(traces[0], traces[1]);
+        assert_eq!(traces[0].len(), 8.min(stop_after));
+    }
+}
+
+declare_builtin_function!(
+    /// Charges 10 instructions and returns its first argument plus one
+    SyscallChargeTen,
+    fn rust(
+        context_object: &mut TestContextObject,
+        arg1: u64,
+        _arg2: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        _memory_mapping: &mut MemoryMapping,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        context_object.consume(10);
+        Ok(arg1 + 1)
+    }
+);
+
+#[test]
+fn test_syscall_replay() {
+    let config = Config {
+        enable_instruction_tracing: true,
+        ..Config::default()
+    };
+    let source = "
+        mov64 r1, 1
+        mov64 r2, 2
+        mov64 r3, 3
+        mov64 r4, 4
+        mov64 r5, 5
+        syscall bpf_gather_bytes
+        mov64 r6, r0
+        mov64 r1, r0
+        syscall bpf_charge_ten
+        add64 r0, r6
+        exit";
+    let mut loader = BuiltinProgram::new_loader(config.clone());
+    loader
+        .register_function("bpf_gather_bytes", syscalls::SyscallGatherBytes::vm)
+        .unwrap();
+    loader
+        .register_function("bpf_charge_ten", SyscallChargeTen::vm)
+        .unwrap();
+    let mut executable = assemble::<TestContextObject>(source, Arc::new(loader)).unwrap();
+    executable.jit_compile().unwrap();
+
+    let mut recordings = Vec::new();
+    for interpreted in [true, false] {
+        let mut records = Vec::new();
+        let mut context_object = TestContextObject::new(100);
+        let (instruction_count, result) = {
+            create_vm!(
+                vm,
+                &executable,
+                &mut context_object,
+                stack,
+                heap,
+                Vec::new(),
+                None
+            );
+            vm.syscall_recorder = Some(&mut records);
+            vm.execute_program(&executable, interpreted)
+        };
+        assert_eq!(format!("{result:?}"), "Ok(8657438731)");
+        recordings.push((records, instruction_count, context_object.trace_log));
+    }
+    let (records, instruction_count, trace_log) = recordings.pop().unwrap();
+    assert_eq!(recordings[0].0, records);
+    let gathered = 0x0102030405;
+    assert_eq!(
+        records,
+        [
+            SyscallRecord {
+                key: syscall_key(b"bpf_gather_bytes"),
+                name: b"bpf_gather_bytes".to_vec(),
+                arguments: [1, 2, 3, 4, 5],
+                result: Ok(gathered),
+                consumed_units: 0,
+            },
+            SyscallRecord {
+                key: syscall_key(b"bpf_charge_ten"),
+                name: b"bpf_charge_ten".to_vec(),
+                arguments: [gathered, 2, 3, 4, 5],
+                result: Ok(gathered + 1),
+                consumed_units: 10,
+            },
+        ]
+    );
+
+    // The replay needs none of the original syscalls
+    let replay = ReplayContextObject::new(records.clone(), 100);
+    let mut executable =
+        assemble::<ReplayContextObject>(source, Arc::new(replay.loader(config).unwrap())).unwrap();
+    executable.jit_compile().unwrap();
+    for interpreted in [true, false] {
+        let mut context_object = replay.clone();
+        let (replayed_instruction_count, result) = {
+            create_vm!(
+                vm,
+                &executable,
+                &mut context_object,
+                stack,
+                heap,
+                Vec::new(),
+                None
+            );
+            vm.execute_program(&executable, interpreted)
+        };
+        assert_eq!(format!("{result:?}"), "Ok(8657438731)");
+        assert!(context_object.is_complete());
+        assert_eq!(replayed_instruction_count, instruction_count);
+        assert!(TestContextObject::compare_trace_log(
+            &TestContextObject {
+                trace_log: trace_log.clone(),
+                ..TestContextObject::default()
+            },
+            &TestContextObject {
+                trace_log: context_object.trace_log,
+                ..TestContextObject::default()
+            },
+        ));
+    }
+
+    // Diverging arguments fail the syscall
+    let mut records = records;
+    records[1].arguments[0] = 0;
+    let mut context_object = ReplayContextObject::new(records, 100);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert_error!(
+        result,
+        "ArgumentsDiverged {{ index: 1, name: \"bpf_charge_ten\""
+    );
+}
+
+#[test]
+fn test_post_mortem_registers_and_call_frames() {
+    let mut loader = BuiltinProgram::new_loader(Config::default());
+    loader
+        .register_function("bpf_syscall_string", syscalls::SyscallString::vm)
+        .unwrap();
+    let loader = Arc::new(loader);
+    let context = || {
+        access_violation_context(
+            Some((
+                MemoryRegionKind::Stack,
+                ebpf::MM_STACK_START..ebpf::MM_STACK_START + 0x40000,
+                (5 << 32) - ebpf::MM_STACK_START - 0x40000,
+            )),
+            None,
+            false,
+            false,
+        )
+    };
+    for (fault, expected_result) in [
+        (
+            "ldxdw r0, [r1+0]",
+            ProgramResult::Err(EbpfError::AccessViolation(
+                AccessType::Load,
+                5 << 32,
+                8,
+                "unknown",
+                context(),
+            )),
+        ),
+        (
+            "syscall bpf_syscall_string",
+            ProgramResult::Err(EbpfError::SyscallError(Box::new(
+                EbpfError::AccessViolation(AccessType::Load, 5 << 32, 3, "unknown", context()),
+            ))),
+        ),
+        ("udiv64 r1, r3", ProgramResult::Err(EbpfError::DivideByZero)),
+    ] {
+        let mut executable = assemble::<TestContextObject>(
+            &format!(
+                "
+                mov64 r6, 6
+                mov64 r7, 7
+                mov64 r8, 8
+                mov64 r9, 9
+                call function_foo
+                exit
+                function_foo:
+                mov64 r6, 16
+                call function_bar
+                exit
+                function_bar:
+                mov64 r1, 5
+                lsh64 r1, 32
+                mov64 r2, 3
+                mov64 r0, 42
+                {fault}
+                exit"
+            ),
+            loader.clone(),
+        )
+        .unwrap();
+        executable.verify::<RequisiteVerifier>().unwrap();
+        executable.jit_compile().unwrap();
+        let mut post_mortems = Vec::new();
+        for interpreted in [true, false] {
+            let mut context_object = TestContextObject::new(100);
+            create_vm!(
+                vm,
+                &executable,
+                &mut context_object,
+                stack,
+                heap,
+                Vec::new(),
+                None
+            );
+            let (_instruction_count, result) = vm.execute_program(&executable, interpreted);
+            post_mortems.push((
+                format!("{result:?}"),
+                *vm.registers(),
+                vm.call_frames().to_vec(),
+            ));
+        }
+        assert_eq!(post_mortems[0], post_mortems[1]);
+        let (result, registers, call_frames) = &post_mortems[0];
+        assert_eq!(result, &format!("{expected_result:?}"));
+        assert_eq!(registers[0], 42);
+        assert_eq!(registers[1], 5 << 32);
+        assert_eq!(registers[6], 16);
+        assert_eq!(registers[11], 13);
+        let callers = call_frames
+            .iter()
+            .map(|frame| {
+                assert_eq!(frame.caller_saved_registers[1..], [7, 8, 9]);
+                let (key, _range) = executable
+                    .function_containing_pc(frame.target_pc as usize)
+                    .unwrap();
+                let (name, _pc) = executable
+                    .get_function_registry()
+                    .lookup_by_key(key)
+                    .unwrap();
+                (frame.target_pc, name.to_vec())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            callers,
+            [(5, b"entrypoint".to_vec()), (8, b"function_foo".to_vec())]
+        );
+    }
+}
+
+#[test]
+fn test_call_depth_and_stack_usage_introspection() {
+    for (sbpf_version, frame_pointer, frame_size) in
+        [(SBPFVersion::V0, "r3", 0), (SBPFVersion::V3, "r10", 64)]
+    {
+        let config = Config {
+            enabled_sbpf_versions: sbpf_version..=sbpf_version,
+            max_call_depth: 5,
+            ..Config::default()
+        };
+        let mut executable = assemble::<TestContextObject>(
+            &format!(
+                "
+                call function_foo
+                exit
+                function_foo:
+                add64 {frame_pointer}, -64
+                jeq r2, 0, +2
+                add64 r2, -1
+                call function_foo
+                exit"
+            ),
+            Arc::new(BuiltinProgram::new_loader(config)),
+        )
+        .unwrap();
+        executable.verify::<RequisiteVerifier>().unwrap();
+        executable.jit_compile().unwrap();
+        for (recursions, expected_result, expected_call_depth) in [
+            (3, "Ok(0)".to_string(), 4),
+            (
+                4,
+                format!(
+                    "{:?}",
+                    ProgramResult::Err(EbpfError::CallDepthExceeded {
+                        pc: 5,
+                        return_addresses: vec![1, 6, 6, 6, 6],
+                    })
+                ),
+                5,
+            ),
+        ] {
+            let mut introspections = Vec::new();
+            for interpreted in [true, false] {
+                let mut context_object = TestContextObject::new(100);
+                create_vm!(
+                    vm,
+                    &executable,
+                    &mut context_object,
+                    stack,
+                    heap,
+                    Vec::new(),
+                    None
+                );
+                vm.initial_registers[2] = recursions;
+                let (_instruction_count, result) = vm.execute_program(&executable, interpreted);
+                introspections.push((
+                    format!("{result:?}"),
+                    vm.max_observed_call_depth(),
+                    vm.peak_stack_usage(),
+                ));
+            }
+            assert_eq!(introspections[0], introspections[1]);
+            // A failed call does not adjust the frame pointer anymore
+            assert_eq!(
+                introspections[0],
+                (expected_result, expected_call_depth, frame_size * 4)
+            );
+        }
+    }
+}
+
+#[test]
+fn test_cow_region() {
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+        ..Config::default()
+    };
+    let mut executable = assemble::<TestContextObject>(
+        "
+        lddw r1, 0x400000000
+        ldxb r2, [r1+1]
+        stb [r1+0], 0x33
+        ldxb r0, [r1+0]
+        lsh64 r0, 8
+        or64 r0, r2
+        exit",
+        Arc::new(BuiltinProgram::new_loader(config)),
+    )
+    .unwrap();
+    executable.verify::<RequisiteVerifier>().unwrap();
+    executable.jit_compile().unwrap();
+    let original = [0x11u8, 0x22];
+    for grant in [true, false] {
+        for interpreted in [true, false] {
+            let copied = Rc::new(RefCell::new(Vec::with_capacity(original.len())));
+            let c = Rc::clone(&copied);
+            let mut context_object = TestContextObject::new(7);
+            create_vm!(
+                vm,
+                &executable,
+                &mut context_object,
+                stack,
+                heap,
+                vec![MemoryRegion::new_cow(&original, ebpf::MM_INPUT_START, 7)],
+                Some(Box::new(move |payload| {
+                    assert_eq!(payload, 7);
+                    if !grant {
+                        return Err(());
+                    }
+                    c.borrow_mut().extend_from_slice(&original);
+                    Ok(c.borrow().as_ptr() as u64)
+                }))
+            );
+            let (_instruction_count, result) = vm.execute_program(&executable, interpreted);
+            if grant {
+                assert_eq!(result.unwrap(), 0x3322);
+                assert_eq!(copied.borrow().as_slice(), &[0x33, 0x22]);
+            } else {
+                assert_eq!(
+                    format!("{result:?}"),
+                    format!(
+                        "{:?}",
+                        ProgramResult::Err(EbpfError::AccessViolation(
+                            AccessType::Store,
+                            ebpf::MM_INPUT_START,
+                            1,
+                            "input",
+                            access_violation_context(
+                                Some((
+                                    MemoryRegionKind::Unnamed,
+                                    ebpf::MM_INPUT_START..ebpf::MM_INPUT_START + 2,
+                                    0
+                                )),
+                                None,
+                                true,
+                                false,
+                            ),
+                        ))
+                    )
+                );
+                assert!(copied.borrow().is_empty());
+            }
+            MemoryDiff::between(&[0x11, 0x22], &original).assert_unchanged();
+        }
+    }
+}
+
+#[test]
+fn test_regions_from_raw_parts() {
+    let mut executable = assemble::<TestContextObject>(
+        "
+        ldxdw r0, [r1+0]
+        add64 r0, 1
+        stxdw [r1+8], r0
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config::default())),
+    )
+    .unwrap();
+    executable.verify::<RequisiteVerifier>().unwrap();
+    executable.jit_compile().unwrap();
+    let config = executable.get_config();
+    let sbpf_version = executable.get_sbpf_version();
+    let stack_len = config.stack_size();
+    // Memory owned by the host, e.g. a C caller, which outlives the VM
+    let stack = Box::leak(vec![0u8; stack_len].into_boxed_slice()).as_mut_ptr();
+    let mut before = [0u8; 16];
+    LittleEndian::write_u64(&mut before[..8], 41);
+    let input = Box::leak(before.to_vec().into_boxed_slice()).as_mut_ptr();
+    let rodata = executable.get_ro_region();
+
+    let regions = || unsafe {
+        vec![
+            MemoryRegion::new_readonly_from_raw(
+                rodata.host_addr.get() as *const u8,
+                rodata.len,
+                rodata.vm_addr,
+            ),
+            MemoryRegion::new_writable_from_raw(stack, stack_len as u64, ebpf::MM_STACK_START),
+            MemoryRegion::new_writable_from_raw(std::ptr::null_mut(), 0, ebpf::MM_HEAP_START),
+            MemoryRegion::new_writable_from_raw(input, 16, ebpf::MM_INPUT_START),
+        ]
+    };
+    for interpreted in [true, false] {
+        let memory_mapping = SendMemoryMapping::new(
+            regions(),
+            config,
+            sbpf_version,
+            Box::new(default_memory_cow_callback),
+        )
+        .unwrap();
+        // Move the prepared mapping to a worker thread and execute there
+        let (instruction_count, result) = std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    let mut context_object = TestContextObject::new(4);
+                    let mut vm = EbpfVm::try_new(
+                        executable.get_loader().clone(),
+                        sbpf_version,
+                        &mut context_object,
+                        memory_mapping.into_inner(),
+                        stack_len,
+                    )
+                    .unwrap();
+                    let (instruction_count, result) = vm.execute_program(&executable, interpreted);
+                    (instruction_count, format!("{result:?}"))
+                })
+                .join()
+                .unwrap()
+        });
+        assert_eq!(instruction_count, 4);
+        assert_eq!(result, "Ok(42)");
+        let output = unsafe { std::slice::from_raw_parts_mut(input, 16) };
+        MemoryDiff::between(&before, output).assert_changes(&[(8, &[42])]);
+        output[8..].fill(0);
+    }
+
+    // Regions created from slices are bound to the current thread
+    let mut regions = regions();
+    regions[3] = MemoryRegion::new_readonly(&[0; 16], ebpf::MM_INPUT_START);
+    assert_error!(
+        SendMemoryMapping::new(
+            regions,
+            config,
+            sbpf_version,
+            Box::new(default_memory_cow_callback),
+        ),
+        "InvalidMemoryRegion(3)"
+    );
+}
+
+#[test]
+fn test_vm_with_bump_allocator() {
+    let mut executable = assemble::<TestContextObject>(
+        "
+        stxdw [r2], r1
+        ldxdw r0, [r2]
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config::default())),
+    )
+    .unwrap();
+    executable.verify::<RequisiteVerifier>().unwrap();
+    executable.jit_compile().unwrap();
+    let mut allocator = BumpAllocator::new(64, 64, ebpf::MM_HEAP_START);
+    let mut input = [0u8; 8];
+    for interpreted in [true, false] {
+        allocator.reset();
+        allocator
+            .alloc(Layout::from_size_align(1, 1).unwrap())
+            .unwrap();
+        let vm_addr = allocator
+            .alloc(Layout::from_size_align(8, 32).unwrap())
+            .unwrap();
+        assert_eq!(vm_addr, ebpf::MM_HEAP_START + 32);
+        let mut context_object = TestContextObject::new(3);
+        let mut stack = AlignedMemory::zero_filled(executable.get_config().stack_size());
+        let mut vm = EbpfVm::new_with_regions(
+            &executable,
+            &mut context_object,
+            &mut stack,
+            Some(&mut allocator),
+            vec![MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START)],
+        )
+        .unwrap();
+        vm.initial_registers[2] = vm_addr;
+        let (_instruction_count, result) = vm.execute_program(&executable, interpreted);
+        assert_eq!(result.unwrap(), ebpf::MM_INPUT_START);
+    }
+
+    // Without an allocator the heap region is empty
+    let mut context_object = TestContextObject::new(3);
+    let mut stack = AlignedMemory::zero_filled(executable.get_config().stack_size());
+    let mut vm = EbpfVm::new_with_regions(
+        &executable,
+        &mut context_object,
+        &mut stack,
+        None,
+        Vec::new(),
+    )
+    .unwrap();
+    vm.initial_registers[2] = ebpf::MM_HEAP_START;
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert_eq!(
+        format!("{result:?}"),
+        format!(
+            "{:?}",
+            ProgramResult::Err(EbpfError::AccessViolation(
+                AccessType::Store,
+                ebpf::MM_HEAP_START,
+                8,
+                "heap",
+                access_violation_context(
+                    Some((
+                        MemoryRegionKind::Stack,
+                        ebpf::MM_STACK_START..ebpf::MM_STACK_START + 0x40000,
+                        ebpf::MM_HEAP_START - ebpf::MM_STACK_START - 0x40000
+                    )),
+                    None,
+                    false,
+                    false,
+                ),
+            ))
+        )
+    );
+}
+
+#[test]
+fn test_snapshot_restore() {
+    // Writes to the input, stack and heap regions
+    let program = "
+        mov64 r3, 0
+        stxdw [r1+0], r3
+        stxdw [r10-8], r3
+        stxdw [r2+0], r3
+        add64 r1, 8
+        add64 r3, 1
+        jlt r3, 16, -6
+        ldxdw r0, [r10-8]
+        exit";
+    const INSTRUCTION_COUNT: u64 = 1 + 16 * 6 + 2;
+    let writable_contents = |memory_mapping: &MemoryMapping| {
+        memory_mapping
+            .get_regions()
+            .iter()
+            .filter(|region| region.writable.get())
+            .map(|region| {
+                let mut contents = vec![0; region.len as usize];
+                memory_mapping
+                    .copy_from_vm(region.vm_addr, &mut contents)
+                    .unwrap();
+                contents
+            })
+            .collect::<Vec<_>>()
+    };
+    for enable_dirty_page_tracking in [false, true] {
+        let config = Config {
+            enable_dirty_page_tracking,
+            ..Config::default()
+        };
+        let mut executable =
+            assemble::<TestContextObject>(program, Arc::new(BuiltinProgram::new_loader(config)))
+                .unwrap();
+        executable.verify::<RequisiteVerifier>().unwrap();
+        executable.jit_compile().unwrap();
+        for interpreted in [true, false] {
+            let mut outcomes = Vec::new();
+            for fail_first in [false, true] {
+                let mut allocator = BumpAllocator::new(64, 64, ebpf::MM_HEAP_START);
+                let mut stack = AlignedMemory::zero_filled(executable.get_config().stack_size());
+                let mut input = [0xAAu8; 128];
+                let mut context_object = TestContextObject::new(INSTRUCTION_COUNT);
+                let mut vm = EbpfVm::new_with_regions(
+                    &executable,
+                    &mut context_object,
+                    &mut stack,
+                    Some(&mut allocator),
+                    vec![MemoryRegion::new_writable(&mut input, ebpf::MM_INPUT_START)],
+                )
+                .unwrap();
+                vm.initial_registers[2] = ebpf::MM_HEAP_START;
+                if fail_first {
+                    let snapshot = vm.memory_mapping.snapshot();
+                    assert_eq!(
+                        snapshot.len(),
+                        executable.get_config().stack_size() + 64 + 128
+                    );
+                    let initial_contents = writable_contents(&vm.memory_mapping);
+                    vm.context_object_pointer.remaining = INSTRUCTION_COUNT / 2;
+                    let (_instruction_count, result) =
+                        vm.execute_program(&executable, interpreted);
+                    assert_error!(result, "ExceededMaxInstructions");
+                    assert_ne!(writable_contents(&vm.memory_mapping), initial_contents);
+                    vm.memory_mapping.restore(&snapshot).unwrap();
+                    assert_eq!(writable_contents(&vm.memory_mapping), initial_contents);
+                    vm.context_object_pointer.remaining = INSTRUCTION_COUNT;
+                }
+                let (instruction_count, result) = vm.execute_program(&executable, interpreted);
+                assert_eq!(result.unwrap(), 15);
+                outcomes.push((
+                    instruction_count,
+                    vm.registers,
+                    writable_contents(&vm.memory_mapping),
+                ));
+            }
+            assert_eq!(outcomes[0], outcomes[1]);
+        }
+    }
+}
+
+#[test]
+fn test_memory_access_stats() {
+    let mut executable = assemble::<TestContextObject>(
+        "
+        lddw r1, 0x100000000
+        ldxdw r2, [r1]
+        ldxb r2, [r1+8]
+        stxdw [r10-8], r2
+        ldxw r3, [r10-8]
+        lddw r4, 0x300000000
+        stxh [r4], r3
+        stb [r4+2], 1
+        ldxdw r0, [r4]
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+            enable_memory_access_stats: true,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    executable.verify::<RequisiteVerifier>().unwrap();
+    executable.jit_compile().unwrap();
+    let mut allocator = BumpAllocator::new(8, 8, ebpf::MM_HEAP_START);
+    for interpreted in [true, false] {
+        let mut context_object = TestContextObject::new(10);
+        let mut stack = AlignedMemory::zero_filled(executable.get_config().stack_size());
+        let mut vm = EbpfVm::new_with_regions(
+            &executable,
+            &mut context_object,
+            &mut stack,
+            Some(&mut allocator),
+            Vec::new(),
+        )
+        .unwrap();
+        let (_instruction_count, result) = vm.execute_program(&executable, interpreted);
+        assert_eq!(result.unwrap(), 0x0001_0000);
+        if !interpreted {
+            assert!(vm.memory_access_stats().is_none());
+            continue;
+        }
+        let stats = vm.memory_access_stats().unwrap();
+        let region = |loads, bytes_loaded, stores, bytes_stored| RegionAccessStats {
+            loads,
+            stores,
+            bytes_loaded,
+            bytes_stored,
+        };
+        assert_eq!(
+            stats.regions.iter().collect::<Vec<_>>(),
+            [
+                (&1, &region(2, 9, 0, 0)),
+                (&2, &region(1, 4, 1, 8)),
+                (&3, &region(1, 8, 2, 3)),
+            ]
+        );
+        assert_eq!(stats.access_sizes, [2, 1, 1, 3]);
+    }
+}
+
+#[test]
+fn test_syscall_base_cost() {
+    for (charge_syscall_base_costs, base_cost) in [(false, 100), (true, 100), (true, 1 << 40)] {
+        let config = Config {
+            charge_syscall_base_costs,
+            enable_instruction_tracing: true,
+            ..Config::default()
+        };
+        let loader = BuiltinProgramBuilder::new_loader(config)
+            .syscall_with_metadata(
+                "bpf_syscall_string",
+                syscalls::SyscallString::vm,
+                true,
+                SyscallMetadata { base_cost },
+            )
+            .build()
+            .unwrap();
+        let mut executable = assemble::<TestContextObject>(
+            "
+            mov64 r1, 0
+            syscall bpf_syscall_string
+            exit",
+            Arc::new(loader),
+        )
+        .unwrap();
+        let charged_base_cost = if charge_syscall_base_costs {
+            base_cost
+        } else {
+            0
+        };
+        // The syscall fails, but its base cost is charged anyway
+        let result = test_interpreter_and_jit!(
+            override_budget => false,
+            executable,
+            [],
+            TestContextObject::new(2 + charged_base_cost),
+        );
+        assert_error!(
+            result,
+            "SyscallError(AccessViolation(Load, 0, 0, \"unknown\", AccessViolationContext {{ below: None, above: Some(NearbyRegion {{ kind: Rodata, vm_addr: {}",
+            ebpf::MM_RODATA_START
+        );
+        if charge_syscall_base_costs {
+            // The syscall is not invoked at all if the budget does not cover its base cost
+            let result = test_interpreter_and_jit!(
+                override_budget => false,
+                executable,
+                [],
+                TestContextObject::new(1 + charged_base_cost),
+            );
+            assert_error!(result, "ExceededMaxInstructions");
+        }
+    }
+}
+
+#[test]
+fn test_instruction_meter_blocks() {
+    // Without instruction tracing the JIT validates the instruction meter once per block. If the
+    // meter runs out within a block, it must still stop at the same instruction as the
+    // interpreter and raise the exceptions which happen before.
+    for (source, instruction_count, expected_result) in [
+        (
+            "
+            mov64 r0, 0
+            mov64 r1, 3
+            loop:
+            add64 r0, 1
+            mov64 r2, r0
+            mul64 r2, 7
+            add64 r1, -1
+            jne r1, 0, loop
+            lddw r3, 0x100000000
+            add64 r0, r3
+            div64 r0, r1
+            exit",
+            20,
+            "DivideByZero",
+        ),
+        (
+            "
+            mov64 r1, 1
+            stxdw [r10-8], r1
+            ldxdw r2, [r10-8]
+            add64 r2, r1
+            stxdw [r2+0], r1
+            exit",
+            5,
+            "AccessViolation(Store, 2, 8",
+        ),
+        // Callx jumps to `add64 r0, 4` in the middle of a block
+        (
+            "
+            mov64 r0, 0
+            lddw r8, 0x100000040
+            callx r8
+            exit
+            mov64 r0, 1
+            mov64 r0, 2
+            mov64 r0, 3
+            add64 r0, 4
+            add64 r0, 5
+            exit",
+            7,
+            "Ok(9)",
+        ),
+    ] {
+        for instruction_meter_checkpoint_distance in [0, 3, 10000] {
+            let config = Config {
+                enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+                instruction_meter_checkpoint_distance,
+                ..Config::default()
+            };
+            let mut executable =
+                assemble::<TestContextObject>(source, Arc::new(BuiltinProgram::new_loader(config)))
+                    .unwrap();
+            for budget in 0..=instruction_count {
+                let result = test_interpreter_and_jit!(
+                    override_budget => false,
+                    executable,
+                    [],
+                    TestContextObject::new(budget),
+                );
+                if budget < instruction_count {
+                    assert_error!(result, "ExceededMaxInstructions");
+                } else {
+                    assert!(format!("{result:?}").contains(expected_result));
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_instruction_meter_block_events() {
+    // The interpreter which finishes an exhausted block of the JIT records the error too
+    let mut executable = assemble::<TestContextObject>(
+        "
+        mov64 r0, 0
+        mov64 r0, 1
+        mov64 r0, 2
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config::default())),
+    )
+    .unwrap();
+    executable.verify::<RequisiteVerifier>().unwrap();
+    executable.jit_compile().unwrap();
+    for interpreted in [true, false] {
+        let mut context_object = TestContextObject {
+            execution_events: Some(ExecutionEventLog::new(usize::MAX)),
+            ..TestContextObject::new(2)
+        };
+        {
+            create_vm!(
+                vm,
+                &executable,
+                &mut context_object,
+                stack,
+                heap,
+                Vec::new(),
+                None
+            );
+            let (_instruction_count, result) = vm.execute_program(&executable, interpreted);
+            assert_error!(result, "ExceededMaxInstructions");
+        }
+        let events = context_object.execution_events.unwrap().events;
+        assert_eq!(
+            events.last(),
+            Some(&(
+                2,
+                ExecutionEvent::Error("exceeded CUs meter at BPF instruction".to_string())
+            ))
+        );
+    }
+}
+
+#[test]
+fn test_predecoded_instructions() {
+    // Without instruction tracing, falling through the last instruction is an overrun too
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+        ..Config::default()
+    };
+    let mut executable = assemble::<TestContextObject>(
+        "
+        lddw r0, 0x1122334455667788
+        add64 r0, 1",
+        Arc::new(BuiltinProgram::new_loader(config.clone())),
+    )
+    .unwrap();
+    assert!(executable.get_predecoded_instructions().is_none());
+    test_interpreter_and_jit!(
+        executable,
+        [],
+        TestContextObject::new(3),
+        ProgramResult::Err(EbpfError::ExecutionOverrun),
+    );
+    let insns = executable.get_predecoded_instructions().unwrap();
+    assert_eq!(insns.len(), 3);
+    assert_eq!(insns[0].imm, 0x1122334455667788);
+    assert_eq!((insns[2].ptr, insns[2].opc), (2, ebpf::ADD64_IMM));
+
+    // Predecoding verifies first
+    let mut executable = assemble::<TestContextObject>(
+        "
+        ja +1
+        exit",
+        Arc::new(BuiltinProgram::new_loader(config)),
+    )
+    .unwrap();
+    assert_error!(executable.predecode(), "JumpOutOfCode");
+    assert!(executable.get_predecoded_instructions().is_none());
+}
@@ -17,7 +17,6 @@ extern crate thiserror;
 use rand::{rngs::SmallRng, RngCore, SeedableRng};
 use solana_sbpf::{
     assembler::assemble,
-    ebpf,
     memory_region::MemoryRegion,
     program::{BuiltinProgram, SBPFVersion},
     static_analysis::Analysis,
@@ -0,0 +1,320 @@
+#![allow(clippy::arithmetic_side_effects)]
+#![cfg(all(target_pointer_width = "64", target_endian = "little"))]
+// Copyright 2020 Solana Maintainers <maintainers@solana.com>
+//
+// Licensed under the Apache License, Version 2.0 <http://www.apache.org/licenses/LICENSE-2.0> or
+// the MIT license <http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use solana_sbpf::{
+    ebpf,
+    error::EbpfError,
+    interpreter::{exec_one, InsnState},
+    memory_region::MemoryMapping,
+    program::SBPFVersion,
+    vm::Config,
+};
+
+const VERSIONS: [SBPFVersion; 2] = [SBPFVersion::V0, SBPFVersion::V3];
+
+const REG_OPERANDS: [u64; 16] = [
+    0,
+    1,
+    2,
+    31,
+    32,
+    63,
+    64,
+    0x7fff_ffff,
+    0x8000_0000,
+    0xffff_ffff,
+    0x1_0000_0000,
+    0x0123_4567_89ab_cdef,
+    0x7fff_ffff_ffff_ffff,
+    0x8000_0000_0000_0000,
+    0xffff_ffff_8000_0000,
+    u64::MAX,
+];
+
+const IMM_OPERANDS: [i32; 10] = [0, 1, 2, 31, 32, 63, 64, -1, i32::MIN, i32::MAX];
+
+/// Opcodes which encode memory accesses once `move_memory_instruction_classes()` is enabled
+const MOVED_MEMORY_OPCODES: [u8; 12] = [
+    ebpf::LD_1B_REG,
+    ebpf::LD_2B_REG,
+    ebpf::LD_4B_REG,
+    ebpf::LD_8B_REG,
+    ebpf::ST_1B_IMM,
+    ebpf::ST_2B_IMM,
+    ebpf::ST_4B_IMM,
+    ebpf::ST_8B_IMM,
+    ebpf::ST_1B_REG,
+    ebpf::ST_2B_REG,
+    ebpf::ST_4B_REG,
+    ebpf::ST_8B_REG,
+];
+
+const ALU32_OPCODES: [u8; 25] = [
+    ebpf::ADD32_IMM,
+    ebpf::ADD32_REG,
+    ebpf::SUB32_IMM,
+    ebpf::SUB32_REG,
+    ebpf::MUL32_IMM,
+    ebpf::MUL32_REG,
+    ebpf::DIV32_IMM,
+    ebpf::DIV32_REG,
+    ebpf::OR32_IMM,
+    ebpf::OR32_REG,
+    ebpf::AND32_IMM,
+    ebpf::AND32_REG,
+    ebpf::LSH32_IMM,
+    ebpf::LSH32_REG,
+    ebpf::RSH32_IMM,
+    ebpf::RSH32_REG,
+    ebpf::NEG32,
+    ebpf::MOD32_IMM,
+    ebpf::MOD32_REG,
+    ebpf::XOR32_IMM,
+    ebpf::XOR32_REG,
+    ebpf::MOV32_IMM,
+    ebpf::MOV32_REG,
+    ebpf::ARSH32_IMM,
+    ebpf::ARSH32_REG,
+];
+
+const ALU64_OPCODES: [u8; 25] = [
+    ebpf::ADD64_IMM,
+    ebpf::ADD64_REG,
+    ebpf::SUB64_IMM,
+    ebpf::SUB64_REG,
+    ebpf::MUL64_IMM,
+    ebpf::MUL64_REG,
+    ebpf::DIV64_IMM,
+    ebpf::DIV64_REG,
+    ebpf::OR64_IMM,
+    ebpf::OR64_REG,
+    ebpf::AND64_IMM,
+    ebpf::AND64_REG,
+    ebpf::LSH64_IMM,
+    ebpf::LSH64_REG,
+    ebpf::RSH64_IMM,
+    ebpf::RSH64_REG,
+    ebpf::NEG64,
+    ebpf::MOD64_IMM,
+    ebpf::MOD64_REG,
+    ebpf::XOR64_IMM,
+    ebpf::XOR64_REG,
+    ebpf::MOV64_IMM,
+    ebpf::MOV64_REG,
+    ebpf::ARSH64_IMM,
+    ebpf::ARSH64_REG,
+];
+
+fn s32(value: u64) -> i128 {
+    value as u32 as i32 as i128
+}
+
+fn u32_(value: u64) -> i128 {
+    value as u32 as i128
+}
+
+fn s64(value: u64) -> i128 {
+    value as i64 as i128
+}
+
+fn u64_(value: u64) -> i128 {
+    value as i128
+}
+
+fn wrap32(value: i128) -> u64 {
+    value.rem_euclid(1 << 32) as u64
+}
+
+fn wrap64(value: i128) -> u64 {
+    value.rem_euclid(1 << 64) as u64
+}
+
+fn sext32(value: i128) -> u64 {
+    let value = wrap32(value);
+    if value >= 1 << 31 {
+        value | 0xffff_ffff_0000_0000
+    } else {
+        value
+    }
+}
+
+/// Straightforward model of the ALU opcodes using i128 arithmetic.
+///
+/// `src` is the register value or the sign extended immediate. Returns `None` if the opcode is
+/// not available in `sbpf_version`.
+fn reference(
+    sbpf_version: SBPFVersion,
+    opc: u8,
+    dst: u64,
+    src: u64,
+) -> Option<Result<u64, EbpfError>> {
+    let v0 = sbpf_version == SBPFVersion::V0;
+    let result32 = |value: i128| {
+        if sbpf_version.explicit_sign_extension_of_results() {
+            wrap32(value)
+        } else {
+            sext32(value)
+        }
+    };
+    let result = match opc {
+        ebpf::ADD32_IMM | ebpf::ADD32_REG => result32(s32(dst) + s32(src)),
+        ebpf::SUB32_IMM if sbpf_version.swap_sub_reg_imm_operands() => result32(s32(src) - s32(dst)),
+        ebpf::SUB32_IMM | ebpf::SUB32_REG => result32(s32(dst) - s32(src)),
+        ebpf::MUL32_IMM | ebpf::MUL32_REG if v0 => sext32(s32(dst) * s32(src)),
+        ebpf::DIV32_IMM | ebpf::DIV32_REG | ebpf::MOD32_IMM | ebpf::MOD32_REG
+            if v0 && u32_(src) == 0 =>
+        {
+            return Some(Err(EbpfError::DivideByZero))
+        }
+        ebpf::DIV32_IMM | ebpf::DIV32_REG if v0 => wrap32(u32_(dst) / u32_(src)),
+        ebpf::MOD32_IMM | ebpf::MOD32_REG if v0 => wrap32(u32_(dst) % u32_(src)),
+        ebpf::OR32_IMM | ebpf::OR32_REG => wrap32(u32_(dst) | u32_(src)),
+        ebpf::AND32_IMM | ebpf::AND32_REG => wrap32(u32_(dst) & u32_(src)),
+        ebpf::XOR32_IMM | ebpf::XOR32_REG => wrap32(u32_(dst) ^ u32_(src)),
+        ebpf::LSH32_IMM | ebpf::LSH32_REG => wrap32(u32_(dst) << (src % 32)),
+        ebpf::RSH32_IMM | ebpf::RSH32_REG => wrap32(u32_(dst) >> (src % 32)),
+        ebpf::ARSH32_IMM | ebpf::ARSH32_REG => wrap32(s32(dst) >> (src % 32)),
+        ebpf::NEG32 if v0 => wrap32(-s32(dst)),
+        ebpf::MOV32_IMM => wrap32(s64(src)),
+        ebpf::MOV32_REG if sbpf_version.explicit_sign_extension_of_results() => sext32(s32(src)),
+        ebpf::MOV32_REG => wrap32(u32_(src)),
+
+        ebpf::ADD64_IMM | ebpf::ADD64_REG => wrap64(u64_(dst) + u64_(src)),
+        ebpf::SUB64_IMM if sbpf_version.swap_sub_reg_imm_operands() => wrap64(s64(src) - u64_(dst)),
+        ebpf::SUB64_IMM | ebpf::SUB64_REG => wrap64(u64_(dst) - u64_(src)),
+        ebpf::MUL64_IMM | ebpf::MUL64_REG if v0 => wrap64(s64(dst) * s64(src)),
+        ebpf::DIV64_IMM | ebpf::DIV64_REG | ebpf::MOD64_IMM | ebpf::MOD64_REG
+            if v0 && src == 0 =>
+        {
+            return Some(Err(EbpfError::DivideByZero))
+        }
+        ebpf::DIV64_IMM | ebpf::DIV64_REG if v0 => wrap64(u64_(dst) / u64_(src)),
+        ebpf::MOD64_IMM | ebpf::MOD64_REG if v0 => wrap64(u64_(dst) % u64_(src)),
+        ebpf::OR64_IMM | ebpf::OR64_REG => wrap64(u64_(dst) | u64_(src)),
+        ebpf::AND64_IMM | ebpf::AND64_REG => wrap64(u64_(dst) & u64_(src)),
+        ebpf::XOR64_IMM | ebpf::XOR64_REG => wrap64(u64_(dst) ^ u64_(src)),
+        ebpf::LSH64_IMM | ebpf::LSH64_REG => wrap64(u64_(dst) << (src % 64)),
+        ebpf::RSH64_IMM | ebpf::RSH64_REG => wrap64(u64_(dst) >> (src % 64)),
+        ebpf::ARSH64_IMM | ebpf::ARSH64_REG => wrap64(s64(dst) >> (src % 64)),
+        ebpf::NEG64 if v0 => wrap64(-s64(dst)),
+        ebpf::MOV64_IMM | ebpf::MOV64_REG => src,
+        _ => return None,
+    };
+    Some(Ok(result))
+}
+
+fn check_against_reference(sbpf_version: SBPFVersion, opc: u8, dst: u64, src: u64, imm: i64) {
+    let config = Config::default();
+    let memory_mapping = MemoryMapping::new(Vec::new(), &config, sbpf_version).unwrap();
+    let mut reg = [0u64; 12];
+    reg[1] = dst;
+    reg[2] = src;
+    let insn = ebpf::Insn {
+        ptr: 0,
+        opc,
+        dst: 1,
+        src: 2,
+        off: 0,
+        imm,
+    };
+    let operand = if opc & ebpf::BPF_X != 0 {
+        src
+    } else {
+        imm as u64
+    };
+    let expected = reference(sbpf_version, opc, dst, operand)
+        .unwrap_or(Err(EbpfError::UnsupportedInstruction));
+    let before = reg;
+    let result = exec_one(
+        &insn,
+        &mut InsnState {
+            reg: &mut reg,
+            memory_mapping: &memory_mapping,
+            sbpf_version,
+        },
+    );
+    let context = format!("{sbpf_version:?} opc={opc:#04x} dst={dst:#x} src={src:#x} imm={imm:#x}");
+    match expected {
+        Ok(value) => {
+            assert!(result.is_ok(), "{}: {:?}", context, result);
+            assert_eq!(reg[1], value, "{context}");
+            assert_eq!(reg[11], 1, "{context}");
+            assert_eq!(reg[2], src, "{context}");
+        }
+        Err(err) => {
+            assert_eq!(format!("{result:?}"), format!("{:?}", Err::<(), _>(err)), "{context}");
+            assert_eq!(reg, before, "{context}");
+        }
+    }
+}
+
+fn check_family(opcodes: &[u8]) {
+    for sbpf_version in VERSIONS {
+        for opc in opcodes.iter().copied() {
+            if sbpf_version.move_memory_instruction_classes() && MOVED_MEMORY_OPCODES.contains(&opc)
+            {
+                continue;
+            }
+            for dst in REG_OPERANDS {
+                if opc & ebpf::BPF_X != 0 {
+                    for src in REG_OPERANDS {
+                        check_against_reference(sbpf_version, opc, dst, src, 0);
+                    }
+                } else {
+                    for imm in IMM_OPERANDS {
+                        // Immediate divisors of zero are rejected by the verifier
+                        if imm == 0
+                            && matches!(
+                                opc,
+                                ebpf::DIV32_IMM | ebpf::MOD32_IMM | ebpf::DIV64_IMM | ebpf::MOD64_IMM
+                            )
+                        {
+                            continue;
+                        }
+                        check_against_reference(sbpf_version, opc, dst, 0, imm as i64);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_alu32_semantics() {
+    check_family(&ALU32_OPCODES);
+}
+
+#[test]
+fn test_alu64_semantics() {
+    check_family(&ALU64_OPCODES);
+}
+
+#[test]
+fn test_exec_one_rejects_control_flow() {
+    let config = Config::default();
+    for sbpf_version in VERSIONS {
+        let memory_mapping = MemoryMapping::new(Vec::new(), &config, sbpf_version).unwrap();
+        for opc in [ebpf::CALL_IMM, ebpf::CALL_REG, ebpf::EXIT, ebpf::RETURN] {
+            let mut reg = [0u64; 12];
+            let insn = ebpf::Insn {
+                opc,
+                ..ebpf::Insn::default()
+            };
+            let result = exec_one(
+                &insn,
+                &mut InsnState {
+                    reg: &mut reg,
+                    memory_mapping: &memory_mapping,
+                    sbpf_version,
+                },
+            );
+            assert!(matches!(result, Err(EbpfError::UnsupportedInstruction)));
+            assert_eq!(reg[11], 0);
+        }
+    }
+}
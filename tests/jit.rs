@@ -2,21 +2,25 @@
 #![cfg(all(test, target_arch = "x86_64", not(target_os = "windows")))]
 
 use byteorder::{ByteOrder, LittleEndian};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
 use solana_sbpf::{
-    disassembler::disassemble_instruction,
+    assembler::assemble,
+    disassembler::{decode, disassemble_instruction, Operands},
     ebpf,
     elf::Executable,
     error::EbpfError,
     jit::{
-        MACHINE_CODE_PER_INSTRUCTION_METER_CHECKPOINT, MAX_EMPTY_PROGRAM_MACHINE_CODE_LENGTH,
-        MAX_MACHINE_CODE_LENGTH_PER_INSTRUCTION,
+        estimate_machine_code_size, MACHINE_CODE_PER_INSTRUCTION_METER_CHECKPOINT,
+        MAX_EMPTY_PROGRAM_MACHINE_CODE_LENGTH, MAX_MACHINE_CODE_LENGTH_PER_INSTRUCTION,
+        MAX_MACHINE_CODE_LENGTH_PER_OPCODE,
     },
     program::{BuiltinProgram, FunctionRegistry, SBPFVersion},
     static_analysis::CfgNode,
+    verifier::RequisiteVerifier,
     vm::Config,
 };
 use std::{collections::BTreeMap, sync::Arc};
-use test_utils::{syscalls, TestContextObject};
+use test_utils::{create_vm, syscalls, TestContextObject};
 
 fn create_mockup_executable(config: Config, program: &[u8]) -> Executable<TestContextObject> {
     let sbpf_version = *config.enabled_sbpf_versions.end();
@@ -65,6 +69,10 @@ fn test_code_length_estimate() {
             empty_program_machine_code_length;
     }
 
+    assert!(MAX_MACHINE_CODE_LENGTH_PER_OPCODE
+        .iter()
+        .all(|length| *length <= MAX_MACHINE_CODE_LENGTH_PER_INSTRUCTION));
+
     let mut instruction_meter_checkpoint_machine_code_length = [0; 2];
     for (index, machine_code_length) in instruction_meter_checkpoint_machine_code_length
         .iter_mut()
@@ -141,30 +149,40 @@ fn test_code_length_estimate() {
             };
             let mut executable = create_mockup_executable(config, &prog);
             let result = Executable::<TestContextObject>::jit_compile(&mut executable);
-            if result.is_err() {
-                assert!(matches!(
-                    result.unwrap_err(),
-                    EbpfError::UnsupportedInstruction
-                ));
+            if let Err(err) = result {
+                assert!(matches!(err, EbpfError::UnsupportedInstruction));
                 continue;
             }
-            let machine_code_length = executable
-                .get_compiled_program()
-                .unwrap()
-                .machine_code_length()
-                - empty_program_machine_code_length;
             let instruction_count = if opcode == 0x18 {
                 // LDDW takes two slots
                 INSTRUCTION_COUNT / 2
             } else {
                 INSTRUCTION_COUNT
             };
+            // Branches and lddw end their instruction meter block, the others share the blocks
+            // starting at pc 0 and at function_foo
+            let instruction_meter_block_count =
+                if opcode == 0x18 || opcode & ebpf::BPF_CLS_MASK == ebpf::BPF_JMP {
+                    instruction_count
+                } else {
+                    2
+                };
+            let machine_code_length = executable
+                .get_compiled_program()
+                .unwrap()
+                .machine_code_length()
+                - empty_program_machine_code_length
+                - instruction_meter_block_count * instruction_meter_checkpoint_machine_code_length;
             let machine_code_length_per_instruction =
                 machine_code_length as f64 / instruction_count as f64;
             assert!(
                 f64::ceil(machine_code_length_per_instruction) as usize
                     <= MAX_MACHINE_CODE_LENGTH_PER_INSTRUCTION
             );
+            assert!(
+                f64::ceil(machine_code_length_per_instruction) as usize
+                    <= MAX_MACHINE_CODE_LENGTH_PER_OPCODE[opcode as usize]
+            );
             let insn = ebpf::get_insn_unchecked(&prog, 0);
             let assembly = disassemble_instruction(
                 &insn,
@@ -178,3 +196,417 @@ fn test_code_length_estimate() {
         }
     }
 }
+
+fn generate_program(prng: &mut SmallRng, sbpf_version: SBPFVersion, len: usize) -> Vec<u8> {
+    let opcodes = (0..=0xFF)
+        .filter(|opc| {
+            let insn = ebpf::Insn {
+                opc: *opc,
+                ..ebpf::Insn::default()
+            };
+            MAX_MACHINE_CODE_LENGTH_PER_OPCODE[*opc as usize] != 0
+                && !matches!(
+                    decode(&insn, sbpf_version).operands,
+                    Operands::Unknown { .. }
+                )
+        })
+        .collect::<Vec<u8>>();
+    let mut prog = vec![0; len * ebpf::INSN_SIZE];
+    let mut pc = 0;
+    while pc + 1 < len {
+        let opcode = opcodes[prng.random_range(0..opcodes.len())];
+        let lddw = opcode == ebpf::LD_DW_IMM && !sbpf_version.disable_lddw();
+        if lddw && pc + 2 >= len {
+            continue;
+        }
+        let imm = match opcode {
+            ebpf::LE | ebpf::BE => [16, 32, 64][prng.random_range(0..3)],
+            // Recursive call of the entrypoint
+            ebpf::CALL_IMM if sbpf_version.static_syscalls() => -(pc as i32) - 1,
+            // Registered and unknown syscall
+            ebpf::CALL_IMM => [0x91020CDD_u32 as i32, 0x12345678][prng.random_range(0..2)],
+            ebpf::SYSCALL if sbpf_version.static_syscalls() => 0x91020CDD_u32 as i32,
+            ebpf::CALL_REG => prng.random_range(0..10),
+            ebpf::LSH32_IMM
+            | ebpf::LSH64_IMM
+            | ebpf::RSH32_IMM
+            | ebpf::RSH64_IMM
+            | ebpf::ARSH32_IMM
+            | ebpf::ARSH64_IMM => prng.random_range(0..32),
+            _ if prng.random() => prng.random_range(1..300),
+            _ => prng.random::<i32>() | 1,
+        };
+        let off = prng.random_range(-(pc as i64) - 1..(len - pc - 2) as i64) as i16;
+        let slot = &mut prog[pc * ebpf::INSN_SIZE..];
+        slot[0] = opcode;
+        slot[1] = prng.random_range(0..10) << 4 | prng.random_range(0..10);
+        LittleEndian::write_i16(&mut slot[2..], off);
+        LittleEndian::write_i32(&mut slot[4..], imm);
+        if lddw {
+            LittleEndian::write_i32(&mut slot[12..], prng.random());
+            pc += 1;
+        }
+        pc += 1;
+    }
+    prog[(len - 1) * ebpf::INSN_SIZE] = if sbpf_version.static_syscalls() {
+        ebpf::RETURN
+    } else {
+        ebpf::EXIT
+    };
+    prog
+}
+
+#[test]
+fn test_machine_code_size_estimate() {
+    let mut prng = SmallRng::seed_from_u64(0xC2DB2F8F282284A0);
+    let mut verified_programs = 0;
+    for _ in 0..2000 {
+        let sbpf_version = [SBPFVersion::V0, SBPFVersion::V3][prng.random_range(0..2)];
+        let len = prng.random_range(2..300);
+        let prog = generate_program(&mut prng, sbpf_version, len);
+        let config = Config {
+            enabled_sbpf_versions: sbpf_version..=sbpf_version,
+            enable_instruction_meter: prng.random(),
+            enable_instruction_tracing: prng.random(),
+            sanitize_user_provided_values: prng.random(),
+            enable_address_translation: prng.random(),
+            enable_stack_frame_gaps: prng.random(),
+            charge_syscall_base_costs: prng.random(),
+            instruction_meter_checkpoint_distance: [0, 1, 7, 10000][prng.random_range(0..4)],
+            noop_instruction_rate: [0, 1, 256][prng.random_range(0..3)],
+            ..Config::default()
+        };
+        let mut loader = BuiltinProgram::new_loader(config);
+        loader
+            .register_function("gather_bytes", syscalls::SyscallGatherBytes::vm)
+            .unwrap();
+        let mut executable = Executable::<TestContextObject>::from_text_bytes(
+            &prog,
+            Arc::new(loader),
+            sbpf_version,
+            FunctionRegistry::default(),
+        )
+        .unwrap();
+        if executable.verify::<RequisiteVerifier>().is_err() {
+            continue;
+        }
+        verified_programs += 1;
+        let estimate = estimate_machine_code_size(&executable);
+        executable.jit_compile().unwrap();
+        let machine_code_length = executable
+            .get_compiled_program()
+            .unwrap()
+            .machine_code_length();
+        assert!(machine_code_length <= estimate);
+    }
+    assert!(verified_programs >= 1000);
+}
+
+#[test]
+fn test_max_machine_code_length() {
+    let prog = assemble::<TestContextObject>(
+        "
+        mov64 r0, 0
+        add64 r0, 1
+        jlt r0, 100, -2
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config::default())),
+    )
+    .unwrap()
+    .get_text_bytes()
+    .1
+    .to_vec();
+    let machine_code_length = {
+        let mut executable = create_mockup_executable(Config::default(), &prog);
+        executable.jit_compile().unwrap();
+        executable
+            .get_compiled_program()
+            .unwrap()
+            .machine_code_length()
+    };
+    assert!(
+        machine_code_length
+            <= estimate_machine_code_size(&create_mockup_executable(Config::default(), &prog))
+    );
+
+    let mut executable = create_mockup_executable(
+        Config {
+            max_machine_code_length: Some(machine_code_length),
+            ..Config::default()
+        },
+        &prog,
+    );
+    executable.jit_compile().unwrap();
+    assert_eq!(
+        executable
+            .get_compiled_program()
+            .unwrap()
+            .machine_code_length(),
+        machine_code_length
+    );
+
+    let mut executable = create_mockup_executable(
+        Config {
+            max_machine_code_length: Some(machine_code_length - 1),
+            ..Config::default()
+        },
+        &prog,
+    );
+    assert!(matches!(
+        executable.jit_compile(),
+        Err(EbpfError::ExceededMaxMachineCodeLength(4))
+    ));
+
+    // The subroutines alone exceed the limit
+    let mut executable = create_mockup_executable(
+        Config {
+            max_machine_code_length: Some(0),
+            ..Config::default()
+        },
+        &prog,
+    );
+    assert!(matches!(
+        executable.jit_compile(),
+        Err(EbpfError::ExceededMaxMachineCodeLength(0))
+    ));
+}
+
+#[test]
+fn test_jit_symbols() {
+    for sbpf_version in [SBPFVersion::V0, SBPFVersion::V3] {
+        let config = Config {
+            enabled_sbpf_versions: sbpf_version..=sbpf_version,
+            ..Config::default()
+        };
+        let mut executable = assemble::<TestContextObject>(
+            "
+            entrypoint:
+            mov64 r1, 1
+            call function_foo
+            exit
+            function_foo:
+            mov64 r0, 42
+            exit",
+            Arc::new(BuiltinProgram::new_loader(config)),
+        )
+        .unwrap();
+        assert!(executable.jit_symbols().is_none());
+        executable.jit_compile().unwrap();
+        let symbols = executable.jit_symbols().unwrap();
+        let program = executable.get_compiled_program().unwrap();
+
+        let mut registered = executable
+            .get_function_registry()
+            .iter()
+            .map(|(_key, (name, pc))| (String::from_utf8(name.to_vec()).unwrap(), pc))
+            .collect::<Vec<_>>();
+        registered.sort_by_key(|(_name, pc)| *pc);
+        assert_eq!(registered.len(), 2);
+        let functions = symbols
+            .iter()
+            .filter_map(|symbol| Some((symbol.name.clone(), symbol.pc?)))
+            .collect::<Vec<_>>();
+        assert_eq!(functions, registered);
+        assert_eq!(symbols.first().unwrap().name, "sbpf_jit_subroutines");
+        assert_eq!(symbols.last().unwrap().name, "sbpf_jit_epilogue");
+
+        // Contiguous, non-overlapping and covering the whole machine code
+        for pair in symbols.windows(2) {
+            assert!(pair[0].len > 0);
+            assert_eq!(pair[0].host_addr + pair[0].len as u64, pair[1].host_addr);
+        }
+        assert_eq!(
+            symbols.iter().map(|symbol| symbol.len).sum::<usize>(),
+            program.machine_code_length()
+        );
+
+        let mut perf_map = Vec::new();
+        program
+            .write_perf_map(executable.get_function_registry(), &mut perf_map)
+            .unwrap();
+        let perf_map = String::from_utf8(perf_map).unwrap();
+        assert_eq!(
+            perf_map.lines().collect::<Vec<_>>(),
+            symbols
+                .iter()
+                .map(|symbol| format!("{:x} {:x} {}", symbol.host_addr, symbol.len, symbol.name))
+                .collect::<Vec<_>>()
+        );
+
+        let mut jitdump = Vec::new();
+        program
+            .write_jitdump(executable.get_function_registry(), 1234, 5678, &mut jitdump)
+            .unwrap();
+        assert_eq!(LittleEndian::read_u32(&jitdump[0..]), 0x4A695444);
+        assert_eq!(LittleEndian::read_u32(&jitdump[8..]), 40);
+        assert_eq!(LittleEndian::read_u32(&jitdump[20..]), 1234);
+        let mut offset = 40;
+        for symbol in &symbols {
+            let record_size = LittleEndian::read_u32(&jitdump[offset + 4..]) as usize;
+            assert_eq!(
+                LittleEndian::read_u64(&jitdump[offset + 24..]),
+                symbol.host_addr
+            );
+            assert_eq!(
+                LittleEndian::read_u64(&jitdump[offset + 40..]),
+                symbol.len as u64
+            );
+            let name_end = offset + 56 + symbol.name.len();
+            assert_eq!(&jitdump[offset + 56..name_end], symbol.name.as_bytes());
+            assert_eq!(jitdump[name_end], 0);
+            assert_eq!(record_size, name_end + 1 + symbol.len - offset);
+            offset += record_size;
+        }
+        assert_eq!(offset, jitdump.len());
+    }
+}
+
+#[test]
+fn test_jit_instruction_offsets() {
+    let source = "
+        function_foo:
+        mov64 r0, 2
+        exit
+        entrypoint:
+        lddw r1, 0x100000000
+        jeq r1, 0, +1
+        call function_foo
+        add64 r0, 40
+        exit";
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+        ..Config::default()
+    };
+    let mut executable =
+        assemble::<TestContextObject>(source, Arc::new(BuiltinProgram::new_loader(config)))
+            .unwrap();
+    executable.jit_compile().unwrap();
+    let program = executable.get_compiled_program().unwrap();
+    assert_eq!(program.pc_to_host_offset(0), None);
+    assert_eq!(program.instruction_machine_code(0), None);
+
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+        record_jit_instruction_offsets: true,
+        ..Config::default()
+    };
+    let mut executable =
+        assemble::<TestContextObject>(source, Arc::new(BuiltinProgram::new_loader(config)))
+            .unwrap();
+    executable.jit_compile().unwrap();
+    let program = executable.get_compiled_program().unwrap();
+    let offsets = (0..8)
+        .map(|pc| program.pc_to_host_offset(pc))
+        .collect::<Vec<_>>();
+    // The second slot of lddw has no code of its own
+    assert_eq!(offsets[3], None);
+    let offsets = offsets.into_iter().flatten().collect::<Vec<_>>();
+    assert_eq!(offsets.len(), 7);
+    assert!(offsets.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(offsets[6] < program.machine_code_length());
+    for (pc, offset) in [0, 1, 2, 4, 5, 6, 7].iter().zip(offsets.iter()) {
+        let code = program.instruction_machine_code(*pc).unwrap();
+        assert!(!code.is_empty());
+        assert_eq!(code.as_ptr(), program.machine_code()[*offset..].as_ptr());
+    }
+
+    // Execution enters at the offset reported for the entrypoint
+    let entry_pc = executable.get_entrypoint_instruction_offset();
+    assert_eq!(entry_pc, 2);
+    let entry_symbol = executable
+        .jit_symbols()
+        .unwrap()
+        .into_iter()
+        .find(|symbol| symbol.pc == Some(entry_pc))
+        .unwrap();
+    assert_eq!(
+        program.pc_to_host_offset(entry_pc),
+        Some((entry_symbol.host_addr - program.machine_code().as_ptr() as u64) as usize)
+    );
+    let mut context_object = TestContextObject::new(7);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, false);
+    assert_eq!(result.unwrap(), 42);
+}
+
+#[test]
+fn test_deterministic_jit_output() {
+    let source = "
+        mov64 r1, 0x12345678
+        add64 r2, r3
+        xor32 r4, 0x7ff0000
+        lsh64 r5, 3
+        lddw r6, 0x1122334455667788
+        jeq r1, 0x5555, +1
+        mov64 r0, r6
+        exit";
+    let compile = |deterministic_jit_output| {
+        let config = Config {
+            enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+            enable_instruction_meter: false,
+            noop_instruction_rate: 1,
+            sanitize_user_provided_values: true,
+            record_jit_instruction_offsets: true,
+            deterministic_jit_output,
+            ..Config::default()
+        };
+        let mut executable =
+            assemble::<TestContextObject>(source, Arc::new(BuiltinProgram::new_loader(config)))
+                .unwrap();
+        executable.jit_compile().unwrap();
+        executable
+    };
+    let hex = |code: &[u8]| {
+        code.iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
+    // Golden output, update after intentional changes of the code generator
+    let executable = compile(true);
+    let program = executable.get_compiled_program().unwrap();
+    for (pc, expected) in [
+        // mov esi, 0x12345678
+        (0, "be 78 56 34 12"),
+        // add rdx, rcx
+        (1, "48 01 ca"),
+        // xor r8d, 0x7ff0000
+        (2, "41 81 f0 00 00 ff 07"),
+        // shl r9, 3
+        (3, "49 c1 e1 03"),
+        // movabs rbx, 0x1122334455667788
+        (4, "48 bb 88 77 66 55 44 33 22 11"),
+        // cmp rsi, 0x5555; mov r11d, 8; je +3
+        (
+            6,
+            "48 81 fe 55 55 00 00 41 bb 08 00 00 00 0f 84 03 00 00 00",
+        ),
+        // mov rax, rbx
+        (7, "48 89 d8"),
+    ] {
+        assert_eq!(
+            hex(program.instruction_machine_code(pc).unwrap()),
+            expected,
+            "pc {}",
+            pc
+        );
+    }
+
+    // Without it the constants are blinded and no-ops are inserted
+    let diversified_executable = compile(false);
+    let diversified_program = diversified_executable.get_compiled_program().unwrap();
+    assert_ne!(
+        hex(diversified_program.instruction_machine_code(0).unwrap()),
+        "be 78 56 34 12"
+    );
+    assert!(diversified_program.machine_code_length() > program.machine_code_length());
+}
@@ -0,0 +1,150 @@
+#![allow(clippy::literal_string_with_formatting_args)]
+#![cfg(all(target_pointer_width = "64", target_endian = "little"))]
+
+use solana_sbpf::{
+    assembler::assemble,
+    memory_region::MemoryRegion,
+    program::{BuiltinProgram, SBPFVersion},
+    verifier::RequisiteVerifier,
+    vm::{Config, MemoryLayoutProfile},
+};
+use std::{fmt::Write, sync::Arc};
+use test_utils::{create_vm, TestContextObject};
+
+/// Programs replayed under every profile, along with the highest SBPF version to assemble for
+const FIXTURES: &[(&str, SBPFVersion, &str)] = &[
+    (
+        "arithmetic",
+        SBPFVersion::V3,
+        "
+        mov64 r0, 7
+        lsh64 r0, 2
+        add64 r0, 14
+        exit",
+    ),
+    (
+        "frame_pointer_v0",
+        SBPFVersion::V0,
+        "
+        stxdw [r1+0], r10
+        mov64 r0, r1
+        exit",
+    ),
+    (
+        "frame_pointer_v3",
+        SBPFVersion::V3,
+        "
+        stxdw [r1+0], r10
+        mov64 r0, r1
+        exit",
+    ),
+    (
+        "recursion",
+        SBPFVersion::V3,
+        "
+        entrypoint:
+        mov64 r6, 30
+        call function_recurse
+        exit
+        function_recurse:
+        add64 r0, 1
+        add64 r6, -1
+        jeq r6, 0, +1
+        call function_recurse
+        exit",
+    ),
+];
+
+fn replay(memory_layout: MemoryLayoutProfile) -> String {
+    let mut outcome = String::new();
+    for (name, highest_sbpf_version, source) in FIXTURES {
+        let config = Config {
+            enabled_sbpf_versions: SBPFVersion::V0..=*highest_sbpf_version,
+            ..Config::default()
+        }
+        .with_memory_layout(memory_layout);
+        #[allow(unused_mut)]
+        let mut executable =
+            assemble::<TestContextObject>(source, Arc::new(BuiltinProgram::new_loader(config)))
+                .unwrap();
+        executable.verify::<RequisiteVerifier>().unwrap();
+        #[cfg(all(feature = "jit", not(target_os = "windows"), target_arch = "x86_64"))]
+        executable.jit_compile().unwrap();
+        let mut results = Vec::new();
+        for interpreted in [true, false] {
+            if !interpreted
+                && !cfg!(all(
+                    feature = "jit",
+                    not(target_os = "windows"),
+                    target_arch = "x86_64"
+                ))
+            {
+                continue;
+            }
+            let mut mem = [0u8; 8];
+            let mem_region = MemoryRegion::new_writable(
+                &mut mem,
+                executable.get_config().memory_layout.input_start(),
+            );
+            let mut context_object = TestContextObject::new(1000);
+            let (instruction_count, result) = {
+                create_vm!(
+                    vm,
+                    &executable,
+                    &mut context_object,
+                    stack,
+                    heap,
+                    vec![mem_region],
+                    None
+                );
+                vm.execute_program(&executable, interpreted)
+            };
+            results.push(format!(
+                "{name}: {result:?} instructions={instruction_count} input={:#x}",
+                u64::from_le_bytes(mem),
+            ));
+        }
+        assert!(
+            results.windows(2).all(|pair| pair[0] == pair[1]),
+            "interpreter and JIT diverged: {:?}",
+            results,
+        );
+        writeln!(outcome, "{}", results[0]).unwrap();
+    }
+    outcome
+}
+
+#[test]
+fn test_memory_layout_profiles() {
+    assert_eq!(
+        Config::default().memory_layout,
+        MemoryLayoutProfile::CURRENT
+    );
+    assert_eq!(
+        Config::default().with_memory_layout(MemoryLayoutProfile::CURRENT),
+        Config::default(),
+    );
+    let config = Config::default().with_memory_layout(MemoryLayoutProfile::V0);
+    assert_eq!(config.max_call_depth, 20);
+    assert_eq!(config.stack_size(), MemoryLayoutProfile::V0.stack_size());
+    assert_ne!(config, Config::default());
+    assert_eq!(MemoryLayoutProfile::V0.version(), 0);
+    assert_eq!(MemoryLayoutProfile::V1.version(), 1);
+}
+
+#[test]
+fn test_memory_layout_replay() {
+    let v0 = replay(MemoryLayoutProfile::V0);
+    let v1 = replay(MemoryLayoutProfile::V1);
+    assert_eq!(v0, include_str!("golden/memory_layout_v0.txt"));
+    assert_eq!(v1, include_str!("golden/memory_layout_v1.txt"));
+
+    // Only the fixtures observing the stack size differ
+    let differing = v0
+        .lines()
+        .zip(v1.lines())
+        .filter(|(v0, v1)| v0 != v1)
+        .map(|(v0, _v1)| v0.split(':').next().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(differing, ["frame_pointer_v3", "recursion"]);
+}
@@ -26,10 +26,13 @@ extern crate thiserror;
 
 use solana_sbpf::{
     assembler::assemble,
+    disassembler::decode,
     ebpf,
     elf::Executable,
+    error::EbpfError,
     program::{BuiltinFunction, BuiltinProgram, FunctionRegistry, SBPFVersion},
-    verifier::{RequisiteVerifier, Verifier, VerifierError},
+    static_analysis::{Analysis, Bound},
+    verifier::{RequisiteVerifier, Verifier, VerifierChain, VerifierConfig, VerifierError},
     vm::{Config, ContextObject},
 };
 use std::sync::Arc;
@@ -48,6 +51,7 @@ impl Verifier for TautologyVerifier {
     fn verify<C: ContextObject>(
         _prog: &[u8],
         _config: &Config,
+        _verifier_config: &VerifierConfig,
         _sbpf_version: SBPFVersion,
         _function_registry: &FunctionRegistry<usize>,
         _syscall_registry: &FunctionRegistry<BuiltinFunction<C>>,
@@ -61,6 +65,7 @@ impl Verifier for ContradictionVerifier {
     fn verify<C: ContextObject>(
         _prog: &[u8],
         _config: &Config,
+        _verifier_config: &VerifierConfig,
         _sbpf_version: SBPFVersion,
         _function_registry: &FunctionRegistry<usize>,
         _syscall_registry: &FunctionRegistry<BuiltinFunction<C>>,
@@ -104,7 +109,7 @@ fn test_verifier_fail() {
 }
 
 #[test]
-#[should_panic(expected = "DivisionByZero(1)")]
+#[should_panic(expected = "DivisionByZero { pc: 1,")]
 fn test_verifier_err_div_by_zero_imm() {
     let executable = assemble::<TestContextObject>(
         "
@@ -118,7 +123,38 @@ fn test_verifier_err_div_by_zero_imm() {
 }
 
 #[test]
-#[should_panic(expected = "UnsupportedLEBEArgument(0)")]
+fn test_verifier_err_display_insn() {
+    let testcases = [
+        (
+            "
+            mov32 r0, 1
+            udiv32 r0, 0
+            exit",
+            "division by 0 (insn #1: udiv32 r0, 0)",
+        ),
+        (
+            "
+            ja +2
+            exit",
+            "jump out of code to #3 (insn #0: ja 3)",
+        ),
+        (
+            "
+            lsh64 r3, 64
+            exit",
+            "Shift with overflow of 64-bit value by 64 (insn #0: lsh64 r3, 64)",
+        ),
+    ];
+    for (source, message) in testcases {
+        let executable =
+            assemble::<TestContextObject>(source, Arc::new(BuiltinProgram::new_mock())).unwrap();
+        let error = executable.verify::<RequisiteVerifier>().unwrap_err();
+        assert_eq!(error.to_string(), format!("Verifier error: {message}"));
+    }
+}
+
+#[test]
+#[should_panic(expected = "UnsupportedLEBEArgument { pc: 0,")]
 fn test_verifier_err_endian_size() {
     let prog = &[
         0xdc, 0x01, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, //
@@ -136,7 +172,7 @@ fn test_verifier_err_endian_size() {
 }
 
 #[test]
-#[should_panic(expected = "IncompleteLDDW(0)")]
+#[should_panic(expected = "IncompleteLDDW { pc: 0,")]
 fn test_verifier_err_incomplete_lddw() {
     // Note: ubpf has test-err-incomplete-lddw2, which is the same
     // lddw r0, 0x55667788
@@ -189,7 +225,7 @@ fn test_verifier_err_invalid_reg_dst() {
         )
         .unwrap();
         let result = executable.verify::<RequisiteVerifier>();
-        assert_error!(result, "VerifierError(InvalidDestinationRegister(0))");
+        assert_error!(result, "VerifierError(InvalidDestinationRegister {{ pc: 0,");
     }
 }
 
@@ -209,7 +245,7 @@ fn test_verifier_err_invalid_reg_src() {
         )
         .unwrap();
         let result = executable.verify::<RequisiteVerifier>();
-        assert_error!(result, "VerifierError(InvalidSourceRegister(0))");
+        assert_error!(result, "VerifierError(InvalidSourceRegister {{ pc: 0,");
     }
 }
 
@@ -230,7 +266,7 @@ fn test_verifier_resize_stack_ptr_success() {
 }
 
 #[test]
-#[should_panic(expected = "UnalignedImmediate(0)")]
+#[should_panic(expected = "UnalignedImmediate { pc: 0,")]
 fn test_verifier_negative_unaligned_stack() {
     let executable = assemble::<TestContextObject>(
         "
@@ -243,7 +279,7 @@ fn test_verifier_negative_unaligned_stack() {
 }
 
 #[test]
-#[should_panic(expected = "UnalignedImmediate(0)")]
+#[should_panic(expected = "UnalignedImmediate { pc: 0,")]
 fn test_verifier_positive_unaligned_stack() {
     let executable = assemble::<TestContextObject>(
         "
@@ -256,35 +292,84 @@ fn test_verifier_positive_unaligned_stack() {
 }
 
 #[test]
-#[should_panic(expected = "JumpToMiddleOfLDDW(2, 0)")]
+#[should_panic(expected = "JumpToMiddleOfLDDW { target_pc: 2, pc: 0,")]
 fn test_verifier_err_jmp_lddw() {
     let executable = assemble::<TestContextObject>(
         "
         ja +1
         lddw r0, 0x1122334455667788
         exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    executable.verify::<RequisiteVerifier>().unwrap();
+}
+
+#[test]
+#[should_panic(expected = "JumpToMiddleOfLDDW { target_pc: 1, pc: 2,")]
+fn test_verifier_err_jmp_lddw_backward() {
+    let executable = assemble::<TestContextObject>(
+        "
+        lddw r0, 0x1122334455667788
+        jne r0, 0, -2
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V1,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    executable.verify::<RequisiteVerifier>().unwrap();
+}
+
+#[test]
+#[should_panic(expected = "JumpToMiddleOfLDDW { target_pc: 2, pc: 0,")]
+fn test_verifier_err_call_into_lddw() {
+    // call 0x1234
+    // lddw r0, 0x1122334455667788
+    // exit
+    let prog = &[
+        0x85, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00, //
+        0x18, 0x00, 0x00, 0x00, 0x88, 0x77, 0x66, 0x55, //
+        0x00, 0x00, 0x00, 0x00, 0x44, 0x33, 0x22, 0x11, //
+        0x95, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+    ];
+    let mut function_registry = FunctionRegistry::default();
+    function_registry
+        .register_function(0x1234, b"function_foo", 2)
+        .unwrap();
+    let executable = Executable::<TestContextObject>::from_text_bytes(
+        prog,
         Arc::new(BuiltinProgram::new_mock()),
+        SBPFVersion::V0,
+        function_registry,
     )
     .unwrap();
     executable.verify::<RequisiteVerifier>().unwrap();
 }
 
 #[test]
-#[should_panic(expected = "InvalidFunction(1)")]
+#[should_panic(expected = "JumpToMiddleOfLDDW { target_pc: 2, pc: 0,")]
 fn test_verifier_err_call_lddw() {
     let executable = assemble::<TestContextObject>(
         "
         call 1
         lddw r0, 0x1122334455667788
         exit",
-        Arc::new(BuiltinProgram::new_mock()),
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+            ..Config::default()
+        })),
     )
     .unwrap();
     executable.verify::<RequisiteVerifier>().unwrap();
 }
 
 #[test]
-#[should_panic(expected = "InvalidRegister(0)")]
+#[should_panic(expected = "InvalidRegister { pc: 0,")]
 fn test_verifier_err_callx_cannot_use_r10() {
     for highest_sbpf_version in [SBPFVersion::V0, SBPFVersion::V3] {
         let executable = assemble::<TestContextObject>(
@@ -317,7 +402,7 @@ fn test_verifier_err_function_fallthrough() {
 }
 
 #[test]
-#[should_panic(expected = "JumpOutOfCode(3, 0)")]
+#[should_panic(expected = "JumpOutOfCode { target_pc: 3, pc: 0,")]
 fn test_verifier_err_jmp_out() {
     let executable = assemble::<TestContextObject>(
         "
@@ -330,7 +415,6 @@ fn test_verifier_err_jmp_out() {
 }
 
 #[test]
-#[should_panic(expected = "JumpOutOfCode(18446744073709551615, 0)")]
 fn test_verifier_err_jmp_out_start() {
     let executable = assemble::<TestContextObject>(
         "
@@ -339,11 +423,16 @@ fn test_verifier_err_jmp_out_start() {
         Arc::new(BuiltinProgram::new_mock()),
     )
     .unwrap();
-    executable.verify::<RequisiteVerifier>().unwrap();
+    // The target pc wraps around, to a width which depends on the target
+    assert_error!(
+        executable.verify::<RequisiteVerifier>(),
+        "JumpOutOfCode {{ target_pc: {}, pc: 0,",
+        usize::MAX
+    );
 }
 
 #[test]
-#[should_panic(expected = "UnknownOpCode(157, 0)")]
+#[should_panic(expected = "UnknownOpCode { opc: 157, pc: 0,")]
 fn test_verifier_err_invalid_return() {
     let prog = &[
         0x9d, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // return
@@ -413,7 +502,7 @@ fn test_verifier_known_syscall() {
 }
 
 #[test]
-#[should_panic(expected = "CannotWriteR10(0)")]
+#[should_panic(expected = "CannotWriteR10 { pc: 0,")]
 fn test_verifier_err_write_r10() {
     let executable = assemble::<TestContextObject>(
         "
@@ -430,25 +519,52 @@ fn test_verifier_err_all_shift_overflows() {
     let testcases = [
         // lsh32_imm
         ("lsh32 r0, 16", Ok(())),
-        ("lsh32 r0, 32", Err("ShiftWithOverflow(32, 32, 0)")),
-        ("lsh32 r0, 64", Err("ShiftWithOverflow(64, 32, 0)")),
+        (
+            "lsh32 r0, 32",
+            Err("ShiftWithOverflow { shift: 32, bits: 32, pc: 0,"),
+        ),
+        (
+            "lsh32 r0, 64",
+            Err("ShiftWithOverflow { shift: 64, bits: 32, pc: 0,"),
+        ),
         // rsh32_imm
         ("rsh32 r0, 16", Ok(())),
-        ("rsh32 r0, 32", Err("ShiftWithOverflow(32, 32, 0)")),
-        ("rsh32 r0, 64", Err("ShiftWithOverflow(64, 32, 0)")),
+        (
+            "rsh32 r0, 32",
+            Err("ShiftWithOverflow { shift: 32, bits: 32, pc: 0,"),
+        ),
+        (
+            "rsh32 r0, 64",
+            Err("ShiftWithOverflow { shift: 64, bits: 32, pc: 0,"),
+        ),
         // arsh32_imm
         ("arsh32 r0, 16", Ok(())),
-        ("arsh32 r0, 32", Err("ShiftWithOverflow(32, 32, 0)")),
-        ("arsh32 r0, 64", Err("ShiftWithOverflow(64, 32, 0)")),
+        (
+            "arsh32 r0, 32",
+            Err("ShiftWithOverflow { shift: 32, bits: 32, pc: 0,"),
+        ),
+        (
+            "arsh32 r0, 64",
+            Err("ShiftWithOverflow { shift: 64, bits: 32, pc: 0,"),
+        ),
         // lsh64_imm
         ("lsh64 r0, 32", Ok(())),
-        ("lsh64 r0, 64", Err("ShiftWithOverflow(64, 64, 0)")),
+        (
+            "lsh64 r0, 64",
+            Err("ShiftWithOverflow { shift: 64, bits: 64, pc: 0,"),
+        ),
         // rsh64_imm
         ("rsh64 r0, 32", Ok(())),
-        ("rsh64 r0, 64", Err("ShiftWithOverflow(64, 64, 0)")),
+        (
+            "rsh64 r0, 64",
+            Err("ShiftWithOverflow { shift: 64, bits: 64, pc: 0,"),
+        ),
         // arsh64_imm
         ("arsh64 r0, 32", Ok(())),
-        ("arsh64 r0, 64", Err("ShiftWithOverflow(64, 64, 0)")),
+        (
+            "arsh64 r0, 64",
+            Err("ShiftWithOverflow { shift: 64, bits: 64, pc: 0,"),
+        ),
     ];
 
     for (overflowing_instruction, expected) in testcases {
@@ -458,7 +574,7 @@ fn test_verifier_err_all_shift_overflows() {
         let result = executable.verify::<RequisiteVerifier>();
         match expected {
             Ok(()) => assert!(result.is_ok()),
-            Err(overflow_msg) => assert_error!(result, "VerifierError({overflow_msg})"),
+            Err(overflow_msg) => assert_error!(result, "VerifierError({overflow_msg}"),
         }
     }
 }
@@ -473,22 +589,82 @@ fn test_sdiv_disabled() {
     ];
 
     for (opc, instruction) in instructions {
-        for highest_sbpf_version in [SBPFVersion::V0, SBPFVersion::V3] {
-            let assembly = format!("\n{instruction}\nexit");
-            let executable = assemble::<TestContextObject>(
-                &assembly,
-                Arc::new(BuiltinProgram::new_loader(Config {
-                    enabled_sbpf_versions: SBPFVersion::V0..=highest_sbpf_version,
-                    ..Config::default()
-                })),
+        let assembly = format!("\n{instruction}\nexit");
+        let executable = assemble::<TestContextObject>(
+            &assembly,
+            Arc::new(BuiltinProgram::new_loader(Config::default())),
+        )
+        .unwrap();
+        executable.verify::<RequisiteVerifier>().unwrap();
+
+        // The assembler rejects the instruction before the verifier sees it
+        let loader = Arc::new(BuiltinProgram::new_loader(Config {
+            enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+            ..Config::default()
+        }));
+        let mnemonic = instruction.split_whitespace().next().unwrap();
+        assert_eq!(
+            assemble::<TestContextObject>(&assembly, loader.clone()).err(),
+            Some(format!(
+                "Instruction {mnemonic:?} is not available in SBPF v0 because enable_pqr is \
+                 disabled at line 2"
+            ))
+        );
+        let mut prog = [0u8; ebpf::INSN_SIZE * 2];
+        prog[0] = opc;
+        prog[ebpf::INSN_SIZE] = ebpf::EXIT;
+        let executable = Executable::<TestContextObject>::from_text_bytes(
+            &prog,
+            loader,
+            SBPFVersion::V0,
+            FunctionRegistry::default(),
+        )
+        .unwrap();
+        assert_error!(
+            executable.verify::<RequisiteVerifier>(),
+            "VerifierError(UnknownOpCode {{ opc: {}, pc: {},",
+            opc,
+            0
+        );
+    }
+}
+
+#[test]
+fn test_verifier_and_decode_agree_on_unknown_opcodes() {
+    for sbpf_version in [
+        SBPFVersion::V0,
+        SBPFVersion::V1,
+        SBPFVersion::V2,
+        SBPFVersion::V3,
+    ] {
+        let exit = if sbpf_version.static_syscalls() {
+            ebpf::RETURN
+        } else {
+            ebpf::EXIT
+        };
+        for opc in 0..=u8::MAX {
+            let mut prog = [0u8; ebpf::INSN_SIZE * 3];
+            prog[0] = opc;
+            prog[ebpf::INSN_SIZE * 2] = exit;
+            let executable = Executable::<TestContextObject>::from_text_bytes(
+                &prog,
+                Arc::new(BuiltinProgram::new_mock()),
+                sbpf_version,
+                FunctionRegistry::default(),
             )
             .unwrap();
-            let result = executable.verify::<RequisiteVerifier>();
-            if highest_sbpf_version == SBPFVersion::V3 {
-                assert!(result.is_ok());
-            } else {
-                assert_error!(result, "VerifierError(UnknownOpCode({}, {}))", opc, 0);
-            }
+            let rejected = matches!(
+                executable.verify::<RequisiteVerifier>(),
+                Err(EbpfError::VerifierError(VerifierError::UnknownOpCode {
+                    pc: 0,
+                    ..
+                }))
+            );
+            assert_eq!(
+                rejected,
+                executable.unsupported_opcodes().first() == Some(&(0, opc)),
+                "opcode {opc:#x} in {sbpf_version}",
+            );
         }
     }
 }
@@ -513,7 +689,7 @@ fn return_instr() {
         if sbpf_version == SBPFVersion::V3 {
             assert_error!(result, "VerifierError(InvalidSyscall(0))");
         } else {
-            assert_error!(result, "VerifierError(UnknownOpCode(157, 2))");
+            assert_error!(result, "VerifierError(UnknownOpCode {{ opc: 157, pc: 2,");
         }
     }
 }
@@ -547,3 +723,558 @@ fn function_without_return() {
     let result = executable.verify::<RequisiteVerifier>();
     assert_error!(result, "VerifierError(InvalidFunction(1))");
 }
+
+#[test]
+fn test_verifier_err_all_out_of_range_immediates() {
+    for sbpf_version in [
+        SBPFVersion::V0,
+        SBPFVersion::V1,
+        SBPFVersion::V2,
+        SBPFVersion::V3,
+    ] {
+        let exit = if sbpf_version.static_syscalls() {
+            ebpf::RETURN
+        } else {
+            ebpf::EXIT
+        };
+        let verify = |opc: u8, imm: i64| {
+            let mut prog = ebpf::Insn {
+                opc,
+                dst: 1,
+                imm,
+                ..ebpf::Insn::default()
+            }
+            .to_vec();
+            prog.extend_from_slice(
+                &ebpf::Insn {
+                    opc: exit,
+                    ..ebpf::Insn::default()
+                }
+                .to_vec(),
+            );
+            Executable::<TestContextObject>::from_text_bytes(
+                &prog,
+                Arc::new(BuiltinProgram::new_mock()),
+                sbpf_version,
+                FunctionRegistry::default(),
+            )
+            .unwrap()
+            .verify::<RequisiteVerifier>()
+        };
+
+        let mut constrained_opcodes = 0;
+        for opc in 0..=u8::MAX {
+            let (valid, invalid): (&[i64], &[i64]) =
+                match ebpf::ImmediateRange::of_opcode(opc, sbpf_version) {
+                    ebpf::ImmediateRange::Any => continue,
+                    ebpf::ImmediateRange::NonZero => (&[1, -1, i32::MIN as i64], &[0]),
+                    ebpf::ImmediateRange::ShiftAmount(32) => (
+                        &[0, 31],
+                        &[-1, 32, 63, 64, i32::MIN as i64, i32::MAX as i64],
+                    ),
+                    ebpf::ImmediateRange::ShiftAmount(64) => (
+                        &[0, 32, 63],
+                        &[-1, 64, 65, i32::MIN as i64, i32::MAX as i64],
+                    ),
+                    ebpf::ImmediateRange::ShiftAmount(_) => unreachable!(),
+                    ebpf::ImmediateRange::ByteSwapWidth => {
+                        (&[16, 32, 64], &[-16, 0, 1, 8, 15, 17, 31, 33, 63, 65, 128])
+                    }
+                };
+            constrained_opcodes += 1;
+            for imm in valid {
+                assert!(
+                    verify(opc, *imm).is_ok(),
+                    "{:?} opcode {:#x} imm {}",
+                    sbpf_version,
+                    opc,
+                    imm
+                );
+            }
+            for imm in invalid {
+                let insn = Box::new(decode(
+                    &ebpf::Insn {
+                        opc,
+                        dst: 1,
+                        imm: *imm,
+                        ..ebpf::Insn::default()
+                    },
+                    sbpf_version,
+                ));
+                let error = match ebpf::ImmediateRange::of_opcode(opc, sbpf_version) {
+                    ebpf::ImmediateRange::NonZero => VerifierError::DivisionByZero { pc: 0, insn },
+                    ebpf::ImmediateRange::ShiftAmount(bits) => VerifierError::ShiftWithOverflow {
+                        shift: *imm as u64,
+                        bits,
+                        pc: 0,
+                        insn,
+                    },
+                    _ => VerifierError::UnsupportedLEBEArgument { pc: 0, insn },
+                };
+                assert_eq!(
+                    verify(opc, *imm).unwrap_err().to_string(),
+                    EbpfError::VerifierError(error).to_string(),
+                    "{:?} opcode {:#x} imm {}",
+                    sbpf_version,
+                    opc,
+                    imm
+                );
+            }
+        }
+        // Division and remainder (4 or 8), shifts (6) and byte swaps (2 or 1)
+        let expected_constrained_opcodes = match sbpf_version {
+            SBPFVersion::V0 | SBPFVersion::V1 => 4 + 6 + 2,
+            _ => 8 + 6 + 1,
+        };
+        assert_eq!(constrained_opcodes, expected_constrained_opcodes);
+    }
+}
+
+#[test]
+fn test_max_execution_bound() {
+    let source = "
+        mov64 r0, 0
+        jeq r1, 0, lbb_5
+        call function_a
+        add64 r0, 1
+        ja lbb_7
+        lbb_5:
+        call function_b
+        add64 r0, 2
+        lbb_7:
+        exit
+        function_a:
+        mov64 r2, 1
+        call function_b
+        exit
+        function_b:
+        add64 r0, 3
+        exit";
+    // function_b: 2, function_a: 3 + 2, through function_a: 2 + (1 + 5) + 2 + 1
+    let executable =
+        assemble::<TestContextObject>(source, Arc::new(BuiltinProgram::new_mock())).unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    assert_eq!(analysis.max_execution_bound(), Bound::Exact(11));
+
+    for (budget, expected) in [(11, Ok(())), (10, Err("ExecutionBoundExceeded(11, 10)"))] {
+        let executable = assemble::<TestContextObject>(
+            source,
+            Arc::new(BuiltinProgram::new_loader(Config {
+                max_execution_bound: Some(budget),
+                ..Config::default()
+            })),
+        )
+        .unwrap();
+        let result = executable.verify::<RequisiteVerifier>();
+        match expected {
+            Ok(()) => assert!(result.is_ok()),
+            Err(error) => assert_error!(result, "VerifierError({error})"),
+        }
+    }
+}
+
+#[test]
+fn test_max_execution_bound_unbounded() {
+    let testcases = [
+        (
+            "
+            mov64 r0, 0
+            lbb_1:
+            add64 r0, 1
+            jlt r0, 10, lbb_1
+            exit",
+            Bound::Unbounded {
+                loops: vec![1],
+                recursion: vec![],
+                indirect_calls: vec![],
+            },
+            "UnboundedExecution(1)",
+        ),
+        (
+            "
+            call function_foo
+            exit
+            function_foo:
+            jeq r1, 0, lbb_4
+            call function_foo
+            lbb_4:
+            exit",
+            Bound::Unbounded {
+                loops: vec![],
+                recursion: vec![vec![2]],
+                indirect_calls: vec![],
+            },
+            "UnboundedExecution(2)",
+        ),
+        (
+            "
+            mov64 r0, 0
+            callx r1
+            exit",
+            Bound::Unbounded {
+                loops: vec![],
+                recursion: vec![],
+                indirect_calls: vec![1],
+            },
+            "UnboundedExecution(1)",
+        ),
+    ];
+    for (source, bound, error) in testcases {
+        let executable = assemble::<TestContextObject>(
+            source,
+            Arc::new(BuiltinProgram::new_loader(Config {
+                max_execution_bound: Some(u64::MAX),
+                ..Config::default()
+            })),
+        )
+        .unwrap();
+        let analysis = Analysis::from_executable(&executable).unwrap();
+        assert_eq!(analysis.max_execution_bound(), bound);
+        assert_error!(
+            executable.verify::<RequisiteVerifier>(),
+            "VerifierError({error})"
+        );
+    }
+}
+
+fn stack_pointer_leak_loader() -> Arc<BuiltinProgram<TestContextObject>> {
+    let mut loader = BuiltinProgram::new_loader(Config {
+        reject_stack_pointer_leaks: true,
+        ..Config::default()
+    });
+    loader
+        .register_function("gather_bytes", syscalls::SyscallString::vm)
+        .unwrap();
+    Arc::new(loader)
+}
+
+#[test]
+fn test_stack_pointer_leaks_frame_relative() {
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r2, 7
+        stxdw [r10-8], r2
+        stxdw [r10-16], r1
+        mov64 r3, r10
+        add64 r3, -24
+        stxdw [r3+0], r2
+        ldxdw r4, [r10-8]
+        ldxdw r5, [r3+0]
+        add64 r4, r5
+        ldxdw r1, [r10-16]
+        stxdw [r1+0], r4
+        call function_a
+        exit
+        function_a:
+        stxdw [r10-8], r4
+        ldxdw r0, [r10-8]
+        exit",
+        stack_pointer_leak_loader(),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    assert_eq!(analysis.stack_pointer_leaks(), vec![]);
+    executable.verify::<RequisiteVerifier>().unwrap();
+}
+
+#[test]
+fn test_stack_pointer_leaks() {
+    let testcases = [
+        // Copies r10 into the output buffer
+        (
+            "
+            mov64 r2, r10
+            stxdw [r1+0], r2
+            mov64 r0, 0
+            exit",
+            vec![(0, 1)],
+        ),
+        // Through the stack
+        (
+            "
+            mov64 r2, r10
+            stxdw [r10-8], r2
+            ldxdw r3, [r10-8]
+            stxdw [r1+0], r3
+            exit",
+            vec![(0, 3)],
+        ),
+        // As a syscall argument
+        (
+            "
+            mov64 r1, r10
+            add64 r1, -8
+            syscall gather_bytes
+            exit",
+            vec![(0, 2)],
+        ),
+        // Through arithmetic into the return value
+        (
+            "
+            mov64 r0, 0
+            add64 r0, r10
+            rsh64 r0, 3
+            exit",
+            vec![(1, 3)],
+        ),
+        // Returned from a function
+        (
+            "
+            call function_a
+            exit
+            function_a:
+            mov64 r0, r10
+            exit",
+            vec![(2, 1)],
+        ),
+    ];
+    for (source, leaks) in testcases {
+        let executable =
+            assemble::<TestContextObject>(source, stack_pointer_leak_loader()).unwrap();
+        let analysis = Analysis::from_executable(&executable).unwrap();
+        assert_eq!(analysis.stack_pointer_leaks(), leaks);
+        let (source, sink) = leaks[0];
+        assert_error!(
+            executable.verify::<RequisiteVerifier>(),
+            "VerifierError(StackPointerLeak({source}, {sink}))"
+        );
+    }
+}
+
+#[test]
+fn test_verifier_config() {
+    let default = VerifierConfig::default();
+    let testcases = [
+        (
+            "
+            mov64 r1, 0
+            callx r1
+            return",
+            VerifierConfig {
+                allow_callx: false,
+                ..default
+            },
+            "VerifierError(CallxNotAllowed { pc: 1,",
+        ),
+        (
+            "
+            mov64 r0, 0
+            mov64 r0, 1
+            mov64 r0, 2
+            return",
+            VerifierConfig {
+                max_insn_count: Some(3),
+                ..default
+            },
+            "VerifierError(TooManyInstructions(4, 3))",
+        ),
+        (
+            "
+            return
+            mov64 r0, 1
+            return",
+            VerifierConfig {
+                reject_unreachable_code: true,
+                ..default
+            },
+            "VerifierError(UnreachableInstruction { pc: 1,",
+        ),
+    ];
+    let executables = testcases
+        .iter()
+        .map(|(source, _verifier_config, _error)| {
+            assemble::<TestContextObject>(source, Arc::new(BuiltinProgram::new_mock())).unwrap()
+        })
+        .collect::<Vec<_>>();
+    // Each knob only rejects its own fixture
+    for (executable_index, executable) in executables.iter().enumerate() {
+        executable.verify::<RequisiteVerifier>().unwrap();
+        for (testcase_index, (_source, verifier_config, error)) in testcases.iter().enumerate() {
+            let result = executable.verify_with_config::<RequisiteVerifier>(verifier_config);
+            if executable_index == testcase_index {
+                assert_error!(result, "{}", error);
+            } else {
+                result.unwrap();
+            }
+        }
+    }
+}
+
+#[test]
+fn test_verifier_reachability() {
+    let verifier_config = VerifierConfig {
+        reject_unreachable_code: true,
+        ..VerifierConfig::default()
+    };
+    // Both branches, the second slot of lddw and a function which is only called
+    let executable = assemble::<TestContextObject>(
+        "
+        lddw r0, 0x1122334455667788
+        jeq r1, 0, +1
+        call function_foo
+        exit
+        function_foo:
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config {
+            enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+            ..Config::default()
+        })),
+    )
+    .unwrap();
+    executable
+        .verify_with_config::<RequisiteVerifier>(&verifier_config)
+        .unwrap();
+
+    // Nothing jumps over the ja
+    let executable = assemble::<TestContextObject>(
+        "
+        ja +1
+        mov64 r0, 1
+        return",
+        Arc::new(BuiltinProgram::new_mock()),
+    )
+    .unwrap();
+    assert_error!(
+        executable.verify_with_config::<RequisiteVerifier>(&verifier_config),
+        "VerifierError(UnreachableInstruction {{ pc: 1,"
+    );
+}
+
+#[test]
+fn test_verifier_unreachable_instruction() {
+    let verifier_config = VerifierConfig {
+        reject_unreachable_code: true,
+        ..VerifierConfig::default()
+    };
+    let verify = |prog: &[u8], sbpf_version: SBPFVersion| {
+        let executable = Executable::<TestContextObject>::from_text_bytes(
+            prog,
+            Arc::new(BuiltinProgram::new_mock()),
+            sbpf_version,
+            FunctionRegistry::default(),
+        )
+        .unwrap();
+        executable.verify_with_config::<RequisiteVerifier>(&verifier_config)
+    };
+
+    // Garbage after the last return which decodes as mov64 r1, 1 and return
+    let prog = &[
+        0xb7, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+        0x9d, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+        0xb7, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, //
+        0x9d, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+    ];
+    verify(&prog[..16], SBPFVersion::V3).unwrap();
+    assert_error!(
+        verify(prog, SBPFVersion::V3),
+        "VerifierError(UnreachableInstruction {{ pc: 2,"
+    );
+
+    // The second slot of lddw holds data, not an instruction
+    let prog = &[
+        0x18, 0x00, 0x00, 0x00, 0x88, 0x77, 0x66, 0x55, //
+        0x00, 0x00, 0x00, 0x00, 0xb7, 0x01, 0x00, 0x00, //
+        0x95, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+    ];
+    verify(prog, SBPFVersion::V0).unwrap();
+}
+
+/// Rejects every program containing neg64
+struct NoNeg64Verifier {}
+impl Verifier for NoNeg64Verifier {
+    fn verify<C: ContextObject>(
+        prog: &[u8],
+        _config: &Config,
+        _verifier_config: &VerifierConfig,
+        sbpf_version: SBPFVersion,
+        _function_registry: &FunctionRegistry<usize>,
+        _syscall_registry: &FunctionRegistry<BuiltinFunction<C>>,
+    ) -> std::result::Result<(), VerifierError> {
+        for pc in 0..prog.len() / ebpf::INSN_SIZE {
+            let insn = ebpf::get_insn(prog, pc);
+            if insn.opc == ebpf::NEG64 {
+                return Err(VerifierError::UnknownOpCode {
+                    opc: insn.opc,
+                    pc,
+                    insn: Box::new(decode(&insn, sbpf_version)),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_verifier_chain() {
+    type RequisiteFirst = VerifierChain<RequisiteVerifier, NoNeg64Verifier>;
+    type RequisiteLast = VerifierChain<NoNeg64Verifier, RequisiteVerifier>;
+    let loader = Arc::new(BuiltinProgram::new_loader(Config {
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V0,
+        ..Config::default()
+    }));
+    let rejected_by = |result: Result<(), EbpfError>| match result {
+        Err(EbpfError::VerifierError(VerifierError::RejectedBy { verifier, error })) => {
+            (verifier.rsplit("::").next().unwrap(), error.to_string())
+        }
+        result => panic!("{:?}", result),
+    };
+
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r0, 1
+        exit",
+        loader.clone(),
+    )
+    .unwrap();
+    executable.verify::<RequisiteFirst>().unwrap();
+    executable.verify::<RequisiteLast>().unwrap();
+
+    // Only rejected by the second verifier of one order
+    let executable = assemble::<TestContextObject>(
+        "
+        neg64 r0
+        exit",
+        loader.clone(),
+    )
+    .unwrap();
+    executable.verify::<RequisiteVerifier>().unwrap();
+    for result in [
+        executable.verify::<RequisiteFirst>(),
+        executable.verify::<RequisiteLast>(),
+    ] {
+        assert_eq!(
+            rejected_by(result),
+            (
+                "NoNeg64Verifier",
+                "unknown eBPF opcode 0x87 (insn #0: neg64 r0)".to_string(),
+            ),
+        );
+    }
+
+    // Rejected by both, the first one in the chain wins
+    let executable = assemble::<TestContextObject>(
+        "
+        neg64 r0
+        div64 r0, 0
+        exit",
+        loader,
+    )
+    .unwrap();
+    assert_eq!(
+        rejected_by(executable.verify::<RequisiteFirst>()),
+        (
+            "RequisiteVerifier",
+            "division by 0 (insn #1: div64 r0, 0)".to_string(),
+        ),
+    );
+    assert_eq!(
+        rejected_by(executable.verify::<RequisiteLast>()).0,
+        "NoNeg64Verifier",
+    );
+
+    // Nested chains keep the innermost attribution
+    assert_error!(
+        executable.verify::<VerifierChain<TautologyVerifier, RequisiteLast>>(),
+        "RejectedBy {{ verifier: \"verifier::NoNeg64Verifier\","
+    );
+}
@@ -1,12 +1,26 @@
 #![allow(clippy::literal_string_with_formatting_args)]
+#![cfg(all(target_pointer_width = "64", target_endian = "little"))]
 
 use solana_sbpf::{
-    elf::Executable,
-    program::BuiltinProgram,
-    vm::{Config, RuntimeEnvironmentSlot},
+    assembler::assemble,
+    declare_builtin_function, ebpf,
+    elf::{ElfError, Executable},
+    error::{EbpfError, ProgramResult},
+    execution_events::{ExecutionEvent, ExecutionEventLog},
+    interpreter::{PauseReason, StepResult},
+    memory_region::{AccessType, MemoryMapping, MemoryRegion},
+    program::{
+        syscall_key, BuiltinProgram, BuiltinProgramBuilder, FunctionRegistry,
+        ParseSBPFVersionError, SBPFVersion, SyscallDifference, SyscallMetadata,
+    },
+    static_analysis::{compare_traces, Analysis, DivergenceReport, Profiler},
+    vm::{
+        CallFrame, Config, ConfigError, ContextObject, EbpfVm, ResumableResult,
+        RuntimeEnvironmentSlot, SuspendedExecution,
+    },
 };
 use std::{fs::File, io::Read, sync::Arc};
-use test_utils::{create_vm, syscalls, TestContextObject};
+use test_utils::{assert_error, create_vm, syscalls, TestContextObject};
 
 #[test]
 fn test_runtime_environment_slots() {
@@ -49,6 +63,11 @@ fn test_runtime_environment_slots() {
     check_slot!(env, stopwatch_denominator, StopwatchDenominator);
     check_slot!(env, registers, Registers);
     check_slot!(env, program_result, ProgramResult);
+    check_slot!(env, call_filter, CallFilter);
+    check_slot!(env, call_frames_pointer, CallFramesPointer);
+    check_slot!(env, max_observed_call_depth, MaxObservedCallDepth);
+    check_slot!(env, lowest_stack_pointer, LowestStackPointer);
+    check_slot!(env, interpreter_fallback, InterpreterFallback);
     check_slot!(env, memory_mapping, MemoryMapping);
 }
 
@@ -75,3 +94,1008 @@ fn test_builtin_program_eq() {
     assert_eq!(builtin_program_a, builtin_program_b);
     assert_ne!(builtin_program_a, builtin_program_c);
 }
+
+#[test]
+fn test_function_registry_collisions() {
+    // Both names hash to the same key
+    let (name_a, name_b) = ("syscall_1803", "syscall_123444");
+    let key = syscall_key(name_a.as_bytes());
+    assert_eq!(key, 0xdb11fd3);
+    assert_eq!(key, syscall_key(name_b.as_bytes()));
+    let key_collision = || ElfError::SyscallKeyCollision {
+        existing: name_a.as_bytes().into(),
+        new: name_b.as_bytes().into(),
+        key,
+    };
+    assert_eq!(
+        key_collision().to_string(),
+        "Key collision 0xdb11fd3 between syscall_1803 and syscall_123444",
+    );
+
+    let mut function_registry = FunctionRegistry::<usize>::default();
+    function_registry.register_function(key, name_a, 1).unwrap();
+    function_registry.register_function(key, name_a, 1).unwrap();
+    for value in [1, 2] {
+        assert_eq!(
+            function_registry.register_function(key, name_b, value),
+            Err(key_collision()),
+        );
+    }
+    assert_eq!(
+        function_registry.register_function(key, name_a, 2),
+        Err(ElfError::SymbolHashCollision(key)),
+    );
+    function_registry.verify_no_collisions().unwrap();
+    assert_eq!(
+        function_registry.lookup_by_key(key),
+        Some((name_a.as_bytes(), 1))
+    );
+    assert_eq!(
+        function_registry.replace(key, name_b, 2),
+        Some((name_a.as_bytes().to_vec(), 1))
+    );
+    assert_eq!(function_registry.lookup_by_name(name_a.as_bytes()), None);
+    assert_eq!(
+        function_registry.lookup_by_key(key),
+        Some((name_b.as_bytes(), 2))
+    );
+    function_registry.unregister_function(key);
+    assert_eq!(function_registry.lookup_by_key(key), None);
+    assert_eq!(function_registry.replace(key, name_a, 1), None);
+
+    // The same for the syscalls of a loader, before it is shared
+    let mut loader = BuiltinProgram::<TestContextObject>::new_loader(Config::default());
+    loader
+        .register_function(name_a, syscalls::SyscallString::vm)
+        .unwrap();
+    for value in [syscalls::SyscallString::vm, syscalls::SyscallU64::vm] {
+        assert_eq!(
+            loader.register_function(name_b, value),
+            Err(key_collision()),
+        );
+    }
+    let mut expected_loader = BuiltinProgram::new_loader(Config::default());
+    expected_loader
+        .register_function(name_b, syscalls::SyscallU64::vm)
+        .unwrap();
+    let (previous_name, _previous_function) = loader
+        .replace_function(name_b, syscalls::SyscallU64::vm)
+        .unwrap();
+    assert_eq!(previous_name, name_a.as_bytes());
+    assert_eq!(loader, expected_loader);
+    loader.unregister_function(name_a);
+    assert_eq!(loader.get_function_registry().lookup_by_key(key), None);
+
+    // Replacing under a foreign key lets name_b shadow name_a
+    let mut function_registry = FunctionRegistry::<usize>::default();
+    function_registry.register_function(key, name_a, 1).unwrap();
+    function_registry.replace(key + 1, name_b, 2);
+    assert_eq!(
+        function_registry.verify_no_collisions(),
+        Err(key_collision())
+    );
+    function_registry.unregister_function(key);
+    function_registry.verify_no_collisions().unwrap();
+}
+
+#[test]
+fn test_builtin_program_builder() {
+    let metadata = SyscallMetadata { base_cost: 100 };
+    let build = |feature_active: bool| {
+        BuiltinProgramBuilder::<TestContextObject>::new_loader(Config::default())
+            .syscall("log", syscalls::SyscallString::vm, true)
+            .syscall("log_64", syscalls::SyscallU64::vm, !feature_active)
+            .syscall_with_metadata(
+                "log_64",
+                syscalls::SyscallGatherBytes::vm,
+                feature_active,
+                metadata,
+            )
+            .syscall("log_rand", syscalls::SyscallString::vm, feature_active)
+            .build()
+            .unwrap()
+    };
+    let inactive = build(false);
+    let active = build(true);
+
+    let mut expected = BuiltinProgram::new_loader(Config::default());
+    expected
+        .register_function("log", syscalls::SyscallString::vm)
+        .unwrap();
+    expected
+        .register_function("log_64", syscalls::SyscallU64::vm)
+        .unwrap();
+    assert_eq!(inactive, expected);
+    let log_64_key = syscall_key(b"log_64");
+    let (name, _function, log_64_metadata) = active.lookup_by_key(log_64_key).unwrap();
+    assert_eq!((name, log_64_metadata), (&b"log_64"[..], metadata));
+    assert_eq!(
+        inactive.lookup_by_key(log_64_key).unwrap().2,
+        SyscallMetadata::default(),
+    );
+    assert_eq!(active.lookup_by_key(syscall_key(b"log_pubkey")), None);
+
+    assert_eq!(inactive.diff(&inactive), Vec::new());
+    // Ordered by key
+    let by_key = |mut differences: Vec<(&[u8], SyscallDifference)>| {
+        differences.sort_by_key(|(name, _difference)| syscall_key(name));
+        differences
+            .into_iter()
+            .map(|(_name, difference)| difference)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        inactive.diff(&active),
+        by_key(vec![
+            (b"log_64", SyscallDifference::Changed(b"log_64".to_vec())),
+            (
+                b"log_rand",
+                SyscallDifference::OnlyInOther(b"log_rand".to_vec())
+            ),
+        ]),
+    );
+    assert_eq!(
+        active.diff(&inactive),
+        by_key(vec![
+            (b"log_64", SyscallDifference::Changed(b"log_64".to_vec())),
+            (
+                b"log_rand",
+                SyscallDifference::OnlyInSelf(b"log_rand".to_vec())
+            ),
+        ]),
+    );
+
+    // Disabled syscalls are checked for collisions as well
+    assert_eq!(
+        BuiltinProgramBuilder::<TestContextObject>::new_builtin()
+            .syscall("syscall_1803", syscalls::SyscallString::vm, true)
+            .syscall("syscall_123444", syscalls::SyscallString::vm, false)
+            .build(),
+        Err(ElfError::SyscallKeyCollision {
+            existing: b"syscall_1803"[..].into(),
+            new: b"syscall_123444"[..].into(),
+            key: 0xdb11fd3,
+        }),
+    );
+    // A name may be added twice, but only one of them may be enabled
+    assert_eq!(
+        BuiltinProgramBuilder::<TestContextObject>::new_builtin()
+            .syscall("log", syscalls::SyscallString::vm, true)
+            .syscall("log", syscalls::SyscallU64::vm, true)
+            .build(),
+        Err(ElfError::SymbolHashCollision(syscall_key(b"log"))),
+    );
+}
+
+#[test]
+fn test_profiler_folded_stacks() {
+    let mut loader = BuiltinProgram::new_loader(Config {
+        enable_instruction_tracing: true,
+        ..Config::default()
+    });
+    loader
+        .register_function("log_64", syscalls::SyscallU64::vm)
+        .unwrap();
+    let executable = assemble::<TestContextObject>(
+        "
+        entrypoint:
+        call function_helper
+        call function_inner
+        exit
+        function_helper:
+        call function_inner
+        call function_inner
+        exit
+        function_inner:
+        syscall log_64
+        mov64 r0, 0
+        exit",
+        Arc::new(loader),
+    )
+    .unwrap();
+    let mut context_object = TestContextObject::new(100);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert!(result.is_ok());
+
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let profiler = Profiler::new(&vm.context_object_pointer.trace_log, &analysis);
+    let mut folded = Vec::new();
+    profiler.write_folded(&mut folded).unwrap();
+    assert_eq!(
+        String::from_utf8(folded).unwrap(),
+        "entrypoint 1\n\
+         entrypoint;function_helper 1\n\
+         entrypoint;function_helper;function_inner 2\n\
+         entrypoint;function_inner 1\n",
+    );
+    let mut collapsed = Vec::new();
+    profiler
+        .write_collapsed_by_instruction_counts(&mut collapsed)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(collapsed).unwrap(),
+        "entrypoint 3\n\
+         entrypoint;function_helper 3\n\
+         entrypoint;function_helper;function_inner 6\n\
+         entrypoint;function_inner 3\n",
+    );
+}
+
+#[test]
+fn test_compare_traces() {
+    let loader = BuiltinProgram::new_loader(Config {
+        enable_instruction_tracing: true,
+        ..Config::default()
+    });
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r1, 1
+        add64 r1, 2
+        mov64 r0, r1
+        return",
+        Arc::new(loader),
+    )
+    .unwrap();
+    let mut context_object = TestContextObject::new(100);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    let (_instruction_count, result) = vm.execute_program(&executable, true);
+    assert!(result.is_ok());
+    let trace_log = vm.context_object_pointer.trace_log.clone();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    assert_eq!(compare_traces(&trace_log, &trace_log), None);
+
+    // The shorter trace diverges where it ends
+    let report = compare_traces(&trace_log, &trace_log[..3]).unwrap();
+    assert_eq!(
+        report,
+        DivergenceReport {
+            index: 3,
+            a: Some(trace_log[3]),
+            b: None,
+        }
+    );
+
+    let mut diverging = trace_log.clone();
+    diverging[2][1] = 4;
+    let report = compare_traces(&trace_log, &diverging).unwrap();
+    assert_eq!(report.index, 2);
+    let mut output = Vec::new();
+    report.write(&mut output, &analysis).unwrap();
+    let stack_pointer = trace_log[2][10];
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        format!(
+            "traces diverge at entry 2
+a:     2: mov64 r0, r1
+b:     2: mov64 r0, r1
+     a                   b
+r0   0x0000000000000000  0x0000000000000000
+r1   0x0000000000000003  0x0000000000000004  <-
+r2   0x0000000000000000  0x0000000000000000
+r3   0x0000000000000000  0x0000000000000000
+r4   0x0000000000000000  0x0000000000000000
+r5   0x0000000000000000  0x0000000000000000
+r6   0x0000000000000000  0x0000000000000000
+r7   0x0000000000000000  0x0000000000000000
+r8   0x0000000000000000  0x0000000000000000
+r9   0x0000000000000000  0x0000000000000000
+r10  {stack_pointer:#018x}  {stack_pointer:#018x}
+pc   0x0000000000000002  0x0000000000000002
+"
+        )
+    );
+}
+
+#[test]
+fn test_translate_type_unaligned() {
+    // Interpreter only, so that the test also runs under miri
+    for reject_unaligned_guest_types in [false, true] {
+        let mut loader = BuiltinProgram::new_loader(Config {
+            reject_unaligned_guest_types,
+            ..Config::default()
+        });
+        loader
+            .register_function("bpf_read_u64", syscalls::SyscallReadU64::vm)
+            .unwrap();
+        let executable = assemble::<TestContextObject>(
+            "
+            add64 r1, 3
+            syscall bpf_read_u64
+            exit",
+            Arc::new(loader),
+        )
+        .unwrap();
+        let mut mem = [0u8; 16];
+        mem[3..11].copy_from_slice(&0x1122334455667788u64.to_le_bytes());
+        let mem_region =
+            MemoryRegion::new_readonly(&mem, executable.get_config().memory_layout.input_start());
+        let mut context_object = TestContextObject::new(3);
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            vec![mem_region],
+            None
+        );
+        let (_instruction_count, result) = vm.execute_program(&executable, true);
+        if reject_unaligned_guest_types {
+            assert_error!(result, "UnalignedGuestType");
+        } else {
+            assert_eq!(result.unwrap(), 0x1122334455667788);
+        }
+    }
+}
+
+declare_builtin_function!(
+    /// Stands in for a builtin growing the heap, only emits the event
+    SyscallGrowHeap,
+    fn rust(
+        context_object: &mut TestContextObject,
+        _arg1: u64,
+        _arg2: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        _memory_mapping: &mut MemoryMapping,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        if let Some(events) = context_object.execution_events() {
+            events.builtin_event(ExecutionEvent::MemoryGrow {
+                vm_addr: ebpf::MM_HEAP_START,
+                len: 64,
+            });
+        }
+        Ok(0)
+    }
+);
+
+fn run_with_execution_events(byte_budget: usize) -> ExecutionEventLog {
+    let mut loader = BuiltinProgram::new_loader(Config::default());
+    loader
+        .register_function("log", syscalls::SyscallString::vm)
+        .unwrap();
+    loader
+        .register_function("bpf_grow", SyscallGrowHeap::vm)
+        .unwrap();
+    let executable = assemble::<TestContextObject>(
+        "
+        entrypoint:
+        call function_work
+        mov64 r1, 0
+        udiv64 r0, r1
+        exit
+        function_work:
+        mov64 r2, 5
+        syscall log
+        syscall bpf_grow
+        exit",
+        Arc::new(loader),
+    )
+    .unwrap();
+    let mut mem = *b"hello\0\0\0";
+    let mem_region = MemoryRegion::new_writable(
+        &mut mem,
+        executable.get_config().memory_layout.input_start(),
+    );
+    let mut context_object = TestContextObject {
+        execution_events: Some(ExecutionEventLog::new(byte_budget)),
+        ..TestContextObject::new(100)
+    };
+    {
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            vec![mem_region],
+            None
+        );
+        let (_instruction_count, result) = vm.execute_program(&executable, true);
+        assert_error!(result, "DivideByZero");
+    }
+    context_object.execution_events.unwrap()
+}
+
+#[test]
+fn test_execution_events() {
+    let log = run_with_execution_events(usize::MAX);
+    assert_eq!(
+        log.events,
+        [
+            (
+                0,
+                ExecutionEvent::FrameEnter {
+                    target_pc: 0,
+                    depth: 0
+                }
+            ),
+            (
+                1,
+                ExecutionEvent::FrameEnter {
+                    target_pc: 4,
+                    depth: 1
+                }
+            ),
+            (
+                3,
+                ExecutionEvent::SyscallEnter {
+                    pc: 5,
+                    name: b"log".to_vec()
+                }
+            ),
+            (3, ExecutionEvent::Log("hello".to_string())),
+            (
+                3,
+                ExecutionEvent::SyscallExit {
+                    pc: 5,
+                    return_value: Some(0)
+                }
+            ),
+            (
+                4,
+                ExecutionEvent::SyscallEnter {
+                    pc: 6,
+                    name: b"bpf_grow".to_vec()
+                }
+            ),
+            (
+                4,
+                ExecutionEvent::MemoryGrow {
+                    vm_addr: ebpf::MM_HEAP_START,
+                    len: 64
+                }
+            ),
+            (
+                4,
+                ExecutionEvent::SyscallExit {
+                    pc: 6,
+                    return_value: Some(0)
+                }
+            ),
+            (5, ExecutionEvent::FrameExit { pc: 7, depth: 1 }),
+            (
+                7,
+                ExecutionEvent::Error("divide by zero at BPF instruction".to_string())
+            ),
+        ]
+    );
+    assert_eq!(log.dropped_events, 0);
+    assert_eq!(
+        log.render_timeline(),
+        "       0 enter frame 0 at pc 0
+       1   enter frame 1 at pc 4
+       3     syscall log at pc 5
+       3       log: hello
+       3     syscall returned 0x0
+       4     syscall bpf_grow at pc 6
+       4       memory region at 0x300000000 grew to 64 bytes
+       4     syscall returned 0x0
+       5   exit frame 1 at pc 7
+       7   error: divide by zero at BPF instruction
+",
+    );
+
+    // Only the first events fit into the budget, the later ones are counted
+    let byte_budget = log.events[..3]
+        .iter()
+        .map(|(_instruction_count, event)| event.mem_size())
+        .sum();
+    let log = run_with_execution_events(byte_budget);
+    assert_eq!(log.events.len(), 3);
+    assert_eq!(log.dropped_events, 7);
+    assert!(log
+        .render_timeline()
+        .ends_with("syscall log at pc 5\n7 more events exceeded the budget\n"));
+}
+
+#[test]
+fn test_sbpf_version_features() {
+    let versions = [
+        SBPFVersion::V0,
+        SBPFVersion::V1,
+        SBPFVersion::V2,
+        SBPFVersion::V3,
+        SBPFVersion::Reserved,
+    ];
+    for version in versions {
+        let expected = [
+            ("dynamic_stack_frames", version.dynamic_stack_frames()),
+            ("enable_pqr", version.enable_pqr()),
+            (
+                "explicit_sign_extension_of_results",
+                version.explicit_sign_extension_of_results(),
+            ),
+            (
+                "swap_sub_reg_imm_operands",
+                version.swap_sub_reg_imm_operands(),
+            ),
+            ("disable_neg", version.disable_neg()),
+            ("callx_uses_src_reg", version.callx_uses_src_reg()),
+            ("disable_lddw", version.disable_lddw()),
+            ("disable_le", version.disable_le()),
+            (
+                "move_memory_instruction_classes",
+                version.move_memory_instruction_classes(),
+            ),
+            ("static_syscalls", version.static_syscalls()),
+            (
+                "enable_stricter_elf_headers",
+                version.enable_stricter_elf_headers(),
+            ),
+            (
+                "enable_lower_bytecode_vaddr",
+                version.enable_lower_bytecode_vaddr(),
+            ),
+            (
+                "reject_rodata_stack_overlap",
+                version.reject_rodata_stack_overlap(),
+            ),
+            ("enable_elf_vaddr", version.enable_elf_vaddr()),
+        ];
+        assert_eq!(version.features().collect::<Vec<_>>(), expected);
+
+        let name = version.to_string();
+        assert_eq!(name.parse::<SBPFVersion>(), Ok(version));
+        assert_eq!(name.to_uppercase().parse::<SBPFVersion>(), Ok(version));
+    }
+    assert_eq!(SBPFVersion::V2.to_string(), "v2");
+    assert_eq!(SBPFVersion::Reserved.to_string(), "v4");
+    assert_eq!(
+        "v5".parse::<SBPFVersion>(),
+        Err(ParseSBPFVersionError("v5".to_string()))
+    );
+}
+
+#[test]
+fn test_config_validate() {
+    assert_eq!(Config::default().validate(), Ok(()));
+
+    // Boundaries of the stack parameters
+    assert_eq!(
+        Config::default().with_stack(64, 0),
+        Err(ConfigError::ZeroStackFrameSize)
+    );
+    assert_eq!(
+        Config::default().with_stack(0, 4096),
+        Err(ConfigError::ZeroCallDepth)
+    );
+    let config = Config::default().with_stack(1, 4096).unwrap();
+    assert_eq!(config.stack_size(), 4096);
+    assert_eq!(
+        Config::default().with_stack(1, ebpf::HOST_ALIGN),
+        Ok(Config {
+            max_call_depth: 1,
+            stack_frame_size: ebpf::HOST_ALIGN,
+            ..Config::default()
+        })
+    );
+    assert_eq!(
+        Config::default().with_stack(1, 4100),
+        Err(ConfigError::UnalignedStackFrameSize(4100))
+    );
+    assert_eq!(
+        Config::default().with_stack(1, 4112),
+        Err(ConfigError::StackFrameSizeNotPowerOfTwo(4112))
+    );
+    let config = Config {
+        enable_stack_frame_gaps: false,
+        ..Config::default()
+    };
+    assert!(config.clone().with_stack(1, 4112).is_ok());
+
+    // The stack has to fit into MM_STACK, gaps double its span
+    let max_call_depth = (ebpf::MM_REGION_SIZE / 2 / 4096) as usize;
+    assert!(Config::default().with_stack(max_call_depth, 4096).is_ok());
+    assert_eq!(
+        Config::default().with_stack(max_call_depth + 1, 4096),
+        Err(ConfigError::StackExceedsRegion {
+            stack_span: ebpf::MM_REGION_SIZE + 2 * 4096,
+            region_size: ebpf::MM_REGION_SIZE,
+        })
+    );
+    assert!(config.with_stack(max_call_depth * 2, 4096).is_ok());
+
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V3..=SBPFVersion::V2,
+        ..Config::default()
+    };
+    assert_eq!(config.validate(), Err(ConfigError::NoSbpfVersionEnabled));
+
+    // The heap has to fit into MM_HEAP
+    let config = Config::default();
+    let region_size = ebpf::MM_REGION_SIZE as usize;
+    assert_eq!(config.validate_heap_size(region_size), Ok(()));
+    assert_eq!(
+        config.validate_heap_size(region_size + 1),
+        Err(ConfigError::HeapExceedsRegion {
+            heap_size: ebpf::MM_REGION_SIZE + 1,
+            region_size: ebpf::MM_REGION_SIZE,
+        })
+    );
+
+    // Loading rejects invalid configs
+    let config = Config {
+        stack_frame_size: 0,
+        ..Config::default()
+    };
+    let loader = Arc::new(BuiltinProgram::<TestContextObject>::new_loader(config));
+    let mut file = File::open("tests/elfs/relative_call_sbpfv0.so").unwrap();
+    let mut elf = Vec::new();
+    file.read_to_end(&mut elf).unwrap();
+    assert!(matches!(
+        Executable::from_elf(&elf, loader.clone()),
+        Err(EbpfError::ConfigError(ConfigError::ZeroStackFrameSize))
+    ));
+    assert!(matches!(
+        Executable::from_text_bytes(
+            &[],
+            loader.clone(),
+            SBPFVersion::V3,
+            FunctionRegistry::default()
+        ),
+        Err(EbpfError::ConfigError(ConfigError::ZeroStackFrameSize))
+    ));
+    let mut context_object = TestContextObject::default();
+    assert!(matches!(
+        EbpfVm::try_new(
+            loader,
+            SBPFVersion::V3,
+            &mut context_object,
+            MemoryMapping::new(Vec::new(), &Config::default(), SBPFVersion::V3).unwrap(),
+            0,
+        ),
+        Err(ConfigError::ZeroStackFrameSize)
+    ));
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "ZeroStackFrameSize")]
+fn test_new_asserts_valid_config() {
+    let config = Config {
+        stack_frame_size: 0,
+        ..Config::default()
+    };
+    let loader = Arc::new(BuiltinProgram::<TestContextObject>::new_loader(config));
+    let mut context_object = TestContextObject::default();
+    EbpfVm::new(
+        loader,
+        SBPFVersion::V3,
+        &mut context_object,
+        MemoryMapping::new(Vec::new(), &Config::default(), SBPFVersion::V3).unwrap(),
+        0,
+    );
+}
+
+declare_builtin_function!(
+    /// Charges 10 instructions in addition to the syscall instruction
+    SyscallConsume,
+    fn rust(
+        context_object: &mut TestContextObject,
+        _arg1: u64,
+        _arg2: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        _memory_mapping: &mut MemoryMapping,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        context_object.consume(10);
+        Ok(0)
+    }
+);
+
+#[test]
+fn test_interpreter_step() {
+    let mut loader = BuiltinProgram::new_loader(Config::default());
+    loader
+        .register_function("bpf_consume", SyscallConsume::vm)
+        .unwrap();
+    let executable = assemble::<TestContextObject>(
+        "
+        entrypoint:
+        mov64 r6, 3
+        loop:
+        call function_work
+        add64 r6, -1
+        jne r6, 0, loop
+        mov64 r0, 42
+        exit
+        function_work:
+        syscall bpf_consume
+        exit",
+        Arc::new(loader),
+    )
+    .unwrap();
+
+    // 1 + 3 * (5 + 10) + 2 instructions are charged in total
+    for budget in [48, 47, 20] {
+        let mut context_object = TestContextObject::new(budget);
+        let expected_result = {
+            create_vm!(
+                vm,
+                &executable,
+                &mut context_object,
+                stack,
+                heap,
+                Vec::new(),
+                None
+            );
+            let (instruction_count, result) = vm.execute_program(&executable, true);
+            (instruction_count, format!("{result:?}"))
+        };
+        let expected_remaining = context_object.get_remaining();
+
+        let mut context_object = TestContextObject::new(budget);
+        let mut pcs = Vec::new();
+        let result = {
+            create_vm!(
+                vm,
+                &executable,
+                &mut context_object,
+                stack,
+                heap,
+                Vec::new(),
+                None
+            );
+            let mut interpreter = vm.step_program(&executable);
+            assert_eq!(interpreter.remaining_instruction_meter(), budget);
+            loop {
+                pcs.push(interpreter.pc());
+                match interpreter.step() {
+                    StepResult::Continue => {}
+                    StepResult::Exited(value) => {
+                        assert_eq!(interpreter.registers()[0], value);
+                        break format!("{:?}", ProgramResult::Ok(value));
+                    }
+                    StepResult::Error(err) => {
+                        break format!("{:?}", ProgramResult::Err(err));
+                    }
+                    StepResult::Paused(reason) => panic!("{:?}", reason),
+                }
+            }
+        };
+        assert_eq!(result, expected_result.1);
+        assert_eq!(context_object.get_remaining(), expected_remaining);
+        assert_eq!(budget - context_object.get_remaining(), expected_result.0);
+        if budget == 48 {
+            assert_eq!(expected_result.1, "Ok(42)");
+            assert_eq!(&pcs[..6], &[0, 1, 6, 7, 2, 3]);
+            assert_eq!(pcs.len(), 1 + 3 * 5 + 2);
+        } else {
+            assert!(expected_result.1.contains("ExceededMaxInstructions"));
+        }
+    }
+}
+
+#[test]
+fn test_interpreter_breakpoints() {
+    let config = Config {
+        enabled_sbpf_versions: SBPFVersion::V0..=SBPFVersion::V1,
+        ..Config::default()
+    };
+    let executable = assemble::<TestContextObject>(
+        "
+        entrypoint:
+        lddw r1, 0x100000007
+        call function_store
+        ldxdw r0, [r10-8]
+        exit
+        function_store:
+        stxdw [r10-8], r1
+        exit",
+        Arc::new(BuiltinProgram::new_loader(config)),
+    )
+    .unwrap();
+    let mut context_object = TestContextObject::new(100);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+    let stack_range =
+        ebpf::MM_STACK_START..ebpf::MM_STACK_START + executable.get_config().stack_size() as u64;
+    let mut interpreter = vm.step_program(&executable);
+    assert_error!(
+        interpreter.set_breakpoint(1),
+        "InvalidBreakpoint {{ pc: 1 }}"
+    );
+    assert_error!(
+        interpreter.set_breakpoint(7),
+        "InvalidBreakpoint {{ pc: 7 }}"
+    );
+    interpreter.set_breakpoint(5).unwrap();
+    interpreter.set_watchpoint(stack_range.clone(), AccessType::Store);
+
+    // The breakpoint in the called function pauses before its instruction is executed
+    assert!(matches!(
+        interpreter.run(),
+        StepResult::Paused(PauseReason::Breakpoint { pc: 5 })
+    ));
+    assert_eq!(interpreter.pc(), 5);
+    assert_eq!(interpreter.registers()[1], 0x100000007);
+
+    // Resuming executes the store, which hits the watchpoint
+    let vm_addr = stack_range.end - 8;
+    assert_eq!(
+        format!("{:?}", interpreter.run()),
+        format!(
+            "{:?}",
+            StepResult::Paused(PauseReason::Watchpoint {
+                pc: 5,
+                access_type: AccessType::Store,
+                vm_addr,
+                len: 8,
+                old_value: 0,
+                new_value: 0x100000007,
+            })
+        )
+    );
+    assert_eq!(interpreter.pc(), 6);
+
+    // The load is not watched and the breakpoint is not hit again
+    assert!(interpreter.clear_breakpoint(5));
+    assert!(!interpreter.clear_breakpoint(5));
+    assert!(matches!(interpreter.run(), StepResult::Exited(0x100000007)));
+    assert!(interpreter.clear_watchpoint(stack_range, AccessType::Store));
+}
+
+#[test]
+fn test_resumable_execution() {
+    let executable = assemble::<TestContextObject>(
+        "
+        mov64 r7, 10
+        loop:
+        call function_add
+        add64 r7, -1
+        jne r7, 0, loop
+        ldxdw r0, [r10-8]
+        exit
+        function_add:
+        ldxdw r1, [r10-8]
+        add64 r1, 3
+        stxdw [r10-8], r1
+        exit",
+        Arc::new(BuiltinProgram::new_loader(Config::default())),
+    )
+    .unwrap();
+
+    let mut context_object = TestContextObject::new(1000);
+    let (expected_instruction_count, expected_result) = {
+        create_vm!(
+            vm,
+            &executable,
+            &mut context_object,
+            stack,
+            heap,
+            Vec::new(),
+            None
+        );
+        vm.execute_program(&executable, true)
+    };
+    assert_eq!(format!("{expected_result:?}"), "Ok(30)");
+    assert_eq!(expected_instruction_count, 1 + 10 * 7 + 2);
+
+    for budget in [1, 7, 10] {
+        let mut context_object = TestContextObject::new(budget);
+        let mut suspensions = 0;
+        {
+            create_vm!(
+                vm,
+                &executable,
+                &mut context_object,
+                stack,
+                heap,
+                Vec::new(),
+                None
+            );
+            let mut result = vm.execute_program_resumable(&executable);
+            let (instruction_count, result) = loop {
+                match result {
+                    ResumableResult::Suspended(suspended) => {
+                        assert_eq!(vm.context_object_pointer.remaining, 0);
+                        assert_eq!(suspended.instruction_count, budget * (suspensions + 1));
+                        suspensions += 1;
+                        vm.context_object_pointer.remaining += budget;
+                        result = vm.resume(&executable, suspended);
+                    }
+                    ResumableResult::Completed(instruction_count, result) => {
+                        break (instruction_count, result);
+                    }
+                }
+            };
+            assert_eq!(format!("{result:?}"), format!("{expected_result:?}"));
+            assert_eq!(instruction_count, expected_instruction_count);
+        }
+        assert_eq!(suspensions, (expected_instruction_count - 1) / budget);
+        assert_eq!(
+            context_object.remaining,
+            budget * (suspensions + 1) - expected_instruction_count
+        );
+    }
+}
+
+#[test]
+fn test_resume_invalid_suspension() {
+    let config = Config::default();
+    let mut executable = assemble::<TestContextObject>(
+        "
+        call function_foo
+        exit
+        function_foo:
+        exit",
+        Arc::new(BuiltinProgram::new_loader(config.clone())),
+    )
+    .unwrap();
+    executable.predecode().unwrap();
+    let mut context_object = TestContextObject::new(10);
+    create_vm!(
+        vm,
+        &executable,
+        &mut context_object,
+        stack,
+        heap,
+        Vec::new(),
+        None
+    );
+
+    // More call frames than the VM has
+    let suspended = SuspendedExecution {
+        registers: [0; 12],
+        call_frames: vec![CallFrame::default(); config.max_call_depth + 1],
+        instruction_count: 0,
+    };
+    assert!(matches!(
+        vm.resume(&executable, suspended),
+        ResumableResult::Completed(
+            0,
+            ProgramResult::Err(EbpfError::CallDepthExceeded { pc: 0, .. })
+        )
+    ));
+
+    // A pc or return address outside of the text section
+    for (pc, target_pc, expected_error) in [
+        (3, 0, EbpfError::ExecutionOverrun),
+        (u64::MAX, 0, EbpfError::ExecutionOverrun),
+        (2, 3, EbpfError::CallOutsideTextSegment),
+    ] {
+        let mut registers = [0; 12];
+        registers[11] = pc;
+        let suspended = SuspendedExecution {
+            registers,
+            call_frames: vec![CallFrame {
+                target_pc,
+                ..CallFrame::default()
+            }],
+            instruction_count: 0,
+        };
+        vm.context_object_pointer.remaining = 10;
+        assert_error!(
+            vm.resume(&executable, suspended),
+            "Completed(1, Err({:?}))",
+            expected_error
+        );
+    }
+}